@@ -0,0 +1,876 @@
+//! Export and import of filesystems as tar archives, and import of host
+//! directories.
+//!
+//! The filesystem is walked or populated on the runtime, while the archive or
+//! host directory is written or read on a blocking thread. File content flows between the two in
+//! bounded pieces, so that no more than a few of them are held in memory at a
+//! time.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Component, Path};
+use std::sync::Arc;
+
+use agentfs_sdk::{AgentFS, AgentFSOptions, FileSystem, Stats};
+use anyhow::{Context, Result as AnyhowResult};
+use tokio::sync::mpsc;
+
+use crate::cmd::init::open_agentfs;
+#[cfg(unix)]
+use crate::ignore::IgnoreRules;
+use crate::parser::ChecksumAlgorithm;
+use crate::passphrase;
+
+/// Size of the reads of file content
+const READ_SIZE: u64 = 64 * 1024;
+
+/// Number of items buffered between the walk and the archive writer
+const CHANNEL_CAPACITY: usize = 16;
+
+/// An item of an archive, passed between the filesystem and archive threads
+enum ArchiveItem {
+    /// A directory, or a file whose content follows in `Data` items
+    Entry { header: tar::Header, path: String },
+    /// A symlink or hard link to `target`
+    Link {
+        header: tar::Header,
+        path: String,
+        target: String,
+    },
+    /// A piece of the content of the last file
+    Data(Vec<u8>),
+}
+
+/// Write the filesystem `id_or_path` to the tar archive `output`
+///
+/// The archive is compressed with gzip if `output` ends in `.tar.gz` or
+/// `.tgz`. Overlay filesystems are exported as seen through the overlay. With
+/// `checksum`, the archive is hashed as it is written, and the hash is printed
+/// to `stderr` at the end.
+pub async fn export_filesystem(
+    stderr: &mut impl Write,
+    id_or_path: String,
+    output: &Path,
+    checksum: Option<ChecksumAlgorithm>,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    eprintln!("Using agent: {}", id_or_path);
+
+    let (_, agentfs) = open_agentfs(options).await?;
+    let fs = open_filesystem(agentfs).await?;
+
+    let file = std::fs::File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let file = HashingWriter {
+        inner: file,
+        hasher: checksum.map(|algorithm| match algorithm {
+            ChecksumAlgorithm::Blake3 => blake3::Hasher::new(),
+        }),
+    };
+    let gzip = is_gzip(output);
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let writer = tokio::task::spawn_blocking(move || -> io::Result<HashingWriter<_>> {
+        if gzip {
+            let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            write_archive(encoder, rx)?.finish()
+        } else {
+            let mut file = write_archive(file, rx)?;
+            file.flush()?;
+            Ok(file)
+        }
+    });
+
+    let walked = walk(fs.as_ref(), &tx).await;
+    drop(tx);
+    // A failed writer stops the walk, so its error comes first
+    let result = writer
+        .await
+        .context("Archive writer panicked")?
+        .with_context(|| format!("Failed to write {}", output.display()))
+        .and_then(|file| walked.map(|()| file));
+    if result.is_err() {
+        std::fs::remove_file(output).ok();
+    }
+    let file = result?;
+
+    eprintln!("Exported to {}", output.display());
+    if let Some(hasher) = file.hasher {
+        writeln!(
+            stderr,
+            "{}  {}",
+            hasher.finalize().to_hex(),
+            output.display()
+        )
+        .context("Failed to write to stderr")?;
+    }
+    Ok(())
+}
+
+/// The filesystem to export: the overlay for overlay filesystems
+async fn open_filesystem(agentfs: AgentFS) -> AnyhowResult<Arc<dyn FileSystem>> {
+    #[cfg(unix)]
+    {
+        if let Some(base_path) = agentfs.is_overlay_enabled().await? {
+            let hostfs = agentfs_sdk::HostFS::new(&base_path)?;
+            let overlay = agentfs_sdk::OverlayFS::new(Arc::new(hostfs), agentfs.fs);
+            return Ok(Arc::new(overlay));
+        }
+    }
+    Ok(Arc::new(agentfs.fs))
+}
+
+fn is_gzip(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// Send every entry of `fs` to the archive writer, parents first
+async fn walk(fs: &dyn FileSystem, tx: &mpsc::Sender<ArchiveItem>) -> AnyhowResult<()> {
+    let send = |item| async move {
+        tx.send(item)
+            .await
+            .map_err(|_| anyhow::anyhow!("Archive writer stopped"))
+    };
+
+    // Archive path of the first entry of each inode with several links
+    let mut linked: HashMap<i64, String> = HashMap::new();
+    let mut queue = VecDeque::from([String::new()]);
+    while let Some(dir) = queue.pop_front() {
+        let fs_dir = if dir.is_empty() {
+            "/".to_string()
+        } else {
+            format!("/{}", dir)
+        };
+        let mut entries = fs
+            .readdir_plus(&fs_dir)
+            .await?
+            .with_context(|| format!("Directory not found: {}", fs_dir))?;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for entry in entries {
+            let path = if dir.is_empty() {
+                entry.name.clone()
+            } else {
+                format!("{}/{}", dir, entry.name)
+            };
+            let stats = &entry.stats;
+            let mut header = header_for(stats);
+
+            if stats.is_directory() {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                send(ArchiveItem::Entry {
+                    header,
+                    path: path.clone(),
+                })
+                .await?;
+                queue.push_back(path);
+            } else if stats.is_symlink() {
+                let target = fs
+                    .readlink(&format!("/{}", path))
+                    .await?
+                    .with_context(|| format!("Symlink not found: /{}", path))?;
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                send(ArchiveItem::Link {
+                    header,
+                    path,
+                    target,
+                })
+                .await?;
+            } else if stats.is_file() {
+                if stats.nlink > 1 {
+                    if let Some(target) = linked.get(&stats.ino) {
+                        header.set_entry_type(tar::EntryType::Link);
+                        header.set_size(0);
+                        send(ArchiveItem::Link {
+                            header,
+                            path,
+                            target: target.clone(),
+                        })
+                        .await?;
+                        continue;
+                    }
+                    linked.insert(stats.ino, path.clone());
+                }
+                let size = stats.size.max(0) as u64;
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(size);
+                send(ArchiveItem::Entry {
+                    header,
+                    path: path.clone(),
+                })
+                .await?;
+
+                let file = fs.open(&format!("/{}", path)).await?;
+                let mut offset = 0;
+                while offset < size {
+                    let len = READ_SIZE.min(size - offset);
+                    let mut data = file.pread(offset, len).await?;
+                    // The end of a file extended by truncation may be unstored
+                    data.resize(len as usize, 0);
+                    offset += len;
+                    send(ArchiveItem::Data(data)).await?;
+                }
+            } else {
+                tracing::warn!("Skipping special file: /{}", path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A tar header with the mode, ownership and modification time of `stats`
+fn header_for(stats: &Stats) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(stats.mode & 0o7777);
+    header.set_uid(stats.uid as u64);
+    header.set_gid(stats.gid as u64);
+    header.set_mtime(stats.mtime.max(0) as u64);
+    header
+}
+
+/// Write the items received from `rx` as a tar archive to `writer`
+fn write_archive<W: Write>(writer: W, mut rx: mpsc::Receiver<ArchiveItem>) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    while let Some(item) = rx.blocking_recv() {
+        match item {
+            ArchiveItem::Entry { mut header, path } => {
+                let remaining = header.size()?;
+                let content = ContentReader {
+                    rx: &mut rx,
+                    data: Vec::new(),
+                    pos: 0,
+                    remaining,
+                };
+                builder.append_data(&mut header, &path, content)?;
+            }
+            ArchiveItem::Link {
+                mut header,
+                path,
+                target,
+            } => builder.append_link(&mut header, &path, &target)?,
+            ArchiveItem::Data(_) => return Err(io::Error::other("unexpected file content")),
+        }
+    }
+    builder.into_inner()
+}
+
+/// Writer that hashes what it passes on to `inner`, if it has a `hasher`
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Option<blake3::Hasher>,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader of the content of a file, from the `Data` items following its entry
+struct ContentReader<'a> {
+    rx: &'a mut mpsc::Receiver<ArchiveItem>,
+    data: Vec<u8>,
+    pos: usize,
+    /// Bytes of the file not yet received
+    remaining: u64,
+}
+
+impl Read for ContentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.data.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            match self.rx.blocking_recv() {
+                Some(ArchiveItem::Data(data)) if data.len() as u64 <= self.remaining => {
+                    self.remaining -= data.len() as u64;
+                    self.data = data;
+                    self.pos = 0;
+                }
+                _ => return Err(io::Error::other("truncated file content")),
+            }
+        }
+        let len = buf.len().min(self.data.len() - self.pos);
+        buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Create the filesystem `id` from the tar archive `input`
+pub async fn import_filesystem(input: &Path, id: String) -> AnyhowResult<()> {
+    if !AgentFSOptions::validate_agent_id(&id) {
+        anyhow::bail!(
+            "Invalid agent ID '{}'. Agent IDs must contain only alphanumeric characters, hyphens, and underscores.",
+            id
+        );
+    }
+    let mut options = AgentFSOptions::with_id(&id);
+    if let Some(passphrase) = passphrase::get() {
+        options = options.with_encryption(passphrase);
+    }
+    import_archive(input, options).await?;
+    eprintln!("Imported {} into agent '{}'", input.display(), id);
+    Ok(())
+}
+
+/// Create the filesystem of `options` from the tar archive `input`
+///
+/// The archive may be compressed with gzip. The database is removed again if
+/// the import fails.
+async fn import_archive(input: &Path, options: AgentFSOptions) -> AnyhowResult<()> {
+    let db_path = options.db_path()?;
+    if Path::new(&db_path).exists() {
+        anyhow::bail!("Filesystem already exists at '{}'", db_path);
+    }
+    let file = std::fs::File::open(input)
+        .with_context(|| format!("Failed to open {}", input.display()))?;
+
+    let agentfs = AgentFS::open(options)
+        .await
+        .context("Failed to create filesystem")?;
+    let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let reader = tokio::task::spawn_blocking(move || read_archive(file, tx));
+
+    let imported = populate(&agentfs.fs, &mut rx).await;
+    drop(rx);
+    drop(agentfs);
+    // A failed reader stops the import, so its error comes first
+    let result = reader
+        .await
+        .context("Archive reader panicked")?
+        .with_context(|| format!("Failed to read {}", input.display()))
+        .and(imported);
+    if result.is_err() {
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{}", db_path, suffix)).ok();
+        }
+    }
+    result
+}
+
+/// Send the entries of the archive in `file` to the importer
+fn read_archive(file: std::fs::File, tx: mpsc::Sender<ArchiveItem>) -> AnyhowResult<()> {
+    let send = |item| {
+        tx.blocking_send(item)
+            .map_err(|_| anyhow::anyhow!("Import stopped"))
+    };
+
+    let mut reader = io::BufReader::new(file);
+    let gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    let reader: Box<dyn Read> = if gzip {
+        Box::new(flate2::read::GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let Some(path) = archive_path(&entry.path()?)? else {
+            continue;
+        };
+        let mut header = entry.header().clone();
+        match header.entry_type() {
+            tar::EntryType::Directory => send(ArchiveItem::Entry { header, path })?,
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(entry.size());
+                send(ArchiveItem::Entry { header, path })?;
+                loop {
+                    let mut data = Vec::with_capacity(READ_SIZE as usize);
+                    (&mut entry).take(READ_SIZE).read_to_end(&mut data)?;
+                    if data.is_empty() {
+                        break;
+                    }
+                    send(ArchiveItem::Data(data))?;
+                }
+            }
+            tar::EntryType::Symlink | tar::EntryType::Link => {
+                let target = entry
+                    .link_name()?
+                    .with_context(|| format!("Link without a target: {}", path))?;
+                let target = if header.entry_type() == tar::EntryType::Link {
+                    archive_path(&target)?
+                        .with_context(|| format!("Invalid hard link target: {}", path))?
+                } else {
+                    target
+                        .to_str()
+                        .with_context(|| format!("Symlink target is not UTF-8: {}", path))?
+                        .to_string()
+                };
+                send(ArchiveItem::Link {
+                    header,
+                    path,
+                    target,
+                })?;
+            }
+            _ => tracing::warn!("Skipping unsupported entry: {}", path),
+        }
+    }
+    Ok(())
+}
+
+/// Copy the host directory `dir` into `fs`, preserving modes, ownership,
+/// modification times, symlinks and hard links
+///
+/// Paths matched by the `.agentfsignore` file of `dir` are left out.
+#[cfg(unix)]
+pub async fn copy_host_dir(dir: &Path, fs: &dyn FileSystem) -> AnyhowResult<()> {
+    let ignore = IgnoreRules::load(dir)
+        .with_context(|| format!("Failed to read the ignore file of {}", dir.display()))?;
+    let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let root = dir.to_path_buf();
+    let reader = tokio::task::spawn_blocking(move || read_host_dir(&root, &ignore, tx));
+
+    let copied = populate(fs, &mut rx).await;
+    drop(rx);
+    // A failed reader stops the copy, so its error comes first
+    reader
+        .await
+        .context("Directory reader panicked")?
+        .with_context(|| format!("Failed to read {}", dir.display()))
+        .and(copied)
+}
+
+/// Send the entries below the host directory `root` to the importer, parents first
+#[cfg(unix)]
+fn read_host_dir(
+    root: &Path,
+    ignore: &IgnoreRules,
+    tx: mpsc::Sender<ArchiveItem>,
+) -> AnyhowResult<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let send = |item| {
+        tx.blocking_send(item)
+            .map_err(|_| anyhow::anyhow!("Import stopped"))
+    };
+
+    // Path of the first entry of each host inode with several links
+    let mut linked: HashMap<(u64, u64), String> = HashMap::new();
+    let mut queue = VecDeque::from([String::new()]);
+    while let Some(dir) = queue.pop_front() {
+        let mut entries = std::fs::read_dir(root.join(&dir))?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name
+                .to_str()
+                .with_context(|| format!("Path is not UTF-8: {}", entry.path().display()))?;
+            let path = if dir.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            let metadata = entry.metadata()?;
+            let file_type = metadata.file_type();
+            if ignore.is_ignored(&path, file_type.is_dir()) {
+                continue;
+            }
+
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(metadata.mode() & 0o7777);
+            header.set_uid(metadata.uid() as u64);
+            header.set_gid(metadata.gid() as u64);
+            header.set_mtime(metadata.mtime().max(0) as u64);
+            header.set_size(0);
+
+            if file_type.is_dir() {
+                header.set_entry_type(tar::EntryType::Directory);
+                send(ArchiveItem::Entry {
+                    header,
+                    path: path.clone(),
+                })?;
+                queue.push_back(path);
+            } else if file_type.is_symlink() {
+                let target = std::fs::read_link(entry.path())?;
+                let target = target
+                    .to_str()
+                    .with_context(|| format!("Symlink target is not UTF-8: {}", path))?
+                    .to_string();
+                header.set_entry_type(tar::EntryType::Symlink);
+                send(ArchiveItem::Link {
+                    header,
+                    path,
+                    target,
+                })?;
+            } else if file_type.is_file() {
+                if metadata.nlink() > 1 {
+                    let key = (metadata.dev(), metadata.ino());
+                    if let Some(target) = linked.get(&key) {
+                        header.set_entry_type(tar::EntryType::Link);
+                        send(ArchiveItem::Link {
+                            header,
+                            path,
+                            target: target.clone(),
+                        })?;
+                        continue;
+                    }
+                    linked.insert(key, path.clone());
+                }
+                let size = metadata.len();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(size);
+                send(ArchiveItem::Entry {
+                    header,
+                    path: path.clone(),
+                })?;
+
+                let mut file = std::fs::File::open(entry.path())?.take(size);
+                let mut sent = 0;
+                while sent < size {
+                    let mut data = Vec::with_capacity(READ_SIZE as usize);
+                    (&mut file).take(READ_SIZE).read_to_end(&mut data)?;
+                    if data.is_empty() {
+                        anyhow::bail!("File shrank while being copied: {}", path);
+                    }
+                    sent += data.len() as u64;
+                    send(ArchiveItem::Data(data))?;
+                }
+            } else {
+                tracing::warn!("Skipping special file: {}", path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Turn the path of an archive entry into a path relative to the root
+///
+/// Fails for paths that would escape the root with `..`. Returns `None` for
+/// the root itself.
+fn archive_path(path: &Path) -> AnyhowResult<Option<String>> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(
+                part.to_str()
+                    .with_context(|| format!("Path is not UTF-8: {}", path.display()))?,
+            ),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                anyhow::bail!("Refusing path that escapes the archive: {}", path.display())
+            }
+        }
+    }
+    Ok((!parts.is_empty()).then(|| parts.join("/")))
+}
+
+/// Create the entries received from `rx` in `fs`
+async fn populate(fs: &dyn FileSystem, rx: &mut mpsc::Receiver<ArchiveItem>) -> AnyhowResult<()> {
+    // Creating entries changes the times of their directory, so these are set last
+    let mut dirs = Vec::new();
+    while let Some(item) = rx.recv().await {
+        match item {
+            ArchiveItem::Entry { header, path } if header.entry_type().is_dir() => {
+                let path = format!("/{}", path);
+                create_dirs(fs, &path).await?;
+                dirs.push((path, header));
+            }
+            ArchiveItem::Entry { header, path } => {
+                let path = format!("/{}", path);
+                create_parent_dirs(fs, &path).await?;
+                fs.write_file(&path, b"")
+                    .await
+                    .with_context(|| format!("Failed to create {}", path))?;
+                let file = fs.open(&path).await?;
+                let size = header.size()?;
+                let mut offset = 0;
+                while offset < size {
+                    let Some(ArchiveItem::Data(data)) = rx.recv().await else {
+                        anyhow::bail!("Truncated content of {}", path);
+                    };
+                    file.pwrite(offset, &data).await?;
+                    offset += data.len() as u64;
+                }
+                set_metadata(fs, &path, &header).await?;
+            }
+            ArchiveItem::Link {
+                header,
+                path,
+                target,
+            } => {
+                let path = format!("/{}", path);
+                create_parent_dirs(fs, &path).await?;
+                if header.entry_type() == tar::EntryType::Link {
+                    fs.link(&format!("/{}", target), &path)
+                        .await
+                        .with_context(|| format!("Failed to link {} to /{}", path, target))?;
+                } else {
+                    fs.symlink(&target, &path)
+                        .await
+                        .with_context(|| format!("Failed to create symlink {}", path))?;
+                    let mtime = header.mtime()? as i64;
+                    fs.utimes(&path, Some(mtime), Some(mtime)).await?;
+                }
+            }
+            ArchiveItem::Data(_) => anyhow::bail!("Unexpected file content"),
+        }
+    }
+    for (path, header) in dirs.iter().rev() {
+        set_metadata(fs, path, header).await?;
+    }
+    Ok(())
+}
+
+/// Create directory `path` and its missing parents
+async fn create_dirs(fs: &dyn FileSystem, path: &str) -> AnyhowResult<()> {
+    let mut current = String::new();
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        current = format!("{}/{}", current, part);
+        match fs.lstat(&current).await? {
+            Some(stats) if stats.is_directory() => {}
+            Some(_) => anyhow::bail!("Not a directory: {}", current),
+            None => fs.mkdir(&current).await?,
+        }
+    }
+    Ok(())
+}
+
+async fn create_parent_dirs(fs: &dyn FileSystem, path: &str) -> AnyhowResult<()> {
+    match path.rsplit_once('/') {
+        Some((parent, _)) => create_dirs(fs, parent).await,
+        None => Ok(()),
+    }
+}
+
+/// Set the mode, ownership and times of `path` from `header`
+async fn set_metadata(fs: &dyn FileSystem, path: &str, header: &tar::Header) -> AnyhowResult<()> {
+    fs.chmod(path, header.mode()? & 0o7777).await?;
+    fs.chown(path, Some(header.uid()? as u32), Some(header.gid()? as u32))
+        .await?;
+    let mtime = header.mtime()? as i64;
+    fs.utimes(path, Some(mtime), Some(mtime)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use tempfile::{tempdir, NamedTempFile};
+
+    use crate::cmd::archive::{export_filesystem, import_archive};
+    use crate::parser::ChecksumAlgorithm;
+
+    async fn agentfs() -> (AgentFS, String, NamedTempFile) {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(path.to_string()))
+            .await
+            .unwrap();
+        (agentfs, file.path().to_str().unwrap().to_string(), file)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn export_tar_gz() {
+        let (agentfs, path, _file) = agentfs().await;
+        let big: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        agentfs.fs.mkdir("/dir").await.unwrap();
+        agentfs.fs.write_file("/dir/big.bin", &big).await.unwrap();
+        agentfs.fs.chmod("/dir/big.bin", 0o600).await.unwrap();
+        agentfs.fs.symlink("big.bin", "/dir/link").await.unwrap();
+        agentfs.fs.link("/dir/big.bin", "/hard").await.unwrap();
+
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("out.tar.gz");
+        export_filesystem(&mut io::sink(), path, &output, None)
+            .await
+            .unwrap();
+
+        let decoder = flate2::read::GzDecoder::new(std::fs::File::open(&output).unwrap());
+        let mut archive = tar::Archive::new(decoder);
+        let mut seen = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let entry_path = entry.path().unwrap().to_string_lossy().into_owned();
+            let entry_path = entry_path.trim_end_matches('/').to_string();
+            let header = entry.header().clone();
+            match entry_path.as_str() {
+                "hard" => {
+                    let mut content = Vec::new();
+                    entry.read_to_end(&mut content).unwrap();
+                    assert_eq!(content, big);
+                    assert_eq!(header.mode().unwrap(), 0o600);
+                }
+                "dir/link" => {
+                    assert_eq!(header.entry_type(), tar::EntryType::Symlink);
+                    let target = header.link_name().unwrap().unwrap();
+                    assert_eq!(target.to_str(), Some("big.bin"));
+                }
+                // The first path of a hard-linked file holds its content
+                "dir/big.bin" => {
+                    assert_eq!(header.entry_type(), tar::EntryType::Link);
+                    let target = header.link_name().unwrap().unwrap();
+                    assert_eq!(target.to_str(), Some("hard"));
+                }
+                _ => {}
+            }
+            seen.push(entry_path);
+        }
+        assert_eq!(seen, vec!["dir", "hard", "dir/big.bin", "dir/link"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn export_checksum() {
+        let (agentfs, path, _file) = agentfs().await;
+        let big: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+        agentfs.fs.write_file("/big.bin", &big).await.unwrap();
+
+        let dir = tempdir().unwrap();
+        for name in ["out.tar", "out.tar.gz"] {
+            let output = dir.path().join(name);
+            let mut stderr = Vec::new();
+            export_filesystem(
+                &mut stderr,
+                path.clone(),
+                &output,
+                Some(ChecksumAlgorithm::Blake3),
+            )
+            .await
+            .unwrap();
+
+            // The hash is of the archive as written, compressed or not
+            let archive = std::fs::read(&output).unwrap();
+            let expected = format!(
+                "{}  {}\n",
+                blake3::hash(&archive).to_hex(),
+                output.display()
+            );
+            assert_eq!(String::from_utf8(stderr).unwrap(), expected);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn export_import_round_trip() {
+        let (agentfs, path, _file) = agentfs().await;
+        let fs = &agentfs.fs;
+        fs.mkdir("/dir").await.unwrap();
+        fs.mkdir("/dir/empty").await.unwrap();
+        fs.write_file("/dir/a.txt", b"hello").await.unwrap();
+        fs.chmod("/dir/a.txt", 0o750).await.unwrap();
+        fs.symlink("a.txt", "/dir/link").await.unwrap();
+        fs.link("/dir/a.txt", "/dir/hard").await.unwrap();
+        fs.utimes("/dir/a.txt", None, Some(1_000_000))
+            .await
+            .unwrap();
+        fs.utimes("/dir", None, Some(2_000_000)).await.unwrap();
+
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("out.tar");
+        export_filesystem(&mut io::sink(), path, &archive, None)
+            .await
+            .unwrap();
+
+        let imported_path = dir.path().join("imported.db");
+        let imported_path = imported_path.to_str().unwrap().to_string();
+        import_archive(&archive, AgentFSOptions::with_path(imported_path.clone()))
+            .await
+            .unwrap();
+
+        let imported = AgentFS::open(AgentFSOptions::with_path(imported_path.clone()))
+            .await
+            .unwrap();
+        let fs = &imported.fs;
+        assert_eq!(fs.read_file("/dir/a.txt").await.unwrap().unwrap(), b"hello");
+        let stats = fs.stat("/dir/a.txt").await.unwrap().unwrap();
+        assert_eq!(stats.mode & 0o7777, 0o750);
+        assert_eq!(stats.mtime, 1_000_000);
+        assert_eq!(stats.nlink, 2);
+        let hard = fs.stat("/dir/hard").await.unwrap().unwrap();
+        assert_eq!(hard.ino, stats.ino);
+        assert_eq!(fs.readlink("/dir/link").await.unwrap().unwrap(), "a.txt");
+        assert!(fs.stat("/dir/empty").await.unwrap().unwrap().is_directory());
+        assert_eq!(fs.stat("/dir").await.unwrap().unwrap().mtime, 2_000_000);
+
+        // The filesystem must be new
+        let options = AgentFSOptions::with_path(imported_path);
+        assert!(import_archive(&archive, options).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn import_rejects_path_traversal() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("evil.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        // set_path() refuses `..`, so the name is written directly
+        let name = b"../evil.txt";
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"evil"[..]).unwrap();
+        builder.finish().unwrap();
+
+        let db_path = dir.path().join("imported.db");
+        let err = import_archive(
+            &archive,
+            AgentFSOptions::with_path(db_path.to_str().unwrap().to_string()),
+        )
+        .await
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("escapes the archive"));
+        assert!(!db_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    pub async fn copy_host_dir_with_ignore_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::cmd::archive::copy_host_dir;
+
+        let src = tempdir().unwrap();
+        let root = src.path();
+        std::fs::create_dir_all(root.join("src/target")).unwrap();
+        std::fs::create_dir(root.join("target")).unwrap();
+        std::fs::write(root.join("target/out.bin"), b"build").unwrap();
+        std::fs::write(root.join("src/main.rs"), b"fn main() {}").unwrap();
+        std::fs::write(root.join("src/debug.log"), b"log").unwrap();
+        let mode = std::fs::Permissions::from_mode(0o750);
+        std::fs::set_permissions(root.join("src/main.rs"), mode).unwrap();
+        std::os::unix::fs::symlink("main.rs", root.join("src/link")).unwrap();
+        std::fs::hard_link(root.join("src/main.rs"), root.join("hard")).unwrap();
+        std::fs::write(root.join(".agentfsignore"), "target/\n*.log\n").unwrap();
+
+        let (agentfs, _path, _file) = agentfs().await;
+        copy_host_dir(root, &agentfs.fs).await.unwrap();
+
+        let fs = &agentfs.fs;
+        assert_eq!(
+            fs.read_file("/src/main.rs").await.unwrap().unwrap(),
+            b"fn main() {}"
+        );
+        let stats = fs.stat("/src/main.rs").await.unwrap().unwrap();
+        assert_eq!(stats.mode & 0o7777, 0o750);
+        let host_mtime = std::fs::metadata(root.join("src/main.rs"))
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert_eq!(stats.mtime, host_mtime as i64);
+        assert_eq!(fs.stat("/hard").await.unwrap().unwrap().ino, stats.ino);
+        assert_eq!(fs.readlink("/src/link").await.unwrap().unwrap(), "main.rs");
+        assert!(fs.stat("/.agentfsignore").await.unwrap().is_some());
+
+        assert!(fs.stat("/target").await.unwrap().is_none());
+        assert!(fs.stat("/src/target").await.unwrap().is_none());
+        assert!(fs.stat("/src/debug.log").await.unwrap().is_none());
+    }
+}
@@ -1,10 +1,14 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 
-use agentfs_sdk::AgentFSOptions;
+use agentfs_sdk::error::Error as SdkError;
+use agentfs_sdk::glob::Glob;
+use agentfs_sdk::{AgentFSOptions, DirEntry, FsError, ManifestEntry};
 use anyhow::{Context, Result as AnyhowResult};
+use serde::Serialize;
 use turso::Value;
 
 use crate::cmd::init::open_agentfs;
+use crate::parser::ChecksumAlgorithm;
 
 const ROOT_INO: i64 = 1;
 const S_IFMT: u32 = 0o170000;
@@ -12,10 +16,19 @@ const S_IFDIR: u32 = 0o040000;
 const S_IFREG: u32 = 0o100000;
 const S_IFLNK: u32 = 0o120000;
 
+/// Size of the reads of file content by `cat`
+const CAT_READ_SIZE: u64 = 64 * 1024;
+
+/// List every entry of the filesystem, breadth first.
+///
+/// Each entry is printed as `f`, `d` or `l` for files, directories and
+/// symlinks, followed by its path, and with `inode`, preceded by its inode
+/// number, which hard links share.
 pub async fn ls_filesystem(
     stdout: &mut impl std::io::Write,
     id_or_path: String,
     path: &str,
+    inode: bool,
 ) -> AnyhowResult<()> {
     let options = AgentFSOptions::resolve(&id_or_path)?;
     eprintln!("Using agent: {}", id_or_path);
@@ -75,13 +88,20 @@ pub async fn ls_filesystem(
 
         for (name, ino, mode) in entries {
             let is_dir = mode & S_IFMT == S_IFDIR;
-            let type_char = if is_dir { 'd' } else { 'f' };
+            let type_char = match mode & S_IFMT {
+                S_IFDIR => 'd',
+                S_IFLNK => 'l',
+                _ => 'f',
+            };
             let full_path = if prefix.is_empty() {
                 name.clone()
             } else {
                 format!("{}/{}", prefix, name)
             };
 
+            if inode {
+                write!(stdout, "{} ", ino).context("Failed to write to stdout")?;
+            }
             stdout
                 .write_fmt(format_args!("{} {}\n", type_char, full_path))
                 .context("Failed to write to stdout")?;
@@ -95,27 +115,497 @@ pub async fn ls_filesystem(
     Ok(())
 }
 
+/// Print the directory tree under `path`, descending at most `max_depth` levels.
+///
+/// Symlinks are printed with their target but not followed, and each directory
+/// inode is visited once, so links can't make the walk loop forever.
+pub async fn tree_filesystem(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    path: &str,
+    max_depth: Option<usize>,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+
+    let root = match agentfs.fs.stat(path).await? {
+        Some(stats) if stats.is_directory() => stats,
+        Some(_) => anyhow::bail!("Not a directory: {}", path),
+        None => anyhow::bail!("Directory not found: {}", path),
+    };
+    writeln!(stdout, "{}", path).context("Failed to write to stdout")?;
+
+    // Entries still to print, as (entry, full path, line prefix, is last, depth)
+    let mut stack = Vec::new();
+    let mut visited = HashSet::from([root.ino]);
+    let mut dirs = 0;
+    let mut files = 0;
+
+    let entries = agentfs.fs.readdir_plus(path).await?.unwrap_or_default();
+    push_tree_entries(&mut stack, entries, path, "", 1);
+
+    while let Some((entry, full_path, prefix, is_last, depth)) = stack.pop() {
+        let connector = if is_last { "└── " } else { "├── " };
+        let stats = &entry.stats;
+        let label = if stats.is_directory() {
+            format!("{}/", entry.name)
+        } else if stats.is_symlink() {
+            let target = agentfs.fs.readlink(&full_path).await?.unwrap_or_default();
+            format!("{} -> {}", entry.name, target)
+        } else {
+            format!("{} ({} bytes)", entry.name, stats.size)
+        };
+        writeln!(stdout, "{}{}{}", prefix, connector, label)
+            .context("Failed to write to stdout")?;
+
+        if !stats.is_directory() {
+            files += 1;
+            continue;
+        }
+        dirs += 1;
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) || !visited.insert(stats.ino) {
+            continue;
+        }
+        let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+        let entries = agentfs
+            .fs
+            .readdir_plus(&full_path)
+            .await?
+            .unwrap_or_default();
+        push_tree_entries(&mut stack, entries, &full_path, &child_prefix, depth + 1);
+    }
+
+    writeln!(
+        stdout,
+        "\n{} director{}, {} file{}",
+        dirs,
+        if dirs == 1 { "y" } else { "ies" },
+        files,
+        if files == 1 { "" } else { "s" }
+    )
+    .context("Failed to write to stdout")?;
+    Ok(())
+}
+
+/// Push the entries of the directory at `dir` onto the `tree_filesystem` stack,
+/// in reverse so that they are popped in order.
+fn push_tree_entries(
+    stack: &mut Vec<(DirEntry, String, String, bool, usize)>,
+    entries: Vec<DirEntry>,
+    dir: &str,
+    prefix: &str,
+    depth: usize,
+) {
+    let count = entries.len();
+    for (i, entry) in entries.into_iter().enumerate().rev() {
+        let full_path = format!("{}/{}", dir.trim_end_matches('/'), entry.name);
+        stack.push((entry, full_path, prefix.to_string(), i + 1 == count, depth));
+    }
+}
+
+/// Print the metadata of `path` as `key: value` lines.
+///
+/// Symlinks are reported themselves, unless `follow` is set.
+pub async fn stat_filesystem(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    path: &str,
+    follow: bool,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+
+    let stats = if follow {
+        agentfs.fs.stat(path).await?
+    } else {
+        agentfs.fs.lstat(path).await?
+    };
+    let Some(stats) = stats else {
+        anyhow::bail!("File not found: {}", path);
+    };
+
+    let file_type = match stats.mode & S_IFMT {
+        S_IFDIR => "directory",
+        S_IFLNK => "symlink",
+        S_IFREG => "file",
+        _ => "other",
+    };
+    write!(
+        stdout,
+        "path: {}\ntype: {}\nsize: {}\nmode: {:04o} ({})\nuid: {}\ngid: {}\nnlink: {}\n\
+         ino: {}\natime: {}\nmtime: {}\nctime: {}\n",
+        path,
+        file_type,
+        stats.size,
+        stats.mode & 0o7777,
+        mode_string(stats.mode),
+        stats.uid,
+        stats.gid,
+        stats.nlink,
+        stats.ino,
+        stats.atime,
+        stats.mtime,
+        stats.ctime,
+    )
+    .context("Failed to write to stdout")?;
+    Ok(())
+}
+
+/// Format a mode like `ls -l` does, e.g. `drwxr-xr-x`.
+fn mode_string(mode: u32) -> String {
+    let type_char = match mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFREG => '-',
+        _ => '?',
+    };
+    let mut s = String::from(type_char);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+/// Print the paths under `root` (including `root` itself) whose basename matches the
+/// `name` glob and whose type (`f`, `d` or `l`) matches `file_type`.
+///
+/// Paths are written as they are found, one directory listing at a time, and
+/// symlinks are not followed.
+pub async fn find_filesystem(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    root: &str,
+    name: Option<&str>,
+    file_type: Option<&str>,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+
+    let Some(stats) = agentfs.fs.lstat(root).await? else {
+        anyhow::bail!("File not found: {}", root);
+    };
+
+    let name = name.map(Glob::new);
+    let matches = |path: &str, mode: u32| {
+        let basename = path.rsplit('/').next().unwrap_or(path);
+        name.as_ref().is_none_or(|name| name.matches(&[basename]))
+            && file_type.is_none_or(|file_type| file_type.starts_with(file_type_char(mode)))
+    };
+
+    if matches(root, stats.mode) {
+        writeln!(stdout, "{}", root).context("Failed to write to stdout")?;
+    }
+    if !stats.is_directory() {
+        return Ok(());
+    }
+
+    let mut dirs = vec![root.trim_end_matches('/').to_string()];
+    while let Some(dir) = dirs.pop() {
+        let entries = agentfs.fs.readdir_plus(&dir).await?.unwrap_or_default();
+        for entry in entries {
+            let path = format!("{}/{}", dir, entry.name);
+            if matches(&path, entry.stats.mode) {
+                writeln!(stdout, "{}", path).context("Failed to write to stdout")?;
+            }
+            if entry.stats.is_directory() {
+                dirs.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the disk usage of `path` and each directory below it, deepest first, as
+/// `<logical bytes>\t<allocated bytes>\t<path>` lines.
+///
+/// The logical size sums file sizes, while the allocated size counts the data
+/// chunks stored for each file, so sparse files use less and partially filled
+/// chunks more. With `stored`, the allocated size is the bytes actually stored
+/// for the chunks instead, which are fewer on compressed filesystems. Files
+/// with several hard links are counted once. With `summarize` only the total
+/// for `path` is printed.
+pub async fn du_filesystem(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    path: &str,
+    summarize: bool,
+    stored: bool,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+
+    let Some(stats) = agentfs.fs.lstat(path).await? else {
+        anyhow::bail!("File not found: {}", path);
+    };
+
+    let usage = agentfs
+        .fs
+        .data_usage()
+        .await
+        .context("Failed to query data chunks")?;
+    let chunk_size = agentfs.fs.chunk_size() as u64;
+    let allocated = |ino: i64| {
+        let usage = usage.get(&ino).copied().unwrap_or_default();
+        if stored {
+            usage.stored_bytes
+        } else {
+            usage.chunks * chunk_size
+        }
+    };
+
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    };
+    if !stats.is_directory() {
+        writeln!(stdout, "{}\t{}\t{}", stats.size, allocated(stats.ino), path)
+            .context("Failed to write to stdout")?;
+        return Ok(());
+    }
+
+    // Directories being walked, as (path, remaining entries, logical, allocated)
+    let entries = agentfs.fs.readdir_plus(path).await?.unwrap_or_default();
+    let mut stack = vec![(
+        path.to_string(),
+        entries.into_iter(),
+        stats.size as u64,
+        allocated(stats.ino),
+    )];
+    let mut seen = HashSet::from([stats.ino]);
+    while let Some((dir, entries, logical, alloc)) = stack.last_mut() {
+        if let Some(entry) = entries.next() {
+            if !seen.insert(entry.stats.ino) {
+                continue;
+            }
+            let size = entry.stats.size as u64;
+            if entry.stats.is_directory() {
+                let child = format!("{}/{}", dir.trim_end_matches('/'), entry.name);
+                let entries = agentfs.fs.readdir_plus(&child).await?.unwrap_or_default();
+                stack.push((child, entries.into_iter(), size, allocated(entry.stats.ino)));
+            } else {
+                *logical += size;
+                *alloc += allocated(entry.stats.ino);
+            }
+            continue;
+        }
+
+        // All entries are counted, so the directory's total is final
+        let (dir, _, logical, alloc) = stack.pop().unwrap();
+        if !summarize || stack.is_empty() {
+            writeln!(stdout, "{}\t{}\t{}", logical, alloc, dir)
+                .context("Failed to write to stdout")?;
+        }
+        if let Some((_, _, parent_logical, parent_alloc)) = stack.last_mut() {
+            *parent_logical += logical;
+            *parent_alloc += alloc;
+        }
+    }
+    Ok(())
+}
+
+/// Write the content of the file at `path` to `stdout`.
+///
+/// The file is read and written `CAT_READ_SIZE` bytes at a time, so that large
+/// files are never held in memory whole. With `checksum`, the content is hashed
+/// as it goes by, and the hash is printed to `stderr` at the end.
 pub async fn cat_filesystem(
     stdout: &mut impl std::io::Write,
+    stderr: &mut impl std::io::Write,
     id_or_path: String,
     path: &str,
+    checksum: Option<ChecksumAlgorithm>,
 ) -> AnyhowResult<()> {
     let options = AgentFSOptions::resolve(&id_or_path)?;
     let (_, agentfs) = open_agentfs(options).await?;
 
-    match agentfs.fs.read_file(path).await? {
-        Some(file) => {
-            stdout.write_all(&file)?;
-            Ok(())
+    let file = match agentfs.fs.open(path).await {
+        Ok(file) => file,
+        Err(SdkError::Fs(FsError::NotFound)) => anyhow::bail!("File not found: {}", path),
+        Err(e) => return Err(e.into()),
+    };
+    let size = file.fstat().await?.size.max(0) as u64;
+    let mut hasher = checksum.map(|algorithm| match algorithm {
+        ChecksumAlgorithm::Blake3 => blake3::Hasher::new(),
+    });
+    let mut offset = 0;
+    while offset < size {
+        let len = CAT_READ_SIZE.min(size - offset);
+        let data = file.pread(offset, len).await?;
+        stdout.write_all(&data)?;
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&data);
         }
-        None => anyhow::bail!("File not found: {}", path),
+        offset += len;
+    }
+
+    if let Some(hasher) = hasher {
+        stdout.flush().context("Failed to write to stdout")?;
+        writeln!(stderr, "{}  {}", hasher.finalize().to_hex(), path)
+            .context("Failed to write to stderr")?;
     }
+    Ok(())
 }
 
 pub async fn write_filesystem(id_or_path: String, path: &str, content: &str) -> AnyhowResult<()> {
     let options = AgentFSOptions::resolve(&id_or_path)?;
     let (_, agentfs) = open_agentfs(options).await?;
 
+    create_parent_dirs(&agentfs.fs, path).await?;
+    agentfs.fs.write_file(path, content.as_bytes()).await?;
+    Ok(())
+}
+
+/// Copy a host file, or `stdin` if `host_path` is `-`, to `dest_path` in the filesystem.
+pub async fn put_filesystem(
+    stdin: &mut impl std::io::Read,
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    host_path: &str,
+    dest_path: &str,
+    no_clobber: bool,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+
+    if no_clobber && agentfs.fs.lstat(dest_path).await?.is_some() {
+        anyhow::bail!("File already exists: {}", dest_path);
+    }
+
+    let content = if host_path == "-" {
+        let mut content = Vec::new();
+        stdin
+            .read_to_end(&mut content)
+            .context("Failed to read from stdin")?;
+        content
+    } else {
+        std::fs::read(host_path).with_context(|| format!("Failed to read {}", host_path))?
+    };
+
+    create_parent_dirs(&agentfs.fs, dest_path).await?;
+    agentfs.fs.write_file(dest_path, &content).await?;
+
+    stdout
+        .write_fmt(format_args!(
+            "Wrote {} bytes to {}\n",
+            content.len(),
+            dest_path
+        ))
+        .context("Failed to write to stdout")?;
+    Ok(())
+}
+
+pub async fn mkdir_filesystem(id_or_path: String, path: &str, parents: bool) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    };
+    if let Some(stats) = agentfs.fs.lstat(path).await? {
+        if parents && stats.is_directory() {
+            return Ok(());
+        }
+        anyhow::bail!("File exists: {}", path);
+    }
+
+    if parents {
+        create_parent_dirs(&agentfs.fs, path).await?;
+    }
+    agentfs
+        .fs
+        .mkdir(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to create directory {}: {}", path, e))
+}
+
+/// Copy `src` to `dest` within the filesystem, preserving modes and timestamps.
+///
+/// If `dest` is an existing directory, `src` is copied into it under its own name,
+/// and an existing file at the destination is overwritten. Directories are only
+/// copied with `recursive`, and symlinks are copied as links.
+pub async fn cp_filesystem(
+    id_or_path: String,
+    src: &str,
+    dest: &str,
+    recursive: bool,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+    let fs = &agentfs.fs;
+
+    let src = src.trim_end_matches('/');
+    let Some(stats) = fs.lstat(src).await? else {
+        anyhow::bail!("File not found: {}", src);
+    };
+    if stats.is_directory() && !recursive {
+        anyhow::bail!("Is a directory: {} (use -r to copy it recursively)", src);
+    }
+
+    let mut dest = dest.trim_end_matches('/').to_string();
+    let dest_stats = fs.stat(&dest).await?;
+    if dest_stats.is_some_and(|stats| stats.is_directory()) {
+        let name = src.rsplit('/').next().unwrap_or(src);
+        dest = format!("{}/{}", dest, name);
+    }
+    if stats.is_directory() && (dest == src || dest.starts_with(&format!("{}/", src))) {
+        anyhow::bail!("Cannot copy directory {} into itself", src);
+    }
+
+    // Directories get their mode and times once their contents are copied, as
+    // adding entries would otherwise update them
+    let mut dirs = Vec::new();
+    let mut stack = vec![(src.to_string(), dest, stats)];
+    while let Some((src, dest, stats)) = stack.pop() {
+        let existing = fs.lstat(&dest).await?;
+        if stats.is_directory() {
+            match existing {
+                Some(existing) if existing.is_directory() => {}
+                Some(_) => anyhow::bail!("Cannot overwrite non-directory {}", dest),
+                None => fs.mkdir(&dest).await?,
+            }
+            for entry in fs.readdir_plus(&src).await?.unwrap_or_default() {
+                stack.push((
+                    format!("{}/{}", src, entry.name),
+                    format!("{}/{}", dest, entry.name),
+                    entry.stats,
+                ));
+            }
+            dirs.push((dest, stats));
+            continue;
+        }
+
+        if matches!(&existing, Some(existing) if existing.is_directory()) {
+            anyhow::bail!("Cannot overwrite directory {}", dest);
+        }
+        if stats.is_symlink() {
+            let target = fs.readlink(&src).await?.unwrap_or_default();
+            if existing.is_some() {
+                fs.remove(&dest).await?;
+            }
+            fs.symlink(&target, &dest).await?;
+        } else {
+            let content = fs.read_file(&src).await?.unwrap_or_default();
+            fs.write_file(&dest, &content).await?;
+            fs.chmod(&dest, stats.mode).await?;
+        }
+        fs.utimes(&dest, Some(stats.atime), Some(stats.mtime))
+            .await?;
+    }
+
+    for (dest, stats) in dirs.iter().rev() {
+        fs.chmod(dest, stats.mode).await?;
+        fs.utimes(dest, Some(stats.atime), Some(stats.mtime))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Create the missing parent directories of `path`.
+async fn create_parent_dirs(fs: &agentfs_sdk::filesystem::AgentFS, path: &str) -> AnyhowResult<()> {
     let mut components = path.split("/").collect::<Vec<_>>();
     if !path.starts_with("/") {
         components.insert(0, "");
@@ -124,16 +614,70 @@ pub async fn write_filesystem(id_or_path: String, path: &str, content: &str) ->
     // we must start with /a (first TWO entries)
     for i in 2..components.len() {
         let dir_path = components[0..i].join("/");
-        if agentfs.fs.stat(&dir_path).await?.is_none() {
-            agentfs.fs.mkdir(&dir_path).await?;
+        if fs.stat(&dir_path).await?.is_none() {
+            fs.mkdir(&dir_path).await?;
         }
     }
-    agentfs.fs.write_file(path, content.as_bytes()).await?;
+    Ok(())
+}
+
+pub async fn rm_filesystem(id_or_path: String, path: &str, recursive: bool) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agentfs) = open_agentfs(options).await?;
+
+    let stats = match agentfs.fs.lstat(path).await? {
+        Some(stats) => stats,
+        None => anyhow::bail!("File not found: {}", path),
+    };
+
+    if !stats.is_directory() {
+        agentfs.fs.remove(path).await?;
+        return Ok(());
+    }
+
+    // Collect the directory tree breadth-first, so that removing it in reverse
+    // order deletes every entry before its parent
+    let mut paths = vec![path.trim_end_matches('/').to_string()];
+    let mut next = 0;
+    while next < paths.len() {
+        let dir = paths[next].clone();
+        next += 1;
+
+        let entries = agentfs.fs.readdir(&dir).await?.unwrap_or_default();
+        if !entries.is_empty() && !recursive {
+            anyhow::bail!(
+                "Directory not empty: {} (use -r to remove it recursively)",
+                path
+            );
+        }
+        for name in entries {
+            let child = format!("{}/{}", dir, name);
+            let is_dir = agentfs
+                .fs
+                .lstat(&child)
+                .await?
+                .is_some_and(|stats| stats.is_directory());
+            if is_dir {
+                paths.push(child);
+            } else {
+                agentfs.fs.remove(&child).await?;
+            }
+        }
+    }
+
+    for dir in paths.iter().rev() {
+        agentfs
+            .fs
+            .remove(dir)
+            .await
+            .with_context(|| format!("Failed to remove directory {}", dir))?;
+    }
     Ok(())
 }
 
 /// Represents a change type in the overlay filesystem
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 enum ChangeType {
     Added,
     Modified,
@@ -160,13 +704,82 @@ fn file_type_char(mode: u32) -> char {
     }
 }
 
+/// Get file type name, as used in JSON output
+fn file_type_name(type_char: char) -> &'static str {
+    match type_char {
+        'd' => "directory",
+        'l' => "symlink",
+        'f' => "file",
+        _ => "unknown",
+    }
+}
+
+/// A changed path, as printed by `diff`
+#[derive(Debug, Serialize)]
+struct Change {
+    path: String,
+    change: ChangeType,
+    #[serde(skip)]
+    type_char: char,
+    #[serde(rename = "type")]
+    file_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_delta: Option<i64>,
+}
+
+impl Change {
+    fn new(change: ChangeType, type_char: char, path: String) -> Self {
+        Self {
+            path,
+            change,
+            type_char,
+            file_type: file_type_name(type_char),
+            old_size: None,
+            new_size: None,
+            size_delta: None,
+        }
+    }
+}
+
+/// Print `changes` in `format`, `text` or `json`
+fn print_changes(
+    stdout: &mut impl std::io::Write,
+    changes: &[Change],
+    format: &str,
+) -> AnyhowResult<()> {
+    if format == "json" {
+        serde_json::to_writer_pretty(&mut *stdout, changes)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+    if changes.is_empty() {
+        writeln!(stdout, "No changes")?;
+    }
+    for change in changes {
+        let (change_type, type_char, path) = (&change.change, change.type_char, &change.path);
+        match change.size_delta {
+            Some(delta) => writeln!(stdout, "{} {} {} {:+}", change_type, type_char, path, delta)?,
+            None => writeln!(stdout, "{} {} {}", change_type, type_char, path)?,
+        }
+    }
+    Ok(())
+}
+
 /// Check if a path exists in the host filesystem (base layer)
 fn path_exists_in_base(base_path: &str, rel_path: &str) -> bool {
     let full_path = format!("{}{}", base_path, rel_path);
     std::path::Path::new(&full_path).exists()
 }
 
-pub async fn diff_filesystem(id_or_path: String) -> AnyhowResult<()> {
+pub async fn diff_filesystem(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    format: &str,
+) -> AnyhowResult<()> {
     let options = AgentFSOptions::resolve(&id_or_path)?;
     eprintln!("Using agent: {}", id_or_path);
 
@@ -178,7 +791,10 @@ pub async fn diff_filesystem(id_or_path: String) -> AnyhowResult<()> {
     let base_path = match agent.is_overlay_enabled().await? {
         Some(path) => path,
         None => {
-            println!("No diff (non-overlay filesystem)");
+            if format == "json" {
+                return print_changes(stdout, &[], format);
+            }
+            writeln!(stdout, "No diff (non-overlay filesystem)")?;
             return Ok(());
         }
     };
@@ -186,7 +802,7 @@ pub async fn diff_filesystem(id_or_path: String) -> AnyhowResult<()> {
     eprintln!("Base: {}", base_path);
 
     // Collect all changes
-    let mut changes: Vec<(ChangeType, char, String)> = Vec::new();
+    let mut changes: Vec<Change> = Vec::new();
 
     // Get all paths in delta layer
     let delta_paths = agent.get_delta_paths().await?;
@@ -201,10 +817,10 @@ pub async fn diff_filesystem(id_or_path: String) -> AnyhowResult<()> {
 
         if path_exists_in_base(&base_path, path) {
             // File exists in both - it was modified (copy-on-write)
-            changes.push((ChangeType::Modified, type_char, path.clone()));
+            changes.push(Change::new(ChangeType::Modified, type_char, path.clone()));
         } else {
             // File only exists in delta - it was added
-            changes.push((ChangeType::Added, type_char, path.clone()));
+            changes.push(Change::new(ChangeType::Added, type_char, path.clone()));
         }
     }
 
@@ -223,31 +839,107 @@ pub async fn diff_filesystem(id_or_path: String) -> AnyhowResult<()> {
             '?'
         };
 
-        changes.push((ChangeType::Deleted, type_char, path.clone()));
+        changes.push(Change::new(ChangeType::Deleted, type_char, path.clone()));
     }
 
     // Sort changes by path for consistent output
-    changes.sort_by(|a, b| a.2.cmp(&b.2));
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
 
-    // Print changes
-    if changes.is_empty() {
-        println!("No changes");
-    } else {
-        for (change_type, type_char, path) in changes {
-            println!("{} {} {}", change_type, type_char, path);
+    print_changes(stdout, &changes, format)
+}
+
+/// Compare two filesystems or snapshots, each given as `ID_OR_PATH[@SNAPSHOT]`
+///
+/// Files are compared by content hash, type and mode. Only what is stored in
+/// each database is compared: for overlay filesystems, that is the delta.
+pub async fn compare_filesystems(
+    stdout: &mut impl std::io::Write,
+    left: &str,
+    right: &str,
+    format: &str,
+) -> AnyhowResult<()> {
+    let left = read_manifest(left).await?;
+    let right = read_manifest(right).await?;
+
+    let mut changes = Vec::new();
+    for (path, old) in &left {
+        match right.get(path) {
+            None => changes.push(Change {
+                old_size: Some(old.size),
+                size_delta: Some(-(old.size as i64)),
+                ..Change::new(ChangeType::Deleted, file_type_char(old.mode), path.clone())
+            }),
+            Some(new) if new.mode != old.mode || new.hash != old.hash => changes.push(Change {
+                old_size: Some(old.size),
+                new_size: Some(new.size),
+                size_delta: Some(new.size as i64 - old.size as i64),
+                ..Change::new(ChangeType::Modified, file_type_char(new.mode), path.clone())
+            }),
+            Some(_) => {}
+        }
+    }
+    for (path, new) in &right {
+        if !left.contains_key(path) {
+            changes.push(Change {
+                new_size: Some(new.size),
+                size_delta: Some(new.size as i64),
+                ..Change::new(ChangeType::Added, file_type_char(new.mode), path.clone())
+            });
         }
     }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
 
-    Ok(())
+    print_changes(stdout, &changes, format)
+}
+
+/// Read the manifest of `ID_OR_PATH[@SNAPSHOT]`, by path
+async fn read_manifest(spec: &str) -> AnyhowResult<BTreeMap<String, ManifestEntry>> {
+    let (id_or_path, snapshot) = parse_snapshot_ref(spec);
+    let options = AgentFSOptions::resolve(id_or_path)?;
+    eprintln!("Using agent: {}", id_or_path);
+
+    let (_, agentfs) = open_agentfs(options).await?;
+    let manifest = agentfs
+        .fs
+        .manifest(snapshot)
+        .await
+        .with_context(|| format!("Failed to read {}", spec))?;
+    Ok(manifest.into_iter().map(|e| (e.path.clone(), e)).collect())
+}
+
+/// Split `ID_OR_PATH@SNAPSHOT` into its parts
+///
+/// An existing file is taken as a database path, even if its name has an `@`.
+fn parse_snapshot_ref(spec: &str) -> (&str, Option<&str>) {
+    if std::path::Path::new(spec).exists() {
+        return (spec, None);
+    }
+    match spec.rsplit_once('@') {
+        Some((id_or_path, snapshot)) if !snapshot.is_empty() && !snapshot.contains('/') => {
+            (id_or_path, Some(snapshot))
+        }
+        _ => (spec, None),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use agentfs_sdk::{AgentFS, AgentFSOptions, Compression};
     use tempfile::NamedTempFile;
 
     use crate::cmd::fs::cat_filesystem;
+    use crate::cmd::fs::compare_filesystems;
+    use crate::cmd::fs::cp_filesystem;
+    use crate::cmd::fs::du_filesystem;
+    use crate::cmd::fs::find_filesystem;
     use crate::cmd::fs::ls_filesystem;
+    use crate::cmd::fs::mkdir_filesystem;
+    use crate::cmd::fs::parse_snapshot_ref;
+    use crate::cmd::fs::put_filesystem;
+    use crate::cmd::fs::rm_filesystem;
+    use crate::cmd::fs::stat_filesystem;
+    use crate::cmd::fs::tree_filesystem;
+    use crate::parser::ChecksumAlgorithm;
 
     async fn agentfs() -> (AgentFS, String, NamedTempFile) {
         let file = NamedTempFile::new().unwrap();
@@ -262,7 +954,9 @@ mod tests {
     pub async fn cat_file_not_found() {
         let (_agentfs, path, _file) = agentfs().await;
         let mut buf = Vec::new();
-        let err = cat_filesystem(&mut buf, path, "test.md").await.unwrap_err();
+        let err = cat_filesystem(&mut buf, &mut std::io::sink(), path, "test.md", None)
+            .await
+            .unwrap_err();
         assert!(err.to_string().contains("File not found"));
     }
 
@@ -272,7 +966,9 @@ mod tests {
         let content = b"hello, agentfs";
         agentfs.fs.write_file("test.md", content).await.unwrap();
         let mut buf = Vec::new();
-        cat_filesystem(&mut buf, path, "test.md").await.unwrap();
+        cat_filesystem(&mut buf, &mut std::io::sink(), path, "test.md", None)
+            .await
+            .unwrap();
         assert_eq!(buf, content);
     }
 
@@ -282,15 +978,55 @@ mod tests {
         let content = vec![100u8; 4 * 1024 * 1024];
         agentfs.fs.write_file("test.md", &content).await.unwrap();
         let mut buf = Vec::new();
-        cat_filesystem(&mut buf, path, "test.md").await.unwrap();
+        cat_filesystem(&mut buf, &mut std::io::sink(), path, "test.md", None)
+            .await
+            .unwrap();
         assert_eq!(buf, content);
     }
 
+    #[tokio::test]
+    pub async fn cat_checksum() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.write_file("abc.txt", b"abc").await.unwrap();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        cat_filesystem(
+            &mut stdout,
+            &mut stderr,
+            path.clone(),
+            "abc.txt",
+            Some(ChecksumAlgorithm::Blake3),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stdout, b"abc");
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85  abc.txt\n"
+        );
+
+        // Content spanning many reads hashes the same as in one piece
+        let content: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+        agentfs.fs.write_file("big.bin", &content).await.unwrap();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        cat_filesystem(
+            &mut stdout,
+            &mut stderr,
+            path,
+            "big.bin",
+            Some(ChecksumAlgorithm::Blake3),
+        )
+        .await
+        .unwrap();
+        assert_eq!(stdout, content);
+        let expected = format!("{}  big.bin\n", blake3::hash(&content).to_hex());
+        assert_eq!(String::from_utf8(stderr).unwrap(), expected);
+    }
+
     #[tokio::test]
     pub async fn ls_empty() {
         let (_agentfs, path, _file) = agentfs().await;
         let mut buf = Vec::new();
-        ls_filesystem(&mut buf, path, "/").await.unwrap();
+        ls_filesystem(&mut buf, path, "/", false).await.unwrap();
         assert_eq!(buf, b"");
     }
 
@@ -302,7 +1038,7 @@ mod tests {
         let big = vec![100u8; 1024 * 1024];
         agentfs.fs.write_file("3.md", &big).await.unwrap();
         let mut buf = Vec::new();
-        ls_filesystem(&mut buf, path, "/").await.unwrap();
+        ls_filesystem(&mut buf, path, "/", false).await.unwrap();
         assert_eq!(
             buf,
             b"f 1.md
@@ -325,7 +1061,7 @@ f 3.md
         let big = vec![100u8; 1024 * 1024];
         agentfs.fs.write_file("d/e/3.md", &big).await.unwrap();
         let mut buf = Vec::new();
-        ls_filesystem(&mut buf, path, "/").await.unwrap();
+        ls_filesystem(&mut buf, path, "/", false).await.unwrap();
         assert_eq!(
             buf,
             b"d a
@@ -339,4 +1075,456 @@ f d/e/3.md
 "
         );
     }
+
+    #[tokio::test]
+    pub async fn ls_links_and_inodes() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.mkdir("dir").await.unwrap();
+        agentfs.fs.write_file("file.md", b"1").await.unwrap();
+        agentfs.fs.link("/file.md", "/hard.md").await.unwrap();
+        agentfs.fs.symlink("file.md", "/soft.md").await.unwrap();
+        let dir_ino = agentfs.fs.stat("/dir").await.unwrap().unwrap().ino;
+        let file_ino = agentfs.fs.stat("/file.md").await.unwrap().unwrap().ino;
+        let link_ino = agentfs.fs.lstat("/soft.md").await.unwrap().unwrap().ino;
+
+        let mut buf = Vec::new();
+        ls_filesystem(&mut buf, path, "/", true).await.unwrap();
+        // Both names of the hard link report the inode stat reports
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!(
+                "{dir_ino} d dir\n{file_ino} f file.md\n{file_ino} f hard.md\n{link_ino} l soft.md\n"
+            )
+        );
+    }
+
+    #[tokio::test]
+    pub async fn rm_file() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.write_file("test.md", b"1").await.unwrap();
+        rm_filesystem(path, "/test.md", false).await.unwrap();
+        assert!(agentfs.fs.stat("/test.md").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    pub async fn rm_not_found() {
+        let (_agentfs, path, _file) = agentfs().await;
+        let err = rm_filesystem(path, "/test.md", false).await.unwrap_err();
+        assert!(err.to_string().contains("File not found"));
+    }
+
+    #[tokio::test]
+    pub async fn rm_dir_requires_recursive() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.mkdir("a").await.unwrap();
+        agentfs.fs.mkdir("a/b").await.unwrap();
+        agentfs.fs.mkdir("e").await.unwrap();
+        agentfs.fs.write_file("a/b/1.md", b"1").await.unwrap();
+        agentfs.fs.write_file("a/2.md", b"2").await.unwrap();
+
+        let err = rm_filesystem(path.clone(), "/a", false).await.unwrap_err();
+        assert!(err.to_string().contains("Directory not empty"));
+        assert!(agentfs.fs.stat("/a/b/1.md").await.unwrap().is_some());
+
+        // Empty directories are removed without -r
+        rm_filesystem(path.clone(), "/e", false).await.unwrap();
+        assert!(agentfs.fs.stat("/e").await.unwrap().is_none());
+
+        rm_filesystem(path, "/a", true).await.unwrap();
+        assert!(agentfs.fs.stat("/a").await.unwrap().is_none());
+        assert_eq!(agentfs.fs.readdir("/").await.unwrap(), Some(vec![]));
+    }
+
+    #[tokio::test]
+    pub async fn put_host_file() {
+        let (agentfs, path, _file) = agentfs().await;
+        let host = NamedTempFile::new().unwrap();
+        std::fs::write(host.path(), b"hello, agentfs").unwrap();
+
+        let mut out = Vec::new();
+        put_filesystem(
+            &mut std::io::empty(),
+            &mut out,
+            path,
+            host.path().to_str().unwrap(),
+            "/a/b/test.md",
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(out, b"Wrote 14 bytes to /a/b/test.md\n");
+        assert_eq!(
+            agentfs.fs.read_file("/a/b/test.md").await.unwrap().unwrap(),
+            b"hello, agentfs"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn put_stdin_no_clobber() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.write_file("test.md", b"old").await.unwrap();
+
+        let mut out = Vec::new();
+        let err = put_filesystem(
+            &mut &b"new"[..],
+            &mut out,
+            path.clone(),
+            "-",
+            "/test.md",
+            true,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("File already exists"));
+        assert_eq!(
+            agentfs.fs.read_file("/test.md").await.unwrap().unwrap(),
+            b"old"
+        );
+
+        put_filesystem(&mut &b"new"[..], &mut out, path, "-", "/test.md", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            agentfs.fs.read_file("/test.md").await.unwrap().unwrap(),
+            b"new"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn mkdir_dir() {
+        let (agentfs, path, _file) = agentfs().await;
+        mkdir_filesystem(path.clone(), "/a", false).await.unwrap();
+        assert!(agentfs.fs.stat("/a").await.unwrap().unwrap().is_directory());
+
+        let err = mkdir_filesystem(path.clone(), "/a", false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("File exists"));
+
+        let err = mkdir_filesystem(path, "/b/c", false).await.unwrap_err();
+        assert!(err.to_string().contains("Failed to create directory /b/c"));
+    }
+
+    #[tokio::test]
+    pub async fn mkdir_parents() {
+        let (agentfs, path, _file) = agentfs().await;
+        mkdir_filesystem(path.clone(), "/a/b/c", true)
+            .await
+            .unwrap();
+        let stats = agentfs.fs.stat("/a/b/c").await.unwrap().unwrap();
+        assert!(stats.is_directory());
+
+        // Existing directories are fine with -p, but files are not
+        mkdir_filesystem(path.clone(), "/a/b", true).await.unwrap();
+        agentfs.fs.write_file("/a/file", b"1").await.unwrap();
+        let err = mkdir_filesystem(path, "/a/file", true).await.unwrap_err();
+        assert!(err.to_string().contains("File exists"));
+    }
+
+    #[tokio::test]
+    pub async fn tree_dirs() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.mkdir("a").await.unwrap();
+        agentfs.fs.mkdir("a/b").await.unwrap();
+        agentfs.fs.mkdir("d").await.unwrap();
+        agentfs.fs.write_file("a/b/1.md", b"1").await.unwrap();
+        agentfs.fs.write_file("a/2.md", b"22").await.unwrap();
+        agentfs.fs.symlink("/a", "d/loop").await.unwrap();
+        let mut buf = Vec::new();
+        tree_filesystem(&mut buf, path.clone(), "/", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "/
+├── a/
+│   ├── 2.md (2 bytes)
+│   └── b/
+│       └── 1.md (1 bytes)
+└── d/
+    └── loop -> /a
+
+3 directories, 3 files
+"
+        );
+
+        let mut buf = Vec::new();
+        tree_filesystem(&mut buf, path, "/a", Some(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "/a
+├── 2.md (2 bytes)
+└── b/
+
+1 directory, 1 file
+"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn stat_file_and_symlink() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.write_file("/test.md", b"hello").await.unwrap();
+        agentfs.fs.chmod("/test.md", 0o640).await.unwrap();
+        agentfs.fs.symlink("/test.md", "/link").await.unwrap();
+        let stats = agentfs.fs.stat("/test.md").await.unwrap().unwrap();
+
+        let mut buf = Vec::new();
+        stat_filesystem(&mut buf, path.clone(), "/link", true)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!(
+                "path: /link\ntype: file\nsize: 5\nmode: 0640 (-rw-r-----)\nuid: {}\ngid: {}\n\
+                 nlink: 1\nino: {}\natime: {}\nmtime: {}\nctime: {}\n",
+                stats.uid, stats.gid, stats.ino, stats.atime, stats.mtime, stats.ctime
+            )
+        );
+
+        let mut buf = Vec::new();
+        stat_filesystem(&mut buf, path.clone(), "/link", false)
+            .await
+            .unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("type: symlink\n"));
+        assert!(output.contains("(lrwxrwxrwx)"));
+
+        let err = stat_filesystem(&mut Vec::new(), path, "/missing", false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("File not found"));
+    }
+
+    #[tokio::test]
+    pub async fn cp_file() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.write_file("/a.md", b"hello").await.unwrap();
+        agentfs.fs.chmod("/a.md", 0o600).await.unwrap();
+        agentfs
+            .fs
+            .utimes("/a.md", Some(10), Some(20))
+            .await
+            .unwrap();
+        agentfs.fs.mkdir("/dir").await.unwrap();
+        agentfs.fs.write_file("/b.md", b"old").await.unwrap();
+
+        // Onto an existing file, and into an existing directory
+        cp_filesystem(path.clone(), "/a.md", "/b.md", false)
+            .await
+            .unwrap();
+        cp_filesystem(path, "/a.md", "/dir", false).await.unwrap();
+
+        for copy in ["/b.md", "/dir/a.md"] {
+            let content = agentfs.fs.read_file(copy).await.unwrap().unwrap();
+            assert_eq!(content, b"hello");
+            let stats = agentfs.fs.stat(copy).await.unwrap().unwrap();
+            assert_eq!(stats.mode & 0o7777, 0o600);
+            assert_eq!((stats.atime, stats.mtime), (10, 20));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn cp_dir_recursive() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.mkdir("/a").await.unwrap();
+        agentfs.fs.mkdir("/a/b").await.unwrap();
+        agentfs.fs.write_file("/a/b/1.md", b"1").await.unwrap();
+        agentfs.fs.symlink("b/1.md", "/a/link").await.unwrap();
+        agentfs.fs.utimes("/a/b", Some(10), Some(20)).await.unwrap();
+
+        let err = cp_filesystem(path.clone(), "/a", "/c", false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Is a directory"));
+        let err = cp_filesystem(path.clone(), "/a", "/a/b", true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("into itself"));
+
+        cp_filesystem(path, "/a", "/c", true).await.unwrap();
+        let content = agentfs.fs.read_file("/c/b/1.md").await.unwrap().unwrap();
+        assert_eq!(content, b"1");
+        let target = agentfs.fs.readlink("/c/link").await.unwrap().unwrap();
+        assert_eq!(target, "b/1.md");
+        let stats = agentfs.fs.stat("/c/b").await.unwrap().unwrap();
+        assert_eq!((stats.atime, stats.mtime), (10, 20));
+    }
+
+    #[tokio::test]
+    pub async fn find_name_and_type() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.mkdir("/logs").await.unwrap();
+        agentfs.fs.mkdir("/logs/old.log").await.unwrap();
+        agentfs.fs.write_file("/logs/app.log", b"1").await.unwrap();
+        agentfs.fs.write_file("/logs/app.txt", b"1").await.unwrap();
+        agentfs
+            .fs
+            .symlink("app.log", "/logs/latest.log")
+            .await
+            .unwrap();
+
+        let find = |name: Option<&'static str>, file_type: Option<&'static str>| {
+            let path = path.clone();
+            async move {
+                let mut buf = Vec::new();
+                find_filesystem(&mut buf, path, "/logs", name, file_type)
+                    .await
+                    .unwrap();
+                String::from_utf8(buf).unwrap()
+            }
+        };
+
+        assert_eq!(
+            find(Some("*.log"), None).await,
+            "/logs/app.log\n/logs/latest.log\n/logs/old.log\n"
+        );
+        assert_eq!(find(Some("*.log"), Some("f")).await, "/logs/app.log\n");
+        assert_eq!(find(None, Some("d")).await, "/logs\n/logs/old.log\n");
+        assert_eq!(find(None, Some("l")).await, "/logs/latest.log\n");
+    }
+
+    #[tokio::test]
+    pub async fn du_dirs() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.mkdir("/a").await.unwrap();
+        agentfs.fs.mkdir("/a/b").await.unwrap();
+        agentfs.fs.mkdir("/c").await.unwrap();
+        agentfs.fs.write_file("/a/1.md", b"1").await.unwrap();
+        agentfs.fs.write_file("/a/b/2.md", b"22").await.unwrap();
+        agentfs.fs.link("/a/b/2.md", "/c/2.md").await.unwrap();
+        let chunk = agentfs.fs.chunk_size();
+        let dir_size = |dir: &'static str| {
+            let fs = &agentfs.fs;
+            async move { fs.stat(dir).await.unwrap().unwrap().size as usize }
+        };
+        let (root, a, b, c) = (
+            dir_size("/").await,
+            dir_size("/a").await,
+            dir_size("/a/b").await,
+            dir_size("/c").await,
+        );
+
+        let mut buf = Vec::new();
+        du_filesystem(&mut buf, path.clone(), "/", false, false)
+            .await
+            .unwrap();
+        // The hard link in /c is only counted once
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!(
+                "{}\t{}\t/a/b\n{}\t{}\t/a\n{}\t0\t/c\n{}\t{}\t/\n",
+                b + 2,
+                chunk,
+                a + b + 3,
+                2 * chunk,
+                c,
+                root + a + b + c + 3,
+                2 * chunk
+            )
+        );
+
+        let mut buf = Vec::new();
+        du_filesystem(&mut buf, path.clone(), "/a", true, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!("{}\t{}\t/a\n", a + b + 3, 2 * chunk)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn du_stored_compressed() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        let options =
+            AgentFSOptions::with_path(path.clone()).with_compression(Compression::Zstd(3));
+        let agentfs = AgentFS::open(options).await.unwrap();
+        let chunk = agentfs.fs.chunk_size();
+        agentfs
+            .fs
+            .write_file("/zeros.bin", &vec![0u8; 4 * chunk])
+            .await
+            .unwrap();
+
+        let mut buf = Vec::new();
+        du_filesystem(&mut buf, path.clone(), "/zeros.bin", false, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!("{}\t{}\t/zeros.bin\n", 4 * chunk, 4 * chunk)
+        );
+
+        let mut buf = Vec::new();
+        du_filesystem(&mut buf, path, "/zeros.bin", false, true)
+            .await
+            .unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        let stored: usize = output.split('\t').nth(1).unwrap().parse().unwrap();
+        assert!(stored < chunk, "unexpected output: {}", output);
+    }
+
+    #[tokio::test]
+    pub async fn compare_two_filesystems() {
+        let (left, left_path, _left_file) = agentfs().await;
+        let (right, right_path, _right_file) = agentfs().await;
+        left.fs.write_file("/same.txt", b"same").await.unwrap();
+        right.fs.write_file("/same.txt", b"same").await.unwrap();
+        left.fs.write_file("/changed.txt", b"old").await.unwrap();
+        right.fs.write_file("/changed.txt", b"newer").await.unwrap();
+        left.fs.write_file("/removed.txt", b"gone").await.unwrap();
+        right.fs.mkdir("/added").await.unwrap();
+
+        let mut buf = Vec::new();
+        compare_filesystems(&mut buf, &left_path, &right_path, "text")
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "A d /added +0\nM f /changed.txt +2\nD f /removed.txt -4\n"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn compare_with_snapshot_json() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.write_file("/a.txt", b"original").await.unwrap();
+        agentfs.fs.create_snapshot("before").await.unwrap();
+        agentfs.fs.write_file("/a.txt", b"changed").await.unwrap();
+
+        let mut buf = Vec::new();
+        let left = format!("{}@before", path);
+        compare_filesystems(&mut buf, &left, &path, "json")
+            .await
+            .unwrap();
+        let changes: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(
+            changes,
+            serde_json::json!([{
+                "path": "/a.txt",
+                "change": "modified",
+                "type": "file",
+                "old_size": 8,
+                "new_size": 7,
+                "size_delta": -1,
+            }])
+        );
+
+        let mut buf = Vec::new();
+        compare_filesystems(&mut buf, &left, &left, "text")
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "No changes\n");
+    }
+
+    #[test]
+    fn snapshot_refs() {
+        assert_eq!(parse_snapshot_ref("agent"), ("agent", None));
+        assert_eq!(parse_snapshot_ref("agent@snap"), ("agent", Some("snap")));
+        assert_eq!(parse_snapshot_ref("a@b/c.db"), ("a@b/c.db", None));
+    }
 }
@@ -1,15 +1,22 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use agentfs_sdk::{agentfs_dir, AgentFS, AgentFSOptions, OverlayFS};
+use agentfs_sdk::error::Error as SdkError;
+use agentfs_sdk::{
+    agentfs_dir, AgentFS, AgentFSOptions, Compression, OverlayFS, Quota, RepairReport,
+};
 use anyhow::{Context, Result as AnyhowResult};
 use turso::sync::{PartialBootstrapStrategy, PartialSyncOpts};
 
 use crate::parser::SyncCommandOptions;
+use crate::passphrase;
 
 pub async fn open_agentfs(
-    options: AgentFSOptions,
+    mut options: AgentFSOptions,
 ) -> anyhow::Result<(Option<turso::sync::Database>, AgentFS)> {
+    if options.passphrase.is_none() {
+        options.passphrase = passphrase::get().map(str::to_string);
+    }
     let path = options.db_path()?;
     let meta_path = format!("{path}-info");
     if !std::fs::exists(meta_path)? {
@@ -17,7 +24,7 @@ pub async fn open_agentfs(
             None,
             AgentFS::open(options)
                 .await
-                .context("Failed to open database")?,
+                .map_err(|e| open_error(e, "Failed to open database"))?,
         ));
     }
     let mut builder = turso::sync::Builder::new_remote(&options.db_path()?);
@@ -26,12 +33,21 @@ pub async fn open_agentfs(
     }
     let db = builder.build().await?;
     let conn = db.connect().await?;
-    let agent = AgentFS::open_with(conn)
+    let agent = AgentFS::open_with_passphrase(conn, options.passphrase.as_deref())
         .await
-        .context("Failed to open synced database")?;
+        .map_err(|e| open_error(e, "Failed to open synced database"))?;
     Ok((Some(db), agent))
 }
 
+/// Add `context` to an error opening a database, except for passphrase errors,
+/// which are clearer on their own
+fn open_error(e: SdkError, context: &'static str) -> anyhow::Error {
+    match e {
+        SdkError::PassphraseRequired | SdkError::InvalidPassphrase => e.into(),
+        e => anyhow::Error::new(e).context(context),
+    }
+}
+
 pub async fn create_agentfs(
     options: AgentFSOptions,
     sync_options: SyncCommandOptions,
@@ -69,7 +85,25 @@ pub async fn create_agentfs(
         }
         let db = builder.build().await?;
         let conn = db.connect().await?;
-        let agent = AgentFS::open_with(conn)
+        if options.dedup {
+            agentfs_sdk::filesystem::AgentFS::enable_dedup(&conn)
+                .await
+                .context("Failed to enable deduplication")?;
+        }
+        agentfs_sdk::filesystem::AgentFS::enable_compression(&conn, options.compression)
+            .await
+            .context("Failed to enable compression")?;
+        if let Some(passphrase) = options.passphrase.as_deref().filter(|_| options.encrypt) {
+            agentfs_sdk::filesystem::AgentFS::enable_encryption(&conn, passphrase)
+                .await
+                .context("Failed to enable encryption")?;
+        }
+        if !options.quota.is_unlimited() {
+            agentfs_sdk::filesystem::AgentFS::set_quota(&conn, options.quota)
+                .await
+                .context("Failed to set quota")?;
+        }
+        let agent = AgentFS::open_with_passphrase(conn, options.passphrase.as_deref())
             .await
             .context("Failed to initialize synced database")?;
         Ok((Some(db), agent))
@@ -83,11 +117,17 @@ pub async fn create_agentfs(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn init_database(
     id: Option<String>,
     sync_options: SyncCommandOptions,
     force: bool,
     base: Option<PathBuf>,
+    from: Option<PathBuf>,
+    dedup: bool,
+    compress: &str,
+    compression_level: i32,
+    quota: Quota,
 ) -> AnyhowResult<()> {
     // Generate ID if not provided
     let id = id.unwrap_or_else(|| {
@@ -116,6 +156,13 @@ pub async fn init_database(
         }
     }
 
+    // Validate source directory if provided
+    if let Some(ref from_path) = from {
+        if !from_path.is_dir() {
+            anyhow::bail!("Source directory does not exist: {}", from_path.display());
+        }
+    }
+
     // Check if agent already exists
     let db_path = agentfs_dir().join(format!("{}.db", &id));
     if db_path.exists() {
@@ -141,6 +188,16 @@ pub async fn init_database(
     if let Some(base_path) = base.as_ref() {
         open_options = open_options.with_base(base_path);
     }
+    if dedup {
+        open_options = open_options.with_dedup();
+    }
+    if compress == "zstd" {
+        open_options = open_options.with_compression(Compression::Zstd(compression_level));
+    }
+    if let Some(passphrase) = passphrase::get() {
+        open_options = open_options.with_encryption(passphrase);
+    }
+    open_options = open_options.with_quota(quota);
 
     // Use the SDK to initialize the database - this ensures consistency
     // The SDK will create .agentfs directory and database file
@@ -167,6 +224,16 @@ pub async fn init_database(
         eprintln!("Agent ID: {}", id);
         eprintln!("Base: {}", base_path.display());
     } else {
+        if let Some(from_path) = from {
+            if let Err(e) = copy_from(&from_path, &agent).await {
+                drop(agent);
+                for suffix in ["", "-wal", "-shm"] {
+                    std::fs::remove_file(format!("{}{}", db_path.display(), suffix)).ok();
+                }
+                return Err(e);
+            }
+        }
+
         if let Some(synced_db) = synced_db {
             synced_db.push().await?;
         }
@@ -177,3 +244,128 @@ pub async fn init_database(
 
     Ok(())
 }
+
+/// Copy the host directory `dir` into the new filesystem `agent`
+#[cfg(unix)]
+async fn copy_from(dir: &Path, agent: &AgentFS) -> AnyhowResult<()> {
+    crate::cmd::archive::copy_host_dir(dir, &agent.fs)
+        .await
+        .with_context(|| format!("Failed to copy {}", dir.display()))
+}
+
+#[cfg(not(unix))]
+async fn copy_from(_dir: &Path, _agent: &AgentFS) -> AnyhowResult<()> {
+    anyhow::bail!("--from is only supported on Unix")
+}
+
+/// Check an existing filesystem for inconsistencies, fixing them if `apply`
+/// is set
+pub async fn repair_database(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+    apply: bool,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (synced_db, agent) = open_agentfs(options).await?;
+    let report = agent
+        .fs
+        .repair(apply)
+        .await
+        .context("Failed to repair database")?;
+    write_report(stdout, &report)?;
+
+    if apply {
+        if let Some(synced_db) = synced_db {
+            synced_db.push().await?;
+        }
+        eprintln!("Repaired {}", id_or_path);
+    } else if !report.is_clean() {
+        eprintln!("Nothing was changed. Use --repair --apply to fix these problems.");
+    }
+    Ok(())
+}
+
+/// Check an existing filesystem for inconsistencies without changing it,
+/// failing if any are found
+pub async fn verify_database(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+) -> AnyhowResult<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    let (_, agent) = open_agentfs(options).await?;
+    let report = agent
+        .fs
+        .verify()
+        .await
+        .context("Failed to verify database")?;
+    write_report(stdout, &report)?;
+
+    if !report.is_clean() {
+        anyhow::bail!("{} failed verification", id_or_path);
+    }
+    Ok(())
+}
+
+/// Write what a repair or verification found, one line per kind of problem
+fn write_report(stdout: &mut impl std::io::Write, report: &RepairReport) -> std::io::Result<()> {
+    if report.integrity_errors.is_empty() {
+        writeln!(stdout, "Integrity check: ok")?;
+    } else {
+        writeln!(stdout, "Integrity check:")?;
+        for error in &report.integrity_errors {
+            writeln!(stdout, "  {}", error)?;
+        }
+    }
+    for (problem, count) in [
+        ("Dangling directory entries", report.dangling_dentries),
+        ("Orphaned inodes", report.orphaned_inodes),
+        ("Wrong link counts", report.link_counts),
+        ("Rows of missing inodes", report.orphaned_rows),
+        ("Wrong content refcounts", report.refcounts),
+        ("Unreferenced content", report.unreferenced_content),
+        ("Missing content", report.missing_content),
+    ] {
+        writeln!(stdout, "{}: {}", problem, count)?;
+    }
+    if report.reindexed {
+        writeln!(stdout, "Indexes rebuilt")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use tempfile::NamedTempFile;
+
+    use crate::cmd::init::verify_database;
+
+    #[tokio::test]
+    pub async fn verify_detects_dangling_entry() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+            .await
+            .unwrap();
+        agentfs.fs.write_file("/a.txt", b"a").await.unwrap();
+        verify_database(&mut Vec::new(), path.clone())
+            .await
+            .unwrap();
+
+        agentfs
+            .get_connection()
+            .execute(
+                "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES ('ghost', 1, 9999)",
+                (),
+            )
+            .await
+            .unwrap();
+        let mut buf = Vec::new();
+        assert!(verify_database(&mut buf, path.clone()).await.is_err());
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("Dangling directory entries: 1"));
+
+        // Verifying doesn't remove the entry
+        assert!(verify_database(&mut Vec::new(), path).await.is_err());
+    }
+}
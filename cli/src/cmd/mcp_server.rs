@@ -31,8 +31,8 @@ pub async fn handle_mcp_server_command(
     let server = McpServer::new(agentfs, tools_filter);
 
     // Run server with stdio transport
-    eprintln!("Starting MCP server on stdio...");
-    eprintln!("Protocol: Model Context Protocol (MCP) over JSON-RPC 2.0");
+    tracing::info!("Starting MCP server on stdio");
+    tracing::info!("Protocol: Model Context Protocol (MCP) over JSON-RPC 2.0");
     server.serve().await?;
 
     Ok(())
@@ -48,12 +48,12 @@ impl McpServer {
     fn new(agentfs: AgentFS, tools_filter: Option<Vec<String>>) -> Self {
         let enabled_tools = tools_filter.map(|tools| {
             let set: HashSet<String> = tools.into_iter().collect();
-            eprintln!("Tool filter enabled. Exposing tools: {:?}", set);
+            tracing::info!("Tool filter enabled. Exposing tools: {:?}", set);
             set
         });
 
         if enabled_tools.is_none() {
-            eprintln!("No tool filter specified. Exposing all tools.");
+            tracing::info!("No tool filter specified. Exposing all tools.");
         }
 
         Self {
@@ -85,7 +85,7 @@ impl McpServer {
             let request: JsonValue = match serde_json::from_str(&line) {
                 Ok(req) => req,
                 Err(e) => {
-                    eprintln!("Failed to parse JSON-RPC request: {}", e);
+                    tracing::warn!("Failed to parse JSON-RPC request: {}", e);
                     continue;
                 }
             };
@@ -110,7 +110,7 @@ impl McpServer {
         let id = request.get("id").cloned();
         let params = request.get("params").cloned().unwrap_or(json!({}));
 
-        eprintln!("Received request: method={}", method);
+        tracing::debug!("Received request: method={}", method);
 
         // Handle method
         let result = match method {
@@ -136,7 +136,7 @@ impl McpServer {
                 })
             }
             Err(e) => {
-                eprintln!("Error handling {}: {}", method, e);
+                tracing::warn!("Error handling {}: {}", method, e);
                 json!({
                     "jsonrpc": "2.0",
                     "id": id,
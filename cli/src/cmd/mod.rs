@@ -1,8 +1,10 @@
+pub mod archive;
 pub mod completions;
 pub mod fs;
 pub mod init;
 pub mod mcp_server;
 pub mod ps;
+pub mod snapshot;
 pub mod sync;
 pub mod timeline;
 
@@ -5,10 +5,11 @@ use std::{
     os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
-use turso::value::Value;
+use turso::{value::Value, Connection};
 
-use crate::{cmd::init::open_agentfs, fuse::FuseMountOptions};
+use crate::{cmd::init::open_agentfs, control::ControlledMount, fuse::FuseMountOptions};
 
 /// Arguments for the mount command.
 #[derive(Debug, Clone)]
@@ -27,6 +28,24 @@ pub struct MountArgs {
     pub uid: Option<u32>,
     /// Group ID to report for all files (defaults to current group).
     pub gid: Option<u32>,
+    /// `(inside, outside)` user IDs, reporting the host ID `outside` as `inside`.
+    pub uid_map: Vec<(u32, u32)>,
+    /// `(inside, outside)` group IDs, reporting the host ID `outside` as `inside`.
+    pub gid_map: Vec<(u32, u32)>,
+    /// Mount the filesystem read-only.
+    pub read_only: bool,
+    /// Bypass the kernel page cache for file reads and writes.
+    pub direct_io: bool,
+    /// How long the kernel may cache file attributes (defaults to forever).
+    pub attr_timeout: Option<Duration>,
+    /// How long the kernel may cache name lookups (defaults to forever).
+    pub entry_timeout: Option<Duration>,
+    /// Push changes made to the filesystem outside the mount to the kernel.
+    pub notify: bool,
+    /// Size in bytes of the cache of file data read from the database.
+    pub vfs_cache_size: Option<u64>,
+    /// Unix socket to listen for control requests on.
+    pub control_socket: Option<PathBuf>,
 }
 
 /// Mount the agent filesystem using FUSE.
@@ -45,7 +64,10 @@ pub fn mount(args: MountArgs) -> Result<()> {
         }
     }
 
-    let opts = AgentFSOptions::resolve(&args.id_or_path)?;
+    let mut opts = AgentFSOptions::resolve(&args.id_or_path)?;
+    if let Some(size) = args.vfs_cache_size {
+        opts = opts.with_block_cache_size(size);
+    }
 
     let fsname = format!(
         "agentfs:{}",
@@ -76,18 +98,32 @@ pub fn mount(args: MountArgs) -> Result<()> {
         }
     };
 
+    // All files are reported as owned by the mount owner, so that is what gets remapped
+    let uid = args.uid.unwrap_or_else(|| unsafe { libc::getuid() });
+    let gid = args.gid.unwrap_or_else(|| unsafe { libc::getgid() });
     let fuse_opts = FuseMountOptions {
         mountpoint: args.mountpoint,
         auto_unmount: args.auto_unmount,
         allow_root: args.allow_root,
         fsname,
-        uid: args.uid,
-        gid: args.gid,
+        uid: Some(map_id(&args.uid_map, uid)),
+        gid: Some(map_id(&args.gid_map, gid)),
+        read_only: args.read_only,
+        direct_io: args.direct_io,
+        attr_timeout: args.attr_timeout,
+        entry_timeout: args.entry_timeout,
+        notify: args.notify,
+        state: Arc::default(),
+        unmount_on_signal: true,
     };
+    let control_socket = args.control_socket;
+    let control_mountpoint = mountpoint.clone();
 
     let mount = move || {
         let rt = crate::get_runtime();
         let (_db, agentfs) = rt.block_on(open_agentfs(opts))?;
+        let snapshot_fs = agentfs.fs.clone();
+        let conn = agentfs.get_connection();
 
         // Check for overlay configuration
         let fs: Arc<dyn FileSystem> = rt.block_on(async {
@@ -114,7 +150,7 @@ pub fn mount(args: MountArgs) -> Result<()> {
 
             if let Some(base_path) = base_path {
                 // Create OverlayFS with HostFS base
-                eprintln!("Using overlay filesystem with base: {}", base_path);
+                tracing::info!("Using overlay filesystem with base: {}", base_path);
                 let hostfs = HostFS::new(&base_path)?;
                 #[cfg(target_family = "unix")]
                 let hostfs = { hostfs.with_fuse_mountpoint(mountpoint_ino) };
@@ -126,7 +162,24 @@ pub fn mount(args: MountArgs) -> Result<()> {
             }
         })?;
 
-        crate::fuse::mount(fs, fuse_opts, rt)
+        if let Some(path) = &control_socket {
+            let mount = ControlledMount {
+                fsname: fuse_opts.fsname.clone(),
+                mountpoint: control_mountpoint,
+                state: fuse_opts.state.clone(),
+                fs: snapshot_fs,
+            };
+            crate::control::spawn(&rt, path, mount)?;
+        }
+
+        let result = crate::fuse::mount(fs, fuse_opts, rt);
+        if let Some(path) = &control_socket {
+            let _ = std::fs::remove_file(path);
+        }
+        if let Err(e) = crate::get_runtime().block_on(checkpoint_wal(&conn)) {
+            tracing::warn!("Failed to checkpoint the database: {}", e);
+        }
+        result
     };
 
     if args.foreground {
@@ -140,6 +193,22 @@ pub fn mount(args: MountArgs) -> Result<()> {
     }
 }
 
+/// Move the changes in the WAL into the database file and empty the WAL, so
+/// that the database is self-contained once the mount is gone.
+async fn checkpoint_wal(conn: &Connection) -> Result<()> {
+    let mut rows = conn.query("PRAGMA wal_checkpoint(TRUNCATE)", ()).await?;
+    while rows.next().await?.is_some() {}
+    Ok(())
+}
+
+/// Map a host ID through `(inside, outside)` pairs, passing unmapped IDs through.
+fn map_id(pairs: &[(u32, u32)], id: u32) -> u32 {
+    pairs
+        .iter()
+        .find(|(_, outside)| *outside == id)
+        .map_or(id, |(inside, _)| *inside)
+}
+
 /// Check if a path is a mountpoint by comparing device IDs
 fn is_mounted(path: &std::path::Path) -> bool {
     let path_meta = match std::fs::metadata(path) {
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use std::{io::Write, path::PathBuf};
+use std::{io::Write, path::PathBuf, time::Duration};
 
 /// Arguments for the mount command.
 #[derive(Debug, Clone)]
@@ -19,6 +19,24 @@ pub struct MountArgs {
     pub uid: Option<u32>,
     /// Group ID to report for all files (defaults to current group).
     pub gid: Option<u32>,
+    /// `(inside, outside)` user IDs, reporting the host ID `outside` as `inside`.
+    pub uid_map: Vec<(u32, u32)>,
+    /// `(inside, outside)` group IDs, reporting the host ID `outside` as `inside`.
+    pub gid_map: Vec<(u32, u32)>,
+    /// Mount the filesystem read-only.
+    pub read_only: bool,
+    /// Bypass the kernel page cache for file reads and writes.
+    pub direct_io: bool,
+    /// How long the kernel may cache file attributes (defaults to forever).
+    pub attr_timeout: Option<Duration>,
+    /// How long the kernel may cache name lookups (defaults to forever).
+    pub entry_timeout: Option<Duration>,
+    /// Push changes made to the filesystem outside the mount to the kernel.
+    pub notify: bool,
+    /// Size in bytes of the cache of file data read from the database.
+    pub vfs_cache_size: Option<u64>,
+    /// Unix socket to listen for control requests on.
+    pub control_socket: Option<PathBuf>,
 }
 
 /// List all currently mounted agentfs filesystems
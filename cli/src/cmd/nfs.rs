@@ -7,16 +7,31 @@
 use agentfs_sdk::{agentfs_dir, AgentFSOptions, FileSystem, HostFS, OverlayFS};
 use anyhow::{Context, Result};
 use nfsserve::tcp::NFSTcp;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio::signal;
 use tokio::sync::Mutex;
 
 use crate::cmd::init::open_agentfs;
 use crate::nfs::AgentNFS;
+use crate::nfs_access::{self, Cidr};
 
 /// Handle the `nfs` command - start a standalone NFS server.
-pub async fn handle_nfs_command(id_or_path: String, bind: String, port: u32) -> Result<()> {
+///
+/// The server listens on `nfs_bind` if given, or else on `bind` and `port`. Port 0
+/// picks a free port, which is printed with the mount command. With `allow`
+/// networks, connections from other addresses are refused. With `read_only`, the
+/// export rejects all changes.
+pub async fn handle_nfs_command(
+    id_or_path: String,
+    bind: String,
+    port: u32,
+    nfs_bind: Option<SocketAddr>,
+    allow: Vec<Cidr>,
+    read_only: bool,
+) -> Result<()> {
     // Resolve database path
     let db_path = resolve_db_path(&id_or_path)?;
 
@@ -39,10 +54,10 @@ pub async fn handle_nfs_command(id_or_path: String, bind: String, port: u32) ->
         let hostfs = HostFS::new(&base_str).context("Failed to create HostFS")?;
         let overlay = OverlayFS::new(Arc::new(hostfs), agentfs.fs);
 
-        eprintln!("Mode: overlay (base: {})", base_str);
+        tracing::info!("Mode: overlay (base: {})", base_str);
         Arc::new(Mutex::new(overlay))
     } else {
-        eprintln!("Mode: direct AgentFS");
+        tracing::info!("Mode: direct AgentFS");
         Arc::new(Mutex::new(agentfs.fs))
     };
 
@@ -51,47 +66,91 @@ pub async fn handle_nfs_command(id_or_path: String, bind: String, port: u32) ->
     let gid = unsafe { libc::getgid() };
 
     // Create NFS adapter
-    let nfs = AgentNFS::new(fs, uid, gid);
+    let nfs = AgentNFS::new(fs, uid, gid).with_read_only(read_only);
 
-    // Bind NFS server
-    let bind_addr = format!("{}:{}", bind, port);
-    let listener = nfsserve::tcp::NFSTcpListener::bind(&bind_addr, nfs)
+    // Bind NFS server. With an allow list, it listens on loopback behind the
+    // access control instead.
+    let bind_addr = match nfs_bind {
+        Some(nfs_bind) => nfs_bind.to_string(),
+        None => format!("{}:{}", bind, port),
+    };
+    let nfs_addr = if allow.is_empty() {
+        bind_addr.clone()
+    } else {
+        "127.0.0.1:0".to_string()
+    };
+    let listener = nfsserve::tcp::NFSTcpListener::bind(&nfs_addr, nfs)
         .await
-        .with_context(|| format!("Failed to bind NFS server to {}", bind_addr))?;
+        .with_context(|| format!("Failed to bind NFS server to {}", nfs_addr))?;
+    let access_listener = if allow.is_empty() {
+        None
+    } else {
+        let access_listener = TcpListener::bind(&bind_addr)
+            .await
+            .with_context(|| format!("Failed to bind NFS server to {}", bind_addr))?;
+        Some(access_listener)
+    };
+
+    // The port clients connect to, which was picked by the system for port 0
+    let nfs_port = listener.get_listen_port();
+    let listen_addr = match &access_listener {
+        Some(access_listener) => access_listener.local_addr()?,
+        None => SocketAddr::new(listener.get_listen_ip(), nfs_port),
+    };
 
     // Print server info
     eprintln!();
     eprintln!("AgentFS NFS Server");
     eprintln!("  Database: {}", db_path.display());
-    eprintln!("  Listening: {}", bind_addr);
-    eprintln!("  Export: /");
+    eprintln!("  Listening: {}", listen_addr);
+    eprintln!("  Export: /{}", if read_only { " (read-only)" } else { "" });
+    if !allow.is_empty() {
+        let networks: Vec<_> = allow.iter().map(|cidr| cidr.to_string()).collect();
+        eprintln!("  Allowed clients: {}", networks.join(", "));
+    }
     eprintln!();
     eprintln!("Mount from client:");
     eprintln!(
         "  mount -t nfs -o vers=3,tcp,port={},mountport={},nolock {}:/ /mnt",
-        port, port, bind
+        listen_addr.port(),
+        listen_addr.port(),
+        listen_addr.ip()
     );
     eprintln!();
     eprintln!("Press Ctrl+C to stop.");
     eprintln!();
 
-    // Spawn the NFS server task
+    // Spawn the NFS server task, and the access control in front of it
     let server_handle = tokio::spawn(async move {
         if let Err(e) = listener.handle_forever().await {
-            eprintln!("NFS server error: {}", e);
+            tracing::error!("NFS server error: {}", e);
         }
     });
+    let access_handle = access_listener.map(|access_listener| {
+        let backend = SocketAddr::from(([127, 0, 0, 1], nfs_port));
+        tokio::spawn(async move {
+            if let Err(e) = nfs_access::serve(access_listener, backend, allow).await {
+                tracing::error!("NFS server error: {}", e);
+            }
+        })
+    });
 
-    // Wait for Ctrl+C
-    signal::ctrl_c()
-        .await
-        .context("Failed to listen for ctrl+c")?;
+    // Wait for Ctrl+C or SIGTERM
+    let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())
+        .context("Failed to listen for SIGTERM")?;
+    tokio::select! {
+        result = signal::ctrl_c() => result.context("Failed to listen for ctrl+c")?,
+        _ = sigterm.recv() => {}
+    }
 
     eprintln!();
     eprintln!("Shutting down...");
 
     // Stop the server
     server_handle.abort();
+    if let Some(access_handle) = access_handle {
+        access_handle.abort();
+    }
 
     Ok(())
 }
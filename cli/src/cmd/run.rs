@@ -4,8 +4,8 @@
 //! - Linux: FUSE + namespace sandbox (or experimental ptrace)
 //! - Darwin: NFS + sandbox-exec
 
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{bail, Result};
+use std::{path::PathBuf, time::Duration};
 
 #[cfg_attr(all(target_os = "linux", feature = "sandbox"), path = "run_linux.rs")]
 #[cfg_attr(all(target_os = "macos", feature = "sandbox"), path = "run_darwin.rs")]
@@ -17,23 +17,134 @@ use std::path::PathBuf;
 mod sys;
 
 /// Handle the `run` command, dispatching to the platform-specific implementation.
+///
+/// The environment the command inherits is adjusted first: it is cleared with
+/// `clear_env`, then `set_env` assignments are applied, then `unset_env` variables
+/// are removed.
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_run_command(
     allow: Vec<PathBuf>,
     no_default_allows: bool,
     experimental_sandbox: bool,
     strace: bool,
+    strace_output: Option<PathBuf>,
+    strace_format: String,
+    strict_fds: bool,
+    max_open_files: usize,
     session: Option<String>,
+    mounts: Vec<String>,
+    ro_host_mounts: Vec<(PathBuf, PathBuf)>,
+    overlay: Option<String>,
+    excludes: Vec<String>,
+    uid_map: Vec<(u32, u32)>,
+    gid_map: Vec<(u32, u32)>,
+    default_mode: Option<u32>,
+    default_dir_mode: Option<u32>,
+    default_owner: Option<(u32, u32)>,
+    deny_syscalls: Vec<String>,
+    allow_only: Vec<String>,
+    deny_errno: String,
+    no_network: bool,
+    dry_run: bool,
+    summary_json: Option<PathBuf>,
+    clear_env: bool,
+    set_env: Vec<String>,
+    unset_env: Vec<String>,
+    workdir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    cpu_limit: Option<Duration>,
+    memory_limit: Option<u64>,
+    vfs_cache_size: Option<u64>,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
+    apply_env(clear_env, &set_env, &unset_env)?;
+
     sys::run(
         allow,
         no_default_allows,
         experimental_sandbox,
         strace,
+        strace_output,
+        strace_format,
+        strict_fds,
+        max_open_files,
         session,
+        mounts,
+        ro_host_mounts,
+        overlay,
+        excludes,
+        uid_map,
+        gid_map,
+        default_mode,
+        default_dir_mode,
+        default_owner,
+        deny_syscalls,
+        allow_only,
+        deny_errno,
+        no_network,
+        dry_run,
+        summary_json,
+        workdir,
+        timeout,
+        cpu_limit,
+        memory_limit,
+        vfs_cache_size,
         command,
         args,
     )
     .await
 }
+
+/// Clear, set, then unset environment variables of the current process, which
+/// the command inherits.
+fn apply_env(clear_env: bool, set_env: &[String], unset_env: &[String]) -> Result<()> {
+    // Validate everything before touching the environment
+    let assignments = set_env
+        .iter()
+        .map(|assignment| parse_env_assignment(assignment))
+        .collect::<Result<Vec<_>>>()?;
+    for var in unset_env {
+        if var.is_empty() || var.contains('=') {
+            bail!("Invalid environment variable name '{}'", var);
+        }
+    }
+
+    if clear_env {
+        for (var, _) in std::env::vars_os() {
+            std::env::remove_var(var);
+        }
+    }
+    for (var, value) in assignments {
+        std::env::set_var(var, value);
+    }
+    for var in unset_env {
+        std::env::remove_var(var);
+    }
+    Ok(())
+}
+
+/// Split a `VAR=VAL` assignment.
+fn parse_env_assignment(assignment: &str) -> Result<(&str, &str)> {
+    match assignment.split_once('=') {
+        Some((var, value)) if !var.is_empty() => Ok((var, value)),
+        _ => bail!(
+            "Invalid environment assignment '{}'. Expected format: VAR=VAL.",
+            assignment
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_assignment() {
+        assert_eq!(parse_env_assignment("FOO=bar").unwrap(), ("FOO", "bar"));
+        assert_eq!(parse_env_assignment("FOO=").unwrap(), ("FOO", ""));
+        assert_eq!(parse_env_assignment("FOO=a=b").unwrap(), ("FOO", "a=b"));
+        assert!(parse_env_assignment("FOO").is_err());
+        assert!(parse_env_assignment("=bar").is_err());
+    }
+}
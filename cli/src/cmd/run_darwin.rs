@@ -15,6 +15,7 @@ use nfsserve::tcp::NFSTcp;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
 use crate::nfs::AgentNFS;
@@ -22,19 +23,49 @@ use crate::nfs::AgentNFS;
 #[cfg(target_os = "macos")]
 use crate::sandbox::darwin::{generate_sandbox_profile, SandboxConfig};
 
-/// Default NFS port to try (use a high port to avoid needing root)
-const DEFAULT_NFS_PORT: u32 = 11111;
-
 /// Run the command in a Darwin sandbox.
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     allow: Vec<PathBuf>,
     no_default_allows: bool,
     _experimental_sandbox: bool,
     _strace: bool,
+    _strace_output: Option<PathBuf>,
+    _strace_format: String,
+    _strict_fds: bool,
+    _max_open_files: usize,
     session_id: Option<String>,
+    _mounts: Vec<String>,
+    _ro_host_mounts: Vec<(PathBuf, PathBuf)>,
+    _overlay: Option<String>,
+    _excludes: Vec<String>,
+    _uid_map: Vec<(u32, u32)>,
+    _gid_map: Vec<(u32, u32)>,
+    _default_mode: Option<u32>,
+    _default_dir_mode: Option<u32>,
+    _default_owner: Option<(u32, u32)>,
+    _deny_syscalls: Vec<String>,
+    _allow_only: Vec<String>,
+    _deny_errno: String,
+    _no_network: bool,
+    dry_run: bool,
+    summary_json: Option<PathBuf>,
+    _workdir: Option<PathBuf>,
+    _timeout: Option<Duration>,
+    _cpu_limit: Option<Duration>,
+    _memory_limit: Option<u64>,
+    vfs_cache_size: Option<u64>,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
+    // Ignoring it would let the command modify files it was meant to be kept from
+    if dry_run {
+        anyhow::bail!("--dry-run is not supported on macOS");
+    }
+    if summary_json.is_some() {
+        anyhow::bail!("--summary-json is not supported on macOS");
+    }
+
     let cwd = std::env::current_dir().context("Failed to get current directory")?;
     let home = dirs::home_dir().context("Failed to get home directory")?;
 
@@ -48,9 +79,9 @@ pub async fn run(
             let exit_code = run_command_in_mount(&session, command, args)?;
             std::process::exit(exit_code);
         } else {
-            eprintln!("Cleaning up stale NFS mount...");
+            tracing::info!("Cleaning up stale NFS mount");
             if let Err(e) = unmount(&session.mountpoint) {
-                eprintln!("Warning: Failed to unmount stale mount: {}", e);
+                tracing::warn!("Failed to unmount stale mount: {}", e);
             }
         }
     }
@@ -61,7 +92,11 @@ pub async fn run(
         .to_str()
         .context("Database path contains non-UTF8 characters")?;
 
-    let agentfs = AgentFS::open(AgentFSOptions::with_path(db_path_str))
+    let mut options = AgentFSOptions::with_path(db_path_str);
+    if let Some(size) = vfs_cache_size {
+        options = options.with_block_cache_size(size);
+    }
+    let agentfs = AgentFS::open(options)
         .await
         .context("Failed to create AgentFS")?;
 
@@ -85,18 +120,17 @@ pub async fn run(
     // Create NFS adapter
     let nfs = AgentNFS::new(fs, uid, gid);
 
-    // Find an available port
-    let port = find_available_port(DEFAULT_NFS_PORT)?;
-
-    // Start NFS server in background
-    let listener = nfsserve::tcp::NFSTcpListener::bind(&format!("127.0.0.1:{}", port), nfs)
+    // Start NFS server in background, on a free port picked by the system so
+    // that several sessions can run at once
+    let listener = nfsserve::tcp::NFSTcpListener::bind("127.0.0.1:0", nfs)
         .await
         .context("Failed to bind NFS server")?;
+    let port = u32::from(listener.get_listen_port());
 
     // Spawn the NFS server task
     let server_handle = tokio::spawn(async move {
         if let Err(e) = listener.handle_forever().await {
-            eprintln!("NFS server error: {}", e);
+            tracing::error!("NFS server error: {}", e);
         }
     });
 
@@ -272,20 +306,6 @@ fn is_mount_healthy(mountpoint: &Path) -> bool {
     std::fs::read_dir(mountpoint).is_ok()
 }
 
-/// Find an available TCP port starting from the given port.
-fn find_available_port(start_port: u32) -> Result<u32> {
-    for port in start_port..start_port + 100 {
-        if std::net::TcpListener::bind(format!("127.0.0.1:{}", port)).is_ok() {
-            return Ok(port);
-        }
-    }
-    anyhow::bail!(
-        "Could not find an available port in range {}-{}",
-        start_port,
-        start_port + 100
-    );
-}
-
 /// Mount the NFS filesystem (macOS version).
 #[cfg(target_os = "macos")]
 fn mount_nfs(port: u32, mountpoint: &Path) -> Result<()> {
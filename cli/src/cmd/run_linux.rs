@@ -3,32 +3,121 @@
 //! Dispatches to either the FUSE+namespace sandbox (default) or the experimental
 //! ptrace-based sandbox based on command-line flags.
 
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{bail, Result};
+use std::{path::PathBuf, time::Duration};
 
 /// Run the command in a Linux sandbox.
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     allow: Vec<PathBuf>,
     no_default_allows: bool,
     experimental_sandbox: bool,
     strace: bool,
+    strace_output: Option<PathBuf>,
+    strace_format: String,
+    strict_fds: bool,
+    max_open_files: usize,
     session: Option<String>,
+    mounts: Vec<String>,
+    ro_host_mounts: Vec<(PathBuf, PathBuf)>,
+    overlay: Option<String>,
+    excludes: Vec<String>,
+    uid_map: Vec<(u32, u32)>,
+    gid_map: Vec<(u32, u32)>,
+    default_mode: Option<u32>,
+    default_dir_mode: Option<u32>,
+    default_owner: Option<(u32, u32)>,
+    deny_syscalls: Vec<String>,
+    allow_only: Vec<String>,
+    deny_errno: String,
+    no_network: bool,
+    dry_run: bool,
+    summary_json: Option<PathBuf>,
+    workdir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    cpu_limit: Option<Duration>,
+    memory_limit: Option<u64>,
+    vfs_cache_size: Option<u64>,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
     if experimental_sandbox {
         if !allow.is_empty() || no_default_allows {
-            eprintln!("Warning: --allow and --no-default-allows are not supported with --experimental-sandbox, ignoring");
+            tracing::warn!("--allow and --no-default-allows are not supported with --experimental-sandbox, ignoring");
         }
         if session.is_some() {
-            eprintln!("Warning: --session is not supported with --experimental-sandbox, ignoring");
+            tracing::warn!("--session is not supported with --experimental-sandbox, ignoring");
         }
-        crate::sandbox::linux_ptrace::run_cmd(strace, command, args).await;
+        crate::sandbox::linux_ptrace::run_cmd(
+            strace,
+            strace_output,
+            strace_format,
+            strict_fds,
+            max_open_files,
+            mounts,
+            ro_host_mounts,
+            overlay,
+            excludes,
+            uid_map,
+            gid_map,
+            default_mode,
+            default_dir_mode,
+            default_owner,
+            deny_syscalls,
+            allow_only,
+            deny_errno,
+            no_network,
+            dry_run,
+            summary_json,
+            workdir,
+            timeout,
+            cpu_limit,
+            memory_limit,
+            vfs_cache_size,
+            command,
+            args,
+        )
+        .await?;
     } else {
-        if strace {
-            eprintln!("Warning: --strace is only supported with --experimental-sandbox, ignoring");
+        if dry_run {
+            bail!("--dry-run is only supported with --experimental-sandbox");
         }
-        crate::sandbox::linux::run_cmd(allow, no_default_allows, session, command, args).await?;
+        if summary_json.is_some() {
+            bail!("--summary-json is only supported with --experimental-sandbox");
+        }
+        if strace || strace_output.is_some() || strict_fds {
+            tracing::warn!("--strace, --strace-output and --strict-fds are only supported with --experimental-sandbox, ignoring");
+        }
+        if !mounts.is_empty()
+            || !ro_host_mounts.is_empty()
+            || overlay.is_some()
+            || !excludes.is_empty()
+        {
+            tracing::warn!("--mount, --mount-ro-host, --overlay and --exclude are only supported with --experimental-sandbox, ignoring");
+        }
+        if !uid_map.is_empty() || !gid_map.is_empty() {
+            tracing::warn!(
+                "--uid-map and --gid-map are only supported with --experimental-sandbox, ignoring"
+            );
+        }
+        if default_mode.is_some() || default_dir_mode.is_some() || default_owner.is_some() {
+            tracing::warn!("--default-mode, --default-dir-mode and --default-owner are only supported with --experimental-sandbox, ignoring");
+        }
+        if !deny_syscalls.is_empty() || !allow_only.is_empty() || no_network {
+            tracing::warn!("--deny-syscall, --allow-only and --no-network are only supported with --experimental-sandbox, ignoring");
+        }
+        if workdir.is_some() || timeout.is_some() || cpu_limit.is_some() || memory_limit.is_some() {
+            tracing::warn!("--workdir, --timeout, --cpu-limit and --memory-limit are only supported with --experimental-sandbox, ignoring");
+        }
+        crate::sandbox::linux::run_cmd(
+            allow,
+            no_default_allows,
+            session,
+            vfs_cache_size,
+            command,
+            args,
+        )
+        .await?;
     }
     Ok(())
 }
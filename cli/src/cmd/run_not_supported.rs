@@ -3,15 +3,40 @@
 //! The `run` command is not supported on Windows.
 
 use anyhow::{bail, Result};
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 /// Run the command in a Windows sandbox.
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     _allow: Vec<PathBuf>,
     _no_default_allows: bool,
     _experimental_sandbox: bool,
     _strace: bool,
+    _strace_output: Option<PathBuf>,
+    _strace_format: String,
+    _strict_fds: bool,
+    _max_open_files: usize,
     _session: Option<String>,
+    _mounts: Vec<String>,
+    _ro_host_mounts: Vec<(PathBuf, PathBuf)>,
+    _overlay: Option<String>,
+    _excludes: Vec<String>,
+    _uid_map: Vec<(u32, u32)>,
+    _gid_map: Vec<(u32, u32)>,
+    _default_mode: Option<u32>,
+    _default_dir_mode: Option<u32>,
+    _default_owner: Option<(u32, u32)>,
+    _deny_syscalls: Vec<String>,
+    _allow_only: Vec<String>,
+    _deny_errno: String,
+    _no_network: bool,
+    _dry_run: bool,
+    _summary_json: Option<PathBuf>,
+    _workdir: Option<PathBuf>,
+    _timeout: Option<Duration>,
+    _cpu_limit: Option<Duration>,
+    _memory_limit: Option<u64>,
+    _vfs_cache_size: Option<u64>,
     _command: PathBuf,
     _args: Vec<String>,
 ) -> Result<()> {
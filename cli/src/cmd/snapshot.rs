@@ -0,0 +1,121 @@
+use agentfs_sdk::AgentFSOptions;
+use chrono::TimeZone;
+
+use crate::cmd::init::open_agentfs;
+
+pub async fn create_snapshot(id_or_path: String, name: &str) -> anyhow::Result<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    eprintln!("Using agent: {}", id_or_path);
+
+    let (_, agentfs) = open_agentfs(options).await?;
+    agentfs.fs.create_snapshot(name).await?;
+    eprintln!("Created snapshot '{}'", name);
+    Ok(())
+}
+
+pub async fn list_snapshots(
+    stdout: &mut impl std::io::Write,
+    id_or_path: String,
+) -> anyhow::Result<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    eprintln!("Using agent: {}", id_or_path);
+
+    let (_, agentfs) = open_agentfs(options).await?;
+    for snapshot in agentfs.fs.list_snapshots().await? {
+        writeln!(
+            stdout,
+            "{}  {}",
+            format_timestamp(snapshot.created_at),
+            snapshot.name
+        )?;
+    }
+    Ok(())
+}
+
+/// Roll the filesystem back to snapshot `name`
+///
+/// This must not run while the filesystem is mounted or used by `agentfs run`,
+/// whose open files would refer to the replaced state.
+pub async fn restore_snapshot(id_or_path: String, name: &str) -> anyhow::Result<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    eprintln!("Using agent: {}", id_or_path);
+
+    let (_, agentfs) = open_agentfs(options).await?;
+    agentfs.fs.restore_snapshot(name).await?;
+    eprintln!("Restored snapshot '{}'", name);
+    Ok(())
+}
+
+pub async fn delete_snapshot(id_or_path: String, name: &str) -> anyhow::Result<()> {
+    let options = AgentFSOptions::resolve(&id_or_path)?;
+    eprintln!("Using agent: {}", id_or_path);
+
+    let (_, agentfs) = open_agentfs(options).await?;
+    agentfs.fs.delete_snapshot(name).await?;
+    eprintln!("Deleted snapshot '{}'", name);
+    Ok(())
+}
+
+/// Format timestamp as YYYY-MM-DD HH:MM:SS
+fn format_timestamp(timestamp: i64) -> String {
+    chrono::Utc
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| format!("Invalid timestamp: {}", timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use agentfs_sdk::{AgentFS, AgentFSOptions};
+    use tempfile::NamedTempFile;
+
+    use crate::cmd::snapshot::{
+        create_snapshot, delete_snapshot, list_snapshots, restore_snapshot,
+    };
+
+    async fn agentfs() -> (AgentFS, String, NamedTempFile) {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(path.to_string()))
+            .await
+            .unwrap();
+        (agentfs, file.path().to_str().unwrap().to_string(), file)
+    }
+
+    #[tokio::test]
+    pub async fn snapshot_restore() {
+        let (agentfs, path, _file) = agentfs().await;
+        agentfs.fs.write_file("/a.txt", b"original").await.unwrap();
+
+        create_snapshot(path.clone(), "before").await.unwrap();
+        agentfs.fs.write_file("/a.txt", b"modified").await.unwrap();
+        restore_snapshot(path.clone(), "before").await.unwrap();
+
+        let content = agentfs.fs.read_file("/a.txt").await.unwrap().unwrap();
+        assert_eq!(content, b"original");
+    }
+
+    #[tokio::test]
+    pub async fn snapshot_list_and_delete() {
+        let (_agentfs, path, _file) = agentfs().await;
+        create_snapshot(path.clone(), "one").await.unwrap();
+        create_snapshot(path.clone(), "two").await.unwrap();
+        assert!(create_snapshot(path.clone(), "one").await.is_err());
+
+        let mut buf = Vec::new();
+        list_snapshots(&mut buf, path.clone()).await.unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        let names: Vec<_> = output
+            .lines()
+            .map(|line| line.rsplit("  ").next().unwrap())
+            .collect();
+        assert_eq!(names, vec!["one", "two"]);
+
+        delete_snapshot(path.clone(), "one").await.unwrap();
+        assert!(restore_snapshot(path.clone(), "one").await.is_err());
+        let mut buf = Vec::new();
+        list_snapshots(&mut buf, path).await.unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap().lines().count(), 1);
+    }
+}
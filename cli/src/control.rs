@@ -0,0 +1,310 @@
+//! Control socket of a running mount.
+//!
+//! With `agentfs mount --control-socket PATH`, the mount listens on a Unix
+//! domain socket for JSON-RPC 2.0 requests, so that orchestrators can inspect
+//! and manage long-running mounts without restarting them. Each request is a
+//! single line of JSON, answered with a single line:
+//!
+//! ```text
+//! -> {"jsonrpc": "2.0", "id": 1, "method": "stats"}
+//! <- {"jsonrpc": "2.0", "id": 1, "result": {"open_files": 2, ...}}
+//! ```
+//!
+//! The methods are:
+//!
+//! - `list-mounts`: the filesystem name, mountpoint and mode of the mount
+//! - `stats`: open file handles and directory listing cache hits and misses
+//! - `snapshot`: record the filesystem as snapshot `params.name`
+//! - `remount-ro`: reject further changes through the mount with `EROFS`
+//!
+//! `snapshot` and `remount-ro` first write back the data the kernel caches
+//! for the mount, so that they include every write made before the request.
+
+use crate::fuse::MountState;
+use agentfs_sdk::filesystem::AgentFS;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use std::{
+    io,
+    os::unix::{fs::FileTypeExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    runtime::Runtime,
+};
+
+/// JSON-RPC error code for requests that are not valid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// JSON-RPC error code for requests without a method.
+const INVALID_REQUEST: i64 = -32600;
+/// JSON-RPC error code for unknown methods.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for missing or malformed parameters.
+const INVALID_PARAMS: i64 = -32602;
+/// JSON-RPC error code for methods that failed.
+const INTERNAL_ERROR: i64 = -32603;
+
+/// A JSON-RPC error code and message.
+type RpcError = (i64, String);
+
+/// The mount served by a control socket.
+pub struct ControlledMount {
+    /// Filesystem name shown in mount output.
+    pub fsname: String,
+    /// The mountpoint path.
+    pub mountpoint: PathBuf,
+    /// State shared with the FUSE session.
+    pub state: Arc<MountState>,
+    /// The AgentFS filesystem snapshots are taken of.
+    pub fs: AgentFS,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotParams {
+    name: String,
+}
+
+/// Listen for control requests on `path`, serving them on `runtime`.
+///
+/// A socket left at `path` by a mount that did not exit cleanly is replaced,
+/// but one that a running mount still listens on is not. The socket is not
+/// removed when the runtime shuts down, which is up to the caller.
+pub fn spawn(runtime: &Runtime, path: &Path, mount: ControlledMount) -> Result<()> {
+    remove_stale_socket(path)?;
+    let listener = std::os::unix::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+    listener.set_nonblocking(true)?;
+
+    let _guard = runtime.enter();
+    let listener = UnixListener::from_std(listener)?;
+    let mount = Arc::new(mount);
+    runtime.spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_connection(stream, mount.clone()));
+                }
+                Err(e) => tracing::warn!("Failed to accept control connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Remove the socket at `path` if nothing listens on it anymore.
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        anyhow::bail!("Control socket {} already exists", path.display());
+    }
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        anyhow::bail!("Control socket {} is already in use", path.display());
+    }
+    std::fs::remove_file(path)
+        .with_context(|| format!("Failed to remove stale control socket {}", path.display()))
+}
+
+/// Answer the requests of one client until it disconnects.
+async fn serve_connection(stream: UnixStream, mount: Arc<ControlledMount>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(response) = handle_request(&line, &mount).await else {
+            continue;
+        };
+        let response = format!("{}\n", response);
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Handle one request, returning its response unless it is a notification.
+async fn handle_request(line: &str, mount: &ControlledMount) -> Option<JsonValue> {
+    let request: JsonValue = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return Some(error_response(JsonValue::Null, PARSE_ERROR, e.to_string())),
+    };
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(JsonValue::as_str) else {
+        let id = id.unwrap_or(JsonValue::Null);
+        return Some(error_response(id, INVALID_REQUEST, "Missing method".into()));
+    };
+    let params = request.get("params").cloned().unwrap_or(json!({}));
+
+    tracing::debug!("Control request: method={}", method);
+    let result = match method {
+        "list-mounts" => Ok(list_mounts(mount)),
+        "stats" => Ok(stats(mount)),
+        "snapshot" => snapshot(mount, params).await,
+        "remount-ro" => remount_ro(mount).await,
+        _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+    };
+
+    // Notifications are not answered
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result
+        }),
+        Err((code, message)) => {
+            tracing::warn!("Control request {} failed: {}", method, message);
+            error_response(id, code, message)
+        }
+    })
+}
+
+fn error_response(id: JsonValue, code: i64, message: String) -> JsonValue {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": code,
+            "message": message
+        }
+    })
+}
+
+fn list_mounts(mount: &ControlledMount) -> JsonValue {
+    json!({
+        "mounts": [{
+            "fsname": mount.fsname,
+            "mountpoint": mount.mountpoint,
+            "read_only": mount.state.read_only.load(Ordering::Relaxed),
+        }]
+    })
+}
+
+fn stats(mount: &ControlledMount) -> JsonValue {
+    let state = &mount.state;
+    json!({
+        "open_files": state.open_files.load(Ordering::Relaxed),
+        "dir_cache_hits": state.dir_cache_hits.load(Ordering::Relaxed),
+        "dir_cache_misses": state.dir_cache_misses.load(Ordering::Relaxed),
+        "read_only": state.read_only.load(Ordering::Relaxed),
+    })
+}
+
+async fn snapshot(mount: &ControlledMount, params: JsonValue) -> Result<JsonValue, RpcError> {
+    let params: SnapshotParams =
+        serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+    sync_mount(&mount.mountpoint).await?;
+    let snapshot = mount
+        .fs
+        .create_snapshot(&params.name)
+        .await
+        .map_err(|e| (INTERNAL_ERROR, e.to_string()))?;
+    Ok(json!({
+        "name": snapshot.name,
+        "created_at": snapshot.created_at,
+    }))
+}
+
+async fn remount_ro(mount: &ControlledMount) -> Result<JsonValue, RpcError> {
+    sync_mount(&mount.mountpoint).await?;
+    mount.state.read_only.store(true, Ordering::Relaxed);
+    Ok(json!({ "read_only": true }))
+}
+
+/// Write back the data the kernel caches for the mount to the filesystem.
+async fn sync_mount(mountpoint: &Path) -> Result<(), RpcError> {
+    let mountpoint = mountpoint.to_path_buf();
+    let result = tokio::task::spawn_blocking(move || {
+        let dir = std::fs::File::open(&mountpoint)?;
+        if unsafe { libc::syncfs(dir.as_raw_fd()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| (INTERNAL_ERROR, e.to_string()))?;
+    result.map_err(|e| (INTERNAL_ERROR, format!("Failed to sync the mount: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn mount() -> (ControlledMount, tempfile::NamedTempFile) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = agentfs_sdk::AgentFSOptions::with_path(file.path().display().to_string());
+        let agentfs = agentfs_sdk::AgentFS::open(options).await.unwrap();
+        let mount = ControlledMount {
+            fsname: "agentfs:test".to_string(),
+            // Syncing is harmless on any directory, and doesn't need a FUSE mount
+            mountpoint: std::env::temp_dir(),
+            state: Arc::default(),
+            fs: agentfs.fs,
+        };
+        (mount, file)
+    }
+
+    #[tokio::test]
+    async fn test_stats_and_remount_ro() {
+        let (mount, _file) = mount().await;
+        mount.state.open_files.store(3, Ordering::Relaxed);
+
+        let response = handle_request(r#"{"jsonrpc":"2.0","id":1,"method":"stats"}"#, &mount)
+            .await
+            .unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["open_files"], 3);
+        assert_eq!(response["result"]["read_only"], false);
+
+        let request = r#"{"jsonrpc":"2.0","id":2,"method":"remount-ro"}"#;
+        let response = handle_request(request, &mount).await.unwrap();
+        assert_eq!(response["result"]["read_only"], true);
+        assert!(mount.state.read_only.load(Ordering::Relaxed));
+
+        let request = r#"{"jsonrpc":"2.0","id":3,"method":"list-mounts"}"#;
+        let response = handle_request(request, &mount).await.unwrap();
+        assert_eq!(response["result"]["mounts"][0]["fsname"], "agentfs:test");
+        assert_eq!(response["result"]["mounts"][0]["read_only"], true);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let (mount, _file) = mount().await;
+
+        let request = r#"{"jsonrpc":"2.0","id":1,"method":"snapshot","params":{"name":"one"}}"#;
+        let response = handle_request(request, &mount).await.unwrap();
+        assert_eq!(response["result"]["name"], "one");
+        let snapshots = mount.fs.list_snapshots().await.unwrap();
+        assert_eq!(snapshots.len(), 1);
+
+        // Taken names and missing parameters are errors
+        let response = handle_request(request, &mount).await.unwrap();
+        assert_eq!(response["error"]["code"], INTERNAL_ERROR);
+        let request = r#"{"jsonrpc":"2.0","id":2,"method":"snapshot"}"#;
+        let response = handle_request(request, &mount).await.unwrap();
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_requests() {
+        let (mount, _file) = mount().await;
+
+        let response = handle_request("{", &mount).await.unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+        let response = handle_request(r#"{"id":1}"#, &mount).await.unwrap();
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+        let request = r#"{"jsonrpc":"2.0","id":1,"method":"unmount"}"#;
+        let response = handle_request(request, &mount).await.unwrap();
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        // Notifications have no id, and get no response
+        let request = r#"{"jsonrpc":"2.0","method":"stats"}"#;
+        assert!(handle_request(request, &mount).await.is_none());
+    }
+}
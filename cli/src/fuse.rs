@@ -1,26 +1,30 @@
 use agentfs_sdk::error::Error as SdkError;
-use agentfs_sdk::{BoxedFile, FileSystem, Stats};
+use agentfs_sdk::{BoxedFile, DirEntry, FileSystem, Stats};
 use fuser::{
     consts::{
-        FUSE_ASYNC_READ, FUSE_CACHE_SYMLINKS, FUSE_NO_OPENDIR_SUPPORT, FUSE_PARALLEL_DIROPS,
-        FUSE_WRITEBACK_CACHE,
+        FOPEN_DIRECT_IO, FUSE_ASYNC_READ, FUSE_CACHE_SYMLINKS, FUSE_DO_READDIRPLUS, FUSE_MAX_PAGES,
+        FUSE_NO_OPENDIR_SUPPORT, FUSE_PARALLEL_DIROPS, FUSE_READDIRPLUS_AUTO, FUSE_WRITEBACK_CACHE,
     },
-    FileAttr, FileType, Filesystem, KernelConfig, MountOption, ReplyAttr, ReplyCreate, ReplyData,
-    ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite,
-    Request,
+    FileAttr, FileType, Filesystem, KernelConfig, MountOption, Notifier, ReplyAttr, ReplyCreate,
+    ReplyData, ReplyDirectory, ReplyDirectoryPlus, ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs,
+    ReplyWrite, Request,
 };
 use parking_lot::Mutex;
 use std::{
-    collections::HashMap,
-    ffi::OsStr,
+    collections::{HashMap, HashSet},
+    ffi::{CString, OsStr},
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::runtime::Runtime;
+use tokio::{
+    runtime::Runtime,
+    signal::unix::{signal, Signal, SignalKind},
+};
 
 /// Convert an SDK error to an errno code for FUSE replies.
 ///
@@ -36,10 +40,19 @@ fn error_to_errno(e: &SdkError) -> i32 {
     }
 }
 
-/// Cache entries never expire - we explicitly invalidate on mutations.
+/// By default cache entries never expire - we explicitly invalidate on mutations.
 /// This is safe because we are the only writer to the filesystem.
 const TTL: Duration = Duration::MAX;
 
+/// Largest write the kernel may send in one request.
+///
+/// This is the kernel's default limit of 256 pages. Without `FUSE_MAX_PAGES`
+/// writes are capped at 32 pages (128 KiB).
+const MAX_WRITE: u32 = 1024 * 1024;
+
+/// How often changes made outside the mount are checked for with `notify`.
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Options for mounting an agent filesystem via FUSE.
 #[derive(Debug, Clone)]
 pub struct FuseMountOptions {
@@ -55,6 +68,35 @@ pub struct FuseMountOptions {
     pub uid: Option<u32>,
     /// Group ID to report for all files (defaults to current group).
     pub gid: Option<u32>,
+    /// Mount the filesystem read-only.
+    pub read_only: bool,
+    /// Bypass the kernel page cache for file reads and writes.
+    pub direct_io: bool,
+    /// How long the kernel may cache file attributes (defaults to forever).
+    pub attr_timeout: Option<Duration>,
+    /// How long the kernel may cache name lookups (defaults to forever).
+    pub entry_timeout: Option<Duration>,
+    /// Push changes made to the filesystem outside the mount to the kernel.
+    pub notify: bool,
+    /// State of the mount, shared with whoever inspects or changes it while
+    /// it runs.
+    pub state: Arc<MountState>,
+    /// Unmount on SIGTERM and SIGINT, and return once the session ends.
+    pub unmount_on_signal: bool,
+}
+
+/// State of a running mount that can be inspected and changed from outside
+/// the FUSE session, e.g. through its control socket.
+#[derive(Debug, Default)]
+pub struct MountState {
+    /// Reject changes through the mount with `EROFS`.
+    pub read_only: AtomicBool,
+    /// Number of open file handles.
+    pub open_files: AtomicU64,
+    /// Directory listings continued from the cached entries.
+    pub dir_cache_hits: AtomicU64,
+    /// Directory listings read from the filesystem.
+    pub dir_cache_misses: AtomicU64,
 }
 
 /// Tracks an open file handle
@@ -82,6 +124,20 @@ struct AgentFSFuse {
     /// to lookup `/mntpnt` from he under filesystem, which will hit our mountpoint again,
     /// causing a deadlock.
     mountpoint_path: String,
+    /// How long the kernel may cache file attributes
+    attr_ttl: Duration,
+    /// How long the kernel may cache name lookups
+    entry_ttl: Duration,
+    /// Open files with `FOPEN_DIRECT_IO`, bypassing the page cache
+    direct_io: bool,
+    /// Entries of the directory last listed, by inode, so that listing a large
+    /// directory over several readdir calls queries the database only once
+    dir_listing: Option<(u64, Arc<Vec<DirEntry>>)>,
+    /// Inodes changed through the mount, which the change watcher must not
+    /// report as changed outside of it. Only tracked with `notify`.
+    local_changes: Option<Arc<Mutex<HashSet<u64>>>>,
+    /// State shared with the mount's control socket
+    state: Arc<MountState>,
 }
 
 impl Filesystem for AgentFSFuse {
@@ -91,20 +147,37 @@ impl Filesystem for AgentFSFuse {
     ///   improving throughput for concurrent file access.
     /// - Writeback caching: allows the kernel to buffer writes and flush them
     ///   later, significantly improving write performance for small writes.
+    ///   Not enabled with direct I/O, which bypasses the page cache anyway.
+    /// - Large writes: lets the kernel send up to `MAX_WRITE` bytes per write,
+    ///   so that flushing the page cache takes one transaction per megabyte
+    ///   rather than per 128 KiB.
     /// - Parallel dirops: allows concurrent lookup() and readdir() on the same
     ///   directory, improving performance for parallel file access patterns.
     /// - Cache symlinks: caches readlink responses, avoiding repeated round-trips
     ///   for symlink resolution.
+    /// - Readdirplus: returns attributes along with directory entries, so that
+    ///   `ls -l` doesn't need a lookup per entry. With readdirplus auto the
+    ///   kernel only asks for attributes when it expects them to be used, and
+    ///   uses plain readdir otherwise.
     /// - No opendir support: skips opendir/releasedir calls since we don't track
     ///   directory handles, reducing round-trips for directory operations.
     fn init(&mut self, _req: &Request, config: &mut KernelConfig) -> Result<(), libc::c_int> {
-        let _ = config.add_capabilities(
-            FUSE_ASYNC_READ
-                | FUSE_WRITEBACK_CACHE
-                | FUSE_PARALLEL_DIROPS
-                | FUSE_CACHE_SYMLINKS
-                | FUSE_NO_OPENDIR_SUPPORT,
-        );
+        let mut capabilities = FUSE_ASYNC_READ
+            | FUSE_PARALLEL_DIROPS
+            | FUSE_CACHE_SYMLINKS
+            | FUSE_NO_OPENDIR_SUPPORT
+            | FUSE_DO_READDIRPLUS
+            | FUSE_READDIRPLUS_AUTO;
+        if !self.direct_io {
+            capabilities |= FUSE_WRITEBACK_CACHE;
+        }
+        let _ = config.add_capabilities(capabilities);
+        // Kernels before 4.20 don't support FUSE_MAX_PAGES, and `max_write` is
+        // then capped at 128 KiB by the kernel regardless of what we ask for
+        let _ = config.add_capabilities(FUSE_MAX_PAGES);
+        if let Err(max) = config.set_max_write(MAX_WRITE) {
+            let _ = config.set_max_write(max);
+        }
         Ok(())
     }
 
@@ -130,7 +203,7 @@ impl Filesystem for AgentFSFuse {
             Ok(Some(stats)) => {
                 let attr = fillattr(&stats, self.uid, self.gid);
                 self.add_path(attr.ino, path);
-                reply.entry(&TTL, &attr, 0);
+                reply.entry(&self.entry_ttl, &attr, 0);
             }
             Ok(None) => reply.error(libc::ENOENT),
             Err(e) => reply.error(error_to_errno(&e)),
@@ -151,7 +224,7 @@ impl Filesystem for AgentFSFuse {
         let result = self.runtime.block_on(async move { fs.lstat(&path).await });
 
         match result {
-            Ok(Some(stats)) => reply.attr(&TTL, &fillattr(&stats, self.uid, self.gid)),
+            Ok(Some(stats)) => reply.attr(&self.attr_ttl, &fillattr(&stats, self.uid, self.gid)),
             Ok(None) => reply.error(libc::ENOENT),
             Err(e) => reply.error(error_to_errno(&e)),
         }
@@ -201,6 +274,10 @@ impl Filesystem for AgentFSFuse {
         _flags: Option<u32>,
         reply: ReplyAttr,
     ) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         // Handle chmod
         if let Some(new_mode) = mode {
             let Some(path) = self.path_cache.lock().get(&ino).cloned() else {
@@ -255,6 +332,10 @@ impl Filesystem for AgentFSFuse {
             }
         }
 
+        if mode.is_some() || size.is_some() {
+            self.mark_changed(ino);
+        }
+
         // Return updated attributes
         let Some(path) = self.get_path(ino) else {
             reply.error(libc::ENOENT);
@@ -265,7 +346,7 @@ impl Filesystem for AgentFSFuse {
         let result = self.runtime.block_on(async move { fs.stat(&path).await });
 
         match result {
-            Ok(Some(stats)) => reply.attr(&TTL, &fillattr(&stats, self.uid, self.gid)),
+            Ok(Some(stats)) => reply.attr(&self.attr_ttl, &fillattr(&stats, self.uid, self.gid)),
             Ok(None) => reply.error(libc::ENOENT),
             Err(e) => reply.error(error_to_errno(&e)),
         }
@@ -281,7 +362,8 @@ impl Filesystem for AgentFSFuse {
     /// Each entry's inode is cached for subsequent lookups.
     ///
     /// Uses readdir_plus to fetch entries with stats in a single query,
-    /// avoiding N+1 database queries.
+    /// avoiding N+1 database queries. The kernel calls this rather than
+    /// `readdirplus` when it doesn't expect to need the attributes.
     fn readdir(
         &mut self,
         _req: &Request,
@@ -295,13 +377,7 @@ impl Filesystem for AgentFSFuse {
             return;
         };
 
-        let fs = self.fs.clone();
-        let (entries_result, path) = self.runtime.block_on(async move {
-            let result = fs.readdir_plus(&path).await;
-            (result, path)
-        });
-
-        let entries = match entries_result {
+        let entries = match self.list_dir(ino, &path, offset) {
             Ok(Some(entries)) => entries,
             Ok(None) => {
                 reply.error(libc::ENOENT);
@@ -349,7 +425,7 @@ impl Filesystem for AgentFSFuse {
         ];
 
         // Process entries with stats already available (no N+1 queries!)
-        for entry in &entries {
+        for entry in entries.iter() {
             let entry_path = if path == "/" {
                 format!("/{}", entry.name)
             } else {
@@ -394,13 +470,7 @@ impl Filesystem for AgentFSFuse {
             return;
         };
 
-        let fs = self.fs.clone();
-        let (entries_result, path) = self.runtime.block_on(async move {
-            let result = fs.readdir_plus(&path).await;
-            (result, path)
-        });
-
-        let entries = match entries_result {
+        let entries = match self.list_dir(ino, &path, offset) {
             Ok(Some(entries)) => entries,
             Ok(None) => {
                 reply.error(libc::ENOENT);
@@ -467,7 +537,7 @@ impl Filesystem for AgentFSFuse {
         if offset <= offset_counter {
             if let Some(ref stats) = dir_stats {
                 let attr = fillattr(stats, uid, gid);
-                if reply.add(ino, offset_counter + 1, ".", &TTL, &attr, 0) {
+                if reply.add(ino, offset_counter + 1, ".", &self.entry_ttl, &attr, 0) {
                     reply.ok();
                     return;
                 }
@@ -479,7 +549,14 @@ impl Filesystem for AgentFSFuse {
         if offset <= offset_counter {
             if let Some(ref stats) = parent_stats {
                 let attr = fillattr(stats, uid, gid);
-                if reply.add(parent_ino, offset_counter + 1, "..", &TTL, &attr, 0) {
+                if reply.add(
+                    parent_ino,
+                    offset_counter + 1,
+                    "..",
+                    &self.entry_ttl,
+                    &attr,
+                    0,
+                ) {
                     reply.ok();
                     return;
                 }
@@ -488,7 +565,7 @@ impl Filesystem for AgentFSFuse {
         offset_counter += 1;
 
         // Add directory entries with their attributes
-        for entry in &entries {
+        for entry in entries.iter() {
             if offset <= offset_counter {
                 let entry_path = if path == "/" {
                     format!("/{}", entry.name)
@@ -503,7 +580,7 @@ impl Filesystem for AgentFSFuse {
                     entry.stats.ino as u64,
                     offset_counter + 1,
                     &entry.name,
-                    &TTL,
+                    &self.entry_ttl,
                     &attr,
                     0,
                 ) {
@@ -530,6 +607,10 @@ impl Filesystem for AgentFSFuse {
         _umask: u32,
         reply: ReplyEntry,
     ) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let Some(path) = self.lookup_path(parent, name) else {
             reply.error(libc::ENOENT);
             return;
@@ -557,7 +638,7 @@ impl Filesystem for AgentFSFuse {
             Ok(Some(stats)) => {
                 let attr = fillattr(&stats, self.uid, self.gid);
                 self.add_path(attr.ino, path);
-                reply.entry(&TTL, &attr, 0);
+                reply.entry(&self.entry_ttl, &attr, 0);
             }
             Ok(None) => {
                 reply.error(libc::ENOENT);
@@ -573,6 +654,10 @@ impl Filesystem for AgentFSFuse {
     /// Verifies the target is a directory and is empty before removal.
     /// Returns `ENOTDIR` if not a directory, `ENOTEMPTY` if not empty.
     fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let Some(path) = self.lookup_path(parent, name) else {
             reply.error(libc::ENOENT);
             return;
@@ -657,6 +742,10 @@ impl Filesystem for AgentFSFuse {
         _flags: i32,
         reply: ReplyCreate,
     ) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let Some(path) = self.lookup_path(parent, name) else {
             reply.error(libc::ENOENT);
             return;
@@ -676,8 +765,9 @@ impl Filesystem for AgentFSFuse {
 
                 let fh = self.alloc_fh();
                 self.open_files.lock().insert(fh, OpenFile { file });
+                self.state.open_files.fetch_add(1, Ordering::Relaxed);
 
-                reply.created(&TTL, &attr, 0, fh, 0);
+                reply.created(&self.entry_ttl, &attr, 0, fh, self.open_flags());
             }
             Err(e) => {
                 reply.error(error_to_errno(&e));
@@ -696,6 +786,10 @@ impl Filesystem for AgentFSFuse {
         target: &Path,
         reply: ReplyEntry,
     ) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let Some(path) = self.lookup_path(parent, link_name) else {
             reply.error(libc::ENOENT);
             return;
@@ -729,7 +823,7 @@ impl Filesystem for AgentFSFuse {
             Ok(Some(stats)) => {
                 let attr = fillattr(&stats, self.uid, self.gid);
                 self.add_path(attr.ino, path);
-                reply.entry(&TTL, &attr, 0);
+                reply.entry(&self.entry_ttl, &attr, 0);
             }
             Ok(None) => {
                 reply.error(libc::ENOENT);
@@ -752,6 +846,10 @@ impl Filesystem for AgentFSFuse {
         newname: &OsStr,
         reply: ReplyEntry,
     ) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         // Get the path for the source inode
         let Some(oldpath) = self.get_path(ino) else {
             reply.error(libc::ENOENT);
@@ -786,7 +884,7 @@ impl Filesystem for AgentFSFuse {
             Ok(Some(stats)) => {
                 let attr = fillattr(&stats, self.uid, self.gid);
                 self.add_path(attr.ino, newpath);
-                reply.entry(&TTL, &attr, 0);
+                reply.entry(&self.entry_ttl, &attr, 0);
             }
             Ok(None) => {
                 reply.error(libc::ENOENT);
@@ -801,6 +899,10 @@ impl Filesystem for AgentFSFuse {
     ///
     /// Gets the file's inode before removal to clean up the path cache.
     fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let Some(path) = self.lookup_path(parent, name) else {
             reply.error(libc::ENOENT);
             return;
@@ -864,6 +966,10 @@ impl Filesystem for AgentFSFuse {
         _flags: u32,
         reply: ReplyEmpty,
     ) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let Some(from_path) = self.lookup_path(parent, name) else {
             reply.error(libc::ENOENT);
             return;
@@ -923,7 +1029,12 @@ impl Filesystem for AgentFSFuse {
     /// Opens a file for reading or writing.
     ///
     /// Allocates a file handle and opens the file in the filesystem layer.
-    fn open(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        let writable = flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0;
+        if writable && self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let Some(path) = self.get_path(ino) else {
             reply.error(libc::ENOENT);
             return;
@@ -939,7 +1050,8 @@ impl Filesystem for AgentFSFuse {
             Ok(file) => {
                 let fh = self.alloc_fh();
                 self.open_files.lock().insert(fh, OpenFile { file });
-                reply.opened(fh, 0);
+                self.state.open_files.fetch_add(1, Ordering::Relaxed);
+                reply.opened(fh, self.open_flags());
             }
             Err(e) => reply.error(error_to_errno(&e)),
         }
@@ -980,7 +1092,7 @@ impl Filesystem for AgentFSFuse {
     fn write(
         &mut self,
         _req: &Request,
-        _ino: u64,
+        ino: u64,
         fh: u64,
         offset: i64,
         data: &[u8],
@@ -989,6 +1101,10 @@ impl Filesystem for AgentFSFuse {
         _lock_owner: Option<u64>,
         reply: ReplyWrite,
     ) {
+        if self.read_only() {
+            reply.error(libc::EROFS);
+            return;
+        }
         let file = {
             let open_files = self.open_files.lock();
             let Some(open_file) = open_files.get(&fh) else {
@@ -1005,7 +1121,10 @@ impl Filesystem for AgentFSFuse {
             .block_on(async move { file.pwrite(offset as u64, &data_vec).await });
 
         match result {
-            Ok(()) => reply.written(data_len as u32),
+            Ok(()) => {
+                self.mark_changed(ino);
+                reply.written(data_len as u32);
+            }
             Err(e) => reply.error(error_to_errno(&e)),
         }
     }
@@ -1026,6 +1145,10 @@ impl Filesystem for AgentFSFuse {
     ///
     /// This now uses the file handle's fsync which knows which layer(s) the
     /// file exists in, avoiding errors when a file only exists in one layer.
+    ///
+    /// With writeback caching, the kernel writes back the file's dirty pages
+    /// before sending the fsync, so they are all in the database by the time
+    /// it is made durable here.
     fn fsync(&mut self, _req: &Request, _ino: u64, fh: u64, _datasync: bool, reply: ReplyEmpty) {
         let file = {
             let open_files = self.open_files.lock();
@@ -1060,39 +1183,44 @@ impl Filesystem for AgentFSFuse {
         _flush: bool,
         reply: ReplyEmpty,
     ) {
-        self.open_files.lock().remove(&fh);
+        if self.open_files.lock().remove(&fh).is_some() {
+            self.state.open_files.fetch_sub(1, Ordering::Relaxed);
+        }
         reply.ok();
     }
 
     /// Returns filesystem statistics.
     ///
     /// Queries actual usage from the SDK and reports it to tools like `df`.
+    /// A quota, if set, is reported as the capacity.
     fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
         const BLOCK_SIZE: u64 = 4096;
         const TOTAL_INODES: u64 = 1_000_000; // Virtual limit
         const MAX_NAMELEN: u32 = 255;
+        // Report a large virtual capacity so tools don't think we're out of space
+        const TOTAL_BLOCKS: u64 = 1024 * 1024 * 1024; // ~4TB virtual size
 
         let fs = self.fs.clone();
         let result = self.runtime.block_on(async move { fs.statfs().await });
 
-        let (used_blocks, used_inodes) = match result {
+        let (used_blocks, used_inodes, quota) = match result {
             Ok(stats) => {
                 let used_blocks = stats.bytes_used.div_ceil(BLOCK_SIZE);
-                (used_blocks, stats.inodes)
+                (used_blocks, stats.inodes, stats.quota)
             }
-            Err(_) => (0, 1), // Fallback: just root inode
+            Err(_) => (0, 1, Default::default()), // Fallback: just root inode
         };
 
-        // Report a large virtual capacity so tools don't think we're out of space
-        const TOTAL_BLOCKS: u64 = 1024 * 1024 * 1024; // ~4TB virtual size
-        let free_blocks = TOTAL_BLOCKS.saturating_sub(used_blocks);
-        let free_inodes = TOTAL_INODES.saturating_sub(used_inodes);
+        let total_blocks = quota.bytes.map_or(TOTAL_BLOCKS, |bytes| bytes / BLOCK_SIZE);
+        let total_inodes = quota.inodes.unwrap_or(TOTAL_INODES);
+        let free_blocks = total_blocks.saturating_sub(used_blocks);
+        let free_inodes = total_inodes.saturating_sub(used_inodes);
 
         reply.statfs(
-            TOTAL_BLOCKS,
+            total_blocks,
             free_blocks,
             free_blocks,
-            TOTAL_INODES,
+            total_inodes,
             free_inodes,
             BLOCK_SIZE as u32,
             MAX_NAMELEN,       // namelen: maximum filename length
@@ -1125,6 +1253,63 @@ impl AgentFSFuse {
             uid,
             gid,
             mountpoint_path: mountpoint_path.as_os_str().to_string_lossy().to_string(),
+            attr_ttl: TTL,
+            entry_ttl: TTL,
+            direct_io: false,
+            dir_listing: None,
+            local_changes: None,
+            state: Arc::default(),
+        }
+    }
+
+    /// Whether changes through the mount are rejected with `EROFS`.
+    fn read_only(&self) -> bool {
+        self.state.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Record that `ino` changed through the mount, if changes are watched for.
+    fn mark_changed(&self, ino: u64) {
+        if let Some(local_changes) = &self.local_changes {
+            local_changes.lock().insert(ino);
+        }
+    }
+
+    /// List the directory `ino` at `path` with readdir_plus.
+    ///
+    /// A listing continued at a non-zero `offset` reuses the entries fetched
+    /// when it started, which also keeps offsets stable between calls.
+    fn list_dir(
+        &mut self,
+        ino: u64,
+        path: &str,
+        offset: i64,
+    ) -> Result<Option<Arc<Vec<DirEntry>>>, SdkError> {
+        if offset > 0 {
+            if let Some((cached_ino, entries)) = &self.dir_listing {
+                if *cached_ino == ino {
+                    self.state.dir_cache_hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(Some(entries.clone()));
+                }
+            }
+        }
+        self.state.dir_cache_misses.fetch_add(1, Ordering::Relaxed);
+
+        let fs = self.fs.clone();
+        let path = path.to_string();
+        let entries = self
+            .runtime
+            .block_on(async move { fs.readdir_plus(&path).await })?;
+        let entries = entries.map(Arc::new);
+        self.dir_listing = entries.clone().map(|entries| (ino, entries));
+        Ok(entries)
+    }
+
+    /// Flags to reply to `open` and `create` with.
+    fn open_flags(&self) -> u32 {
+        if self.direct_io {
+            FOPEN_DIRECT_IO
+        } else {
+            0
         }
     }
 
@@ -1248,7 +1433,17 @@ pub fn mount(
     let uid = opts.uid.unwrap_or_else(|| unsafe { libc::getuid() });
     let gid = opts.gid.unwrap_or_else(|| unsafe { libc::getgid() });
 
-    let fs = AgentFSFuse::new(fs, runtime, uid, gid, opts.mountpoint.clone());
+    let mut fs = AgentFSFuse::new(fs, runtime, uid, gid, opts.mountpoint.clone());
+    fs.attr_ttl = opts.attr_timeout.unwrap_or(TTL);
+    fs.entry_ttl = opts.entry_timeout.unwrap_or(TTL);
+    fs.direct_io = opts.direct_io;
+    if opts.read_only {
+        opts.state.read_only.store(true, Ordering::Relaxed);
+    }
+    fs.state = opts.state.clone();
+    if opts.notify {
+        fs.local_changes = Some(Arc::new(Mutex::new(HashSet::new())));
+    }
 
     fs.add_path(1, "/".to_string());
 
@@ -1259,8 +1454,201 @@ pub fn mount(
     if opts.allow_root {
         mount_opts.push(MountOption::AllowRoot);
     }
+    if opts.read_only {
+        mount_opts.push(MountOption::RO);
+    }
 
-    fuser::mount2(fs, &opts.mountpoint, &mount_opts)?;
+    let runtime = fs.runtime.handle().clone();
+    let watcher = fs.local_changes.clone().map(|local_changes| ChangeWatcher {
+        fs: fs.fs.clone(),
+        runtime: runtime.clone(),
+        path_cache: fs.path_cache.clone(),
+        local_changes,
+        mountpoint: opts.mountpoint.clone(),
+        read_only: opts.read_only,
+        known: HashMap::new(),
+    });
+
+    // Registered before the mount exists, so that no signal can kill the
+    // process while it is mounted
+    let signals = if opts.unmount_on_signal {
+        let _guard = runtime.enter();
+        Some((
+            signal(SignalKind::terminate())?,
+            signal(SignalKind::interrupt())?,
+        ))
+    } else {
+        None
+    };
+
+    let mut session = fuser::Session::new(fs, &opts.mountpoint, &mount_opts)?;
+    let signals = signals.map(|(sigterm, sigint)| {
+        runtime.spawn(unmount_on_signal(sigterm, sigint, opts.mountpoint.clone()))
+    });
+    let stop = Arc::new(AtomicBool::new(false));
+    let watcher = watcher.map(|watcher| {
+        let notifier = session.notifier();
+        let stop = stop.clone();
+        std::thread::spawn(move || watcher.run(&notifier, &stop))
+    });
+
+    let result = session.run();
+    if let Some(signals) = signals {
+        signals.abort();
+    }
+    stop.store(true, Ordering::Relaxed);
+    if let Some(watcher) = watcher {
+        let _ = watcher.join();
+    }
+    result?;
 
     Ok(())
 }
+
+/// Unmount `mountpoint` on SIGTERM or SIGINT, so that the session ends instead
+/// of the process being killed with the filesystem still mounted.
+///
+/// The unmount is lazy: the mount disappears at once, but files open in it
+/// keep working until they are closed, after which the session ends.
+async fn unmount_on_signal(mut sigterm: Signal, mut sigint: Signal, mountpoint: PathBuf) {
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = sigint.recv() => {}
+    }
+    tracing::info!("Unmounting {}", mountpoint.display());
+    if let Err(e) = lazy_unmount(&mountpoint) {
+        tracing::error!("{}", e);
+    }
+}
+
+/// Detach the mount at `mountpoint` from the filesystem tree.
+///
+/// Mounts made without privileges can only be unmounted through the setuid
+/// `fusermount` helper.
+fn lazy_unmount(mountpoint: &Path) -> std::io::Result<()> {
+    let path = CString::new(mountpoint.as_os_str().as_bytes())?;
+    if unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) } == 0 {
+        return Ok(());
+    }
+    for cmd in ["fusermount3", "fusermount"] {
+        let status = std::process::Command::new(cmd)
+            .arg("-u")
+            .arg("-z")
+            .arg(mountpoint)
+            .status();
+        if status.is_ok_and(|status| status.success()) {
+            return Ok(());
+        }
+    }
+    Err(std::io::Error::other(format!(
+        "Failed to unmount {}",
+        mountpoint.display()
+    )))
+}
+
+/// Attributes of an inode that change when it is modified
+type Version = (u32, u32, i64, i64, i64);
+
+fn version(stats: &Stats) -> Version {
+    (
+        stats.mode,
+        stats.nlink,
+        stats.size,
+        stats.mtime,
+        stats.ctime,
+    )
+}
+
+/// Pushes changes made to the filesystem outside the mount, e.g. by
+/// `agentfs fs put`, to the kernel with FUSE notifications.
+///
+/// The inodes the kernel knows about are polled for changes every
+/// `NOTIFY_INTERVAL`. A changed inode has its cached attributes and data
+/// invalidated, and a removed one is deleted from the kernel's directory
+/// cache, which inotify watchers see as `IN_DELETE`. Invalidation itself
+/// is invisible to inotify, so a changed file is also opened and closed for
+/// writing through the mount, which watchers see as `IN_CLOSE_WRITE`.
+struct ChangeWatcher {
+    fs: Arc<dyn FileSystem>,
+    runtime: tokio::runtime::Handle,
+    path_cache: Arc<Mutex<HashMap<u64, String>>>,
+    local_changes: Arc<Mutex<HashSet<u64>>>,
+    mountpoint: PathBuf,
+    read_only: bool,
+    /// Version of each inode when it was last polled
+    known: HashMap<u64, Version>,
+}
+
+impl ChangeWatcher {
+    fn run(mut self, notifier: &Notifier, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(NOTIFY_INTERVAL);
+            self.poll(notifier);
+        }
+    }
+
+    fn poll(&mut self, notifier: &Notifier) {
+        let paths: Vec<(u64, String)> = self
+            .path_cache
+            .lock()
+            .iter()
+            .map(|(ino, path)| (*ino, path.clone()))
+            .collect();
+        let inodes: HashMap<&str, u64> = paths
+            .iter()
+            .map(|(ino, path)| (path.as_str(), *ino))
+            .collect();
+
+        for (ino, path) in &paths {
+            let fs = self.fs.clone();
+            let path_for_stat = path.clone();
+            let result = self
+                .runtime
+                .block_on(async move { fs.lstat(&path_for_stat).await });
+
+            match result {
+                Ok(Some(stats)) if stats.ino as u64 == *ino => {
+                    // Changes through the mount are made before they are
+                    // marked, so they are all in the version recorded here
+                    let old = self.known.insert(*ino, version(&stats));
+                    let local = self.local_changes.lock().remove(ino);
+                    if old.is_some_and(|old| old != version(&stats)) && !local {
+                        self.changed(notifier, *ino, path, &stats);
+                    }
+                }
+                // Removed, or replaced by another inode
+                Ok(_) => {
+                    if self.known.remove(ino).is_some() {
+                        self.removed(notifier, *ino, path, &inodes);
+                    }
+                }
+                // Try again on the next poll, e.g. if the database was busy
+                Err(_) => {}
+            }
+        }
+
+        // Forget inodes dropped from the path cache since the last poll
+        let polled: HashSet<u64> = paths.iter().map(|(ino, _)| *ino).collect();
+        self.known.retain(|ino, _| polled.contains(ino));
+    }
+
+    fn changed(&self, notifier: &Notifier, ino: u64, path: &str, stats: &Stats) {
+        let _ = notifier.inval_inode(ino, 0, 0);
+        if stats.is_file() && !self.read_only {
+            let _ = std::fs::OpenOptions::new()
+                .write(true)
+                .open(self.mountpoint.join(path.trim_start_matches('/')));
+        }
+    }
+
+    fn removed(&self, notifier: &Notifier, ino: u64, path: &str, inodes: &HashMap<&str, u64>) {
+        let path = Path::new(path);
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        if let Some(parent_ino) = parent.to_str().and_then(|parent| inodes.get(parent)) {
+            // Fails harmlessly if the kernel has already forgotten the entry
+            let _ = notifier.delete(*parent_ino, ino, name);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Gitignore-style patterns of `.agentfsignore` files.
+//!
+//! Supported are `#` comments, `!` negation, a trailing `/` to match only
+//! directories, and `*`, `?`, `[...]` and `**` wildcards. A pattern without a
+//! `/` other than a trailing one matches a name at any depth; any other
+//! pattern matches paths relative to the directory of the ignore file.
+
+use std::io;
+use std::path::Path;
+
+use agentfs_sdk::glob::Glob;
+
+/// Name of the file with the patterns of paths to leave out of a directory
+pub const IGNORE_FILE: &str = ".agentfsignore";
+
+/// The patterns of an ignore file
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    /// The pattern, without the negation and trailing `/`
+    glob: Glob,
+    /// Match relative to the root rather than a name at any depth
+    anchored: bool,
+    /// Match only directories
+    dir_only: bool,
+    /// Re-include paths excluded by an earlier pattern
+    negated: bool,
+}
+
+impl IgnoreRules {
+    /// Parse the patterns of an ignore file, one per line
+    pub fn parse(text: &str) -> Self {
+        let rules = text.lines().filter_map(Rule::parse).collect();
+        Self { rules }
+    }
+
+    /// Load the ignore file of `dir`, if it has one
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check whether `path`, relative to the root and separated by `/`, is ignored
+    ///
+    /// As with gitignore, the last pattern that matches decides.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let parts: Vec<&str> = path.split('/').collect();
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.matches(&parts, is_dir) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (line, negated) = match line.strip_prefix('!') {
+            Some(line) => (line, true),
+            None => (line.strip_prefix('\\').unwrap_or(line), false),
+        };
+        let (line, dir_only) = match line.strip_suffix('/') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let anchored = line.contains('/');
+        if line.split('/').all(str::is_empty) {
+            return None;
+        }
+        Some(Self {
+            glob: Glob::new(line),
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    fn matches(&self, parts: &[&str], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.glob.matches(parts)
+        } else {
+            parts.last().is_some_and(|name| self.glob.matches(&[name]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = IgnoreRules::parse(
+            "# build output\n\
+             target/\n\
+             *.log\n\
+             !keep.log\n\
+             /secret.txt\n\
+             docs/**/*.tmp\n\
+             \n",
+        );
+
+        assert!(rules.is_ignored("target", true));
+        assert!(rules.is_ignored("sub/target", true));
+        assert!(!rules.is_ignored("target", false));
+
+        assert!(rules.is_ignored("debug.log", false));
+        assert!(rules.is_ignored("a/b/debug.log", false));
+        assert!(!rules.is_ignored("keep.log", false));
+
+        assert!(rules.is_ignored("secret.txt", false));
+        assert!(!rules.is_ignored("sub/secret.txt", false));
+
+        assert!(rules.is_ignored("docs/x.tmp", false));
+        assert!(rules.is_ignored("docs/a/b/x.tmp", false));
+        assert!(!rules.is_ignored("x.tmp", false));
+
+        assert!(!rules.is_ignored("src/main.rs", false));
+        assert!(!IgnoreRules::default().is_ignored("anything", false));
+    }
+}
@@ -1,7 +1,12 @@
 pub mod cmd;
+pub mod ignore;
 pub mod parser;
+pub mod passphrase;
 pub mod sandbox;
 
+#[cfg(target_os = "linux")]
+pub mod control;
+
 #[cfg(target_os = "linux")]
 pub mod daemon;
 
@@ -11,6 +16,9 @@ pub mod fuse;
 #[cfg(unix)]
 pub mod nfs;
 
+#[cfg(unix)]
+pub mod nfs_access;
+
 pub fn get_runtime() -> tokio::runtime::Runtime {
     tokio::runtime::Runtime::new().expect("Internal error: failed to initialize runtime")
 }
@@ -1,35 +1,66 @@
 use agentfs::{
     cmd::{self, completions::handle_completions},
     get_runtime,
-    parser::{Args, Command, FsCommand, PruneCommand, ServeCommand, SyncCommand},
+    parser::{
+        Args, Command, FsCommand, LogFormat, LogLevel, PruneCommand, ServeCommand, SnapshotCommand,
+        SyncCommand,
+    },
+    passphrase,
 };
 use clap::{CommandFactory, Parser};
 use clap_complete::CompleteEnv;
 use tracing_subscriber::prelude::*;
 
 fn main() {
-    let _ = tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "agentfs=info".into()),
-        )
-        .try_init();
-
     reset_sigpipe();
 
     CompleteEnv::with_factory(Args::command).complete();
     let args = Args::parse();
 
+    init_tracing(args.log_level, args.log_format);
+
     match args.command {
         Command::Init {
             id,
             force,
+            repair,
+            apply,
             base,
+            from,
+            dedup,
+            compress,
+            compression_level,
+            passphrase_file,
+            quota,
+            quota_inodes,
             sync,
         } => {
+            init_passphrase(passphrase_file.as_deref());
             let rt = get_runtime();
-            if let Err(e) = rt.block_on(cmd::init::init_database(id, sync, force, base)) {
+            let result = if repair {
+                // clap requires an ID with --repair
+                rt.block_on(cmd::init::repair_database(
+                    &mut std::io::stdout(),
+                    id.unwrap_or_default(),
+                    apply,
+                ))
+            } else {
+                rt.block_on(cmd::init::init_database(
+                    id,
+                    sync,
+                    force,
+                    base,
+                    from,
+                    dedup,
+                    &compress,
+                    compression_level,
+                    agentfs_sdk::Quota {
+                        bytes: quota,
+                        inodes: quota_inodes,
+                    },
+                ))
+            };
+            if let Err(e) = result {
                 eprintln!("Error: {}", e);
                 std::process::exit(1);
             }
@@ -75,10 +106,39 @@ fn main() {
             no_default_allows,
             experimental_sandbox,
             strace,
+            strace_output,
+            strace_format,
+            strict_fds,
+            max_open_files,
             session,
+            mounts,
+            ro_host_mounts,
+            overlay,
+            excludes,
+            uid_map,
+            gid_map,
+            default_mode,
+            default_dir_mode,
+            default_owner,
+            deny_syscalls,
+            allow_only,
+            deny_errno,
+            no_network,
+            dry_run,
+            summary_json,
+            clear_env,
+            set_env,
+            unset_env,
+            workdir,
+            timeout,
+            cpu_limit,
+            memory_limit,
+            vfs_cache_size,
+            passphrase_file,
             command,
             args,
         } => {
+            init_passphrase(passphrase_file.as_deref());
             let command = command.unwrap_or_else(default_shell);
             let rt = get_runtime();
             if let Err(e) = rt.block_on(cmd::handle_run_command(
@@ -86,7 +146,34 @@ fn main() {
                 no_default_allows,
                 experimental_sandbox,
                 strace,
+                strace_output,
+                strace_format,
+                strict_fds,
+                max_open_files,
                 session,
+                mounts,
+                ro_host_mounts,
+                overlay,
+                excludes,
+                uid_map,
+                gid_map,
+                default_mode,
+                default_dir_mode,
+                default_owner,
+                deny_syscalls,
+                allow_only,
+                deny_errno,
+                no_network,
+                dry_run,
+                summary_json,
+                clear_env,
+                set_env,
+                unset_env,
+                workdir,
+                timeout,
+                cpu_limit,
+                memory_limit,
+                vfs_cache_size,
                 command,
                 args,
             )) {
@@ -102,8 +189,19 @@ fn main() {
             foreground,
             uid,
             gid,
+            uid_map,
+            gid_map,
+            read_only,
+            direct_io,
+            attr_timeout,
+            entry_timeout,
+            notify,
+            vfs_cache_size,
+            passphrase_file,
+            control_socket,
         } => match (id_or_path, mountpoint) {
             (Some(id_or_path), Some(mountpoint)) => {
+                init_passphrase(passphrase_file.as_deref());
                 if let Err(e) = cmd::mount(cmd::MountArgs {
                     id_or_path,
                     mountpoint,
@@ -112,6 +210,15 @@ fn main() {
                     foreground,
                     uid,
                     gid,
+                    uid_map,
+                    gid_map,
+                    read_only,
+                    direct_io,
+                    attr_timeout,
+                    entry_timeout,
+                    notify,
+                    vfs_cache_size,
+                    control_socket,
                 }) {
                     eprintln!("Error: {}", e);
                     std::process::exit(1);
@@ -125,9 +232,81 @@ fn main() {
                 std::process::exit(1);
             }
         },
-        Command::Diff { id_or_path } => {
+        Command::Snapshot {
+            id_or_path,
+            command,
+        } => {
+            let rt = get_runtime();
+            let result = match command {
+                SnapshotCommand::Create { snapshot_name } => {
+                    rt.block_on(cmd::snapshot::create_snapshot(id_or_path, &snapshot_name))
+                }
+                SnapshotCommand::List => rt.block_on(cmd::snapshot::list_snapshots(
+                    &mut std::io::stdout(),
+                    id_or_path,
+                )),
+                SnapshotCommand::Restore { snapshot_name } => {
+                    rt.block_on(cmd::snapshot::restore_snapshot(id_or_path, &snapshot_name))
+                }
+                SnapshotCommand::Delete { snapshot_name } => {
+                    rt.block_on(cmd::snapshot::delete_snapshot(id_or_path, &snapshot_name))
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Verify { id_or_path } => {
             let rt = get_runtime();
-            if let Err(e) = rt.block_on(cmd::fs::diff_filesystem(id_or_path)) {
+            if let Err(e) = rt.block_on(cmd::init::verify_database(
+                &mut std::io::stdout(),
+                id_or_path,
+            )) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Export {
+            id_or_path,
+            output_tar,
+            checksum,
+        } => {
+            let rt = get_runtime();
+            if let Err(e) = rt.block_on(cmd::archive::export_filesystem(
+                &mut std::io::stderr(),
+                id_or_path,
+                &output_tar,
+                checksum,
+            )) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Import { input_tar, id } => {
+            let rt = get_runtime();
+            if let Err(e) = rt.block_on(cmd::archive::import_filesystem(&input_tar, id)) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Diff {
+            left,
+            right,
+            format,
+        } => {
+            let rt = get_runtime();
+            let mut stdout = std::io::stdout();
+            let result = match right {
+                Some(right) => rt.block_on(cmd::fs::compare_filesystems(
+                    &mut stdout,
+                    &left,
+                    &right,
+                    &format,
+                )),
+                None => rt.block_on(cmd::fs::diff_filesystem(&mut stdout, left, &format)),
+            };
+            if let Err(e) = result {
                 eprintln!("Error: {}", e);
                 std::process::exit(1);
             }
@@ -161,21 +340,81 @@ fn main() {
         } => {
             let rt = get_runtime();
             match command {
-                FsCommand::Ls { fs_path } => {
+                FsCommand::Ls { fs_path, inode } => {
                     if let Err(e) = rt.block_on(cmd::fs::ls_filesystem(
                         &mut std::io::stdout(),
                         id_or_path,
                         &fs_path,
+                        inode,
                     )) {
                         eprintln!("Error: {}", e);
                         std::process::exit(1);
                     }
                 }
-                FsCommand::Cat { file_path } => {
+                FsCommand::Tree { fs_path, max_depth } => {
+                    if let Err(e) = rt.block_on(cmd::fs::tree_filesystem(
+                        &mut std::io::stdout(),
+                        id_or_path,
+                        &fs_path,
+                        max_depth,
+                    )) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Stat { file_path, follow } => {
+                    if let Err(e) = rt.block_on(cmd::fs::stat_filesystem(
+                        &mut std::io::stdout(),
+                        id_or_path,
+                        &file_path,
+                        follow,
+                    )) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Find {
+                    root,
+                    name_glob,
+                    type_filter,
+                } => {
+                    if let Err(e) = rt.block_on(cmd::fs::find_filesystem(
+                        &mut std::io::stdout(),
+                        id_or_path,
+                        &root,
+                        name_glob.as_deref(),
+                        type_filter.as_deref(),
+                    )) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Du {
+                    path,
+                    summarize,
+                    stored,
+                } => {
+                    if let Err(e) = rt.block_on(cmd::fs::du_filesystem(
+                        &mut std::io::stdout(),
+                        id_or_path,
+                        &path,
+                        summarize,
+                        stored,
+                    )) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Cat {
+                    file_path,
+                    checksum,
+                } => {
                     if let Err(e) = rt.block_on(cmd::fs::cat_filesystem(
                         &mut std::io::stdout(),
+                        &mut std::io::stderr(),
                         id_or_path,
                         &file_path,
+                        checksum,
                     )) {
                         eprintln!("Error: {}", e);
                         std::process::exit(1);
@@ -189,6 +428,54 @@ fn main() {
                         std::process::exit(1);
                     }
                 }
+                FsCommand::Put {
+                    host_path,
+                    dest_path,
+                    no_clobber,
+                } => {
+                    if let Err(e) = rt.block_on(cmd::fs::put_filesystem(
+                        &mut std::io::stdin(),
+                        &mut std::io::stdout(),
+                        id_or_path,
+                        &host_path,
+                        &dest_path,
+                        no_clobber,
+                    )) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Mkdir { dir_path, parents } => {
+                    if let Err(e) =
+                        rt.block_on(cmd::fs::mkdir_filesystem(id_or_path, &dir_path, parents))
+                    {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Cp {
+                    src,
+                    dest,
+                    recursive,
+                } => {
+                    if let Err(e) =
+                        rt.block_on(cmd::fs::cp_filesystem(id_or_path, &src, &dest, recursive))
+                    {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                FsCommand::Rm {
+                    file_path,
+                    recursive,
+                } => {
+                    if let Err(e) =
+                        rt.block_on(cmd::fs::rm_filesystem(id_or_path, &file_path, recursive))
+                    {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
             }
         }
         Command::Completions { command } => handle_completions(command),
@@ -197,10 +484,15 @@ fn main() {
             id_or_path,
             bind,
             port,
+            nfs_bind,
+            nfs_allow,
+            nfs_ro,
         } => {
             eprintln!("Warning: `agentfs nfs` is deprecated, use `agentfs serve nfs` instead");
             let rt = get_runtime();
-            if let Err(e) = rt.block_on(cmd::nfs::handle_nfs_command(id_or_path, bind, port)) {
+            if let Err(e) = rt.block_on(cmd::nfs::handle_nfs_command(
+                id_or_path, bind, port, nfs_bind, nfs_allow, nfs_ro,
+            )) {
                 eprintln!("Error: {}", e);
                 std::process::exit(1);
             }
@@ -223,9 +515,14 @@ fn main() {
                 id_or_path,
                 bind,
                 port,
+                nfs_bind,
+                nfs_allow,
+                nfs_ro,
             } => {
                 let rt = get_runtime();
-                if let Err(e) = rt.block_on(cmd::nfs::handle_nfs_command(id_or_path, bind, port)) {
+                if let Err(e) = rt.block_on(cmd::nfs::handle_nfs_command(
+                    id_or_path, bind, port, nfs_bind, nfs_allow, nfs_ro,
+                )) {
                     eprintln!("Error: {}", e);
                     std::process::exit(1);
                 }
@@ -259,6 +556,24 @@ fn main() {
 
 /// Reset SIGPIPE to the default behavior (terminate the process) so that
 /// piping output to tools like `head` doesn't cause a panic.
+/// Log to stderr at `level`, or as set by `RUST_LOG` if no level is given.
+fn init_tracing(level: Option<LogLevel>, format: LogFormat) {
+    let filter = match level {
+        Some(level) => tracing_subscriber::EnvFilter::new(format!("agentfs={}", level.as_str())),
+        None => tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "agentfs=info".into()),
+    };
+    let layer = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    let layer = match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+    let _ = tracing_subscriber::registry()
+        .with(layer)
+        .with(filter)
+        .try_init();
+}
+
 #[cfg(unix)]
 fn reset_sigpipe() {
     unsafe {
@@ -281,3 +596,11 @@ fn default_shell() -> std::path::PathBuf {
         std::path::PathBuf::from("bash")
     }
 }
+
+/// Load the passphrase of encrypted filesystems, exiting if it can't be read.
+fn init_passphrase(file: Option<&std::path::Path>) {
+    if let Err(e) = passphrase::init(file) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
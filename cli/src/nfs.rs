@@ -28,6 +28,8 @@ pub struct AgentNFS {
     uid: u32,
     /// Group ID for all files
     gid: u32,
+    /// Whether the export rejects all changes
+    read_only: bool,
 }
 
 /// Bidirectional mapping between inodes and paths.
@@ -90,9 +92,18 @@ impl AgentNFS {
             inode_map: RwLock::new(InodeMap::new()),
             uid,
             gid,
+            read_only: false,
         }
     }
 
+    /// Export the filesystem read-only, whatever the underlying filesystem allows
+    ///
+    /// nfsserve answers requests that change the filesystem with `NFS3ERR_ROFS`.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
     /// Convert AgentFS Stats to NFS fattr3.
     fn stats_to_fattr(&self, stats: &Stats, ino: fileid3) -> fattr3 {
         let ftype = match stats.mode & S_IFMT {
@@ -154,7 +165,11 @@ impl NFSFileSystem for AgentNFS {
     }
 
     fn capabilities(&self) -> VFSCapabilities {
-        VFSCapabilities::ReadWrite
+        if self.read_only {
+            VFSCapabilities::ReadOnly
+        } else {
+            VFSCapabilities::ReadWrite
+        }
     }
 
     async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
@@ -0,0 +1,234 @@
+//! Source address access control for the NFS server.
+//!
+//! nfsserve accepts every connection it gets, so access control sits in front
+//! of it: connections from allowed addresses are forwarded to the server, and
+//! anything else gets its first RPC call rejected with `AUTH_ERROR` before the
+//! connection is closed.
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Last-fragment bit of an RPC record marking header (RFC 5531, section 11)
+const LAST_FRAGMENT: u32 = 0x8000_0000;
+
+/// Largest RPC call read from a denied client before replying
+const MAX_DENIED_CALL_SIZE: u32 = 64 * 1024;
+
+/// `msg_type` of a reply
+const REPLY: u32 = 1;
+/// `reply_stat` of a rejected call
+const MSG_DENIED: u32 = 1;
+/// `reject_stat` of a call rejected for its credentials
+const AUTH_ERROR: u32 = 1;
+/// `auth_stat` for credentials the server does not accept
+const AUTH_TOOWEAK: u32 = 5;
+
+/// An IPv4 or IPv6 network in CIDR notation, e.g. `10.0.0.0/8`
+///
+/// A bare address is a network of that single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Check whether `ip` is in this network
+    ///
+    /// IPv4-mapped IPv6 addresses, as reported for IPv4 clients of an IPv6
+    /// socket, match as their IPv4 address.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_matches(
+                net.to_bits().into(),
+                ip.to_bits().into(),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(net.to_bits(), ip.to_bits(), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Compare the leading `prefix_len` bits of two addresses of `bits` bits
+fn prefix_matches(net: u128, ip: u128, bits: u32, prefix_len: u8) -> bool {
+    let shift = bits - u32::from(prefix_len);
+    net.checked_shr(shift).unwrap_or(0) == ip.checked_shr(shift).unwrap_or(0)
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid network '{}'. Expected e.g. 10.0.0.0/8.", s);
+
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| invalid())?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(invalid());
+        }
+        Ok(Cidr { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Accept connections on `listener`, forwarding those from an `allowed` address
+/// to the NFS server at `backend` and denying the rest.
+pub async fn serve(
+    listener: TcpListener,
+    backend: SocketAddr,
+    allowed: Vec<Cidr>,
+) -> io::Result<()> {
+    let allowed = Arc::new(allowed);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let allowed = allowed.clone();
+        tokio::spawn(async move {
+            let result = if allowed.iter().any(|cidr| cidr.contains(peer.ip())) {
+                forward(stream, backend).await
+            } else {
+                tracing::info!("Denied NFS connection from {}", peer);
+                deny(stream).await
+            };
+            if let Err(e) = result {
+                tracing::debug!("NFS connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Relay a client connection to the NFS server until either side closes it
+async fn forward(mut client: TcpStream, backend: SocketAddr) -> io::Result<()> {
+    let mut server = TcpStream::connect(backend).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+    Ok(())
+}
+
+/// Reject the first RPC call of a client with `AUTH_ERROR`, then close the connection
+async fn deny(mut stream: TcpStream) -> io::Result<()> {
+    let header = stream.read_u32().await?;
+    let len = header & !LAST_FRAGMENT;
+    if !(4..=MAX_DENIED_CALL_SIZE).contains(&len) {
+        return Ok(());
+    }
+    let xid = stream.read_u32().await?;
+
+    // Read the rest of the call, so that closing the connection doesn't reset it
+    // before the client reads the reply
+    tokio::io::copy(
+        &mut (&mut stream).take(u64::from(len - 4)),
+        &mut tokio::io::sink(),
+    )
+    .await?;
+
+    stream.write_all(&denied_reply(xid)).await?;
+    stream.shutdown().await
+}
+
+/// Encode a record with the reply rejecting call `xid` for its credentials
+fn denied_reply(xid: u32) -> Vec<u8> {
+    let words = [xid, REPLY, MSG_DENIED, AUTH_ERROR, AUTH_TOOWEAK];
+    let mut record = (LAST_FRAGMENT | (words.len() * 4) as u32)
+        .to_be_bytes()
+        .to_vec();
+    for word in words {
+        record.extend_from_slice(&word.to_be_bytes());
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!net.contains("fe80::1".parse().unwrap()));
+
+        let host: Cidr = "192.168.1.5".parse().unwrap();
+        assert!(host.contains("192.168.1.5".parse().unwrap()));
+        assert!(!host.contains("192.168.1.6".parse().unwrap()));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("8.8.8.8".parse().unwrap()));
+        let v6: Cidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains("fd12::1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("host/8".parse::<Cidr>().is_err());
+    }
+
+    /// Start `serve` in front of a backend that answers every connection with "ok"
+    async fn start(allowed: &str) -> SocketAddr {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = backend.accept().await.unwrap();
+                stream.write_all(b"ok").await.unwrap();
+            }
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let allowed = vec![allowed.parse().unwrap()];
+        tokio::spawn(serve(listener, backend_addr, allowed));
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_allowed_connection_is_forwarded() {
+        let addr = start("127.0.0.0/8").await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ok");
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_connection_is_refused() {
+        let addr = start("10.0.0.0/8").await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // A NULL call to the MOUNT program, with AUTH_NONE credentials
+        let call = [0x1234, 0, 2, 100005, 3, 0, 0, 0, 0, 0];
+        let mut record = (LAST_FRAGMENT | (call.len() * 4) as u32)
+            .to_be_bytes()
+            .to_vec();
+        for word in call {
+            record.extend_from_slice(&u32::to_be_bytes(word));
+        }
+        stream.write_all(&record).await.unwrap();
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, denied_reply(0x1234));
+    }
+}
@@ -1,20 +1,69 @@
 use crate::cmd::completions::Shell;
 use agentfs_sdk::agentfs_dir;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use clap_complete::{
     engine::ValueCompleter, ArgValueCompleter, CompletionCandidate, PathCompleter,
 };
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "agentfs")]
 #[command(version = env!("AGENTFS_VERSION"))]
 #[command(about = "The filesystem for agents", long_about = None)]
 pub struct Args {
+    /// Log verbosity (default: info, or as set by the RUST_LOG environment variable)
+    #[arg(long, global = true, value_enum, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+
+    /// Log output format
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// Verbosity of the logs written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// The level as a `tracing` filter directive
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+/// Format of the logs written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Hash function for checksums of file content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChecksumAlgorithm {
+    Blake3,
+}
+
 #[derive(Debug, Parser)]
 pub struct SyncCommandOptions {
     #[arg(long)]
@@ -38,17 +87,76 @@ pub enum Command {
     },
     /// Initialize a new agent filesystem
     Init {
-        /// Agent identifier (if not provided, generates a unique one)
+        /// Agent identifier (if not provided, generates a unique one), or with
+        /// --repair, the agent ID or database path of an existing filesystem
         id: Option<String>,
 
         /// Overwrite existing file if it exists
         #[arg(long)]
         force: bool,
 
+        /// Check an existing filesystem for inconsistencies, such as orphaned
+        /// inodes and wrong link counts or refcounts, and report them
+        #[arg(
+            long,
+            requires = "id",
+            conflicts_with_all = ["force", "base", "from"]
+        )]
+        repair: bool,
+
+        /// Fix the problems found by --repair instead of only reporting them
+        #[arg(long, requires = "repair")]
+        apply: bool,
+
         /// Base directory for overlay filesystem (copy-on-write)
         #[arg(long)]
         base: Option<PathBuf>,
 
+        /// Copy this host directory into the new filesystem, leaving out paths
+        /// matched by its .agentfsignore file
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with = "base",
+            add = ArgValueCompleter::new(PathCompleter::dir())
+        )]
+        from: Option<PathBuf>,
+
+        /// Store identical file content only once
+        #[arg(long)]
+        dedup: bool,
+
+        /// Compress file content: none or zstd
+        #[arg(
+            long,
+            value_name = "ALGORITHM",
+            default_value = "none",
+            value_parser = ["none", "zstd"]
+        )]
+        compress: String,
+
+        /// Compression level, from 1 (fastest) to 22 (smallest)
+        #[arg(
+            long,
+            value_name = "LEVEL",
+            default_value_t = 3,
+            value_parser = clap::value_parser!(i32).range(1..=22)
+        )]
+        compression_level: i32,
+
+        /// Encrypt file content with the passphrase in this file. The
+        /// AGENTFS_PASSPHRASE environment variable can be used instead.
+        #[arg(long = "passphrase-file", value_name = "PATH")]
+        passphrase_file: Option<PathBuf>,
+
+        /// Maximum total size of file content (e.g. 512M, 1G)
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        quota: Option<u64>,
+
+        /// Maximum number of files, directories and symlinks
+        #[arg(long, value_name = "COUNT")]
+        quota_inodes: Option<u64>,
+
         #[command(flatten)]
         sync: SyncCommandOptions,
     },
@@ -70,6 +178,24 @@ pub enum Command {
         #[command(subcommand)]
         command: FsCommand,
     },
+    /// Manage point-in-time snapshots of a filesystem
+    Snapshot {
+        /// Agent ID or database path
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
+        id_or_path: String,
+
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Check a filesystem for corruption without changing it.
+    ///
+    /// Runs the checks of `init --repair` and exits with an error if any
+    /// of them finds a problem.
+    Verify {
+        /// Agent ID or database path
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
+        id_or_path: String,
+    },
     /// Run a command in the sandboxed environment.
     ///
     /// By default, uses FUSE+overlay with Linux user and mount namespaces for isolation.
@@ -93,12 +219,179 @@ pub enum Command {
         #[arg(long = "strace")]
         strace: bool,
 
+        /// Write strace output to a file instead of stderr. Implies --strace.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "strace-output", value_name = "PATH")]
+        strace_output: Option<PathBuf>,
+
+        /// Format of strace output: text, or json for one object per line
+        /// Only used with --experimental-sandbox
+        #[arg(
+            long = "strace-format",
+            value_name = "FORMAT",
+            default_value = "text",
+            value_parser = ["text", "json"]
+        )]
+        strace_format: String,
+
+        /// Fail if the command's processes exit with virtual file descriptors open,
+        /// including ones left for the exit to close
+        /// Only used with --experimental-sandbox
+        #[arg(long = "strict-fds")]
+        strict_fds: bool,
+
+        /// Maximum number of virtual files a process can have open at once.
+        /// Opening more fails with EMFILE.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "max-open-files", value_name = "N", default_value_t = 1024)]
+        max_open_files: usize,
+
         /// Session identifier for sharing delta layer across multiple runs.
         /// If not provided, a unique session ID is generated for each run.
         /// Use the same session ID to share the delta layer between runs.
         #[arg(long = "session", value_name = "ID")]
         session: Option<String>,
 
+        /// Mount a filesystem into the sandbox (can be specified multiple times).
+        /// Format: type=bind|sqlite,src=PATH,dst=PATH[,readonly]
+        /// Only used with --experimental-sandbox
+        #[arg(long = "mount", value_name = "SPEC")]
+        mounts: Vec<String>,
+
+        /// Make a host directory readable at a sandbox path, rejecting writes
+        /// with EROFS (can be specified multiple times).
+        /// Only used with --experimental-sandbox
+        #[arg(long = "mount-ro-host", value_name = "GUEST:HOST", value_parser = parse_ro_host_mount)]
+        ro_host_mounts: Vec<(PathBuf, PathBuf)>,
+
+        /// Mount an overlay of two AgentFS databases at /agent instead of agent.db.
+        /// LOWER is the read-only base and UPPER receives all changes.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "overlay", value_name = "LOWER:UPPER")]
+        overlay: Option<String>,
+
+        /// Pass paths matching a glob pattern through to the host, even under
+        /// a mount (can be specified multiple times). Exclusions win over mounts.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "exclude", value_name = "GLOB")]
+        excludes: Vec<String>,
+
+        /// Report a host user ID as another one inside virtual mounts
+        /// (can be specified multiple times)
+        /// Only used with --experimental-sandbox
+        #[arg(long = "uid-map", value_name = "INSIDE:OUTSIDE", value_parser = parse_id_map)]
+        uid_map: Vec<(u32, u32)>,
+
+        /// Report a host group ID as another one inside virtual mounts
+        /// (can be specified multiple times)
+        /// Only used with --experimental-sandbox
+        #[arg(long = "gid-map", value_name = "INSIDE:OUTSIDE", value_parser = parse_id_map)]
+        gid_map: Vec<(u32, u32)>,
+
+        /// Mode of files created in sqlite, overlay and vfs mounts that don't set
+        /// their own, in octal (e.g. 0644). The umask still applies on top.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "default-mode", value_name = "MODE", value_parser = parse_mode)]
+        default_mode: Option<u32>,
+
+        /// Mode of directories created in sqlite, overlay and vfs mounts that
+        /// don't set their own, in octal (e.g. 0755). The umask still applies on top.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "default-dir-mode", value_name = "MODE", value_parser = parse_mode)]
+        default_dir_mode: Option<u32>,
+
+        /// Owner reported for files in sqlite and overlay mounts that don't set
+        /// their own (defaults to the current user and group)
+        /// Only used with --experimental-sandbox
+        #[arg(long = "default-owner", value_name = "UID:GID", value_parser = parse_owner)]
+        default_owner: Option<(u32, u32)>,
+
+        /// Fail a syscall with --deny-errno instead of running it
+        /// (can be specified multiple times)
+        /// Only used with --experimental-sandbox
+        #[arg(long = "deny-syscall", value_name = "NAME")]
+        deny_syscalls: Vec<String>,
+
+        /// Fail every syscall except the listed ones with --deny-errno.
+        /// --deny-syscall takes precedence.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "allow-only", value_name = "NAME,...", value_delimiter = ',')]
+        allow_only: Vec<String>,
+
+        /// Error returned for denied syscalls, by name or number
+        /// Only used with --experimental-sandbox
+        #[arg(long = "deny-errno", value_name = "ERRNO", default_value = "EPERM")]
+        deny_errno: String,
+
+        /// Keep the command off the network: only AF_UNIX sockets can be used.
+        /// This filters syscalls, it doesn't set up a network namespace.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "no-network")]
+        no_network: bool,
+
+        /// Log the paths the command accesses without letting it modify any:
+        /// modifying calls succeed without running and files opened for writing
+        /// are replaced by /dev/null. A summary of the paths is printed at exit.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        /// Write a JSON summary of the files the command created, modified and
+        /// deleted in virtual mounts, with their sizes and hashes, once it exits.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "summary-json", value_name = "PATH")]
+        summary_json: Option<PathBuf>,
+
+        /// Start the command with an empty environment (before --set-env)
+        #[arg(long = "clear-env")]
+        clear_env: bool,
+
+        /// Set an environment variable for the command (can be specified
+        /// multiple times). Applied after --clear-env.
+        #[arg(long = "set-env", value_name = "VAR=VAL")]
+        set_env: Vec<String>,
+
+        /// Remove an environment variable from the command's environment
+        /// (can be specified multiple times). Applied after --set-env.
+        #[arg(long = "unset-env", value_name = "VAR")]
+        unset_env: Vec<String>,
+
+        /// Start the command in this directory instead of the current one.
+        /// Directories under a --mount are resolved through it.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "workdir", value_name = "PATH")]
+        workdir: Option<PathBuf>,
+
+        /// Terminate the command after this much wall-clock time, e.g. 30s, 5m or 1h.
+        /// It gets SIGTERM, then SIGKILL if it is still running 5 seconds later,
+        /// and agentfs exits with status 124.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "timeout", value_name = "DURATION", value_parser = parse_duration)]
+        timeout: Option<Duration>,
+
+        /// Limit the CPU time of each process of the command, e.g. 30s or 5m
+        /// Only used with --experimental-sandbox
+        #[arg(long = "cpu-limit", value_name = "DURATION", value_parser = parse_duration)]
+        cpu_limit: Option<Duration>,
+
+        /// Limit the memory of the command, e.g. 512M or 2G. As root, this caps
+        /// all its processes together with a cgroup, otherwise the address space
+        /// of each process with RLIMIT_AS.
+        /// Only used with --experimental-sandbox
+        #[arg(long = "memory-limit", value_name = "SIZE", value_parser = parse_size)]
+        memory_limit: Option<u64>,
+
+        /// Size of the cache of file data read from the delta layer, or with
+        /// --experimental-sandbox from each AgentFS mount, e.g. 64M, or 0 to
+        /// disable it (default: 16M)
+        #[arg(long = "vfs-cache-size", value_name = "SIZE", value_parser = parse_cache_size)]
+        vfs_cache_size: Option<u64>,
+
+        /// Read the passphrase of encrypted filesystems from this file
+        /// (default: the AGENTFS_PASSPHRASE environment variable)
+        #[arg(long = "passphrase-file", value_name = "PATH")]
+        passphrase_file: Option<PathBuf>,
+
         /// Command to execute (defaults to bash on Linux, zsh on macOS)
         command: Option<PathBuf>,
 
@@ -135,12 +428,90 @@ pub enum Command {
         /// Group ID to report for all files (defaults to current group)
         #[arg(long)]
         gid: Option<u32>,
+
+        /// Report the host user ID OUTSIDE as INSIDE (can be specified multiple times)
+        #[arg(long = "uid-map", value_name = "INSIDE:OUTSIDE", value_parser = parse_id_map)]
+        uid_map: Vec<(u32, u32)>,
+
+        /// Report the host group ID OUTSIDE as INSIDE (can be specified multiple times)
+        #[arg(long = "gid-map", value_name = "INSIDE:OUTSIDE", value_parser = parse_id_map)]
+        gid_map: Vec<(u32, u32)>,
+
+        /// Mount the filesystem read-only
+        #[arg(long)]
+        read_only: bool,
+
+        /// Bypass the kernel page cache for file reads and writes
+        #[arg(long = "fuse-direct-io")]
+        direct_io: bool,
+
+        /// How long the kernel may cache file attributes (default: forever)
+        #[arg(long = "attr-timeout", value_name = "DURATION", value_parser = parse_duration)]
+        attr_timeout: Option<Duration>,
+
+        /// How long the kernel may cache name lookups (default: forever)
+        #[arg(long = "entry-timeout", value_name = "DURATION", value_parser = parse_duration)]
+        entry_timeout: Option<Duration>,
+
+        /// Push changes made outside the mount (e.g. by `fs put`) to the kernel and inotify
+        #[arg(long)]
+        notify: bool,
+
+        /// Size of the cache of file data read from the database, e.g. 64M,
+        /// or 0 to disable it (default: 16M)
+        #[arg(long = "vfs-cache-size", value_name = "SIZE", value_parser = parse_cache_size)]
+        vfs_cache_size: Option<u64>,
+
+        /// Read the passphrase of encrypted filesystems from this file
+        /// (default: the AGENTFS_PASSPHRASE environment variable)
+        #[arg(long = "passphrase-file", value_name = "PATH")]
+        passphrase_file: Option<PathBuf>,
+
+        /// Listen for JSON-RPC control requests (stats, snapshots, ...) on
+        /// this Unix socket
+        #[arg(long = "control-socket", value_name = "PATH")]
+        control_socket: Option<PathBuf>,
     },
-    /// Show differences between base filesystem and delta (overlay mode only)
-    Diff {
+    /// Export a filesystem to a tar archive
+    Export {
         /// Agent ID or database path
-        #[arg(value_name = "ID_OR_PATH", add = ArgValueCompleter::new(id_or_path_completer))]
+        #[arg(add = ArgValueCompleter::new(id_or_path_completer))]
         id_or_path: String,
+
+        /// Archive to write, compressed with gzip if it ends in .tar.gz or .tgz
+        #[arg(value_name = "OUTPUT_TAR")]
+        output_tar: PathBuf,
+
+        /// Print a checksum of the archive to stderr once it is written
+        #[arg(long, value_enum, value_name = "ALGORITHM")]
+        checksum: Option<ChecksumAlgorithm>,
+    },
+    /// Create a filesystem from a tar archive
+    Import {
+        /// Archive to read, optionally compressed with gzip
+        #[arg(value_name = "INPUT_TAR")]
+        input_tar: PathBuf,
+
+        /// Agent identifier of the new filesystem
+        id: String,
+    },
+    /// Show differences between two filesystems or snapshots.
+    ///
+    /// With one filesystem, shows differences between the base filesystem and
+    /// the delta (overlay mode only). A snapshot is referred to as
+    /// ID_OR_PATH@SNAPSHOT.
+    Diff {
+        /// Agent ID or database path, optionally followed by @SNAPSHOT
+        #[arg(value_name = "LEFT", add = ArgValueCompleter::new(id_or_path_completer))]
+        left: String,
+
+        /// Agent ID or database path to compare LEFT with, optionally followed by @SNAPSHOT
+        #[arg(value_name = "RIGHT", add = ArgValueCompleter::new(id_or_path_completer))]
+        right: Option<String>,
+
+        /// Output format
+        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
     },
     /// Display agent action timeline from tool call audit log
     Timeline {
@@ -176,9 +547,24 @@ pub enum Command {
         #[arg(long, default_value = "127.0.0.1")]
         bind: String,
 
-        /// Port to listen on
+        /// Port to listen on (0 picks a free port)
         #[arg(long, default_value = "11111")]
         port: u32,
+
+        /// Address and port to listen on, overriding --bind and --port,
+        /// e.g. 192.168.122.1:2049. Port 0 picks a free port.
+        #[arg(long = "nfs-bind", value_name = "ADDR:PORT", conflicts_with_all = ["bind", "port"])]
+        nfs_bind: Option<std::net::SocketAddr>,
+
+        /// Only accept connections from this network, e.g. 10.0.0.0/8 or
+        /// 192.168.1.5 (can be specified multiple times). Other clients get
+        /// an authentication error. Accepts every client if not given.
+        #[arg(long = "nfs-allow", value_name = "CIDR")]
+        nfs_allow: Vec<crate::nfs_access::Cidr>,
+
+        /// Export the filesystem read-only
+        #[arg(long = "nfs-ro")]
+        nfs_ro: bool,
     },
 
     /// Start an MCP server exposing filesystem and KV-store tools
@@ -216,11 +602,66 @@ pub enum FsCommand {
         /// Path to list (default: /)
         #[arg(default_value = "/")]
         fs_path: String,
+        /// Print the inode number of each entry
+        #[arg(short = 'i', long = "inode")]
+        inode: bool,
+    },
+    /// Print the directory tree with file sizes
+    Tree {
+        /// Path to the directory (default: /)
+        #[arg(default_value = "/")]
+        fs_path: String,
+
+        /// Descend at most this many levels below the directory
+        #[arg(long = "max-depth", value_name = "N")]
+        max_depth: Option<usize>,
+    },
+    /// Show file metadata
+    Stat {
+        /// Path to the file in the filesystem
+        file_path: String,
+
+        /// Show the metadata of the file a symlink points to
+        #[arg(short = 'L', long = "follow")]
+        follow: bool,
+    },
+    /// Find files by name and type
+    Find {
+        /// Directory to search (default: /)
+        #[arg(default_value = "/")]
+        root: String,
+
+        /// Only print paths whose file name matches a glob pattern, e.g. '*.log'
+        #[arg(long = "name", value_name = "GLOB")]
+        name_glob: Option<String>,
+
+        /// Only print files (f), directories (d) or symlinks (l)
+        #[arg(long = "type", value_name = "TYPE", value_parser = ["f", "d", "l"])]
+        type_filter: Option<String>,
+    },
+    /// Show disk usage of a directory tree
+    Du {
+        /// Path to the file or directory (default: /)
+        #[arg(default_value = "/")]
+        path: String,
+
+        /// Only print the total for the path
+        #[arg(short = 's', long = "summarize")]
+        summarize: bool,
+
+        /// Report the bytes stored in the database, after compression, instead
+        /// of the allocated chunks
+        #[arg(long)]
+        stored: bool,
     },
     /// Display file contents
     Cat {
         /// Path to the file in the filesystem
         file_path: String,
+
+        /// Print a checksum of the content to stderr once it is written
+        #[arg(long, value_enum, value_name = "ALGORITHM")]
+        checksum: Option<ChecksumAlgorithm>,
     },
     /// Write file content
     Write {
@@ -230,6 +671,48 @@ pub enum FsCommand {
         /// Content of the file
         content: String,
     },
+    /// Copy a host file into the filesystem
+    Put {
+        /// Path to the file on the host, or - to read from stdin
+        host_path: String,
+
+        /// Destination path in the filesystem
+        dest_path: String,
+
+        /// Fail instead of overwriting an existing file
+        #[arg(short = 'n', long = "no-clobber")]
+        no_clobber: bool,
+    },
+    /// Create a directory
+    Mkdir {
+        /// Path to the directory in the filesystem
+        dir_path: String,
+
+        /// Create parent directories as needed, and succeed if the directory exists
+        #[arg(short = 'p', long = "parents")]
+        parents: bool,
+    },
+    /// Copy a file or directory within the filesystem
+    Cp {
+        /// Path to the source in the filesystem
+        src: String,
+
+        /// Destination path, or an existing directory to copy into
+        dest: String,
+
+        /// Copy directories and their contents recursively
+        #[arg(short = 'r', long = "recursive")]
+        recursive: bool,
+    },
+    /// Remove a file or directory
+    Rm {
+        /// Path to the file or directory in the filesystem
+        file_path: String,
+
+        /// Remove directories and their contents recursively
+        #[arg(short = 'r', long = "recursive")]
+        recursive: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -244,6 +727,29 @@ pub enum SyncCommand {
     Checkpoint,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum SnapshotCommand {
+    /// Record the current state of the filesystem under a name
+    Create {
+        /// Snapshot name
+        snapshot_name: String,
+    },
+    /// List snapshots, oldest first
+    List,
+    /// Roll the filesystem back to a snapshot
+    ///
+    /// The filesystem must not be mounted or in use by `agentfs run`.
+    Restore {
+        /// Snapshot name
+        snapshot_name: String,
+    },
+    /// Delete a snapshot
+    Delete {
+        /// Snapshot name
+        snapshot_name: String,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ServeCommand {
     /// Start an NFS server to export an AgentFS filesystem over the network
@@ -257,9 +763,24 @@ pub enum ServeCommand {
         #[arg(long, default_value = "127.0.0.1")]
         bind: String,
 
-        /// Port to listen on
+        /// Port to listen on (0 picks a free port)
         #[arg(long, default_value = "11111")]
         port: u32,
+
+        /// Address and port to listen on, overriding --bind and --port,
+        /// e.g. 192.168.122.1:2049. Port 0 picks a free port.
+        #[arg(long = "nfs-bind", value_name = "ADDR:PORT", conflicts_with_all = ["bind", "port"])]
+        nfs_bind: Option<std::net::SocketAddr>,
+
+        /// Only accept connections from this network, e.g. 10.0.0.0/8 or
+        /// 192.168.1.5 (can be specified multiple times). Other clients get
+        /// an authentication error. Accepts every client if not given.
+        #[arg(long = "nfs-allow", value_name = "CIDR")]
+        nfs_allow: Vec<crate::nfs_access::Cidr>,
+
+        /// Export the filesystem read-only
+        #[arg(long = "nfs-ro")]
+        nfs_ro: bool,
     },
 
     /// Start an MCP server exposing filesystem and KV-store tools
@@ -304,6 +825,98 @@ pub enum CompletionsCommand {
     Show,
 }
 
+/// Parse a duration like `500ms`, `30s`, `5m` or `1h`; plain numbers are seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "Invalid duration '{}'. Expected e.g. 500ms, 30s, 5m or 1h.",
+            s
+        )
+    };
+
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.');
+    let (value, unit) = s.split_at(split.unwrap_or(s.len()));
+    let value: f64 = value.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| invalid())
+}
+
+/// Parse a size in bytes like `4096`, `512K`, `512M` or `2G`, in powers of 1024.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size '{}'. Expected e.g. 512M or 2G.", s);
+
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.');
+    let (value, unit) = s.split_at(split.unwrap_or(s.len()));
+    let value: f64 = value.parse().map_err(|_| invalid())?;
+    let unit = unit.trim_end_matches(['B', 'b']).trim_end_matches('i');
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    let bytes = value * multiplier as f64;
+    if bytes < 1.0 || bytes >= u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+/// Parse a cache size like `parse_size()`, where `0` disables the cache.
+fn parse_cache_size(s: &str) -> Result<u64, String> {
+    match s {
+        "0" => Ok(0),
+        _ => parse_size(s),
+    }
+}
+
+/// Parse an `INSIDE:OUTSIDE` pair of user or group IDs
+fn parse_id_map(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid ID mapping '{}'. Expected e.g. 0:1000.", s);
+
+    let (inside, outside) = s.split_once(':').ok_or_else(invalid)?;
+    let inside = inside.parse().map_err(|_| invalid())?;
+    let outside = outside.parse().map_err(|_| invalid())?;
+    Ok((inside, outside))
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid mode '{}'. Expected an octal mode, e.g. 0644.", s);
+
+    let mode = u32::from_str_radix(s, 8).map_err(|_| invalid())?;
+    if mode > 0o7777 {
+        return Err(invalid());
+    }
+    Ok(mode)
+}
+
+fn parse_owner(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid owner '{}'. Expected e.g. 1000:1000.", s);
+
+    let (uid, gid) = s.split_once(':').ok_or_else(invalid)?;
+    let uid = uid.parse().map_err(|_| invalid())?;
+    let gid = gid.parse().map_err(|_| invalid())?;
+    Ok((uid, gid))
+}
+
+fn parse_ro_host_mount(s: &str) -> Result<(PathBuf, PathBuf), String> {
+    let invalid = || format!("Invalid mount '{}'. Expected e.g. /toolchain:/opt/rust.", s);
+
+    let (guest, host) = s.split_once(':').ok_or_else(invalid)?;
+    if !guest.starts_with('/') || host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((PathBuf::from(guest), PathBuf::from(host)))
+}
+
 fn id_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
     let mut completions = vec![];
     let Some(current) = current.to_str() else {
@@ -351,3 +964,81 @@ fn id_or_path_completer(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
 
     completions
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1s"), Ok(Duration::from_secs(1)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512K"), Ok(512 << 10));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_size("64mb"), Ok(64 << 20));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("12Q").is_err());
+        assert!(parse_size("G").is_err());
+    }
+
+    #[test]
+    fn test_parse_cache_size() {
+        assert_eq!(parse_cache_size("0"), Ok(0));
+        assert_eq!(parse_cache_size("64M"), Ok(64 << 20));
+        assert!(parse_cache_size("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_id_map() {
+        assert_eq!(parse_id_map("0:1000"), Ok((0, 1000)));
+        assert_eq!(parse_id_map("1000:0"), Ok((1000, 0)));
+        assert!(parse_id_map("1000").is_err());
+        assert!(parse_id_map("a:1000").is_err());
+        assert!(parse_id_map("0:-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("0644"), Ok(0o644));
+        assert_eq!(parse_mode("755"), Ok(0o755));
+        assert_eq!(parse_mode("1777"), Ok(0o1777));
+        assert!(parse_mode("0888").is_err());
+        assert!(parse_mode("10000").is_err());
+        assert!(parse_mode("").is_err());
+    }
+
+    #[test]
+    fn test_parse_owner() {
+        assert_eq!(parse_owner("1000:100"), Ok((1000, 100)));
+        assert!(parse_owner("1000").is_err());
+        assert!(parse_owner("root:root").is_err());
+    }
+
+    #[test]
+    fn test_parse_ro_host_mount() {
+        assert_eq!(
+            parse_ro_host_mount("/toolchain:/opt/rust"),
+            Ok((PathBuf::from("/toolchain"), PathBuf::from("/opt/rust")))
+        );
+        assert_eq!(
+            parse_ro_host_mount("/toolchain:rust"),
+            Ok((PathBuf::from("/toolchain"), PathBuf::from("rust")))
+        );
+        assert!(parse_ro_host_mount("/toolchain").is_err());
+        assert!(parse_ro_host_mount("toolchain:/opt/rust").is_err());
+        assert!(parse_ro_host_mount("/toolchain:").is_err());
+    }
+}
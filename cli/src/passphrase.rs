@@ -0,0 +1,74 @@
+//! Passphrase of encrypted filesystems.
+//!
+//! The passphrase is read from the file given with `--passphrase-file`, or
+//! from the `AGENTFS_PASSPHRASE` environment variable. The variable is removed
+//! from the environment once read, so that sandboxed commands don't inherit it.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Environment variable holding the passphrase
+pub const PASSPHRASE_ENV: &str = "AGENTFS_PASSPHRASE";
+
+static PASSPHRASE: OnceLock<Option<String>> = OnceLock::new();
+
+/// Load the passphrase from `file`, or from the environment without one
+///
+/// This must be called before any thread that reads the environment is
+/// started, and before spawning commands.
+pub fn init(file: Option<&Path>) -> Result<()> {
+    let passphrase = match file {
+        Some(file) => Some(read_passphrase_file(file)?),
+        None => from_env(),
+    };
+    // Drop the variable even when a file takes precedence over it
+    from_env();
+    let _ = PASSPHRASE.set(passphrase);
+    Ok(())
+}
+
+/// Get the passphrase, if any
+///
+/// Without a prior `init()`, it is loaded from the environment.
+pub fn get() -> Option<&'static str> {
+    PASSPHRASE.get_or_init(from_env).as_deref()
+}
+
+/// Take the passphrase out of the environment
+fn from_env() -> Option<String> {
+    let passphrase = std::env::var(PASSPHRASE_ENV).ok();
+    std::env::remove_var(PASSPHRASE_ENV);
+    passphrase.filter(|passphrase| !passphrase.is_empty())
+}
+
+/// Read a passphrase file, without its trailing newline
+fn read_passphrase_file(file: &Path) -> Result<String> {
+    let contents = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read passphrase file {}", file.display()))?;
+    let passphrase = trim_newline(&contents);
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase file {} is empty", file.display());
+    }
+    Ok(passphrase.to_string())
+}
+
+fn trim_newline(s: &str) -> &str {
+    s.strip_suffix('\n')
+        .map(|s| s.strip_suffix('\r').unwrap_or(s))
+        .unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_newline() {
+        assert_eq!(trim_newline("secret\n"), "secret");
+        assert_eq!(trim_newline("secret\r\n"), "secret");
+        assert_eq!(trim_newline("secret"), "secret");
+        // Only the final line break is dropped
+        assert_eq!(trim_newline("secret \n\n"), "secret \n");
+    }
+}
@@ -42,6 +42,7 @@ static CHILD_PID: AtomicI32 = AtomicI32::new(0);
 static TERM_SIGNAL_COUNT: AtomicI32 = AtomicI32::new(0);
 
 use crate::fuse::FuseMountOptions;
+use crate::passphrase;
 
 /// Exit code returned when exec fails (standard shell convention for "command not found")
 const EXIT_COMMAND_NOT_FOUND: i32 = 127;
@@ -145,6 +146,7 @@ pub async fn run_cmd(
     allow: Vec<PathBuf>,
     no_default_allows: bool,
     session_id: Option<String>,
+    vfs_cache_size: Option<u64>,
     command: PathBuf,
     args: Vec<String>,
 ) -> Result<()> {
@@ -183,7 +185,14 @@ pub async fn run_cmd(
         .db_path
         .to_str()
         .context("Database path contains non-UTF8 characters")?;
-    let agentfs = AgentFS::open(AgentFSOptions::with_path(db_path_str))
+    let mut options = AgentFSOptions::with_path(db_path_str);
+    if let Some(passphrase) = passphrase::get() {
+        options = options.with_encryption(passphrase);
+    }
+    if let Some(size) = vfs_cache_size {
+        options = options.with_block_cache_size(size);
+    }
+    let agentfs = AgentFS::open(options)
         .await
         .context("Failed to create delta AgentFS")?;
 
@@ -221,6 +230,13 @@ pub async fn run_cmd(
         fsname: format!("agentfs:{}", session.run_id),
         uid: Some(uid),
         gid: Some(gid),
+        read_only: false,
+        direct_io: false,
+        attr_timeout: None,
+        entry_timeout: None,
+        notify: false,
+        state: Default::default(),
+        unmount_on_signal: false,
     };
 
     // Start FUSE in a separate thread
@@ -297,7 +313,7 @@ pub async fn run_cmd(
         if let Err(e) =
             crate::cmd::ps::write_proc_file(&session.run_id, true, &command.to_string_lossy(), &cwd)
         {
-            eprintln!("Warning: Failed to write proc file: {}", e);
+            tracing::warn!("Failed to write proc file: {}", e);
         }
 
         // Keep cwd_fd alive - it's needed by HostFS in the FUSE thread
@@ -382,7 +398,7 @@ fn run_in_existing_session(
         if let Err(e) =
             crate::cmd::ps::write_proc_file(session_id, false, &command.to_string_lossy(), cwd)
         {
-            eprintln!("Warning: Failed to write proc file: {}", e);
+            tracing::warn!("Failed to write proc file: {}", e);
         }
 
         // Store child PID and install signal handlers before waiting
@@ -4,47 +4,360 @@
 //! virtualization. This is experimental and requires root or CAP_SYS_PTRACE.
 
 use agentfs_sandbox::{
-    init_fd_tables, init_mount_table, init_strace, MountTable, Sandbox, SqliteVfs,
+    accessed_paths, check_mount_points, format_accessed_paths, format_open_fds, BindVfs,
+    ExitStatus, IdMap, MountConfig, MountDefaults, MountTable, MountType, OverlayVfs,
+    SandboxBuilder, SandboxExit, SqliteVfs, StraceFormat, SyscallFilter, Vfs, VfsError,
+    VfsRegistry,
 };
-use reverie_process::Command;
-use reverie_ptrace::TracerBuilder;
-use std::{path::PathBuf, sync::Arc};
+use agentfs_sdk::AgentFSOptions;
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Exit status when the command is terminated by `--timeout`, like timeout(1)
+const EXIT_TIMEOUT: i32 = 124;
 
 /// Run a command using the experimental ptrace-based syscall interception sandbox.
-pub async fn run_cmd(strace: bool, command: PathBuf, args: Vec<String>) {
+///
+/// `mounts` are `--mount` specifications added on top of the default `agent.db` mount
+/// at `/agent`, which is skipped if one of them targets `/agent` itself. Each pair of
+/// `ro_host_mounts` makes a host directory readable at a sandbox path. An `--overlay`
+/// of `LOWER:UPPER` databases takes the place of the default mount. Paths matching
+/// one of the `excludes` glob patterns pass through to the host, even under a mount.
+/// AgentFS mounts report the host IDs in `uid_map` and `gid_map` as the paired inside
+/// IDs. Mounts that don't set their own create files with `default_mode` and
+/// directories with `default_dir_mode`, and AgentFS mounts report `default_owner`.
+///
+/// Syscalls in `deny_syscalls`, or missing from a non-empty `allow_only` list, fail
+/// with `deny_errno` without running. With `no_network`, only `AF_UNIX` sockets work.
+/// With `dry_run`, the paths the command accesses are logged and summarized at exit,
+/// and it can't modify any of them. With `summary_json`, the files it changed in
+/// virtual mounts are written there as JSON once it exits, whatever its status.
+/// The command starts in `workdir` if given, resolved through the mount table.
+/// It is terminated after `timeout`, and each of its processes can use up to
+/// `cpu_limit` of CPU time. Its memory is capped at `memory_limit` bytes.
+/// AgentFS mounts cache up to `vfs_cache_size` bytes of file data each, if given.
+///
+/// Virtual file descriptors still open when the command exits are logged at debug
+/// level, or reported as an error with `strict_fds`. Each process can have at most
+/// `max_open_files` virtual files open at once.
+///
+/// Traced syscalls go to stderr with `strace`, or to `strace_output` in the given
+/// `strace_format`, which is flushed once the command exits.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_cmd(
+    strace: bool,
+    strace_output: Option<PathBuf>,
+    strace_format: String,
+    strict_fds: bool,
+    max_open_files: usize,
+    mounts: Vec<String>,
+    ro_host_mounts: Vec<(PathBuf, PathBuf)>,
+    overlay: Option<String>,
+    excludes: Vec<String>,
+    uid_map: Vec<(u32, u32)>,
+    gid_map: Vec<(u32, u32)>,
+    default_mode: Option<u32>,
+    default_dir_mode: Option<u32>,
+    default_owner: Option<(u32, u32)>,
+    deny_syscalls: Vec<String>,
+    allow_only: Vec<String>,
+    deny_errno: String,
+    no_network: bool,
+    dry_run: bool,
+    summary_json: Option<PathBuf>,
+    workdir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    cpu_limit: Option<Duration>,
+    memory_limit: Option<u64>,
+    vfs_cache_size: Option<u64>,
+    command: PathBuf,
+    args: Vec<String>,
+) -> Result<()> {
+    let mut filter = SyscallFilter::new();
+    for name in &deny_syscalls {
+        filter.deny(name).map_err(|e| anyhow!(e))?;
+    }
+    for name in &allow_only {
+        filter.allow(name).map_err(|e| anyhow!(e))?;
+    }
+    filter.set_errno(&deny_errno).map_err(|e| anyhow!(e))?;
+
+    let mut configs = mounts
+        .iter()
+        .map(|spec| spec.parse::<MountConfig>().map_err(|e| anyhow!(e)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Default mount: agent.db (or the overlay) at /agent
+    let mount_point = PathBuf::from("/agent");
+    if !configs.iter().any(|config| config.dst == mount_point) {
+        let mount_type = match overlay {
+            Some(overlay) => {
+                let (lower, upper) = overlay.split_once(':').ok_or_else(|| {
+                    anyhow!(
+                        "Invalid overlay '{}'. Expected format: LOWER:UPPER.",
+                        overlay
+                    )
+                })?;
+                MountType::Overlay {
+                    lower: PathBuf::from(lower),
+                    upper: PathBuf::from(upper),
+                }
+            }
+            None => MountType::Sqlite {
+                src: PathBuf::from("agent.db"),
+            },
+        };
+        configs.insert(
+            0,
+            MountConfig {
+                mount_type,
+                dst: mount_point,
+                read_only: false,
+                defaults: MountDefaults::default(),
+            },
+        );
+    } else if overlay.is_some() {
+        tracing::warn!("--overlay is ignored because a --mount targets /agent");
+    }
+    for (dst, src) in ro_host_mounts {
+        let src = src
+            .canonicalize()
+            .with_context(|| format!("Failed to find host directory {}", src.display()))?;
+        if !src.is_dir() {
+            bail!("Host path {} is not a directory", src.display());
+        }
+        configs.push(MountConfig {
+            mount_type: MountType::Bind { src },
+            dst,
+            read_only: true,
+            defaults: MountDefaults::default(),
+        });
+    }
+    check_mount_points(&configs).map_err(|e| anyhow!(e))?;
+
     eprintln!("Welcome to AgentFS!");
     eprintln!();
+    eprintln!("The following mount points are sandboxed:");
 
+    let id_map = IdMap::new(uid_map, gid_map);
+    let global_defaults = MountDefaults {
+        file_mode: default_mode,
+        dir_mode: default_dir_mode,
+        uid: default_owner.map(|(uid, _)| uid),
+        gid: default_owner.map(|(_, gid)| gid),
+    };
+    let registry = vfs_registry(&id_map, vfs_cache_size);
     let mut mount_table = MountTable::new();
+    for config in configs {
+        let defaults = match config.mount_type {
+            // Bind mounts create host files, which get the host's modes and owner
+            MountType::Bind { .. } => MountDefaults::default(),
+            // Other backends have no way to report an owner
+            MountType::Vfs { .. } => MountDefaults {
+                uid: None,
+                gid: None,
+                ..config.defaults.or(global_defaults)
+            },
+            _ => config.defaults.or(global_defaults),
+        };
+        let (vfs, src, kind): (Arc<dyn Vfs>, _, _) = match config.mount_type {
+            MountType::Bind { src } => {
+                let vfs = BindVfs::new(src.clone(), config.dst.clone());
+                (Arc::new(vfs), src.display().to_string(), "bind")
+            }
+            MountType::Sqlite { src } => {
+                let vfs =
+                    open_sqlite(&src, &config.dst, &id_map, &defaults, vfs_cache_size).await?;
+                (Arc::new(vfs), src.display().to_string(), "agentfs")
+            }
+            MountType::Overlay { lower, upper } => {
+                let lower_vfs =
+                    open_sqlite(&lower, &config.dst, &id_map, &defaults, vfs_cache_size).await?;
+                let upper_vfs =
+                    open_sqlite(&upper, &config.dst, &id_map, &defaults, vfs_cache_size).await?;
+                let vfs =
+                    OverlayVfs::new(Arc::new(lower_vfs), Arc::new(upper_vfs), config.dst.clone());
+                let src = format!("{}:{}", lower.display(), upper.display());
+                (Arc::new(vfs), src, "agentfs overlay")
+            }
+            MountType::Vfs { url } => {
+                let vfs = registry
+                    .open(&url, &config.dst)
+                    .await
+                    .with_context(|| format!("Failed to open VFS {}", url))?;
+                (vfs, url, "vfs")
+            }
+        };
 
-    // Default mount: agent.db at /agent
-    let db_path = PathBuf::from("agent.db");
-    let mount_point = PathBuf::from("/agent");
+        let mode = if config.read_only { ", read-only" } else { "" };
+        eprintln!(" - {} -> {} ({}{})", config.dst.display(), src, kind, mode);
 
-    eprintln!("The following mount points are sandboxed:");
-    eprintln!(
-        " - {} -> {} (agentfs)",
-        mount_point.display(),
-        db_path.display()
-    );
+        if config.read_only {
+            mount_table.add_read_only_mount(config.dst.clone(), vfs);
+        } else {
+            mount_table.add_mount(config.dst.clone(), vfs);
+        }
+        mount_table.set_defaults(&config.dst, defaults);
+    }
+    if !excludes.is_empty() {
+        eprintln!();
+        eprintln!("The following paths pass through to the host:");
+        for pattern in excludes {
+            eprintln!(" - {}", pattern);
+            mount_table.add_exclusion(pattern);
+        }
+    }
+    if no_network {
+        eprintln!();
+        eprintln!("Network access is disabled.");
+    }
+    if dry_run {
+        eprintln!();
+        eprintln!("This is a dry run: accessed paths are logged, and nothing is modified.");
+    }
+    if !filter.is_empty() {
+        eprintln!();
+        if !allow_only.is_empty() {
+            eprintln!("Only these syscalls are allowed: {}", allow_only.join(", "));
+        }
+        if !deny_syscalls.is_empty() {
+            eprintln!("These syscalls are denied: {}", deny_syscalls.join(", "));
+        }
+    }
     eprintln!();
 
-    let vfs = SqliteVfs::new(&db_path, mount_point.clone())
-        .await
-        .expect("Failed to create AgentFS VFS");
-    mount_table.add_mount(mount_point, Arc::new(vfs));
+    let mut builder = SandboxBuilder::new()
+        .mount_table(mount_table)
+        .strace(strace)
+        .max_open_files(max_open_files)
+        .syscall_filter(filter)
+        .no_network(no_network)
+        .dry_run(dry_run)
+        .track_changes(summary_json.is_some())
+        .timeout(timeout)
+        .cpu_limit(cpu_limit)
+        .memory_limit(memory_limit);
+    if let Some(path) = strace_output {
+        let format = strace_format
+            .parse::<StraceFormat>()
+            .map_err(|e| anyhow!(e))?;
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create strace output {}", path.display()))?;
+        builder = builder.strace_output(Box::new(BufWriter::new(file)), format);
+    }
+    if let Some(workdir) = workdir {
+        builder = builder.workdir(workdir);
+    }
 
-    init_mount_table(mount_table);
-    init_fd_tables();
-    init_strace(strace);
+    let exit = builder.spawn(command, args).await?.wait().await?;
+
+    if let Some(path) = summary_json {
+        write_summary(&path, &exit)
+            .with_context(|| format!("Failed to write summary {}", path.display()))?;
+    }
+
+    // A command that was terminated can't be expected to close its files
+    if !exit.open_fds.is_empty() {
+        let report = format_open_fds(&exit.open_fds);
+        if strict_fds && !exit.timed_out {
+            bail!(report);
+        }
+        tracing::debug!("{}", report);
+    }
+
+    if dry_run {
+        eprintln!("{}", format_accessed_paths(&accessed_paths()));
+    }
 
-    let mut cmd = Command::new(command);
-    for arg in args {
-        cmd.arg(arg);
+    if exit.timed_out {
+        eprintln!("Command timed out after {:?}", timeout.unwrap_or_default());
+        std::process::exit(EXIT_TIMEOUT);
     }
+    if exit.out_of_memory && !exit.success() {
+        bail!(
+            "Command exceeded the memory limit of {} bytes",
+            memory_limit.unwrap_or_default()
+        );
+    }
+    exit.status.raise_or_exit()
+}
 
-    let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+/// Write how the command exited and the files it changed to `path` as JSON
+fn write_summary(path: &Path, exit: &SandboxExit) -> Result<()> {
+    let exit_code = match exit.status {
+        ExitStatus::Exited(code) => Some(code),
+        _ => None,
+    };
+    let summary = serde_json::json!({
+        "exit_code": exit_code,
+        "timed_out": exit.timed_out,
+        "files": exit.changed_files,
+    });
+    let mut file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut file, &summary)?;
+    writeln!(file)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Open the AgentFS database at `db_path` as a VFS mounted at `mount_point`,
+/// remapping ownership with `id_map` and owned by the owner in `defaults`, if any.
+/// File data read through it is cached up to `cache_size` bytes, or the SDK's default.
+async fn open_sqlite(
+    db_path: &Path,
+    mount_point: &Path,
+    id_map: &IdMap,
+    defaults: &MountDefaults,
+    cache_size: Option<u64>,
+) -> Result<SqliteVfs> {
+    let db_path_str = db_path
+        .to_str()
+        .context("Database path contains non-UTF8 characters")?;
+    let mut options = AgentFSOptions::with_path(db_path_str);
+    if let Some(passphrase) = crate::passphrase::get() {
+        options = options.with_passphrase(passphrase);
+    }
+    if let Some(size) = cache_size {
+        options = options.with_block_cache_size(size);
+    }
+    let vfs = SqliteVfs::open(options, mount_point.to_path_buf())
+        .await
+        .with_context(|| format!("Failed to create AgentFS VFS for {}", db_path.display()))?;
+    let vfs = vfs.with_id_map(id_map.clone());
+    if defaults.uid.is_none() && defaults.gid.is_none() {
+        return Ok(vfs);
+    }
+    // SAFETY: getuid/getgid are always safe
+    let uid = defaults.uid.unwrap_or_else(|| unsafe { libc::getuid() });
+    let gid = defaults.gid.unwrap_or_else(|| unsafe { libc::getgid() });
+    Ok(vfs.with_owner(uid, gid))
+}
 
-    let (status, _) = tracer.wait().await.unwrap();
-    status.raise_or_exit()
+/// The backends `type=vfs` mounts can be opened from, with `sqlite://` databases
+/// opened like the other AgentFS mounts.
+fn vfs_registry(id_map: &IdMap, cache_size: Option<u64>) -> VfsRegistry {
+    let mut registry = VfsRegistry::with_defaults();
+    let id_map = id_map.clone();
+    registry.register("sqlite", move |location, mount_point| {
+        let id_map = id_map.clone();
+        async move {
+            open_sqlite(
+                Path::new(&location),
+                &mount_point,
+                &id_map,
+                &MountDefaults::default(),
+                cache_size,
+            )
+            .await
+            .map(|vfs| Arc::new(vfs) as Arc<dyn Vfs>)
+            .map_err(|e| VfsError::Other(format!("{:#}", e)))
+        }
+    });
+    registry
 }
@@ -11,6 +11,7 @@ DIR="$(dirname "$0")"
 
 # 2. ptrace-based sandbox (--experimental-sandbox)
 "$DIR/test-run-experimental-syscalls.sh"
+"$DIR/test-run-dry-run.sh"
 
 # 3. FUSE overlay (agentfs run) - tests copy-on-write
 "$DIR/test-run-syscalls.sh" || true  # Requires user namespaces (may fail in CI)
@@ -18,4 +19,8 @@ DIR="$(dirname "$0")"
 "$DIR/test-run-bash.sh" || true  # Requires user namespaces (may fail in CI)
 "$DIR/test-run-git.sh" || true  # Requires user namespaces (may fail in CI)
 "$DIR/test-mount.sh"
+"$DIR/test-mount-direct-io.sh"
+"$DIR/test-mount-notify.sh"
+"$DIR/test-mount-control.sh"
+"$DIR/test-mount-signal.sh"
 "$DIR/test-symlinks.sh" || true  # Requires user namespaces (may fail in CI)
@@ -0,0 +1,119 @@
+//! Streaming of huge files by `fs cat`.
+//!
+//! This test has a binary of its own, so that the allocator counting its
+//! memory use sees no allocations of other tests.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use agentfs::cmd::fs::cat_filesystem;
+use agentfs_sdk::{AgentFS, AgentFSOptions};
+
+const MB: usize = 1024 * 1024;
+
+/// Bytes currently allocated
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Most bytes allocated at once since the last reset
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting the bytes allocated
+struct CountingAlloc;
+
+impl CountingAlloc {
+    fn grow(size: usize) {
+        let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+    }
+
+    fn shrink(size: usize) {
+        ALLOCATED.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            Self::grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Self::shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            Self::shrink(layout.size());
+            Self::grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+/// A sink that checks what is written to it against the pattern of the huge
+/// file without keeping it
+struct PatternSink {
+    written: usize,
+    largest_write: usize,
+}
+
+impl std::io::Write for PatternSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for (i, &byte) in buf.iter().enumerate() {
+            assert_eq!(byte, ((self.written + i) / MB % 251) as u8);
+        }
+        self.written += buf.len();
+        self.largest_write = self.largest_write.max(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn cat_huge_file_streams() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let path = file.path().to_str().unwrap().to_string();
+    let agentfs = AgentFS::open(AgentFSOptions::with_path(path.clone()))
+        .await
+        .unwrap();
+    let (_, huge) = agentfs.fs.create_file("/huge.bin", 0o644).await.unwrap();
+    for i in 0..200 {
+        let block = vec![(i % 251) as u8; MB];
+        huge.pwrite((i * MB) as u64, &block).await.unwrap();
+    }
+    drop(huge);
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let mut sink = PatternSink {
+        written: 0,
+        largest_write: 0,
+    };
+    cat_filesystem(&mut sink, &mut std::io::sink(), path, "/huge.bin", None)
+        .await
+        .unwrap();
+
+    assert_eq!(sink.written, 200 * MB);
+    assert!(sink.largest_write <= 64 * 1024);
+    let growth = PEAK.load(Ordering::Relaxed) - before;
+    assert!(growth < 64 * MB, "peak allocation grew by {} bytes", growth);
+}
@@ -0,0 +1,60 @@
+/*
+ * Send a request to a mount's control socket and print the response.
+ *
+ * Usage: control_call SOCKET REQUEST
+ *
+ * REQUEST is a single line of JSON. Prints the response line and exits 0,
+ * or exits 1 if the socket can't be reached or closes without answering.
+ */
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/un.h>
+#include <unistd.h>
+
+int main(int argc, char **argv) {
+    struct sockaddr_un addr;
+    char buf[4096];
+    size_t len = 0;
+    int fd;
+
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s SOCKET REQUEST\n", argv[0]);
+        return 1;
+    }
+
+    fd = socket(AF_UNIX, SOCK_STREAM | SOCK_CLOEXEC, 0);
+    if (fd < 0) {
+        perror("socket");
+        return 1;
+    }
+    memset(&addr, 0, sizeof(addr));
+    addr.sun_family = AF_UNIX;
+    strncpy(addr.sun_path, argv[1], sizeof(addr.sun_path) - 1);
+    if (connect(fd, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        perror("connect");
+        return 1;
+    }
+    if (write(fd, argv[2], strlen(argv[2])) < 0 || write(fd, "\n", 1) < 0) {
+        perror("write");
+        return 1;
+    }
+
+    while (len < sizeof(buf) - 1) {
+        ssize_t n = read(fd, buf + len, sizeof(buf) - 1 - len);
+        if (n <= 0) {
+            break;
+        }
+        len += n;
+        if (buf[len - 1] == '\n') {
+            break;
+        }
+    }
+    if (len == 0 || buf[len - 1] != '\n') {
+        fprintf(stderr, "no response\n");
+        return 1;
+    }
+    buf[len] = '\0';
+    fputs(buf, stdout);
+    return 0;
+}
@@ -0,0 +1,50 @@
+/*
+ * Wait for an inotify event on a path.
+ *
+ * Usage: inotify_wait PATH TIMEOUT_SECS
+ *
+ * Prints "ready" once the watch is in place, then the mask of the first
+ * event. Exits 0 if an event arrived and 1 on timeout or error.
+ */
+#include <poll.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/inotify.h>
+#include <unistd.h>
+
+int main(int argc, char **argv) {
+    char buf[4096] __attribute__((aligned(__alignof__(struct inotify_event))));
+    struct pollfd pfd;
+    int fd, n;
+
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s PATH TIMEOUT_SECS\n", argv[0]);
+        return 1;
+    }
+
+    fd = inotify_init1(IN_CLOEXEC);
+    if (fd < 0) {
+        perror("inotify_init1");
+        return 1;
+    }
+    if (inotify_add_watch(fd, argv[1], IN_MODIFY | IN_CLOSE_WRITE | IN_ATTRIB | IN_DELETE | IN_DELETE_SELF) < 0) {
+        perror("inotify_add_watch");
+        return 1;
+    }
+    printf("ready\n");
+    fflush(stdout);
+
+    pfd.fd = fd;
+    pfd.events = POLLIN;
+    if (poll(&pfd, 1, atoi(argv[2]) * 1000) <= 0) {
+        fprintf(stderr, "no event\n");
+        return 1;
+    }
+    n = read(fd, buf, sizeof(buf));
+    if (n < (int)sizeof(struct inotify_event)) {
+        perror("read");
+        return 1;
+    }
+    printf("0x%x\n", ((struct inotify_event *)buf)->mask);
+    return 0;
+}
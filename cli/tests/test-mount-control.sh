@@ -0,0 +1,116 @@
+#!/bin/sh
+set -e
+
+echo -n "TEST mount --control-socket... "
+
+DIR="$(cd "$(dirname "$0")" && pwd)"
+TEST_AGENT_ID="test-mount-control-agent"
+MOUNTPOINT="/tmp/agentfs-test-mount-control-$$"
+SOCKET="/tmp/agentfs-test-mount-control-$$.sock"
+
+cleanup() {
+    # Unmount if mounted
+    fusermount -u "$MOUNTPOINT" 2>/dev/null || true
+    # Remove mountpoint and socket
+    rmdir "$MOUNTPOINT" 2>/dev/null || true
+    rm -f "$SOCKET"
+    # Remove test database
+    rm -f ".agentfs/${TEST_AGENT_ID}.db" ".agentfs/${TEST_AGENT_ID}.db-shm" ".agentfs/${TEST_AGENT_ID}.db-wal"
+}
+
+# Ensure cleanup on exit
+trap cleanup EXIT
+
+# Clean up any existing test artifacts
+cleanup
+
+# Build the control client
+cc -o "$DIR/control_call" "$DIR/control_call.c"
+
+# Initialize the database
+cargo run -- init "$TEST_AGENT_ID" > /dev/null 2>&1
+
+# Create mountpoint
+mkdir -p "$MOUNTPOINT"
+
+# Mount in foreground mode (background it ourselves so we can control it)
+cargo run -- mount ".agentfs/${TEST_AGENT_ID}.db" "$MOUNTPOINT" --foreground --control-socket "$SOCKET" &
+MOUNT_PID=$!
+
+# Wait for mount to be ready
+MAX_WAIT=10
+WAITED=0
+while [ $WAITED -lt $MAX_WAIT ]; do
+    if mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+        break
+    fi
+    sleep 0.5
+    WAITED=$((WAITED + 1))
+done
+
+if ! mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+    echo "FAILED: mount did not become ready in time"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+call() {
+    "$DIR/control_call" "$SOCKET" "$1"
+}
+
+# Keep a file open while querying stats
+echo "hello" > "$MOUNTPOINT/open.txt"
+exec 3< "$MOUNTPOINT/open.txt"
+
+STATS=$(call '{"jsonrpc":"2.0","id":1,"method":"stats"}')
+exec 3<&-
+if ! echo "$STATS" | grep -q '"open_files":[1-9]'; then
+    echo "FAILED: stats do not count the open file"
+    echo "Got: $STATS"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+SNAPSHOT=$(call '{"jsonrpc":"2.0","id":2,"method":"snapshot","params":{"name":"live"}}')
+if ! echo "$SNAPSHOT" | grep -q '"name":"live"'; then
+    echo "FAILED: snapshot of the running mount"
+    echo "Got: $SNAPSHOT"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+# After remount-ro, writes fail but reads still work
+call '{"jsonrpc":"2.0","id":3,"method":"remount-ro"}' > /dev/null
+if echo "changed" > "$MOUNTPOINT/open.txt" 2>/dev/null; then
+    echo "FAILED: write succeeded after remount-ro"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+CONTENT=$(cat "$MOUNTPOINT/open.txt")
+if [ "$CONTENT" != "hello" ]; then
+    echo "FAILED: file changed after remount-ro"
+    echo "Got: $CONTENT"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+MOUNTS=$(call '{"jsonrpc":"2.0","id":4,"method":"list-mounts"}')
+if ! echo "$MOUNTS" | grep -q '"read_only":true'; then
+    echo "FAILED: list-mounts does not report the mount as read-only"
+    echo "Got: $MOUNTS"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+# Unmount
+fusermount -u "$MOUNTPOINT"
+
+# Wait for mount process to exit
+wait $MOUNT_PID 2>/dev/null || true
+
+if [ -e "$SOCKET" ]; then
+    echo "FAILED: control socket left behind after unmount"
+    exit 1
+fi
+
+echo "OK"
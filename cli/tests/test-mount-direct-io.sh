@@ -0,0 +1,78 @@
+#!/bin/sh
+set -e
+
+echo -n "TEST mount --fuse-direct-io... "
+
+TEST_AGENT_ID="test-mount-direct-io-agent"
+MOUNTPOINT="/tmp/agentfs-test-mount-direct-io-$$"
+
+cleanup() {
+    # Close file descriptors held open on the mount
+    exec 3>&- 4<&-
+    # Unmount if mounted
+    fusermount -u "$MOUNTPOINT" 2>/dev/null || true
+    # Remove mountpoint
+    rmdir "$MOUNTPOINT" 2>/dev/null || true
+    # Remove test database
+    rm -f ".agentfs/${TEST_AGENT_ID}.db" ".agentfs/${TEST_AGENT_ID}.db-shm" ".agentfs/${TEST_AGENT_ID}.db-wal"
+}
+
+# Ensure cleanup on exit
+trap cleanup EXIT
+
+# Clean up any existing test artifacts
+cleanup
+
+# Initialize the database
+cargo run -- init "$TEST_AGENT_ID" > /dev/null 2>&1
+
+# Create mountpoint
+mkdir -p "$MOUNTPOINT"
+
+# Mount in foreground mode (background it ourselves so we can control it)
+cargo run -- mount ".agentfs/${TEST_AGENT_ID}.db" "$MOUNTPOINT" --foreground \
+    --fuse-direct-io --attr-timeout 0 --entry-timeout 0 &
+MOUNT_PID=$!
+
+# Wait for mount to be ready
+MAX_WAIT=10
+WAITED=0
+while [ $WAITED -lt $MAX_WAIT ]; do
+    if mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+        break
+    fi
+    sleep 0.5
+    WAITED=$((WAITED + 1))
+done
+
+if ! mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+    echo "FAILED: mount did not become ready in time"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+# Open the file through two separate handles, then write through the first
+echo "original" > "$MOUNTPOINT/shared.txt"
+exec 3<>"$MOUNTPOINT/shared.txt"
+exec 4<"$MOUNTPOINT/shared.txt"
+printf "updated!" >&3
+
+# The write must be visible through the second handle straight away
+CONTENT=$(cat <&4)
+if [ "$CONTENT" != "updated!" ]; then
+    echo "FAILED: write through one handle not visible through another"
+    echo "Expected: updated!"
+    echo "Got: $CONTENT"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+exec 3>&- 4<&-
+
+# Unmount
+fusermount -u "$MOUNTPOINT"
+
+# Wait for mount process to exit
+wait $MOUNT_PID 2>/dev/null || true
+
+echo "OK"
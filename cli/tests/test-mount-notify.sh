@@ -0,0 +1,97 @@
+#!/bin/sh
+set -e
+
+echo -n "TEST mount --notify... "
+
+DIR="$(cd "$(dirname "$0")" && pwd)"
+TEST_AGENT_ID="test-mount-notify-agent"
+MOUNTPOINT="/tmp/agentfs-test-mount-notify-$$"
+HOST_FILE="/tmp/agentfs-test-mount-notify-$$.txt"
+EVENTS="/tmp/agentfs-test-mount-notify-$$.events"
+
+cleanup() {
+    # Unmount if mounted
+    fusermount -u "$MOUNTPOINT" 2>/dev/null || true
+    # Remove mountpoint and scratch files
+    rmdir "$MOUNTPOINT" 2>/dev/null || true
+    rm -f "$HOST_FILE" "$EVENTS"
+    # Remove test database
+    rm -f ".agentfs/${TEST_AGENT_ID}.db" ".agentfs/${TEST_AGENT_ID}.db-shm" ".agentfs/${TEST_AGENT_ID}.db-wal"
+}
+
+# Ensure cleanup on exit
+trap cleanup EXIT
+
+# Clean up any existing test artifacts
+cleanup
+
+# Build the inotify watcher
+cc -o "$DIR/inotify_wait" "$DIR/inotify_wait.c"
+
+# Initialize the database
+cargo run -- init "$TEST_AGENT_ID" > /dev/null 2>&1
+
+# Create mountpoint
+mkdir -p "$MOUNTPOINT"
+
+# Mount in foreground mode (background it ourselves so we can control it)
+cargo run -- mount ".agentfs/${TEST_AGENT_ID}.db" "$MOUNTPOINT" --foreground --notify &
+MOUNT_PID=$!
+
+# Wait for mount to be ready
+MAX_WAIT=10
+WAITED=0
+while [ $WAITED -lt $MAX_WAIT ]; do
+    if mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+        break
+    fi
+    sleep 0.5
+    WAITED=$((WAITED + 1))
+done
+
+if ! mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+    echo "FAILED: mount did not become ready in time"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+# Create a file through the mount and read it, so the kernel caches it
+echo "before" > "$MOUNTPOINT/watched.txt"
+cat "$MOUNTPOINT/watched.txt" > /dev/null
+
+# Let the change watcher record the file's current state
+sleep 2
+
+# Watch the file, then change it on the host side
+"$DIR/inotify_wait" "$MOUNTPOINT/watched.txt" 10 > "$EVENTS" &
+WATCH_PID=$!
+while ! grep -q ready "$EVENTS" 2>/dev/null; do
+    sleep 0.1
+done
+
+echo "changed on the host" > "$HOST_FILE"
+cargo run -- fs ".agentfs/${TEST_AGENT_ID}.db" put "$HOST_FILE" /watched.txt > /dev/null 2>&1
+
+if ! wait $WATCH_PID; then
+    echo "FAILED: no inotify event after a host-side write"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+# The new content must be visible through the mount
+CONTENT=$(cat "$MOUNTPOINT/watched.txt")
+if [ "$CONTENT" != "changed on the host" ]; then
+    echo "FAILED: host-side write not visible through the mount"
+    echo "Expected: changed on the host"
+    echo "Got: $CONTENT"
+    kill $MOUNT_PID 2>/dev/null || true
+    exit 1
+fi
+
+# Unmount
+fusermount -u "$MOUNTPOINT"
+
+# Wait for mount process to exit
+wait $MOUNT_PID 2>/dev/null || true
+
+echo "OK"
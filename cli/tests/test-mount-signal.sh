@@ -0,0 +1,79 @@
+#!/bin/sh
+set -e
+
+echo -n "TEST mount unmounts on SIGTERM... "
+
+TEST_AGENT_ID="test-mount-signal-agent"
+MOUNTPOINT="/tmp/agentfs-test-mount-signal-$$"
+
+cleanup() {
+    # Unmount if mounted
+    fusermount -u "$MOUNTPOINT" 2>/dev/null || true
+    # Remove mountpoint
+    rmdir "$MOUNTPOINT" 2>/dev/null || true
+    # Remove test database
+    rm -f ".agentfs/${TEST_AGENT_ID}.db" ".agentfs/${TEST_AGENT_ID}.db-shm" ".agentfs/${TEST_AGENT_ID}.db-wal"
+}
+
+# Ensure cleanup on exit
+trap cleanup EXIT
+
+# Clean up any existing test artifacts
+cleanup
+
+# Initialize the database
+cargo run -- init "$TEST_AGENT_ID" > /dev/null 2>&1
+
+# Create mountpoint
+mkdir -p "$MOUNTPOINT"
+
+# Mount as a daemon, which returns once the mount is ready
+cargo run -- mount ".agentfs/${TEST_AGENT_ID}.db" "$MOUNTPOINT" > /dev/null 2>&1
+
+if ! mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+    echo "FAILED: mount did not become ready"
+    exit 1
+fi
+
+DAEMON_PID=$(pgrep -f "mount .*$MOUNTPOINT" | head -n 1)
+if [ -z "$DAEMON_PID" ]; then
+    echo "FAILED: mount daemon not found"
+    exit 1
+fi
+
+echo "written before SIGTERM" > "$MOUNTPOINT/test.txt"
+
+kill -TERM "$DAEMON_PID"
+
+# Wait for the daemon to unmount and exit
+MAX_WAIT=10
+WAITED=0
+while [ $WAITED -lt $MAX_WAIT ]; do
+    if ! kill -0 "$DAEMON_PID" 2>/dev/null; then
+        break
+    fi
+    sleep 0.5
+    WAITED=$((WAITED + 1))
+done
+
+if kill -0 "$DAEMON_PID" 2>/dev/null; then
+    echo "FAILED: mount daemon still running after SIGTERM"
+    kill -KILL "$DAEMON_PID" 2>/dev/null || true
+    exit 1
+fi
+
+if mountpoint -q "$MOUNTPOINT" 2>/dev/null; then
+    echo "FAILED: mountpoint still mounted after SIGTERM"
+    exit 1
+fi
+
+# What was written through the mount must be in the database
+CONTENT=$(cargo run -- fs ".agentfs/${TEST_AGENT_ID}.db" cat /test.txt 2>/dev/null)
+if [ "$CONTENT" != "written before SIGTERM" ]; then
+    echo "FAILED: write through the mount lost"
+    echo "Expected: written before SIGTERM"
+    echo "Got: $CONTENT"
+    exit 1
+fi
+
+echo "OK"
@@ -0,0 +1,48 @@
+#!/bin/sh
+#
+# Test agentfs run --experimental-sandbox --dry-run.
+#
+# A dry run logs the paths the command accesses, but must not modify any of
+# them in the AgentFS database mounted at /agent.
+#
+set -e
+
+echo -n "TEST dry run (agentfs run --experimental-sandbox --dry-run)... "
+
+TEST_DB="agent.db"
+
+cleanup() {
+    rm -f "$TEST_DB" "${TEST_DB}-wal" "${TEST_DB}-shm"
+}
+
+cleanup
+cargo run -- init > /dev/null 2>&1
+
+# Writing a file and creating a directory succeed as far as the command can tell
+if ! output=$(cargo run -- run --experimental-sandbox --dry-run /bin/bash -c 'echo hello > /agent/dry.txt && mkdir /agent/dry-dir && echo done' 2>&1); then
+    echo "FAILED: dry run failed"
+    echo "Output was: $output"
+    cleanup
+    exit 1
+fi
+
+for expected in "done" "modify /agent/dry.txt" "modify /agent/dry-dir"; do
+    echo "$output" | grep -q -- "$expected" || {
+        echo "FAILED: '$expected' not found"
+        echo "Output was: $output"
+        cleanup
+        exit 1
+    }
+done
+
+# Nothing reached the database
+if output=$(cargo run -- run --experimental-sandbox /bin/ls /agent/dry.txt /agent/dry-dir 2>&1); then
+    echo "FAILED: the dry run modified /agent"
+    echo "Output was: $output"
+    cleanup
+    exit 1
+fi
+
+cleanup
+
+echo "OK"
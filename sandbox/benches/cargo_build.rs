@@ -0,0 +1,85 @@
+//! Building a small crate with `cargo` inside the sandbox.
+//!
+//! A build spends most of its syscalls on paths no mount owns: the toolchain
+//! under `~/.rustup`, the registry under `~/.cargo`, and the system linker and
+//! libraries under `/usr` and `/lib`. Only the crate itself lives under the
+//! `/work` mount, so this measures what intercepting the rest costs.
+//!
+//! The build runs offline, and the benchmark is skipped if `cargo` is missing.
+//!
+//! Run with: cargo bench --bench cargo_build
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[cfg(target_os = "linux")]
+fn bench_cargo_build(c: &mut Criterion) {
+    use agentfs_sandbox::{
+        init_fd_tables, init_mount_table, init_strace, BindVfs, MountTable, Sandbox,
+    };
+    use reverie_process::{Command, ExitStatus};
+    use reverie_ptrace::TracerBuilder;
+    use std::{path::PathBuf, sync::Arc};
+
+    let cargo = std::process::Command::new("cargo")
+        .arg("--version")
+        .output();
+    if !cargo.is_ok_and(|output| output.status.success()) {
+        eprintln!("cargo not found, skipping the cargo_build benchmark");
+        return;
+    }
+
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    std::fs::write(
+        dir.path().join("Cargo.toml"),
+        "[package]\nname = \"bench\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .expect("Failed to write Cargo.toml");
+    std::fs::create_dir(dir.path().join("src")).expect("Failed to create src");
+    let modules: String = (0..20)
+        .map(|i| format!("mod m{} {{ pub fn f() -> usize {{ {} }} }}\n", i, i))
+        .collect();
+    let calls: String = (0..20).map(|i| format!("m{}::f() + ", i)).collect();
+    std::fs::write(
+        dir.path().join("src/main.rs"),
+        format!(
+            "{}fn main() {{ println!(\"{{}}\", {}0); }}\n",
+            modules, calls
+        ),
+    )
+    .expect("Failed to write main.rs");
+
+    let mount_point = PathBuf::from("/work");
+    let mut table = MountTable::new();
+    table.add_mount(
+        mount_point.clone(),
+        Arc::new(BindVfs::new(dir.path().to_path_buf(), mount_point)),
+    );
+    init_mount_table(table);
+    init_fd_tables();
+    init_strace(false);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("cargo_build");
+    group.sample_size(10);
+
+    group.bench_function("build", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let mut cmd = Command::new("/bin/sh");
+                cmd.arg("-c")
+                    .arg("cd /work && rm -rf target && cargo build --offline --quiet");
+                let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+                let (status, _) = tracer.wait().await.unwrap();
+                assert_eq!(status, ExitStatus::Exited(0));
+            })
+        });
+    });
+
+    group.finish();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bench_cargo_build(_c: &mut Criterion) {}
+
+criterion_group!(benches, bench_cargo_build);
+criterion_main!(benches);
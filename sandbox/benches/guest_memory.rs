@@ -0,0 +1,84 @@
+//! Reading a path argument from the memory of another process.
+//!
+//! `single_read` reads the path with one `process_vm_readv` call, as the
+//! syscall handlers do; `word_by_word` reads it one word per call up to its
+//! NUL, which is what reading it with a round-trip per word costs.
+//!
+//! Run with: cargo bench --bench guest_memory
+
+#[cfg(target_os = "linux")]
+mod bench {
+    use agentfs_sandbox::syscall::memory::read_c_string;
+    use criterion::{BenchmarkId, Criterion};
+    use std::ffi::CString;
+
+    const WORD: usize = std::mem::size_of::<usize>();
+
+    /// Read the NUL-terminated string at `addr` in process `pid` one word at a time
+    fn read_word_by_word(pid: i32, addr: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let mut word = [0u8; WORD];
+            let local = libc::iovec {
+                iov_base: word.as_mut_ptr().cast(),
+                iov_len: WORD,
+            };
+            let remote = libc::iovec {
+                iov_base: (addr + bytes.len()) as *mut libc::c_void,
+                iov_len: WORD,
+            };
+            let read = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+            assert_eq!(read, WORD as isize);
+            match word.iter().position(|&b| b == 0) {
+                Some(nul) => {
+                    bytes.extend_from_slice(&word[..nul]);
+                    return bytes;
+                }
+                None => bytes.extend_from_slice(&word),
+            }
+        }
+    }
+
+    pub fn bench_read_path(c: &mut Criterion) {
+        let paths: Vec<CString> = [16, 64, 256]
+            .into_iter()
+            .map(|len| CString::new(vec![b'a'; len]).unwrap())
+            .collect();
+
+        // The child is a copy of this process, so the paths are at the same
+        // addresses in its memory
+        let child = unsafe { libc::fork() };
+        assert!(child >= 0);
+        if child == 0 {
+            loop {
+                unsafe { libc::pause() };
+            }
+        }
+
+        let mut group = c.benchmark_group("read_path");
+        for path in &paths {
+            let addr = path.as_ptr() as usize;
+            let len = path.as_bytes().len();
+            group.bench_with_input(BenchmarkId::new("single_read", len), &addr, |b, &addr| {
+                b.iter(|| read_c_string(child, addr, libc::PATH_MAX as usize).unwrap())
+            });
+            group.bench_with_input(BenchmarkId::new("word_by_word", len), &addr, |b, &addr| {
+                b.iter(|| read_word_by_word(child, addr))
+            });
+        }
+        group.finish();
+
+        unsafe {
+            libc::kill(child, libc::SIGKILL);
+            libc::waitpid(child, std::ptr::null_mut(), 0);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+criterion::criterion_group!(benches, bench::bench_read_path);
+#[cfg(target_os = "linux")]
+criterion::criterion_main!(benches);
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
@@ -0,0 +1,97 @@
+//! A short-lived agent run against the in-memory and SQLite VFS backends.
+//!
+//! `scratch_run` checks out a small project of 500 files, reads it back the
+//! way a build does, lists and stats it, and cleans it up again, all through
+//! the `Vfs` trait, so both backends do the same work.
+//!
+//! Run with: cargo bench --bench memory_vfs
+
+use agentfs_sandbox::{MemoryVfs, SqliteVfs, Vfs};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const DIRS: usize = 10;
+const FILES_PER_DIR: usize = 50;
+const FILE_SIZE: usize = 4096;
+
+fn dir_path(dir: usize) -> PathBuf {
+    PathBuf::from(format!("/work/src{}", dir))
+}
+
+fn file_path(dir: usize, file: usize) -> PathBuf {
+    PathBuf::from(format!("/work/src{}/file{}.rs", dir, file))
+}
+
+/// Create the project, read it back, and remove it
+async fn scratch_run(vfs: &dyn Vfs) {
+    let data = vec![b'x'; FILE_SIZE];
+
+    for dir in 0..DIRS {
+        vfs.mkdir(&dir_path(dir), 0o755).await.unwrap();
+        for file in 0..FILES_PER_DIR {
+            let handle = vfs
+                .open(
+                    &file_path(dir, file),
+                    libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+                    0o644,
+                )
+                .await
+                .unwrap();
+            vfs.write(&handle, 0, &data).await.unwrap();
+            handle.close().await.unwrap();
+        }
+    }
+
+    for dir in 0..DIRS {
+        let handle = vfs
+            .open(&dir_path(dir), libc::O_RDONLY | libc::O_DIRECTORY, 0)
+            .await
+            .unwrap();
+        std::hint::black_box(vfs.readdir(&handle).await.unwrap());
+        handle.close().await.unwrap();
+
+        for file in 0..FILES_PER_DIR {
+            let path = file_path(dir, file);
+            std::hint::black_box(vfs.stat(&path).await.unwrap());
+            let handle = vfs.open(&path, libc::O_RDONLY, 0).await.unwrap();
+            std::hint::black_box(vfs.read(&handle, 0, FILE_SIZE).await.unwrap());
+            handle.close().await.unwrap();
+        }
+    }
+
+    for dir in 0..DIRS {
+        for file in 0..FILES_PER_DIR {
+            vfs.unlink(&file_path(dir, file)).await.unwrap();
+        }
+        vfs.rmdir(&dir_path(dir)).await.unwrap();
+    }
+}
+
+fn bench_scratch_run(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let mount_point = PathBuf::from("/work");
+
+    let sqlite: Arc<dyn Vfs> = rt.block_on(async {
+        let vfs = SqliteVfs::new(dir.path().join("bench.db"), mount_point.clone())
+            .await
+            .unwrap();
+        Arc::new(vfs)
+    });
+    let memory: Arc<dyn Vfs> = Arc::new(MemoryVfs::new(mount_point));
+
+    let mut group = c.benchmark_group("scratch_run_500_files");
+    group.sample_size(10);
+
+    for (name, vfs) in [("sqlite", &sqlite), ("memory", &memory)] {
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter(|| scratch_run(vfs.as_ref()));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_scratch_run);
+criterion_main!(benches);
@@ -0,0 +1,131 @@
+//! Resolving the paths of a `find` over a tree of 10k files.
+//!
+//! `resolve` compares resolving each path through the mount table with and
+//! without its cache. `find` runs `find` over the tree inside the sandbox;
+//! the sandbox's mount table is set once per process, so run it with
+//! `RESOLVE_CACHE_SIZE=0` to measure it without the cache.
+//!
+//! Run with: cargo bench --bench path_resolution
+
+use agentfs_sandbox::{BindVfs, MountTable};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const DIRS: usize = 100;
+const FILES_PER_DIR: usize = 100;
+
+/// Build a mount table like the one `agentfs run` sets up, mounting `/bench`
+/// over `root`
+fn mount_table(root: &Path) -> MountTable {
+    let mut table = MountTable::new();
+    for (sandbox_path, host_path) in [
+        ("/bench", root.to_path_buf()),
+        ("/bench/dir0", root.join("dir0")),
+        ("/tmp", std::env::temp_dir()),
+    ] {
+        table.add_mount(
+            PathBuf::from(sandbox_path),
+            Arc::new(BindVfs::new(host_path, PathBuf::from(sandbox_path))),
+        );
+    }
+    table.add_exclusion("/bench/**/.git");
+    table.add_exclusion("/home/*/.ssh");
+    table
+}
+
+/// Paths a `find` over the tree looks up: each directory and file under
+/// `/bench`, and the host paths of the program itself
+fn find_paths() -> Vec<PathBuf> {
+    let mut paths = vec![
+        PathBuf::from("/usr/bin/find"),
+        PathBuf::from("/etc/ld.so.cache"),
+        PathBuf::from("/lib/x86_64-linux-gnu/libc.so.6"),
+        PathBuf::from("/bench"),
+    ];
+    for dir in 0..DIRS {
+        paths.push(PathBuf::from(format!("/bench/dir{}", dir)));
+        for file in 0..FILES_PER_DIR {
+            paths.push(PathBuf::from(format!("/bench/dir{}/file{}.txt", dir, file)));
+        }
+    }
+    paths
+}
+
+fn bench_resolve(c: &mut Criterion) {
+    let paths = find_paths();
+    let cached = mount_table(Path::new("/host"));
+    let mut uncached = cached.clone();
+    uncached.set_resolve_cache_size(0);
+
+    let mut group = c.benchmark_group("resolve_10k_paths");
+    group.sample_size(20);
+
+    // A `find` makes several syscalls per path: open or stat, then getdents
+    // and close on the resulting fd
+    for (name, table) in [("uncached", &uncached), ("cached", &cached)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for path in &paths {
+                    for _ in 0..3 {
+                        std::hint::black_box(table.resolve(path));
+                    }
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+#[cfg(target_os = "linux")]
+fn bench_find(c: &mut Criterion) {
+    use agentfs_sandbox::{init_fd_tables, init_mount_table, init_strace, Sandbox};
+    use reverie_process::{Command, ExitStatus};
+    use reverie_ptrace::TracerBuilder;
+
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    for i in 0..DIRS {
+        let sub = dir.path().join(format!("dir{}", i));
+        std::fs::create_dir(&sub).expect("Failed to create directory");
+        for j in 0..FILES_PER_DIR {
+            std::fs::write(sub.join(format!("file{}.txt", j)), b"x")
+                .expect("Failed to create file");
+        }
+    }
+
+    let mut table = mount_table(dir.path());
+    if let Some(size) = std::env::var("RESOLVE_CACHE_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+    {
+        table.set_resolve_cache_size(size);
+    }
+    init_mount_table(table);
+    init_fd_tables();
+    init_strace(false);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("find_10k_files");
+    group.sample_size(10);
+
+    group.bench_function("find", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let mut cmd = Command::new("/bin/sh");
+                cmd.arg("-c").arg("find /bench -name '*.txt' > /dev/null");
+                let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+                let (status, _) = tracer.wait().await.unwrap();
+                assert_eq!(status, ExitStatus::Exited(0));
+            })
+        });
+    });
+
+    group.finish();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bench_find(_c: &mut Criterion) {}
+
+criterion_group!(benches, bench_resolve, bench_find);
+criterion_main!(benches);
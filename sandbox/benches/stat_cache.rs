@@ -0,0 +1,77 @@
+//! Stat-ing the files of a SQLite VFS the way a configure script does.
+//!
+//! A configure script probes thousands of paths: it checks whether headers
+//! and programs exist, many of which don't, and stats the ones that do again
+//! before opening them. `configure` runs such probes over a tree of 2k files
+//! with and without the VFS stat cache.
+//!
+//! Run with: cargo bench --bench stat_cache
+
+use agentfs_sandbox::{SqliteVfs, Vfs};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::{Path, PathBuf};
+
+const DIRS: usize = 50;
+const FILES_PER_DIR: usize = 40;
+
+/// Paths a configure script probes: each file, and a missing variant of it
+fn probe_paths() -> Vec<(PathBuf, PathBuf)> {
+    let mut paths = Vec::new();
+    for dir in 0..DIRS {
+        for file in 0..FILES_PER_DIR {
+            paths.push((
+                PathBuf::from(format!("/agent/usr/include{}/header{}.h", dir, file)),
+                PathBuf::from(format!("/agent/usr/include{}/missing{}.h", dir, file)),
+            ));
+        }
+    }
+    paths
+}
+
+fn bench_configure(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let paths = probe_paths();
+
+    let cached = rt.block_on(async {
+        let vfs = SqliteVfs::new(dir.path().join("bench.db"), PathBuf::from("/agent"))
+            .await
+            .unwrap();
+        vfs.mkdir(Path::new("/agent/usr"), 0o755).await.unwrap();
+        for dir in 0..DIRS {
+            let path = PathBuf::from(format!("/agent/usr/include{}", dir));
+            vfs.mkdir(&path, 0o755).await.unwrap();
+        }
+        for (path, _) in &paths {
+            let file = vfs
+                .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+                .await
+                .unwrap();
+            file.close().await.unwrap();
+        }
+        vfs
+    });
+    let uncached = cached.clone().with_stat_cache_size(0);
+
+    let mut group = c.benchmark_group("configure_2k_files");
+    group.sample_size(10);
+
+    for (name, vfs) in [("uncached", &uncached), ("cached", &cached)] {
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter(|| async {
+                for (path, missing) in &paths {
+                    // `test -f`, then the stat before opening it
+                    for _ in 0..2 {
+                        std::hint::black_box(vfs.stat(path).await.unwrap());
+                    }
+                    std::hint::black_box(vfs.stat(missing).await.unwrap_err());
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_configure);
+criterion_main!(benches);
@@ -5,10 +5,23 @@ pub mod syscall;
 pub mod vfs;
 
 #[cfg(target_os = "linux")]
-pub use sandbox::{init_fd_tables, init_mount_table, init_strace, Sandbox};
+pub use sandbox::{
+    accessed_paths, changed_files, close_strace_output, close_virtual_files, format_accessed_paths,
+    format_open_fds, init_change_tracking, init_cpu_limit, init_dry_run, init_fd_tables,
+    init_max_open_files, init_memory_limit, init_mount_table, init_no_network, init_strace,
+    init_strace_output, init_syscall_filter, init_workdir, memory_limit_exceeded, open_fds_at_exit,
+    release_memory_limit, wait_with_timeout, AccessedPath, ChangeKind, ChangedFile, ExitStatus,
+    OpenFd, Sandbox, SandboxBuilder, SandboxExit, SandboxHandle, StraceFormat, SyscallFilter,
+    KILL_GRACE_PERIOD,
+};
 pub use vfs::{
     bind::BindVfs,
-    mount::{MountConfig, MountTable, MountType},
+    fdtable::{GuestCwd, DEFAULT_MAX_OPEN_FILES},
+    idmap::IdMap,
+    memory::MemoryVfs,
+    mount::{check_mount_points, MountConfig, MountDefaults, MountTable, MountType},
+    overlay::OverlayVfs,
+    registry::VfsRegistry,
     Vfs, VfsError, VfsResult,
 };
 
@@ -0,0 +1,363 @@
+//! Running a command in the sandbox from Rust.
+//!
+//! The sandbox is configured through process-wide state, which the `init_*`
+//! functions set up before the traced command is spawned and the teardown
+//! functions flush once it has exited. `SandboxBuilder` and `SandboxHandle` do
+//! both in order for embedders.
+
+use super::{
+    changed_files, changes::is_tracking_changes, close_strace_output, close_virtual_files,
+    init_change_tracking, init_cpu_limit, init_dry_run, init_fd_tables, init_max_open_files,
+    init_memory_limit, init_mount_table, init_no_network, init_strace, init_strace_output,
+    init_syscall_filter, init_workdir, memory_limit_exceeded, open_fds_at_exit,
+    release_memory_limit, signal_guests, wait_with_timeout, ChangedFile, OpenFd, Sandbox,
+    StraceFormat, SyscallFilter,
+};
+use crate::vfs::{
+    fdtable::{GuestCwd, DEFAULT_MAX_OPEN_FILES},
+    mount::MountTable,
+    Vfs,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use reverie::Tool;
+use reverie_process::Command;
+pub use reverie_process::ExitStatus;
+use reverie_ptrace::{Tracer, TracerBuilder};
+use std::{
+    ffi::{OsStr, OsString},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Configuration of a sandboxed command, spawned with `spawn()`
+///
+/// ```no_run
+/// use agentfs_sandbox::{MemoryVfs, SandboxBuilder};
+/// use std::sync::Arc;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let workspace = MemoryVfs::new("/workspace".into());
+/// let handle = SandboxBuilder::new()
+///     .mount("/workspace", Arc::new(workspace.clone()))
+///     .strace(true)
+///     .env("HOME", "/workspace")
+///     .spawn("/bin/sh", ["-c", "echo hello > /workspace/out.txt"])
+///     .await?;
+/// let exit = handle.wait().await?;
+/// assert!(exit.success());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// As the sandbox state is global, a process can only spawn one sandbox.
+pub struct SandboxBuilder {
+    mount_table: MountTable,
+    strace: Option<(Box<dyn Write + Send>, StraceFormat)>,
+    env: Vec<(OsString, OsString)>,
+    workdir: Option<PathBuf>,
+    syscall_filter: SyscallFilter,
+    no_network: bool,
+    dry_run: bool,
+    track_changes: bool,
+    max_open_files: usize,
+    timeout: Option<Duration>,
+    cpu_limit: Option<Duration>,
+    memory_limit: Option<u64>,
+}
+
+impl Default for SandboxBuilder {
+    fn default() -> Self {
+        Self {
+            mount_table: MountTable::new(),
+            strace: None,
+            env: Vec::new(),
+            workdir: None,
+            syscall_filter: SyscallFilter::new(),
+            no_network: false,
+            dry_run: false,
+            track_changes: false,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            timeout: None,
+            cpu_limit: None,
+            memory_limit: None,
+        }
+    }
+}
+
+impl SandboxBuilder {
+    /// Create a sandbox without mounts, in which everything passes through to
+    /// the host
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mount `vfs` at `path` in the sandbox
+    pub fn mount(mut self, path: impl Into<PathBuf>, vfs: Arc<dyn Vfs>) -> Self {
+        self.mount_table.add_mount(path.into(), vfs);
+        self
+    }
+
+    /// Mount `vfs` at `path` in the sandbox, rejecting changes with `EROFS`
+    pub fn mount_read_only(mut self, path: impl Into<PathBuf>, vfs: Arc<dyn Vfs>) -> Self {
+        self.mount_table.add_read_only_mount(path.into(), vfs);
+        self
+    }
+
+    /// Pass paths matching a glob pattern through to the host, even under a mount
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.mount_table.add_exclusion(pattern);
+        self
+    }
+
+    /// Use `mount_table` in place of the mounts and exclusions added so far
+    pub fn mount_table(mut self, mount_table: MountTable) -> Self {
+        self.mount_table = mount_table;
+        self
+    }
+
+    /// Log the command's syscalls to stderr
+    pub fn strace(mut self, enabled: bool) -> Self {
+        self.strace = None;
+        if enabled {
+            self.strace = Some((Box::new(std::io::stderr()), StraceFormat::Text));
+        }
+        self
+    }
+
+    /// Log the command's syscalls to `output` in the given format
+    ///
+    /// The output is flushed when `SandboxHandle::wait()` returns.
+    pub fn strace_output(mut self, output: Box<dyn Write + Send>, format: StraceFormat) -> Self {
+        self.strace = Some((output, format));
+        self
+    }
+
+    /// Set an environment variable for the command, on top of the ones it
+    /// inherits
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.env
+            .push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
+    /// Start the command in `workdir`, resolved through the mounts
+    ///
+    /// Relative paths are taken from the current directory. Under a mount, the
+    /// command starts in the host directory the path translates to, and sees
+    /// `workdir` as its working directory.
+    pub fn workdir(mut self, workdir: impl Into<PathBuf>) -> Self {
+        self.workdir = Some(workdir.into());
+        self
+    }
+
+    /// Fail the syscalls `filter` rejects without running them
+    pub fn syscall_filter(mut self, filter: SyscallFilter) -> Self {
+        self.syscall_filter = filter;
+        self
+    }
+
+    /// Only allow `AF_UNIX` sockets
+    pub fn no_network(mut self, disabled: bool) -> Self {
+        self.no_network = disabled;
+        self
+    }
+
+    /// Log the paths the command accesses without letting it modify them
+    ///
+    /// The paths are listed by `accessed_paths()` once it has exited.
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// Record the files the command creates, modifies and deletes in virtual
+    /// mounts
+    ///
+    /// They are listed in `SandboxExit::changed_files`, with their final size
+    /// and hash.
+    pub fn track_changes(mut self, enabled: bool) -> Self {
+        self.track_changes = enabled;
+        self
+    }
+
+    /// Limit the number of virtual files each process can have open at once
+    pub fn max_open_files(mut self, max_open_files: usize) -> Self {
+        self.max_open_files = max_open_files;
+        self
+    }
+
+    /// Terminate the command once `timeout` elapses
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limit the CPU time of each of the command's processes
+    pub fn cpu_limit(mut self, limit: Option<Duration>) -> Self {
+        self.cpu_limit = limit;
+        self
+    }
+
+    /// Cap the memory of the command and its children, in bytes
+    pub fn memory_limit(mut self, limit: Option<u64>) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Set up the sandbox and spawn `program` with `args` in it
+    ///
+    /// This can only be called once per process.
+    pub async fn spawn<I, S>(self, program: impl AsRef<OsStr>, args: I) -> Result<SandboxHandle>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let workdir = match &self.workdir {
+            Some(workdir) => Some(resolve_workdir(&self.mount_table, workdir).await?),
+            None => None,
+        };
+
+        init_mount_table(self.mount_table);
+        init_fd_tables();
+        if let Some((_, Some(cwd))) = &workdir {
+            init_workdir(cwd.clone());
+        }
+        match self.strace {
+            Some((output, format)) => init_strace_output(output, format),
+            None => init_strace(false),
+        }
+        init_max_open_files(self.max_open_files);
+        init_syscall_filter(self.syscall_filter);
+        init_no_network(self.no_network);
+        init_dry_run(self.dry_run);
+        init_change_tracking(self.track_changes);
+        init_cpu_limit(self.cpu_limit);
+        init_memory_limit(self.memory_limit).context("Failed to set up the memory limit")?;
+
+        let mut cmd = Command::new(program);
+        for arg in args {
+            cmd.arg(arg);
+        }
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        if let Some((host_dir, _)) = workdir {
+            cmd.current_dir(host_dir);
+        }
+
+        let tracer = TracerBuilder::<Sandbox>::new(cmd)
+            .spawn()
+            .await
+            .map_err(|e| anyhow!("Failed to spawn the sandboxed command: {}", e))?;
+        Ok(SandboxHandle {
+            tracer,
+            timeout: self.timeout,
+        })
+    }
+}
+
+/// A command running in the sandbox
+pub struct SandboxHandle {
+    tracer: Tracer<<Sandbox as Tool>::GlobalState>,
+    timeout: Option<Duration>,
+}
+
+impl SandboxHandle {
+    /// Kill the command and every process it started
+    pub fn kill(&self) {
+        signal_guests(libc::SIGKILL);
+    }
+
+    /// Wait for the command to exit, and tear the sandbox down
+    ///
+    /// The command is terminated if it outlives the timeout. Virtual files it
+    /// left open are reported in `SandboxExit::open_fds` and then closed, so
+    /// that their buffered writes reach their VFS before the changed files are
+    /// collected.
+    pub async fn wait(self) -> Result<SandboxExit> {
+        let (result, timed_out) = wait_with_timeout(self.tracer.wait(), self.timeout).await;
+        let (status, _) =
+            result.map_err(|e| anyhow!("Failed to wait for the sandboxed command: {}", e))?;
+        let out_of_memory = memory_limit_exceeded();
+        release_memory_limit();
+
+        close_strace_output().context("Failed to write strace output")?;
+        let open_fds = open_fds_at_exit();
+        close_virtual_files().await;
+        let changed_files = if is_tracking_changes() {
+            changed_files().await
+        } else {
+            Vec::new()
+        };
+
+        Ok(SandboxExit {
+            status,
+            timed_out,
+            out_of_memory,
+            open_fds,
+            changed_files,
+        })
+    }
+}
+
+/// How a sandboxed command exited
+#[derive(Debug)]
+pub struct SandboxExit {
+    /// Exit status of the command
+    pub status: ExitStatus,
+    /// Whether the command was terminated for running past the timeout
+    pub timed_out: bool,
+    /// Whether a process of the command was killed for exceeding the memory limit
+    pub out_of_memory: bool,
+    /// Virtual FDs the command's processes had open when they exited
+    pub open_fds: Vec<OpenFd>,
+    /// Files the command changed in virtual mounts, with `track_changes()`
+    pub changed_files: Vec<ChangedFile>,
+}
+
+impl SandboxExit {
+    /// Whether the command exited with status 0
+    pub fn success(&self) -> bool {
+        self.status == ExitStatus::Exited(0)
+    }
+}
+
+/// Resolve `workdir` to the host directory the command starts in, and the working
+/// directory the guest sees there if that is another one.
+///
+/// Relative paths are taken from the current directory. Under a mount, the
+/// command starts in the host directory the path translates to. A directory in a
+/// virtual mount has no host directory, so the command starts in `/` with the
+/// sandbox reporting it as its working directory.
+async fn resolve_workdir(
+    mount_table: &MountTable,
+    workdir: &Path,
+) -> Result<(PathBuf, Option<GuestCwd>)> {
+    let workdir = std::path::absolute(workdir)
+        .with_context(|| format!("Invalid working directory {}", workdir.display()))?;
+
+    let host_dir = match mount_table.resolve(&workdir) {
+        Some((vfs, _, _)) if vfs.is_virtual() => {
+            let stat = vfs
+                .stat(&workdir)
+                .await
+                .map_err(|e| anyhow!("Working directory {} not found: {}", workdir.display(), e))?;
+            if stat.st_mode & libc::S_IFMT != libc::S_IFDIR {
+                bail!("Working directory {} is not a directory", workdir.display());
+            }
+            return Ok((PathBuf::from("/"), Some(GuestCwd::Virtual(workdir))));
+        }
+        Some((_, translated, _)) => translated,
+        None => workdir.clone(),
+    };
+
+    let metadata = std::fs::metadata(&host_dir)
+        .with_context(|| format!("Working directory {} not found", workdir.display()))?;
+    if !metadata.is_dir() {
+        bail!("Working directory {} is not a directory", workdir.display());
+    }
+    let cwd = (host_dir != workdir).then_some(GuestCwd::Translated(workdir));
+    Ok((host_dir, cwd))
+}
@@ -0,0 +1,307 @@
+//! Summary of the files a guest changed in virtual mounts.
+//!
+//! Before a system call that creates, writes, truncates, renames or removes a
+//! path in a virtual mount runs, the path is recorded along with whether it
+//! existed then. Once the guest has exited, `changed_files()` compares that with
+//! what is there now, so that a file created and removed again during the run
+//! is left out, and hashes the final content of the files.
+//!
+//! Paths are recorded as the guest named them, made absolute against virtual
+//! directory FDs and working directories. Entries moved along with a renamed
+//! directory are only reported under the directory's names.
+
+use crate::{
+    sandbox::{get_mount_table, Sandbox},
+    syscall::{memory::read_path, open::resolve_dirfd},
+    vfs::{fdtable::FdTable, mount::MountTable, Vfs},
+};
+use reverie::{
+    syscalls::{PathPtr, Syscall},
+    Guest,
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Global flag to record the paths the guest changes
+static TRACK_CHANGES: AtomicBool = AtomicBool::new(false);
+
+/// Paths changed by the guest, and whether each existed before its first change
+static CHANGED_PATHS: Mutex<BTreeMap<PathBuf, bool>> = Mutex::new(BTreeMap::new());
+
+/// Size of the reads hashing the content of changed files
+const HASH_CHUNK: usize = 1024 * 1024;
+
+/// How a file in a virtual mount changed over a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// The path did not exist before the run
+    Created,
+    /// The path existed before the run and still does
+    Modified,
+    /// The path existed before the run and is gone
+    Deleted,
+}
+
+/// A path the guest changed in a virtual mount
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangedFile {
+    /// Absolute path in the sandbox
+    pub path: PathBuf,
+    pub change: ChangeKind,
+    /// Final size, `None` for deleted paths
+    pub size: Option<u64>,
+    /// BLAKE3 hash of the final content in hex, for regular files only
+    pub hash: Option<String>,
+}
+
+/// Initialize the tracking of changed files
+///
+/// This must be called before spawning the traced process.
+pub fn init_change_tracking(enabled: bool) {
+    TRACK_CHANGES.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if changed files are tracked
+pub(crate) fn is_tracking_changes() -> bool {
+    TRACK_CHANGES.load(Ordering::Relaxed)
+}
+
+fn lock_changed_paths() -> std::sync::MutexGuard<'static, BTreeMap<PathBuf, bool>> {
+    CHANGED_PATHS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The paths a system call changes, with the dirfd each one is relative to,
+/// and whether it only counts as a change if the path doesn't exist yet
+fn changed_path_args(syscall: &Syscall) -> Vec<(i32, Option<PathPtr<'_>>, bool)> {
+    match syscall {
+        Syscall::Openat(args) => {
+            let flags = args.flags().bits();
+            if flags & libc::O_TRUNC != 0 {
+                vec![(args.dirfd(), args.path(), false)]
+            } else if flags & libc::O_CREAT != 0 {
+                vec![(args.dirfd(), args.path(), true)]
+            } else {
+                Vec::new()
+            }
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Mkdir(args) => vec![(libc::AT_FDCWD, args.path(), false)],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Rmdir(args) => vec![(libc::AT_FDCWD, args.path(), false)],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Unlink(args) => vec![(libc::AT_FDCWD, args.path(), false)],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Symlink(args) => vec![(libc::AT_FDCWD, args.linkpath(), false)],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Rename(args) => vec![
+            (libc::AT_FDCWD, args.oldpath(), false),
+            (libc::AT_FDCWD, args.newpath(), false),
+        ],
+        Syscall::Mkdirat(args) => vec![(args.dirfd(), args.path(), false)],
+        Syscall::Unlinkat(args) => vec![(args.dirfd(), args.path(), false)],
+        Syscall::Symlinkat(args) => vec![(args.newdirfd(), args.linkpath(), false)],
+        Syscall::Renameat2(args) => vec![
+            (args.olddirfd(), args.oldpath(), false),
+            (args.newdirfd(), args.newpath(), false),
+        ],
+        Syscall::Linkat(args) => vec![(args.newdirfd(), args.newpath(), false)],
+        Syscall::Truncate(args) => vec![(libc::AT_FDCWD, args.path(), false)],
+        _ => Vec::new(),
+    }
+}
+
+/// The FD whose file a system call writes to, if any
+fn written_fd(syscall: &Syscall) -> Option<i32> {
+    match syscall {
+        Syscall::Write(args) => Some(args.fd()),
+        Syscall::Pwrite64(args) => Some(args.fd()),
+        Syscall::Ftruncate(args) => Some(args.fd()),
+        Syscall::Fallocate(args) => Some(args.fd()),
+        Syscall::CopyFileRange(args) => Some(args.fd_out()),
+        Syscall::Sendfile(args) => Some(args.out_fd()),
+        Syscall::Writev(_) | Syscall::Pwritev(_) => Some(syscall.into_parts().1.arg0 as i32),
+        _ => None,
+    }
+}
+
+/// Record the paths in virtual mounts a system call is about to change
+pub(crate) async fn record_changes<T: Guest<Sandbox>>(
+    guest: &mut T,
+    syscall: &Syscall,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) {
+    let mut paths = Vec::new();
+    for (dirfd, path_addr, only_new) in changed_path_args(syscall) {
+        let Some(mut path) = path_addr.and_then(|addr| read_path(guest, addr).ok()) else {
+            continue;
+        };
+        if resolve_dirfd(dirfd, &mut path, fd_table).is_ok() && path.is_absolute() {
+            paths.push((path, only_new));
+        }
+    }
+    if let Some(path) = written_fd(syscall)
+        .and_then(|fd| fd_table.get(fd))
+        .filter(|entry| entry.kernel_fd().is_none())
+        .and_then(|entry| entry.path().cloned())
+    {
+        paths.push((path, false));
+    }
+
+    for (path, only_new) in paths {
+        if lock_changed_paths().contains_key(&path) {
+            continue;
+        }
+        let Some((vfs, _, false)) = mount_table.resolve(&path) else {
+            continue;
+        };
+        if !vfs.is_virtual() {
+            continue;
+        }
+        let existed = vfs.lstat(&path).await.is_ok();
+        if !(only_new && existed) {
+            lock_changed_paths().entry(path).or_insert(existed);
+        }
+    }
+}
+
+/// Collect the files the guest changed in virtual mounts, sorted by path
+///
+/// This is meant to be called at teardown, after the traced process has exited
+/// and its virtual files are closed, so that their writes have reached the VFS.
+pub async fn changed_files() -> Vec<ChangedFile> {
+    collect_changed_files(get_mount_table()).await
+}
+
+async fn collect_changed_files(mount_table: &MountTable) -> Vec<ChangedFile> {
+    let recorded: Vec<(PathBuf, bool)> = lock_changed_paths()
+        .iter()
+        .map(|(path, existed)| (path.clone(), *existed))
+        .collect();
+
+    let mut changes = Vec::new();
+    for (path, existed) in recorded {
+        let Some((vfs, _, _)) = mount_table.resolve(&path) else {
+            continue;
+        };
+        let stat = vfs.lstat(&path).await.ok();
+        let change = match (existed, &stat) {
+            (false, None) => continue,
+            (false, Some(_)) => ChangeKind::Created,
+            (true, Some(_)) => ChangeKind::Modified,
+            (true, None) => ChangeKind::Deleted,
+        };
+        let hash = match &stat {
+            Some(stat) if stat.st_mode & libc::S_IFMT == libc::S_IFREG => {
+                hash_file(&*vfs, &path).await
+            }
+            _ => None,
+        };
+        changes.push(ChangedFile {
+            path,
+            change,
+            size: stat.map(|stat| stat.st_size as u64),
+            hash,
+        });
+    }
+    changes
+}
+
+/// Hash the content of the file at `path` with BLAKE3, `None` if it can't be read
+async fn hash_file(vfs: &dyn Vfs, path: &Path) -> Option<String> {
+    let file = vfs.open(path, libc::O_RDONLY, 0).await.ok()?;
+    let mut hasher = blake3::Hasher::new();
+    let mut offset = 0;
+    let result = loop {
+        match vfs.read(&file, offset, HASH_CHUNK).await {
+            Ok(data) if data.is_empty() => break Some(hasher.finalize().to_hex().to_string()),
+            Ok(data) => {
+                hasher.update(&data);
+                offset += data.len() as u64;
+            }
+            Err(_) => break None,
+        }
+    };
+    file.close().await.ok();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::memory::MemoryVfs;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_changed_files() {
+        let mount_point = PathBuf::from("/changes");
+        let vfs = MemoryVfs::new(mount_point.clone());
+        for path in ["/changes/kept.txt", "/changes/removed.txt"] {
+            let file = vfs
+                .open(Path::new(path), libc::O_WRONLY | libc::O_CREAT, 0o644)
+                .await
+                .unwrap();
+            vfs.write(&file, 0, b"old").await.unwrap();
+            file.close().await.unwrap();
+        }
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs.clone()));
+
+        for (path, existed) in [
+            ("/changes/kept.txt", true),
+            ("/changes/removed.txt", true),
+            ("/changes/new.txt", false),
+            ("/changes/temporary.txt", false),
+        ] {
+            lock_changed_paths().insert(PathBuf::from(path), existed);
+        }
+        let file = vfs
+            .open(
+                Path::new("/changes/new.txt"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        vfs.write(&file, 0, b"hello").await.unwrap();
+        file.close().await.unwrap();
+        vfs.unlink(Path::new("/changes/removed.txt")).await.unwrap();
+
+        let changes = collect_changed_files(&mount_table).await;
+        let hello = blake3::hash(b"hello").to_hex().to_string();
+        let old = blake3::hash(b"old").to_hex().to_string();
+        assert_eq!(
+            changes,
+            vec![
+                ChangedFile {
+                    path: PathBuf::from("/changes/kept.txt"),
+                    change: ChangeKind::Modified,
+                    size: Some(3),
+                    hash: Some(old),
+                },
+                ChangedFile {
+                    path: PathBuf::from("/changes/new.txt"),
+                    change: ChangeKind::Created,
+                    size: Some(5),
+                    hash: Some(hello),
+                },
+                ChangedFile {
+                    path: PathBuf::from("/changes/removed.txt"),
+                    change: ChangeKind::Deleted,
+                    size: None,
+                    hash: None,
+                },
+            ]
+        );
+    }
+}
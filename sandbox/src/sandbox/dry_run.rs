@@ -0,0 +1,229 @@
+//! Dry runs, which audit the paths a guest touches without letting it change them.
+//!
+//! Every path a system call refers to is logged to stderr along with its
+//! translation through the mount table, and recorded for `accessed_paths()`.
+//! Calls that would modify the filesystem at their paths are logged and report
+//! success without running. Files opened for writing are opened as `/dev/null`
+//! instead, so that writes to them succeed and go nowhere. Everything else,
+//! reads included, runs as usual.
+//!
+//! Paths are recorded as the guest passed them, so relative paths stay relative
+//! to the directory they were used from.
+
+use crate::{
+    sandbox::{strace::syscall_paths, Sandbox},
+    syscall::memory::{push_paths, read_path},
+    vfs::{
+        fdtable::{FdEntry, FdTable},
+        mount::MountTable,
+    },
+};
+use reverie::{
+    syscalls::{Syscall, SyscallArgs, SyscallInfo},
+    Error, Guest,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Global flag to keep the guest from modifying the filesystem
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Paths accessed by the guest, and whether it tried to modify them
+static ACCESSED_PATHS: Mutex<BTreeMap<PathBuf, bool>> = Mutex::new(BTreeMap::new());
+
+/// `openat` flags that make it modify the file it opens
+const WRITE_FLAGS: i32 = libc::O_WRONLY | libc::O_RDWR | libc::O_CREAT | libc::O_TRUNC;
+
+/// `openat` flags that can't be used to open `/dev/null` in place of a file
+const CREATE_FLAGS: i32 = libc::O_CREAT | libc::O_EXCL | libc::O_TRUNC | libc::O_TMPFILE;
+
+/// A path the guest accessed during a dry run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessedPath {
+    /// The path as passed by the guest
+    pub path: PathBuf,
+    /// Whether the guest tried to modify it
+    pub modified: bool,
+}
+
+/// Initialize dry-run mode
+///
+/// This must be called before spawning the traced process.
+pub fn init_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if dry-run mode is enabled
+pub(crate) fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Collect the distinct paths the guest accessed, sorted by path
+///
+/// This is meant to be called at teardown, after the traced process has exited.
+pub fn accessed_paths() -> Vec<AccessedPath> {
+    lock_accessed_paths()
+        .iter()
+        .map(|(path, modified)| AccessedPath {
+            path: path.clone(),
+            modified: *modified,
+        })
+        .collect()
+}
+
+/// Format a summary of the paths accessed in a dry run, one line per path
+pub fn format_accessed_paths(paths: &[AccessedPath]) -> String {
+    let mut summary = format!(
+        "{} path{} accessed in the dry run",
+        paths.len(),
+        if paths.len() == 1 { "" } else { "s" }
+    );
+    for path in paths {
+        summary.push_str(&format!(
+            "\n  {} {}",
+            if path.modified { "modify" } else { "read  " },
+            path.path.display()
+        ));
+    }
+    summary
+}
+
+fn lock_accessed_paths() -> std::sync::MutexGuard<'static, BTreeMap<PathBuf, bool>> {
+    ACCESSED_PATHS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Record an access to `path`, which stays marked as modified once it is
+fn record_access(path: &Path, modified: bool) {
+    *lock_accessed_paths().entry(path.to_path_buf()).or_default() |= modified;
+}
+
+/// Check whether the system call modifies the filesystem at its paths
+fn modifies(syscall: &Syscall) -> bool {
+    match syscall {
+        Syscall::Openat(args) => args.flags().bits() & WRITE_FLAGS != 0,
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Mkdir(_)
+        | Syscall::Rmdir(_)
+        | Syscall::Unlink(_)
+        | Syscall::Symlink(_)
+        | Syscall::Rename(_)
+        | Syscall::Chmod(_) => true,
+        Syscall::Mkdirat(_)
+        | Syscall::Unlinkat(_)
+        | Syscall::Symlinkat(_)
+        | Syscall::Renameat2(_)
+        | Syscall::Linkat(_)
+        | Syscall::Truncate(_)
+        | Syscall::Fchmodat(_)
+        | Syscall::Utimensat(_) => true,
+        _ => false,
+    }
+}
+
+/// Log and record the paths of a system call, and keep it from modifying them.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub(crate) async fn handle_dry_run<T: Guest<Sandbox>>(
+    guest: &mut T,
+    syscall: Syscall,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let pid = guest.pid().as_raw();
+    let modified = modifies(&syscall);
+    let paths: Vec<PathBuf> = syscall_paths(&syscall)
+        .into_iter()
+        .filter_map(|path_addr| read_path(guest, path_addr).ok())
+        .collect();
+
+    for path in &paths {
+        let translated = match mount_table.resolve(path) {
+            Some((_vfs, translated, _read_only)) if translated != *path => {
+                format!(" ({})", translated.display())
+            }
+            _ => String::new(),
+        };
+        eprintln!(
+            "dry-run: [{}] {}{:?} {}{}",
+            pid,
+            if modified { "would " } else { "" },
+            syscall.number(),
+            path.display(),
+            translated
+        );
+        record_access(path, modified);
+    }
+
+    if !modified {
+        return Ok(None);
+    }
+    let Syscall::Openat(args) = syscall else {
+        return Ok(Some(0));
+    };
+
+    // Open /dev/null in place of the file, with the access mode the guest asked for
+    let flags = args.flags().bits();
+    let null_path = push_paths(guest, &[c"/dev/null"]).await?.into_iter().next();
+    let (num, null_args) =
+        Syscall::Openat(args.with_dirfd(libc::AT_FDCWD).with_path(null_path)).into_parts();
+    let kernel_fd = guest
+        .inject(Syscall::Other(
+            num,
+            SyscallArgs {
+                arg2: (flags & !CREATE_FLAGS) as usize,
+                ..null_args
+            },
+        ))
+        .await?;
+    if kernel_fd < 0 {
+        return Ok(Some(kernel_fd));
+    }
+
+    let entry = FdEntry::Passthrough {
+        kernel_fd: kernel_fd as i32,
+        flags,
+        path: paths.into_iter().next(),
+    };
+    Ok(Some(fd_table.allocate(entry) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_accessed_paths() {
+        record_access(Path::new("/agent/out.txt"), false);
+        record_access(Path::new("/etc/passwd"), false);
+        record_access(Path::new("/agent/out.txt"), true);
+        record_access(Path::new("/agent/out.txt"), false);
+
+        let paths = accessed_paths();
+        assert_eq!(
+            paths,
+            vec![
+                AccessedPath {
+                    path: PathBuf::from("/agent/out.txt"),
+                    modified: true,
+                },
+                AccessedPath {
+                    path: PathBuf::from("/etc/passwd"),
+                    modified: false,
+                },
+            ]
+        );
+        assert_eq!(
+            format_accessed_paths(&paths),
+            "2 paths accessed in the dry run\n  modify /agent/out.txt\n  read   /etc/passwd"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Allow/deny filtering of system calls.
+//!
+//! Syscalls rejected by the filter fail with the configured errno (`EPERM` by
+//! default) before any handler runs, so they never reach a VFS or the kernel.
+
+use reverie::{syscalls::Sysno, Errno};
+use std::collections::HashSet;
+
+/// Errno names accepted by `SyscallFilter::set_errno()`
+const ERRNO_NAMES: &[(&str, i32)] = &[
+    ("EPERM", libc::EPERM),
+    ("ENOENT", libc::ENOENT),
+    ("EIO", libc::EIO),
+    ("EACCES", libc::EACCES),
+    ("EINVAL", libc::EINVAL),
+    ("EROFS", libc::EROFS),
+    ("ENOSYS", libc::ENOSYS),
+    ("EOPNOTSUPP", libc::EOPNOTSUPP),
+    ("ENOTSUP", libc::ENOTSUP),
+    ("ENETUNREACH", libc::ENETUNREACH),
+    ("ECONNREFUSED", libc::ECONNREFUSED),
+];
+
+/// A set of syscalls the guest is not allowed to make
+#[derive(Debug, Clone)]
+pub struct SyscallFilter {
+    /// Syscalls that are always rejected
+    denied: HashSet<Sysno>,
+    /// If set, only these syscalls are let through
+    allowed: Option<HashSet<Sysno>>,
+    /// Error returned for rejected syscalls
+    errno: Errno,
+}
+
+impl Default for SyscallFilter {
+    fn default() -> Self {
+        Self {
+            denied: HashSet::new(),
+            allowed: None,
+            errno: Errno::EPERM,
+        }
+    }
+}
+
+impl SyscallFilter {
+    /// Create a filter that lets every syscall through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject the syscall called `name`
+    pub fn deny(&mut self, name: &str) -> Result<(), String> {
+        self.denied.insert(parse_sysno(name)?);
+        Ok(())
+    }
+
+    /// Let the syscall called `name` through, rejecting any syscall that was
+    /// not allowed this way
+    ///
+    /// Denied syscalls are rejected even if they are also allowed.
+    pub fn allow(&mut self, name: &str) -> Result<(), String> {
+        let sysno = parse_sysno(name)?;
+        self.allowed.get_or_insert_with(HashSet::new).insert(sysno);
+        Ok(())
+    }
+
+    /// Set the error returned for rejected syscalls, either by name (`EACCES`)
+    /// or by number
+    pub fn set_errno(&mut self, errno: &str) -> Result<(), String> {
+        let value = ERRNO_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(errno))
+            .map(|(_, value)| *value)
+            .or_else(|| errno.parse::<i32>().ok().filter(|value| *value > 0));
+        match value {
+            Some(value) => {
+                self.errno = Errno::new(value);
+                Ok(())
+            }
+            None => Err(format!(
+                "Unknown errno '{}'. Expected a positive number or a name such as EPERM.",
+                errno
+            )),
+        }
+    }
+
+    /// Whether the filter lets every syscall through
+    pub fn is_empty(&self) -> bool {
+        self.denied.is_empty() && self.allowed.is_none()
+    }
+
+    /// Return the error to fail `sysno` with, or `None` if it is let through
+    pub fn check(&self, sysno: Sysno) -> Option<Errno> {
+        let allowed = self
+            .allowed
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&sysno));
+        if allowed && !self.denied.contains(&sysno) {
+            None
+        } else {
+            Some(self.errno)
+        }
+    }
+}
+
+fn parse_sysno(name: &str) -> Result<Sysno, String> {
+    name.trim()
+        .parse::<Sysno>()
+        .map_err(|_| format!("Unknown syscall '{}'.", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deny_syscall() {
+        let mut filter = SyscallFilter::new();
+        assert!(filter.is_empty());
+        assert_eq!(filter.check(Sysno::openat), None);
+
+        filter.deny("openat").unwrap();
+        assert!(!filter.is_empty());
+        assert_eq!(filter.check(Sysno::openat), Some(Errno::EPERM));
+        assert_eq!(filter.check(Sysno::read), None);
+
+        assert!(filter.deny("not_a_syscall").is_err());
+    }
+
+    #[test]
+    fn test_allow_only() {
+        let mut filter = SyscallFilter::new();
+        filter.allow("read").unwrap();
+        filter.allow("write").unwrap();
+        filter.deny("write").unwrap();
+
+        assert_eq!(filter.check(Sysno::read), None);
+        assert_eq!(filter.check(Sysno::write), Some(Errno::EPERM));
+        assert_eq!(filter.check(Sysno::socket), Some(Errno::EPERM));
+    }
+
+    #[test]
+    fn test_set_errno() {
+        let mut filter = SyscallFilter::new();
+        filter.deny("socket").unwrap();
+
+        filter.set_errno("eacces").unwrap();
+        assert_eq!(filter.check(Sysno::socket), Some(Errno::EACCES));
+
+        filter.set_errno("38").unwrap();
+        assert_eq!(filter.check(Sysno::socket), Some(Errno::ENOSYS));
+
+        assert!(filter.set_errno("EBOGUS").is_err());
+        assert!(filter.set_errno("0").is_err());
+    }
+}
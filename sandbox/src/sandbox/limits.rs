@@ -0,0 +1,260 @@
+//! Wall-clock time, CPU time and memory limits for the sandboxed command.
+//!
+//! Memory is capped with a cgroup v2 group when running as root, so that the
+//! kernel's OOM killer enforces it, and with `RLIMIT_AS` otherwise, which makes
+//! allocations beyond the limit fail with `ENOMEM`.
+
+use crate::sandbox::guest_pids;
+use std::{
+    fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// How long the guest gets to exit after `SIGTERM` before it is killed
+pub const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// CPU time limit of each guest process in seconds, or 0 for none
+static CPU_LIMIT_SECS: AtomicU64 = AtomicU64::new(0);
+
+/// Address space limit of each guest process in bytes, or 0 for none
+static ADDRESS_SPACE_LIMIT: AtomicU64 = AtomicU64::new(0);
+
+/// Cgroup the guest processes are moved into, if memory is capped that way
+static MEMORY_CGROUP: Mutex<Option<MemoryCgroup>> = Mutex::new(None);
+
+/// Whether an allocation of a guest failed for lack of memory
+static OUT_OF_MEMORY: AtomicBool = AtomicBool::new(false);
+
+/// Root of the cgroup v2 hierarchy
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Initialize the CPU time limit of each guest process
+///
+/// Processes get `SIGXCPU` once they used up `limit`, and `SIGKILL` a second
+/// later. The limit is rounded up to whole seconds.
+/// This must be called before spawning the traced process.
+pub fn init_cpu_limit(limit: Option<Duration>) {
+    let secs = limit.map_or(0, |limit| limit.as_secs_f64().ceil().max(1.0) as u64);
+    CPU_LIMIT_SECS.store(secs, Ordering::Relaxed);
+}
+
+/// Initialize the memory limit of the guest
+///
+/// As root, with the cgroup v2 memory controller available, all guest
+/// processes together are limited to `limit` bytes and killed by the OOM
+/// killer beyond it. Otherwise, each process's address space is limited to
+/// `limit` bytes.
+/// This must be called before spawning the traced process.
+pub fn init_memory_limit(limit: Option<u64>) -> io::Result<()> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    // SAFETY: geteuid is always safe
+    if unsafe { libc::geteuid() } == 0 && MemoryCgroup::is_supported() {
+        *MEMORY_CGROUP.lock().unwrap() = Some(MemoryCgroup::create(limit)?);
+    } else {
+        ADDRESS_SPACE_LIMIT.store(limit, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Check whether the guest ran out of memory because of the memory limit
+pub fn memory_limit_exceeded() -> bool {
+    let oom_killed = MEMORY_CGROUP
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|cgroup| cgroup.oom_kills() > 0);
+    oom_killed || OUT_OF_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Remove the cgroup set up by `init_memory_limit()`, if any
+///
+/// This must be called once the traced process has exited.
+pub fn release_memory_limit() {
+    if let Some(cgroup) = MEMORY_CGROUP.lock().unwrap().take() {
+        cgroup.remove();
+    }
+}
+
+/// Check if allocations are limited with `RLIMIT_AS`
+pub(crate) fn is_address_space_limited() -> bool {
+    ADDRESS_SPACE_LIMIT.load(Ordering::Relaxed) > 0
+}
+
+/// Record that an allocation of the guest failed with `ENOMEM`
+pub(crate) fn record_out_of_memory() {
+    OUT_OF_MEMORY.store(true, Ordering::Relaxed);
+}
+
+/// Apply the CPU time and memory limits to process `pid`
+pub(crate) fn apply_limits(pid: i32) {
+    let secs = CPU_LIMIT_SECS.load(Ordering::Relaxed);
+    if secs > 0 {
+        set_rlimit(pid, libc::RLIMIT_CPU, secs, secs + 1);
+    }
+
+    let bytes = ADDRESS_SPACE_LIMIT.load(Ordering::Relaxed);
+    if bytes > 0 {
+        set_rlimit(pid, libc::RLIMIT_AS, bytes, bytes);
+    }
+
+    if let Some(cgroup) = MEMORY_CGROUP.lock().unwrap().as_ref() {
+        cgroup.add(pid);
+    }
+}
+
+fn set_rlimit(pid: i32, resource: libc::__rlimit_resource_t, soft: u64, hard: u64) {
+    let limit = libc::rlimit {
+        rlim_cur: soft,
+        rlim_max: hard,
+    };
+    // SAFETY: prlimit only reads the new limit, and the old one isn't requested
+    unsafe { libc::prlimit(pid, resource, &limit, std::ptr::null_mut()) };
+}
+
+/// A cgroup v2 group capping the memory of the processes in it
+struct MemoryCgroup {
+    path: PathBuf,
+}
+
+impl MemoryCgroup {
+    /// Check that the memory controller is enabled for new cgroups
+    fn is_supported() -> bool {
+        fs::read_to_string(Path::new(CGROUP_ROOT).join("cgroup.subtree_control"))
+            .is_ok_and(|controllers| controllers.split_whitespace().any(|c| c == "memory"))
+    }
+
+    /// Create a cgroup limited to `limit` bytes, without swap
+    fn create(limit: u64) -> io::Result<Self> {
+        let path = Path::new(CGROUP_ROOT).join(format!("agentfs-{}", std::process::id()));
+        fs::create_dir(&path)?;
+        let cgroup = Self { path };
+
+        if let Err(e) = fs::write(cgroup.path.join("memory.max"), limit.to_string()) {
+            cgroup.remove();
+            return Err(e);
+        }
+        // Not every kernel accounts for swap, in which case there is none to disable
+        fs::write(cgroup.path.join("memory.swap.max"), "0").ok();
+        Ok(cgroup)
+    }
+
+    /// Move process `pid` into the cgroup
+    fn add(&self, pid: i32) {
+        fs::write(self.path.join("cgroup.procs"), pid.to_string()).ok();
+    }
+
+    /// Number of processes the OOM killer killed in the cgroup
+    fn oom_kills(&self) -> u64 {
+        fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| oom_kills(&events))
+            .unwrap_or(0)
+    }
+
+    /// Remove the cgroup, which only works once its processes have exited
+    fn remove(self) {
+        fs::remove_dir(&self.path).ok();
+    }
+}
+
+/// Parse the `oom_kill` count of a cgroup's `memory.events` file
+fn oom_kills(events: &str) -> Option<u64> {
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+/// Wait for the traced command to finish, terminating it once `timeout` elapses
+///
+/// `wait` is the tracer's wait future. On timeout, the guest processes get
+/// `SIGTERM`, and `SIGKILL` if they are still running after `KILL_GRACE_PERIOD`.
+/// Returns the output of `wait` along with whether the command timed out.
+pub async fn wait_with_timeout<F: Future>(wait: F, timeout: Option<Duration>) -> (F::Output, bool) {
+    tokio::pin!(wait);
+
+    let Some(timeout) = timeout else {
+        return (wait.await, false);
+    };
+    if let Ok(output) = tokio::time::timeout(timeout, &mut wait).await {
+        return (output, false);
+    }
+
+    signal_guests(libc::SIGTERM);
+    if let Ok(output) = tokio::time::timeout(KILL_GRACE_PERIOD, &mut wait).await {
+        return (output, true);
+    }
+
+    signal_guests(libc::SIGKILL);
+    (wait.await, true)
+}
+
+/// Send `signal` to every guest process that is still running
+pub(crate) fn signal_guests(signal: i32) {
+    for pid in guest_pids() {
+        if is_traced_by_self(pid) {
+            // SAFETY: kill has no memory safety requirements
+            unsafe { libc::kill(pid, signal) };
+        }
+    }
+}
+
+/// Whether process `pid` is traced by one of our threads
+///
+/// This tells running guests apart from processes that reused the pid of a
+/// guest that has exited.
+fn is_traced_by_self(pid: i32) -> bool {
+    let Ok(status) = std::fs::read_to_string(format!("/proc/{}/status", pid)) else {
+        return false;
+    };
+    match tracer_pid(&status) {
+        Some(tracer) if tracer > 0 => Path::new(&format!("/proc/self/task/{}", tracer)).exists(),
+        _ => false,
+    }
+}
+
+/// Parse the `TracerPid` field of a `/proc/<pid>/status` file
+fn tracer_pid(status: &str) -> Option<i32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("TracerPid:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracer_pid() {
+        let status = "Name:\tsleep\nState:\tt (tracing stop)\nTracerPid:\t4242\nUid:\t0\n";
+        assert_eq!(tracer_pid(status), Some(4242));
+        assert_eq!(tracer_pid("Name:\tsleep\n"), None);
+    }
+
+    #[test]
+    fn test_oom_kills() {
+        let events = "low 0\nhigh 0\nmax 12\noom 1\noom_kill 1\noom_group_kill 0\n";
+        assert_eq!(oom_kills(events), Some(1));
+        assert_eq!(oom_kills("low 0\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_wait_with_timeout() {
+        let (output, timed_out) = wait_with_timeout(async { 42 }, None).await;
+        assert_eq!((output, timed_out), (42, false));
+
+        let (output, timed_out) =
+            wait_with_timeout(async { 42 }, Some(Duration::from_secs(10))).await;
+        assert_eq!((output, timed_out), (42, false));
+    }
+}
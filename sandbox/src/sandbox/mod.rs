@@ -1,13 +1,39 @@
 use crate::{
     syscall,
-    vfs::{fdtable::FdTable, mount::MountTable},
+    vfs::{
+        fdtable::{FdTable, GuestCwd, DEFAULT_MAX_OPEN_FILES},
+        mount::MountTable,
+    },
+};
+use reverie::{
+    syscalls::{Syscall, SyscallInfo},
+    Errno, Error, Guest, Tool,
 };
-use reverie::{syscalls::Syscall, Error, Guest, Tool};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Mutex, OnceLock,
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    Arc, Mutex, OnceLock,
+};
+
+mod builder;
+mod changes;
+mod dry_run;
+mod filter;
+mod limits;
+mod strace;
+
+pub use builder::{ExitStatus, SandboxBuilder, SandboxExit, SandboxHandle};
+pub use changes::{changed_files, init_change_tracking, ChangeKind, ChangedFile};
+pub use dry_run::{accessed_paths, format_accessed_paths, init_dry_run, AccessedPath};
+pub use filter::SyscallFilter;
+pub use limits::{
+    init_cpu_limit, init_memory_limit, memory_limit_exceeded, release_memory_limit,
+    wait_with_timeout, KILL_GRACE_PERIOD,
 };
+pub(crate) use limits::{is_address_space_limited, record_out_of_memory, signal_guests};
+pub use strace::{close_strace_output, init_strace, init_strace_output, StraceFormat};
+use strace::{SyscallTrace, TraceResult};
 
 /// Global mount table shared across all threads
 static MOUNT_TABLE: OnceLock<MountTable> = OnceLock::new();
@@ -15,8 +41,27 @@ static MOUNT_TABLE: OnceLock<MountTable> = OnceLock::new();
 /// Global FD tables, one per process (keyed by pid)
 static FD_TABLES: OnceLock<Mutex<HashMap<i32, FdTable>>> = OnceLock::new();
 
-/// Global flag to enable strace-like output
-static STRACE_ENABLED: AtomicBool = AtomicBool::new(false);
+/// Global umasks, one per process (keyed by pid)
+///
+/// Processes created with `CLONE_FS` share their umask with their parent, so the
+/// mask is shared between their entries.
+static UMASKS: OnceLock<Mutex<HashMap<i32, Arc<AtomicU32>>>> = OnceLock::new();
+
+/// Umask of processes that have not set one
+pub const DEFAULT_UMASK: u32 = 0o022;
+
+/// Global limit on the number of virtual FDs each process can have open
+static MAX_OPEN_FILES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_OPEN_FILES);
+
+/// Global working directory of the traced process as the guest sees it, where the
+/// kernel's differs
+static WORKDIR: OnceLock<GuestCwd> = OnceLock::new();
+
+/// Global flag to keep the guest off the network
+static NETWORK_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Global filter of the syscalls the guest may make
+static SYSCALL_FILTER: OnceLock<SyscallFilter> = OnceLock::new();
 
 /// Initialize the global mount table
 ///
@@ -41,16 +86,47 @@ pub fn init_fd_tables() {
         .expect("FD tables already initialized");
 }
 
-/// Initialize strace mode
+/// Initialize the limit on open virtual FDs per process
+///
+/// Opening or duplicating a virtual file beyond the limit fails with `EMFILE`.
+/// This must be called before spawning the traced process.
+pub fn init_max_open_files(max_open_files: usize) {
+    MAX_OPEN_FILES.store(max_open_files, Ordering::Relaxed);
+}
+
+/// Initialize the working directory the traced process starts in, as the guest sees it
+///
+/// This is only needed if the kernel's working directory of the spawned command is
+/// not that directory: a directory in a virtual mount has none, and one under a
+/// host-backed mount is the host directory behind it.
+/// This must be called before spawning the traced process.
+pub fn init_workdir(cwd: GuestCwd) {
+    WORKDIR
+        .set(cwd)
+        .expect("Working directory already initialized");
+}
+
+/// Initialize the global syscall filter
 ///
+/// Without a filter, every syscall is let through.
 /// This must be called before spawning the traced process.
-pub fn init_strace(enabled: bool) {
-    STRACE_ENABLED.store(enabled, Ordering::Relaxed);
+pub fn init_syscall_filter(filter: SyscallFilter) {
+    SYSCALL_FILTER
+        .set(filter)
+        .expect("Syscall filter already initialized");
 }
 
-/// Check if strace is enabled
-fn is_strace_enabled() -> bool {
-    STRACE_ENABLED.load(Ordering::Relaxed)
+/// Initialize network isolation
+///
+/// With networking disabled, the guest can only use `AF_UNIX` sockets.
+/// This must be called before spawning the traced process.
+pub fn init_no_network(disabled: bool) {
+    NETWORK_DISABLED.store(disabled, Ordering::Relaxed);
+}
+
+/// Check if networking is disabled
+pub(crate) fn is_network_disabled() -> bool {
+    NETWORK_DISABLED.load(Ordering::Relaxed)
 }
 
 /// Get or create an FD table for a specific process
@@ -58,7 +134,14 @@ fn get_fd_table(pid: i32) -> FdTable {
     let tables = FD_TABLES.get().expect("FD tables not initialized");
     let mut tables = tables.lock().unwrap();
 
-    tables.entry(pid).or_default().clone()
+    tables
+        .entry(pid)
+        .or_insert_with(|| {
+            let fd_table = FdTable::with_max_open_files(MAX_OPEN_FILES.load(Ordering::Relaxed));
+            fd_table.set_cwd(WORKDIR.get().cloned());
+            fd_table
+        })
+        .clone()
 }
 
 /// Insert an FD table for a specific process (used for fork/clone)
@@ -69,15 +152,125 @@ pub(crate) fn insert_fd_table(pid: i32, fd_table: FdTable) {
     tables.insert(pid, fd_table);
 }
 
-/// Format a syscall for strace-like output
-fn format_syscall(syscall: &Syscall) -> String {
-    // Using the Debug implementation as a starting point
-    format!("{:?}", syscall)
+/// Get or create the umask of a specific process
+fn umask_cell(pid: i32) -> Arc<AtomicU32> {
+    let umasks = UMASKS.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut umasks = umasks.lock().unwrap();
+
+    umasks
+        .entry(pid)
+        .or_insert_with(|| Arc::new(AtomicU32::new(DEFAULT_UMASK)))
+        .clone()
+}
+
+/// Get the umask of a process, which VFS creation paths apply to modes
+pub(crate) fn umask(pid: i32) -> u32 {
+    umask_cell(pid).load(Ordering::Relaxed)
+}
+
+/// Set the umask of a process, returning the previous one
+pub(crate) fn set_umask(pid: i32, mask: u32) -> u32 {
+    umask_cell(pid).swap(mask & 0o777, Ordering::Relaxed)
+}
+
+/// Give a new process the umask of its parent (used for fork/clone)
+///
+/// With `shared` (`CLONE_FS`), later changes by either process apply to both.
+pub(crate) fn inherit_umask(parent: i32, child: i32, shared: bool) {
+    let parent = umask_cell(parent);
+    let cell = if shared {
+        parent
+    } else {
+        Arc::new(AtomicU32::new(parent.load(Ordering::Relaxed)))
+    };
+
+    let umasks = UMASKS.get_or_init(|| Mutex::new(HashMap::new()));
+    umasks.lock().unwrap().insert(child, cell);
+}
+
+/// List the guest processes the sandbox has seen, including ones that exited
+pub(crate) fn guest_pids() -> Vec<i32> {
+    let tables = FD_TABLES.get().expect("FD tables not initialized");
+    let tables = tables.lock().unwrap();
+
+    let mut pids: Vec<i32> = tables.keys().copied().collect();
+    pids.sort_unstable();
+    pids
+}
+
+/// A virtual FD that was still open when its process exited
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenFd {
+    /// Process that owned the FD
+    pub pid: i32,
+    /// The virtual FD number
+    pub fd: i32,
+    /// Path the FD was opened with (empty if unknown)
+    pub path: PathBuf,
+}
+
+/// Collect the virtual FDs the sandboxed processes had open when they exited
+///
+/// The kernel closes a process's FDs when it exits, but not the virtual files
+/// behind them, so these include FDs a process simply left for the exit to
+/// close, as well as its standard streams if they are virtual. This is meant
+/// to be called at teardown, after the traced process has exited. FDs are
+/// sorted by pid and FD number.
+pub fn open_fds_at_exit() -> Vec<OpenFd> {
+    let tables = FD_TABLES.get().expect("FD tables not initialized");
+    let tables = tables.lock().unwrap();
+
+    collect_open_fds(&tables)
+}
+
+fn collect_open_fds(tables: &HashMap<i32, FdTable>) -> Vec<OpenFd> {
+    let mut pids: Vec<i32> = tables.keys().copied().collect();
+    pids.sort_unstable();
+
+    pids.into_iter()
+        .flat_map(|pid| {
+            tables[&pid]
+                .open_virtual_handles()
+                .into_iter()
+                .map(move |(fd, path)| OpenFd { pid, fd, path })
+        })
+        .collect()
+}
+
+/// Format a report of the virtual FDs open at exit, one line per FD
+pub fn format_open_fds(open_fds: &[OpenFd]) -> String {
+    let mut report = format!(
+        "{} virtual fd{} open at exit",
+        open_fds.len(),
+        if open_fds.len() == 1 { "" } else { "s" }
+    );
+    for open_fd in open_fds {
+        report.push_str(&format!(
+            "\n  [{}] fd {} -> {}",
+            open_fd.pid,
+            open_fd.fd,
+            open_fd.path.display()
+        ));
+    }
+    report
 }
 
-/// Format a syscall result for strace-like output
-fn format_result(value: i64) -> String {
-    format!("{}", value)
+/// Close the virtual files still open in the sandboxed processes
+///
+/// This flushes their buffered writes to the VFS, even if the processes were
+/// killed. It is meant to be called at teardown, after `open_fds_at_exit()`.
+pub async fn close_virtual_files() {
+    let released: Vec<_> = {
+        let tables = FD_TABLES.get().expect("FD tables not initialized");
+        let tables = tables.lock().unwrap();
+        tables.values().flat_map(FdTable::release_all).collect()
+    };
+
+    for (entry, last) in released {
+        if let (Some(file_ops), true) = (entry.file_ops(), last) {
+            file_ops.close().await.ok();
+        }
+    }
 }
 
 /// The Sandbox tool
@@ -101,25 +294,57 @@ impl Tool for Sandbox {
         let pid = guest.pid().as_raw();
         let fd_table = get_fd_table(pid);
 
-        if is_strace_enabled() {
-            eprintln!("[{}] {}", pid, format_syscall(&syscall));
+        let trace = SyscallTrace::start(guest, &syscall, mount_table).await;
+
+        if let Some(errno) = SYSCALL_FILTER
+            .get()
+            .and_then(|filter| filter.check(syscall.number()))
+        {
+            let e = Error::Errno(errno);
+            if let Some(trace) = trace {
+                trace.finish(TraceResult::Error(&e));
+            }
+            return Err(e);
+        }
+
+        if dry_run::is_dry_run() {
+            match dry_run::handle_dry_run(guest, syscall, mount_table, &fd_table).await {
+                Ok(Some(value)) => {
+                    if let Some(trace) = trace {
+                        trace.finish(TraceResult::Value(value));
+                    }
+                    return Ok(value);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    if let Some(trace) = trace {
+                        trace.finish(TraceResult::Error(&e));
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        if changes::is_tracking_changes() {
+            changes::record_changes(guest, &syscall, mount_table, &fd_table).await;
         }
 
         let result = match syscall::dispatch_syscall(guest, syscall, mount_table, &fd_table).await {
             Ok(syscall::SyscallResult::Value(value)) => {
-                if is_strace_enabled() {
-                    eprintln!("[{}] = {}", pid, format_result(value));
+                if let Some(trace) = trace {
+                    trace.finish(TraceResult::Value(value));
                 }
                 Ok(value)
             }
-            Ok(syscall::SyscallResult::Syscall(syscall)) => guest.tail_inject(syscall).await,
+            Ok(syscall::SyscallResult::Syscall(syscall)) => {
+                if let Some(trace) = trace {
+                    trace.finish(TraceResult::Injected);
+                }
+                guest.tail_inject(syscall).await
+            }
             Err(e) => {
-                if is_strace_enabled() {
-                    if let Error::Errno(errno) = &e {
-                        eprintln!("[{}] = -1 {}", pid, errno);
-                    } else {
-                        eprintln!("[{}] = error: {:?}", pid, e);
-                    }
+                if let Some(trace) = trace {
+                    trace.finish(TraceResult::Error(&e));
                 }
                 Err(e)
             }
@@ -127,4 +352,115 @@ impl Tool for Sandbox {
 
         result
     }
+
+    async fn handle_thread_start<T: Guest<Self>>(&self, guest: &mut T) -> Result<(), Error> {
+        limits::apply_limits(guest.pid().as_raw());
+        Ok(())
+    }
+
+    async fn handle_post_exec<T: Guest<Self>>(&self, guest: &mut T) -> Result<(), Errno> {
+        let pid = guest.pid().as_raw();
+        let fd_table = get_fd_table(pid);
+        syscall::process::handle_post_exec(guest, &fd_table).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{fdtable::FdEntry, file::FileOps, VfsResult};
+    use std::os::unix::io::RawFd;
+    use std::sync::Arc;
+
+    /// Virtual file that does nothing
+    struct NullFile;
+
+    #[async_trait::async_trait]
+    impl FileOps for NullFile {
+        async fn read(&self, _buf: &mut [u8]) -> VfsResult<usize> {
+            Ok(0)
+        }
+        async fn write(&self, buf: &[u8]) -> VfsResult<usize> {
+            Ok(buf.len())
+        }
+        async fn seek(&self, _offset: i64, _whence: i32) -> VfsResult<i64> {
+            Ok(0)
+        }
+        async fn fstat(&self) -> VfsResult<libc::stat> {
+            Ok(unsafe { std::mem::zeroed() })
+        }
+        async fn fsync(&self) -> VfsResult<()> {
+            Ok(())
+        }
+        async fn fdatasync(&self) -> VfsResult<()> {
+            Ok(())
+        }
+        fn fcntl(&self, _cmd: i32, _arg: i64) -> VfsResult<i64> {
+            Ok(0)
+        }
+        fn ioctl(&self, _request: u64, _arg: u64) -> VfsResult<i64> {
+            Ok(0)
+        }
+        fn as_raw_fd(&self) -> Option<RawFd> {
+            None
+        }
+        async fn close(&self) -> VfsResult<()> {
+            Ok(())
+        }
+        fn get_flags(&self) -> i32 {
+            0
+        }
+        fn set_flags(&self, _flags: i32) -> VfsResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_report_open_fds() {
+        let table = FdTable::new();
+        let closed = table.allocate(FdEntry::Virtual {
+            file_ops: Arc::new(NullFile),
+            flags: 0,
+            path: Some(PathBuf::from("/agent/closed")),
+        });
+        let leaked = table.allocate(FdEntry::Virtual {
+            file_ops: Arc::new(NullFile),
+            flags: 0,
+            path: Some(PathBuf::from("/agent/leaked")),
+        });
+        table.release(closed);
+
+        let mut tables = HashMap::new();
+        tables.insert(42, table);
+        tables.insert(43, FdTable::new());
+
+        let open_fds = collect_open_fds(&tables);
+        assert_eq!(
+            open_fds,
+            vec![OpenFd {
+                pid: 42,
+                fd: leaked,
+                path: PathBuf::from("/agent/leaked"),
+            }]
+        );
+        assert_eq!(
+            format_open_fds(&open_fds),
+            format!("1 virtual fd open at exit\n  [42] fd {leaked} -> /agent/leaked")
+        );
+    }
+
+    #[test]
+    fn test_umask_inheritance() {
+        assert_eq!(umask(1000), DEFAULT_UMASK);
+        assert_eq!(set_umask(1000, 0o077), DEFAULT_UMASK);
+
+        // A forked child gets a copy, a CLONE_FS child shares the mask
+        inherit_umask(1000, 1001, false);
+        inherit_umask(1000, 1002, true);
+        set_umask(1001, 0o027);
+        set_umask(1002, 0o007);
+        assert_eq!(umask(1000), 0o007);
+        assert_eq!(umask(1001), 0o027);
+    }
 }
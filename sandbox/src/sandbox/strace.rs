@@ -0,0 +1,372 @@
+//! Strace-like logging of intercepted system calls.
+//!
+//! Calls are logged to stderr by default, or to any writer set up with
+//! `init_strace_output()`, in one of two formats:
+//!
+//! - `text` prints each call when it is intercepted and its result once the
+//!   handler returns, like `strace`.
+//! - `json` prints one object per line once the call completes, with the
+//!   syscall name, its arguments, the paths it refers to (annotated with their
+//!   translation through the mount table), the return value and a timestamp.
+//!   Calls that are passed on to the kernel as the guest's last action have a
+//!   `null` return value, since the sandbox never sees it.
+
+use crate::{sandbox::Sandbox, syscall::memory::read_path, vfs::mount::MountTable};
+use reverie::{
+    syscalls::{PathPtr, Syscall},
+    Error, Guest,
+};
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Output format of the strace log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StraceFormat {
+    /// `strace`-like text lines
+    #[default]
+    Text,
+    /// One JSON object per system call
+    Json,
+}
+
+impl std::str::FromStr for StraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(StraceFormat::Text),
+            "json" => Ok(StraceFormat::Json),
+            _ => Err(format!(
+                "Unsupported strace format '{}'. Supported formats: text, json.",
+                s
+            )),
+        }
+    }
+}
+
+/// Global flag to enable strace-like output
+static STRACE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Where strace output goes, and in which format
+type StraceOutput = Option<(Box<dyn Write + Send>, StraceFormat)>;
+
+static STRACE_OUTPUT: Mutex<StraceOutput> = Mutex::new(None);
+
+/// Initialize strace mode, logging text to stderr
+///
+/// This must be called before spawning the traced process.
+pub fn init_strace(enabled: bool) {
+    if enabled {
+        init_strace_output(Box::new(std::io::stderr()), StraceFormat::Text);
+    }
+}
+
+/// Initialize strace mode, logging to `output` in the given format
+///
+/// This must be called before spawning the traced process. Call
+/// `close_strace_output()` once it has exited to flush the output.
+pub fn init_strace_output(output: Box<dyn Write + Send>, format: StraceFormat) {
+    *lock_output() = Some((output, format));
+    STRACE_ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stop strace mode, flushing and closing its output
+pub fn close_strace_output() -> std::io::Result<()> {
+    STRACE_ENABLED.store(false, Ordering::Relaxed);
+    match lock_output().take() {
+        Some((mut output, _)) => output.flush(),
+        None => Ok(()),
+    }
+}
+
+/// Check if strace is enabled
+pub(crate) fn is_strace_enabled() -> bool {
+    STRACE_ENABLED.load(Ordering::Relaxed)
+}
+
+fn lock_output() -> MutexGuard<'static, StraceOutput> {
+    STRACE_OUTPUT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn output_format() -> Option<StraceFormat> {
+    lock_output().as_ref().map(|(_, format)| *format)
+}
+
+/// Write a line to the strace output
+///
+/// Errors are ignored, as logging must not change how the guest runs.
+fn write_line(line: &str) {
+    if let Some((output, _)) = lock_output().as_mut() {
+        let _ = writeln!(output, "{}", line);
+    }
+}
+
+/// A path argument of a traced system call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TracedPath {
+    /// The path as passed by the guest
+    pub path: PathBuf,
+    /// The path it translates to, if it is under a mount point
+    pub translated: Option<PathBuf>,
+}
+
+/// How a traced system call completed
+pub(crate) enum TraceResult<'a> {
+    /// The sandbox returned a value to the guest
+    Value(i64),
+    /// The handler failed
+    Error(&'a Error),
+    /// The call was passed on to the kernel
+    Injected,
+}
+
+/// A system call being traced, from interception until it completes
+pub(crate) struct SyscallTrace {
+    pid: i32,
+    format: StraceFormat,
+    syscall: String,
+    paths: Vec<TracedPath>,
+    timestamp: f64,
+}
+
+impl SyscallTrace {
+    /// Start tracing an intercepted system call, if strace is enabled
+    ///
+    /// Path arguments are read from guest memory before the handler runs.
+    pub(crate) async fn start<T: Guest<Sandbox>>(
+        guest: &mut T,
+        syscall: &Syscall,
+        mount_table: &MountTable,
+    ) -> Option<Self> {
+        if !is_strace_enabled() {
+            return None;
+        }
+        let format = output_format()?;
+        let pid = guest.pid().as_raw();
+
+        let paths = match format {
+            StraceFormat::Text => {
+                write_line(&format!("[{}] {:?}", pid, syscall));
+                Vec::new()
+            }
+            StraceFormat::Json => syscall_paths(syscall)
+                .into_iter()
+                .filter_map(|path_addr| read_path(guest, path_addr).ok())
+                .map(|path| TracedPath {
+                    translated: mount_table
+                        .resolve(&path)
+                        .map(|(_vfs, translated, _)| translated),
+                    path,
+                })
+                .collect(),
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        Some(Self {
+            pid,
+            format,
+            syscall: format!("{:?}", syscall),
+            paths,
+            timestamp,
+        })
+    }
+
+    /// Log how the system call completed
+    pub(crate) fn finish(self, result: TraceResult<'_>) {
+        let line = match self.format {
+            StraceFormat::Text => match result {
+                TraceResult::Value(value) => format!("[{}] = {}", self.pid, value),
+                TraceResult::Error(Error::Errno(errno)) => {
+                    format!("[{}] = -1 {}", self.pid, errno)
+                }
+                TraceResult::Error(e) => format!("[{}] = error: {:?}", self.pid, e),
+                TraceResult::Injected => return,
+            },
+            StraceFormat::Json => self.to_json(&result),
+        };
+        write_line(&line);
+    }
+
+    /// Format the system call as a single-line JSON object
+    fn to_json(&self, result: &TraceResult<'_>) -> String {
+        let paths: Vec<String> = self
+            .paths
+            .iter()
+            .map(|path| {
+                let translated = match &path.translated {
+                    Some(translated) => json_string(&translated.to_string_lossy()),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "{{\"path\":{},\"translated\":{}}}",
+                    json_string(&path.path.to_string_lossy()),
+                    translated
+                )
+            })
+            .collect();
+        let ret = match result {
+            TraceResult::Value(value) => value.to_string(),
+            TraceResult::Error(Error::Errno(errno)) => {
+                format!("-1,\"error\":{}", json_string(&errno.to_string()))
+            }
+            TraceResult::Error(e) => format!("-1,\"error\":{}", json_string(&format!("{:?}", e))),
+            TraceResult::Injected => "null".to_string(),
+        };
+        format!(
+            "{{\"timestamp\":{:.6},\"pid\":{},\"syscall\":{},\"args\":{},\"paths\":[{}],\"ret\":{}}}",
+            self.timestamp,
+            self.pid,
+            json_string(&syscall_name(&self.syscall)),
+            json_string(&self.syscall),
+            paths.join(","),
+            ret
+        )
+    }
+}
+
+/// The path arguments of the system calls that take them
+///
+/// Symlink targets are not included, as they are stored rather than resolved.
+pub(crate) fn syscall_paths(syscall: &Syscall) -> Vec<PathPtr<'_>> {
+    let paths = match syscall {
+        Syscall::Openat(args) => vec![args.path()],
+        Syscall::Statx(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Newfstatat(args) => vec![args.path()],
+        #[cfg(target_arch = "aarch64")]
+        Syscall::Fstatat(args) => vec![args.path()],
+        Syscall::Statfs(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Access(args) => vec![args.path()],
+        Syscall::Faccessat(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Mkdir(args) => vec![args.path()],
+        Syscall::Mkdirat(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Rmdir(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Unlink(args) => vec![args.path()],
+        Syscall::Unlinkat(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Readlink(args) => vec![args.path()],
+        Syscall::Readlinkat(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Symlink(args) => vec![args.linkpath()],
+        Syscall::Symlinkat(args) => vec![args.linkpath()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Rename(args) => vec![args.oldpath(), args.newpath()],
+        Syscall::Renameat2(args) => vec![args.oldpath(), args.newpath()],
+        Syscall::Linkat(args) => vec![args.oldpath(), args.newpath()],
+        Syscall::Truncate(args) => vec![args.path()],
+        Syscall::Chdir(args) => vec![args.path()],
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Chmod(args) => vec![args.path()],
+        Syscall::Fchmodat(args) => vec![args.path()],
+        Syscall::Utimensat(args) => vec![args.path()],
+        _ => Vec::new(),
+    };
+    paths.into_iter().flatten().collect()
+}
+
+/// Extract the system call name from its `Debug` output, e.g. `openat`
+fn syscall_name(syscall: &str) -> String {
+    syscall
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Quote and escape a string for JSON
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_strace_format() {
+        assert_eq!("text".parse(), Ok(StraceFormat::Text));
+        assert_eq!("json".parse(), Ok(StraceFormat::Json));
+        let format: Result<StraceFormat, _> = "xml".parse();
+        assert!(format.unwrap_err().contains("Unsupported strace format"));
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("/agent/a b"), "\"/agent/a b\"");
+        assert_eq!(json_string("\"q\"\\\n\u{1}"), "\"\\\"q\\\"\\\\\\n\\u0001\"");
+    }
+
+    #[test]
+    fn test_syscall_name() {
+        assert_eq!(syscall_name("Openat(Openat { dirfd: -100 })"), "openat");
+        assert_eq!(syscall_name("exit_group(0)"), "exit_group");
+    }
+
+    #[test]
+    fn test_trace_to_json() {
+        let trace = SyscallTrace {
+            pid: 42,
+            format: StraceFormat::Json,
+            syscall: "Openat(Openat { path: \"/agent/a\" })".to_string(),
+            paths: vec![
+                TracedPath {
+                    path: PathBuf::from("/agent/a"),
+                    translated: Some(PathBuf::from("/a")),
+                },
+                TracedPath {
+                    path: PathBuf::from("/tmp"),
+                    translated: None,
+                },
+            ],
+            timestamp: 1.5,
+        };
+
+        assert_eq!(
+            trace.to_json(&TraceResult::Value(3)),
+            "{\"timestamp\":1.500000,\"pid\":42,\"syscall\":\"openat\",\
+             \"args\":\"Openat(Openat { path: \\\"/agent/a\\\" })\",\
+             \"paths\":[{\"path\":\"/agent/a\",\"translated\":\"/a\"},\
+             {\"path\":\"/tmp\",\"translated\":null}],\"ret\":3}"
+        );
+
+        let errno = Error::Errno(reverie::syscalls::Errno::ENOENT);
+        let json = trace.to_json(&TraceResult::Error(&errno));
+        assert!(json.ends_with(&format!(
+            "\"ret\":-1,\"error\":\"{}\"}}",
+            reverie::syscalls::Errno::ENOENT
+        )));
+        assert!(trace
+            .to_json(&TraceResult::Injected)
+            .ends_with("\"ret\":null}"));
+    }
+}
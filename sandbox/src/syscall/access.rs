@@ -0,0 +1,263 @@
+use crate::{
+    sandbox::Sandbox,
+    syscall::{memory::read_path, open::resolve_dirfd, translate_path},
+    vfs::{fdtable::FdTable, mount::MountTable, Vfs},
+};
+use reverie::{
+    syscalls::{PathPtr, Syscall, SyscallArgs, Sysno},
+    Error, Guest,
+};
+use std::path::{Path, PathBuf};
+
+/// All permission bits accepted in the `mode` argument of the access family.
+const ACCESS_MODE_MASK: i32 = libc::R_OK | libc::W_OK | libc::X_OK;
+
+/// Flags accepted by `faccessat2`.
+const FACCESSAT2_FLAGS: i32 = libc::AT_EACCESS | libc::AT_SYMLINK_NOFOLLOW | libc::AT_EMPTY_PATH;
+
+/// Check the `R_OK`/`W_OK`/`X_OK` bits of `mode` against a file's permission bits.
+///
+/// The owner, group or other class is picked the same way the kernel does it.
+/// Supplementary groups are not consulted. Root may always read and write, and
+/// may execute if any execute bit is set or the file is a directory.
+pub(crate) fn permitted(stat: &libc::stat, mode: i32, uid: u32, gid: u32) -> bool {
+    if uid == 0 {
+        let executable = stat.st_mode & libc::S_IFMT == libc::S_IFDIR || stat.st_mode & 0o111 != 0;
+        return mode & libc::X_OK == 0 || executable;
+    }
+
+    let granted = if uid == stat.st_uid {
+        (stat.st_mode >> 6) & 0o7
+    } else if gid == stat.st_gid {
+        (stat.st_mode >> 3) & 0o7
+    } else {
+        stat.st_mode & 0o7
+    };
+    (mode as u32) & !granted == 0
+}
+
+/// The caller's user and group IDs as seen inside `vfs`.
+///
+/// The guest inherits the sandbox's credentials, which are mapped through the VFS's
+/// ID mapping so that they compare against the ownership it reports. `effective`
+/// selects the effective rather than the real IDs.
+pub(crate) fn caller_ids(vfs: &dyn Vfs, effective: bool) -> (u32, u32) {
+    let (uid, gid) = if effective {
+        unsafe { (libc::geteuid(), libc::getegid()) }
+    } else {
+        unsafe { (libc::getuid(), libc::getgid()) }
+    };
+    let id_map = vfs.id_map();
+    (id_map.uid_inside(uid), id_map.gid_inside(gid))
+}
+
+/// Check accessibility of a path in a virtual VFS.
+///
+/// The path is looked up with `Vfs::stat` (or `Vfs::lstat` for `AT_SYMLINK_NOFOLLOW`)
+/// and the requested mask is checked against its permission bits using the sandbox's
+/// credentials, which the guest inherits. `AT_EACCESS` selects the effective IDs.
+///
+/// Returns `Some(result)` if the path lives under a virtual mount, or `None` if the
+/// kernel should perform the check.
+async fn access_virtual(
+    path: &Path,
+    mode: i32,
+    flags: i32,
+    mount_table: &MountTable,
+) -> Option<i64> {
+    let (vfs, _translated_path, _read_only) = mount_table.resolve(path)?;
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    if mode & !ACCESS_MODE_MASK != 0 {
+        return Some(-libc::EINVAL as i64);
+    }
+
+    let stat_result = if flags & libc::AT_SYMLINK_NOFOLLOW != 0 {
+        vfs.lstat(path).await
+    } else {
+        vfs.stat(path).await
+    };
+    let stat = match stat_result {
+        Ok(stat) => stat,
+        Err(e) => return Some(e.to_errno()),
+    };
+
+    if mode == libc::F_OK {
+        return Some(0);
+    }
+
+    let (uid, gid) = caller_ids(&*vfs, flags & libc::AT_EACCESS != 0);
+
+    Some(if permitted(&stat, mode, uid, gid) {
+        0
+    } else {
+        -libc::EACCES as i64
+    })
+}
+
+/// The `access` system call.
+///
+/// This intercepts `access` system calls and checks virtual paths against the VFS,
+/// or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_access<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Access,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        let mode = args.mode().bits() as i32;
+        if let Some(result) = access_virtual(&path, mode, 0, mount_table).await {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Access(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `faccessat` system call.
+///
+/// This intercepts `faccessat` system calls and virtualizes the dirfd. Virtual paths are
+/// checked against the VFS, and host paths are translated and `faccessat` is injected
+/// with the kernel dirfd.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_faccessat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Faccessat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let mode = args.mode().bits() as i32;
+    if let Some(result) = access_virtual(&path, mode, 0, mount_table).await {
+        return Ok(Some(result));
+    }
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Faccessat(new_syscall)).await?;
+    Ok(Some(result))
+}
+
+/// The `faccessat2` system call.
+///
+/// This intercepts `faccessat2` system calls and virtualizes the dirfd. Virtual paths are
+/// checked against the VFS honoring `AT_EACCESS` and `AT_SYMLINK_NOFOLLOW`, and host paths
+/// are translated and `faccessat2` is injected with the kernel dirfd and the original flags.
+/// Signature: int faccessat2(int dirfd, const char *pathname, int mode, int flags);
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_faccessat2<T: Guest<Sandbox>>(
+    guest: &mut T,
+    syscall_args: &SyscallArgs,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let dirfd = syscall_args.arg0 as i32;
+    let path_addr = match PathPtr::from_ptr(syscall_args.arg1 as _) {
+        Some(ptr) => ptr,
+        // A NULL path is reported by the kernel
+        None => return Ok(None),
+    };
+    let mode = syscall_args.arg2 as i32;
+    let flags = syscall_args.arg3 as i32;
+
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(dirfd, &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    if flags & !FACCESSAT2_FLAGS != 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+
+    if let Some(result) = access_virtual(&path, mode, flags, mount_table).await {
+        return Ok(Some(result));
+    }
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_path_raw: usize = unsafe { std::mem::transmute(new_path_addr.unwrap_or(path_addr)) };
+
+    let result = guest
+        .inject(Syscall::Other(
+            Sysno::faccessat2,
+            SyscallArgs {
+                arg0: kernel_dirfd as usize,
+                arg1: new_path_raw,
+                arg2: mode as usize,
+                arg3: flags as usize,
+                arg4: 0,
+                arg5: 0,
+            },
+        ))
+        .await?;
+
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat_with(mode: u32, uid: u32, gid: u32) -> libc::stat {
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        stat.st_mode = mode;
+        stat.st_uid = uid;
+        stat.st_gid = gid;
+        stat
+    }
+
+    #[test]
+    fn test_permitted_classes() {
+        let stat = stat_with(libc::S_IFREG | 0o640, 1000, 100);
+
+        // Owner
+        assert!(permitted(&stat, libc::R_OK | libc::W_OK, 1000, 100));
+        assert!(!permitted(&stat, libc::X_OK, 1000, 100));
+        // Group
+        assert!(permitted(&stat, libc::R_OK, 2000, 100));
+        assert!(!permitted(&stat, libc::W_OK, 2000, 100));
+        // Other
+        assert!(!permitted(&stat, libc::R_OK, 2000, 200));
+    }
+
+    #[test]
+    fn test_permitted_root() {
+        let file = stat_with(libc::S_IFREG | 0o600, 1000, 100);
+        assert!(permitted(&file, libc::R_OK | libc::W_OK, 0, 0));
+        assert!(!permitted(&file, libc::X_OK, 0, 0));
+
+        let script = stat_with(libc::S_IFREG | 0o700, 1000, 100);
+        assert!(permitted(&script, libc::X_OK, 0, 0));
+
+        let dir = stat_with(libc::S_IFDIR, 1000, 100);
+        assert!(permitted(&dir, libc::X_OK, 0, 0));
+    }
+}
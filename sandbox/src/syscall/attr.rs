@@ -0,0 +1,603 @@
+use crate::{
+    sandbox::Sandbox,
+    syscall::{
+        access::{caller_ids, permitted},
+        fd_is_read_only,
+        io::lookup_virtual,
+        memory::read_path,
+        open::resolve_dirfd,
+        translate_path,
+    },
+    vfs::{fdtable::FdTable, mount::MountTable, Vfs},
+};
+use reverie::{
+    syscalls::{AtFlags, MemoryAccess, Syscall},
+    Error, Guest,
+};
+use std::path::{Path, PathBuf};
+
+/// Change the permission bits of a path in a virtual VFS.
+///
+/// Only the owner reported by `Vfs::stat` or root
+/// may change the mode; anyone else gets `EPERM`. The check uses the sandbox's effective
+/// uid, which the guest inherits, mapped through the VFS's ID mapping.
+///
+/// Returns `Some(result)` if the path lives under a virtual or read-only mount, or `None` if the
+/// kernel should perform the change.
+async fn chmod_virtual(path: &Path, mode: u32, mount_table: &MountTable) -> Option<i64> {
+    let (vfs, _translated_path, read_only) = mount_table.resolve(path)?;
+    if read_only {
+        return Some(-libc::EROFS as i64);
+    }
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    let stat = match vfs.stat(path).await {
+        Ok(stat) => stat,
+        Err(e) => return Some(e.to_errno()),
+    };
+
+    let (euid, _) = caller_ids(&*vfs, true);
+    if euid != 0 && euid != stat.st_uid {
+        return Some(-libc::EPERM as i64);
+    }
+
+    Some(match vfs.chmod(path, mode).await {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// The `chmod` system call.
+///
+/// This intercepts `chmod` system calls and updates permission bits in virtual mounts via
+/// `Vfs::chmod`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_chmod<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Chmod,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(result) = chmod_virtual(&path, args.mode().bits(), mount_table).await {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Chmod(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `fchmodat` system call.
+///
+/// This intercepts `fchmodat` system calls and virtualizes the dirfd. For virtual mounts,
+/// the permission bits are updated via `Vfs::chmod`. For host mounts, the path is translated
+/// and `fchmodat` is injected with the kernel dirfd.
+/// Note: On ARM (aarch64), chmod is implemented via fchmodat with AT_FDCWD.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fchmodat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fchmodat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    if let Some(result) = chmod_virtual(&path, args.mode().bits(), mount_table).await {
+        return Ok(Some(result));
+    }
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Fchmodat(new_syscall)).await?;
+    Ok(Some(result))
+}
+
+/// The `fchmod` system call.
+///
+/// This intercepts `fchmod` system calls. FDs of files in virtual or read-only mounts are
+/// handled like `chmod` on the path they were opened by, and other passthrough FDs are
+/// translated to kernel FDs.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fchmod<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fchmod,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let Some(entry) = fd_table.get(args.fd()) else {
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        return Ok(None);
+    };
+    if let Some(path) = entry.path() {
+        if let Some(result) = chmod_virtual(path, args.mode().bits(), mount_table).await {
+            return Ok(Some(result));
+        }
+    }
+
+    match entry.kernel_fd() {
+        Some(kernel_fd) => {
+            let new_syscall = args.with_fd(kernel_fd);
+            let result = guest.inject(Syscall::Fchmod(new_syscall)).await?;
+            Ok(Some(result))
+        }
+        None => Ok(Some(-libc::EBADF as i64)),
+    }
+}
+
+/// Whether the caller belongs to `gid`, either as its effective or a supplementary group.
+fn in_group(gid: u32) -> bool {
+    if unsafe { libc::getegid() } == gid {
+        return true;
+    }
+
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if count <= 0 {
+        return false;
+    }
+    let mut groups = vec![0 as libc::gid_t; count as usize];
+    let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+    count > 0 && groups[..count as usize].contains(&gid)
+}
+
+/// Change the ownership of a path in a virtual VFS.
+///
+/// `-1` for `uid` or `gid` leaves that field unchanged. Following the kernel's rules,
+/// only root may change the owner, while the owner may change the group to one it
+/// belongs to; anything else gets `EPERM`. With `follow` unset, a symlink at `path`
+/// is changed itself via `Vfs::lchown`.
+///
+/// Returns `Some(result)` if the path lives under a virtual or read-only mount, or `None` if the
+/// kernel should perform the change.
+async fn chown_virtual(
+    path: &Path,
+    uid: u32,
+    gid: u32,
+    follow: bool,
+    mount_table: &MountTable,
+) -> Option<i64> {
+    let (vfs, _translated_path, read_only) = mount_table.resolve(path)?;
+    if read_only {
+        return Some(-libc::EROFS as i64);
+    }
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    let uid = (uid != u32::MAX).then_some(uid);
+    let gid = (gid != u32::MAX).then_some(gid);
+
+    let stat_result = if follow {
+        vfs.stat(path).await
+    } else {
+        vfs.lstat(path).await
+    };
+    let stat = match stat_result {
+        Ok(stat) => stat,
+        Err(e) => return Some(e.to_errno()),
+    };
+
+    let (euid, _) = caller_ids(&*vfs, true);
+    if euid != 0 {
+        let uid_changes = uid.is_some_and(|uid| uid != stat.st_uid);
+        let gid_denied =
+            gid.is_some_and(|gid| gid != stat.st_gid && !in_group(vfs.id_map().gid_outside(gid)));
+        if uid_changes || euid != stat.st_uid || gid_denied {
+            return Some(-libc::EPERM as i64);
+        }
+    }
+
+    let result = if follow {
+        vfs.chown(path, uid, gid).await
+    } else {
+        vfs.lchown(path, uid, gid).await
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// The `chown` system call.
+///
+/// This intercepts `chown` system calls and updates ownership in virtual mounts via
+/// `Vfs::chown`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_chown<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Chown,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(result) =
+            chown_virtual(&path, args.owner(), args.group(), true, mount_table).await
+        {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Chown(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `lchown` system call.
+///
+/// Like `chown`, but a symlink in a virtual mount is changed itself via `Vfs::lchown`.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_lchown<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Lchown,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(result) =
+            chown_virtual(&path, args.owner(), args.group(), false, mount_table).await
+        {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Lchown(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `fchownat` system call.
+///
+/// This intercepts `fchownat` system calls and virtualizes the dirfd. For virtual mounts,
+/// ownership is updated via `Vfs::chown`, or `Vfs::lchown` when `AT_SYMLINK_NOFOLLOW` is
+/// set. For host mounts, the path is translated and `fchownat` is injected with the kernel
+/// dirfd.
+/// Note: On ARM (aarch64), chown and lchown are implemented via fchownat.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fchownat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fchownat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let follow = !args.flags().contains(AtFlags::AT_SYMLINK_NOFOLLOW);
+    if let Some(result) =
+        chown_virtual(&path, args.owner(), args.group(), follow, mount_table).await
+    {
+        return Ok(Some(result));
+    }
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Fchownat(new_syscall)).await?;
+    Ok(Some(result))
+}
+
+/// The `fchown` system call.
+///
+/// This intercepts `fchown` system calls. FDs of files in virtual or read-only mounts are
+/// handled like `chown` on the path they were opened by, and other passthrough FDs are
+/// translated to kernel FDs.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fchown<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fchown,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let Some(entry) = fd_table.get(args.fd()) else {
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        return Ok(None);
+    };
+    if let Some(path) = entry.path() {
+        if let Some(result) =
+            chown_virtual(path, args.owner(), args.group(), true, mount_table).await
+        {
+            return Ok(Some(result));
+        }
+    }
+
+    match entry.kernel_fd() {
+        Some(kernel_fd) => {
+            let new_syscall = args.with_fd(kernel_fd);
+            let result = guest.inject(Syscall::Fchown(new_syscall)).await?;
+            Ok(Some(result))
+        }
+        None => Ok(Some(-libc::EBADF as i64)),
+    }
+}
+
+/// Flags accepted by `utimensat`.
+const UTIMENSAT_FLAGS: i32 = libc::AT_SYMLINK_NOFOLLOW | libc::AT_EMPTY_PATH;
+
+/// Timestamps requested by `utimensat`, in seconds since the Unix epoch.
+///
+/// A `None` time is left unchanged (`UTIME_OMIT`). VFS timestamps have a resolution
+/// of one second, so the nanoseconds of a requested time are dropped, rounding it
+/// down to the second, and the current time is likewise taken in whole seconds.
+struct Times {
+    atime: Option<i64>,
+    mtime: Option<i64>,
+    /// Whether either time was given explicitly rather than as `UTIME_NOW`
+    explicit: bool,
+}
+
+impl Times {
+    /// Interpret the `times` argument of `utimensat`, where NULL means "now" for both.
+    ///
+    /// Returns `None` if a nanosecond field is out of range.
+    fn from_timespecs(times: Option<[libc::timespec; 2]>) -> Option<Times> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let Some(times) = times else {
+            return Some(Times {
+                atime: Some(now),
+                mtime: Some(now),
+                explicit: false,
+            });
+        };
+
+        let mut explicit = false;
+        let mut resolve = |ts: &libc::timespec| match ts.tv_nsec {
+            libc::UTIME_OMIT => Some(None),
+            libc::UTIME_NOW => Some(Some(now)),
+            nsec if (0..1_000_000_000).contains(&nsec) => {
+                explicit = true;
+                Some(Some(ts.tv_sec as i64))
+            }
+            _ => None,
+        };
+        Some(Times {
+            atime: resolve(&times[0])?,
+            mtime: resolve(&times[1])?,
+            explicit,
+        })
+    }
+
+    /// Check whether the caller may apply these times to a file.
+    ///
+    /// Following the kernel's rules, only the owner or root may set explicit times,
+    /// while setting both to the current time is also allowed with write access.
+    /// Returns the errno to fail with otherwise.
+    fn check_permission(&self, vfs: &dyn Vfs, stat: &libc::stat) -> Result<(), i64> {
+        let (euid, egid) = caller_ids(vfs, true);
+        if euid == 0 || euid == stat.st_uid || (self.atime.is_none() && self.mtime.is_none()) {
+            return Ok(());
+        }
+        if self.explicit {
+            return Err(-libc::EPERM as i64);
+        }
+        if !permitted(stat, libc::W_OK, euid, egid) {
+            return Err(-libc::EACCES as i64);
+        }
+        Ok(())
+    }
+}
+
+/// Change the timestamps of a path in a virtual VFS.
+///
+/// With `follow` unset, a symlink at `path` is changed itself via `Vfs::lutimes`.
+///
+/// Returns `Some(result)` if the path lives under a virtual or read-only mount, or `None` if the
+/// kernel should perform the change.
+async fn utimens_virtual(
+    path: &Path,
+    times: &Times,
+    follow: bool,
+    mount_table: &MountTable,
+) -> Option<i64> {
+    let (vfs, _translated_path, read_only) = mount_table.resolve(path)?;
+    if read_only {
+        return Some(-libc::EROFS as i64);
+    }
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    let stat_result = if follow {
+        vfs.stat(path).await
+    } else {
+        vfs.lstat(path).await
+    };
+    let stat = match stat_result {
+        Ok(stat) => stat,
+        Err(e) => return Some(e.to_errno()),
+    };
+    if let Err(errno) = times.check_permission(&*vfs, &stat) {
+        return Some(errno);
+    }
+    if times.atime.is_none() && times.mtime.is_none() {
+        return Some(0);
+    }
+
+    let result = if follow {
+        vfs.utimes(path, times.atime, times.mtime).await
+    } else {
+        vfs.lutimes(path, times.atime, times.mtime).await
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// The `utimensat` system call.
+///
+/// This intercepts `utimensat` system calls and virtualizes the dirfd. For virtual mounts,
+/// the timestamps are stored via `Vfs::utimes`, or `Vfs::lutimes` when
+/// `AT_SYMLINK_NOFOLLOW` is set. `UTIME_NOW` and `UTIME_OMIT` are supported, and a NULL
+/// `times` sets both timestamps to the current time. A NULL path changes the file open
+/// at dirfd, which is how `futimens` is implemented; virtual files go through
+/// `Vfs::futimes`. Times are stored in whole seconds, so `stat` reports them with the
+/// nanoseconds zeroed (see `Times`). Changes to files in read-only mounts fail with
+/// `EROFS`. For host mounts, the path is translated and `utimensat` is injected
+/// with the kernel dirfd.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_utimensat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Utimensat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let flags = args.flags().bits();
+    if flags & !UTIMENSAT_FLAGS != 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+
+    let timespecs = match args.times() {
+        Some(addr) => Some(
+            guest
+                .memory()
+                .read_value(addr.cast::<[libc::timespec; 2]>())?,
+        ),
+        None => None,
+    };
+    let times = match Times::from_timespecs(timespecs) {
+        Some(times) => times,
+        None => return Ok(Some(-libc::EINVAL as i64)),
+    };
+
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => {
+            // futimens: change the file open at dirfd
+            if fd_is_read_only(args.dirfd(), mount_table, fd_table) {
+                return Ok(Some(-libc::EROFS as i64));
+            }
+            if let Some((vfs, file_ops)) = lookup_virtual(args.dirfd(), mount_table, fd_table) {
+                let stat = match file_ops.fstat().await {
+                    Ok(stat) => stat,
+                    Err(e) => return Ok(Some(e.to_errno())),
+                };
+                if let Err(errno) = times.check_permission(&*vfs, &stat) {
+                    return Ok(Some(errno));
+                }
+                if times.atime.is_none() && times.mtime.is_none() {
+                    return Ok(Some(0));
+                }
+                return Ok(Some(
+                    match vfs.futimes(&file_ops, times.atime, times.mtime).await {
+                        Ok(()) => 0,
+                        Err(e) => e.to_errno(),
+                    },
+                ));
+            }
+
+            return match fd_table.translate(args.dirfd()) {
+                Some(kernel_fd) => {
+                    let new_syscall = args.with_dirfd(kernel_fd);
+                    let result = guest.inject(Syscall::Utimensat(new_syscall)).await?;
+                    Ok(Some(result))
+                }
+                // FD not in table, let the original syscall through (will likely fail with EBADF)
+                None => Ok(None),
+            };
+        }
+    };
+
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let follow = flags & libc::AT_SYMLINK_NOFOLLOW == 0;
+    if let Some(result) = utimens_virtual(&path, &times, follow, mount_table).await {
+        return Ok(Some(result));
+    }
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Utimensat(new_syscall)).await?;
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timespec(tv_sec: i64, tv_nsec: i64) -> libc::timespec {
+        libc::timespec { tv_sec, tv_nsec }
+    }
+
+    #[test]
+    fn test_times_from_timespecs() {
+        let omit = timespec(0, libc::UTIME_OMIT);
+        let times = Times::from_timespecs(Some([timespec(100, 5), omit])).unwrap();
+        assert_eq!((times.atime, times.mtime), (Some(100), None));
+        assert!(times.explicit);
+
+        // Nanoseconds are dropped, rounding down to the second
+        let times = Times::from_timespecs(Some([omit, timespec(100, 999_999_999)])).unwrap();
+        assert_eq!(times.mtime, Some(100));
+
+        // NULL and UTIME_NOW both mean the current time, which is not explicit
+        let null = Times::from_timespecs(None).unwrap();
+        assert!(null.atime.is_some() && null.mtime.is_some());
+        assert!(!null.explicit);
+        let utime_now = timespec(0, libc::UTIME_NOW);
+        let now = Times::from_timespecs(Some([utime_now, utime_now])).unwrap();
+        assert!(now.atime.is_some() && !now.explicit);
+
+        let zero = timespec(0, 0);
+        assert!(Times::from_timespecs(Some([timespec(0, -1), zero])).is_none());
+        assert!(Times::from_timespecs(Some([zero, timespec(0, 1_000_000_000)])).is_none());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    sandbox::{self, Sandbox},
+    syscall::{io::lookup_virtual, memory::read_path, open::resolve_dirfd, translate_path},
+    vfs::{fdtable::FdTable, mount::MountTable, DirEntry},
+};
+use reverie::{
+    syscalls::{MemoryAccess, Syscall},
+    Error, Guest,
+};
+use std::path::{Path, PathBuf};
+
+/// Create a directory in a virtual VFS, with `mode` (or the mount's default directory
+/// mode) restricted by the process `umask`.
+///
+/// Returns `Some(result)` if the path lives under a virtual or read-only mount, or `None` if the
+/// kernel should create the directory.
+async fn mkdir_virtual(
+    path: &Path,
+    mode: u32,
+    umask: u32,
+    mount_table: &MountTable,
+) -> Option<i64> {
+    let (vfs, _translated_path, read_only) = mount_table.resolve(path)?;
+    if read_only {
+        return Some(-libc::EROFS as i64);
+    }
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    let mode = mount_table.create_mode(path, mode, true);
+    Some(match vfs.mkdir(path, mode & !umask).await {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// The `mkdir` system call.
+///
+/// This intercepts `mkdir` system calls and creates directories in virtual mounts via
+/// `Vfs::mkdir`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_mkdir<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Mkdir,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        let umask = sandbox::umask(guest.pid().as_raw());
+        if let Some(result) = mkdir_virtual(&path, args.mode().bits(), umask, mount_table).await {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Mkdir(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `mkdirat` system call.
+///
+/// This intercepts `mkdirat` system calls and virtualizes the dirfd. For virtual mounts,
+/// the directory is created via `Vfs::mkdir`. For host mounts, the path is translated and
+/// `mkdirat` is injected with the kernel dirfd.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_mkdirat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Mkdirat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let umask = sandbox::umask(guest.pid().as_raw());
+    if let Some(result) = mkdir_virtual(&path, args.mode().bits(), umask, mount_table).await {
+        return Ok(Some(result));
+    }
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Mkdirat(new_syscall)).await?;
+    Ok(Some(result))
+}
+
+/// Size of the fixed part of `linux_dirent64`: d_ino, d_off, d_reclen and d_type.
+const DIRENT64_HEADER_LEN: usize = 19;
+
+/// Serialize directory entries as `linux_dirent64` records.
+///
+/// Entries are taken from `entries[start..]` until the next record would not fit in
+/// `count` bytes. Each record's `d_off` is the position of the entry that follows it,
+/// so it can be passed back to `lseek` to resume the listing. Returns the buffer and
+/// the number of entries consumed.
+fn serialize_dirents(entries: &[DirEntry], start: usize, count: usize) -> (Vec<u8>, usize) {
+    let mut buf = Vec::new();
+    let mut consumed = 0;
+
+    for (index, entry) in entries.iter().enumerate().skip(start) {
+        // Calculate record length (aligned to 8 bytes)
+        let name_len = entry.name.len() + 1; // +1 for null terminator
+        let reclen = (DIRENT64_HEADER_LEN + name_len).div_ceil(8) * 8;
+
+        if buf.len() + reclen > count {
+            break; // Not enough space
+        }
+
+        let next_offset = (index + 1) as i64;
+        buf.extend_from_slice(&entry.ino.to_ne_bytes()); // d_ino (u64)
+        buf.extend_from_slice(&next_offset.to_ne_bytes()); // d_off (i64)
+        buf.extend_from_slice(&(reclen as u16).to_ne_bytes()); // d_reclen (u16)
+        buf.push(entry.d_type); // d_type (u8)
+        buf.extend_from_slice(entry.name.as_bytes()); // d_name
+        buf.push(0); // null terminator
+
+        // Pad to 8-byte alignment
+        buf.resize(buf.len().next_multiple_of(8), 0);
+
+        consumed += 1;
+    }
+
+    (buf, consumed)
+}
+
+/// The `getdents64` system call for virtual directories.
+///
+/// This intercepts `getdents64` system calls on virtual FDs, lists the directory via
+/// `Vfs::readdir`, and writes as many `linux_dirent64` records as fit into the guest
+/// buffer. The directory position is advanced past the returned entries, so repeated
+/// calls page through the listing and return 0 once it is exhausted.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_getdents64<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Getdents64,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (vfs, file_ops) = match lookup_virtual(args.fd() as i32, mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    let dirent_addr = match args.dirent() {
+        Some(addr) => addr,
+        None => return Ok(Some(-libc::EFAULT as i64)),
+    };
+
+    let entries = match vfs.readdir(&file_ops).await {
+        Ok(entries) => entries,
+        Err(e) => return Ok(Some(e.to_errno())),
+    };
+
+    let position = match file_ops.seek(0, libc::SEEK_CUR).await {
+        Ok(position) => position as usize,
+        Err(e) => return Ok(Some(e.to_errno())),
+    };
+
+    let (buf, consumed) = serialize_dirents(&entries, position, args.count() as usize);
+    if consumed == 0 {
+        // Either the listing is exhausted, or the buffer is too small for the next entry
+        return Ok(Some(if position >= entries.len() {
+            0
+        } else {
+            -libc::EINVAL as i64
+        }));
+    }
+
+    guest.memory().write_exact(dirent_addr.cast::<u8>(), &buf)?;
+
+    // Advance the directory position past the entries we returned
+    if let Err(e) = file_ops
+        .seek((position + consumed) as i64, libc::SEEK_SET)
+        .await
+    {
+        return Ok(Some(e.to_errno()));
+    }
+
+    Ok(Some(buf.len() as i64))
+}
@@ -1,195 +1,50 @@
 use crate::{
-    sandbox::Sandbox,
-    syscall::translate_path,
+    sandbox::{self, Sandbox},
+    syscall::{
+        access::{caller_ids, permitted},
+        io::io_errno,
+        lock,
+        memory::{push_paths, read_path},
+        net, SyscallResult,
+    },
     vfs::{
-        fdtable::{FdEntry, FdTable},
+        fdtable::{FdEntry, FdTable, GuestCwd},
+        file::BoxedFileOps,
         mount::MountTable,
+        Vfs,
     },
 };
 use reverie::{
-    syscalls::{MemoryAccess, ReadAddr, Syscall},
-    Error, Guest, Stack,
+    syscalls::{AddrMut, MemoryAccess, ReadAddr, Syscall, SyscallArgs, Sysno},
+    Errno, Error, Guest, Stack,
+};
+use std::{
+    ffi::CString,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
 };
-use std::mem::MaybeUninit;
-
-/// The `openat` system call.
-///
-/// This intercepts `openat` system calls and translates paths according to the mount table,
-/// virtualizes the dirfd parameter, and virtualizes the returned file descriptor.
-///
-/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
-/// or `None` if the original syscall should be used.
-pub async fn handle_openat<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Openat,
-    mount_table: &MountTable,
-    fd_table: &FdTable,
-) -> Result<Option<i64>, Error> {
-    if let Some(path_addr) = args.path() {
-        // Read the original path from guest memory
-        let mut path: std::path::PathBuf = path_addr.read(&guest.memory())?;
-
-        // Handle dirfd resolution for relative paths
-        let dirfd = args.dirfd();
-        let kernel_dirfd = if dirfd == libc::AT_FDCWD {
-            dirfd
-        } else if path.is_relative() {
-            // For relative paths, resolve against dirfd
-            if let Some(dir_entry) = fd_table.get(dirfd) {
-                // Check if this is a passthrough directory with a kernel FD first
-                if let Some(kfd) = dir_entry.kernel_fd() {
-                    // Passthrough directory - use the kernel FD and keep path as-is
-                    kfd
-                } else if let Some(dir_path) = dir_entry.path() {
-                    // Virtual directory - resolve relative path against the directory's path
-                    path = dir_path.join(&path);
-                    // For virtual directories, we'll use AT_FDCWD since we have the full path now
-                    libc::AT_FDCWD
-                } else {
-                    // Virtual file without a path - this shouldn't happen for directories
-                    return Ok(Some(-libc::EBADF as i64));
-                }
-            } else {
-                // dirfd not in table - will likely fail
-                dirfd
-            }
-        } else {
-            // Absolute path - dirfd is ignored, use AT_FDCWD
-            libc::AT_FDCWD
-        };
-
-        // Check if this path matches a mount point
-        if let Some((vfs, _translated_path)) = mount_table.resolve(&path) {
-            // Check if this is a virtual VFS (like SQLite)
-            if vfs.is_virtual() {
-                // For virtual VFS, open the file directly without going to the kernel
-                let mode = args.mode().map(|m| m.bits()).unwrap_or(0o644);
-                match vfs.open(&path, args.flags().bits(), mode).await {
-                    Ok(file_ops) => {
-                        // Store the path with the FD entry for directories
-                        let entry = FdEntry::Virtual {
-                            file_ops,
-                            flags: args.flags().bits(),
-                            path: Some(path.clone()),
-                        };
-                        let virtual_fd = fd_table.allocate(entry);
-                        return Ok(Some(virtual_fd as i64));
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EIO as i64,
-                        };
-                        return Ok(Some(errno));
-                    }
-                }
-            } else {
-                // For passthrough VFS, translate the path and call the kernel
-                let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
-
-                let new_syscall = reverie::syscalls::Openat::new()
-                    .with_dirfd(kernel_dirfd)
-                    .with_path(new_path_addr.or(Some(path_addr)))
-                    .with_flags(args.flags())
-                    .with_mode(args.mode());
-
-                let kernel_fd = guest.inject(Syscall::Openat(new_syscall)).await?;
-
-                if kernel_fd >= 0 {
-                    // Mounted path - create passthrough FD entry
-                    let entry = FdEntry::Passthrough {
-                        kernel_fd: kernel_fd as i32,
-                        flags: args.flags().bits(),
-                        path: Some(path.clone()),
-                    };
-                    let virtual_fd = fd_table.allocate(entry);
-                    return Ok(Some(virtual_fd as i64));
-                } else {
-                    return Ok(Some(kernel_fd));
-                }
-            }
-        } else {
-            // No mount point matches - pass through to kernel with original path
-            let new_syscall = reverie::syscalls::Openat::new()
-                .with_dirfd(kernel_dirfd)
-                .with_path(Some(path_addr))
-                .with_flags(args.flags())
-                .with_mode(args.mode());
-
-            let kernel_fd = guest.inject(Syscall::Openat(new_syscall)).await?;
-
-            if kernel_fd >= 0 {
-                // Unmounted path - create passthrough FD entry
-                let entry = FdEntry::Passthrough {
-                    kernel_fd: kernel_fd as i32,
-                    flags: args.flags().bits(),
-                    path: Some(path.clone()),
-                };
-                let virtual_fd = fd_table.allocate(entry);
-                return Ok(Some(virtual_fd as i64));
-            } else {
-                return Ok(Some(kernel_fd));
-            }
-        }
-    }
-    Ok(None)
-}
 
 /// The `read` system call.
 ///
-/// This intercepts `read` system calls and translates virtual FDs to kernel FDs,
-/// or calls FileOps directly for virtual files.
+/// This intercepts `read` system calls and translates virtual FDs to kernel FDs.
+/// Reads from virtual files are served by `io::handle_read`.
 pub async fn handle_read<T: Guest<Sandbox>>(
-    guest: &mut T,
+    _guest: &mut T,
     syscall: Syscall,
     args: &reverie::syscalls::Read,
     fd_table: &FdTable,
 ) -> Result<crate::syscall::SyscallResult, Error> {
     let virtual_fd = args.fd();
 
-    // Get the FD entry
-    if let Some(entry) = fd_table.get(virtual_fd) {
-        match entry {
-            FdEntry::Passthrough { kernel_fd, .. } => {
-                // Passthrough file - rewrite FD and return modified syscall for tail_inject
-                let new_syscall = args.with_fd(kernel_fd);
-
-                return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Read(
-                    new_syscall,
-                )));
-            }
-            FdEntry::Virtual { file_ops, .. } => {
-                // Virtual file - use FileOps directly
-                let buf_addr = match args.buf() {
-                    Some(addr) => addr,
-                    None => return Ok(crate::syscall::SyscallResult::Value(-libc::EFAULT as i64)),
-                };
-
-                let buf_len = args.len();
-                let mut buf = vec![0u8; buf_len];
+    // Translate virtual FD to kernel FD
+    if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
+        // Passthrough file - rewrite FD and return modified syscall for tail_inject
+        let new_syscall = args.with_fd(kernel_fd);
 
-                match file_ops.read(&mut buf).await {
-                    Ok(n) => {
-                        // Write the data back to guest memory
-                        if n > 0 {
-                            guest.memory().write_exact(buf_addr, &buf[..n])?;
-                        }
-                        return Ok(crate::syscall::SyscallResult::Value(n as i64));
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EIO as i64,
-                        };
-                        return Ok(crate::syscall::SyscallResult::Value(errno));
-                    }
-                }
-            }
-        }
+        return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Read(
+            new_syscall,
+        )));
     }
 
     // FD not in table, let the original syscall through (will likely fail with EBADF)
@@ -198,68 +53,106 @@ pub async fn handle_read<T: Guest<Sandbox>>(
 
 /// The `write` system call.
 ///
-/// This intercepts `write` system calls and translates virtual FDs to kernel FDs,
-/// or calls FileOps directly for virtual files.
+/// This intercepts `write` system calls and translates virtual FDs to kernel FDs.
+/// Writes to virtual files are served by `io::handle_write`.
 pub async fn handle_write<T: Guest<Sandbox>>(
-    guest: &mut T,
+    _guest: &mut T,
     syscall: Syscall,
     args: &reverie::syscalls::Write,
     fd_table: &FdTable,
 ) -> Result<crate::syscall::SyscallResult, Error> {
     let virtual_fd = args.fd();
 
-    // Get the FD entry
-    if let Some(entry) = fd_table.get(virtual_fd) {
-        match entry {
-            FdEntry::Passthrough { kernel_fd, .. } => {
-                // Passthrough file - rewrite FD and return modified syscall for tail_inject
-                let new_syscall = args.with_fd(kernel_fd);
+    // Translate virtual FD to kernel FD
+    if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
+        // Passthrough file - rewrite FD and return modified syscall for tail_inject
+        let new_syscall = args.with_fd(kernel_fd);
 
-                return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Write(
-                    new_syscall,
-                )));
-            }
-            FdEntry::Virtual { file_ops, .. } => {
-                // Virtual file - use FileOps directly
-                let buf_addr = match args.buf() {
-                    Some(addr) => addr,
-                    None => return Ok(crate::syscall::SyscallResult::Value(-libc::EFAULT as i64)),
-                };
+        return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Write(
+            new_syscall,
+        )));
+    }
 
-                let buf_len = args.len();
-                let mut buf = vec![0u8; buf_len];
+    // FD not in table, let the original syscall through (will likely fail with EBADF)
+    Ok(crate::syscall::SyscallResult::Syscall(syscall))
+}
 
-                // Read data from guest memory
-                guest.memory().read_exact(buf_addr, &mut buf)?;
+/// The `fsync` system call.
+///
+/// This intercepts `fsync` system calls and translates virtual FDs to kernel FDs.
+/// Virtual files are synced by `io::handle_fsync`.
+pub async fn handle_fsync<T: Guest<Sandbox>>(
+    _guest: &mut T,
+    syscall: Syscall,
+    args: &reverie::syscalls::Fsync,
+    fd_table: &FdTable,
+) -> Result<crate::syscall::SyscallResult, Error> {
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        let new_syscall = args.with_fd(kernel_fd);
+        return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Fsync(
+            new_syscall,
+        )));
+    }
 
-                match file_ops.write(&buf).await {
-                    Ok(n) => {
-                        return Ok(crate::syscall::SyscallResult::Value(n as i64));
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EIO as i64,
-                        };
-                        return Ok(crate::syscall::SyscallResult::Value(errno));
-                    }
-                }
-            }
-        }
+    // FD not in table, let the original syscall through (will likely fail with EBADF)
+    Ok(crate::syscall::SyscallResult::Syscall(syscall))
+}
+
+/// The `fdatasync` system call.
+///
+/// This intercepts `fdatasync` system calls and translates virtual FDs to kernel FDs.
+/// Virtual files are synced by `io::handle_fdatasync`.
+pub async fn handle_fdatasync<T: Guest<Sandbox>>(
+    _guest: &mut T,
+    syscall: Syscall,
+    args: &reverie::syscalls::Fdatasync,
+    fd_table: &FdTable,
+) -> Result<crate::syscall::SyscallResult, Error> {
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        let new_syscall = args.with_fd(kernel_fd);
+        return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Fdatasync(
+            new_syscall,
+        )));
     }
 
     // FD not in table, let the original syscall through (will likely fail with EBADF)
     Ok(crate::syscall::SyscallResult::Syscall(syscall))
 }
 
+/// Close the kernel FD or virtual file handle behind an entry removed from the FD table.
+///
+/// `last` tells whether the entry held the last reference to its virtual file handle;
+/// a handle still shared with other FDs is left open.
+pub(crate) async fn close_entry<T: Guest<Sandbox>>(
+    guest: &mut T,
+    entry: FdEntry,
+    last: bool,
+) -> Result<(), Error> {
+    match entry {
+        FdEntry::Passthrough { kernel_fd, .. } => {
+            guest
+                .inject(Syscall::Close(
+                    reverie::syscalls::Close::new().with_fd(kernel_fd),
+                ))
+                .await?;
+        }
+        FdEntry::Virtual { file_ops, .. } => {
+            lock::release_locks(guest.pid().as_raw(), &file_ops, last).await;
+            if last {
+                file_ops.close().await.ok();
+            }
+        }
+    }
+    Ok(())
+}
+
 /// The `close` system call.
 ///
 /// This intercepts `close` system calls, translates virtual FDs to kernel FDs,
-/// and cleans up the FD mapping.
+/// and cleans up the FD mapping. A virtual file is closed along with the last FD
+/// referring to it.
 pub async fn handle_close<T: Guest<Sandbox>>(
-    _guest: &mut T,
+    guest: &mut T,
     syscall: Syscall,
     args: &reverie::syscalls::Close,
     fd_table: &FdTable,
@@ -267,7 +160,7 @@ pub async fn handle_close<T: Guest<Sandbox>>(
     let virtual_fd = args.fd();
 
     // Translate and deallocate the virtual FD
-    if let Some(entry) = fd_table.deallocate(virtual_fd) {
+    if let Some((entry, last)) = fd_table.release(virtual_fd) {
         match entry {
             FdEntry::Passthrough { kernel_fd, .. } => {
                 // Passthrough file - rewrite FD and return modified syscall for tail_inject
@@ -278,8 +171,11 @@ pub async fn handle_close<T: Guest<Sandbox>>(
                 )));
             }
             FdEntry::Virtual { file_ops, .. } => {
-                // Virtualized file - just call close on the FileOps
-                file_ops.close().await.ok();
+                // Virtualized file - close the FileOps unless another FD still shares it
+                lock::release_locks(guest.pid().as_raw(), &file_ops, last).await;
+                if last {
+                    file_ops.close().await.ok();
+                }
                 return Ok(crate::syscall::SyscallResult::Value(0)); // Success
             }
         }
@@ -292,6 +188,7 @@ pub async fn handle_close<T: Guest<Sandbox>>(
 /// The `dup` system call.
 ///
 /// This intercepts `dup` system calls and duplicates both the virtual and kernel FDs.
+/// A duplicated virtual FD shares the file handle, and with it the file offset.
 pub async fn handle_dup<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Dup,
@@ -315,11 +212,12 @@ pub async fn handle_dup<T: Guest<Sandbox>>(
                     return Ok(Some(result));
                 }
 
-                // Create a new passthrough FD entry with the new kernel FD
+                // Create a new passthrough FD entry with the new kernel FD.
+                // Like the kernel, dup() does not carry over O_CLOEXEC.
                 let new_kernel_fd = result as i32;
                 let entry = FdEntry::Passthrough {
                     kernel_fd: new_kernel_fd,
-                    flags,
+                    flags: flags & !libc::O_CLOEXEC,
                     path,
                 };
 
@@ -328,6 +226,9 @@ pub async fn handle_dup<T: Guest<Sandbox>>(
                 return Ok(Some(new_vfd as i64));
             }
             FdEntry::Virtual { .. } => {
+                if fd_table.is_full() {
+                    return Ok(Some(-libc::EMFILE as i64));
+                }
                 // Virtualized file - just duplicate the virtual FD
                 if let Some(new_vfd) = fd_table.duplicate(old_vfd) {
                     return Ok(Some(new_vfd as i64));
@@ -340,97 +241,112 @@ pub async fn handle_dup<T: Guest<Sandbox>>(
     Ok(None)
 }
 
-/// The `dup2` system call.
+/// Duplicate `old_vfd` onto `new_vfd`, closing whatever `new_vfd` referred to.
 ///
-/// This intercepts `dup2` system calls and handles virtual FD duplication.
-#[cfg(not(target_arch = "aarch64"))]
-pub async fn handle_dup2<T: Guest<Sandbox>>(
+/// The new FD keeps the flags of `old_vfd`, except that `O_CLOEXEC` is set only if
+/// `cloexec` is. Passthrough FDs get a fresh kernel FD, while virtual FDs share the
+/// file handle of `old_vfd`.
+///
+/// Returns `None` if `old_vfd` is not in the FD table.
+async fn dup_to<T: Guest<Sandbox>>(
     guest: &mut T,
-    args: &reverie::syscalls::Dup2,
+    old_vfd: i32,
+    new_vfd: i32,
+    cloexec: bool,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    let old_vfd = args.oldfd();
-    let new_vfd = args.newfd();
+    use reverie::syscalls::FcntlCmd;
 
-    // Get the entry for the old virtual FD
-    if let Some(old_entry) = fd_table.get(old_vfd) {
-        // Get the entry at new_vfd if it exists (we need to close its kernel FD)
-        let old_new_entry = fd_table.get(new_vfd);
+    let old_entry = match fd_table.get(old_vfd) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
 
-        match old_entry {
-            FdEntry::Passthrough {
-                kernel_fd: old_kernel_fd,
-                flags: _,
-                path,
-            } => {
-                // Allocate a new kernel FD - we need to duplicate to a fresh FD first,
-                // then close the old one if needed, to avoid race conditions
-                let new_kernel_fd = guest
-                    .inject(Syscall::Dup(
-                        reverie::syscalls::Dup::new().with_oldfd(old_kernel_fd),
-                    ))
-                    .await?;
-
-                if new_kernel_fd < 0 {
-                    // Dup failed, return the error
-                    return Ok(Some(new_kernel_fd));
-                }
+    let mut flags = old_entry.flags() & !libc::O_CLOEXEC;
+    if cloexec {
+        flags |= libc::O_CLOEXEC;
+    }
 
-                // Close the old kernel FD at new_vfd if it existed
-                if let Some(old_entry) = old_new_entry {
-                    match old_entry {
-                        FdEntry::Passthrough { kernel_fd, .. } => {
-                            let _ = guest
-                                .inject(Syscall::Close(
-                                    reverie::syscalls::Close::new().with_fd(kernel_fd),
-                                ))
-                                .await?;
-                        }
-                        FdEntry::Virtual { file_ops, .. } => {
-                            // Close the FileOps if it's a virtualized file
-                            file_ops.close().await.ok();
-                        }
-                    }
-                }
+    let entry = match old_entry {
+        FdEntry::Passthrough {
+            kernel_fd, path, ..
+        } => {
+            // Duplicate to a fresh kernel FD first, so that a failure leaves new_vfd
+            // untouched. The kernel FD carries O_CLOEXEC too, for the kernel's execve.
+            let cmd = if cloexec {
+                FcntlCmd::F_DUPFD_CLOEXEC(0)
+            } else {
+                FcntlCmd::F_DUPFD(0)
+            };
+            let new_kernel_fd = guest
+                .inject(Syscall::Fcntl(
+                    reverie::syscalls::Fcntl::new()
+                        .with_fd(kernel_fd)
+                        .with_cmd(cmd),
+                ))
+                .await?;
+
+            if new_kernel_fd < 0 {
+                // Dup failed, return the error
+                return Ok(Some(new_kernel_fd));
+            }
 
-                // Create new passthrough FD entry for the duplicated kernel FD
-                let entry = FdEntry::Passthrough {
-                    kernel_fd: new_kernel_fd as i32,
-                    flags: 0,
-                    path,
-                };
-                let _ = fd_table.allocate_at(new_vfd, entry);
+            FdEntry::Passthrough {
+                kernel_fd: new_kernel_fd as i32,
+                flags,
+                path,
             }
-            FdEntry::Virtual { .. } => {
-                // Virtualized file - close old entry at new_vfd if exists, then duplicate
-                if let Some(old_entry) = old_new_entry {
-                    match old_entry {
-                        FdEntry::Virtual { file_ops, .. } => {
-                            file_ops.close().await.ok();
-                        }
-                        FdEntry::Passthrough { kernel_fd, .. } => {
-                            let _ = guest
-                                .inject(Syscall::Close(
-                                    reverie::syscalls::Close::new().with_fd(kernel_fd),
-                                ))
-                                .await?;
-                        }
-                    }
-                }
-                let _ = fd_table.allocate_at(new_vfd, old_entry);
+        }
+        entry @ FdEntry::Virtual { .. } => {
+            // Replacing a virtual FD doesn't change the number of them that are open
+            let replaces_virtual = fd_table
+                .get(new_vfd)
+                .is_some_and(|replaced| replaced.file_ops().is_some());
+            if !replaces_virtual && fd_table.is_full() {
+                return Ok(Some(-libc::EMFILE as i64));
             }
+            entry.with_flags(flags)
         }
+    };
 
-        Ok(Some(new_vfd as i64))
-    } else {
-        // FD not in table, let the original syscall through
-        Ok(None)
+    // Install the new entry before closing the one it replaces, so new_vfd is
+    // never free in between
+    if let Some((replaced, last)) = fd_table.replace(new_vfd, entry) {
+        close_entry(guest, replaced, last).await?;
+    }
+
+    Ok(Some(new_vfd as i64))
+}
+
+/// The `dup2` system call.
+///
+/// This intercepts `dup2` system calls and handles virtual FD duplication. Any FD
+/// open at `newfd` is closed first, and the new FD does not have `O_CLOEXEC` set.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_dup2<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Dup2,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let old_vfd = args.oldfd();
+    let new_vfd = args.newfd();
+
+    if old_vfd == new_vfd {
+        // Nothing to do, but the FD must still be valid
+        return Ok(fd_table.get(old_vfd).map(|_| new_vfd as i64));
+    }
+    if new_vfd < 0 {
+        return Ok(Some(-libc::EBADF as i64));
     }
+
+    dup_to(guest, old_vfd, new_vfd, false, fd_table).await
 }
 
 /// The `dup3` system call.
 ///
 /// This intercepts `dup3` system calls and handles virtual FD duplication with flags.
+/// Like `dup2`, any FD open at `newfd` is closed first. `O_CLOEXEC` is recorded in
+/// the FD table so that it can be honored on exec.
 pub async fn handle_dup3<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Dup3,
@@ -438,93 +354,18 @@ pub async fn handle_dup3<T: Guest<Sandbox>>(
 ) -> Result<Option<i64>, Error> {
     let old_vfd = args.oldfd();
     let new_vfd = args.newfd();
-    let flags = args.flags();
-
-    // Get the entry for the old virtual FD
-    if let Some(old_entry) = fd_table.get(old_vfd) {
-        // Get the entry at new_vfd if it exists (we need to close its kernel FD)
-        let old_new_entry = fd_table.get(new_vfd);
-
-        match old_entry {
-            FdEntry::Passthrough {
-                kernel_fd: old_kernel_fd,
-                path,
-                ..
-            } => {
-                // Allocate a new kernel FD - we need to duplicate to a fresh FD first,
-                // then close the old one if needed, to avoid race conditions
-                let new_kernel_fd = guest
-                    .inject(Syscall::Dup(
-                        reverie::syscalls::Dup::new().with_oldfd(old_kernel_fd),
-                    ))
-                    .await?;
-
-                if new_kernel_fd < 0 {
-                    // Dup failed, return the error
-                    return Ok(Some(new_kernel_fd));
-                }
-
-                // Close the old kernel FD at new_vfd if it existed
-                if let Some(old_entry) = old_new_entry {
-                    match old_entry {
-                        FdEntry::Passthrough { kernel_fd, .. } => {
-                            let _ = guest
-                                .inject(Syscall::Close(
-                                    reverie::syscalls::Close::new().with_fd(kernel_fd),
-                                ))
-                                .await?;
-                        }
-                        FdEntry::Virtual { file_ops, .. } => {
-                            file_ops.close().await.ok();
-                        }
-                    }
-                }
-
-                // Note: dup3 flags (O_CLOEXEC) are stored in the FD table and will be
-                // applied later when needed (e.g., on exec). The kernel FD itself doesn't
-                // need the flag since we're virtualizing the behavior.
-
-                // Create new passthrough FD entry for the duplicated kernel FD
-                let entry = FdEntry::Passthrough {
-                    kernel_fd: new_kernel_fd as i32,
-                    flags: flags.bits(),
-                    path,
-                };
-                let _ = fd_table.allocate_at(new_vfd, entry);
-            }
-            FdEntry::Virtual { .. } => {
-                // Virtualized file - close old entry at new_vfd if exists, then duplicate
-                if let Some(old_entry) = old_new_entry {
-                    match old_entry {
-                        FdEntry::Virtual { file_ops, .. } => {
-                            file_ops.close().await.ok();
-                        }
-                        FdEntry::Passthrough { kernel_fd, .. } => {
-                            let _ = guest
-                                .inject(Syscall::Close(
-                                    reverie::syscalls::Close::new().with_fd(kernel_fd),
-                                ))
-                                .await?;
-                        }
-                    }
-                }
-                let entry = match old_entry {
-                    FdEntry::Virtual { file_ops, path, .. } => FdEntry::Virtual {
-                        file_ops,
-                        flags: flags.bits(),
-                        path,
-                    },
-                    _ => unreachable!(),
-                };
-                let _ = fd_table.allocate_at(new_vfd, entry);
-            }
-        }
+    let flags = args.flags().bits();
 
-        Ok(Some(new_vfd as i64))
-    } else {
-        // FD not in table, let the original syscall through
-        Ok(None)
+    // Unlike dup2, dup3 rejects duplicating an FD onto itself
+    if flags & !libc::O_CLOEXEC != 0 || old_vfd == new_vfd {
+        return Ok(Some(-libc::EINVAL as i64));
     }
+    if new_vfd < 0 {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let cloexec = flags & libc::O_CLOEXEC != 0;
+    dup_to(guest, old_vfd, new_vfd, cloexec, fd_table).await
 }
 
 /// The `ioctl` system call.
@@ -558,11 +399,75 @@ pub async fn handle_ioctl<T: Guest<Sandbox>>(
     Ok(None)
 }
 
+/// Status flags that `F_SETFL` may change on a virtual FD.
+const SETFL_FLAGS: i32 = libc::O_APPEND | libc::O_NONBLOCK;
+
+/// Open flags that only apply while opening a file, which `F_GETFL` does not report.
+const OPEN_ONLY_FLAGS: i32 =
+    libc::O_CREAT | libc::O_EXCL | libc::O_NOCTTY | libc::O_TRUNC | libc::O_CLOEXEC;
+
+/// Perform an `fcntl` command on a virtual FD.
+///
+/// The status flags (access mode, `O_APPEND`, `O_NONBLOCK`) belong to the file handle
+/// and are shared with duplicates, while `FD_CLOEXEC` is recorded per FD in the FD table.
+/// Other commands are not supported on virtual FDs and fail with `EINVAL`.
+fn fcntl_virtual(
+    vfd: i32,
+    entry: &FdEntry,
+    file_ops: &BoxedFileOps,
+    cmd: reverie::syscalls::FcntlCmd,
+    fd_table: &FdTable,
+) -> i64 {
+    use reverie::syscalls::FcntlCmd;
+
+    match cmd {
+        FcntlCmd::F_DUPFD(min) | FcntlCmd::F_DUPFD_CLOEXEC(min) => {
+            if min < 0 {
+                return -libc::EINVAL as i64;
+            }
+            if fd_table.is_full() {
+                return -libc::EMFILE as i64;
+            }
+            let mut flags = entry.flags() & !libc::O_CLOEXEC;
+            if matches!(cmd, FcntlCmd::F_DUPFD_CLOEXEC(_)) {
+                flags |= libc::O_CLOEXEC;
+            }
+            fd_table.allocate_min(min, entry.with_flags(flags)) as i64
+        }
+        FcntlCmd::F_GETFD => {
+            if entry.is_cloexec() {
+                libc::FD_CLOEXEC as i64
+            } else {
+                0
+            }
+        }
+        FcntlCmd::F_SETFD(arg) => {
+            let mut flags = entry.flags() & !libc::O_CLOEXEC;
+            if arg & libc::FD_CLOEXEC != 0 {
+                flags |= libc::O_CLOEXEC;
+            }
+            fd_table.set_flags(vfd, flags);
+            0
+        }
+        FcntlCmd::F_GETFL => (file_ops.get_flags() & !OPEN_ONLY_FLAGS) as i64,
+        FcntlCmd::F_SETFL(arg) => {
+            let flags = (file_ops.get_flags() & !SETFL_FLAGS) | (arg & SETFL_FLAGS);
+            match file_ops.set_flags(flags) {
+                Ok(()) => 0,
+                Err(e) => e.to_errno(),
+            }
+        }
+        _ => -libc::EINVAL as i64,
+    }
+}
+
 /// The `fcntl` system call.
 ///
 /// This intercepts `fcntl` system calls and handles virtual FD operations.
 /// Special handling is needed for F_DUPFD and F_DUPFD_CLOEXEC commands which
-/// duplicate file descriptors.
+/// duplicate file descriptors, and for F_SETFD which changes the close-on-exec
+/// flag recorded in the FD table. Record locks on virtual FDs are served by
+/// `lock::fcntl_lock`, and their other commands by `fcntl_virtual`.
 pub async fn handle_fcntl<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Fcntl,
@@ -572,68 +477,96 @@ pub async fn handle_fcntl<T: Guest<Sandbox>>(
 
     let virtual_fd = args.fd();
 
-    // Translate virtual FD to kernel FD
-    if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
-        match args.cmd() {
-            FcntlCmd::F_DUPFD(arg) | FcntlCmd::F_DUPFD_CLOEXEC(arg) => {
-                // For dup commands, we need to:
-                // 1. Execute the syscall with the kernel FD to get a new kernel FD
-                // 2. Allocate a new virtual FD for the result
-
-                let is_cloexec = matches!(args.cmd(), FcntlCmd::F_DUPFD_CLOEXEC(_));
-                // O_CLOEXEC = 0o2000000 on Linux
-                let flags = if is_cloexec { 0o2000000 } else { 0 };
-
-                // Translate the arg if it's a virtual FD (it specifies minimum FD number)
-                // For now, we'll use 0 as the minimum for the kernel FD since we're virtualizing
-                let kernel_arg = 0;
-
-                let new_cmd = if is_cloexec {
-                    FcntlCmd::F_DUPFD_CLOEXEC(kernel_arg)
-                } else {
-                    FcntlCmd::F_DUPFD(kernel_arg)
-                };
+    // FD not in table, let the original syscall through (will likely fail with EBADF)
+    let entry = match fd_table.get(virtual_fd) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
 
-                let new_syscall = reverie::syscalls::Fcntl::new()
-                    .with_fd(kernel_fd)
-                    .with_cmd(new_cmd);
-
-                let new_kernel_fd = guest.inject(Syscall::Fcntl(new_syscall)).await?;
-
-                // If the syscall succeeded, allocate a new virtual FD
-                if new_kernel_fd >= 0 {
-                    // Get the old entry to preserve the path
-                    let old_entry = fd_table.get(virtual_fd);
-                    let fd_path = old_entry.as_ref().and_then(|e| e.path());
-
-                    // Create passthrough FD entry for the new kernel FD
-                    let entry = FdEntry::Passthrough {
-                        kernel_fd: new_kernel_fd as i32,
-                        flags,
-                        path: fd_path.cloned(),
-                    };
-                    // Allocate virtual FD at or above the requested minimum
-                    let new_vfd = fd_table.allocate_min(arg, entry);
-                    return Ok(Some(new_vfd as i64));
-                } else {
-                    // Return the error code as-is
-                    return Ok(Some(new_kernel_fd));
+    if let Some(file_ops) = entry.file_ops() {
+        if let Some(result) = lock::fcntl_lock(guest, file_ops, args.cmd()).await? {
+            return Ok(Some(result));
+        }
+        let result = fcntl_virtual(virtual_fd, &entry, file_ops, args.cmd(), fd_table);
+        return Ok(Some(result));
+    }
+    let kernel_fd = match entry.kernel_fd() {
+        Some(kernel_fd) => kernel_fd,
+        None => return Ok(None),
+    };
+
+    match args.cmd() {
+        FcntlCmd::F_DUPFD(arg) | FcntlCmd::F_DUPFD_CLOEXEC(arg) => {
+            // For dup commands, we need to:
+            // 1. Execute the syscall with the kernel FD to get a new kernel FD
+            // 2. Allocate a new virtual FD for the result
+
+            let is_cloexec = matches!(args.cmd(), FcntlCmd::F_DUPFD_CLOEXEC(_));
+
+            // Translate the arg if it's a virtual FD (it specifies minimum FD number)
+            // For now, we'll use 0 as the minimum for the kernel FD since we're virtualizing
+            let kernel_arg = 0;
+
+            let new_cmd = if is_cloexec {
+                FcntlCmd::F_DUPFD_CLOEXEC(kernel_arg)
+            } else {
+                FcntlCmd::F_DUPFD(kernel_arg)
+            };
+
+            let new_syscall = reverie::syscalls::Fcntl::new()
+                .with_fd(kernel_fd)
+                .with_cmd(new_cmd);
+
+            let new_kernel_fd = guest.inject(Syscall::Fcntl(new_syscall)).await?;
+
+            // If the syscall succeeded, allocate a new virtual FD
+            if new_kernel_fd >= 0 {
+                // Preserve the flags and path of the old entry
+                let mut flags = entry.flags() & !libc::O_CLOEXEC;
+                if is_cloexec {
+                    flags |= libc::O_CLOEXEC;
                 }
+
+                // Create passthrough FD entry for the new kernel FD
+                let entry = FdEntry::Passthrough {
+                    kernel_fd: new_kernel_fd as i32,
+                    flags,
+                    path: entry.path().cloned(),
+                };
+                // Allocate virtual FD at or above the requested minimum
+                let new_vfd = fd_table.allocate_min(arg, entry);
+                Ok(Some(new_vfd as i64))
+            } else {
+                // Return the error code as-is
+                Ok(Some(new_kernel_fd))
             }
-            _ => {
-                // For other fcntl commands, just translate the FD and pass through
-                let new_syscall = reverie::syscalls::Fcntl::new()
-                    .with_fd(kernel_fd)
-                    .with_cmd(args.cmd());
-
-                let result = guest.inject(Syscall::Fcntl(new_syscall)).await?;
-                return Ok(Some(result));
+        }
+        FcntlCmd::F_SETFD(arg) => {
+            let new_syscall = reverie::syscalls::Fcntl::new()
+                .with_fd(kernel_fd)
+                .with_cmd(args.cmd());
+            let result = guest.inject(Syscall::Fcntl(new_syscall)).await?;
+
+            // Keep the close-on-exec flag in the FD table in sync with the kernel's
+            if result >= 0 {
+                let mut flags = entry.flags() & !libc::O_CLOEXEC;
+                if arg & libc::FD_CLOEXEC != 0 {
+                    flags |= libc::O_CLOEXEC;
+                }
+                fd_table.set_flags(virtual_fd, flags);
             }
+            Ok(Some(result))
+        }
+        _ => {
+            // For other fcntl commands, just translate the FD and pass through
+            let new_syscall = reverie::syscalls::Fcntl::new()
+                .with_fd(kernel_fd)
+                .with_cmd(args.cmd());
+
+            let result = guest.inject(Syscall::Fcntl(new_syscall)).await?;
+            Ok(Some(result))
         }
     }
-
-    // FD not in table, let the original syscall through (will likely fail with EBADF)
-    Ok(None)
 }
 
 /// Helper functions for working with fd_set
@@ -852,106 +785,12 @@ pub async fn handle_pselect6<T: Guest<Sandbox>>(
     Ok(Some(result))
 }
 
-/// The `poll` system call.
-///
-/// This intercepts `poll` system calls and translates virtual FDs in the pollfd array
-/// to kernel FDs before calling the real syscall, then translates the results back.
-#[cfg(not(target_arch = "aarch64"))]
-pub async fn handle_poll<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Poll,
-    fd_table: &FdTable,
-) -> Result<Option<i64>, Error> {
-    use reverie::syscalls::{MemoryAccess, PollFd};
-
-    let nfds = args.nfds();
-    if nfds == 0 {
-        return Ok(None);
-    }
-
-    let fds_addr = match args.fds() {
-        Some(addr) => addr,
-        None => return Ok(None),
-    };
-
-    // Read the pollfd array from guest memory
-    let mut pollfds: Vec<PollFd> = Vec::with_capacity(nfds as usize);
-    for i in 0..nfds {
-        let offset = i as isize * std::mem::size_of::<PollFd>() as isize;
-        let pollfd: PollFd = unsafe { guest.memory().read_value(fds_addr.offset(offset))? };
-        pollfds.push(pollfd);
-    }
-
-    // Allocate space on stack for kernel pollfd array
-    let mut stack = guest.stack().await;
-    let kernel_fds_addr: reverie::syscalls::AddrMut<PollFd> = stack.reserve();
-
-    // Reserve space for remaining pollfds
-    for _ in 1..nfds {
-        let _: reverie::syscalls::AddrMut<PollFd> = stack.reserve();
-    }
-
-    stack.commit()?;
-
-    // Write kernel pollfds to guest memory
-    for (i, pollfd) in pollfds.iter().enumerate() {
-        let kernel_fd = fd_table.translate(pollfd.fd).unwrap_or(pollfd.fd);
-        let kernel_pollfd = PollFd {
-            fd: kernel_fd,
-            events: pollfd.events,
-            revents: reverie::syscalls::PollFlags::empty(),
-        };
-
-        let offset = i as isize * std::mem::size_of::<PollFd>() as isize;
-        unsafe {
-            guest
-                .memory()
-                .write_value(kernel_fds_addr.offset(offset), &kernel_pollfd)?;
-        }
-    }
-
-    // Create and inject the syscall with translated FDs
-    let new_syscall = reverie::syscalls::Poll::new()
-        .with_fds(Some(kernel_fds_addr))
-        .with_nfds(nfds)
-        .with_timeout(args.timeout());
-
-    let result = guest.inject(Syscall::Poll(new_syscall)).await?;
-
-    // If the syscall failed or timed out, return early
-    if result <= 0 {
-        return Ok(Some(result));
-    }
-
-    // Read back the kernel pollfds and translate to virtual FDs
-    for i in 0..nfds {
-        let offset = i as isize * std::mem::size_of::<PollFd>() as isize;
-        let kernel_pollfd: PollFd =
-            unsafe { guest.memory().read_value(kernel_fds_addr.offset(offset))? };
-
-        // Write back the revents to the original pollfd array
-        let virt_pollfd = PollFd {
-            fd: pollfds[i as usize].fd, // Keep the virtual FD
-            events: pollfds[i as usize].events,
-            revents: kernel_pollfd.revents,
-        };
-
-        unsafe {
-            guest
-                .memory()
-                .write_value(fds_addr.offset(offset), &virt_pollfd)?;
-        }
-    }
-
-    Ok(Some(result))
-}
-
 /// The `getdents64` system call.
 ///
-/// This intercepts `getdents64` system calls and translates virtual FDs to kernel FDs,
-/// or calls FileOps::getdents() for virtual files.
+/// This intercepts `getdents64` system calls and translates virtual FDs to kernel FDs.
+/// Virtual directories are handled by `dir::handle_getdents64`.
 pub async fn handle_getdents64<T: Guest<Sandbox>>(
-    guest: &mut T,
+    _guest: &mut T,
     syscall: Syscall,
     args: &reverie::syscalls::Getdents64,
     fd_table: &FdTable,
@@ -959,73 +798,13 @@ pub async fn handle_getdents64<T: Guest<Sandbox>>(
     let virtual_fd = args.fd() as i32;
 
     // Get the FD entry
-    if let Some(entry) = fd_table.get(virtual_fd) {
-        match entry {
-            FdEntry::Passthrough { kernel_fd, .. } => {
-                // Passthrough file - rewrite FD and return modified syscall for tail_inject
-                let new_syscall = args.with_fd(kernel_fd as u32);
+    if let Some(FdEntry::Passthrough { kernel_fd, .. }) = fd_table.get(virtual_fd) {
+        // Passthrough file - rewrite FD and return modified syscall for tail_inject
+        let new_syscall = args.with_fd(kernel_fd as u32);
 
-                return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Getdents64(
-                    new_syscall,
-                )));
-            }
-            FdEntry::Virtual { file_ops, .. } => {
-                // Virtual file - use FileOps::getdents()
-                match file_ops.getdents().await {
-                    Ok(entries) => {
-                        // Format as linux_dirent64 structures
-                        let dirent_addr = match args.dirent() {
-                            Some(addr) => addr,
-                            None => {
-                                return Ok(crate::syscall::SyscallResult::Value(
-                                    -libc::EFAULT as i64,
-                                ))
-                            }
-                        };
-                        let count = args.count() as usize;
-
-                        let mut buf = Vec::new();
-                        let mut offset = 1i64;
-
-                        for (ino, name, d_type) in entries {
-                            // Calculate record length (aligned to 8 bytes)
-                            let name_len = name.len() + 1; // +1 for null terminator
-                            let reclen = (19 + name_len).div_ceil(8) * 8; // 19 = sizeof(ino + off + reclen + type)
-
-                            if buf.len() + reclen > count {
-                                break; // Not enough space
-                            }
-
-                            // Write linux_dirent64 structure
-                            buf.extend_from_slice(&ino.to_ne_bytes()); // d_ino (u64)
-                            buf.extend_from_slice(&offset.to_ne_bytes()); // d_off (i64)
-                            buf.extend_from_slice(&(reclen as u16).to_ne_bytes()); // d_reclen (u16)
-                            buf.push(d_type); // d_type (u8)
-                            buf.extend_from_slice(name.as_bytes()); // d_name
-                            buf.push(0); // null terminator
-
-                            // Pad to 8-byte alignment
-                            while buf.len() % 8 != 0 {
-                                buf.push(0);
-                            }
-
-                            offset += 1;
-                        }
-
-                        // Write to guest memory
-                        if !buf.is_empty() {
-                            guest.memory().write_exact(dirent_addr.cast::<u8>(), &buf)?;
-                        }
-
-                        return Ok(crate::syscall::SyscallResult::Value(buf.len() as i64));
-                    }
-                    Err(_) => {
-                        // Not a directory or error
-                        return Ok(crate::syscall::SyscallResult::Value(-libc::ENOTDIR as i64));
-                    }
-                }
-            }
-        }
+        return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Getdents64(
+            new_syscall,
+        )));
     }
 
     // FD not in table, let the original syscall through (will likely fail with EBADF)
@@ -1049,102 +828,15 @@ pub async fn handle_fstat<T: Guest<Sandbox>>(
         match entry {
             FdEntry::Passthrough { kernel_fd, .. } => {
                 // Passthrough file - rewrite FD and return modified syscall for tail_inject
-                let new_syscall = args.with_fd(kernel_fd);
-
-                return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Fstat(
-                    new_syscall,
-                )));
-            }
-            FdEntry::Virtual { file_ops, .. } => {
-                // Virtual file - use FileOps::fstat()
-                match file_ops.fstat().await {
-                    Ok(stat_buf) => {
-                        // Write the stat result to guest memory
-                        if let Some(stat_addr) = args.stat() {
-                            // Convert stat struct to bytes and write
-                            let stat_bytes: &[u8] = unsafe {
-                                std::slice::from_raw_parts(
-                                    &stat_buf as *const _ as *const u8,
-                                    std::mem::size_of::<libc::stat>(),
-                                )
-                            };
-                            guest
-                                .memory()
-                                .write_exact(stat_addr.0.cast::<u8>(), stat_bytes)?;
-                        }
-                        return Ok(crate::syscall::SyscallResult::Value(0)); // Success
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EIO as i64,
-                        };
-                        return Ok(crate::syscall::SyscallResult::Value(errno));
-                    }
-                }
-            }
-        }
-    }
-
-    // FD not in table, let the original syscall through (will likely fail with EBADF)
-    Ok(crate::syscall::SyscallResult::Syscall(syscall))
-}
-
-/// The `fstatat` system call.
-///
-/// This intercepts `fstatat` system calls and translates virtual FDs to kernel FDs,
-/// or calls FileOps::fstatat() for virtual files.
-#[cfg(target_arch = "aarch64")]
-pub async fn handle_fstatat<T: Guest<Sandbox>>(
-    guest: &mut T,
-    syscall: Syscall,
-    args: &reverie::syscalls::Fstatat,
-    fd_table: &FdTable,
-    mount_table: &MountTable,
-) -> Result<crate::syscall::SyscallResult, Error> {
-    if let Some(path_addr) = args.path() {
-        // Read the original path from guest memory
-        let mut path: std::path::PathBuf = path_addr.read(&guest.memory())?;
-
-        // Handle dirfd resolution for relative paths
-        let dirfd = args.dirfd();
-        let kernel_dirfd = if dirfd == libc::AT_FDCWD {
-            dirfd
-        } else if path.is_relative() {
-            // For relative paths, resolve against dirfd
-            if let Some(dir_entry) = fd_table.get(dirfd) {
-                // Check if this is a passthrough directory with a kernel FD first
-                if let Some(kfd) = dir_entry.kernel_fd() {
-                    // Passthrough directory - use the kernel FD and keep path as-is
-                    kfd
-                } else if let Some(dir_path) = dir_entry.path() {
-                    // Virtual directory - resolve relative path against the directory's path
-                    path = dir_path.join(&path);
-                    // For virtual directories, we'll use AT_FDCWD since we have the full path now
-                    libc::AT_FDCWD
-                } else {
-                    // Virtual file without a path - this shouldn't happen for directories
-
-                    use reverie::Errno;
-                    return Err(Error::Errno(Errno::EBADFD));
-                }
-            } else {
-                // dirfd not in table - will likely fail
-                dirfd
-            }
-        } else {
-            // Absolute path - dirfd is ignored, use AT_FDCWD
-            libc::AT_FDCWD
-        };
+                let new_syscall = args.with_fd(kernel_fd);
 
-        // Check if this path matches a mount point
-        if let Some((vfs, _translated_path)) = mount_table.resolve(&path) {
-            // Check if this is a virtual VFS (like SQLite)
-            if vfs.is_virtual() {
-                // For virtual VFS, open the file directly without going to the kernel
-                match vfs.stat(&path).await {
+                return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Fstat(
+                    new_syscall,
+                )));
+            }
+            FdEntry::Virtual { file_ops, .. } => {
+                // Virtual file - use FileOps::fstat()
+                match file_ops.fstat().await {
                     Ok(stat_buf) => {
                         // Write the stat result to guest memory
                         if let Some(stat_addr) = args.stat() {
@@ -1162,24 +854,13 @@ pub async fn handle_fstatat<T: Guest<Sandbox>>(
                         return Ok(crate::syscall::SyscallResult::Value(0)); // Success
                     }
                     Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EIO as i64,
-                        };
-                        return Ok(crate::syscall::SyscallResult::Value(errno));
+                        return Ok(crate::syscall::SyscallResult::Value(io_errno(e)));
                     }
                 }
             }
         }
-        // No mount point matches - pass through to kernel with original path
-        let new_syscall = args.with_dirfd(kernel_dirfd).with_path(Some(path_addr));
-
-        return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Fstatat(
-            new_syscall,
-        )));
     }
+
     // FD not in table, let the original syscall through (will likely fail with EBADF)
     Ok(crate::syscall::SyscallResult::Syscall(syscall))
 }
@@ -1187,6 +868,7 @@ pub async fn handle_fstatat<T: Guest<Sandbox>>(
 /// The `pread64` system call.
 ///
 /// This intercepts `pread64` system calls and translates virtual FDs to kernel FDs.
+/// Reads from virtual files are served by `io::handle_pread64`.
 pub async fn handle_pread64<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Pread64,
@@ -1213,6 +895,7 @@ pub async fn handle_pread64<T: Guest<Sandbox>>(
 /// The `pwrite64` system call.
 ///
 /// This intercepts `pwrite64` system calls and translates virtual FDs to kernel FDs.
+/// Writes to virtual files are served by `io::handle_pwrite64`.
 pub async fn handle_pwrite64<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Pwrite64,
@@ -1238,8 +921,8 @@ pub async fn handle_pwrite64<T: Guest<Sandbox>>(
 
 /// The `lseek` system call.
 ///
-/// This intercepts `lseek` system calls and translates virtual FDs to kernel FDs,
-/// or calls FileOps::seek() for virtual files.
+/// This intercepts `lseek` system calls and translates virtual FDs to kernel FDs.
+/// Seeks on virtual files are served by `io::handle_lseek`.
 #[cfg(not(target_arch = "aarch64"))]
 pub async fn handle_lseek<T: Guest<Sandbox>>(
     _guest: &mut T,
@@ -1247,292 +930,105 @@ pub async fn handle_lseek<T: Guest<Sandbox>>(
     args: &reverie::syscalls::Lseek,
     fd_table: &FdTable,
 ) -> Result<crate::syscall::SyscallResult, Error> {
-    let virtual_fd = args.fd();
-
-    // Get the FD entry
-    if let Some(entry) = fd_table.get(virtual_fd) {
-        match entry {
-            FdEntry::Passthrough { kernel_fd, .. } => {
-                // Passthrough file - rewrite FD and return modified syscall for tail_inject
-                let new_syscall = args.with_fd(kernel_fd);
-
-                return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Lseek(
-                    new_syscall,
-                )));
-            }
-            FdEntry::Virtual { file_ops, .. } => {
-                // Virtual file - use FileOps::seek()
-                // Convert Whence enum to i32
-                use reverie::syscalls::Whence;
-                let whence = match args.whence() {
-                    Whence::SEEK_SET => libc::SEEK_SET,
-                    Whence::SEEK_CUR => libc::SEEK_CUR,
-                    Whence::SEEK_END => libc::SEEK_END,
-                    Whence::SEEK_DATA => libc::SEEK_DATA,
-                    Whence::SEEK_HOLE => libc::SEEK_HOLE,
-                    _ => return Ok(crate::syscall::SyscallResult::Value(-libc::EINVAL as i64)),
-                };
-                match file_ops.seek(args.offset(), whence).await {
-                    Ok(new_offset) => {
-                        return Ok(crate::syscall::SyscallResult::Value(new_offset));
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EIO as i64,
-                        };
-                        return Ok(crate::syscall::SyscallResult::Value(errno));
-                    }
-                }
-            }
-        }
+    // Translate virtual FD to kernel FD
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        // Passthrough file - rewrite FD and return modified syscall for tail_inject
+        let new_syscall = args.with_fd(kernel_fd);
+        return Ok(crate::syscall::SyscallResult::Syscall(Syscall::Lseek(
+            new_syscall,
+        )));
     }
 
     // FD not in table, let the original syscall through (will likely fail with EBADF)
     Ok(crate::syscall::SyscallResult::Syscall(syscall))
 }
 
-/// The `mmap` system call.
-///
-/// This intercepts `mmap` system calls and translates virtual FDs to kernel FDs
-/// when mapping files. Anonymous mappings (fd == -1) pass through unchanged.
-pub async fn handle_mmap<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Mmap,
-    fd_table: &FdTable,
-) -> Result<Option<i64>, Error> {
-    let virtual_fd = args.fd();
-
-    // If fd is -1, it's an anonymous mapping - pass through
-    if virtual_fd == -1 {
-        return Ok(None);
-    }
-
-    // Translate virtual FD to kernel FD for file-backed mappings
-    if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
-        let new_syscall = reverie::syscalls::Mmap::new()
-            .with_addr(args.addr())
-            .with_len(args.len())
-            .with_prot(args.prot())
-            .with_flags(args.flags())
-            .with_fd(kernel_fd)
-            .with_offset(args.offset());
-
-        let result = guest.inject(Syscall::Mmap(new_syscall)).await?;
-        return Ok(Some(result));
-    }
-
-    // FD not in table, let the original syscall through (will likely fail with EBADF)
-    Ok(None)
-}
-
-/// The `access` system call.
+/// The `copy_file_range` system call.
 ///
-/// This intercepts `access` system calls and translates paths according to the mount table.
-#[cfg(not(target_arch = "aarch64"))]
-pub async fn handle_access<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Access,
-    mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
-    if let Some(path_addr) = args.path() {
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = args.with_path(Some(new_path_addr));
-
-            return Ok(Some(Syscall::Access(new_syscall)));
-        }
-    }
-    Ok(None)
-}
-
-/// The `faccessat2` system call.
+/// This intercepts `copy_file_range` system calls and translates both virtual FDs to
+/// kernel FDs. Copies involving virtual files are served by `io::handle_copy_file_range`.
 ///
-/// This intercepts `faccessat2` system calls, translates paths according to the mount table,
-/// and virtualizes the dirfd parameter.
-/// Signature: int faccessat2(int dirfd, const char *pathname, int mode, int flags);
-pub async fn handle_faccessat2<T: Guest<Sandbox>>(
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_copy_file_range<T: Guest<Sandbox>>(
     guest: &mut T,
-    syscall_args: &reverie::syscalls::SyscallArgs,
-    mount_table: &MountTable,
+    args: &reverie::syscalls::CopyFileRange,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    use reverie::syscalls::PathPtr;
-
-    let dirfd = syscall_args.arg0 as i32;
-    let pathname_addr: PathPtr = unsafe { std::mem::transmute(syscall_args.arg1) };
-    let mode = syscall_args.arg2 as i32;
-    let flags = syscall_args.arg3 as i32;
-
-    // Check if dirfd needs virtualization
-    let dirfd_needs_translation = dirfd != libc::AT_FDCWD && fd_table.translate(dirfd).is_some();
-
-    // Check if path needs virtualization
-    let translated_path_opt = translate_path(guest, pathname_addr, mount_table).await?;
-    let path_needs_translation = translated_path_opt.is_some();
-
-    // If nothing needs virtualization, let the original syscall pass through
-    if !dirfd_needs_translation && !path_needs_translation {
-        return Ok(None);
-    }
-
-    // Virtualize the dirfd if needed
-    let kernel_dirfd = if dirfd == libc::AT_FDCWD {
-        dirfd
-    } else {
-        fd_table.translate(dirfd).unwrap_or(dirfd)
+    let kernel_fd_in = fd_table.translate(args.fd_in());
+    let kernel_fd_out = fd_table.translate(args.fd_out());
+    let (kernel_fd_in, kernel_fd_out) = match (kernel_fd_in, kernel_fd_out) {
+        (Some(fd_in), Some(fd_out)) => (fd_in, fd_out),
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        _ => return Ok(None),
     };
 
-    let new_path_addr = translated_path_opt.unwrap_or(pathname_addr);
-    let new_path_raw: usize = unsafe { std::mem::transmute(new_path_addr) };
-
-    // Build and inject the syscall with virtualized parameters
-    let result = guest
-        .inject(Syscall::Other(
-            reverie::syscalls::Sysno::faccessat2,
-            reverie::syscalls::SyscallArgs {
-                arg0: kernel_dirfd as usize,
-                arg1: new_path_raw,
-                arg2: mode as usize,
-                arg3: flags as usize,
-                arg4: 0,
-                arg5: 0,
-            },
-        ))
-        .await?;
-
+    let new_syscall = args.with_fd_in(kernel_fd_in).with_fd_out(kernel_fd_out);
+    let result = guest.inject(Syscall::CopyFileRange(new_syscall)).await?;
     Ok(Some(result))
 }
 
-/// The `faccessat` system call.
+/// The `sendfile` system call.
 ///
-/// This intercepts `faccessat` system calls, translates paths according to the mount table,
-/// and virtualizes the dirfd parameter.
-pub async fn handle_faccessat<T: Guest<Sandbox>>(
+/// This intercepts `sendfile` system calls and translates both virtual FDs to
+/// kernel FDs. Sends from virtual files are served by `io::handle_sendfile`.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_sendfile<T: Guest<Sandbox>>(
     guest: &mut T,
-    args: &reverie::syscalls::Faccessat,
-    mount_table: &MountTable,
+    args: &reverie::syscalls::Sendfile,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    let Some(pathname_addr) = args.path() else {
-        return Ok(None);
-    };
-    let dirfd = args.dirfd();
-
-    // Check if dirfd needs virtualization
-    let dirfd_needs_translation = dirfd != libc::AT_FDCWD && fd_table.translate(dirfd).is_some();
-
-    // Check if path needs virtualization
-    let translated_path_opt = translate_path(guest, pathname_addr, mount_table).await?;
-    let path_needs_translation = translated_path_opt.is_some();
-
-    // If nothing needs virtualization, let the original syscall pass through
-    if !dirfd_needs_translation && !path_needs_translation {
-        return Ok(None);
-    }
-
-    // Virtualize the dirfd if needed
-    let kernel_dirfd = if dirfd == libc::AT_FDCWD {
-        dirfd
-    } else {
-        fd_table.translate(dirfd).unwrap_or(dirfd)
+    let kernel_in_fd = fd_table.translate(args.in_fd());
+    let kernel_out_fd = fd_table.translate(args.out_fd());
+    let (kernel_in_fd, kernel_out_fd) = match (kernel_in_fd, kernel_out_fd) {
+        (Some(in_fd), Some(out_fd)) => (in_fd, out_fd),
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        _ => return Ok(None),
     };
 
-    let new_path_addr = translated_path_opt.unwrap_or(pathname_addr);
-    let new_syscall =
-        Syscall::Faccessat(args.with_dirfd(kernel_dirfd).with_path(Some(new_path_addr)));
-
-    // Build and inject the syscall with virtualized parameters
-    let result = guest.inject(new_syscall).await?;
-
+    let new_syscall = args.with_in_fd(kernel_in_fd).with_out_fd(kernel_out_fd);
+    let result = guest.inject(Syscall::Sendfile(new_syscall)).await?;
     Ok(Some(result))
 }
 
-/// The `rename` system call.
+/// The `mmap` system call.
 ///
-/// This intercepts `rename` system calls and translates both paths according to the mount table.
-#[cfg(not(target_arch = "aarch64"))]
-pub async fn handle_rename<T: Guest<Sandbox>>(
+/// This intercepts `mmap` system calls and translates virtual FDs to kernel FDs
+/// when mapping files. Anonymous mappings (fd == -1) pass through unchanged.
+/// Mappings of virtual files are emulated by `io::handle_mmap`.
+pub async fn handle_mmap<T: Guest<Sandbox>>(
     guest: &mut T,
-    args: &reverie::syscalls::Rename,
-    mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
-    // Only translate if we need to - otherwise pass through unchanged
-    let oldpath_needs_translation = args.oldpath().is_some();
-    let newpath_needs_translation = args.newpath().is_some();
-
-    if !oldpath_needs_translation && !newpath_needs_translation {
-        return Ok(None);
-    }
-
-    // Build new syscall with translated paths
-    let mut new_syscall = *args;
-    let mut modified = false;
-
-    // Translate oldpath
-    if let Some(oldpath_addr) = args.oldpath() {
-        if let Some(new_path_addr) = translate_path(guest, oldpath_addr, mount_table).await? {
-            new_syscall = new_syscall.with_oldpath(Some(new_path_addr));
-            modified = true;
-        } else {
-            new_syscall = new_syscall.with_oldpath(Some(oldpath_addr));
-        }
-    }
+    args: &reverie::syscalls::Mmap,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let virtual_fd = args.fd();
 
-    // Translate newpath
-    if let Some(newpath_addr) = args.newpath() {
-        if let Some(new_path_addr) = translate_path(guest, newpath_addr, mount_table).await? {
-            new_syscall = new_syscall.with_newpath(Some(new_path_addr));
-            modified = true;
-        } else {
-            new_syscall = new_syscall.with_newpath(Some(newpath_addr));
+    // If fd is -1, it's an anonymous mapping - pass through, watching for
+    // allocations that fail because of the memory limit
+    if virtual_fd == -1 {
+        if !sandbox::is_address_space_limited() {
+            return Ok(None);
         }
-    }
-
-    if modified {
-        Ok(Some(Syscall::Rename(new_syscall)))
-    } else {
-        Ok(None)
-    }
-}
-
-/// The `unlink` system call.
-///
-/// This intercepts `unlink` system calls and translates paths according to the mount table.
-#[cfg(not(target_arch = "aarch64"))]
-pub async fn handle_unlink<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Unlink,
-    mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
-    if let Some(path_addr) = args.path() {
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = args.with_path(Some(new_path_addr));
-
-            return Ok(Some(Syscall::Unlink(new_syscall)));
+        let result = guest.inject(Syscall::Mmap(*args)).await;
+        if result == Err(Errno::ENOMEM) {
+            sandbox::record_out_of_memory();
         }
+        return Ok(Some(result?));
     }
-    Ok(None)
-}
-
-/// The `readv` system call.
-///
-/// This intercepts `readv` system calls and translates virtual FDs to kernel FDs.
-pub async fn handle_readv<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Readv,
-    fd_table: &FdTable,
-) -> Result<Option<i64>, Error> {
-    let virtual_fd = args.fd();
 
-    // Translate virtual FD to kernel FD
+    // Translate virtual FD to kernel FD for file-backed mappings
     if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
-        let new_syscall = reverie::syscalls::Readv::new()
+        let new_syscall = reverie::syscalls::Mmap::new()
+            .with_addr(args.addr())
+            .with_len(args.len())
+            .with_prot(args.prot())
+            .with_flags(args.flags())
             .with_fd(kernel_fd)
-            .with_iov(args.iov());
+            .with_offset(args.offset());
 
-        let result = guest.inject(Syscall::Readv(new_syscall)).await?;
+        let result = guest.inject(Syscall::Mmap(new_syscall)).await?;
         return Ok(Some(result));
     }
 
@@ -1540,23 +1036,23 @@ pub async fn handle_readv<T: Guest<Sandbox>>(
     Ok(None)
 }
 
-/// The `writev` system call.
+/// The `readv`, `writev`, `preadv` and `pwritev` system calls.
 ///
-/// This intercepts `writev` system calls and translates virtual FDs to kernel FDs.
-pub async fn handle_writev<T: Guest<Sandbox>>(
+/// This intercepts vectored I/O system calls and translates virtual FDs to kernel FDs.
+/// Vectored I/O on virtual files is served by `io::handle_readv` and `io::handle_writev`.
+pub async fn handle_vectored<T: Guest<Sandbox>>(
     guest: &mut T,
-    args: &reverie::syscalls::Writev,
+    num: Sysno,
+    args: &SyscallArgs,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    let virtual_fd = args.fd();
-
     // Translate virtual FD to kernel FD
-    if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
-        let new_syscall = reverie::syscalls::Writev::new()
-            .with_fd(kernel_fd)
-            .with_iov(args.iov());
-
-        let result = guest.inject(Syscall::Writev(new_syscall)).await?;
+    if let Some(kernel_fd) = fd_table.translate(args.arg0 as i32) {
+        let new_args = SyscallArgs {
+            arg0: kernel_fd as usize,
+            ..*args
+        };
+        let result = guest.inject(Syscall::Other(num, new_args)).await?;
         return Ok(Some(result));
     }
 
@@ -1620,11 +1116,16 @@ pub async fn handle_pipe2<T: Guest<Sandbox>>(
 /// The `socket` system call.
 ///
 /// This intercepts `socket` system calls and virtualizes the returned file descriptor.
+/// With networking disabled, only `AF_UNIX` sockets can be created.
 pub async fn handle_socket<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Socket,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
+    if let Some(result) = net::check_socket_family(args.family()) {
+        return Ok(Some(result));
+    }
+
     // Execute the syscall to create the socket
     let kernel_fd = guest.inject(Syscall::Socket(*args)).await?;
 
@@ -1656,12 +1157,11 @@ pub async fn handle_sendto<T: Guest<Sandbox>>(
 
     // Translate virtual FD to kernel FD
     if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
-        let new_syscall = reverie::syscalls::Sendto::new()
-            .with_fd(kernel_fd)
-            .with_buf(args.buf())
-            .with_flags(args.flags())
-            .with_addr(args.addr());
+        if let Some(result) = net::check_network_access(guest, kernel_fd, args.addr())? {
+            return Ok(Some(result));
+        }
 
+        let new_syscall = args.with_fd(kernel_fd);
         let result = guest.inject(Syscall::Sendto(new_syscall)).await?;
         return Ok(Some(result));
     }
@@ -1682,10 +1182,11 @@ pub async fn handle_connect<T: Guest<Sandbox>>(
 
     // Translate virtual FD to kernel FD
     if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
-        let new_syscall = reverie::syscalls::Connect::new()
-            .with_fd(kernel_fd)
-            .with_addrlen(args.addrlen());
+        if let Some(result) = net::check_network_access(guest, kernel_fd, args.uaddr())? {
+            return Ok(Some(result));
+        }
 
+        let new_syscall = args.with_fd(kernel_fd);
         let result = guest.inject(Syscall::Connect(new_syscall)).await?;
         return Ok(Some(result));
     }
@@ -1694,6 +1195,31 @@ pub async fn handle_connect<T: Guest<Sandbox>>(
     Ok(None)
 }
 
+/// The `recvfrom` system call.
+///
+/// This intercepts `recvfrom` system calls and translates virtual FDs to kernel FDs.
+pub async fn handle_recvfrom<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Recvfrom,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let virtual_fd = args.fd();
+
+    // Translate virtual FD to kernel FD
+    if let Some(kernel_fd) = fd_table.translate(virtual_fd) {
+        if let Some(result) = net::check_network_access(guest, kernel_fd, None)? {
+            return Ok(Some(result));
+        }
+
+        let new_syscall = args.with_fd(kernel_fd);
+        let result = guest.inject(Syscall::Recvfrom(new_syscall)).await?;
+        return Ok(Some(result));
+    }
+
+    // FD not in table, let the original syscall through (will likely fail with EBADF)
+    Ok(None)
+}
+
 /// The `getpeername` system call.
 ///
 /// This intercepts `getpeername` system calls and translates virtual FDs to kernel FDs.
@@ -1719,158 +1245,199 @@ pub async fn handle_getpeername<T: Guest<Sandbox>>(
     Ok(None)
 }
 
-/// The `chdir` system call.
-///
-/// This intercepts `chdir` system calls and translates paths according to the mount table.
-pub async fn handle_chdir<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Chdir,
-    mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
-    if let Some(path_addr) = args.path() {
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = args.with_path(Some(new_path_addr));
-
-            return Ok(Some(Syscall::Chdir(new_syscall)));
+/// Lexically normalize an absolute path, resolving `.` and `..` components
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(name) => normalized.push(name),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
         }
     }
-    Ok(None)
+    normalized
 }
 
-/// The `rmdir` system call.
+/// Make the virtual directory at `path` the working directory.
 ///
-/// This intercepts `rmdir` system calls and translates paths according to the mount table.
-#[cfg(not(target_arch = "aarch64"))]
-pub async fn handle_rmdir<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Rmdir,
-    mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
-    if let Some(path_addr) = args.path() {
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = args.with_path(Some(new_path_addr));
-            return Ok(Some(Syscall::Rmdir(new_syscall)));
-        }
+/// Returns 0 or the negated errno, `ENOTDIR` if `path` is not a directory.
+async fn chdir_virtual(vfs: &dyn Vfs, path: PathBuf, fd_table: &FdTable) -> i64 {
+    match vfs.stat(&path).await {
+        Ok(stat) => enter_virtual_dir(vfs, &stat, path, fd_table),
+        Err(e) => e.to_errno(),
     }
-    Ok(None)
 }
 
-/// The `unlinkat` system call (used for `unlink` and `rmdir` on ARM).
+/// Make the virtual directory at `path`, whose status is `stat`, the working
+/// directory.
 ///
-/// This intercepts `unlinkat` system calls and translates paths according to the mount table.
-/// Signature: int unlinkat(int dirfd, const char *pathname, int flags);
-/// Note: On ARM (aarch64), both unlink and rmdir are implemented via unlinkat:
-///   - unlink: unlinkat(AT_FDCWD, pathname, 0)
-///   - rmdir: unlinkat(AT_FDCWD, pathname, AT_REMOVEDIR)
-#[cfg(target_arch = "aarch64")]
-pub async fn handle_unlinkat<T: Guest<Sandbox>>(
-    guest: &mut T,
-    args: &reverie::syscalls::Unlinkat,
-    mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
-    use libc::AT_FDCWD;
-
-    let dirfd = args.dirfd();
-    let flags = args.flags();
-
-    // Only handle AT_FDCWD for now (same as handle_chmod)
-    if dirfd != AT_FDCWD {
-        return Ok(None);
+/// Like the kernel, this needs search permission on the directory, checked with
+/// the sandbox's effective IDs. Returns 0 or the negated errno, `ENOTDIR` if
+/// `path` is not a directory and `EACCES` if it can't be searched.
+fn enter_virtual_dir(vfs: &dyn Vfs, stat: &libc::stat, path: PathBuf, fd_table: &FdTable) -> i64 {
+    if stat.st_mode & libc::S_IFMT != libc::S_IFDIR {
+        return -libc::ENOTDIR as i64;
+    }
+    let (euid, egid) = caller_ids(vfs, true);
+    if !permitted(stat, libc::X_OK, euid, egid) {
+        return -libc::EACCES as i64;
     }
+    fd_table.set_cwd(Some(GuestCwd::Virtual(path)));
+    0
+}
 
-    if let Some(path_addr) = args.path() {
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = args
-                .with_dirfd(AT_FDCWD)
-                .with_path(Some(new_path_addr))
-                .with_flags(flags);
-            return Ok(Some(Syscall::Unlinkat(new_syscall)));
+/// Get the working directory the guest sees in the host directory at `path`, if
+/// the kernel knows that directory by another path.
+///
+/// `path` is normalized, and a relative one is left to the kernel.
+fn translated_cwd(path: &Path, mount_table: &MountTable) -> Option<GuestCwd> {
+    if !path.is_absolute() {
+        return None;
+    }
+    let path = normalize_path(path);
+    match mount_table.resolve(&path) {
+        Some((_vfs, translated_path, _read_only)) if translated_path != path => {
+            Some(GuestCwd::Translated(path))
         }
+        _ => None,
     }
-    Ok(None)
 }
 
-/// The `fchmodat` system call (used for `chmod` on ARM).
+/// The `chdir` system call.
 ///
-/// This intercepts `fchmodat` system calls and translates paths according to the mount table.
-/// Signature: int fchmodat(int dirfd, const char *pathname, mode_t mode, int flags);
-/// Note: On ARM (aarch64), chmod is implemented via fchmodat with AT_FDCWD.
-pub async fn handle_chmod<T: Guest<Sandbox>>(
+/// This intercepts `chdir` system calls and translates paths according to the mount table.
+/// The kernel knows nothing of directories in virtual mounts, so changing into one only
+/// records it as the virtual working directory (see `FdTable::cwd`), once it is checked
+/// to be a directory the guest may search. Relative paths are resolved against it from
+/// then on. Changing into a directory the kernel knows by another path records the path
+/// the guest sees, for `handle_getcwd`. While either is recorded, the kernel is passed
+/// the absolute host path of the new working directory.
+pub async fn handle_chdir<T: Guest<Sandbox>>(
     guest: &mut T,
-    syscall_args: &reverie::syscalls::SyscallArgs,
+    syscall: Syscall,
+    args: &reverie::syscalls::Chdir,
     mount_table: &MountTable,
-) -> Result<Option<i64>, Error> {
-    use libc::AT_FDCWD;
-    use reverie::syscalls::{AtFlags, Mode, PathPtr, Syscall};
+    fd_table: &FdTable,
+) -> Result<SyscallResult, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(SyscallResult::Syscall(syscall)),
+    };
 
-    let dirfd = syscall_args.arg0 as i32;
-    let pathname_addr = match PathPtr::from_ptr(syscall_args.arg1 as _) {
-        Some(ptr) => ptr,
-        None => {
-            return Ok(None);
+    let mut path = read_path(guest, path_addr)?;
+    let cwd = fd_table.cwd();
+    if let Some(cwd) = &cwd {
+        path = cwd.path().join(&path);
+    }
+    if path.is_absolute() {
+        path = normalize_path(&path);
+    }
+
+    let host_path = match mount_table.resolve(&path) {
+        Some((vfs, _translated_path, _read_only)) if vfs.is_virtual() => {
+            return Ok(SyscallResult::Value(
+                chdir_virtual(&*vfs, path, fd_table).await,
+            ));
         }
+        Some((_vfs, translated_path, _read_only)) => translated_path,
+        None => path.clone(),
     };
-    let mode = syscall_args.arg2 as u32;
-    let flags = syscall_args.arg3 as i32;
+    let new_cwd = translated_cwd(&path, mount_table);
 
-    if dirfd != libc::AT_FDCWD {
-        return Ok(None);
+    // The kernel's working directory is the one the guest sees, before and after
+    if cwd.is_none() && new_cwd.is_none() {
+        return Ok(SyscallResult::Syscall(syscall));
     }
 
-    if let Some(new_path_addr) = translate_path(guest, pathname_addr, mount_table).await? {
-        let new_syscall = reverie::syscalls::Fchmodat::new()
-            .with_dirfd(AT_FDCWD)
-            .with_path(Some(new_path_addr))
-            .with_mode(Mode::from_bits_truncate(mode))
-            .with_flags(AtFlags::from_bits_truncate(flags));
-        let injected = guest.inject(Syscall::Fchmodat(new_syscall)).await?;
-
-        Ok(Some(injected))
-    } else {
-        Ok(None)
+    let host_path = CString::new(host_path.as_os_str().as_bytes()).map_err(|_| Errno::EINVAL)?;
+    let new_path_addr = push_paths(guest, &[&host_path]).await?.into_iter().next();
+    let result = guest
+        .inject(Syscall::Chdir(args.with_path(new_path_addr)))
+        .await?;
+    if result == 0 {
+        fd_table.set_cwd(new_cwd);
     }
+    Ok(SyscallResult::Value(result))
 }
 
-/// The `fchownat` system call.
+/// The `fchdir` system call.
+///
+/// This intercepts `fchdir` system calls and translates virtual FDs to kernel FDs.
+/// Once the kernel has changed directory, the working directory the guest sees is
+/// recorded from the path the FD was opened with, as with `handle_chdir`. A virtual
+/// directory FD becomes the virtual working directory.
 ///
-/// This intercepts `fchownat` system calls, translates paths according to the mount table,
-/// and virtualizes the dirfd parameter.
-pub async fn handle_fchownat<T: Guest<Sandbox>>(
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fchdir<T: Guest<Sandbox>>(
     guest: &mut T,
-    args: &reverie::syscalls::Fchownat,
+    args: &reverie::syscalls::Fchdir,
     mount_table: &MountTable,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    let Some(pathname_addr) = args.path() else {
-        return Ok(None);
-    };
-    let dirfd = args.dirfd();
-
-    // Check if dirfd needs virtualization
-    let dirfd_needs_translation = dirfd != libc::AT_FDCWD && fd_table.translate(dirfd).is_some();
-
-    // Check if path needs virtualization
-    let translated_path_opt = translate_path(guest, pathname_addr, mount_table).await?;
-    let path_needs_translation = translated_path_opt.is_some();
-
-    // If nothing needs virtualization, let the original syscall pass through
-    if !dirfd_needs_translation && !path_needs_translation {
-        return Ok(None);
+    match fd_table.get(args.fd()) {
+        Some(FdEntry::Passthrough {
+            kernel_fd, path, ..
+        }) => {
+            let result = guest
+                .inject(Syscall::Fchdir(args.with_fd(kernel_fd)))
+                .await?;
+            if result == 0 {
+                // A relative path was relative to the working directory the guest saw
+                let path = path.map(|path| match fd_table.cwd() {
+                    Some(cwd) => cwd.path().join(path),
+                    None => path,
+                });
+                fd_table.set_cwd(path.and_then(|path| translated_cwd(&path, mount_table)));
+            }
+            Ok(Some(result))
+        }
+        Some(FdEntry::Virtual { file_ops, path, .. }) => {
+            let result = match (file_ops.fstat().await, path) {
+                (Ok(stat), Some(path)) => match mount_table.resolve(&path) {
+                    Some((vfs, _translated_path, _read_only)) => {
+                        enter_virtual_dir(&*vfs, &stat, path, fd_table)
+                    }
+                    None => -libc::ENOENT as i64,
+                },
+                (Ok(_), None) => -libc::ENOTDIR as i64,
+                (Err(e), _) => e.to_errno(),
+            };
+            Ok(Some(result))
+        }
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        None => Ok(None),
     }
+}
 
-    // Virtualize the dirfd if needed
-    let kernel_dirfd = if dirfd == libc::AT_FDCWD {
-        dirfd
-    } else {
-        fd_table.translate(dirfd).unwrap_or(dirfd)
+/// The `getcwd` system call.
+///
+/// This intercepts `getcwd` system calls to report the working directory the guest
+/// sees when the kernel's differs from it, as recorded in `FdTable::cwd`. Like the
+/// kernel, it returns the length of the path including its NUL terminator, and fails
+/// with `ERANGE` if the buffer is too small for it.
+///
+/// Returns `Some(result)` if the working directory is recorded, or `None` if the
+/// original syscall should be used.
+pub async fn handle_getcwd<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &SyscallArgs,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let Some(cwd) = fd_table.cwd() else {
+        return Ok(None);
     };
 
-    let new_path_addr = translated_path_opt.unwrap_or(pathname_addr);
-    let new_syscall =
-        Syscall::Fchownat(args.with_dirfd(kernel_dirfd).with_path(Some(new_path_addr)));
-
-    // Build and inject the syscall with virtualized parameters
-    let result = guest.inject(new_syscall).await?;
-
-    Ok(Some(result))
+    let mut bytes = cwd.path().as_os_str().as_bytes().to_vec();
+    bytes.push(0);
+    if bytes.len() > args.arg1 {
+        return Ok(Some(-libc::ERANGE as i64));
+    }
+    match AddrMut::<u8>::from_raw(args.arg0) {
+        Some(buf) => guest.memory().write_exact(buf, &bytes)?,
+        None => return Ok(Some(-libc::EFAULT as i64)),
+    }
+    Ok(Some(bytes.len() as i64))
 }
@@ -0,0 +1,1334 @@
+use crate::{
+    sandbox::{self, Sandbox},
+    vfs::{
+        fdtable::{FdEntry, FdTable},
+        file::BoxedFileOps,
+        mount::MountTable,
+        Vfs, VfsError,
+    },
+};
+use reverie::{
+    syscalls::{Addr, AddrMut, MapFlags, MemoryAccess, ProtFlags, Syscall, SyscallArgs, Sysno},
+    Errno, Error, Guest,
+};
+use std::sync::Arc;
+
+/// Look up a virtual file and the VFS that owns it.
+///
+/// Returns `None` if the FD is not in the table or refers to a passthrough file,
+/// in which case the kernel should handle the I/O.
+pub(crate) fn lookup_virtual(
+    fd: i32,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Option<(Arc<dyn Vfs>, BoxedFileOps)> {
+    match fd_table.get(fd)? {
+        FdEntry::Virtual {
+            file_ops,
+            path: Some(path),
+            ..
+        } => {
+            let (vfs, _translated_path, _read_only) = mount_table.resolve(&path)?;
+            Some((vfs, file_ops))
+        }
+        _ => None,
+    }
+}
+
+/// The result of a syscall on an FD that `lookup_virtual()` didn't find.
+///
+/// Passthrough and unknown FDs return `None`, so the real syscall runs. A virtual
+/// FD whose path no longer resolves to a VFS fails with `EBADF` instead, since
+/// the kernel would act on whatever file it has under the same number.
+fn unresolved_virtual(fd: i32, fd_table: &FdTable) -> Option<i64> {
+    match fd_table.get(fd)? {
+        FdEntry::Virtual { .. } => Some(-libc::EBADF as i64),
+        _ => None,
+    }
+}
+
+/// Map a VFS error from an I/O operation on an open file to errno.
+///
+/// A file that disappears from underneath an open FD is reported as `EBADF`
+/// rather than `ENOENT`, since I/O syscalls never resolve paths.
+pub(crate) fn io_errno(err: VfsError) -> i64 {
+    match err {
+        VfsError::NotFound => -libc::EBADF as i64,
+        VfsError::PermissionDenied => -libc::EACCES as i64,
+        other => other.to_errno(),
+    }
+}
+
+/// Whether the FD was opened with an access mode other than `denied`.
+///
+/// Reading an `O_WRONLY` FD or writing an `O_RDONLY` one fails with `EBADF`.
+fn accessible(fd: i32, fd_table: &FdTable, denied: i32) -> bool {
+    fd_table
+        .get(fd)
+        .is_some_and(|entry| entry.flags() & libc::O_ACCMODE != denied)
+}
+
+/// Largest number of bytes read from a virtual file, or from guest memory for a
+/// write, at once.
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Read up to `len` bytes of a virtual file at `offset` into guest memory at `buf`.
+///
+/// The data is staged in chunks of at most `READ_CHUNK_SIZE`, so that a large
+/// read doesn't hold all of it in memory at once, stopping early at end-of-file.
+/// Returns the number of bytes read, or the negated errno if nothing could be read.
+async fn read_to_guest<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    file_ops: &BoxedFileOps,
+    offset: u64,
+    buf: usize,
+    len: usize,
+) -> Result<Result<usize, i64>, Error> {
+    let mut read = 0;
+    while read < len {
+        let chunk_len = (len - read).min(READ_CHUNK_SIZE);
+        let data = match vfs.read(file_ops, offset + read as u64, chunk_len).await {
+            Ok(data) => data,
+            Err(e) if read == 0 => return Ok(Err(io_errno(e))),
+            Err(_) => break,
+        };
+        if data.is_empty() {
+            break;
+        }
+        let addr = match AddrMut::from_raw(buf + read) {
+            Some(addr) => addr,
+            None if read == 0 => return Ok(Err(-libc::EFAULT as i64)),
+            None => break,
+        };
+        guest.memory().write_exact(addr, &data)?;
+        read += data.len();
+        if data.len() < chunk_len {
+            break;
+        }
+    }
+    Ok(Ok(read))
+}
+
+/// Write `len` bytes from guest memory at `buf` to a virtual file at `offset`.
+///
+/// Like `read_to_guest`, the data is staged in chunks of at most `READ_CHUNK_SIZE`,
+/// so the length the guest passes never sizes an allocation in the tracer. Stops
+/// early at a short write. Returns the number of bytes written, or the negated
+/// errno if nothing could be written.
+async fn write_from_guest<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    file_ops: &BoxedFileOps,
+    offset: u64,
+    buf: usize,
+    len: usize,
+) -> Result<Result<usize, i64>, Error> {
+    let mut chunk = vec![0u8; len.min(READ_CHUNK_SIZE)];
+    let mut written = 0;
+    while written < len {
+        let chunk_len = (len - written).min(READ_CHUNK_SIZE);
+        let addr = match Addr::from_raw(buf + written) {
+            Some(addr) => addr,
+            None if written == 0 => return Ok(Err(-libc::EFAULT as i64)),
+            None => break,
+        };
+        guest.memory().read_exact(addr, &mut chunk[..chunk_len])?;
+        let pos = offset + written as u64;
+        let n = match vfs.write(file_ops, pos, &chunk[..chunk_len]).await {
+            Ok(n) => n,
+            Err(e) if written == 0 => return Ok(Err(io_errno(e))),
+            Err(_) => break,
+        };
+        written += n;
+        if n < chunk_len {
+            break;
+        }
+    }
+    Ok(Ok(written))
+}
+
+/// The `read` system call for virtual files.
+///
+/// This intercepts `read` system calls on virtual FDs and serves bytes through
+/// `Vfs::read` at the current file offset, advancing the offset by the number
+/// of bytes read. FDs opened `O_WRONLY` fail with `EBADF`.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_read<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Read,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+    if !accessible(args.fd(), fd_table, libc::O_WRONLY) {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let buf_addr = match args.buf() {
+        Some(addr) => addr,
+        None => return Ok(Some(-libc::EFAULT as i64)),
+    };
+
+    // Get the current offset
+    let offset = match file_ops.seek(0, libc::SEEK_CUR).await {
+        Ok(offset) => offset as u64,
+        Err(e) => return Ok(Some(io_errno(e))),
+    };
+
+    let buf = buf_addr.as_raw();
+    let read = match read_to_guest(guest, &*vfs, &file_ops, offset, buf, args.len()).await? {
+        Ok(read) => read,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    if read > 0 {
+        // Advance the file offset past the bytes we returned
+        if let Err(e) = file_ops
+            .seek((offset + read as u64) as i64, libc::SEEK_SET)
+            .await
+        {
+            return Ok(Some(io_errno(e)));
+        }
+    }
+
+    Ok(Some(read as i64))
+}
+
+/// The `pread64` system call for virtual files.
+///
+/// This intercepts `pread64` system calls on virtual FDs and serves bytes through
+/// `Vfs::read` at the given offset, without changing the file offset.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_pread64<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Pread64,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+    if !accessible(args.fd(), fd_table, libc::O_WRONLY) {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let buf_addr = match args.buf() {
+        Some(addr) => addr,
+        None => return Ok(Some(-libc::EFAULT as i64)),
+    };
+
+    if args.offset() < 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+
+    let (offset, buf) = (args.offset() as u64, buf_addr.as_raw());
+    match read_to_guest(guest, &*vfs, &file_ops, offset, buf, args.len()).await? {
+        Ok(read) => Ok(Some(read as i64)),
+        Err(errno) => Ok(Some(errno)),
+    }
+}
+
+/// Compute the offset at which a write to a virtual file should start.
+///
+/// Append-mode FDs always write at end-of-file, otherwise `offset` is used.
+/// `O_APPEND` is taken from the file handle rather than the FD table entry, since
+/// it is a status flag of the open file, which `fcntl(F_SETFL)` may change.
+async fn write_offset(file_ops: &BoxedFileOps, offset: u64) -> Result<u64, VfsError> {
+    if file_ops.get_flags() & libc::O_APPEND != 0 {
+        Ok(file_ops.seek(0, libc::SEEK_END).await? as u64)
+    } else {
+        Ok(offset)
+    }
+}
+
+/// The `write` system call for virtual files.
+///
+/// This intercepts `write` system calls on virtual FDs, reads the data from the
+/// guest buffer, and writes it through `Vfs::write` at the current file offset
+/// (or at end-of-file for `O_APPEND` FDs), advancing the offset by the number of
+/// bytes written. FDs opened `O_RDONLY` fail with `EBADF`.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_write<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Write,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+    if !accessible(args.fd(), fd_table, libc::O_RDONLY) {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let buf_addr = match args.buf() {
+        Some(addr) => addr,
+        None => return Ok(Some(-libc::EFAULT as i64)),
+    };
+
+    let current = match file_ops.seek(0, libc::SEEK_CUR).await {
+        Ok(offset) => offset as u64,
+        Err(e) => return Ok(Some(io_errno(e))),
+    };
+    let offset = match write_offset(&file_ops, current).await {
+        Ok(offset) => offset,
+        Err(e) => return Ok(Some(io_errno(e))),
+    };
+
+    let buf = buf_addr.as_raw();
+    let written = match write_from_guest(guest, &*vfs, &file_ops, offset, buf, args.len()).await? {
+        Ok(written) => written,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    if written > 0 {
+        // Advance the file offset past the bytes we wrote
+        if let Err(e) = file_ops
+            .seek((offset + written as u64) as i64, libc::SEEK_SET)
+            .await
+        {
+            return Ok(Some(io_errno(e)));
+        }
+    }
+
+    Ok(Some(written as i64))
+}
+
+/// The `pwrite64` system call for virtual files.
+///
+/// This intercepts `pwrite64` system calls on virtual FDs, reads the data from the
+/// guest buffer, and writes it through `Vfs::write` at the given offset without
+/// changing the file offset. As on Linux, `O_APPEND` FDs write at end-of-file
+/// regardless of the offset argument.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_pwrite64<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Pwrite64,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+    if !accessible(args.fd(), fd_table, libc::O_RDONLY) {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let buf_addr = match args.buf() {
+        Some(addr) => addr,
+        None => return Ok(Some(-libc::EFAULT as i64)),
+    };
+
+    if args.offset() < 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+
+    let offset = match write_offset(&file_ops, args.offset() as u64).await {
+        Ok(offset) => offset,
+        Err(e) => return Ok(Some(io_errno(e))),
+    };
+
+    let buf = buf_addr.as_raw();
+    match write_from_guest(guest, &*vfs, &file_ops, offset, buf, args.len()).await? {
+        Ok(written) => Ok(Some(written as i64)),
+        Err(errno) => Ok(Some(errno)),
+    }
+}
+
+/// Largest number of buffers a vectored I/O syscall takes, as on Linux
+const IOV_MAX: usize = 1024;
+
+/// Read the array of `count` iovecs at `addr` in guest memory, as pairs of
+/// buffer address and length.
+///
+/// Returns the negated errno if the array can't be read, has more than
+/// `IOV_MAX` entries, or its lengths add up to more than `isize::MAX`.
+fn read_iovecs<T: Guest<Sandbox>>(
+    guest: &T,
+    addr: usize,
+    count: usize,
+) -> Result<Vec<(usize, usize)>, i64> {
+    if count > IOV_MAX {
+        return Err(-libc::EINVAL as i64);
+    }
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let size = std::mem::size_of::<libc::iovec>();
+    let mut bytes = vec![0u8; count * size];
+    let array = Addr::<u8>::from_raw(addr).ok_or(-libc::EFAULT as i64)?;
+    guest
+        .memory()
+        .read_exact(array, &mut bytes)
+        .map_err(|_| -libc::EFAULT as i64)?;
+
+    let mut total: usize = 0;
+    let mut iovecs = Vec::with_capacity(count);
+    for entry in bytes.chunks_exact(size) {
+        // SAFETY: `entry` holds the bytes of one iovec, which is plain data
+        let iov: libc::iovec = unsafe { std::ptr::read_unaligned(entry.as_ptr().cast()) };
+        total = total
+            .checked_add(iov.iov_len)
+            .filter(|&total| total <= isize::MAX as usize)
+            .ok_or(-libc::EINVAL as i64)?;
+        iovecs.push((iov.iov_base as usize, iov.iov_len));
+    }
+    Ok(iovecs)
+}
+
+/// The offset of a `preadv` or `pwritev`, or `None` for `readv` and `writev`,
+/// which use the file offset.
+///
+/// On 64-bit targets the whole offset is passed in `pos_l`, the fourth argument.
+fn vectored_offset(num: Sysno, args: &SyscallArgs) -> Option<i64> {
+    match num {
+        Sysno::preadv | Sysno::pwritev => Some(args.arg3 as i64),
+        _ => None,
+    }
+}
+
+/// The `readv` and `preadv` system calls for virtual files.
+///
+/// This intercepts vectored reads on virtual FDs, gathers the iovec array from
+/// guest memory, and fills its buffers in order through `Vfs::read`. `readv` reads
+/// at the file offset and advances it by the number of bytes read, `preadv`
+/// reads at the given offset without changing the file offset.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_readv<T: Guest<Sandbox>>(
+    guest: &mut T,
+    num: Sysno,
+    args: &SyscallArgs,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (vfs, file_ops) = match lookup_virtual(args.arg0 as i32, mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.arg0 as i32, fd_table)),
+    };
+    if !accessible(args.arg0 as i32, fd_table, libc::O_WRONLY) {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let iovecs = match read_iovecs(guest, args.arg1, args.arg2) {
+        Ok(iovecs) => iovecs,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let positioned = vectored_offset(num, args);
+    let offset = match positioned {
+        Some(offset) if offset < 0 => return Ok(Some(-libc::EINVAL as i64)),
+        Some(offset) => offset as u64,
+        None => match file_ops.seek(0, libc::SEEK_CUR).await {
+            Ok(offset) => offset as u64,
+            Err(e) => return Ok(Some(io_errno(e))),
+        },
+    };
+
+    // Fill the buffers in order, leaving the ones past end-of-file untouched
+    let mut read = 0;
+    for (base, len) in iovecs {
+        if len == 0 {
+            continue;
+        }
+        let pos = offset + read as u64;
+        match read_to_guest(guest, &*vfs, &file_ops, pos, base, len).await? {
+            Ok(n) => {
+                read += n;
+                if n < len {
+                    break;
+                }
+            }
+            Err(errno) if read == 0 => return Ok(Some(errno)),
+            Err(_) => break,
+        }
+    }
+
+    if positioned.is_none() && read > 0 {
+        // Advance the file offset past the bytes we returned
+        if let Err(e) = file_ops
+            .seek((offset + read as u64) as i64, libc::SEEK_SET)
+            .await
+        {
+            return Ok(Some(io_errno(e)));
+        }
+    }
+
+    Ok(Some(read as i64))
+}
+
+/// The `writev` and `pwritev` system calls for virtual files.
+///
+/// This intercepts vectored writes on virtual FDs, gathers the iovec array from
+/// guest memory, and writes its buffers in order through `Vfs::write`, staging
+/// each in bounded chunks as `write` does. `writev` writes at the file offset (or
+/// at end-of-file for `O_APPEND` FDs) and advances it by the number of bytes
+/// written, `pwritev` writes at the given offset without changing the file
+/// offset, except that `O_APPEND` FDs write at end-of-file as with `pwrite64`.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_writev<T: Guest<Sandbox>>(
+    guest: &mut T,
+    num: Sysno,
+    args: &SyscallArgs,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (vfs, file_ops) = match lookup_virtual(args.arg0 as i32, mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.arg0 as i32, fd_table)),
+    };
+    if !accessible(args.arg0 as i32, fd_table, libc::O_RDONLY) {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let iovecs = match read_iovecs(guest, args.arg1, args.arg2) {
+        Ok(iovecs) => iovecs,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let positioned = vectored_offset(num, args);
+    let current = match positioned {
+        Some(offset) if offset < 0 => return Ok(Some(-libc::EINVAL as i64)),
+        Some(offset) => offset as u64,
+        None => match file_ops.seek(0, libc::SEEK_CUR).await {
+            Ok(offset) => offset as u64,
+            Err(e) => return Ok(Some(io_errno(e))),
+        },
+    };
+    let offset = match write_offset(&file_ops, current).await {
+        Ok(offset) => offset,
+        Err(e) => return Ok(Some(io_errno(e))),
+    };
+
+    // Write the buffers in order, stopping at the first short write
+    let mut written = 0;
+    for (base, len) in iovecs {
+        if len == 0 {
+            continue;
+        }
+        let pos = offset + written as u64;
+        match write_from_guest(guest, &*vfs, &file_ops, pos, base, len).await? {
+            Ok(n) => {
+                written += n;
+                if n < len {
+                    break;
+                }
+            }
+            Err(errno) if written == 0 => return Ok(Some(errno)),
+            Err(_) => break,
+        }
+    }
+
+    if positioned.is_none() && written > 0 {
+        // Advance the file offset past the bytes we wrote
+        if let Err(e) = file_ops
+            .seek((offset + written as u64) as i64, libc::SEEK_SET)
+            .await
+        {
+            return Ok(Some(io_errno(e)));
+        }
+    }
+
+    Ok(Some(written as i64))
+}
+
+/// The `fsync` system call for virtual files.
+///
+/// This intercepts `fsync` system calls on virtual FDs, writes any buffered data to the
+/// database and flushes the database to disk, so the data survives a crash. Any failure
+/// is reported as `EIO`.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_fsync<T: Guest<Sandbox>>(
+    _guest: &mut T,
+    args: &reverie::syscalls::Fsync,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (_vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+
+    Ok(Some(match file_ops.fsync().await {
+        Ok(()) => 0,
+        Err(_) => -libc::EIO as i64,
+    }))
+}
+
+/// The `fdatasync` system call for virtual files.
+///
+/// Like `fsync`, but the flush to disk is skipped when only metadata changed since the
+/// last sync. Any failure is reported as `EIO`.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_fdatasync<T: Guest<Sandbox>>(
+    _guest: &mut T,
+    args: &reverie::syscalls::Fdatasync,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (_vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+
+    Ok(Some(match file_ops.fdatasync().await {
+        Ok(()) => 0,
+        Err(_) => -libc::EIO as i64,
+    }))
+}
+
+/// Reposition the offset of a virtual file.
+///
+/// `SEEK_END` is relative to the file size reported by `FileOps::fstat`. The whole
+/// file is treated as data, so `SEEK_DATA` returns `offset` itself and `SEEK_HOLE`
+/// returns the file size, both failing with `ENXIO` at or past end-of-file. Directories
+/// only support `SEEK_SET` and `SEEK_CUR`. A resulting negative offset is `EINVAL`.
+///
+/// Returns the new absolute offset or a negated errno.
+async fn lseek_virtual(file_ops: &BoxedFileOps, offset: i64, whence: i32) -> i64 {
+    let stat = match file_ops.fstat().await {
+        Ok(stat) => stat,
+        Err(e) => return io_errno(e),
+    };
+    let is_dir = stat.st_mode & libc::S_IFMT == libc::S_IFDIR;
+    let size = stat.st_size;
+
+    let new_offset = match whence {
+        libc::SEEK_SET => Some(offset),
+        libc::SEEK_CUR => {
+            let current = match file_ops.seek(0, libc::SEEK_CUR).await {
+                Ok(current) => current,
+                Err(e) => return io_errno(e),
+            };
+            current.checked_add(offset)
+        }
+        libc::SEEK_END if !is_dir => size.checked_add(offset),
+        libc::SEEK_DATA | libc::SEEK_HOLE if !is_dir => {
+            if offset < 0 || offset >= size {
+                return -libc::ENXIO as i64;
+            }
+            Some(if whence == libc::SEEK_DATA {
+                offset
+            } else {
+                size
+            })
+        }
+        _ => return -libc::EINVAL as i64,
+    };
+
+    let new_offset = match new_offset {
+        Some(new_offset) if new_offset >= 0 => new_offset,
+        _ => return -libc::EINVAL as i64,
+    };
+    match file_ops.seek(new_offset, libc::SEEK_SET).await {
+        Ok(new_offset) => new_offset,
+        Err(e) => io_errno(e),
+    }
+}
+
+/// The `lseek` system call for virtual files.
+///
+/// This intercepts `lseek` system calls on virtual FDs and repositions the file offset
+/// shared by reads and writes, as described in `lseek_virtual`.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_lseek<T: Guest<Sandbox>>(
+    _guest: &mut T,
+    args: &reverie::syscalls::Lseek,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    use reverie::syscalls::Whence;
+
+    let (_vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+
+    let whence = match args.whence() {
+        Whence::SEEK_SET => libc::SEEK_SET,
+        Whence::SEEK_CUR => libc::SEEK_CUR,
+        Whence::SEEK_END => libc::SEEK_END,
+        Whence::SEEK_DATA => libc::SEEK_DATA,
+        Whence::SEEK_HOLE => libc::SEEK_HOLE,
+        _ => return Ok(Some(-libc::EINVAL as i64)),
+    };
+
+    Ok(Some(lseek_virtual(&file_ops, args.offset(), whence).await))
+}
+
+/// The `lseek` system call, when it reaches the sandbox as a raw syscall.
+///
+/// Virtual FDs are handled like `handle_lseek`, and passthrough FDs are translated to
+/// their kernel FD before `lseek` is injected.
+/// Signature: off_t lseek(int fd, off_t offset, int whence);
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_lseek_raw<T: Guest<Sandbox>>(
+    guest: &mut T,
+    syscall_args: &SyscallArgs,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let fd = syscall_args.arg0 as i32;
+    let offset = syscall_args.arg1 as i64;
+    let whence = syscall_args.arg2 as i32;
+
+    if let Some((_vfs, file_ops)) = lookup_virtual(fd, mount_table, fd_table) {
+        return Ok(Some(lseek_virtual(&file_ops, offset, whence).await));
+    }
+
+    let kernel_fd = match fd_table.translate(fd) {
+        Some(kernel_fd) => kernel_fd,
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        None => return Ok(None),
+    };
+    let result = guest
+        .inject(Syscall::Other(
+            Sysno::lseek,
+            SyscallArgs {
+                arg0: kernel_fd as usize,
+                ..*syscall_args
+            },
+        ))
+        .await?;
+    Ok(Some(result))
+}
+
+/// Largest number of bytes `copy_file_range` moves between virtual files at once.
+const COPY_CHUNK_SIZE: usize = 128 * 1024;
+
+/// Copy up to `len` bytes between two virtual files at the given offsets.
+///
+/// The data moves in chunks of `COPY_CHUNK_SIZE` through the VFS without touching
+/// guest memory, stopping early at end-of-file of the source. Returns the number of
+/// bytes copied.
+async fn copy_virtual(
+    vfs_in: &dyn Vfs,
+    file_in: &BoxedFileOps,
+    off_in: u64,
+    vfs_out: &dyn Vfs,
+    file_out: &BoxedFileOps,
+    off_out: u64,
+    len: usize,
+) -> Result<usize, VfsError> {
+    let mut copied = 0;
+    while copied < len {
+        let chunk_len = (len - copied).min(COPY_CHUNK_SIZE);
+        let data = vfs_in
+            .read(file_in, off_in + copied as u64, chunk_len)
+            .await?;
+        if data.is_empty() {
+            break;
+        }
+
+        let written = vfs_out
+            .write(file_out, off_out + copied as u64, &data)
+            .await?;
+        copied += written;
+        if written < data.len() {
+            break;
+        }
+    }
+    Ok(copied)
+}
+
+/// Get the number of bytes `copy_file_range` copies from `start_in` in the file with
+/// status `stat_in` to `start_out` in the one with status `stat_out`, of the `len`
+/// requested.
+///
+/// Nothing is copied past end-of-file of the source, so `len` is clamped to what is
+/// left of it before the ranges are checked: a copy within one file only fails with
+/// `EINVAL` if the bytes actually copied would overlap. A destination range ending
+/// past the largest file offset fails with `EFBIG`. Returns the length or the
+/// negated errno.
+fn copy_len(
+    stat_in: &libc::stat,
+    start_in: u64,
+    stat_out: &libc::stat,
+    start_out: u64,
+    len: usize,
+) -> Result<usize, i64> {
+    let left = (stat_in.st_size.max(0) as u64).saturating_sub(start_in);
+    let len = (len as u64).min(left);
+
+    let end_in = start_in + len;
+    let end_out = match start_out.checked_add(len) {
+        Some(end_out) if end_out <= i64::MAX as u64 => end_out,
+        _ => return Err(-libc::EFBIG as i64),
+    };
+
+    // Copying a file onto an overlapping range of itself is not allowed
+    let same_file = stat_in.st_dev == stat_out.st_dev && stat_in.st_ino == stat_out.st_ino;
+    if same_file && start_in < end_out && start_out < end_in {
+        return Err(-libc::EINVAL as i64);
+    }
+    Ok(len as usize)
+}
+
+/// The `copy_file_range` system call for virtual files.
+///
+/// This intercepts `copy_file_range` system calls where either FD is virtual. When both
+/// are, the range is copied inside the sandbox with `copy_virtual`, within or across
+/// virtual mounts. As with the kernel, a NULL offset pointer means the FD's file offset
+/// is used and advanced, while otherwise the pointed-to offset is used and updated.
+/// When only one FD is virtual the copy fails with `EXDEV`, so that userspace falls back
+/// to `read` and `write`.
+///
+/// Returns `Some(result)` if either FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_copy_file_range<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::CopyFileRange,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (fd_in, fd_out) = (args.fd_in(), args.fd_out());
+    let (vfs_in, file_in, vfs_out, file_out) = match (
+        lookup_virtual(fd_in, mount_table, fd_table),
+        lookup_virtual(fd_out, mount_table, fd_table),
+    ) {
+        (Some((vfs_in, file_in)), Some((vfs_out, file_out))) => {
+            (vfs_in, file_in, vfs_out, file_out)
+        }
+        (None, None) => return Ok(None),
+        _ => return Ok(Some(-libc::EXDEV as i64)),
+    };
+
+    if args.flags() != 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+
+    // The source must be readable, and the destination writable without O_APPEND
+    let in_flags = fd_table.get(fd_in).map_or(0, |entry| entry.flags());
+    let out_flags = fd_table.get(fd_out).map_or(0, |entry| entry.flags());
+    if in_flags & libc::O_ACCMODE == libc::O_WRONLY
+        || out_flags & libc::O_ACCMODE == libc::O_RDONLY
+        || file_out.get_flags() & libc::O_APPEND != 0
+    {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    let off_in = match args.off_in() {
+        Some(addr) => guest.memory().read_value(addr)?,
+        None => match file_in.seek(0, libc::SEEK_CUR).await {
+            Ok(offset) => offset,
+            Err(e) => return Ok(Some(io_errno(e))),
+        },
+    };
+    let off_out = match args.off_out() {
+        Some(addr) => guest.memory().read_value(addr)?,
+        None => match file_out.seek(0, libc::SEEK_CUR).await {
+            Ok(offset) => offset,
+            Err(e) => return Ok(Some(io_errno(e))),
+        },
+    };
+    if off_in < 0 || off_out < 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+
+    let (stat_in, stat_out) = match (file_in.fstat().await, file_out.fstat().await) {
+        (Ok(stat_in), Ok(stat_out)) => (stat_in, stat_out),
+        (Err(e), _) | (_, Err(e)) => return Ok(Some(io_errno(e))),
+    };
+    if stat_in.st_mode & libc::S_IFMT == libc::S_IFDIR
+        || stat_out.st_mode & libc::S_IFMT == libc::S_IFDIR
+    {
+        return Ok(Some(-libc::EISDIR as i64));
+    }
+    let (start_in, start_out) = (off_in as u64, off_out as u64);
+    let len = match copy_len(&stat_in, start_in, &stat_out, start_out, args.len()) {
+        Ok(len) => len,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let copied = match copy_virtual(
+        &*vfs_in, &file_in, start_in, &*vfs_out, &file_out, start_out, len,
+    )
+    .await
+    {
+        Ok(copied) => copied as i64,
+        Err(e) => return Ok(Some(io_errno(e))),
+    };
+
+    // Advance whichever offsets the copy used
+    match args.off_in() {
+        Some(addr) => guest.memory().write_value(addr, &(off_in + copied))?,
+        None => {
+            if let Err(e) = file_in.seek(off_in + copied, libc::SEEK_SET).await {
+                return Ok(Some(io_errno(e)));
+            }
+        }
+    }
+    match args.off_out() {
+        Some(addr) => guest.memory().write_value(addr, &(off_out + copied))?,
+        None => {
+            if let Err(e) = file_out.seek(off_out + copied, libc::SEEK_SET).await {
+                return Ok(Some(io_errno(e)));
+            }
+        }
+    }
+
+    Ok(Some(copied))
+}
+
+/// Largest number of bytes `sendfile` moves from a virtual file to a kernel FD at once.
+const SENDFILE_CHUNK_SIZE: usize = 128 * 1024;
+
+/// The `sendfile` system call for virtual files.
+///
+/// This intercepts `sendfile` system calls whose input FD is virtual. When the output
+/// FD is virtual too, the range is copied with `copy_virtual` at its file offset.
+/// Otherwise the output is a kernel FD such as a socket or a pipe, and the range is
+/// staged in chunks of `SENDFILE_CHUNK_SIZE` through a scratch anonymous mapping in the
+/// guest, from which a `write` is injected on the kernel FD. The copy stops at
+/// end-of-file and at the first short write, e.g. to a full non-blocking socket, and
+/// an error from the output is only reported if nothing was sent. As with the kernel,
+/// a NULL offset pointer means the input's file offset is used and advanced, while
+/// otherwise the pointed-to offset is used and updated.
+///
+/// Sending from a kernel FD to a virtual one fails with `EINVAL`, so that userspace
+/// falls back to `read` and `write`.
+///
+/// Returns `Some(result)` if either FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_sendfile<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Sendfile,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (in_fd, out_fd) = (args.in_fd(), args.out_fd());
+    let (vfs_in, file_in, out) = match (
+        lookup_virtual(in_fd, mount_table, fd_table),
+        lookup_virtual(out_fd, mount_table, fd_table),
+    ) {
+        (Some((vfs_in, file_in)), out) => (vfs_in, file_in, out),
+        (None, None) => return Ok(None),
+        (None, Some(_)) => return Ok(Some(-libc::EINVAL as i64)),
+    };
+
+    // The input must be readable, and a virtual output writable without O_APPEND
+    let in_flags = fd_table.get(in_fd).map_or(0, |entry| entry.flags());
+    if in_flags & libc::O_ACCMODE == libc::O_WRONLY {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+    if let Some((_vfs_out, file_out)) = &out {
+        let out_flags = fd_table.get(out_fd).map_or(0, |entry| entry.flags());
+        if out_flags & libc::O_ACCMODE == libc::O_RDONLY {
+            return Ok(Some(-libc::EBADF as i64));
+        }
+        if file_out.get_flags() & libc::O_APPEND != 0 {
+            return Ok(Some(-libc::EINVAL as i64));
+        }
+    }
+
+    let offset = match args.offset() {
+        Some(addr) => guest.memory().read_value(addr)?,
+        None => match file_in.seek(0, libc::SEEK_CUR).await {
+            Ok(offset) => offset,
+            Err(e) => return Ok(Some(io_errno(e))),
+        },
+    };
+    if offset < 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+    match file_in.fstat().await {
+        Ok(stat) if stat.st_mode & libc::S_IFMT == libc::S_IFDIR => {
+            return Ok(Some(-libc::EINVAL as i64));
+        }
+        Ok(_) => {}
+        Err(e) => return Ok(Some(io_errno(e))),
+    }
+    let count = args.count();
+    if count == 0 {
+        return Ok(Some(0));
+    }
+
+    let sent = match out {
+        Some((vfs_out, file_out)) => {
+            let off_out = match file_out.seek(0, libc::SEEK_CUR).await {
+                Ok(off_out) => off_out as u64,
+                Err(e) => return Ok(Some(io_errno(e))),
+            };
+            let copied = match copy_virtual(
+                &*vfs_in,
+                &file_in,
+                offset as u64,
+                &*vfs_out,
+                &file_out,
+                off_out,
+                count,
+            )
+            .await
+            {
+                Ok(copied) => copied,
+                Err(e) => return Ok(Some(io_errno(e))),
+            };
+            if let Err(e) = file_out
+                .seek((off_out + copied as u64) as i64, libc::SEEK_SET)
+                .await
+            {
+                return Ok(Some(io_errno(e)));
+            }
+            copied as i64
+        }
+        None => {
+            let kernel_out = match fd_table.translate(out_fd) {
+                Some(kernel_fd) => kernel_fd,
+                None => return Ok(Some(-libc::EBADF as i64)),
+            };
+            match send_to_kernel_fd(guest, &*vfs_in, &file_in, offset as u64, kernel_out, count)
+                .await?
+            {
+                Ok(sent) => sent as i64,
+                Err(errno) => return Ok(Some(errno)),
+            }
+        }
+    };
+
+    // Advance whichever input offset the copy used
+    match args.offset() {
+        Some(addr) => guest.memory().write_value(addr, &(offset + sent))?,
+        None => {
+            if let Err(e) = file_in.seek(offset + sent, libc::SEEK_SET).await {
+                return Ok(Some(io_errno(e)));
+            }
+        }
+    }
+
+    Ok(Some(sent))
+}
+
+/// Send up to `count` bytes of a virtual file at `offset` to a kernel FD.
+///
+/// The data is written from a scratch anonymous mapping in the guest, which is
+/// unmapped again afterwards. Returns the number of bytes sent, or the negated
+/// errno if nothing could be sent.
+async fn send_to_kernel_fd<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    file_ops: &BoxedFileOps,
+    offset: u64,
+    kernel_fd: i32,
+    count: usize,
+) -> Result<Result<usize, i64>, Error> {
+    let scratch_len = count.min(SENDFILE_CHUNK_SIZE);
+    let scratch = reverie::syscalls::Mmap::new()
+        .with_addr(None)
+        .with_len(scratch_len)
+        .with_prot(ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
+        .with_flags(MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS)
+        .with_fd(-1)
+        .with_offset(0);
+    let scratch = match guest.inject(Syscall::Mmap(scratch)).await {
+        Ok(addr) => addr as usize,
+        Err(errno) => return Ok(Err(-(errno.into_raw() as i64))),
+    };
+
+    let mut sent = 0;
+    let mut error = None;
+    while sent < count {
+        let chunk_len = (count - sent).min(scratch_len);
+        let data = match vfs.read(file_ops, offset + sent as u64, chunk_len).await {
+            Ok(data) => data,
+            Err(e) => {
+                error = Some(io_errno(e));
+                break;
+            }
+        };
+        if data.is_empty() {
+            break;
+        }
+        if let Some(addr) = AddrMut::from_raw(scratch) {
+            guest.memory().write_exact(addr, &data)?;
+        }
+
+        let write = reverie::syscalls::Write::new()
+            .with_fd(kernel_fd)
+            .with_buf(Addr::from_raw(scratch))
+            .with_len(data.len());
+        match guest.inject(Syscall::Write(write)).await {
+            Ok(written) => {
+                sent += written as usize;
+                if (written as usize) < data.len() {
+                    break;
+                }
+            }
+            Err(errno) => {
+                error = Some(-(errno.into_raw() as i64));
+                break;
+            }
+        }
+    }
+
+    guest
+        .inject(Syscall::Munmap(
+            reverie::syscalls::Munmap::new()
+                .with_addr(Addr::from_raw(scratch))
+                .with_len(scratch_len),
+        ))
+        .await?;
+    Ok(match error {
+        Some(errno) if sent == 0 => Err(errno),
+        _ => Ok(sent),
+    })
+}
+
+/// Largest number of bytes `mmap` copies from a virtual file into guest memory at once.
+const MMAP_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The `mmap` system call for virtual files.
+///
+/// Virtual files have no kernel FD to map, so a `MAP_PRIVATE` mapping is emulated
+/// with an anonymous mapping that is filled with the file contents at `offset`.
+/// Private mappings never write back to the file, so this only differs from the
+/// kernel in that later changes to the file don't show through the mapping, and
+/// in that pages past end-of-file read as zeros rather than raising `SIGBUS`. The
+/// mapping is an ordinary anonymous one, which `munmap` and `mprotect` handle as
+/// usual.
+///
+/// `MAP_SHARED` mappings of virtual files would need writes through the mapping
+/// to reach the VFS, which is not supported, and fail with `ENODEV`.
+///
+/// Returns `Some(result)` if the FD is virtual and the syscall was handled,
+/// or `None` if the real syscall should run.
+pub async fn handle_mmap<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Mmap,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    if args.flags().contains(MapFlags::MAP_ANONYMOUS) {
+        return Ok(None);
+    }
+    let (vfs, file_ops) = match lookup_virtual(args.fd(), mount_table, fd_table) {
+        Some(found) => found,
+        None => return Ok(unresolved_virtual(args.fd(), fd_table)),
+    };
+
+    if args.flags().contains(MapFlags::MAP_SHARED) {
+        return Ok(Some(-libc::ENODEV as i64));
+    }
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as i64;
+    if args.len() == 0 || args.offset() < 0 || args.offset() % page_size != 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+    // Like the kernel, even a private mapping needs a readable FD
+    let flags = fd_table.get(args.fd()).map_or(0, |entry| entry.flags());
+    if flags & libc::O_ACCMODE == libc::O_WRONLY {
+        return Ok(Some(-libc::EACCES as i64));
+    }
+
+    // Map the memory writable until it has been filled in
+    let prot = args.prot();
+    let mapping = reverie::syscalls::Mmap::new()
+        .with_addr(args.addr())
+        .with_len(args.len())
+        .with_prot(prot | ProtFlags::PROT_WRITE)
+        .with_flags(args.flags() | MapFlags::MAP_ANONYMOUS)
+        .with_fd(-1)
+        .with_offset(0);
+    let result = guest.inject(Syscall::Mmap(mapping)).await;
+    if result == Err(Errno::ENOMEM) && sandbox::is_address_space_limited() {
+        sandbox::record_out_of_memory();
+    }
+    let addr = result? as usize;
+
+    if let Err(errno) = fill_mapping(guest, &*vfs, &file_ops, addr, args).await {
+        guest
+            .inject(Syscall::Munmap(
+                reverie::syscalls::Munmap::new()
+                    .with_addr(Addr::from_raw(addr))
+                    .with_len(args.len()),
+            ))
+            .await?;
+        return Ok(Some(errno));
+    }
+
+    if !prot.contains(ProtFlags::PROT_WRITE) {
+        guest
+            .inject(Syscall::Mprotect(
+                reverie::syscalls::Mprotect::new()
+                    .with_addr(AddrMut::from_raw(addr))
+                    .with_len(args.len())
+                    .with_protection(prot),
+            ))
+            .await?;
+    }
+    Ok(Some(addr as i64))
+}
+
+/// Copy the contents of a virtual file into the anonymous mapping at `addr`
+/// that emulates mapping it.
+///
+/// The copy stops at end-of-file, leaving the rest of the mapping zeroed.
+/// Returns the negated errno on failure.
+async fn fill_mapping<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    file_ops: &BoxedFileOps,
+    addr: usize,
+    args: &reverie::syscalls::Mmap,
+) -> Result<(), i64> {
+    let mut filled = 0;
+    while filled < args.len() {
+        let chunk_len = (args.len() - filled).min(MMAP_CHUNK_SIZE);
+        let data = vfs
+            .read(file_ops, args.offset() as u64 + filled as u64, chunk_len)
+            .await
+            .map_err(io_errno)?;
+        if data.is_empty() {
+            break;
+        }
+
+        let chunk_addr = AddrMut::from_raw(addr + filled).ok_or(-libc::EFAULT as i64)?;
+        guest
+            .memory()
+            .write_exact(chunk_addr, &data)
+            .map_err(|_| -libc::EFAULT as i64)?;
+        filled += data.len();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::sqlite::SqliteVfs;
+    use std::path::{Path, PathBuf};
+
+    #[tokio::test]
+    async fn test_lseek_virtual() {
+        let dir = tempfile::tempdir().unwrap();
+        let vfs = SqliteVfs::new(&dir.path().join("test.db"), PathBuf::from("/agent"))
+            .await
+            .unwrap();
+        let file = vfs
+            .open(
+                Path::new("/agent/file.txt"),
+                libc::O_RDWR | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        vfs.write(&file, 0, b"0123456789").await.unwrap();
+
+        assert_eq!(lseek_virtual(&file, 4, libc::SEEK_SET).await, 4);
+        assert_eq!(lseek_virtual(&file, 2, libc::SEEK_CUR).await, 6);
+        assert_eq!(lseek_virtual(&file, -3, libc::SEEK_END).await, 7);
+        assert_eq!(lseek_virtual(&file, 5, libc::SEEK_END).await, 15);
+
+        // Negative results are rejected and leave the offset alone
+        assert_eq!(
+            lseek_virtual(&file, -1, libc::SEEK_SET).await,
+            -libc::EINVAL as i64
+        );
+        assert_eq!(
+            lseek_virtual(&file, -20, libc::SEEK_END).await,
+            -libc::EINVAL as i64
+        );
+        assert_eq!(lseek_virtual(&file, 0, libc::SEEK_CUR).await, 15);
+
+        // The whole file is data, with a hole at end-of-file
+        assert_eq!(lseek_virtual(&file, 3, libc::SEEK_DATA).await, 3);
+        assert_eq!(lseek_virtual(&file, 3, libc::SEEK_HOLE).await, 10);
+        assert_eq!(
+            lseek_virtual(&file, 10, libc::SEEK_DATA).await,
+            -libc::ENXIO as i64
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_virtual() {
+        let dir = tempfile::tempdir().unwrap();
+        let vfs = SqliteVfs::new(&dir.path().join("test.db"), PathBuf::from("/agent"))
+            .await
+            .unwrap();
+        let flags = libc::O_RDWR | libc::O_CREAT;
+        let src = vfs
+            .open(Path::new("/agent/src.txt"), flags, 0o644)
+            .await
+            .unwrap();
+        let dst = vfs
+            .open(Path::new("/agent/dst.txt"), flags, 0o644)
+            .await
+            .unwrap();
+
+        // Larger than one chunk, so the copy takes several rounds
+        let data: Vec<u8> = (0..COPY_CHUNK_SIZE * 2 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        vfs.write(&src, 0, &data).await.unwrap();
+
+        let copied = copy_virtual(&vfs, &src, 10, &vfs, &dst, 0, data.len())
+            .await
+            .unwrap();
+        assert_eq!(copied, data.len() - 10);
+        let copy = vfs.read(&dst, 0, data.len()).await.unwrap();
+        assert_eq!(copy, &data[10..]);
+
+        // Copying from end-of-file copies nothing
+        let end = data.len() as u64;
+        let copied = copy_virtual(&vfs, &src, end, &vfs, &dst, 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(copied, 0);
+    }
+
+    #[test]
+    fn test_copy_len() {
+        let mut file: libc::stat = unsafe { std::mem::zeroed() };
+        file.st_ino = 1;
+        file.st_size = 100;
+        let mut other = file;
+        other.st_ino = 2;
+
+        // The length is clamped to the end of the source
+        assert_eq!(copy_len(&file, 90, &other, 0, 64), Ok(10));
+        assert_eq!(copy_len(&file, 200, &other, 0, 64), Ok(0));
+
+        // Within one file only the bytes actually copied must not overlap
+        assert_eq!(copy_len(&file, 0, &file, 100, usize::MAX), Ok(100));
+        assert_eq!(copy_len(&file, 50, &file, 100, 64), Ok(50));
+        assert_eq!(copy_len(&file, 0, &file, 50, 64), Err(-libc::EINVAL as i64));
+
+        // The destination range can't end past the largest file offset
+        let last = i64::MAX as u64;
+        assert_eq!(copy_len(&file, 0, &other, last - 10, 10), Ok(10));
+        assert_eq!(
+            copy_len(&file, 0, &other, last - 10, 64),
+            Err(-libc::EFBIG as i64)
+        );
+        assert_eq!(
+            copy_len(&file, 0, &other, u64::MAX, 64),
+            Err(-libc::EFBIG as i64)
+        );
+    }
+}
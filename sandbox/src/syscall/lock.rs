@@ -0,0 +1,256 @@
+//! Advisory locking of virtual files with `flock` and `fcntl`.
+//!
+//! The locks live in the sandbox's lock table (see `vfs::lock`). A blocking
+//! request keeps the guest thread in the system call until the conflicting
+//! locks are released; it is not interrupted by signals.
+
+use crate::{
+    sandbox::Sandbox,
+    syscall::io::io_errno,
+    vfs::{
+        fdtable::{FdEntry, FdTable},
+        file::BoxedFileOps,
+        lock::{lock_table, FileId, FileLock, LockKind, LockOwner, TO_EOF},
+    },
+};
+use reverie::{
+    syscalls::{FcntlCmd, MemoryAccess, Syscall},
+    Error, Guest,
+};
+
+/// Get the identity of the file behind a virtual file handle in the lock table
+async fn file_id(file_ops: &BoxedFileOps) -> Result<FileId, i64> {
+    let stat = file_ops.fstat().await.map_err(io_errno)?;
+    Ok((stat.st_dev as u64, stat.st_ino as u64))
+}
+
+/// Release the locks a virtual file handle held for a closed FD.
+///
+/// Closing any FD of a file releases the `fcntl` locks `pid` holds on it, while
+/// `flock` locks are released along with the `last` FD of the handle.
+pub(crate) async fn release_locks(pid: i32, file_ops: &BoxedFileOps, last: bool) {
+    let table = lock_table();
+    let handle = LockOwner::handle(file_ops);
+    let process = LockOwner::Process(pid);
+    let release_handle = last && table.holds_locks(handle);
+    if !release_handle && !table.holds_locks(process) {
+        return;
+    }
+
+    if release_handle {
+        table.release_owner(handle);
+    }
+    if let Ok(file) = file_id(file_ops).await {
+        table.release(file, process);
+    }
+}
+
+/// Release the `fcntl` locks of a process that is exiting.
+pub(crate) fn release_process_locks(pid: i32) {
+    lock_table().release_owner(LockOwner::Process(pid));
+}
+
+/// The `flock` system call.
+///
+/// This intercepts `flock` system calls, locking virtual files in the sandbox's
+/// lock table and translating virtual FDs to kernel FDs for passthrough files.
+/// A contended `LOCK_NB` request fails with `EWOULDBLOCK`.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_flock<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Flock,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let file_ops = match fd_table.get(args.fd()) {
+        Some(FdEntry::Virtual { file_ops, .. }) => file_ops,
+        Some(FdEntry::Passthrough { kernel_fd, .. }) => {
+            let result = guest
+                .inject(Syscall::Flock(args.with_fd(kernel_fd)))
+                .await?;
+            return Ok(Some(result));
+        }
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        None => return Ok(None),
+    };
+
+    let operation = args.operation();
+    let kind = match operation & !libc::LOCK_NB {
+        libc::LOCK_SH => Some(LockKind::Shared),
+        libc::LOCK_EX => Some(LockKind::Exclusive),
+        libc::LOCK_UN => None,
+        _ => return Ok(Some(-libc::EINVAL as i64)),
+    };
+    let file = match file_id(&file_ops).await {
+        Ok(file) => file,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let owner = LockOwner::handle(&file_ops);
+    let Some(kind) = kind else {
+        lock_table().release(file, owner);
+        return Ok(Some(0));
+    };
+    let lock = FileLock {
+        owner,
+        kind,
+        start: 0,
+        end: TO_EOF,
+    };
+    if operation & libc::LOCK_NB != 0 {
+        if lock_table().try_lock(file, lock).is_err() {
+            return Ok(Some(-libc::EWOULDBLOCK as i64));
+        }
+    } else {
+        lock_table().lock(file, lock).await;
+    }
+    Ok(Some(0))
+}
+
+/// Perform a record locking `fcntl` command (`F_GETLK`, `F_SETLK` or `F_SETLKW`)
+/// on a virtual FD.
+///
+/// The locks belong to the calling process. A contended `F_SETLK` fails with
+/// `EAGAIN`, while `F_SETLKW` waits for the conflicting locks to be released.
+///
+/// Returns `Some(result)` for the record locking commands, or `None` for other
+/// commands.
+pub(crate) async fn fcntl_lock<T: Guest<Sandbox>>(
+    guest: &mut T,
+    file_ops: &BoxedFileOps,
+    cmd: FcntlCmd<'_>,
+) -> Result<Option<i64>, Error> {
+    let owner = LockOwner::Process(guest.pid().as_raw());
+
+    match cmd {
+        FcntlCmd::F_GETLK(addr) => {
+            let Some(addr) = addr else {
+                return Ok(Some(-libc::EFAULT as i64));
+            };
+            let mut flock: libc::flock = guest.memory().read_value(addr)?;
+            let (file, kind, start, end) = match lock_request(file_ops, &flock).await {
+                Ok((file, Some(kind), start, end)) => (file, kind, start, end),
+                Ok((_, None, _, _)) => return Ok(Some(-libc::EINVAL as i64)),
+                Err(errno) => return Ok(Some(errno)),
+            };
+            let lock = FileLock {
+                owner,
+                kind,
+                start,
+                end,
+            };
+
+            match lock_table().conflict(file, &lock) {
+                Some(held) => {
+                    flock.l_type = match held.kind {
+                        LockKind::Shared => libc::F_RDLCK,
+                        LockKind::Exclusive => libc::F_WRLCK,
+                    } as _;
+                    flock.l_whence = libc::SEEK_SET as _;
+                    flock.l_start = held.start as _;
+                    flock.l_len = if held.end == TO_EOF {
+                        0
+                    } else {
+                        (held.end - held.start) as _
+                    };
+                    flock.l_pid = match held.owner {
+                        LockOwner::Process(pid) => pid,
+                        LockOwner::Handle(_) => -1,
+                    };
+                }
+                None => flock.l_type = libc::F_UNLCK as _,
+            }
+            guest.memory().write_value(addr, &flock)?;
+            Ok(Some(0))
+        }
+        FcntlCmd::F_SETLK(addr) | FcntlCmd::F_SETLKW(addr) => {
+            let blocking = matches!(cmd, FcntlCmd::F_SETLKW(_));
+            let Some(addr) = addr else {
+                return Ok(Some(-libc::EFAULT as i64));
+            };
+            let flock: libc::flock = guest.memory().read_value(addr)?;
+            let (file, kind, start, end) = match lock_request(file_ops, &flock).await {
+                Ok(request) => request,
+                Err(errno) => return Ok(Some(errno)),
+            };
+            let Some(kind) = kind else {
+                lock_table().unlock(file, owner, start, end);
+                return Ok(Some(0));
+            };
+
+            // Like the kernel, a read lock needs a readable FD and a write lock a writable one
+            let access = file_ops.get_flags() & libc::O_ACCMODE;
+            let permitted = match kind {
+                LockKind::Shared => access != libc::O_WRONLY,
+                LockKind::Exclusive => access != libc::O_RDONLY,
+            };
+            if !permitted {
+                return Ok(Some(-libc::EBADF as i64));
+            }
+
+            let lock = FileLock {
+                owner,
+                kind,
+                start,
+                end,
+            };
+            if blocking {
+                lock_table().lock(file, lock).await;
+            } else if lock_table().try_lock(file, lock).is_err() {
+                return Ok(Some(-libc::EAGAIN as i64));
+            }
+            Ok(Some(0))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Read the request of a `struct flock`.
+///
+/// Returns the locked file, the kind of lock (`None` for `F_UNLCK`) and the
+/// byte range it covers.
+async fn lock_request(
+    file_ops: &BoxedFileOps,
+    flock: &libc::flock,
+) -> Result<(FileId, Option<LockKind>, u64, u64), i64> {
+    let kind = match flock.l_type as i32 {
+        libc::F_RDLCK => Some(LockKind::Shared),
+        libc::F_WRLCK => Some(LockKind::Exclusive),
+        libc::F_UNLCK => None,
+        _ => return Err(-libc::EINVAL as i64),
+    };
+    let file = file_id(file_ops).await?;
+    let (start, end) = lock_range(file_ops, flock).await?;
+    Ok((file, kind, start, end))
+}
+
+/// Resolve the byte range of a `struct flock` to absolute offsets.
+///
+/// A length of zero extends the range to the end of the file, and a negative
+/// length covers the bytes before the start.
+async fn lock_range(file_ops: &BoxedFileOps, flock: &libc::flock) -> Result<(u64, u64), i64> {
+    let base = match flock.l_whence as i32 {
+        libc::SEEK_SET => 0,
+        libc::SEEK_CUR => file_ops.seek(0, libc::SEEK_CUR).await.map_err(io_errno)?,
+        libc::SEEK_END => file_ops.fstat().await.map_err(io_errno)?.st_size,
+        _ => return Err(-libc::EINVAL as i64),
+    };
+    let start = base
+        .checked_add(flock.l_start)
+        .ok_or(-libc::EOVERFLOW as i64)?;
+    let len = flock.l_len;
+
+    let (start, end) = if len > 0 {
+        let end = start.checked_add(len).ok_or(-libc::EOVERFLOW as i64)?;
+        (start, end as u64)
+    } else if len == 0 {
+        (start, TO_EOF)
+    } else {
+        (start + len, start as u64)
+    };
+    if start < 0 {
+        return Err(-libc::EINVAL as i64);
+    }
+    Ok((start as u64, end))
+}
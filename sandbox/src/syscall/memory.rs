@@ -0,0 +1,186 @@
+//! Batched access to the path arguments of guest syscalls.
+//!
+//! Reading a path with Reverie's `ReadAddr::read` takes several round-trips to
+//! the guest, and so does writing a translated path: reserving stack space,
+//! committing it and writing to it, once per path. Path-heavy workloads pay for
+//! these on every syscall, so paths are read here with a single
+//! `process_vm_readv` call, and all translated paths of a syscall are written
+//! with one reservation and one write.
+
+use crate::sandbox::Sandbox;
+use reverie::{
+    syscalls::{AddrMut, Errno, MemoryAccess, PathPtr, ReadAddr},
+    Error, Guest, Stack,
+};
+use std::{
+    ffi::{CStr, OsString},
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+};
+
+/// Longest path read from the guest, including its terminating NUL
+const PATH_MAX: usize = libc::PATH_MAX as usize;
+
+/// Read the NUL-terminated path at `addr` in the guest
+pub(crate) fn read_path<T: Guest<Sandbox>>(guest: &T, addr: PathPtr<'_>) -> Result<PathBuf, Errno> {
+    match read_c_string(guest.tid().as_raw(), addr.as_ptr() as usize, PATH_MAX) {
+        Ok(bytes) => Ok(PathBuf::from(OsString::from_vec(bytes))),
+        // Without access to `process_vm_readv`, e.g. under seccomp, fall back
+        // to reading through Reverie
+        Err(errno) if errno == Errno::EPERM || errno == Errno::ENOSYS => addr.read(&guest.memory()),
+        Err(errno) => Err(errno),
+    }
+}
+
+/// Read a NUL-terminated string of at most `max_len` bytes, including the NUL,
+/// at `addr` in process `pid` with a single `process_vm_readv` call
+///
+/// The remote range is split into one iovec per page, so that a string ending
+/// just before an unmapped page is read up to that page instead of failing as a
+/// whole. Fails with `EFAULT` if the string runs into unmapped memory, and with
+/// `ENAMETOOLONG` if it is longer than `max_len`.
+pub fn read_c_string(pid: i32, addr: usize, max_len: usize) -> Result<Vec<u8>, Errno> {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let end = addr.checked_add(max_len).ok_or(Errno::EFAULT)?;
+    let mut remote = Vec::new();
+    let mut start = addr;
+    while start < end {
+        let len = (page_size - start % page_size).min(end - start);
+        remote.push(libc::iovec {
+            iov_base: start as *mut libc::c_void,
+            iov_len: len,
+        });
+        start += len;
+    }
+
+    let mut buf = vec![0u8; max_len];
+    let local = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    // SAFETY: `local` covers `buf`, which outlives the call, and the remote
+    // iovecs are only read, from the other process
+    let read = unsafe {
+        libc::process_vm_readv(
+            pid,
+            &local,
+            1,
+            remote.as_ptr(),
+            remote.len() as libc::c_ulong,
+            0,
+        )
+    };
+    if read < 0 {
+        let errno = std::io::Error::last_os_error().raw_os_error();
+        return Err(Errno::new(errno.unwrap_or(libc::EFAULT)));
+    }
+    buf.truncate(read as usize);
+
+    match buf.iter().position(|&b| b == 0) {
+        Some(nul) => {
+            buf.truncate(nul);
+            Ok(buf)
+        }
+        None if buf.len() == max_len => Err(Errno::ENAMETOOLONG),
+        None => Err(Errno::EFAULT),
+    }
+}
+
+/// Write `paths` to the guest stack, returning their addresses
+///
+/// The paths share one stack reservation and are written with one write. They
+/// stay valid until the syscall they are passed to returns, as the guest
+/// unwinds its own stack frame.
+pub(crate) async fn push_paths<'a, T: Guest<Sandbox>>(
+    guest: &'a mut T,
+    paths: &[&CStr],
+) -> Result<Vec<PathPtr<'a>>, Error> {
+    let bytes: Vec<u8> = paths
+        .iter()
+        .flat_map(|path| path.to_bytes_with_nul())
+        .copied()
+        .collect();
+
+    let mut stack = guest.stack().await;
+    let base = reserve_bytes(&mut stack, bytes.len())?;
+    stack.commit()?;
+    guest.memory().write_exact(base, &bytes)?;
+
+    let mut offset = 0;
+    Ok(paths
+        .iter()
+        .map(|path| {
+            let addr = base.offset(offset as isize);
+            offset += path.to_bytes_with_nul().len();
+            // SAFETY: The transmute converts AddrMut<u8> to PathPtr<'a>. The
+            // address was just reserved on the guest stack and holds a valid
+            // NUL-terminated string, and PathPtr is a thin wrapper around the
+            // raw pointer, which the guest reads as a `const char *`.
+            unsafe { std::mem::transmute::<AddrMut<'_, u8>, PathPtr<'_>>(addr) }
+        })
+        .collect())
+}
+
+/// Reserve at least `len` bytes on the guest stack
+///
+/// Reservations are typed, so `len` is rounded up to one of a few sizes.
+fn reserve_bytes<'a, S: Stack>(stack: &mut S, len: usize) -> Result<AddrMut<'a, u8>, Errno> {
+    Ok(match len {
+        0..=256 => stack.reserve::<[u8; 256]>().cast(),
+        257..=1024 => stack.reserve::<[u8; 1024]>().cast(),
+        1025..=PATH_MAX => stack.reserve::<[u8; PATH_MAX]>().cast(),
+        _ if len <= 2 * PATH_MAX => stack.reserve::<[u8; 2 * PATH_MAX]>().cast(),
+        _ => return Err(Errno::ENAMETOOLONG),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_c_string() {
+        let pid = std::process::id() as i32;
+        let path = std::ffi::CString::new("/agent/dir/file.txt").unwrap();
+        let bytes = read_c_string(pid, path.as_ptr() as usize, PATH_MAX).unwrap();
+        assert_eq!(bytes, path.to_bytes());
+
+        let long = std::ffi::CString::new(vec![b'a'; 100]).unwrap();
+        assert_eq!(
+            read_c_string(pid, long.as_ptr() as usize, 64),
+            Err(Errno::ENAMETOOLONG)
+        );
+    }
+
+    #[test]
+    fn test_read_c_string_before_unmapped_page() {
+        let pid = std::process::id() as i32;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let pages = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                2 * page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(pages, libc::MAP_FAILED);
+        let second_page = pages as usize + page_size;
+        unsafe { libc::munmap(second_page as *mut libc::c_void, page_size) };
+
+        // The string ends on the last byte of the mapped page
+        let path = b"/agent/file\0";
+        let addr = second_page - path.len();
+        unsafe { std::ptr::copy_nonoverlapping(path.as_ptr(), addr as *mut u8, path.len()) };
+        let bytes = read_c_string(pid, addr, PATH_MAX).unwrap();
+        assert_eq!(bytes, b"/agent/file");
+
+        // Without its NUL, it runs into the unmapped page
+        unsafe { *((second_page - 1) as *mut u8) = b'x' };
+        assert_eq!(read_c_string(pid, addr, PATH_MAX), Err(Errno::EFAULT));
+
+        unsafe { libc::munmap(pages, page_size) };
+    }
+}
@@ -1,17 +1,59 @@
+//! System call handlers for the sandbox.
+//!
+//! The set of path-based system calls the kernel delivers depends on the architecture.
+//! aarch64 only has the `*at` variants, so libc implements the legacy calls on top of
+//! them, and the legacy handlers are compiled for x86_64 only:
+//!
+//! | Operation        | x86_64                        | aarch64                |
+//! |------------------|-------------------------------|------------------------|
+//! | `stat`/`lstat`   | `newfstatat`, `statx`         | `fstatat`, `statx`     |
+//! | `open`           | `openat`                      | `openat`               |
+//! | `access`         | `access`, `faccessat(2)`      | `faccessat(2)`         |
+//! | `readlink`       | `readlink`, `readlinkat`      | `readlinkat`           |
+//! | `symlink`        | `symlink`, `symlinkat`        | `symlinkat`            |
+//! | `mkdir`          | `mkdir`, `mkdirat`            | `mkdirat`              |
+//! | `unlink`/`rmdir` | `unlink`, `rmdir`, `unlinkat` | `unlinkat`             |
+//! | `rename`         | `rename`, `renameat2`         | `renameat2`            |
+//! | `chmod`          | `chmod`, `fchmodat`           | `fchmodat`             |
+//! | `chown`/`lchown` | `chown`, `lchown`, `fchownat` | `fchownat`             |
+//!
+//! `fstatat` is the aarch64 name of `newfstatat`. glibc issues `newfstatat` and
+//! `openat` for `stat` and `open` on x86_64 as well, so the bare `stat`, `lstat` and
+//! `open` system calls are not intercepted.
+//!
+//! Each legacy handler shares its virtual-path logic with the `*at` handler, so both
+//! architectures behave the same.
+
+pub mod access;
+pub mod attr;
+pub mod dir;
 pub mod file;
+pub mod io;
+pub mod lock;
+pub mod memory;
+pub mod net;
+pub mod open;
+pub mod poll;
 pub mod process;
+pub mod rename;
 pub mod stat;
+pub mod truncate;
+pub mod unlink;
 pub mod xattr;
 
 use crate::{
     sandbox::Sandbox,
     vfs::{fdtable::FdTable, mount::MountTable},
 };
+use memory::{push_paths, read_path};
 use reverie::{
-    syscalls::{MemoryAccess, PathPtr, ReadAddr, Syscall},
-    Error, Guest, Stack,
+    syscalls::{Errno, PathPtr, Syscall, SyscallInfo},
+    Error, Guest,
+};
+use std::{
+    ffi::{CStr, CString},
+    path::PathBuf,
 };
-use std::{ffi::CString, path::PathBuf};
 
 /// Common path translation logic for syscalls.
 ///
@@ -20,10 +62,8 @@ use std::{ffi::CString, path::PathBuf};
 ///
 /// 1. Reads the original path from guest memory
 /// 2. Resolves the path through the mount table to get the translated host path
-/// 3. Converts the translated path to a C string
-/// 4. Allocates space on the guest stack for the new path
-/// 5. Writes the translated path to guest memory
-/// 6. Returns the new path address for use in the modified syscall
+/// 3. Writes the translated path to the guest stack
+/// 4. Returns the new path address for use in the modified syscall
 ///
 /// # Arguments
 /// * `guest` - The guest process being traced
@@ -45,7 +85,7 @@ pub(crate) async fn translate_path<'a, T: Guest<Sandbox>>(
     mount_table: &MountTable,
 ) -> Result<Option<PathPtr<'a>>, Error> {
     // Read the original path from guest memory
-    let path: PathBuf = path_addr.read(&guest.memory())?;
+    let path = read_path(guest, path_addr)?;
 
     // Only process valid UTF-8 paths
     if path.to_str().is_none() {
@@ -53,36 +93,96 @@ pub(crate) async fn translate_path<'a, T: Guest<Sandbox>>(
     }
 
     // Resolve through mount table to get the translated host path
-    let (_vfs, translated_path) = match mount_table.resolve(&path) {
+    let (_vfs, translated_path, _read_only) = match mount_table.resolve(&path) {
         Some(result) => result,
         None => return Ok(None), // No mount point matches, use original path
     };
 
-    // Convert translated path to a C string for the syscall
-    let new_path_str = translated_path.to_string_lossy().to_string();
-    let new_path_cstr = CString::new(new_path_str).map_err(|_| reverie::syscalls::Errno::EINVAL)?;
+    let new_path_cstr = path_cstring(translated_path)?;
+    let addrs = push_paths(guest, &[&new_path_cstr]).await?;
+    Ok(addrs.into_iter().next())
+}
+
+/// Whether the file open at `fd` lives under a read-only mount.
+///
+/// FD-based changes such as `fchmod` or `fsetxattr` check this against the path the
+/// FD was opened by, so that they fail with `EROFS` like their path-based
+/// counterparts, for passthrough and virtual FDs alike.
+pub(crate) fn fd_is_read_only(fd: i32, mount_table: &MountTable, fd_table: &FdTable) -> bool {
+    fd_table
+        .get(fd)
+        .and_then(|entry| entry.path().cloned())
+        .is_some_and(|path| matches!(mount_table.resolve(&path), Some((_, _, true))))
+}
+
+/// Path translation for syscalls that take two paths (like `linkat` and `renameat2`).
+///
+/// Both paths are resolved through the mount table before any guest memory is
+/// allocated. Paths that match a mount point are written to the guest stack,
+/// while paths that don't are returned unchanged.
+///
+/// # Returns
+/// * `Ok(Some((old, new)))` - At least one path was translated
+/// * `Ok(None)` - Neither path needs translation
+/// * `Err(e)` - An error occurred during translation
+pub(crate) async fn translate_path_pair<'a, T: Guest<Sandbox>>(
+    guest: &'a mut T,
+    oldpath_addr: PathPtr<'a>,
+    newpath_addr: PathPtr<'a>,
+    mount_table: &MountTable,
+) -> Result<Option<(PathPtr<'a>, PathPtr<'a>)>, Error> {
+    let oldpath = read_path(guest, oldpath_addr)?;
+    let newpath = read_path(guest, newpath_addr)?;
+
+    let translated_oldpath = mount_table.resolve(&oldpath).map(|(_vfs, path, _)| path);
+    let translated_newpath = mount_table.resolve(&newpath).map(|(_vfs, path, _)| path);
+    write_path_pair(
+        guest,
+        (oldpath_addr, translated_oldpath),
+        (newpath_addr, translated_newpath),
+    )
+    .await
+}
 
-    // Allocate space on the guest stack and write the new path
-    let bytes = new_path_cstr.as_bytes_with_nul();
-    let mut stack = guest.stack().await;
-    let addr: reverie::syscalls::AddrMut<PathBuf> = stack.reserve();
-    stack.commit()?;
+/// Write the translated paths of a syscall that takes two paths to the guest
+/// stack, given each path's address and its translation, if any.
+///
+/// # Returns
+/// * `Ok(Some((old, new)))` - The addresses to pass to the syscall: those of the
+///   translations, or the original address of a path without one
+/// * `Ok(None)` - Neither path has a translation
+/// * `Err(e)` - An error occurred writing the paths
+pub(crate) async fn write_path_pair<'a, T: Guest<Sandbox>>(
+    guest: &'a mut T,
+    (oldpath_addr, translated_oldpath): (PathPtr<'a>, Option<PathBuf>),
+    (newpath_addr, translated_newpath): (PathPtr<'a>, Option<PathBuf>),
+) -> Result<Option<(PathPtr<'a>, PathPtr<'a>)>, Error> {
+    let old_cstr = translated_oldpath.map(path_cstring).transpose()?;
+    let new_cstr = translated_newpath.map(path_cstring).transpose()?;
+    let paths: Vec<&CStr> = old_cstr
+        .iter()
+        .chain(&new_cstr)
+        .map(|c| c.as_c_str())
+        .collect();
+    if paths.is_empty() {
+        return Ok(None);
+    }
 
-    let byte_addr = addr.cast::<u8>();
-    guest.memory().write_exact(byte_addr, bytes)?;
+    let mut addrs = push_paths(guest, &paths).await?.into_iter();
+    let new_oldpath_addr = match old_cstr {
+        Some(_) => addrs.next().unwrap(),
+        None => oldpath_addr,
+    };
+    let new_newpath_addr = match new_cstr {
+        Some(_) => addrs.next().unwrap(),
+        None => newpath_addr,
+    };
+    Ok(Some((new_oldpath_addr, new_newpath_addr)))
+}
 
-    // SAFETY: The transmute converts AddrMut<u8> to PathPtr<'a>.
-    // This is safe because:
-    // 1. We just allocated `byte_addr` from the guest stack via stack.reserve()
-    // 2. We wrote a valid null-terminated C string to this address
-    // 3. Reverie treats these pointer types as thin wrappers around raw pointers
-    // 4. PathPtr is a newtype around CStrPtr, which is compatible with a char* pointer
-    // 5. The guest will read this as a const char* pointer for the syscall path argument
-    Ok(Some(unsafe {
-        std::mem::transmute::<reverie::syscalls::AddrMut<'_, u8>, reverie::syscalls::PathPtr<'_>>(
-            byte_addr,
-        )
-    }))
+/// Convert a translated path to a C string for a syscall
+fn path_cstring(path: PathBuf) -> Result<CString, Errno> {
+    CString::new(path.to_string_lossy().to_string()).map_err(|_| Errno::EINVAL)
 }
 
 /// System call dispatch.
@@ -102,15 +202,66 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
     // FIXME: We need to intercept all system calls that use a path or file descriptor.
     match &syscall {
         Syscall::Openat(args) => {
-            if let Some(result) = file::handle_openat(guest, args, mount_table, fd_table).await? {
+            if let Some(result) = open::handle_openat(guest, args, mount_table, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
-        Syscall::Read(args) => file::handle_read(guest, syscall, args, fd_table).await,
-        Syscall::Write(args) => file::handle_write(guest, syscall, args, fd_table).await,
+        Syscall::Read(args) => {
+            if let Some(result) = io::handle_read(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                file::handle_read(guest, syscall, args, fd_table).await
+            }
+        }
+        Syscall::Write(args) => {
+            if let Some(result) = io::handle_write(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                file::handle_write(guest, syscall, args, fd_table).await
+            }
+        }
         Syscall::Close(args) => file::handle_close(guest, syscall, args, fd_table).await,
+        Syscall::Fsync(args) => {
+            if let Some(result) = io::handle_fsync(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                file::handle_fsync(guest, syscall, args, fd_table).await
+            }
+        }
+        Syscall::Fdatasync(args) => {
+            if let Some(result) = io::handle_fdatasync(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                file::handle_fdatasync(guest, syscall, args, fd_table).await
+            }
+        }
+        Syscall::Truncate(args) => {
+            if let Some(result) = truncate::handle_truncate(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Ftruncate(args) => {
+            if let Some(result) =
+                truncate::handle_ftruncate(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Fallocate(args) => {
+            if let Some(result) =
+                truncate::handle_fallocate(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
         Syscall::Dup(args) => {
             if let Some(result) = file::handle_dup(guest, args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
@@ -134,17 +285,33 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
             }
         }
         #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Mkdir(args) => {
+            if let Some(result) = dir::handle_mkdir(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Mkdirat(args) => {
+            if let Some(result) = dir::handle_mkdirat(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        #[cfg(not(target_arch = "aarch64"))]
         Syscall::Rmdir(args) => {
-            if let Some(modified) = file::handle_rmdir(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+            if let Some(result) = unlink::handle_rmdir(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
-        #[cfg(target_arch = "aarch64")]
         Syscall::Unlinkat(args) => {
-            if let Some(modified) = file::handle_unlinkat(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+            if let Some(result) =
+                unlink::handle_unlinkat(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
@@ -196,16 +363,21 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
-        Syscall::Statfs(args) => {
-            if let Some(modified) = stat::handle_statfs(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+        #[cfg(target_arch = "aarch64")]
+        Syscall::Fstatat(args) => {
+            if let Some(result) = stat::handle_fstatat(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
+        Syscall::Statfs(args) => stat::handle_statfs(guest, syscall, args, mount_table).await,
+        Syscall::Fstatfs(args) => {
+            stat::handle_fstatfs(guest, syscall, args, mount_table, fd_table).await
+        }
         #[cfg(not(target_arch = "aarch64"))]
         Syscall::Readlink(args) => {
-            if let Some(result) = stat::handle_readlink(guest, args, mount_table).await? {
+            if let Some(result) = stat::handle_readlink(guest, args, mount_table, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
@@ -243,16 +415,94 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
-        Syscall::Llistxattr(args) => {
-            if let Some(modified) = xattr::handle_llistxattr(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+        Syscall::Getxattr(args) => {
+            if let Some(result) = xattr::handle_getxattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
         Syscall::Lgetxattr(args) => {
-            if let Some(modified) = xattr::handle_lgetxattr(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+            if let Some(result) = xattr::handle_lgetxattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Fgetxattr(args) => {
+            if let Some(result) =
+                xattr::handle_fgetxattr(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Setxattr(args) => {
+            if let Some(result) = xattr::handle_setxattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Lsetxattr(args) => {
+            if let Some(result) = xattr::handle_lsetxattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Fsetxattr(args) => {
+            if let Some(result) =
+                xattr::handle_fsetxattr(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Listxattr(args) => {
+            if let Some(result) = xattr::handle_listxattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Llistxattr(args) => {
+            if let Some(result) = xattr::handle_llistxattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Flistxattr(args) => {
+            if let Some(result) =
+                xattr::handle_flistxattr(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Removexattr(args) => {
+            if let Some(result) = xattr::handle_removexattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Lremovexattr(args) => {
+            if let Some(result) = xattr::handle_lremovexattr(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Fremovexattr(args) => {
+            if let Some(result) =
+                xattr::handle_fremovexattr(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
@@ -264,6 +514,13 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
+        Syscall::Flock(args) => {
+            if let Some(result) = lock::handle_flock(guest, args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
         Syscall::Fcntl(args) => {
             if let Some(result) = file::handle_fcntl(guest, args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
@@ -279,44 +536,138 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
             }
         }
         #[cfg(not(target_arch = "aarch64"))]
-        Syscall::Poll(args) => {
-            if let Some(result) = file::handle_poll(guest, args, fd_table).await? {
+        Syscall::Poll(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) = poll::handle_poll(guest, num, &args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
-        Syscall::Getdents64(args) => file::handle_getdents64(guest, syscall, args, fd_table).await,
-        Syscall::Fstat(args) => file::handle_fstat(guest, syscall, args, fd_table).await,
-        #[cfg(target_arch = "aarch64")]
-        Syscall::Fstatat(args) => {
-            file::handle_fstatat(guest, syscall, args, fd_table, mount_table).await
+        Syscall::Ppoll(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) = poll::handle_poll(guest, num, &args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::EpollCreate(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) = poll::handle_epoll_create(guest, num, &args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::EpollCreate1(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) = poll::handle_epoll_create(guest, num, &args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::EpollCtl(_) => {
+            let (_, args) = syscall.into_parts();
+            if let Some(result) = poll::handle_epoll_ctl(guest, &args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
         }
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::EpollWait(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) = poll::handle_epoll_wait(guest, num, &args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::EpollPwait(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) = poll::handle_epoll_wait(guest, num, &args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Getdents64(args) => {
+            if let Some(result) = dir::handle_getdents64(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                file::handle_getdents64(guest, syscall, args, fd_table).await
+            }
+        }
+        Syscall::Fstat(args) => file::handle_fstat(guest, syscall, args, fd_table).await,
         Syscall::Pread64(args) => {
-            if let Some(result) = file::handle_pread64(guest, args, fd_table).await? {
+            if let Some(result) = io::handle_pread64(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else if let Some(result) = file::handle_pread64(guest, args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
         Syscall::Pwrite64(args) => {
-            if let Some(result) = file::handle_pwrite64(guest, args, fd_table).await? {
+            if let Some(result) = io::handle_pwrite64(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else if let Some(result) = file::handle_pwrite64(guest, args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::CopyFileRange(args) => {
+            if let Some(result) =
+                io::handle_copy_file_range(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else if let Some(result) = file::handle_copy_file_range(guest, args, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Sendfile(args) => {
+            if let Some(result) = io::handle_sendfile(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else if let Some(result) = file::handle_sendfile(guest, args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
         #[cfg(not(target_arch = "aarch64"))]
-        Syscall::Lseek(args) => file::handle_lseek(guest, syscall, args, fd_table).await,
-        Syscall::Readv(args) => {
-            if let Some(result) = file::handle_readv(guest, args, fd_table).await? {
+        Syscall::Lseek(args) => {
+            if let Some(result) = io::handle_lseek(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                file::handle_lseek(guest, syscall, args, fd_table).await
+            }
+        }
+        Syscall::Readv(_) | Syscall::Preadv(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) = io::handle_readv(guest, num, &args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else if let Some(result) = file::handle_vectored(guest, num, &args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
-        Syscall::Writev(args) => {
-            if let Some(result) = file::handle_writev(guest, args, fd_table).await? {
+        Syscall::Writev(_) | Syscall::Pwritev(_) => {
+            let (num, args) = syscall.into_parts();
+            if let Some(result) =
+                io::handle_writev(guest, num, &args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else if let Some(result) = file::handle_vectored(guest, num, &args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
@@ -350,6 +701,13 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
+        Syscall::Recvfrom(args) => {
+            if let Some(result) = file::handle_recvfrom(guest, args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
         Syscall::Getpeername(args) => {
             if let Some(result) = file::handle_getpeername(guest, args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
@@ -366,7 +724,11 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
         Syscall::Execve(_) => Ok(SyscallResult::Syscall(syscall)),
         Syscall::Execveat(_) => Ok(SyscallResult::Syscall(syscall)),
         Syscall::Exit(_) => Ok(SyscallResult::Syscall(syscall)),
-        Syscall::ExitGroup(_) => Ok(SyscallResult::Syscall(syscall)),
+        Syscall::ExitGroup(_) => {
+            // The kernel won't see the process's record locks on virtual files
+            lock::release_process_locks(guest.pid().as_raw());
+            Ok(SyscallResult::Syscall(syscall))
+        }
         // Process information - passthrough
         Syscall::Getpid(_) => Ok(SyscallResult::Syscall(syscall)),
         Syscall::Getppid(_) => Ok(SyscallResult::Syscall(syscall)),
@@ -383,7 +745,9 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
         #[cfg(not(target_arch = "aarch64"))]
         Syscall::ArchPrctl(_) => Ok(SyscallResult::Syscall(syscall)),
         Syscall::Mmap(args) => {
-            if let Some(result) = file::handle_mmap(guest, args, fd_table).await? {
+            if let Some(result) = io::handle_mmap(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else if let Some(result) = file::handle_mmap(guest, args, fd_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
@@ -396,14 +760,15 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
         // Path-based file operations
         #[cfg(not(target_arch = "aarch64"))]
         Syscall::Access(args) => {
-            if let Some(modified) = file::handle_access(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+            if let Some(result) = access::handle_access(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
         Syscall::Faccessat(args) => {
-            if let Some(result) = file::handle_faccessat(guest, args, mount_table, fd_table).await?
+            if let Some(result) =
+                access::handle_faccessat(guest, args, mount_table, fd_table).await?
             {
                 Ok(SyscallResult::Value(result))
             } else {
@@ -412,29 +777,102 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
         }
         #[cfg(not(target_arch = "aarch64"))]
         Syscall::Rename(args) => {
-            if let Some(modified) = file::handle_rename(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+            if let Some(result) = rename::handle_rename(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Renameat2(args) => {
+            if let Some(result) =
+                rename::handle_renameat2(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
         #[cfg(not(target_arch = "aarch64"))]
         Syscall::Unlink(args) => {
-            if let Some(modified) = file::handle_unlink(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+            if let Some(result) = unlink::handle_unlink(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
         Syscall::Chdir(args) => {
-            if let Some(modified) = file::handle_chdir(guest, args, mount_table).await? {
-                Ok(SyscallResult::Syscall(modified))
+            file::handle_chdir(guest, syscall, args, mount_table, fd_table).await
+        }
+        Syscall::Fchdir(args) => {
+            if let Some(result) = file::handle_fchdir(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Getcwd(_) => {
+            let (_, args) = syscall.into_parts();
+            if let Some(result) = file::handle_getcwd(guest, &args, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Chmod(args) => {
+            if let Some(result) = attr::handle_chmod(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Fchmodat(args) => {
+            if let Some(result) = attr::handle_fchmodat(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Fchmod(args) => {
+            if let Some(result) = attr::handle_fchmod(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Fchown(args) => {
+            if let Some(result) = attr::handle_fchown(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
             }
         }
         Syscall::Fchownat(args) => {
-            if let Some(result) = file::handle_fchownat(guest, args, mount_table, fd_table).await? {
+            if let Some(result) = attr::handle_fchownat(guest, args, mount_table, fd_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        Syscall::Utimensat(args) => {
+            if let Some(result) = attr::handle_utimensat(guest, args, mount_table, fd_table).await?
+            {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Chown(args) => {
+            if let Some(result) = attr::handle_chown(guest, args, mount_table).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        Syscall::Lchown(args) => {
+            if let Some(result) = attr::handle_lchown(guest, args, mount_table).await? {
                 Ok(SyscallResult::Value(result))
             } else {
                 Ok(SyscallResult::Syscall(syscall))
@@ -450,6 +888,8 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
         Syscall::ClockGettime(_) => Ok(SyscallResult::Syscall(syscall)),
         Syscall::ClockGetres(_) => Ok(SyscallResult::Syscall(syscall)),
         Syscall::Gettimeofday(_) => Ok(SyscallResult::Syscall(syscall)),
+        Syscall::Nanosleep(_) => Ok(SyscallResult::Syscall(syscall)),
+        Syscall::ClockNanosleep(_) => Ok(SyscallResult::Syscall(syscall)),
         // Random - passthrough
         Syscall::Getrandom(_) => Ok(SyscallResult::Syscall(syscall)),
         // Resource limits - passthrough
@@ -470,7 +910,13 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
         // Permission management - passthrough
         Syscall::Setfsuid(_) => Ok(SyscallResult::Syscall(syscall)),
         Syscall::Setfsgid(_) => Ok(SyscallResult::Syscall(syscall)),
-        Syscall::Umask(_) => Ok(SyscallResult::Syscall(syscall)),
+        Syscall::Umask(args) => {
+            if let Some(result) = process::handle_umask(guest, args).await? {
+                Ok(SyscallResult::Value(result))
+            } else {
+                Ok(SyscallResult::Syscall(syscall))
+            }
+        }
         // Process control - passthrough
         Syscall::Prctl(_) => Ok(SyscallResult::Syscall(syscall)),
         // Handle specific "Other" syscalls by syscall number
@@ -478,9 +924,10 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
             use reverie::syscalls::Sysno;
             match *num {
                 Sysno::rseq => Ok(SyscallResult::Syscall(syscall)), // rseq - passthrough
-                Sysno::lseek => Ok(SyscallResult::Syscall(syscall)),
-                Sysno::fchmodat => {
-                    if let Some(result) = file::handle_chmod(guest, args, mount_table).await? {
+                Sysno::lseek => {
+                    if let Some(result) =
+                        io::handle_lseek_raw(guest, args, mount_table, fd_table).await?
+                    {
                         Ok(SyscallResult::Value(result))
                     } else {
                         Ok(SyscallResult::Syscall(syscall))
@@ -488,7 +935,7 @@ pub async fn dispatch_syscall<T: Guest<Sandbox>>(
                 }
                 Sysno::faccessat2 => {
                     if let Some(result) =
-                        file::handle_faccessat2(guest, args, mount_table, fd_table).await?
+                        access::handle_faccessat2(guest, args, mount_table, fd_table).await?
                     {
                         Ok(SyscallResult::Value(result))
                     } else {
@@ -0,0 +1,112 @@
+//! Network isolation.
+//!
+//! With networking disabled, the guest can only create and talk over `AF_UNIX`
+//! sockets: `socket` fails with `EACCES` for any other address family, and
+//! `connect`, `sendto` and `recvfrom` fail with `ENETUNREACH` when they target a
+//! non-local address or use a non-local socket the guest inherited.
+//!
+//! This is syscall-level filtering, not a network namespace. `socketcall` is
+//! not filtered since it only exists for 32-bit guests, which aren't supported.
+
+use crate::sandbox::{is_network_disabled, Sandbox};
+use reverie::{
+    syscalls::{Addr, MemoryAccess},
+    Error, Guest,
+};
+
+/// Whether sockets of address `family` stay on the local host
+pub fn is_local_family(family: i32) -> bool {
+    // AF_UNSPEC is used to dissolve a socket's association in `connect`
+    family == libc::AF_UNIX || family == libc::AF_UNSPEC
+}
+
+/// Fail with `EACCES` if networking is disabled and `family` isn't local.
+pub fn check_socket_family(family: i32) -> Option<i64> {
+    if is_network_disabled() && !is_local_family(family) {
+        Some(-libc::EACCES as i64)
+    } else {
+        None
+    }
+}
+
+/// Fail with `ENETUNREACH` if networking is disabled and the guest is about to
+/// use the network through `kernel_fd`.
+///
+/// With an `addr`, its address family decides. Without one, the socket
+/// itself must be local.
+pub fn check_network_access<T: Guest<Sandbox>>(
+    guest: &T,
+    kernel_fd: i32,
+    addr: Option<Addr<libc::sockaddr>>,
+) -> Result<Option<i64>, Error> {
+    if !is_network_disabled() {
+        return Ok(None);
+    }
+    let local = match addr {
+        Some(addr) => {
+            let family: libc::sa_family_t = guest.memory().read_value(addr.cast())?;
+            is_local_family(family as i32)
+        }
+        None => !is_network_socket(guest.pid().as_raw(), kernel_fd),
+    };
+    Ok((!local).then_some(-libc::ENETUNREACH as i64))
+}
+
+/// Whether `kernel_fd` of process `pid` is a socket other than `AF_UNIX`.
+///
+/// Sockets are looked up by inode in `/proc/<pid>/net/unix`. Descriptors that
+/// aren't sockets are left for the kernel to reject.
+fn is_network_socket(pid: i32, kernel_fd: i32) -> bool {
+    let Ok(link) = std::fs::read_link(format!("/proc/{}/fd/{}", pid, kernel_fd)) else {
+        return false;
+    };
+    let Some(inode) = socket_inode(&link.to_string_lossy()) else {
+        return false;
+    };
+    match std::fs::read_to_string(format!("/proc/{}/net/unix", pid)) {
+        Ok(net_unix) => !lists_unix_socket(&net_unix, inode),
+        // Can't tell, so err on the side of isolation
+        Err(_) => true,
+    }
+}
+
+/// Parse the inode out of a `socket:[<inode>]` FD link.
+fn socket_inode(link: &str) -> Option<u64> {
+    link.strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// Whether a `/proc/net/unix` table lists the socket with `inode`.
+fn lists_unix_socket(net_unix: &str, inode: u64) -> bool {
+    net_unix
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(6))
+        .any(|field| field.parse() == Ok(inode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_inode() {
+        assert_eq!(socket_inode("socket:[12345]"), Some(12345));
+        assert_eq!(socket_inode("pipe:[12345]"), None);
+        assert_eq!(socket_inode("/tmp/file"), None);
+    }
+
+    #[test]
+    fn test_lists_unix_socket() {
+        let net_unix = "\
+Num       RefCount Protocol Flags    Type St Inode Path
+0000000000000000: 00000002 00000000 00010000 0001 01 20567 /run/systemd/private
+0000000000000000: 00000003 00000000 00000000 0001 03 31337
+";
+        assert!(lists_unix_socket(net_unix, 20567));
+        assert!(lists_unix_socket(net_unix, 31337));
+        assert!(!lists_unix_socket(net_unix, 1));
+    }
+}
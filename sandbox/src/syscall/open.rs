@@ -0,0 +1,195 @@
+use crate::{
+    sandbox::{self, Sandbox},
+    syscall::{memory::read_path, translate_path},
+    vfs::{
+        fdtable::{FdEntry, FdTable},
+        mount::MountTable,
+        Vfs,
+    },
+};
+use reverie::{syscalls::Syscall, Error, Guest};
+use std::path::{Path, PathBuf};
+
+/// Resolve the `dirfd` argument of an `*at` system call.
+///
+/// `AT_FDCWD` is passed through, absolute paths ignore the dirfd, and virtual FDs
+/// are translated to kernel FDs. If the dirfd refers to a virtual directory, the
+/// relative path is joined onto the directory path so that it can be resolved
+/// through the mount table, and `AT_FDCWD` is returned as the kernel dirfd. The
+/// same goes for `AT_FDCWD` while the working directory is a virtual directory
+/// (see `FdTable::virtual_cwd`).
+///
+/// Returns `Err(errno)` if the dirfd refers to a virtual file without a path.
+pub(crate) fn resolve_dirfd(
+    dirfd: i32,
+    path: &mut PathBuf,
+    fd_table: &FdTable,
+) -> Result<i32, i64> {
+    if dirfd == libc::AT_FDCWD {
+        if path.is_relative() {
+            if let Some(cwd) = fd_table.virtual_cwd() {
+                *path = cwd.join(&*path);
+            }
+        }
+        return Ok(dirfd);
+    }
+    if path.is_absolute() {
+        // Absolute path - dirfd is ignored
+        return Ok(libc::AT_FDCWD);
+    }
+    match fd_table.get(dirfd) {
+        Some(dir_entry) => {
+            if let Some(kfd) = dir_entry.kernel_fd() {
+                // Passthrough directory - use the kernel FD and keep path as-is
+                Ok(kfd)
+            } else if let Some(dir_path) = dir_entry.path() {
+                // Virtual directory - resolve relative path against the directory's path
+                *path = dir_path.join(&*path);
+                Ok(libc::AT_FDCWD)
+            } else {
+                // Virtual file without a path - cannot be used as a directory
+                Err(-libc::EBADF as i64)
+            }
+        }
+        // dirfd not in table - let the kernel report the error
+        None => Ok(dirfd),
+    }
+}
+
+/// Whether an open with `flags` would modify the filesystem.
+///
+/// Write access and `O_TRUNC` always do. `O_CREAT` only does if the file does not exist
+/// yet, which is checked in the VFS for virtual mounts and on the host otherwise.
+async fn opens_for_write(flags: i32, vfs: &dyn Vfs, path: &Path, translated_path: &Path) -> bool {
+    if flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0 {
+        return true;
+    }
+    if flags & libc::O_CREAT == 0 {
+        return false;
+    }
+    if vfs.is_virtual() {
+        vfs.stat(path).await.is_err()
+    } else {
+        !translated_path.exists()
+    }
+}
+
+/// Open an unnamed file in the virtual directory `dir` for `O_TMPFILE`.
+///
+/// As on Linux, the file must be opened for writing, and its FD reads as
+/// `dir/#INO (deleted)` through `/proc/self/fd`. That path lies under the mount,
+/// so I/O on the FD still finds the VFS.
+async fn open_tmpfile(
+    vfs: &dyn Vfs,
+    dir: PathBuf,
+    flags: i32,
+    mode: u32,
+    fd_table: &FdTable,
+) -> i64 {
+    if flags & libc::O_ACCMODE == libc::O_RDONLY {
+        return -libc::EINVAL as i64;
+    }
+    let file_ops = match vfs.open_tmpfile(&dir, flags, mode).await {
+        Ok(file_ops) => file_ops,
+        Err(e) => return e.to_errno(),
+    };
+    let ino = match file_ops.fstat().await {
+        Ok(stat) => stat.st_ino,
+        Err(e) => return e.to_errno(),
+    };
+    let entry = FdEntry::Virtual {
+        file_ops,
+        flags,
+        path: Some(dir.join(format!("#{} (deleted)", ino))),
+    };
+    fd_table.allocate(entry) as i64
+}
+
+/// The `openat` system call.
+///
+/// This intercepts `openat` system calls and translates paths according to the mount table,
+/// virtualizes the dirfd parameter, and virtualizes the returned file descriptor.
+///
+/// For virtual mounts (like SQLite), the file is opened through `Vfs::open` and a virtual
+/// FD is registered in the FD table. `O_CREAT`, `O_EXCL`, `O_TRUNC` and `O_DIRECTORY`
+/// are handled by the VFS and errors are mapped to errno values via `VfsError::to_errno`.
+/// `O_TMPFILE` creates an unnamed file through `Vfs::open_tmpfile` instead.
+/// Opens that would modify a read-only mount fail with `EROFS`.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_openat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Openat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+
+    // Read the original path from guest memory
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    // Check if this path matches a mount point
+    if let Some((vfs, translated_path, read_only)) = mount_table.resolve(&path) {
+        let flags = args.flags().bits();
+        if read_only && opens_for_write(flags, &*vfs, &path, &translated_path).await {
+            return Ok(Some(-libc::EROFS as i64));
+        }
+
+        if vfs.is_virtual() {
+            if fd_table.is_full() {
+                return Ok(Some(-libc::EMFILE as i64));
+            }
+
+            // For virtual VFS, open the file directly without going to the kernel
+            let mode = args.mode().map(|m| m.bits()).unwrap_or(0o644);
+            let mode = mount_table.create_mode(&path, mode, false);
+            let mode = mode & !sandbox::umask(guest.pid().as_raw());
+            if flags & libc::O_TMPFILE == libc::O_TMPFILE {
+                return Ok(Some(open_tmpfile(&*vfs, path, flags, mode, fd_table).await));
+            }
+            return match vfs.open(&path, flags, mode).await {
+                Ok(file_ops) => {
+                    // Store the path with the FD entry so it can serve as a dirfd
+                    let entry = FdEntry::Virtual {
+                        file_ops,
+                        flags,
+                        path: Some(path),
+                    };
+                    Ok(Some(fd_table.allocate(entry) as i64))
+                }
+                Err(e) => Ok(Some(e.to_errno())),
+            };
+        }
+    }
+
+    // Passthrough: translate the path if it is under a mount point, then call the kernel
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+
+    let new_syscall = reverie::syscalls::Openat::new()
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)))
+        .with_flags(args.flags())
+        .with_mode(args.mode());
+
+    let kernel_fd = guest.inject(Syscall::Openat(new_syscall)).await?;
+
+    if kernel_fd < 0 {
+        return Ok(Some(kernel_fd));
+    }
+
+    let entry = FdEntry::Passthrough {
+        kernel_fd: kernel_fd as i32,
+        flags: args.flags().bits(),
+        path: Some(path),
+    };
+    Ok(Some(fd_table.allocate(entry) as i64))
+}
@@ -0,0 +1,293 @@
+//! Readiness of FDs with `poll`, `ppoll` and `epoll`.
+//!
+//! Passthrough FDs are translated to their kernel FDs before the real syscall is
+//! injected. Virtual files have no kernel FD to wait on, but like regular files on
+//! the kernel they never block, so they are reported readable and writable right
+//! away. When any of them is ready the real syscall still runs for the kernel FDs,
+//! with a zero timeout so that it doesn't wait.
+//!
+//! The kernel refuses to add virtual files to an epoll instance, so `epoll_ctl`
+//! keeps their registrations in the FD table instead (see `FdTable::epoll_ctl`),
+//! and `epoll_wait` reports them after the events of the kernel FDs.
+
+use crate::{
+    sandbox::Sandbox,
+    vfs::fdtable::{FdEntry, FdTable},
+};
+use reverie::{
+    syscalls::{AddrMut, MemoryAccess, PollFd, PollFlags, Syscall, SyscallArgs, Sysno},
+    Error, Guest, Stack,
+};
+
+/// Events a virtual file is always ready for, in `poll` terms
+fn virtual_poll_events() -> PollFlags {
+    PollFlags::POLLIN | PollFlags::POLLOUT | PollFlags::POLLRDNORM | PollFlags::POLLWRNORM
+}
+
+/// The `poll` and `ppoll` system calls.
+///
+/// This intercepts `poll` and `ppoll` system calls and translates virtual FDs in the
+/// pollfd array to kernel FDs before calling the real syscall, then copies the
+/// results back. Virtual files are left out of the kernel's array, as negative FDs
+/// are ignored, and report the events they are asked for out of `POLLIN`, `POLLOUT`,
+/// `POLLRDNORM` and `POLLWRNORM`. When any of them does, the timeout is replaced by
+/// zero.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_poll<T: Guest<Sandbox>>(
+    guest: &mut T,
+    num: Sysno,
+    args: &SyscallArgs,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let nfds = args.arg1;
+    if nfds == 0 {
+        return Ok(None);
+    }
+
+    let fds_addr = match AddrMut::<PollFd>::from_raw(args.arg0) {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+    let pollfd_size = std::mem::size_of::<PollFd>() as isize;
+
+    // Read the pollfd array from guest memory
+    let mut pollfds: Vec<PollFd> = Vec::with_capacity(nfds);
+    for i in 0..nfds {
+        let offset = i as isize * pollfd_size;
+        let pollfd: PollFd = unsafe { guest.memory().read_value(fds_addr.offset(offset))? };
+        pollfds.push(pollfd);
+    }
+
+    // Virtual files are ready without asking the kernel
+    let virtual_revents: Vec<PollFlags> = pollfds
+        .iter()
+        .map(|pollfd| match fd_table.get(pollfd.fd) {
+            Some(FdEntry::Virtual { .. }) => pollfd.events & virtual_poll_events(),
+            _ => PollFlags::empty(),
+        })
+        .collect();
+    let virtual_ready = virtual_revents.iter().any(|revents| !revents.is_empty());
+
+    // Allocate space on stack for the kernel pollfd array and a zero timeout
+    let mut stack = guest.stack().await;
+    let kernel_fds_addr: AddrMut<PollFd> = stack.reserve();
+
+    // Reserve space for remaining pollfds
+    for _ in 1..nfds {
+        let _: AddrMut<PollFd> = stack.reserve();
+    }
+    let zero_timeout_addr: AddrMut<libc::timespec> = stack.reserve();
+
+    stack.commit()?;
+
+    // Write kernel pollfds to guest memory
+    for (i, pollfd) in pollfds.iter().enumerate() {
+        let kernel_fd = match fd_table.get(pollfd.fd) {
+            Some(FdEntry::Virtual { .. }) => -1,
+            _ => fd_table.translate(pollfd.fd).unwrap_or(pollfd.fd),
+        };
+        let kernel_pollfd = PollFd {
+            fd: kernel_fd,
+            events: pollfd.events,
+            revents: PollFlags::empty(),
+        };
+
+        let offset = i as isize * pollfd_size;
+        unsafe {
+            guest
+                .memory()
+                .write_value(kernel_fds_addr.offset(offset), &kernel_pollfd)?;
+        }
+    }
+
+    // Create and inject the syscall with translated FDs, without waiting if a
+    // virtual file is ready. `poll` takes its timeout in milliseconds and `ppoll`
+    // takes a pointer to a timespec.
+    let mut kernel_args = SyscallArgs {
+        arg0: kernel_fds_addr.as_raw(),
+        ..*args
+    };
+    if virtual_ready {
+        kernel_args.arg2 = if num == Sysno::ppoll {
+            let zero = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            guest.memory().write_value(zero_timeout_addr, &zero)?;
+            zero_timeout_addr.as_raw()
+        } else {
+            0
+        };
+    }
+
+    let result = guest.inject(Syscall::Other(num, kernel_args)).await?;
+
+    // If the syscall failed, return early
+    if result < 0 {
+        return Ok(Some(result));
+    }
+
+    // Read back the kernel pollfds and merge in the events of virtual files
+    let mut ready = 0;
+    for (i, pollfd) in pollfds.iter().enumerate() {
+        let offset = i as isize * pollfd_size;
+        let kernel_pollfd: PollFd =
+            unsafe { guest.memory().read_value(kernel_fds_addr.offset(offset))? };
+
+        // Write back the revents to the original pollfd array
+        let revents = kernel_pollfd.revents | virtual_revents[i];
+        if !revents.is_empty() {
+            ready += 1;
+        }
+        let virt_pollfd = PollFd {
+            fd: pollfd.fd, // Keep the virtual FD
+            events: pollfd.events,
+            revents,
+        };
+
+        unsafe {
+            guest
+                .memory()
+                .write_value(fds_addr.offset(offset), &virt_pollfd)?;
+        }
+    }
+
+    Ok(Some(ready))
+}
+
+/// The `epoll_create` and `epoll_create1` system calls.
+///
+/// This runs the real syscall and allocates a virtual FD for the new epoll instance.
+pub async fn handle_epoll_create<T: Guest<Sandbox>>(
+    guest: &mut T,
+    num: Sysno,
+    args: &SyscallArgs,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let kernel_fd = guest.inject(Syscall::Other(num, *args)).await?;
+
+    // `EPOLL_CLOEXEC` is `O_CLOEXEC`, and `epoll_create` has no flags
+    let flags = match num {
+        Sysno::epoll_create1 => args.arg0 as i32 & libc::EPOLL_CLOEXEC,
+        _ => 0,
+    };
+    let entry = FdEntry::Passthrough {
+        kernel_fd: kernel_fd as i32,
+        flags,
+        path: None,
+    };
+    Ok(Some(fd_table.allocate(entry) as i64))
+}
+
+/// The `epoll_ctl` system call.
+///
+/// This intercepts `epoll_ctl` system calls and translates the epoll FD and the
+/// target FD to kernel FDs. Virtual files are registered in the FD table instead,
+/// with the events and user data of the `epoll_event`, for `handle_epoll_wait`
+/// to report.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_epoll_ctl<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &SyscallArgs,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (epfd, op, fd) = (args.arg0 as i32, args.arg1 as i32, args.arg2 as i32);
+
+    let kernel_epfd = match fd_table.get(epfd) {
+        Some(FdEntry::Passthrough { kernel_fd, .. }) => kernel_fd,
+        // A virtual file is not an epoll instance
+        Some(FdEntry::Virtual { .. }) => return Ok(Some(-libc::EINVAL as i64)),
+        None => return Ok(Some(-libc::EBADF as i64)),
+    };
+
+    match fd_table.get(fd) {
+        Some(FdEntry::Passthrough { kernel_fd, .. }) => {
+            let result = guest
+                .inject(Syscall::Other(
+                    Sysno::epoll_ctl,
+                    SyscallArgs {
+                        arg0: kernel_epfd as usize,
+                        arg2: kernel_fd as usize,
+                        ..*args
+                    },
+                ))
+                .await?;
+            Ok(Some(result))
+        }
+        Some(FdEntry::Virtual { .. }) => {
+            let event = if op == libc::EPOLL_CTL_DEL {
+                libc::epoll_event { events: 0, u64: 0 }
+            } else {
+                match AddrMut::<libc::epoll_event>::from_raw(args.arg3) {
+                    Some(addr) => guest.memory().read_value(addr)?,
+                    None => return Ok(Some(-libc::EFAULT as i64)),
+                }
+            };
+
+            let result = match fd_table.epoll_ctl(kernel_epfd, op, fd, event.events, event.u64) {
+                Ok(()) => 0,
+                Err(errno) => -errno as i64,
+            };
+            Ok(Some(result))
+        }
+        None => Ok(Some(-libc::EBADF as i64)),
+    }
+}
+
+/// The `epoll_wait` and `epoll_pwait` system calls.
+///
+/// This intercepts `epoll_wait` system calls and translates the epoll FD to its
+/// kernel FD. If virtual files registered with the instance are ready, the real
+/// syscall runs with a zero timeout, and their events fill the rest of the array
+/// after those of the kernel FDs.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_epoll_wait<T: Guest<Sandbox>>(
+    guest: &mut T,
+    num: Sysno,
+    args: &SyscallArgs,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let kernel_epfd = match fd_table.translate(args.arg0 as i32) {
+        Some(kernel_epfd) => kernel_epfd,
+        // FD not in table, let the original syscall through (will likely fail with EBADF)
+        None => return Ok(None),
+    };
+
+    let max_events = args.arg2 as i32;
+    let virtual_ready = max_events > 0 && fd_table.epoll_has_ready(kernel_epfd);
+    let mut kernel_args = SyscallArgs {
+        arg0: kernel_epfd as usize,
+        ..*args
+    };
+    if virtual_ready {
+        kernel_args.arg3 = 0;
+    }
+
+    let result = guest.inject(Syscall::Other(num, kernel_args)).await?;
+    if result < 0 || !virtual_ready {
+        return Ok(Some(result));
+    }
+
+    let kernel_events = result as usize;
+    let ready = fd_table.epoll_take_ready(kernel_epfd, max_events as usize - kernel_events);
+    let event_size = std::mem::size_of::<libc::epoll_event>();
+    for (i, (events, data)) in ready.iter().enumerate() {
+        let addr = args.arg1 + (kernel_events + i) * event_size;
+        let event = libc::epoll_event {
+            events: *events,
+            u64: *data,
+        };
+        match AddrMut::<libc::epoll_event>::from_raw(addr) {
+            Some(addr) => guest.memory().write_value(addr, &event)?,
+            None => return Ok(Some(-libc::EFAULT as i64)),
+        }
+    }
+
+    Ok(Some((kernel_events + ready.len()) as i64))
+}
@@ -1,5 +1,13 @@
-use crate::{sandbox, sandbox::Sandbox, vfs::fdtable::FdTable};
-use reverie::{syscalls::Syscall, Error, Guest};
+use crate::{
+    sandbox,
+    sandbox::Sandbox,
+    syscall::file,
+    vfs::fdtable::{FdEntry, FdTable},
+};
+use reverie::{
+    syscalls::{MemoryAccess, Syscall},
+    Error, Guest,
+};
 
 /// The `fork` system call.
 ///
@@ -21,6 +29,7 @@ pub async fn handle_fork<T: Guest<Sandbox>>(
         // Create a deep copy of our FD table for the child
         let child_fd_table = parent_fd_table.deep_clone();
         sandbox::insert_fd_table(result as i32, child_fd_table);
+        sandbox::inherit_umask(guest.pid().as_raw(), result as i32, false);
     }
     // If result == 0, we're in the child - the FD table was already set up by the parent
     // If result < 0, fork failed - no action needed
@@ -52,16 +61,36 @@ pub async fn handle_vfork<T: Guest<Sandbox>>(
         // since the child will exec or exit, and we need independent FD tracking)
         let child_fd_table = parent_fd_table.deep_clone();
         sandbox::insert_fd_table(result as i32, child_fd_table);
+        sandbox::inherit_umask(guest.pid().as_raw(), result as i32, false);
     }
 
     Ok(Some(result))
 }
 
-/// The `clone` system call.
+/// Set up the FD table and umask of a child created by `clone` or `clone3`.
 ///
-/// This intercepts `clone` system calls. Clone behavior depends on flags:
 /// - CLONE_FILES: child shares FD table with parent (shallow copy)
 /// - No CLONE_FILES: child gets independent FD table (deep copy, like fork)
+/// - CLONE_FS: child shares the umask with the parent, otherwise it gets a copy
+///
+/// Either way the child's virtual FDs refer to the parent's file handles, and so
+/// share their file offsets, as the kernel's do after `fork`.
+fn inherit_process_state(parent_pid: i32, child_pid: i32, parent_fd_table: &FdTable, flags: u64) {
+    if flags & libc::CLONE_FILES as u64 != 0 {
+        // Both parent and child will see the same FdTable Arc
+        sandbox::insert_fd_table(child_pid, parent_fd_table.clone());
+    } else {
+        sandbox::insert_fd_table(child_pid, parent_fd_table.deep_clone());
+    }
+
+    let share_fs = flags & libc::CLONE_FS as u64 != 0;
+    sandbox::inherit_umask(parent_pid, child_pid, share_fs);
+}
+
+/// The `clone` system call.
+///
+/// This intercepts `clone` system calls to give the child an FD table according to
+/// the clone flags; see `inherit_process_state`.
 ///
 /// Note: CLONE_FILES is typically used for threads, while process clones
 /// without CLONE_FILES behave like fork.
@@ -75,21 +104,8 @@ pub async fn handle_clone<T: Guest<Sandbox>>(
 
     if result > 0 {
         // We are in the parent process - result is the child PID/TID
-        // Check if CLONE_FILES flag is set (0x00000400)
-        const CLONE_FILES: i32 = 0x00000400;
-
-        let flags = args.flags();
-        let share_fds = flags.bits() & CLONE_FILES != 0;
-
-        if share_fds {
-            // CLONE_FILES set - share the FD table (shallow copy)
-            // Both parent and child will see the same FdTable Arc
-            sandbox::insert_fd_table(result as i32, parent_fd_table.clone());
-        } else {
-            // CLONE_FILES not set - create independent FD table (deep copy)
-            let child_fd_table = parent_fd_table.deep_clone();
-            sandbox::insert_fd_table(result as i32, child_fd_table);
-        }
+        let flags = args.flags().bits() as u32 as u64;
+        inherit_process_state(guest.pid().as_raw(), result as i32, parent_fd_table, flags);
     }
     // If result == 0, we're in the child - FD table already set up by parent
     // If result < 0, clone failed
@@ -99,24 +115,57 @@ pub async fn handle_clone<T: Guest<Sandbox>>(
 
 /// The `clone3` system call.
 ///
-/// This is the modern clone interface. We need to parse the clone_args structure
-/// to determine the flags.
+/// This is the modern clone interface, which glibc uses for `fork` and threads
+/// alike. The flags are the first field of the `clone_args` structure, which is
+/// read from guest memory before the child starts.
 pub async fn handle_clone3<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Clone3,
     parent_fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
+    let flags = match args.args() {
+        Some(addr) => guest.memory().read_value(addr.cast::<u64>())?,
+        None => return Ok(Some(-libc::EFAULT as i64)),
+    };
+
     // Execute the clone3 syscall
     let result = guest.inject(Syscall::Clone3(*args)).await?;
 
     if result > 0 {
         // Parent process - result is child PID/TID
-        // For clone3, we'd need to read the clone_args structure from memory
-        // to get the flags. For now, we default to deep copy (safer).
-        // TODO: Parse clone_args to check CLONE_FILES flag
-        let child_fd_table = parent_fd_table.deep_clone();
-        sandbox::insert_fd_table(result as i32, child_fd_table);
+        inherit_process_state(guest.pid().as_raw(), result as i32, parent_fd_table, flags);
     }
 
     Ok(Some(result))
 }
+
+/// The `umask` system call.
+///
+/// This records the new mask for the process, so that files and directories created
+/// in virtual mounts get `mode & !umask`. The syscall is still run, so that the
+/// kernel applies the mask to host files as well. The previous mask is returned
+/// as tracked by the sandbox, which starts at `DEFAULT_UMASK`.
+pub async fn handle_umask<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Umask,
+) -> Result<Option<i64>, Error> {
+    guest.inject(Syscall::Umask(*args)).await?;
+    let previous = sandbox::set_umask(guest.pid().as_raw(), args.mask().bits());
+    Ok(Some(previous as i64))
+}
+
+/// Drop close-on-exec FDs after a successful `execve`.
+///
+/// The kernel closes its own `O_CLOEXEC` FDs, but the new program must not inherit the
+/// matching FD table entries either. Virtual files are closed along with their last FD.
+/// Passthrough entries are only forgotten: the kernel alone decides which of its FDs
+/// survive the exec, and a kernel FD it kept may be in use by the new program.
+/// All other entries are preserved.
+pub async fn handle_post_exec<T: Guest<Sandbox>>(guest: &mut T, fd_table: &FdTable) {
+    for (entry, last) in fd_table.release_cloexec() {
+        if let FdEntry::Virtual { .. } = entry {
+            // The program has already been replaced, so there is nobody to report errors to
+            file::close_entry(guest, entry, last).await.ok();
+        }
+    }
+}
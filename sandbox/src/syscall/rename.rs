@@ -0,0 +1,170 @@
+use crate::{
+    sandbox::Sandbox,
+    syscall::{memory::read_path, open::resolve_dirfd, translate_path_pair},
+    vfs::{fdtable::FdTable, mount::MountTable, Vfs},
+};
+use reverie::{syscalls::Syscall, Error, Guest};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Outcome of resolving both paths of a rename through the mount table.
+enum RenameTarget {
+    /// Both paths live in the same virtual VFS
+    Virtual(Arc<dyn Vfs>),
+    /// One path is virtual and the other is not (or they are different VFSes)
+    CrossDevice,
+    /// Both paths live in the same read-only mount
+    ReadOnly,
+    /// Neither path is virtual - the kernel performs the rename
+    Host,
+}
+
+/// Classify a rename by the VFSes owning its source and destination paths.
+///
+/// A rename touching a read-only mount fails with `EROFS` when both paths are in that
+/// mount, and with `EXDEV` otherwise, matching the kernel's order of checks.
+fn classify(oldpath: &Path, newpath: &Path, mount_table: &MountTable) -> RenameTarget {
+    let old_mount = mount_table.resolve(oldpath);
+    let new_mount = mount_table.resolve(newpath);
+
+    let read_only =
+        |mount: &Option<(Arc<dyn Vfs>, PathBuf, bool)>| matches!(mount, Some((_, _, true)));
+    if read_only(&old_mount) || read_only(&new_mount) {
+        return match (old_mount, new_mount) {
+            (Some((old_vfs, ..)), Some((new_vfs, ..))) if Arc::ptr_eq(&old_vfs, &new_vfs) => {
+                RenameTarget::ReadOnly
+            }
+            _ => RenameTarget::CrossDevice,
+        };
+    }
+
+    let old_vfs = old_mount
+        .map(|(vfs, _, _)| vfs)
+        .filter(|vfs| vfs.is_virtual());
+    let new_vfs = new_mount
+        .map(|(vfs, _, _)| vfs)
+        .filter(|vfs| vfs.is_virtual());
+
+    match (old_vfs, new_vfs) {
+        (Some(old_vfs), Some(new_vfs)) if Arc::ptr_eq(&old_vfs, &new_vfs) => {
+            RenameTarget::Virtual(old_vfs)
+        }
+        (None, None) => RenameTarget::Host,
+        _ => RenameTarget::CrossDevice,
+    }
+}
+
+/// Perform a rename within a virtual VFS, returning the syscall result.
+async fn rename_virtual(vfs: &dyn Vfs, oldpath: &Path, newpath: &Path, flags: u32) -> i64 {
+    match vfs.rename(oldpath, newpath, flags).await {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
+/// The `rename` system call.
+///
+/// This intercepts `rename` system calls and resolves both paths through the mount table.
+/// Renames within a virtual VFS are performed by `Vfs::rename`, renames between a virtual
+/// and a host path fail with `EXDEV` so that callers fall back to copy and unlink, and
+/// host-to-host renames are translated and passed to the kernel.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_rename<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Rename,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    let (oldpath_addr, newpath_addr) = match (args.oldpath(), args.newpath()) {
+        (Some(oldpath_addr), Some(newpath_addr)) => (oldpath_addr, newpath_addr),
+        _ => return Ok(None),
+    };
+
+    let oldpath: PathBuf = read_path(guest, oldpath_addr)?;
+    let newpath: PathBuf = read_path(guest, newpath_addr)?;
+
+    match classify(&oldpath, &newpath, mount_table) {
+        RenameTarget::Virtual(vfs) => {
+            return Ok(Some(rename_virtual(&*vfs, &oldpath, &newpath, 0).await));
+        }
+        RenameTarget::CrossDevice => return Ok(Some(-libc::EXDEV as i64)),
+        RenameTarget::ReadOnly => return Ok(Some(-libc::EROFS as i64)),
+        RenameTarget::Host => {}
+    }
+
+    if let Some((new_oldpath_addr, new_newpath_addr)) =
+        translate_path_pair(guest, oldpath_addr, newpath_addr, mount_table).await?
+    {
+        let new_syscall = reverie::syscalls::Rename::new()
+            .with_oldpath(Some(new_oldpath_addr))
+            .with_newpath(Some(new_newpath_addr));
+        let result = guest.inject(Syscall::Rename(new_syscall)).await?;
+        return Ok(Some(result));
+    }
+    Ok(None)
+}
+
+/// The `renameat2` system call.
+///
+/// This intercepts `renameat2` system calls, virtualizes both dirfds, and resolves both
+/// paths through the mount table. Renames within a virtual VFS are performed by
+/// `Vfs::rename` honoring `RENAME_NOREPLACE` and `RENAME_EXCHANGE`, as well as
+/// `RENAME_WHITEOUT` in overlay mounts (other virtual mounts fail with `EINVAL`),
+/// renames between a virtual and a host path fail with `EXDEV`, and host-to-host
+/// renames are translated and passed to the kernel.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_renameat2<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Renameat2,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let (oldpath_addr, newpath_addr) = match (args.oldpath(), args.newpath()) {
+        (Some(oldpath_addr), Some(newpath_addr)) => (oldpath_addr, newpath_addr),
+        _ => return Ok(None),
+    };
+
+    let mut oldpath: PathBuf = read_path(guest, oldpath_addr)?;
+    let mut newpath: PathBuf = read_path(guest, newpath_addr)?;
+
+    let kernel_olddirfd = match resolve_dirfd(args.olddirfd(), &mut oldpath, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+    let kernel_newdirfd = match resolve_dirfd(args.newdirfd(), &mut newpath, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    match classify(&oldpath, &newpath, mount_table) {
+        RenameTarget::Virtual(vfs) => {
+            return Ok(Some(
+                rename_virtual(&*vfs, &oldpath, &newpath, args.flags()).await,
+            ));
+        }
+        RenameTarget::CrossDevice => return Ok(Some(-libc::EXDEV as i64)),
+        RenameTarget::ReadOnly => return Ok(Some(-libc::EROFS as i64)),
+        RenameTarget::Host => {}
+    }
+
+    let (new_oldpath_addr, new_newpath_addr) =
+        match translate_path_pair(guest, oldpath_addr, newpath_addr, mount_table).await? {
+            Some(translated) => translated,
+            None => (oldpath_addr, newpath_addr),
+        };
+
+    let new_syscall = reverie::syscalls::Renameat2::new()
+        .with_olddirfd(kernel_olddirfd)
+        .with_oldpath(Some(new_oldpath_addr))
+        .with_newdirfd(kernel_newdirfd)
+        .with_newpath(Some(new_newpath_addr))
+        .with_flags(args.flags());
+    let result = guest.inject(Syscall::Renameat2(new_syscall)).await?;
+    Ok(Some(result))
+}
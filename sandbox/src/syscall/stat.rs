@@ -1,17 +1,80 @@
 use crate::{
     sandbox::Sandbox,
-    syscall::translate_path,
-    vfs::{fdtable::FdTable, mount::MountTable},
+    syscall::{
+        io::{io_errno, lookup_virtual},
+        memory::{push_paths, read_path},
+        open::resolve_dirfd,
+        translate_path, write_path_pair, SyscallResult,
+    },
+    vfs::{
+        fdtable::{FdEntry, FdTable},
+        file::BoxedFileOps,
+        mount::MountTable,
+        Vfs, VfsError,
+    },
 };
 use reverie::{
-    syscalls::{MemoryAccess, ReadAddr, Syscall},
-    Error, Guest, Stack,
+    syscalls::{AddrMut, AtFlags, MemoryAccess, StatPtr, Syscall},
+    Error, Guest,
 };
+use std::{
+    ffi::CString,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+/// The virtual file open as `dirfd`, if `flags` has `AT_EMPTY_PATH` and `path` is empty,
+/// in which case an `*at` system call operates on the dirfd itself rather than a path.
+fn empty_path_file(
+    dirfd: i32,
+    path: &Path,
+    flags: AtFlags,
+    fd_table: &FdTable,
+) -> Option<BoxedFileOps> {
+    if !flags.contains(AtFlags::AT_EMPTY_PATH) || !path.as_os_str().is_empty() {
+        return None;
+    }
+    match fd_table.get(dirfd)? {
+        FdEntry::Virtual { file_ops, .. } => Some(file_ops),
+        FdEntry::Passthrough { .. } => None,
+    }
+}
+
+/// Convert the result of `FileOps::fstat` to a `struct statx` with the basic statistics.
+fn statx_from_stat(stat_buf: &libc::stat) -> libc::statx {
+    // Fields newer than the basic statistics vary between kernel versions, so start
+    // from zeros
+    let mut statx: libc::statx = unsafe { std::mem::zeroed() };
+    statx.stx_mask = libc::STATX_BASIC_STATS;
+    statx.stx_blksize = stat_buf.st_blksize as u32;
+    statx.stx_nlink = stat_buf.st_nlink as u32;
+    statx.stx_uid = stat_buf.st_uid;
+    statx.stx_gid = stat_buf.st_gid;
+    statx.stx_mode = stat_buf.st_mode as u16;
+    statx.stx_ino = stat_buf.st_ino;
+    statx.stx_size = stat_buf.st_size as u64;
+    statx.stx_blocks = stat_buf.st_blocks as u64;
+    statx.stx_atime.tv_sec = stat_buf.st_atime;
+    statx.stx_atime.tv_nsec = stat_buf.st_atime_nsec as u32;
+    statx.stx_mtime.tv_sec = stat_buf.st_mtime;
+    statx.stx_mtime.tv_nsec = stat_buf.st_mtime_nsec as u32;
+    statx.stx_ctime.tv_sec = stat_buf.st_ctime;
+    statx.stx_ctime.tv_nsec = stat_buf.st_ctime_nsec as u32;
+    // Split device numbers the way the kernel's major() and minor() do
+    let major = |dev: u64| (((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff)) as u32;
+    let minor = |dev: u64| ((dev & 0xff) | ((dev >> 12) & !0xff)) as u32;
+    statx.stx_rdev_major = major(stat_buf.st_rdev);
+    statx.stx_rdev_minor = minor(stat_buf.st_rdev);
+    statx.stx_dev_major = major(stat_buf.st_dev);
+    statx.stx_dev_minor = minor(stat_buf.st_dev);
+    statx
+}
 
 /// The `statx` system call.
 ///
 /// This intercepts `statx` system calls and translates paths according to the mount table
-/// and virtualizes the dirfd.
+/// and virtualizes the dirfd. With `AT_EMPTY_PATH` and an empty path, a virtual dirfd
+/// reports the statistics of its open file through `FileOps::fstat`.
 /// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
 /// or `None` if the original syscall should be used.
 pub async fn handle_statx<T: Guest<Sandbox>>(
@@ -29,11 +92,34 @@ pub async fn handle_statx<T: Guest<Sandbox>>(
     };
 
     if let Some(path_addr) = args.path() {
-        // Read the original path from guest memory
-        let path: std::path::PathBuf = path_addr.read(&guest.memory())?;
+        // Read the original path from guest memory, joined onto a virtual dirfd or
+        // working directory
+        let mut path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(file_ops) = empty_path_file(dirfd, &path, args.flags(), fd_table) {
+            let stat_buf = match file_ops.fstat().await {
+                Ok(stat_buf) => stat_buf,
+                Err(e) => return Ok(Some(io_errno(e))),
+            };
+            if let Some(statx_addr) = args.statx() {
+                let statx = statx_from_stat(&stat_buf);
+                let statx_bytes: &[u8] = unsafe {
+                    std::slice::from_raw_parts(
+                        &statx as *const _ as *const u8,
+                        std::mem::size_of::<libc::statx>(),
+                    )
+                };
+                guest
+                    .memory()
+                    .write_exact(statx_addr.0.cast::<u8>(), statx_bytes)?;
+            }
+            return Ok(Some(0));
+        }
+        if let Err(errno) = resolve_dirfd(dirfd, &mut path, fd_table) {
+            return Ok(Some(errno));
+        }
 
         // Check if this path matches a mount point
-        if let Some((vfs, _translated_path)) = mount_table.resolve(&path) {
+        if let Some((vfs, _translated_path, _read_only)) = mount_table.resolve(&path) {
             // Check if this is a virtual VFS (like SQLite)
             if vfs.is_virtual() {
                 // For virtual VFS, statx is not supported - return ENOSYS
@@ -57,10 +143,83 @@ pub async fn handle_statx<T: Guest<Sandbox>>(
     Ok(None)
 }
 
+/// Stat a path in a virtual VFS and write the result to guest memory.
+///
+/// Shared by `newfstatat` (x86_64) and `fstatat` (aarch64), which are the same system
+/// call under different names. `AT_SYMLINK_NOFOLLOW` selects `Vfs::lstat`.
+///
+/// Returns `Some(result)` if the path lives under a virtual mount, or `None` if the
+/// kernel should perform the stat.
+async fn stat_virtual<T: Guest<Sandbox>>(
+    guest: &mut T,
+    path: &Path,
+    flags: AtFlags,
+    stat_addr: Option<StatPtr<'_>>,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    let vfs = match mount_table.resolve(path) {
+        Some((vfs, _translated_path, _read_only)) if vfs.is_virtual() => vfs,
+        _ => return Ok(None),
+    };
+
+    let stat_result = if flags.contains(AtFlags::AT_SYMLINK_NOFOLLOW) {
+        vfs.lstat(path).await
+    } else {
+        vfs.stat(path).await
+    };
+
+    match stat_result {
+        Ok(stat_buf) => {
+            write_stat(guest, &stat_buf, stat_addr)?;
+            Ok(Some(0))
+        }
+        Err(e) => Ok(Some(e.to_errno())),
+    }
+}
+
+/// Stat the virtual file open as the dirfd of an `AT_EMPTY_PATH` stat through
+/// `FileOps::fstat` and write the result to guest memory.
+async fn stat_empty_path<T: Guest<Sandbox>>(
+    guest: &mut T,
+    file_ops: BoxedFileOps,
+    stat_addr: Option<StatPtr<'_>>,
+) -> Result<i64, Error> {
+    match file_ops.fstat().await {
+        Ok(stat_buf) => {
+            write_stat(guest, &stat_buf, stat_addr)?;
+            Ok(0)
+        }
+        Err(e) => Ok(io_errno(e)),
+    }
+}
+
+/// Write a stat result to guest memory, if the guest passed a buffer for it.
+fn write_stat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    stat_buf: &libc::stat,
+    stat_addr: Option<StatPtr<'_>>,
+) -> Result<(), Error> {
+    if let Some(stat_addr) = stat_addr {
+        // Convert stat struct to bytes and write
+        let stat_bytes: &[u8] = unsafe {
+            std::slice::from_raw_parts(
+                stat_buf as *const _ as *const u8,
+                std::mem::size_of::<libc::stat>(),
+            )
+        };
+        guest
+            .memory()
+            .write_exact(stat_addr.0.cast::<u8>(), stat_bytes)?;
+    }
+    Ok(())
+}
+
 /// The `newfstatat` system call.
 ///
-/// This intercepts `newfstatat` system calls and translates paths according to the mount table
-/// and virtualizes the dirfd.
+/// This intercepts `newfstatat` system calls and virtualizes the dirfd. Virtual paths are
+/// served by `Vfs::stat` or `Vfs::lstat`, and host paths are translated according to the
+/// mount table and `newfstatat` is injected with the kernel dirfd. With `AT_EMPTY_PATH`
+/// and an empty path, a virtual dirfd is served by `FileOps::fstat`, as `fstat` is.
 /// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
 /// or `None` if the original syscall should be used.
 #[cfg(not(target_arch = "aarch64"))]
@@ -70,140 +229,315 @@ pub async fn handle_newfstatat<T: Guest<Sandbox>>(
     mount_table: &MountTable,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    use reverie::syscalls::AtFlags;
-    let dirfd = args.dirfd();
-    // AT_FDCWD is -100
-    let kernel_dirfd = if dirfd == -100 {
-        dirfd
-    } else {
-        fd_table.translate(dirfd).unwrap_or(dirfd)
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
     };
 
-    if let Some(path_addr) = args.path() {
-        // Read the original path from guest memory
-        let path: std::path::PathBuf = path_addr.read(&guest.memory())?;
+    // Read the original path from guest memory
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    if let Some(file_ops) = empty_path_file(args.dirfd(), &path, args.flags(), fd_table) {
+        return Ok(Some(stat_empty_path(guest, file_ops, args.stat()).await?));
+    }
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
 
-        // Check if this path matches a mount point
-        if let Some((vfs, _translated_path)) = mount_table.resolve(&path) {
-            // Check if this is a virtual VFS (like SQLite)
-            if vfs.is_virtual() {
-                let flags = args.flags();
-                let follow_symlinks = !flags.contains(AtFlags::AT_SYMLINK_NOFOLLOW);
+    if let Some(result) = stat_virtual(guest, &path, args.flags(), args.stat(), mount_table).await?
+    {
+        return Ok(Some(result));
+    }
 
-                let stat_result = if follow_symlinks {
-                    vfs.stat(&path).await
-                } else {
-                    vfs.lstat(&path).await
-                };
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Newfstatat(new_syscall)).await?;
+    Ok(Some(result))
+}
 
-                match stat_result {
-                    Ok(stat_buf) => {
-                        // Write the stat result to guest memory
-                        if let Some(stat_addr) = args.stat() {
-                            // Convert stat struct to bytes and write
-                            let stat_bytes: &[u8] = unsafe {
-                                std::slice::from_raw_parts(
-                                    &stat_buf as *const _ as *const u8,
-                                    std::mem::size_of::<libc::stat>(),
-                                )
-                            };
-                            guest
-                                .memory()
-                                .write_exact(stat_addr.0.cast::<u8>(), stat_bytes)?;
-                        }
-                        return Ok(Some(0)); // Success
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EIO as i64,
-                        };
-                        return Ok(Some(errno));
-                    }
-                }
-            }
-        }
+/// The `fstatat` system call.
+///
+/// This is the aarch64 name for `newfstatat`, which is also how `stat` and `lstat`
+/// reach the kernel on that architecture. It is handled exactly like `handle_newfstatat`.
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(target_arch = "aarch64")]
+pub async fn handle_fstatat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fstatat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
 
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = reverie::syscalls::Newfstatat::new()
-                .with_dirfd(kernel_dirfd)
-                .with_path(Some(new_path_addr))
-                .with_stat(args.stat())
-                .with_flags(args.flags());
+    // Read the original path from guest memory
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    if let Some(file_ops) = empty_path_file(args.dirfd(), &path, args.flags(), fd_table) {
+        return Ok(Some(stat_empty_path(guest, file_ops, args.stat()).await?));
+    }
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
 
-            let result = guest.inject(Syscall::Newfstatat(new_syscall)).await?;
-            return Ok(Some(result));
-        }
+    if let Some(result) = stat_virtual(guest, &path, args.flags(), args.stat(), mount_table).await?
+    {
+        return Ok(Some(result));
     }
-    Ok(None)
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Fstatat(new_syscall)).await?;
+    Ok(Some(result))
 }
 
 /// The `statfs` system call.
 ///
-/// This intercepts `statfs` system calls and translates paths according to the mount table.
+/// This intercepts `statfs` system calls. Paths in a virtual mount get the
+/// statistics of `Vfs::statfs()`, and other paths are translated according to the
+/// mount table.
 pub async fn handle_statfs<T: Guest<Sandbox>>(
     guest: &mut T,
+    syscall: Syscall,
     args: &reverie::syscalls::Statfs,
     mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
-    if let Some(path_addr) = args.path() {
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = args.with_path(Some(new_path_addr));
+) -> Result<SyscallResult, Error> {
+    let Some(path_addr) = args.path() else {
+        return Ok(SyscallResult::Syscall(syscall));
+    };
 
-            return Ok(Some(Syscall::Statfs(new_syscall)));
+    let path: PathBuf = read_path(guest, path_addr)?;
+    if let Some((vfs, _translated_path, _read_only)) = mount_table.resolve(&path) {
+        if vfs.is_virtual() {
+            // `path` must exist in the VFS, as with the kernel
+            if let Err(e) = vfs.stat(&path).await {
+                return Ok(SyscallResult::Value(e.to_errno()));
+            }
+            let result = statfs_virtual(guest, vfs.as_ref(), args.buf()).await?;
+            return Ok(SyscallResult::Value(result));
         }
     }
-    Ok(None)
+
+    if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+        let new_syscall = args.with_path(Some(new_path_addr));
+        return Ok(SyscallResult::Syscall(Syscall::Statfs(new_syscall)));
+    }
+    Ok(SyscallResult::Syscall(syscall))
+}
+
+/// The `fstatfs` system call.
+///
+/// This intercepts `fstatfs` system calls. Virtual FDs get the statistics of
+/// `Vfs::statfs()` for the mount their file lives in, like `statfs` on its path,
+/// and other FDs are translated to kernel FDs.
+pub async fn handle_fstatfs<T: Guest<Sandbox>>(
+    guest: &mut T,
+    syscall: Syscall,
+    args: &reverie::syscalls::Fstatfs,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<SyscallResult, Error> {
+    if let Some((vfs, _file_ops)) = lookup_virtual(args.fd(), mount_table, fd_table) {
+        let result = statfs_virtual(guest, vfs.as_ref(), args.buf()).await?;
+        return Ok(SyscallResult::Value(result));
+    }
+
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        let new_syscall = args.with_fd(kernel_fd);
+        return Ok(SyscallResult::Syscall(Syscall::Fstatfs(new_syscall)));
+    }
+    Ok(SyscallResult::Syscall(syscall))
+}
+
+/// Write the statistics of a virtual VFS to the guest's `struct statfs`.
+async fn statfs_virtual<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    buf_addr: Option<AddrMut<'_, libc::statfs>>,
+) -> Result<i64, Error> {
+    let stats = match vfs.statfs().await {
+        Ok(stats) => stats,
+        Err(e) => return Ok(e.to_errno()),
+    };
+    let Some(buf_addr) = buf_addr else {
+        return Ok(-libc::EFAULT as i64);
+    };
+
+    let mut buf: libc::statfs = unsafe { std::mem::zeroed() };
+    buf.f_type = stats.fs_type as _;
+    buf.f_bsize = stats.block_size as _;
+    buf.f_frsize = stats.block_size as _;
+    buf.f_blocks = stats.blocks as _;
+    buf.f_bfree = stats.blocks_free as _;
+    buf.f_bavail = stats.blocks_available as _;
+    buf.f_files = stats.files as _;
+    buf.f_ffree = stats.files_free as _;
+    buf.f_namelen = stats.name_max as _;
+    guest.memory().write_value(buf_addr, &buf)?;
+    Ok(0)
+}
+
+/// Read a symlink in a virtual VFS into the guest buffer.
+///
+/// Shared by `readlink` and `readlinkat`. As with the kernel, the target is truncated
+/// to the buffer size and is not NUL-terminated.
+///
+/// Returns `Some(result)` if the path lives under a virtual mount, or `None` if the
+/// kernel should read the link.
+async fn readlink_virtual<T: Guest<Sandbox>>(
+    guest: &mut T,
+    path: &Path,
+    buf_addr: Option<AddrMut<'_, u8>>,
+    bufsize: usize,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    let vfs = match mount_table.resolve(path) {
+        Some((vfs, _translated_path, _read_only)) if vfs.is_virtual() => vfs,
+        _ => return Ok(None),
+    };
+
+    match vfs.readlink(path).await {
+        Ok(target) => Ok(Some(write_link_target(guest, &target, buf_addr, bufsize)?)),
+        Err(e) => {
+            // Map VFS errors to errno
+            let errno = match e {
+                VfsError::NotFound => -libc::ENOENT as i64,
+                VfsError::PermissionDenied => -libc::EACCES as i64,
+                VfsError::SymlinkLoop | VfsError::NameTooLong => e.to_errno(),
+                _ => -libc::EINVAL as i64,
+            };
+            Ok(Some(errno))
+        }
+    }
+}
+
+/// Write the target of a symlink to the guest buffer, truncated to `bufsize` and
+/// without a NUL terminator, returning the number of bytes written.
+fn write_link_target<T: Guest<Sandbox>>(
+    guest: &mut T,
+    target: &Path,
+    buf_addr: Option<AddrMut<'_, u8>>,
+    bufsize: usize,
+) -> Result<i64, Error> {
+    let Some(buf_addr) = buf_addr else {
+        return Ok(0);
+    };
+    let target_str = target.to_string_lossy();
+    let target_bytes = target_str.as_bytes();
+    let bytes_to_write = std::cmp::min(target_bytes.len(), bufsize);
+
+    guest
+        .memory()
+        .write_exact(buf_addr, &target_bytes[..bytes_to_write])?;
+    Ok(bytes_to_write as i64)
+}
+
+/// The FD that a link in procfs refers to: `N` for `/proc/self/fd/N`,
+/// `/proc/thread-self/fd/N`, or `/proc/<id>/fd/N` where `id` is the guest's
+/// own process or thread ID.
+///
+/// Other processes have FD tables of their own, so their links are left to the kernel.
+fn proc_fd_link(path: &Path, pid: i32, tid: i32) -> Option<i32> {
+    let mut components = path.components();
+    if components.next() != Some(Component::RootDir) {
+        return None;
+    }
+    let names = components
+        .map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let ["proc", owner, "fd", fd] = names.as_slice() else {
+        return None;
+    };
+    let own = match *owner {
+        "self" | "thread-self" => true,
+        id => id.parse::<i32>().is_ok_and(|id| id == pid || id == tid),
+    };
+    if !own || !fd.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    fd.parse().ok()
+}
+
+/// Read a `/proc/self/fd/N` link of the guest in terms of its virtual FDs.
+///
+/// Shared by `readlink` and `readlinkat`. The guest's FD numbers are not the kernel's,
+/// so the link of a virtual file reads as the path it was opened with, and that of a
+/// passthrough FD is read from the link of its kernel FD. FDs that are not open fail
+/// with `ENOENT`, as in procfs.
+///
+/// Returns `Some(result)` if `path` is such a link, or `None` if the kernel should
+/// read it.
+async fn readlink_proc_fd<T: Guest<Sandbox>>(
+    guest: &mut T,
+    path: &Path,
+    buf_addr: Option<AddrMut<'_, u8>>,
+    bufsize: usize,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let fd = match proc_fd_link(path, guest.pid().as_raw(), guest.tid().as_raw()) {
+        Some(fd) => fd,
+        None => return Ok(None),
+    };
+
+    let kernel_fd = match fd_table.get(fd) {
+        Some(FdEntry::Virtual {
+            path: Some(path), ..
+        }) => return Ok(Some(write_link_target(guest, &path, buf_addr, bufsize)?)),
+        Some(FdEntry::Virtual { path: None, .. }) | None => {
+            return Ok(Some(-libc::ENOENT as i64));
+        }
+        Some(FdEntry::Passthrough { kernel_fd, .. }) => kernel_fd,
+    };
+
+    let kernel_path = CString::new(format!("/proc/self/fd/{}", kernel_fd))
+        .expect("procfs paths have no NUL bytes");
+    let kernel_path_addr = push_paths(guest, &[&kernel_path]).await?.into_iter().next();
+    let new_syscall = reverie::syscalls::Readlinkat::new()
+        .with_dirfd(libc::AT_FDCWD)
+        .with_path(kernel_path_addr)
+        .with_buf(buf_addr.map(|addr| addr.cast()))
+        .with_buf_len(bufsize);
+
+    let result = guest.inject(Syscall::Readlinkat(new_syscall)).await?;
+    Ok(Some(result))
 }
 
 /// The `readlink` system call.
 ///
-/// This intercepts `readlink` system calls and translates paths according to the mount table.
+/// This intercepts `readlink` system calls and reads virtual links via `Vfs::readlink`,
+/// or translates paths according to the mount table for host mounts. The guest's own
+/// `/proc/self/fd/N` links are read with `readlink_proc_fd`.
+/// aarch64 has no `readlink`; the same logic is reached through `readlinkat`.
 #[cfg(not(target_arch = "aarch64"))]
 pub async fn handle_readlink<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Readlink,
     mount_table: &MountTable,
+    fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
     if let Some(path_addr) = args.path() {
-        let path: std::path::PathBuf = path_addr.read(&guest.memory())?;
-
-        // Check if this path matches a mount point
-        if let Some((vfs, _translated_path)) = mount_table.resolve(&path) {
-            // Check if this is a virtual VFS (like SQLite)
-            if vfs.is_virtual() {
-                // Call VFS readlink method directly
-                match vfs.readlink(&path).await {
-                    Ok(target) => {
-                        // Write the target to the user's buffer
-                        if let Some(buf_addr) = args.buf() {
-                            let bufsize = args.bufsize();
-                            let target_str = target.to_string_lossy();
-                            let target_bytes = target_str.as_bytes();
-                            let bytes_to_write = std::cmp::min(target_bytes.len(), bufsize);
-
-                            guest.memory().write_exact(
-                                buf_addr.cast::<u8>(),
-                                &target_bytes[..bytes_to_write],
-                            )?;
-
-                            return Ok(Some(bytes_to_write as i64));
-                        }
-                        return Ok(Some(0));
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EINVAL as i64,
-                        };
-                        return Ok(Some(errno));
-                    }
-                }
-            }
+        let path: PathBuf = read_path(guest, path_addr)?;
+        let buf_addr = args.buf().map(|addr| addr.cast::<u8>());
+        if let Some(result) =
+            readlink_proc_fd(guest, &path, buf_addr, args.bufsize(), fd_table).await?
+        {
+            return Ok(Some(result));
+        }
+        if let Some(result) =
+            readlink_virtual(guest, &path, buf_addr, args.bufsize(), mount_table).await?
+        {
+            return Ok(Some(result));
         }
 
         if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
@@ -221,79 +555,73 @@ pub async fn handle_readlink<T: Guest<Sandbox>>(
 
 /// The `readlinkat` system call.
 ///
-/// This intercepts `readlinkat` system calls and translates paths according to the mount table
-/// and virtualizes the dirfd.
+/// This intercepts `readlinkat` system calls and virtualizes the dirfd. Virtual links
+/// are read via `Vfs::readlink`, the guest's own `/proc/self/fd/N` links with
+/// `readlink_proc_fd`, and host paths are translated according to the mount table and
+/// `readlinkat` is injected with the kernel dirfd.
 pub async fn handle_readlinkat<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Readlinkat,
     mount_table: &MountTable,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    let dirfd = args.dirfd();
-    // AT_FDCWD is -100
-    let kernel_dirfd = if dirfd == -100 {
-        dirfd
-    } else {
-        fd_table.translate(dirfd).unwrap_or(dirfd)
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
     };
 
-    if let Some(path_addr) = args.path() {
-        let path: std::path::PathBuf = path_addr.read(&guest.memory())?;
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
 
-        // Check if this path matches a mount point
-        if let Some((vfs, _translated_path)) = mount_table.resolve(&path) {
-            // Check if this is a virtual VFS (like SQLite)
-            if vfs.is_virtual() {
-                // Call VFS readlink method directly
-                match vfs.readlink(&path).await {
-                    Ok(target) => {
-                        // Write the target to the user's buffer
-                        if let Some(buf_addr) = args.buf() {
-                            let bufsize = args.buf_len();
-                            let target_str = target.to_string_lossy();
-                            let target_bytes = target_str.as_bytes();
-                            let bytes_to_write = std::cmp::min(target_bytes.len(), bufsize);
-
-                            guest.memory().write_exact(
-                                buf_addr.cast::<u8>(),
-                                &target_bytes[..bytes_to_write],
-                            )?;
-
-                            return Ok(Some(bytes_to_write as i64));
-                        }
-                        return Ok(Some(0));
-                    }
-                    Err(e) => {
-                        // Map VFS errors to errno
-                        let errno = match e {
-                            crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                            crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                            _ => -libc::EINVAL as i64,
-                        };
-                        return Ok(Some(errno));
-                    }
-                }
-            }
-        }
+    let buf_addr = args.buf().map(|addr| addr.cast::<u8>());
+    if let Some(result) = readlink_proc_fd(guest, &path, buf_addr, args.buf_len(), fd_table).await?
+    {
+        return Ok(Some(result));
+    }
+    if let Some(result) =
+        readlink_virtual(guest, &path, buf_addr, args.buf_len(), mount_table).await?
+    {
+        return Ok(Some(result));
+    }
 
-        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
-            let new_syscall = reverie::syscalls::Readlinkat::new()
-                .with_dirfd(kernel_dirfd)
-                .with_path(Some(new_path_addr))
-                .with_buf(args.buf())
-                .with_buf_len(args.buf_len());
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = reverie::syscalls::Readlinkat::new()
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)))
+        .with_buf(args.buf())
+        .with_buf_len(args.buf_len());
 
-            let result = guest.inject(Syscall::Readlinkat(new_syscall)).await?;
-            return Ok(Some(result));
-        }
+    let result = guest.inject(Syscall::Readlinkat(new_syscall)).await?;
+    Ok(Some(result))
+}
+
+/// Create a symlink in a virtual VFS.
+///
+/// Shared by `symlink` and `symlinkat`. Returns `Some(result)` if the link path lives
+/// under a virtual or read-only mount, or `None` if the kernel should create the link.
+async fn symlink_virtual(target: &Path, linkpath: &Path, mount_table: &MountTable) -> Option<i64> {
+    let (vfs, _translated_path, read_only) = mount_table.resolve(linkpath)?;
+    if read_only {
+        return Some(-libc::EROFS as i64);
     }
-    Ok(None)
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    Some(match vfs.symlink(target, linkpath).await {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
 }
 
 /// The `symlink` system call.
 ///
 /// This intercepts `symlink` system calls and translates the linkpath according to the mount table.
 /// The target path is left as-is since it's just a string stored in the symlink.
+/// aarch64 has no `symlink`; the same logic is reached through `symlinkat`.
 /// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
 /// or `None` if the original syscall should be used.
 #[cfg(not(target_arch = "aarch64"))]
@@ -302,46 +630,26 @@ pub async fn handle_symlink<T: Guest<Sandbox>>(
     args: &reverie::syscalls::Symlink,
     mount_table: &MountTable,
 ) -> Result<Option<i64>, Error> {
-    // Read the linkpath from guest memory
-    if let Some(linkpath_addr) = args.linkpath() {
-        let linkpath: std::path::PathBuf = linkpath_addr.read(&guest.memory())?;
+    let (target_addr, linkpath_addr) = match (args.target(), args.linkpath()) {
+        (Some(target_addr), Some(linkpath_addr)) => (target_addr, linkpath_addr),
+        _ => return Ok(None),
+    };
 
-        // Read the target from guest memory
-        if let Some(target_addr) = args.target() {
-            let target: std::path::PathBuf = target_addr.read(&guest.memory())?;
+    // Read the target and linkpath from guest memory
+    let target: PathBuf = read_path(guest, target_addr)?;
+    let linkpath: PathBuf = read_path(guest, linkpath_addr)?;
 
-            // Check if this path matches a mount point
-            if let Some((vfs, _translated_path)) = mount_table.resolve(&linkpath) {
-                // Check if this is a virtual VFS (like SQLite)
-                if vfs.is_virtual() {
-                    // Call VFS symlink method directly
-                    match vfs.symlink(&target, &linkpath).await {
-                        Ok(()) => return Ok(Some(0)), // Success
-                        Err(e) => {
-                            // Map VFS errors to errno
-                            let errno = match e {
-                                crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                                crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                                crate::vfs::VfsError::AlreadyExists => -libc::EEXIST as i64,
-                                _ => -libc::EIO as i64,
-                            };
-                            return Ok(Some(errno));
-                        }
-                    }
-                }
-            }
+    if let Some(result) = symlink_virtual(&target, &linkpath, mount_table).await {
+        return Ok(Some(result));
+    }
 
-            if let Some(new_linkpath_addr) =
-                translate_path(guest, linkpath_addr, mount_table).await?
-            {
-                let new_syscall = reverie::syscalls::Symlink::new()
-                    .with_target(args.target())
-                    .with_linkpath(Some(new_linkpath_addr));
+    if let Some(new_linkpath_addr) = translate_path(guest, linkpath_addr, mount_table).await? {
+        let new_syscall = reverie::syscalls::Symlink::new()
+            .with_target(Some(target_addr))
+            .with_linkpath(Some(new_linkpath_addr));
 
-                let result = guest.inject(Syscall::Symlink(new_syscall)).await?;
-                return Ok(Some(result));
-            }
-        }
+        let result = guest.inject(Syscall::Symlink(new_syscall)).await?;
+        return Ok(Some(result));
     }
     Ok(None)
 }
@@ -359,62 +667,91 @@ pub async fn handle_symlinkat<T: Guest<Sandbox>>(
     mount_table: &MountTable,
     fd_table: &FdTable,
 ) -> Result<Option<i64>, Error> {
-    let dirfd = args.newdirfd();
-    // AT_FDCWD is -100
-    let kernel_dirfd = if dirfd == -100 {
-        dirfd
-    } else {
-        fd_table.translate(dirfd).unwrap_or(dirfd)
+    let (target_addr, linkpath_addr) = match (args.target(), args.linkpath()) {
+        (Some(target_addr), Some(linkpath_addr)) => (target_addr, linkpath_addr),
+        _ => return Ok(None),
     };
 
-    // Read linkpath and target from guest memory
-    if let Some(linkpath_addr) = args.linkpath() {
-        let linkpath: std::path::PathBuf = linkpath_addr.read(&guest.memory())?;
+    // Read the target and linkpath from guest memory
+    let target: PathBuf = read_path(guest, target_addr)?;
+    let mut linkpath: PathBuf = read_path(guest, linkpath_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.newdirfd(), &mut linkpath, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
 
-        if let Some(target_addr) = args.target() {
-            let target: std::path::PathBuf = target_addr.read(&guest.memory())?;
+    if let Some(result) = symlink_virtual(&target, &linkpath, mount_table).await {
+        return Ok(Some(result));
+    }
 
-            // Check if this path matches a mount point
-            if let Some((vfs, _translated_path)) = mount_table.resolve(&linkpath) {
-                // Check if this is a virtual VFS (like SQLite)
-                if vfs.is_virtual() {
-                    // Call VFS symlink method directly
-                    match vfs.symlink(&target, &linkpath).await {
-                        Ok(()) => return Ok(Some(0)), // Success
-                        Err(e) => {
-                            // Map VFS errors to errno
-                            let errno = match e {
-                                crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
-                                crate::vfs::VfsError::PermissionDenied => -libc::EACCES as i64,
-                                crate::vfs::VfsError::AlreadyExists => -libc::EEXIST as i64,
-                                _ => -libc::EIO as i64,
-                            };
-                            return Ok(Some(errno));
-                        }
-                    }
-                }
-            }
+    let new_linkpath_addr = translate_path(guest, linkpath_addr, mount_table).await?;
+    let new_syscall = reverie::syscalls::Symlinkat::new()
+        .with_target(Some(target_addr))
+        .with_newdirfd(kernel_dirfd)
+        .with_linkpath(new_linkpath_addr.or(Some(linkpath_addr)));
 
-            if let Some(new_linkpath_addr) =
-                translate_path(guest, linkpath_addr, mount_table).await?
-            {
-                let new_syscall = reverie::syscalls::Symlinkat::new()
-                    .with_target(args.target())
-                    .with_newdirfd(kernel_dirfd)
-                    .with_linkpath(Some(new_linkpath_addr));
+    let result = guest.inject(Syscall::Symlinkat(new_syscall)).await?;
+    Ok(Some(result))
+}
 
-                let result = guest.inject(Syscall::Symlinkat(new_syscall)).await?;
-                return Ok(Some(result));
-            }
-        }
+/// The FD whose file `linkat` links instead of a path: `olddirfd` itself for an empty
+/// `oldpath` with `AT_EMPTY_PATH`, or the FD behind the guest's own `/proc/self/fd/N`
+/// link with `AT_SYMLINK_FOLLOW`.
+fn linked_fd(olddirfd: i32, oldpath: &Path, flags: AtFlags, pid: i32, tid: i32) -> Option<i32> {
+    if flags.contains(AtFlags::AT_EMPTY_PATH) && oldpath.as_os_str().is_empty() {
+        return Some(olddirfd);
     }
-    Ok(None)
+    if flags.contains(AtFlags::AT_SYMLINK_FOLLOW) {
+        return proc_fd_link(oldpath, pid, tid);
+    }
+    None
+}
+
+/// Link the virtual file open as `fd` at `newpath` through `Vfs::link_file`.
+///
+/// As on Linux, a file without links can only be linked if it was opened with
+/// `O_TMPFILE` but not `O_EXCL`, and both must be on the same mount.
+///
+/// Returns `None` if `fd` is not a virtual file, for the kernel to link.
+async fn link_virtual_fd(
+    fd: i32,
+    newpath: &Path,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Option<i64> {
+    let Some(FdEntry::Virtual {
+        file_ops,
+        flags,
+        path: Some(path),
+    }) = fd_table.get(fd)
+    else {
+        return None;
+    };
+    let (vfs, _translated_path, _read_only) = mount_table.resolve(&path)?;
+    match mount_table.resolve(newpath) {
+        Some((new_vfs, _, _)) if !Arc::ptr_eq(&vfs, &new_vfs) => return Some(-libc::EXDEV as i64),
+        Some((_, _, true)) => return Some(-libc::EROFS as i64),
+        Some(_) => {}
+        None => return Some(-libc::EXDEV as i64),
+    }
+
+    let tmpfile = flags & libc::O_TMPFILE == libc::O_TMPFILE && flags & libc::O_EXCL == 0;
+    match file_ops.fstat().await {
+        Ok(stat) if stat.st_nlink == 0 && !tmpfile => return Some(-libc::ENOENT as i64),
+        Ok(_) => {}
+        Err(e) => return Some(e.to_errno()),
+    }
+    Some(match vfs.link_file(&file_ops, newpath).await {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
 }
 
 /// The `linkat` system call.
 ///
 /// This intercepts `linkat` system calls and translates paths according to the mount table
-/// and virtualizes the dirfds.
+/// and virtualizes the dirfds. Virtual files linked by FD, such as those opened with
+/// `O_TMPFILE`, are linked with `link_virtual_fd`.
 /// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
 /// or `None` if the original syscall should be used.
 pub async fn handle_linkat<T: Guest<Sandbox>>(
@@ -440,13 +777,27 @@ pub async fn handle_linkat<T: Guest<Sandbox>>(
 
     // Read oldpath and newpath from guest memory
     if let Some(oldpath_addr) = args.oldpath() {
-        let oldpath: std::path::PathBuf = oldpath_addr.read(&guest.memory())?;
+        let oldpath: std::path::PathBuf = read_path(guest, oldpath_addr)?;
 
         if let Some(newpath_addr) = args.newpath() {
-            let newpath: std::path::PathBuf = newpath_addr.read(&guest.memory())?;
+            let newpath: std::path::PathBuf = read_path(guest, newpath_addr)?;
+
+            let (pid, tid) = (guest.pid().as_raw(), guest.tid().as_raw());
+            if let Some(fd) = linked_fd(olddirfd, &oldpath, args.flags(), pid, tid) {
+                let mut newpath = newpath.clone();
+                if let Err(errno) = resolve_dirfd(newdirfd, &mut newpath, fd_table) {
+                    return Ok(Some(errno));
+                }
+                if let Some(result) = link_virtual_fd(fd, &newpath, mount_table, fd_table).await {
+                    return Ok(Some(result));
+                }
+            }
 
             // Check if newpath matches a mount point with virtual VFS
-            if let Some((vfs, _translated_path)) = mount_table.resolve(&newpath) {
+            if let Some((vfs, _translated_path, read_only)) = mount_table.resolve(&newpath) {
+                if read_only {
+                    return Ok(Some(-libc::EROFS as i64));
+                }
                 // Check if this is a virtual VFS (like SQLite)
                 if vfs.is_virtual() {
                     // Call VFS link method directly
@@ -458,7 +809,7 @@ pub async fn handle_linkat<T: Guest<Sandbox>>(
                                 crate::vfs::VfsError::NotFound => -libc::ENOENT as i64,
                                 crate::vfs::VfsError::PermissionDenied => -libc::EPERM as i64,
                                 crate::vfs::VfsError::AlreadyExists => -libc::EEXIST as i64,
-                                _ => -libc::EIO as i64,
+                                other => other.to_errno(),
                             };
                             return Ok(Some(errno));
                         }
@@ -466,89 +817,50 @@ pub async fn handle_linkat<T: Guest<Sandbox>>(
                 }
             }
 
-            // Check if either path needs translation by consulting the mount table.
-            // We resolve both paths first (without guest memory allocation) to determine
-            // what needs translation, then allocate and inject if needed.
-            let oldpath_translated = mount_table.resolve(&oldpath);
-            let newpath_translated = mount_table.resolve(&newpath);
-
-            match (oldpath_translated, newpath_translated) {
-                (Some((_vfs1, translated_oldpath)), Some((_vfs2, translated_newpath))) => {
-                    // Both paths need translation
-                    use std::ffi::CString;
-
-                    let old_cstr =
-                        CString::new(translated_oldpath.to_string_lossy().to_string())
-                            .map_err(|_| reverie::syscalls::Errno::EINVAL)?;
-                    let new_cstr =
-                        CString::new(translated_newpath.to_string_lossy().to_string())
-                            .map_err(|_| reverie::syscalls::Errno::EINVAL)?;
-
-                    // Allocate space for both paths on guest stack
-                    let old_bytes = old_cstr.as_bytes_with_nul();
-                    let new_bytes = new_cstr.as_bytes_with_nul();
-
-                    let mut stack = guest.stack().await;
-                    let old_addr: reverie::syscalls::AddrMut<std::path::PathBuf> = stack.reserve();
-                    let new_addr: reverie::syscalls::AddrMut<std::path::PathBuf> = stack.reserve();
-                    stack.commit()?;
-
-                    let old_byte_addr = old_addr.cast::<u8>();
-                    let new_byte_addr = new_addr.cast::<u8>();
-                    guest.memory().write_exact(old_byte_addr, old_bytes)?;
-                    guest.memory().write_exact(new_byte_addr, new_bytes)?;
-
-                    let new_oldpath_ptr: reverie::syscalls::PathPtr = unsafe {
-                        std::mem::transmute(old_byte_addr)
-                    };
-                    let new_newpath_ptr: reverie::syscalls::PathPtr = unsafe {
-                        std::mem::transmute(new_byte_addr)
-                    };
-
-                    let new_syscall = reverie::syscalls::Linkat::new()
-                        .with_olddirfd(kernel_olddirfd)
-                        .with_oldpath(Some(new_oldpath_ptr))
-                        .with_newdirfd(kernel_newdirfd)
-                        .with_newpath(Some(new_newpath_ptr))
-                        .with_flags(args.flags());
-                    let result = guest.inject(Syscall::Linkat(new_syscall)).await?;
-                    return Ok(Some(result));
-                }
-                (Some(_), None) => {
-                    // Only oldpath needs translation
-                    if let Some(new_oldpath_addr) =
-                        translate_path(guest, oldpath_addr, mount_table).await?
-                    {
-                        let new_syscall = reverie::syscalls::Linkat::new()
-                            .with_olddirfd(kernel_olddirfd)
-                            .with_oldpath(Some(new_oldpath_addr))
-                            .with_newdirfd(kernel_newdirfd)
-                            .with_newpath(Some(newpath_addr))
-                            .with_flags(args.flags());
-                        let result = guest.inject(Syscall::Linkat(new_syscall)).await?;
-                        return Ok(Some(result));
-                    }
-                }
-                (None, Some(_)) => {
-                    // Only newpath needs translation
-                    if let Some(new_newpath_addr) =
-                        translate_path(guest, newpath_addr, mount_table).await?
-                    {
-                        let new_syscall = reverie::syscalls::Linkat::new()
-                            .with_olddirfd(kernel_olddirfd)
-                            .with_oldpath(Some(oldpath_addr))
-                            .with_newdirfd(kernel_newdirfd)
-                            .with_newpath(Some(new_newpath_addr))
-                            .with_flags(args.flags());
-                        let result = guest.inject(Syscall::Linkat(new_syscall)).await?;
-                        return Ok(Some(result));
-                    }
-                }
-                (None, None) => {
-                    // Neither path needs translation
-                }
+            // Translate both paths with a single write to the guest
+            let translated_oldpath = mount_table.resolve(&oldpath).map(|(_vfs, path, _)| path);
+            let translated_newpath = mount_table.resolve(&newpath).map(|(_vfs, path, _)| path);
+            if let Some((new_oldpath_addr, new_newpath_addr)) = write_path_pair(
+                guest,
+                (oldpath_addr, translated_oldpath),
+                (newpath_addr, translated_newpath),
+            )
+            .await?
+            {
+                let new_syscall = reverie::syscalls::Linkat::new()
+                    .with_olddirfd(kernel_olddirfd)
+                    .with_oldpath(Some(new_oldpath_addr))
+                    .with_newdirfd(kernel_newdirfd)
+                    .with_newpath(Some(new_newpath_addr))
+                    .with_flags(args.flags());
+                let result = guest.inject(Syscall::Linkat(new_syscall)).await?;
+                return Ok(Some(result));
             }
         }
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proc_fd_link() {
+        let link = |path: &str| proc_fd_link(Path::new(path), 100, 101);
+
+        assert_eq!(link("/proc/self/fd/3"), Some(3));
+        assert_eq!(link("/proc/thread-self/fd/3"), Some(3));
+        assert_eq!(link("/proc/100/fd/12"), Some(12));
+        assert_eq!(link("/proc/101/fd/12"), Some(12));
+        assert_eq!(link("//proc/self//fd/3/"), Some(3));
+
+        // Other processes, other files and malformed FDs are left to the kernel
+        assert_eq!(link("/proc/102/fd/3"), None);
+        assert_eq!(link("/proc/self/fdinfo/3"), None);
+        assert_eq!(link("/proc/self/fd"), None);
+        assert_eq!(link("/proc/self/fd/+3"), None);
+        assert_eq!(link("/proc/self/fd/3/x"), None);
+        assert_eq!(link("proc/self/fd/3"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    sandbox::Sandbox,
+    syscall::{
+        access::{caller_ids, permitted},
+        io::{io_errno, lookup_virtual},
+        memory::read_path,
+        translate_path,
+    },
+    vfs::{fdtable::FdTable, mount::MountTable},
+};
+use reverie::{syscalls::Syscall, Error, Guest};
+use std::path::{Path, PathBuf};
+
+/// Truncate a path in a virtual VFS.
+///
+/// The caller needs write permission on the file, checked with the sandbox's effective
+/// credentials. Read-only mounts report `EROFS`.
+///
+/// Returns `Some(result)` if the path lives under a virtual or read-only mount, or `None` if the
+/// kernel should perform the truncation.
+async fn truncate_virtual(path: &Path, length: i64, mount_table: &MountTable) -> Option<i64> {
+    let (vfs, _translated_path, read_only) = mount_table.resolve(path)?;
+    if read_only {
+        return Some(-libc::EROFS as i64);
+    }
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    if length < 0 {
+        return Some(-libc::EINVAL as i64);
+    }
+
+    let stat = match vfs.stat(path).await {
+        Ok(stat) => stat,
+        Err(e) => return Some(e.to_errno()),
+    };
+    if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+        return Some(-libc::EISDIR as i64);
+    }
+
+    let (euid, egid) = caller_ids(&*vfs, true);
+    if !permitted(&stat, libc::W_OK, euid, egid) {
+        return Some(-libc::EACCES as i64);
+    }
+
+    Some(match vfs.truncate(path, length as u64).await {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// The `truncate` system call.
+///
+/// This intercepts `truncate` system calls and resizes files in virtual mounts via
+/// `Vfs::truncate`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_truncate<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Truncate,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(result) = truncate_virtual(&path, args.length(), mount_table).await {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Truncate(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `ftruncate` system call.
+///
+/// This intercepts `ftruncate` system calls. Virtual files are resized via
+/// `Vfs::ftruncate`, which requires the FD to be open for writing (`EBADF` otherwise).
+/// For passthrough files, the virtual FD is translated to the kernel FD.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_ftruncate<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Ftruncate,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let fd = args.fd();
+
+    let Some((vfs, file_ops)) = lookup_virtual(fd, mount_table, fd_table) else {
+        if let Some(kernel_fd) = fd_table.translate(fd) {
+            let new_syscall = args.with_fd(kernel_fd);
+            let result = guest.inject(Syscall::Ftruncate(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+        return Ok(None);
+    };
+
+    if args.length() < 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+
+    let writable = fd_table
+        .get(fd)
+        .map(|entry| entry.flags() & libc::O_ACCMODE != libc::O_RDONLY)
+        .unwrap_or(false);
+    if !writable {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    Ok(Some(
+        match vfs.ftruncate(&file_ops, args.length() as u64).await {
+            Ok(()) => 0,
+            Err(e) => io_errno(e),
+        },
+    ))
+}
+
+/// The `fallocate` system call.
+///
+/// This intercepts `fallocate` system calls. Virtual files are preallocated, or have
+/// holes punched or ranges zeroed, via `Vfs::fallocate`, which requires the FD to be
+/// open for writing (`EBADF` otherwise). Modes the VFS does not implement fail with
+/// `EOPNOTSUPP`. For passthrough files, the virtual FD is translated to the kernel FD.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fallocate<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fallocate,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let fd = args.fd();
+
+    let Some((vfs, file_ops)) = lookup_virtual(fd, mount_table, fd_table) else {
+        if let Some(kernel_fd) = fd_table.translate(fd) {
+            let new_syscall = args.with_fd(kernel_fd);
+            let result = guest.inject(Syscall::Fallocate(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+        return Ok(None);
+    };
+
+    let (offset, len) = (args.offset(), args.len());
+    if offset < 0 || len <= 0 {
+        return Ok(Some(-libc::EINVAL as i64));
+    }
+    if offset.checked_add(len).is_none() {
+        return Ok(Some(-libc::EFBIG as i64));
+    }
+
+    let writable = fd_table
+        .get(fd)
+        .map(|entry| entry.flags() & libc::O_ACCMODE != libc::O_RDONLY)
+        .unwrap_or(false);
+    if !writable {
+        return Ok(Some(-libc::EBADF as i64));
+    }
+
+    Ok(Some(
+        match vfs
+            .fallocate(&file_ops, args.mode(), offset as u64, len as u64)
+            .await
+        {
+            Ok(()) => 0,
+            Err(e) => io_errno(e),
+        },
+    ))
+}
@@ -0,0 +1,132 @@
+use crate::{
+    sandbox::Sandbox,
+    syscall::{memory::read_path, open::resolve_dirfd, translate_path},
+    vfs::{fdtable::FdTable, mount::MountTable},
+};
+use reverie::{
+    syscalls::{AtFlags, Syscall},
+    Error, Guest,
+};
+use std::path::{Path, PathBuf};
+
+/// Remove a path from a virtual VFS.
+///
+/// Returns `Some(result)` if the path lives under a virtual or read-only mount, or `None` if the
+/// kernel should perform the removal.
+async fn remove_virtual(path: &Path, remove_dir: bool, mount_table: &MountTable) -> Option<i64> {
+    let (vfs, _translated_path, read_only) = mount_table.resolve(path)?;
+    if read_only {
+        return Some(-libc::EROFS as i64);
+    }
+    if !vfs.is_virtual() {
+        return None;
+    }
+
+    let result = if remove_dir {
+        vfs.rmdir(path).await
+    } else {
+        vfs.unlink(path).await
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// The `unlink` system call.
+///
+/// This intercepts `unlink` system calls and removes files from virtual mounts via
+/// `Vfs::unlink`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_unlink<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Unlink,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(result) = remove_virtual(&path, false, mount_table).await {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Unlink(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `rmdir` system call.
+///
+/// This intercepts `rmdir` system calls and removes directories from virtual mounts via
+/// `Vfs::rmdir`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+#[cfg(not(target_arch = "aarch64"))]
+pub async fn handle_rmdir<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Rmdir,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(result) = remove_virtual(&path, true, mount_table).await {
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Rmdir(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `unlinkat` system call.
+///
+/// This intercepts `unlinkat` system calls and virtualizes the dirfd. For virtual mounts,
+/// the entry is removed via `Vfs::rmdir` when `AT_REMOVEDIR` is set and `Vfs::unlink`
+/// otherwise. For host mounts, the path is translated and `unlinkat` is injected with the
+/// kernel dirfd.
+/// Note: On ARM (aarch64), both unlink and rmdir are implemented via unlinkat:
+///   - unlink: unlinkat(AT_FDCWD, pathname, 0)
+///   - rmdir: unlinkat(AT_FDCWD, pathname, AT_REMOVEDIR)
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_unlinkat<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Unlinkat,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    let path_addr = match args.path() {
+        Some(addr) => addr,
+        None => return Ok(None),
+    };
+
+    let mut path: PathBuf = read_path(guest, path_addr)?;
+    let kernel_dirfd = match resolve_dirfd(args.dirfd(), &mut path, fd_table) {
+        Ok(fd) => fd,
+        Err(errno) => return Ok(Some(errno)),
+    };
+
+    let remove_dir = args.flags().contains(AtFlags::AT_REMOVEDIR);
+    if let Some(result) = remove_virtual(&path, remove_dir, mount_table).await {
+        return Ok(Some(result));
+    }
+
+    let new_path_addr = translate_path(guest, path_addr, mount_table).await?;
+    let new_syscall = args
+        .with_dirfd(kernel_dirfd)
+        .with_path(new_path_addr.or(Some(path_addr)));
+    let result = guest.inject(Syscall::Unlinkat(new_syscall)).await?;
+    Ok(Some(result))
+}
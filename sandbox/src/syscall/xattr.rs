@@ -1,21 +1,238 @@
-use crate::{sandbox::Sandbox, syscall::translate_path, vfs::mount::MountTable};
-use reverie::{syscalls::Syscall, Error, Guest};
+use crate::{
+    sandbox::Sandbox,
+    syscall::{fd_is_read_only, memory::read_path, translate_path},
+    vfs::{
+        fdtable::{FdEntry, FdTable},
+        mount::MountTable,
+        Vfs,
+    },
+};
+use reverie::{
+    syscalls::{Addr, AddrMut, CStrPtr, MemoryAccess, ReadAddr, Syscall},
+    Error, Guest,
+};
+use std::{
+    ffi::CString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-/// The `llistxattr` system call.
+/// Longest attribute name accepted by the kernel (`XATTR_NAME_MAX`).
+const XATTR_NAME_MAX: usize = 255;
+
+/// Largest attribute value accepted by the kernel (`XATTR_SIZE_MAX`).
+const XATTR_SIZE_MAX: usize = 64 * 1024;
+
+/// Look up the virtual VFS that owns a path.
 ///
-/// This intercepts llistxattr syscalls and translates paths according to the mount table.
-/// Returns Some(syscall) if the path was translated and should be injected,
-/// or None if the original syscall should be used.
-pub async fn handle_llistxattr<T: Guest<Sandbox>>(
+/// Returns `None` for paths outside virtual mounts, which the kernel handles.
+fn lookup_virtual_path(path: &Path, mount_table: &MountTable) -> Option<Arc<dyn Vfs>> {
+    let (vfs, _translated_path, _read_only) = mount_table.resolve(path)?;
+    vfs.is_virtual().then_some(vfs)
+}
+
+/// Whether a path lives under a read-only mount, where attributes cannot be changed.
+fn is_read_only(path: &Path, mount_table: &MountTable) -> bool {
+    matches!(mount_table.resolve(path), Some((_, _, true)))
+}
+
+/// Look up the virtual VFS and path behind a virtual FD.
+///
+/// Returns `None` if the FD is not in the table or refers to a passthrough file.
+fn lookup_virtual_fd(
+    fd: i32,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Option<(Arc<dyn Vfs>, PathBuf)> {
+    match fd_table.get(fd)? {
+        FdEntry::Virtual {
+            path: Some(path), ..
+        } => {
+            let vfs = lookup_virtual_path(&path, mount_table)?;
+            Some((vfs, path))
+        }
+        _ => None,
+    }
+}
+
+/// Read an attribute name from guest memory.
+///
+/// Returns the name, or the errno to report: `EFAULT` for a NULL pointer and `ERANGE`
+/// for an empty or overlong name.
+fn read_name<T: Guest<Sandbox>>(
     guest: &mut T,
-    args: &reverie::syscalls::Llistxattr,
+    name_addr: Option<CStrPtr<'_>>,
+) -> Result<Result<String, i64>, Error> {
+    let Some(name_addr) = name_addr else {
+        return Ok(Err(-libc::EFAULT as i64));
+    };
+    let name: CString = name_addr.read(&guest.memory())?;
+    let name = name.to_string_lossy().into_owned();
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Ok(Err(-libc::ERANGE as i64));
+    }
+    Ok(Ok(name))
+}
+
+/// Copy an attribute value or name list into a guest buffer.
+///
+/// As with the kernel, a zero `size` only reports the length the data needs, and a
+/// buffer that is too small gets `ERANGE`.
+fn write_sized<T: Guest<Sandbox>>(
+    guest: &mut T,
+    addr: Option<AddrMut<'_, u8>>,
+    size: usize,
+    data: &[u8],
+) -> Result<i64, Error> {
+    if size == 0 {
+        return Ok(data.len() as i64);
+    }
+    if data.len() > size {
+        return Ok(-libc::ERANGE as i64);
+    }
+    let Some(addr) = addr else {
+        return Ok(-libc::EFAULT as i64);
+    };
+    guest.memory().write_exact(addr, data)?;
+    Ok(data.len() as i64)
+}
+
+/// Serve the `getxattr` family from a virtual VFS.
+async fn getxattr_virtual<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    path: &Path,
+    follow: bool,
+    name_addr: Option<CStrPtr<'_>>,
+    value_addr: Option<AddrMut<'_, u8>>,
+    size: usize,
+) -> Result<i64, Error> {
+    let name = match read_name(guest, name_addr)? {
+        Ok(name) => name,
+        Err(errno) => return Ok(errno),
+    };
+
+    match vfs.getxattr(path, &name, follow).await {
+        Ok(value) => write_sized(guest, value_addr, size, &value),
+        Err(e) => Ok(e.to_errno()),
+    }
+}
+
+/// Serve the `setxattr` family from a virtual VFS.
+#[allow(clippy::too_many_arguments)]
+async fn setxattr_virtual<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    path: &Path,
+    follow: bool,
+    name_addr: Option<CStrPtr<'_>>,
+    value_addr: Option<Addr<'_, u8>>,
+    size: usize,
+    flags: i32,
+) -> Result<i64, Error> {
+    if flags & !(libc::XATTR_CREATE | libc::XATTR_REPLACE) != 0 {
+        return Ok(-libc::EINVAL as i64);
+    }
+
+    let name = match read_name(guest, name_addr)? {
+        Ok(name) => name,
+        Err(errno) => return Ok(errno),
+    };
+
+    if size > XATTR_SIZE_MAX {
+        return Ok(-libc::E2BIG as i64);
+    }
+    let mut value = vec![0u8; size];
+    if size > 0 {
+        let Some(value_addr) = value_addr else {
+            return Ok(-libc::EFAULT as i64);
+        };
+        guest.memory().read_exact(value_addr, &mut value)?;
+    }
+
+    let result = vfs.setxattr(path, &name, &value, flags, follow).await;
+    Ok(match result {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// Serve the `listxattr` family from a virtual VFS.
+///
+/// Names are written as a sequence of NUL-terminated strings.
+async fn listxattr_virtual<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    path: &Path,
+    follow: bool,
+    list_addr: Option<AddrMut<'_, u8>>,
+    size: usize,
+) -> Result<i64, Error> {
+    let names = match vfs.listxattr(path, follow).await {
+        Ok(names) => names,
+        Err(e) => return Ok(e.to_errno()),
+    };
+
+    let mut list = Vec::new();
+    for name in names {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+    }
+    write_sized(guest, list_addr, size, &list)
+}
+
+/// Serve the `removexattr` family from a virtual VFS.
+async fn removexattr_virtual<T: Guest<Sandbox>>(
+    guest: &mut T,
+    vfs: &dyn Vfs,
+    path: &Path,
+    follow: bool,
+    name_addr: Option<CStrPtr<'_>>,
+) -> Result<i64, Error> {
+    let name = match read_name(guest, name_addr)? {
+        Ok(name) => name,
+        Err(errno) => return Ok(errno),
+    };
+
+    Ok(match vfs.removexattr(path, &name, follow).await {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    })
+}
+
+/// The `getxattr` system call.
+///
+/// This intercepts `getxattr` system calls and reads attributes from virtual mounts via
+/// `Vfs::getxattr`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_getxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Getxattr,
     mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
+) -> Result<Option<i64>, Error> {
     if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let value_addr = args.value().map(|addr| addr.cast::<u8>());
+            let result = getxattr_virtual(
+                guest,
+                &*vfs,
+                &path,
+                true,
+                args.name(),
+                value_addr,
+                args.size(),
+            )
+            .await?;
+            return Ok(Some(result));
+        }
+
         if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
             let new_syscall = args.with_path(Some(new_path_addr));
-
-            return Ok(Some(Syscall::Llistxattr(new_syscall)));
+            let result = guest.inject(Syscall::Getxattr(new_syscall)).await?;
+            return Ok(Some(result));
         }
     }
     Ok(None)
@@ -23,20 +240,374 @@ pub async fn handle_llistxattr<T: Guest<Sandbox>>(
 
 /// The `lgetxattr` system call.
 ///
-/// This intercepts lgetxattr syscalls and translates paths according to the mount table.
-/// Returns Some(syscall) if the path was translated and should be injected,
-/// or None if the original syscall should be used.
+/// Like `getxattr`, but a symlink in a virtual mount is not followed.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
 pub async fn handle_lgetxattr<T: Guest<Sandbox>>(
     guest: &mut T,
     args: &reverie::syscalls::Lgetxattr,
     mount_table: &MountTable,
-) -> Result<Option<Syscall>, Error> {
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let value_addr = args.value().map(|addr| addr.cast::<u8>());
+            let result = getxattr_virtual(
+                guest,
+                &*vfs,
+                &path,
+                false,
+                args.name(),
+                value_addr,
+                args.size(),
+            )
+            .await?;
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Lgetxattr(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `fgetxattr` system call.
+///
+/// This intercepts `fgetxattr` system calls. Virtual FDs read attributes via
+/// `Vfs::getxattr`, and passthrough FDs are translated to kernel FDs.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fgetxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fgetxattr,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    if let Some((vfs, path)) = lookup_virtual_fd(args.fd(), mount_table, fd_table) {
+        let value_addr = args.value().map(|addr| addr.cast::<u8>());
+        let result = getxattr_virtual(
+            guest,
+            &*vfs,
+            &path,
+            true,
+            args.name(),
+            value_addr,
+            args.size(),
+        )
+        .await?;
+        return Ok(Some(result));
+    }
+
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        let new_syscall = args.with_fd(kernel_fd);
+        let result = guest.inject(Syscall::Fgetxattr(new_syscall)).await?;
+        return Ok(Some(result));
+    }
+    Ok(None)
+}
+
+/// The `setxattr` system call.
+///
+/// This intercepts `setxattr` system calls and stores attributes in virtual mounts via
+/// `Vfs::setxattr`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_setxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Setxattr,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if is_read_only(&path, mount_table) {
+            return Ok(Some(-libc::EROFS as i64));
+        }
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let value_addr = args.value().map(|addr| addr.cast::<u8>());
+            let result = setxattr_virtual(
+                guest,
+                &*vfs,
+                &path,
+                true,
+                args.name(),
+                value_addr,
+                args.size(),
+                args.flags(),
+            )
+            .await?;
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Setxattr(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `lsetxattr` system call.
+///
+/// Like `setxattr`, but a symlink in a virtual mount is not followed.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_lsetxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Lsetxattr,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
     if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if is_read_only(&path, mount_table) {
+            return Ok(Some(-libc::EROFS as i64));
+        }
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let value_addr = args.value().map(|addr| addr.cast::<u8>());
+            let result = setxattr_virtual(
+                guest,
+                &*vfs,
+                &path,
+                false,
+                args.name(),
+                value_addr,
+                args.size(),
+                args.flags(),
+            )
+            .await?;
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Lsetxattr(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `fsetxattr` system call.
+///
+/// This intercepts `fsetxattr` system calls. Virtual FDs store attributes via
+/// `Vfs::setxattr`, and passthrough FDs are translated to kernel FDs. FDs of files
+/// in read-only mounts fail with `EROFS`.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fsetxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fsetxattr,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    if fd_is_read_only(args.fd(), mount_table, fd_table) {
+        return Ok(Some(-libc::EROFS as i64));
+    }
+    if let Some((vfs, path)) = lookup_virtual_fd(args.fd(), mount_table, fd_table) {
+        let value_addr = args.value().map(|addr| addr.cast::<u8>());
+        let result = setxattr_virtual(
+            guest,
+            &*vfs,
+            &path,
+            true,
+            args.name(),
+            value_addr,
+            args.size(),
+            args.flags(),
+        )
+        .await?;
+        return Ok(Some(result));
+    }
+
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        let new_syscall = args.with_fd(kernel_fd);
+        let result = guest.inject(Syscall::Fsetxattr(new_syscall)).await?;
+        return Ok(Some(result));
+    }
+    Ok(None)
+}
+
+/// The `listxattr` system call.
+///
+/// This intercepts `listxattr` system calls and lists attribute names in virtual mounts via
+/// `Vfs::listxattr`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_listxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Listxattr,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let list_addr = args.list().map(|addr| addr.cast::<u8>());
+            let result =
+                listxattr_virtual(guest, &*vfs, &path, true, list_addr, args.size()).await?;
+            return Ok(Some(result));
+        }
+
         if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
             let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Listxattr(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `llistxattr` system call.
+///
+/// Like `listxattr`, but a symlink in a virtual mount is not followed.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_llistxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Llistxattr,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let list_addr = args.list().map(|addr| addr.cast::<u8>());
+            let result =
+                listxattr_virtual(guest, &*vfs, &path, false, list_addr, args.size()).await?;
+            return Ok(Some(result));
+        }
 
-            return Ok(Some(Syscall::Lgetxattr(new_syscall)));
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Llistxattr(new_syscall)).await?;
+            return Ok(Some(result));
         }
     }
     Ok(None)
 }
+
+/// The `flistxattr` system call.
+///
+/// This intercepts `flistxattr` system calls. Virtual FDs list attribute names via
+/// `Vfs::listxattr`, and passthrough FDs are translated to kernel FDs.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_flistxattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Flistxattr,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    if let Some((vfs, path)) = lookup_virtual_fd(args.fd(), mount_table, fd_table) {
+        let list_addr = args.list().map(|addr| addr.cast::<u8>());
+        let result = listxattr_virtual(guest, &*vfs, &path, true, list_addr, args.size()).await?;
+        return Ok(Some(result));
+    }
+
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        let new_syscall = args.with_fd(kernel_fd);
+        let result = guest.inject(Syscall::Flistxattr(new_syscall)).await?;
+        return Ok(Some(result));
+    }
+    Ok(None)
+}
+
+/// The `removexattr` system call.
+///
+/// This intercepts `removexattr` system calls and removes attributes in virtual mounts via
+/// `Vfs::removexattr`, or translates paths according to the mount table for host mounts.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_removexattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Removexattr,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if is_read_only(&path, mount_table) {
+            return Ok(Some(-libc::EROFS as i64));
+        }
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let result = removexattr_virtual(guest, &*vfs, &path, true, args.name()).await?;
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Removexattr(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `lremovexattr` system call.
+///
+/// Like `removexattr`, but a symlink in a virtual mount is not followed.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_lremovexattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Lremovexattr,
+    mount_table: &MountTable,
+) -> Result<Option<i64>, Error> {
+    if let Some(path_addr) = args.path() {
+        let path: PathBuf = read_path(guest, path_addr)?;
+        if is_read_only(&path, mount_table) {
+            return Ok(Some(-libc::EROFS as i64));
+        }
+        if let Some(vfs) = lookup_virtual_path(&path, mount_table) {
+            let result = removexattr_virtual(guest, &*vfs, &path, false, args.name()).await?;
+            return Ok(Some(result));
+        }
+
+        if let Some(new_path_addr) = translate_path(guest, path_addr, mount_table).await? {
+            let new_syscall = args.with_path(Some(new_path_addr));
+            let result = guest.inject(Syscall::Lremovexattr(new_syscall)).await?;
+            return Ok(Some(result));
+        }
+    }
+    Ok(None)
+}
+
+/// The `fremovexattr` system call.
+///
+/// This intercepts `fremovexattr` system calls. Virtual FDs remove attributes via
+/// `Vfs::removexattr`, and passthrough FDs are translated to kernel FDs. FDs of files
+/// in read-only mounts fail with `EROFS`.
+///
+/// Returns `Some(result)` if the syscall was handled and the result should be returned directly,
+/// or `None` if the original syscall should be used.
+pub async fn handle_fremovexattr<T: Guest<Sandbox>>(
+    guest: &mut T,
+    args: &reverie::syscalls::Fremovexattr,
+    mount_table: &MountTable,
+    fd_table: &FdTable,
+) -> Result<Option<i64>, Error> {
+    if fd_is_read_only(args.fd(), mount_table, fd_table) {
+        return Ok(Some(-libc::EROFS as i64));
+    }
+    if let Some((vfs, path)) = lookup_virtual_fd(args.fd(), mount_table, fd_table) {
+        let result = removexattr_virtual(guest, &*vfs, &path, true, args.name()).await?;
+        return Ok(Some(result));
+    }
+
+    if let Some(kernel_fd) = fd_table.translate(args.fd()) {
+        let new_syscall = args.with_fd(kernel_fd);
+        let result = guest.inject(Syscall::Fremovexattr(new_syscall)).await?;
+        return Ok(Some(result));
+    }
+    Ok(None)
+}
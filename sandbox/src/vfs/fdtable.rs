@@ -1,5 +1,5 @@
 use super::file::BoxedFileOps;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
 use std::sync::{Arc, Mutex};
 
 /// Standard file descriptor constants
@@ -8,6 +8,28 @@ const STDOUT_FILENO: i32 = 1;
 const STDERR_FILENO: i32 = 2;
 const FIRST_USER_FD: i32 = 3;
 
+/// Default limit on the number of virtual FDs open in a table
+pub const DEFAULT_MAX_OPEN_FILES: usize = 1024;
+
+/// Working directory of a process as the guest sees it, where the kernel's differs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GuestCwd {
+    /// A directory in a virtual mount, which the kernel knows nothing about, so its
+    /// working directory is left wherever it was
+    Virtual(std::path::PathBuf),
+    /// A directory under a host-backed mount, which the kernel knows by its host path
+    Translated(std::path::PathBuf),
+}
+
+impl GuestCwd {
+    /// Get the path of the directory in the sandbox
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            GuestCwd::Virtual(path) | GuestCwd::Translated(path) => path,
+        }
+    }
+}
+
 /// Information about a virtualized file descriptor
 #[derive(Clone)]
 pub enum FdEntry {
@@ -42,6 +64,14 @@ impl FdEntry {
         }
     }
 
+    /// Check whether writes through this FD append to the end of the file
+    ///
+    /// This is tracked per FD entry (like the kernel's per-open-file `O_APPEND`)
+    /// so that `write` and `pwrite64` on virtual files honor append semantics.
+    pub fn is_append(&self) -> bool {
+        self.flags() & libc::O_APPEND != 0
+    }
+
     /// Get the path for this FD entry
     pub fn path(&self) -> Option<&std::path::PathBuf> {
         match self {
@@ -57,6 +87,59 @@ impl FdEntry {
             FdEntry::Virtual { file_ops, .. } => Some(file_ops),
         }
     }
+
+    /// Check whether this FD is closed on `execve` (`O_CLOEXEC`)
+    pub fn is_cloexec(&self) -> bool {
+        self.flags() & libc::O_CLOEXEC != 0
+    }
+
+    /// Return a copy of this entry with different flags
+    ///
+    /// The copy refers to the same kernel FD or virtual file handle.
+    pub fn with_flags(&self, flags: i32) -> FdEntry {
+        match self {
+            FdEntry::Passthrough {
+                kernel_fd, path, ..
+            } => FdEntry::Passthrough {
+                kernel_fd: *kernel_fd,
+                flags,
+                path: path.clone(),
+            },
+            FdEntry::Virtual { file_ops, path, .. } => FdEntry::Virtual {
+                file_ops: file_ops.clone(),
+                flags,
+                path: path.clone(),
+            },
+        }
+    }
+
+    /// Key identifying the virtual file handle behind this entry, if any
+    fn handle_key(&self) -> Option<usize> {
+        self.file_ops()
+            .map(|file_ops| Arc::as_ptr(file_ops) as *const () as usize)
+    }
+}
+
+/// Events a virtual file is always ready for, in `epoll` terms
+///
+/// Like regular files on the kernel, virtual files never block, so they are
+/// reported readable and writable whenever these events are asked for.
+const EPOLL_VIRTUAL_EVENTS: u32 =
+    (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDNORM | libc::EPOLLWRNORM) as u32;
+
+/// A virtual FD in the interest list of an epoll instance
+///
+/// The kernel refuses to watch virtual files, which have no kernel FD, so the
+/// table keeps their registrations itself.
+#[derive(Clone, Copy, Debug)]
+struct EpollInterest {
+    /// Events the FD was registered for, including `EPOLLET` and `EPOLLONESHOT`
+    events: u32,
+    /// User data reported along with the events
+    data: u64,
+    /// Whether the FD is reported; cleared once an edge-triggered or one-shot
+    /// registration has been reported, until it is modified
+    armed: bool,
 }
 
 /// Inner state of the FD table, protected by a single mutex
@@ -67,6 +150,36 @@ struct FdTableInner {
     next_vfd: i32,
     /// Min-heap of freed FDs available for reuse (stored as negative for min-heap behavior)
     free_fds: BinaryHeap<std::cmp::Reverse<i32>>,
+    /// Virtual FDs watched by each epoll instance, keyed by the instance's kernel FD
+    epoll_interest: HashMap<i32, BTreeMap<i32, EpollInterest>>,
+    /// Working directory of the process while the kernel's is not what the guest sees
+    cwd: Option<GuestCwd>,
+}
+
+impl FdTableInner {
+    /// Forget the epoll registrations that go away with an FD removed from the table
+    ///
+    /// A virtual FD leaves every interest list, and the interest list of an epoll
+    /// instance is dropped along with the last FD referring to its kernel FD.
+    fn forget_epoll_interest(&mut self, vfd: i32, entry: &FdEntry) {
+        match entry.kernel_fd() {
+            None => {
+                for interest in self.epoll_interest.values_mut() {
+                    interest.remove(&vfd);
+                }
+            }
+            Some(kernel_fd) => {
+                if self.epoll_interest.contains_key(&kernel_fd)
+                    && !self
+                        .entries
+                        .values()
+                        .any(|entry| entry.kernel_fd() == Some(kernel_fd))
+                {
+                    self.epoll_interest.remove(&kernel_fd);
+                }
+            }
+        }
+    }
 }
 
 /// Per-process file descriptor table that virtualizes file descriptors
@@ -75,16 +188,38 @@ struct FdTableInner {
 /// to kernel (actual) file descriptors. It is thread-safe and can be shared across
 /// threads within the same process.
 ///
+/// Virtual FDs created by `dup()` and friends share a virtual file handle (and with
+/// it the file offset). The table counts the FDs referring to each handle, so that
+/// the handle is only closed along with the last of them; see `release()`.
+///
+/// The number of virtual FDs in the table is capped by a soft limit, which syscall
+/// handlers check with `is_full()` before opening or duplicating a virtual file.
+///
+/// The table also holds the working directory of the process while that is a
+/// directory in a mount, which the kernel either knows nothing about or knows by
+/// its host path; see `cwd()`.
+///
 /// Note: Clone creates a shallow copy that shares the same underlying FD table.
 /// For fork/clone syscalls, use `deep_clone()` instead.
 #[derive(Clone)]
 pub struct FdTable {
     inner: Arc<Mutex<FdTableInner>>,
+    /// Number of FDs referring to each virtual file handle, keyed by the handle's
+    /// address. This is shared with tables created by `deep_clone()`, since a forked
+    /// child's FDs refer to the same open files as its parent's.
+    handle_refs: Arc<Mutex<HashMap<usize, usize>>>,
+    /// Maximum number of virtual FDs open at once
+    max_open_files: usize,
 }
 
 impl FdTable {
     /// Create a new FD table with standard FDs (stdin, stdout, stderr)
     pub fn new() -> Self {
+        Self::with_max_open_files(DEFAULT_MAX_OPEN_FILES)
+    }
+
+    /// Create a new FD table that holds at most `max_open_files` virtual FDs
+    pub fn with_max_open_files(max_open_files: usize) -> Self {
         let mut entries = HashMap::new();
 
         // Initialize standard file descriptors (0, 1, 2) as passthrough files
@@ -118,7 +253,11 @@ impl FdTable {
                 entries,
                 next_vfd: FIRST_USER_FD,
                 free_fds: BinaryHeap::new(),
+                epoll_interest: HashMap::new(),
+                cwd: None,
             })),
+            handle_refs: Arc::new(Mutex::new(HashMap::new())),
+            max_open_files,
         }
     }
 
@@ -132,12 +271,79 @@ impl FdTable {
             .lock()
             .unwrap_or_else(|poisoned| poisoned.into_inner());
 
+        // The child's FDs are new references to the same virtual file handles
+        for entry in inner.entries.values() {
+            self.retain_handle(entry.handle_key());
+        }
+
         Self {
             inner: Arc::new(Mutex::new(FdTableInner {
                 entries: inner.entries.clone(),
                 next_vfd: inner.next_vfd,
                 free_fds: inner.free_fds.clone(),
+                epoll_interest: inner.epoll_interest.clone(),
+                cwd: inner.cwd.clone(),
             })),
+            handle_refs: self.handle_refs.clone(),
+            max_open_files: self.max_open_files,
+        }
+    }
+
+    /// Get the maximum number of virtual FDs open at once
+    pub fn max_open_files(&self) -> usize {
+        self.max_open_files
+    }
+
+    /// Check whether the table holds as many virtual FDs as its limit allows
+    ///
+    /// Handlers return `EMFILE` instead of allocating another virtual FD when this
+    /// is true. Passthrough FDs are not counted, as the kernel limits those itself.
+    pub fn is_full(&self) -> bool {
+        let inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let open = inner
+            .entries
+            .values()
+            .filter(|entry| entry.file_ops().is_some())
+            .count();
+        open >= self.max_open_files
+    }
+
+    /// Count a new FD referring to a virtual file handle
+    fn retain_handle(&self, key: Option<usize>) {
+        let Some(key) = key else {
+            return;
+        };
+        let mut refs = self
+            .handle_refs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *refs.entry(key).or_insert(0) += 1;
+    }
+
+    /// Drop an FD's reference to a virtual file handle
+    ///
+    /// Returns true if this was the last FD referring to the handle.
+    fn release_handle(&self, key: Option<usize>) -> bool {
+        let Some(key) = key else {
+            return false;
+        };
+        let mut refs = self
+            .handle_refs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match refs.get_mut(&key) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            _ => {
+                refs.remove(&key);
+                true
+            }
         }
     }
 
@@ -168,6 +374,7 @@ impl FdTable {
             }
         };
 
+        self.retain_handle(entry.handle_key());
         inner.entries.insert(vfd, entry);
         vfd
     }
@@ -208,6 +415,7 @@ impl FdTable {
             .filter(|&std::cmp::Reverse(fd)| fd != vfd)
             .collect();
 
+        self.retain_handle(entry.handle_key());
         inner.entries.insert(vfd, entry);
         vfd
     }
@@ -217,6 +425,15 @@ impl FdTable {
     /// Returns the old FdEntry if the VFD was already allocated, which the caller
     /// should close if needed.
     pub fn allocate_at(&self, vfd: i32, entry: FdEntry) -> Option<FdEntry> {
+        self.replace(vfd, entry).map(|(old, _)| old)
+    }
+
+    /// Allocate a specific virtual FD, like `allocate_at()`
+    ///
+    /// If the VFD was already allocated, returns the old FdEntry along with
+    /// whether it held the last reference to its virtual file handle, in which
+    /// case the caller should close the handle.
+    pub fn replace(&self, vfd: i32, entry: FdEntry) -> Option<(FdEntry, bool)> {
         let mut inner = self
             .inner
             .lock()
@@ -246,7 +463,11 @@ impl FdTable {
         }
 
         // Insert the new entry and return the old one if it existed
-        inner.entries.insert(vfd, entry)
+        self.retain_handle(entry.handle_key());
+        let old = inner.entries.insert(vfd, entry)?;
+        inner.forget_epoll_interest(vfd, &old);
+        let last = self.release_handle(old.handle_key());
+        Some((old, last))
     }
 
     /// Translate a virtual FD to a kernel FD
@@ -270,37 +491,287 @@ impl FdTable {
         inner.entries.get(&vfd).cloned()
     }
 
+    /// Replace the flags of a virtual FD (for fcntl F_SETFD)
+    ///
+    /// Returns false if the VFD is not allocated.
+    pub fn set_flags(&self, vfd: i32, flags: i32) -> bool {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match inner.entries.get_mut(&vfd) {
+            Some(entry) => {
+                *entry = entry.with_flags(flags);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Deallocate a virtual FD and mark it as available for reuse
     pub fn deallocate(&self, vfd: i32) -> Option<FdEntry> {
+        self.release(vfd).map(|(entry, _)| entry)
+    }
+
+    /// Deallocate a virtual FD, like `deallocate()`
+    ///
+    /// Also returns whether the FD held the last reference to its virtual file
+    /// handle, in which case the caller should close the handle. This is always
+    /// false for passthrough FDs, whose kernel FD is closed individually.
+    pub fn release(&self, vfd: i32) -> Option<(FdEntry, bool)> {
         let mut inner = self
             .inner
             .lock()
             .unwrap_or_else(|poisoned| poisoned.into_inner());
 
         let entry = inner.entries.remove(&vfd)?;
+        inner.forget_epoll_interest(vfd, &entry);
 
         // Add to free list for reuse (unless it's a standard FD)
         if vfd >= FIRST_USER_FD {
             inner.free_fds.push(std::cmp::Reverse(vfd));
         }
 
-        Some(entry)
+        let last = self.release_handle(entry.handle_key());
+        Some((entry, last))
+    }
+
+    /// Deallocate all FDs with `O_CLOEXEC` set (for execve)
+    ///
+    /// Both virtual and passthrough entries are removed. Returns the removed
+    /// entries along with whether each held the last reference to its virtual
+    /// file handle, like `release()`; a passthrough entry never does.
+    pub fn release_cloexec(&self) -> Vec<(FdEntry, bool)> {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let mut vfds: Vec<i32> = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_cloexec())
+            .map(|(vfd, _)| *vfd)
+            .collect();
+        vfds.sort_unstable();
+
+        let mut released = Vec::with_capacity(vfds.len());
+        for vfd in vfds {
+            let Some(entry) = inner.entries.remove(&vfd) else {
+                continue;
+            };
+            inner.forget_epoll_interest(vfd, &entry);
+            if vfd >= FIRST_USER_FD {
+                inner.free_fds.push(std::cmp::Reverse(vfd));
+            }
+            let last = self.release_handle(entry.handle_key());
+            released.push((entry, last));
+        }
+        released
+    }
+
+    /// Deallocate all FDs (for a process that is gone)
+    ///
+    /// Returns the removed entries along with whether each held the last reference
+    /// to its virtual file handle, like `release()`.
+    pub fn release_all(&self) -> Vec<(FdEntry, bool)> {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let mut entries: Vec<(i32, FdEntry)> = inner.entries.drain().collect();
+        entries.sort_unstable_by_key(|(vfd, _)| *vfd);
+        inner.free_fds.clear();
+        inner.epoll_interest.clear();
+        inner.next_vfd = FIRST_USER_FD;
+
+        entries
+            .into_iter()
+            .map(|(_, entry)| {
+                let last = self.release_handle(entry.handle_key());
+                (entry, last)
+            })
+            .collect()
     }
 
     /// Duplicate a virtual FD (for dup syscall)
+    ///
+    /// The new FD shares the file handle, but not `O_CLOEXEC`, which is cleared.
     pub fn duplicate(&self, old_vfd: i32) -> Option<i32> {
         let entry = self.get(old_vfd)?;
+        let entry = entry.with_flags(entry.flags() & !libc::O_CLOEXEC);
         // Allocate a new virtual FD pointing to the same file operations
         Some(self.allocate(entry))
     }
 
     /// Duplicate a virtual FD to a specific new FD (for dup2 syscall)
     ///
+    /// Like `duplicate()`, `O_CLOEXEC` is cleared on the new FD.
     /// Returns the old entry that was at new_vfd if it existed (caller should close it)
     pub fn duplicate_at(&self, old_vfd: i32, new_vfd: i32) -> Option<FdEntry> {
         let entry = self.get(old_vfd)?;
+        let entry = entry.with_flags(entry.flags() & !libc::O_CLOEXEC);
         self.allocate_at(new_vfd, entry)
     }
+
+    /// List the virtual FDs that are still open, sorted by FD number
+    ///
+    /// Each entry is paired with the path it was opened with (empty if unknown).
+    /// Passthrough FDs are not included, since the kernel closes those when the
+    /// process exits.
+    pub fn open_virtual_handles(&self) -> Vec<(i32, std::path::PathBuf)> {
+        let inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let mut handles: Vec<_> = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.file_ops().is_some())
+            .map(|(vfd, entry)| (*vfd, entry.path().cloned().unwrap_or_default()))
+            .collect();
+        handles.sort_unstable_by_key(|(vfd, _)| *vfd);
+        handles
+    }
+
+    /// Get the working directory of the process as the guest sees it, if the
+    /// kernel's is not it
+    pub fn cwd(&self) -> Option<GuestCwd> {
+        let inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.cwd.clone()
+    }
+
+    /// Set the working directory of the process as the guest sees it, or back to
+    /// the kernel's with `None`
+    pub fn set_cwd(&self, cwd: Option<GuestCwd>) {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.cwd = cwd;
+    }
+
+    /// Get the working directory of the process if it is a virtual directory
+    ///
+    /// Relative paths are resolved against it instead of the kernel's working
+    /// directory, which is left wherever it was when the process changed into the
+    /// virtual directory.
+    pub fn virtual_cwd(&self) -> Option<std::path::PathBuf> {
+        match self.cwd() {
+            Some(GuestCwd::Virtual(path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Add, modify or remove the registration of a virtual FD with an epoll
+    /// instance, like `epoll_ctl` does for kernel FDs
+    ///
+    /// `kernel_epfd` is the kernel FD of the epoll instance. `events` and `data`
+    /// are ignored for `EPOLL_CTL_DEL`. Returns the errno on failure: `EEXIST` when
+    /// adding an FD that is already registered, `ENOENT` when modifying or removing
+    /// one that isn't, and `EINVAL` for an unknown operation.
+    pub fn epoll_ctl(
+        &self,
+        kernel_epfd: i32,
+        op: i32,
+        vfd: i32,
+        events: u32,
+        data: u64,
+    ) -> Result<(), i32> {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let interest = EpollInterest {
+            events,
+            data,
+            armed: true,
+        };
+        match op {
+            libc::EPOLL_CTL_ADD => {
+                let list = inner.epoll_interest.entry(kernel_epfd).or_default();
+                if list.contains_key(&vfd) {
+                    return Err(libc::EEXIST);
+                }
+                list.insert(vfd, interest);
+            }
+            libc::EPOLL_CTL_MOD => {
+                let registered = inner
+                    .epoll_interest
+                    .get_mut(&kernel_epfd)
+                    .and_then(|list| list.get_mut(&vfd))
+                    .ok_or(libc::ENOENT)?;
+                *registered = interest;
+            }
+            libc::EPOLL_CTL_DEL => {
+                inner
+                    .epoll_interest
+                    .get_mut(&kernel_epfd)
+                    .and_then(|list| list.remove(&vfd))
+                    .ok_or(libc::ENOENT)?;
+            }
+            _ => return Err(libc::EINVAL),
+        }
+        Ok(())
+    }
+
+    /// Check whether any virtual FD registered with an epoll instance is ready
+    pub fn epoll_has_ready(&self, kernel_epfd: i32) -> bool {
+        let inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner
+            .epoll_interest
+            .get(&kernel_epfd)
+            .is_some_and(|list| list.values().any(|interest| ready_events(interest) != 0))
+    }
+
+    /// Take up to `max` events of the virtual FDs registered with an epoll
+    /// instance, in FD order, as pairs of ready events and user data
+    ///
+    /// Edge-triggered and one-shot registrations are only reported once, until
+    /// they are modified, since virtual files never become ready anew.
+    pub fn epoll_take_ready(&self, kernel_epfd: i32, max: usize) -> Vec<(u32, u64)> {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(list) = inner.epoll_interest.get_mut(&kernel_epfd) else {
+            return Vec::new();
+        };
+
+        let mut ready = Vec::new();
+        for interest in list.values_mut() {
+            if ready.len() == max {
+                break;
+            }
+            let events = ready_events(interest);
+            if events == 0 {
+                continue;
+            }
+            if interest.events & (libc::EPOLLET | libc::EPOLLONESHOT) as u32 != 0 {
+                interest.armed = false;
+            }
+            ready.push((events, interest.data));
+        }
+        ready
+    }
+}
+
+/// The events an epoll registration of a virtual FD currently reports
+fn ready_events(interest: &EpollInterest) -> u32 {
+    if interest.armed {
+        interest.events & EPOLL_VIRTUAL_EVENTS
+    } else {
+        0
+    }
 }
 
 impl Default for FdTable {
@@ -323,6 +794,8 @@ impl std::fmt::Debug for FdTable {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vfs::{file::FileOps, VfsResult};
+    use std::os::unix::io::RawFd;
 
     #[test]
     fn test_standard_fds() {
@@ -375,6 +848,23 @@ mod tests {
         assert_eq!(table.translate(vfd), None);
     }
 
+    #[test]
+    fn test_is_append() {
+        let entry = FdEntry::Passthrough {
+            kernel_fd: 100,
+            flags: libc::O_WRONLY | libc::O_APPEND,
+            path: None,
+        };
+        assert!(entry.is_append());
+
+        let entry = FdEntry::Passthrough {
+            kernel_fd: 100,
+            flags: libc::O_WRONLY,
+            path: None,
+        };
+        assert!(!entry.is_append());
+    }
+
     #[test]
     fn test_duplicate() {
         let table = FdTable::new();
@@ -409,6 +899,311 @@ mod tests {
         assert!(result.is_none());
         assert_eq!(table.translate(10), Some(100));
     }
+
+    /// Virtual file that does nothing, for tracking handle references
+    struct NullFile;
+
+    #[async_trait::async_trait]
+    impl FileOps for NullFile {
+        async fn read(&self, _buf: &mut [u8]) -> VfsResult<usize> {
+            Ok(0)
+        }
+        async fn write(&self, buf: &[u8]) -> VfsResult<usize> {
+            Ok(buf.len())
+        }
+        async fn seek(&self, _offset: i64, _whence: i32) -> VfsResult<i64> {
+            Ok(0)
+        }
+        async fn fstat(&self) -> VfsResult<libc::stat> {
+            Ok(unsafe { std::mem::zeroed() })
+        }
+        async fn fsync(&self) -> VfsResult<()> {
+            Ok(())
+        }
+        async fn fdatasync(&self) -> VfsResult<()> {
+            Ok(())
+        }
+        fn fcntl(&self, _cmd: i32, _arg: i64) -> VfsResult<i64> {
+            Ok(0)
+        }
+        fn ioctl(&self, _request: u64, _arg: u64) -> VfsResult<i64> {
+            Ok(0)
+        }
+        fn as_raw_fd(&self) -> Option<RawFd> {
+            None
+        }
+        async fn close(&self) -> VfsResult<()> {
+            Ok(())
+        }
+        fn get_flags(&self) -> i32 {
+            0
+        }
+        fn set_flags(&self, _flags: i32) -> VfsResult<()> {
+            Ok(())
+        }
+    }
+
+    fn virtual_entry(flags: i32) -> FdEntry {
+        FdEntry::Virtual {
+            file_ops: Arc::new(NullFile),
+            flags,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn test_release_last_handle_reference() {
+        let table = FdTable::new();
+
+        let vfd1 = table.allocate(virtual_entry(libc::O_RDWR));
+        let vfd2 = table.duplicate(vfd1).unwrap();
+        table.duplicate_at(vfd1, 10);
+
+        // The handle stays open until its last FD is released
+        let (_, last) = table.release(vfd1).unwrap();
+        assert!(!last);
+        let (_, last) = table.release(10).unwrap();
+        assert!(!last);
+        let (_, last) = table.release(vfd2).unwrap();
+        assert!(last);
+    }
+
+    #[test]
+    fn test_replace_reports_last_handle_reference() {
+        let table = FdTable::new();
+
+        let vfd1 = table.allocate(virtual_entry(0));
+        let vfd2 = table.duplicate(vfd1).unwrap();
+
+        // Replacing one of two FDs sharing a handle keeps the handle open
+        let passthrough = FdEntry::Passthrough {
+            kernel_fd: 100,
+            flags: 0,
+            path: None,
+        };
+        let (_, last) = table.replace(vfd1, passthrough.clone()).unwrap();
+        assert!(!last);
+        let (_, last) = table.replace(vfd2, passthrough).unwrap();
+        assert!(last);
+    }
+
+    #[test]
+    fn test_deep_clone_shares_handle_references() {
+        let table = FdTable::new();
+        let vfd = table.allocate(virtual_entry(0));
+
+        let child = table.deep_clone();
+        let (_, last) = table.release(vfd).unwrap();
+        assert!(!last);
+        let (_, last) = child.release(vfd).unwrap();
+        assert!(last);
+    }
+
+    #[test]
+    fn test_duplicate_clears_cloexec() {
+        let table = FdTable::new();
+
+        let vfd = table.allocate(virtual_entry(libc::O_WRONLY | libc::O_CLOEXEC));
+        assert!(table.get(vfd).unwrap().is_cloexec());
+
+        let dup = table.duplicate(vfd).unwrap();
+        let entry = table.get(dup).unwrap();
+        assert!(!entry.is_cloexec());
+        assert_eq!(entry.flags(), libc::O_WRONLY);
+
+        table.duplicate_at(vfd, 10);
+        assert!(!table.get(10).unwrap().is_cloexec());
+    }
+
+    #[test]
+    fn test_set_flags() {
+        let table = FdTable::new();
+
+        let vfd = table.allocate(virtual_entry(libc::O_RDWR));
+        let dup = table.duplicate(vfd).unwrap();
+        assert!(table.set_flags(vfd, libc::O_RDWR | libc::O_CLOEXEC));
+        assert!(table.get(vfd).unwrap().is_cloexec());
+
+        // FD flags are per FD, so the duplicate is unaffected
+        assert!(!table.get(dup).unwrap().is_cloexec());
+        assert!(!table.set_flags(99, 0));
+    }
+
+    #[test]
+    fn test_release_cloexec() {
+        let table = FdTable::new();
+
+        let kept = table.allocate(virtual_entry(libc::O_RDONLY));
+        let cloexec = table.allocate(virtual_entry(libc::O_RDONLY | libc::O_CLOEXEC));
+        let shared = table.duplicate(cloexec).unwrap();
+        let passthrough = table.allocate(FdEntry::Passthrough {
+            kernel_fd: 100,
+            flags: libc::O_CLOEXEC,
+            path: None,
+        });
+
+        let released = table.release_cloexec();
+        assert_eq!(released.len(), 2);
+        // The dup still refers to the virtual handle, so it must stay open
+        assert!(!released[0].1);
+        // A passthrough entry holds no handle, so it is only forgotten
+        assert_eq!(released[1].0.kernel_fd(), Some(100));
+        assert!(!released[1].1);
+
+        assert!(table.get(kept).is_some());
+        assert!(table.get(shared).is_some());
+        assert!(table.get(cloexec).is_none());
+        assert!(table.get(passthrough).is_none());
+
+        // Released FDs are reused
+        assert_eq!(table.allocate(virtual_entry(0)), cloexec);
+    }
+
+    #[test]
+    fn test_release_all() {
+        let table = FdTable::new();
+
+        let vfd = table.allocate(virtual_entry(libc::O_RDONLY));
+        table.duplicate(vfd).unwrap();
+
+        let released = table.release_all();
+        // stdin, stdout, stderr and the two virtual FDs
+        assert_eq!(released.len(), 5);
+        // Only the last FD referring to the virtual handle closes it
+        let last: Vec<bool> = released.iter().map(|(_, last)| *last).collect();
+        assert_eq!(last, [false, false, false, false, true]);
+
+        assert!(table.get(vfd).is_none());
+        assert!(table.open_virtual_handles().is_empty());
+    }
+
+    #[test]
+    fn test_max_open_files() {
+        let table = FdTable::with_max_open_files(2);
+
+        let vfd = table.allocate(virtual_entry(0));
+        table.duplicate(vfd).unwrap();
+        assert!(table.is_full());
+
+        // Passthrough FDs don't count towards the limit
+        table.release(vfd);
+        assert!(!table.is_full());
+        table.allocate(FdEntry::Passthrough {
+            kernel_fd: 100,
+            flags: 0,
+            path: None,
+        });
+        assert!(!table.is_full());
+
+        // Forked children inherit the limit
+        assert_eq!(table.deep_clone().max_open_files(), 2);
+    }
+
+    #[test]
+    fn test_open_virtual_handles() {
+        let table = FdTable::new();
+
+        let closed = table.allocate(virtual_entry(0));
+        let open = table.allocate(FdEntry::Virtual {
+            file_ops: Arc::new(NullFile),
+            flags: 0,
+            path: Some("/agent/file".into()),
+        });
+        table.allocate(FdEntry::Passthrough {
+            kernel_fd: 100,
+            flags: 0,
+            path: Some("/tmp/host".into()),
+        });
+        table.release(closed);
+
+        assert_eq!(
+            table.open_virtual_handles(),
+            vec![(open, std::path::PathBuf::from("/agent/file"))]
+        );
+    }
+
+    #[test]
+    fn test_epoll_interest() {
+        let table = FdTable::new();
+        let epfd = table.allocate(FdEntry::Passthrough {
+            kernel_fd: 100,
+            flags: 0,
+            path: None,
+        });
+        let level = table.allocate(virtual_entry(libc::O_RDWR));
+        let edge = table.allocate(virtual_entry(libc::O_RDWR));
+        let (epollin, epollout) = (libc::EPOLLIN as u32, libc::EPOLLOUT as u32);
+        let epollet = libc::EPOLLET as u32;
+
+        assert!(!table.epoll_has_ready(100));
+        assert_eq!(
+            table.epoll_ctl(100, libc::EPOLL_CTL_ADD, level, epollin, 1),
+            Ok(())
+        );
+        assert_eq!(
+            table.epoll_ctl(100, libc::EPOLL_CTL_ADD, level, epollin, 1),
+            Err(libc::EEXIST)
+        );
+        assert_eq!(
+            table.epoll_ctl(100, libc::EPOLL_CTL_ADD, edge, epollout | epollet, 2),
+            Ok(())
+        );
+
+        // Level-triggered FDs are reported every time, edge-triggered ones once
+        assert!(table.epoll_has_ready(100));
+        assert_eq!(table.epoll_take_ready(100, 1), [(epollin, 1)]);
+        assert_eq!(
+            table.epoll_take_ready(100, 8),
+            [(epollin, 1), (epollout, 2)]
+        );
+        assert_eq!(table.epoll_take_ready(100, 8), [(epollin, 1)]);
+        assert_eq!(
+            table.epoll_ctl(100, libc::EPOLL_CTL_MOD, edge, epollin | epollet, 3),
+            Ok(())
+        );
+        assert_eq!(table.epoll_take_ready(100, 8), [(epollin, 1), (epollin, 3)]);
+
+        // Closing a virtual FD removes it from the interest list
+        table.release(level);
+        assert_eq!(
+            table.epoll_ctl(100, libc::EPOLL_CTL_DEL, level, 0, 0),
+            Err(libc::ENOENT)
+        );
+        assert!(!table.epoll_has_ready(100));
+
+        // The interest list goes away with the last FD of the epoll instance
+        let dup = table.duplicate(epfd).unwrap();
+        table
+            .epoll_ctl(100, libc::EPOLL_CTL_MOD, edge, epollin, 4)
+            .unwrap();
+        table.release(epfd);
+        assert!(table.epoll_has_ready(100));
+        table.release(dup);
+        assert!(!table.epoll_has_ready(100));
+    }
+
+    #[test]
+    fn test_cwd() {
+        let table = FdTable::new();
+        assert_eq!(table.cwd(), None);
+
+        table.set_cwd(Some(GuestCwd::Virtual("/agent/dir".into())));
+        let child = table.deep_clone();
+        table.set_cwd(Some(GuestCwd::Translated("/host/dir".into())));
+
+        // Relative paths are only resolved against a virtual directory
+        assert_eq!(table.virtual_cwd(), None);
+        assert_eq!(
+            table.cwd().unwrap().path(),
+            std::path::Path::new("/host/dir")
+        );
+
+        // A forked child keeps the working directory it was created with
+        assert_eq!(
+            child.virtual_cwd(),
+            Some(std::path::PathBuf::from("/agent/dir"))
+        );
+    }
 }
 
 /// Property tests for `FdTable` correctness.
@@ -3,6 +3,14 @@ use async_trait::async_trait;
 use std::os::unix::io::RawFd;
 use std::sync::Arc;
 
+/// `fallocate` mode flags, which libc only defines on Linux
+pub const FALLOC_FL_KEEP_SIZE: i32 = 0x01;
+pub const FALLOC_FL_PUNCH_HOLE: i32 = 0x02;
+pub const FALLOC_FL_ZERO_RANGE: i32 = 0x10;
+
+/// Largest run of zeros `FileOps::fallocate` writes at once
+const ZERO_FILL_CHUNK: u64 = 1024 * 1024;
+
 /// File operations trait for VFS implementations.
 ///
 /// This trait provides a VFS-level abstraction over file operations,
@@ -16,6 +24,74 @@ pub trait FileOps: Send + Sync {
     /// Write to the file at the current offset
     async fn write(&self, buf: &[u8]) -> VfsResult<usize>;
 
+    /// Read up to `len` bytes at `offset` without changing the current offset
+    ///
+    /// Returns fewer than `len` bytes when the read crosses end-of-file and an
+    /// empty buffer when `offset` is at or past end-of-file.
+    async fn pread(&self, _offset: u64, _len: usize) -> VfsResult<Vec<u8>> {
+        Err(super::VfsError::Other("pread not supported".to_string()))
+    }
+
+    /// Write `buf` at `offset` without changing the current offset
+    ///
+    /// Writing past end-of-file grows the file, filling any gap with zeros.
+    async fn pwrite(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(super::VfsError::Other("pwrite not supported".to_string()))
+    }
+
+    /// Truncate the file to `size` bytes, zero-filling when it grows
+    ///
+    /// The file offset is left unchanged.
+    async fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(super::VfsError::Other("truncate not supported".to_string()))
+    }
+
+    /// Allocate or deallocate `len` bytes at `offset`, as `fallocate(2)` does
+    ///
+    /// Mode 0 grows the file to `offset + len` with zeros. `FALLOC_FL_PUNCH_HOLE`
+    /// (which requires `FALLOC_FL_KEEP_SIZE`) and `FALLOC_FL_ZERO_RANGE` zero the part
+    /// of the range inside the file, and the latter also grows the file unless
+    /// `FALLOC_FL_KEEP_SIZE` is set. Other modes report `VfsError::NotSupported`.
+    ///
+    /// The default implementation zero-fills with `pwrite()` and grows the file with
+    /// `truncate()`, for files that don't track allocated space apart from their data.
+    async fn fallocate(&self, mode: i32, offset: u64, len: u64) -> VfsResult<()> {
+        let keep_size = mode & FALLOC_FL_KEEP_SIZE != 0;
+        let zero = match mode & !FALLOC_FL_KEEP_SIZE {
+            0 => false,
+            FALLOC_FL_PUNCH_HOLE if keep_size => true,
+            FALLOC_FL_ZERO_RANGE => true,
+            _ => return Err(super::VfsError::NotSupported),
+        };
+
+        let size = self.fstat().await?.st_size as u64;
+        let end = offset.saturating_add(len);
+        if zero {
+            let zero_end = end.min(size);
+            let mut pos = offset;
+            while pos < zero_end {
+                let n = (zero_end - pos).min(ZERO_FILL_CHUNK);
+                self.pwrite(pos, &vec![0; n as usize]).await?;
+                pos += n;
+            }
+        }
+        if !keep_size && end > size {
+            self.truncate(end).await?;
+        }
+        Ok(())
+    }
+
+    /// Set the access and modification times of the file
+    ///
+    /// Times are in seconds since the Unix epoch, and a `None` time leaves that
+    /// timestamp unchanged. Data written through this handle is stored first, so
+    /// that writing it back later does not replace the new modification time.
+    async fn set_times(&self, _atime: Option<i64>, _mtime: Option<i64>) -> VfsResult<()> {
+        Err(super::VfsError::Other(
+            "set_times not supported".to_string(),
+        ))
+    }
+
     /// Seek to a position in the file
     async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64>;
 
@@ -49,12 +125,13 @@ pub trait FileOps: Send + Sync {
     /// Set flags associated with this file descriptor
     fn set_flags(&self, flags: i32) -> VfsResult<()>;
 
-    /// Read directory entries (for directories only)
+    /// List directory entries (for directories only)
     ///
-    /// This is used to implement getdents64. Returns a vector of (inode, name, type) tuples.
-    /// Returns an error if this is not a directory.
-    async fn getdents(&self) -> VfsResult<Vec<(u64, String, u8)>> {
-        Err(super::VfsError::Other("Not a directory".to_string()))
+    /// This is used to implement getdents64. Returns the full listing, including
+    /// `.` and `..`; the directory position tracked by `seek()` records how much
+    /// of it has been consumed. Returns an error if this is not a directory.
+    async fn readdir(&self) -> VfsResult<Vec<super::DirEntry>> {
+        Err(super::VfsError::NotADirectory)
     }
 }
 
@@ -0,0 +1,87 @@
+//! Remapping of user and group IDs for virtual mounts.
+//!
+//! This is the VFS-level counterpart of a user namespace mapping: a host
+//! ("outside") ID recorded in the filesystem is reported as a different
+//! ("inside") ID to the sandboxed process, and IDs passed in by the process are
+//! mapped back before they are stored.
+
+/// User and group ID mappings of a mount
+///
+/// Unlike a user namespace, IDs without a mapping are passed through unchanged
+/// rather than reported as the overflow ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdMap {
+    /// `(inside, outside)` user ID pairs
+    uids: Vec<(u32, u32)>,
+    /// `(inside, outside)` group ID pairs
+    gids: Vec<(u32, u32)>,
+}
+
+/// The mapping of mounts that do not remap IDs
+pub static IDENTITY: IdMap = IdMap::new(Vec::new(), Vec::new());
+
+impl IdMap {
+    /// Create a mapping from `(inside, outside)` user and group ID pairs
+    pub const fn new(uids: Vec<(u32, u32)>, gids: Vec<(u32, u32)>) -> Self {
+        Self { uids, gids }
+    }
+
+    /// Check whether no IDs are remapped
+    pub fn is_empty(&self) -> bool {
+        self.uids.is_empty() && self.gids.is_empty()
+    }
+
+    /// Map a host user ID to the ID seen inside the mount
+    pub fn uid_inside(&self, uid: u32) -> u32 {
+        to_inside(&self.uids, uid)
+    }
+
+    /// Map a user ID seen inside the mount to the host ID
+    pub fn uid_outside(&self, uid: u32) -> u32 {
+        to_outside(&self.uids, uid)
+    }
+
+    /// Map a host group ID to the ID seen inside the mount
+    pub fn gid_inside(&self, gid: u32) -> u32 {
+        to_inside(&self.gids, gid)
+    }
+
+    /// Map a group ID seen inside the mount to the host ID
+    pub fn gid_outside(&self, gid: u32) -> u32 {
+        to_outside(&self.gids, gid)
+    }
+}
+
+fn to_inside(pairs: &[(u32, u32)], id: u32) -> u32 {
+    pairs
+        .iter()
+        .find(|(_, outside)| *outside == id)
+        .map_or(id, |(inside, _)| *inside)
+}
+
+fn to_outside(pairs: &[(u32, u32)], id: u32) -> u32 {
+    pairs
+        .iter()
+        .find(|(inside, _)| *inside == id)
+        .map_or(id, |(_, outside)| *outside)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_map() {
+        let map = IdMap::new(vec![(0, 1000)], vec![(0, 100), (50, 200)]);
+        assert_eq!(map.uid_inside(1000), 0);
+        assert_eq!(map.uid_outside(0), 1000);
+        assert_eq!(map.gid_inside(200), 50);
+        assert_eq!(map.gid_outside(50), 200);
+
+        // Unmapped IDs pass through
+        assert_eq!(map.uid_inside(2000), 2000);
+        assert_eq!(map.uid_outside(2000), 2000);
+        assert!(IDENTITY.is_empty());
+        assert_eq!(IDENTITY.gid_inside(100), 100);
+    }
+}
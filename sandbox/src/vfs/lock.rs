@@ -0,0 +1,316 @@
+//! Advisory file locks on virtual files.
+//!
+//! Virtual files have no kernel FD for the kernel to lock, so the sandbox keeps
+//! its own table of the locks taken with `flock` and `fcntl` (`F_SETLK` and
+//! `F_SETLKW`), keyed by the device and inode of the locked file.
+//!
+//! As on Linux, the two kinds of locks don't interact. A `flock` lock covers the
+//! whole file and belongs to the open file handle, so it is shared with the FDs
+//! duplicated from it. A `fcntl` lock covers a byte range and belongs to the
+//! process, which releases all its locks on a file when it closes any FD of it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::Notify;
+
+use super::file::BoxedFileOps;
+
+/// Identity of a locked file, as its device and inode numbers
+pub type FileId = (u64, u64);
+
+/// End of a range that extends to the end of the file, however far it grows
+pub const TO_EOF: u64 = u64::MAX;
+
+/// Holder of a lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockOwner {
+    /// A `flock` lock, held by a virtual file handle
+    Handle(usize),
+    /// A `fcntl` record lock, held by a process
+    Process(i32),
+}
+
+impl LockOwner {
+    /// The owner of the `flock` locks taken through `file_ops`
+    pub fn handle(file_ops: &BoxedFileOps) -> Self {
+        LockOwner::Handle(Arc::as_ptr(file_ops) as *const () as usize)
+    }
+
+    /// Check whether locks of the two owners can conflict
+    fn same_kind(&self, other: &LockOwner) -> bool {
+        matches!(
+            (self, other),
+            (LockOwner::Handle(_), LockOwner::Handle(_))
+                | (LockOwner::Process(_), LockOwner::Process(_))
+        )
+    }
+}
+
+/// Whether a lock may be shared with other owners
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    Shared,
+    Exclusive,
+}
+
+/// A lock on the byte range `start..end` of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLock {
+    pub owner: LockOwner,
+    pub kind: LockKind,
+    pub start: u64,
+    /// End of the range (exclusive), or `TO_EOF`
+    pub end: u64,
+}
+
+impl FileLock {
+    /// Check whether this lock keeps `other` from being taken
+    fn conflicts_with(&self, other: &FileLock) -> bool {
+        self.owner != other.owner
+            && self.owner.same_kind(&other.owner)
+            && self.start < other.end
+            && other.start < self.end
+            && (self.kind == LockKind::Exclusive || other.kind == LockKind::Exclusive)
+    }
+}
+
+/// Table of the advisory locks held on virtual files
+#[derive(Default)]
+pub struct LockTable {
+    files: Mutex<HashMap<FileId, Vec<FileLock>>>,
+    /// Woken up whenever locks are released or downgraded
+    released: Notify,
+}
+
+impl LockTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Find a lock held on `file` that keeps `lock` from being taken
+    pub fn conflict(&self, file: FileId, lock: &FileLock) -> Option<FileLock> {
+        let files = self
+            .files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        files
+            .get(&file)?
+            .iter()
+            .find(|held| held.conflicts_with(lock))
+            .copied()
+    }
+
+    /// Take `lock` on `file` unless another owner holds a conflicting lock
+    ///
+    /// The new lock replaces whatever the owner held on the same range, so that
+    /// a lock can be converted between shared and exclusive. On conflict, the
+    /// conflicting lock is returned and nothing changes.
+    pub fn try_lock(&self, file: FileId, lock: FileLock) -> Result<(), FileLock> {
+        let mut files = self
+            .files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(held) = files
+            .get(&file)
+            .and_then(|locks| locks.iter().find(|held| held.conflicts_with(&lock)))
+        {
+            return Err(*held);
+        }
+        let locks = files.entry(file).or_default();
+        remove_range(locks, lock.owner, lock.start, lock.end);
+        locks.push(lock);
+        drop(files);
+
+        // Downgrading to a shared lock may let others in
+        self.released.notify_waiters();
+        Ok(())
+    }
+
+    /// Take `lock` on `file`, waiting for conflicting locks to be released
+    pub async fn lock(&self, file: FileId, lock: FileLock) {
+        loop {
+            // Registered before checking, so that a release in between wakes us
+            let released = self.released.notified();
+            if self.try_lock(file, lock).is_ok() {
+                return;
+            }
+            released.await;
+        }
+    }
+
+    /// Release the locks `owner` holds on the byte range `start..end` of `file`
+    ///
+    /// Locks partially covered by the range are split.
+    pub fn unlock(&self, file: FileId, owner: LockOwner, start: u64, end: u64) {
+        self.update(|files| {
+            if let Some(locks) = files.get_mut(&file) {
+                remove_range(locks, owner, start, end);
+                if locks.is_empty() {
+                    files.remove(&file);
+                }
+            }
+        });
+    }
+
+    /// Release all the locks `owner` holds on `file`
+    pub fn release(&self, file: FileId, owner: LockOwner) {
+        self.unlock(file, owner, 0, TO_EOF);
+    }
+
+    /// Release all the locks `owner` holds on any file
+    pub fn release_owner(&self, owner: LockOwner) {
+        self.update(|files| {
+            files.retain(|_, locks| {
+                locks.retain(|held| held.owner != owner);
+                !locks.is_empty()
+            });
+        });
+    }
+
+    /// Check whether `owner` holds any lock
+    pub fn holds_locks(&self, owner: LockOwner) -> bool {
+        let files = self
+            .files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        files
+            .values()
+            .any(|locks| locks.iter().any(|held| held.owner == owner))
+    }
+
+    /// Apply `f` to the table and wake up the waiters
+    fn update(&self, f: impl FnOnce(&mut HashMap<FileId, Vec<FileLock>>)) {
+        let mut files = self
+            .files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut files);
+        drop(files);
+        self.released.notify_waiters();
+    }
+}
+
+/// Remove the byte range `start..end` from the locks of `owner`, splitting
+/// the locks that extend past it
+fn remove_range(locks: &mut Vec<FileLock>, owner: LockOwner, start: u64, end: u64) {
+    let mut kept = Vec::with_capacity(locks.len());
+    for held in locks.drain(..) {
+        if held.owner != owner || held.end <= start || end <= held.start {
+            kept.push(held);
+            continue;
+        }
+        if held.start < start {
+            kept.push(FileLock { end: start, ..held });
+        }
+        if end < held.end {
+            kept.push(FileLock { start: end, ..held });
+        }
+    }
+    *locks = kept;
+}
+
+/// Global lock table shared by all the processes in the sandbox
+static LOCK_TABLE: OnceLock<LockTable> = OnceLock::new();
+
+/// Get the global lock table
+pub fn lock_table() -> &'static LockTable {
+    LOCK_TABLE.get_or_init(LockTable::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const FILE: FileId = (1, 42);
+
+    fn whole_file(owner: LockOwner, kind: LockKind) -> FileLock {
+        FileLock {
+            owner,
+            kind,
+            start: 0,
+            end: TO_EOF,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exclusive_lock_contention() {
+        let table = Arc::new(LockTable::new());
+        let first = whole_file(LockOwner::Handle(1), LockKind::Exclusive);
+        let second = whole_file(LockOwner::Handle(2), LockKind::Exclusive);
+
+        table.try_lock(FILE, first).unwrap();
+        assert_eq!(table.try_lock(FILE, second), Err(first));
+        assert_eq!(table.conflict(FILE, &second), Some(first));
+
+        // A blocking request parks until the lock is released
+        let waiter = {
+            let table = table.clone();
+            tokio::spawn(async move { table.lock(FILE, second).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        table.release(FILE, LockOwner::Handle(1));
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(table.holds_locks(LockOwner::Handle(2)));
+        assert_eq!(table.try_lock(FILE, first), Err(second));
+    }
+
+    #[test]
+    fn test_shared_locks() {
+        let table = LockTable::new();
+        table
+            .try_lock(FILE, whole_file(LockOwner::Handle(1), LockKind::Shared))
+            .unwrap();
+        table
+            .try_lock(FILE, whole_file(LockOwner::Handle(2), LockKind::Shared))
+            .unwrap();
+        assert!(table
+            .try_lock(FILE, whole_file(LockOwner::Handle(3), LockKind::Exclusive))
+            .is_err());
+
+        // Converting to an exclusive lock conflicts with the other shared lock
+        assert!(table
+            .try_lock(FILE, whole_file(LockOwner::Handle(1), LockKind::Exclusive))
+            .is_err());
+        table.release(FILE, LockOwner::Handle(2));
+        table
+            .try_lock(FILE, whole_file(LockOwner::Handle(1), LockKind::Exclusive))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_record_locks_split_ranges() {
+        let table = LockTable::new();
+        let owner = LockOwner::Process(100);
+        let other = LockOwner::Process(200);
+        table
+            .try_lock(FILE, whole_file(owner, LockKind::Exclusive))
+            .unwrap();
+
+        // Unlocking the middle of the range leaves the two ends locked
+        table.unlock(FILE, owner, 10, 20);
+        let range = |owner, start, end| FileLock {
+            owner,
+            kind: LockKind::Exclusive,
+            start,
+            end,
+        };
+        table.try_lock(FILE, range(other, 10, 20)).unwrap();
+        assert!(table.try_lock(FILE, range(other, 5, 15)).is_err());
+        assert!(table.try_lock(FILE, range(other, 15, 25)).is_err());
+
+        // Record locks and flock locks don't interact
+        table
+            .try_lock(FILE, whole_file(LockOwner::Handle(1), LockKind::Exclusive))
+            .unwrap();
+
+        table.release_owner(owner);
+        assert!(!table.holds_locks(owner));
+        table.try_lock(FILE, range(other, 0, TO_EOF)).unwrap();
+    }
+}
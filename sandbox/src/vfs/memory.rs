@@ -0,0 +1,1594 @@
+//! An in-memory virtual filesystem.
+//!
+//! `MemoryVfs` keeps its files in memory, so they are gone once the VFS is
+//! dropped. It is the reference implementation of a virtual `Vfs`, with the
+//! same semantics as `SqliteVfs`, and a fast scratch mount for commands and
+//! tests whose files don't need to outlive them.
+
+use super::file::{BoxedFileOps, FileOps};
+use super::{check_path_length, DirEntry, StatFs, Vfs, VfsError, VfsResult};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::os::unix::io::RawFd;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Inode number of the root directory
+const ROOT_INO: u64 = 1;
+
+/// Maximum total size of the extended attribute names and values of an inode
+const MAX_XATTR_SIZE: usize = 64 * 1024;
+
+/// Maximum number of symlinks followed while resolving a path, as on Linux
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Filesystem type reported by `statfs()`, that of tmpfs
+const TMPFS_MAGIC: i64 = 0x0102_1994;
+
+/// Block size reported by `statfs()` and `stat()`
+const STATFS_BLOCK_SIZE: u64 = 4096;
+
+/// Total blocks reported by `statfs()`, as memory is not bounded up front
+const STATFS_TOTAL_BLOCKS: u64 = 1024 * 1024 * 1024;
+
+/// Total inodes reported by `statfs()`
+const STATFS_TOTAL_INODES: u64 = 1_000_000;
+
+/// An in-memory virtual filesystem
+///
+/// Clones share the same files, so a clone can be kept to inspect what the
+/// sandboxed process wrote to a mount of the VFS.
+#[derive(Clone)]
+pub struct MemoryVfs {
+    /// The inodes, shared with the open files
+    tree: Arc<Mutex<Tree>>,
+    /// The virtual path as seen by the sandboxed process
+    mount_point: PathBuf,
+    /// User ID that owns new files
+    uid: u32,
+    /// Group ID that owns new files
+    gid: u32,
+}
+
+impl MemoryVfs {
+    /// Create a new, empty in-memory VFS
+    ///
+    /// Files are owned by the current user and group; use `with_owner()` to
+    /// create them with a different owner.
+    ///
+    /// # Arguments
+    /// * `mount_point` - The virtual path seen by the guest (e.g., "/scratch")
+    pub fn new(mount_point: PathBuf) -> Self {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let mut root = Inode::new(
+            Kind::Directory {
+                entries: BTreeMap::new(),
+                parent: ROOT_INO,
+            },
+            libc::S_IFDIR | 0o755,
+            uid,
+            gid,
+        );
+        // The root has no entry naming it, but is its own parent
+        root.nlink = 2;
+
+        Self {
+            tree: Arc::new(Mutex::new(Tree {
+                inodes: HashMap::from([(ROOT_INO, root)]),
+                next_ino: ROOT_INO + 1,
+                whiteouts: HashSet::new(),
+            })),
+            mount_point,
+            uid,
+            gid,
+        }
+    }
+
+    /// Set the user and group IDs that own the root and the files created
+    /// from now on
+    pub fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        if let Some(root) = self.tree().inodes.get_mut(&ROOT_INO) {
+            root.uid = uid;
+            root.gid = gid;
+        }
+        self
+    }
+
+    /// Get the mount point path
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    fn tree(&self) -> MutexGuard<'_, Tree> {
+        lock(&self.tree)
+    }
+
+    /// Translate a sandbox path to a path relative to the root of the VFS
+    fn relative<'a>(&self, path: &'a Path) -> VfsResult<&'a Path> {
+        check_path_length(path)?;
+        path.strip_prefix(&self.mount_point)
+            .map_err(|_| VfsError::NotFound)
+    }
+
+    /// Look up a sandbox path, following a final symlink if `follow` is set
+    fn lookup(&self, path: &Path, follow: bool) -> VfsResult<(MutexGuard<'_, Tree>, Lookup)> {
+        let relative = self.relative(path)?;
+        let tree = self.tree();
+        let lookup = tree.lookup(relative, follow)?;
+        Ok((tree, lookup))
+    }
+
+    /// Look up an existing inode by sandbox path, and update it with `f`
+    fn update(
+        &self,
+        path: &Path,
+        follow: bool,
+        f: impl FnOnce(&mut Inode) -> VfsResult<()>,
+    ) -> VfsResult<()> {
+        let (mut tree, lookup) = self.lookup(path, follow)?;
+        let inode = tree.get_mut(lookup.ino.ok_or(VfsError::NotFound)?)?;
+        f(inode)?;
+        inode.ctime = now();
+        Ok(())
+    }
+
+    /// Create an inode at a sandbox path that must not exist yet
+    fn create(&self, path: &Path, kind: Kind, mode: u32) -> VfsResult<u64> {
+        let (mut tree, lookup) = self.lookup(path, false)?;
+        let name = match (lookup.name, lookup.ino) {
+            (Some(name), None) => name,
+            _ => return Err(VfsError::AlreadyExists),
+        };
+        let ino = tree.allocate(Inode::new(kind, mode, self.uid, self.gid));
+        tree.attach(lookup.parent, name, ino)?;
+        Ok(ino)
+    }
+
+    /// Open a handle on an existing inode
+    fn handle(&self, tree: &mut Tree, ino: u64, flags: i32) -> VfsResult<BoxedFileOps> {
+        let inode = tree.get_mut(ino)?;
+        inode.open += 1;
+        let is_directory = matches!(inode.kind, Kind::Directory { .. });
+
+        let handle = Handle {
+            tree: self.tree.clone(),
+            ino,
+            flags: Mutex::new(flags),
+            closed: AtomicBool::new(false),
+        };
+        if is_directory {
+            Ok(Arc::new(MemoryDirectoryOps {
+                handle,
+                entries: Mutex::new(None),
+                position: Mutex::new(0),
+            }))
+        } else {
+            Ok(Arc::new(MemoryFileOps {
+                handle,
+                offset: Mutex::new(0),
+            }))
+        }
+    }
+}
+
+/// Lock the inodes, even if a thread panicked while holding them
+fn lock(tree: &Mutex<Tree>) -> MutexGuard<'_, Tree> {
+    tree.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Current time in seconds since the Unix epoch
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+/// Split a relative path into its components, keeping `.` and `..`
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            Component::CurDir => Some(".".to_string()),
+            Component::ParentDir => Some("..".to_string()),
+            Component::RootDir | Component::Prefix(_) => None,
+        })
+        .collect()
+}
+
+/// What an inode holds
+enum Kind {
+    File(Vec<u8>),
+    Directory {
+        /// Entries by name, not including `.` and `..`
+        entries: BTreeMap<String, u64>,
+        /// Inode of the directory holding this one
+        parent: u64,
+    },
+    Symlink(PathBuf),
+}
+
+/// A file, directory or symlink
+struct Inode {
+    kind: Kind,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    atime: i64,
+    mtime: i64,
+    ctime: i64,
+    xattrs: BTreeMap<String, Vec<u8>>,
+    /// Number of open handles, which keep the inode alive once it is removed
+    open: usize,
+}
+
+impl Inode {
+    /// Create an inode that no entry links to yet
+    ///
+    /// Directories start with the link of their `.` entry.
+    fn new(kind: Kind, mode: u32, uid: u32, gid: u32) -> Self {
+        let now = now();
+        let nlink = match kind {
+            Kind::Directory { .. } => 1,
+            _ => 0,
+        };
+        Self {
+            kind,
+            mode,
+            uid,
+            gid,
+            nlink,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            xattrs: BTreeMap::new(),
+            open: 0,
+        }
+    }
+
+    fn is_directory(&self) -> bool {
+        matches!(self.kind, Kind::Directory { .. })
+    }
+
+    fn size(&self) -> u64 {
+        match &self.kind {
+            Kind::File(data) => data.len() as u64,
+            Kind::Directory { .. } => STATFS_BLOCK_SIZE,
+            Kind::Symlink(target) => target.as_os_str().len() as u64,
+        }
+    }
+
+    fn d_type(&self) -> u8 {
+        match self.kind {
+            Kind::File(_) => libc::DT_REG,
+            Kind::Directory { .. } => libc::DT_DIR,
+            Kind::Symlink(_) => libc::DT_LNK,
+        }
+    }
+
+    fn data(&self) -> VfsResult<&Vec<u8>> {
+        match &self.kind {
+            Kind::File(data) => Ok(data),
+            Kind::Directory { .. } => Err(VfsError::IsADirectory),
+            Kind::Symlink(_) => Err(VfsError::InvalidInput("Not a file".to_string())),
+        }
+    }
+
+    fn data_mut(&mut self) -> VfsResult<&mut Vec<u8>> {
+        match &mut self.kind {
+            Kind::File(data) => Ok(data),
+            Kind::Directory { .. } => Err(VfsError::IsADirectory),
+            Kind::Symlink(_) => Err(VfsError::InvalidInput("Not a file".to_string())),
+        }
+    }
+
+    /// Write `buf` at `offset`, zero-filling any gap past the end of the file
+    fn write_at(&mut self, offset: usize, buf: &[u8]) -> VfsResult<usize> {
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or_else(|| VfsError::InvalidInput("Offset overflow".to_string()))?;
+        let data = self.data_mut()?;
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(buf);
+        self.touch();
+        Ok(buf.len())
+    }
+
+    /// Update the modification and change times after changing the contents
+    fn touch(&mut self) {
+        let now = now();
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    fn set_times(&mut self, atime: Option<i64>, mtime: Option<i64>) {
+        if let Some(atime) = atime {
+            self.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            self.mtime = mtime;
+        }
+        self.ctime = now();
+    }
+
+    fn stat(&self, ino: u64) -> libc::stat {
+        let size = self.size() as i64;
+
+        // Use MaybeUninit to construct libc::stat safely
+        let mut stat: std::mem::MaybeUninit<libc::stat> = std::mem::MaybeUninit::zeroed();
+        unsafe {
+            let stat_ptr = stat.as_mut_ptr();
+            (*stat_ptr).st_dev = 0;
+            (*stat_ptr).st_ino = ino;
+            (*stat_ptr).st_nlink = self.nlink.into();
+            (*stat_ptr).st_mode = self.mode;
+            (*stat_ptr).st_uid = self.uid;
+            (*stat_ptr).st_gid = self.gid;
+            (*stat_ptr).st_rdev = 0;
+            (*stat_ptr).st_size = size;
+            (*stat_ptr).st_blksize = STATFS_BLOCK_SIZE as _;
+            (*stat_ptr).st_blocks = (size + 4095) / 4096;
+            (*stat_ptr).st_atime = self.atime;
+            (*stat_ptr).st_atime_nsec = 0;
+            (*stat_ptr).st_mtime = self.mtime;
+            (*stat_ptr).st_mtime_nsec = 0;
+            (*stat_ptr).st_ctime = self.ctime;
+            (*stat_ptr).st_ctime_nsec = 0;
+            stat.assume_init()
+        }
+    }
+}
+
+/// The result of looking up a path
+struct Lookup {
+    /// Directory holding the final component
+    parent: u64,
+    /// Name of the final component, or `None` if the path ends in a directory
+    /// named by `.`, `..` or the mount point itself
+    name: Option<String>,
+    /// Inode of the final component, if it exists
+    ino: Option<u64>,
+}
+
+/// The inodes of a `MemoryVfs`
+struct Tree {
+    inodes: HashMap<u64, Inode>,
+    next_ino: u64,
+    /// Paths with a whiteout, relative to the root, for when the VFS is the
+    /// upper layer of an overlay
+    whiteouts: HashSet<PathBuf>,
+}
+
+impl Tree {
+    fn get(&self, ino: u64) -> VfsResult<&Inode> {
+        self.inodes.get(&ino).ok_or(VfsError::NotFound)
+    }
+
+    fn get_mut(&mut self, ino: u64) -> VfsResult<&mut Inode> {
+        self.inodes.get_mut(&ino).ok_or(VfsError::NotFound)
+    }
+
+    fn entries(&self, dir: u64) -> VfsResult<&BTreeMap<String, u64>> {
+        match &self.get(dir)?.kind {
+            Kind::Directory { entries, .. } => Ok(entries),
+            _ => Err(VfsError::NotADirectory),
+        }
+    }
+
+    fn entries_mut(&mut self, dir: u64) -> VfsResult<&mut BTreeMap<String, u64>> {
+        match &mut self.get_mut(dir)?.kind {
+            Kind::Directory { entries, .. } => Ok(entries),
+            _ => Err(VfsError::NotADirectory),
+        }
+    }
+
+    fn parent(&self, dir: u64) -> u64 {
+        match self.inodes.get(&dir).map(|inode| &inode.kind) {
+            Some(Kind::Directory { parent, .. }) => *parent,
+            _ => ROOT_INO,
+        }
+    }
+
+    /// Check whether `ino` is `dir` or somewhere below it
+    fn is_within(&self, ino: u64, dir: u64) -> bool {
+        let mut current = ino;
+        loop {
+            if current == dir {
+                return true;
+            }
+            if current == ROOT_INO {
+                return false;
+            }
+            current = self.parent(current);
+        }
+    }
+
+    /// Look up a path relative to the root
+    ///
+    /// Symlinks in the directories leading to the final component are always
+    /// followed, and the final component only with `follow`. As in `SqliteVfs`,
+    /// absolute targets are resolved from the root of the VFS, and a dangling
+    /// link resolves to its missing target, so that `O_CREAT` can create it.
+    fn lookup(&self, path: &Path, follow: bool) -> VfsResult<Lookup> {
+        // Components left to resolve, the next one last
+        let mut pending: Vec<String> = components(path).into_iter().rev().collect();
+        let mut dir = ROOT_INO;
+        let mut hops = 0;
+
+        while let Some(component) = pending.pop() {
+            match component.as_str() {
+                "." => continue,
+                ".." => {
+                    dir = self.parent(dir);
+                    continue;
+                }
+                _ => {}
+            }
+
+            let ino = self.entries(dir)?.get(&component).copied();
+            let last = pending.is_empty();
+            match ino.map(|ino| &self.inodes[&ino].kind) {
+                Some(Kind::Symlink(target)) if !last || follow => {
+                    hops += 1;
+                    if hops > MAX_SYMLINK_DEPTH {
+                        return Err(VfsError::SymlinkLoop);
+                    }
+                    if target.is_absolute() {
+                        dir = ROOT_INO;
+                    }
+                    pending.extend(components(target).into_iter().rev());
+                }
+                Some(Kind::Directory { .. }) if !last => dir = ino.unwrap_or(ROOT_INO),
+                Some(_) if !last => return Err(VfsError::NotADirectory),
+                None if !last => return Err(VfsError::NotFound),
+                _ => {
+                    return Ok(Lookup {
+                        parent: dir,
+                        name: Some(component),
+                        ino,
+                    })
+                }
+            }
+        }
+
+        Ok(Lookup {
+            parent: self.parent(dir),
+            name: None,
+            ino: Some(dir),
+        })
+    }
+
+    fn allocate(&mut self, inode: Inode) -> u64 {
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.inodes.insert(ino, inode);
+        ino
+    }
+
+    /// Link `ino` into directory `dir` as `name`
+    fn attach(&mut self, dir: u64, name: String, ino: u64) -> VfsResult<()> {
+        self.entries_mut(dir)?.insert(name, ino);
+        let inode = self.get_mut(ino)?;
+        inode.nlink += 1;
+        inode.ctime = now();
+        if let Kind::Directory { parent, .. } = &mut inode.kind {
+            // The directory's `..` entry links to its new parent
+            *parent = dir;
+            self.get_mut(dir)?.nlink += 1;
+        }
+        self.get_mut(dir)?.touch();
+        Ok(())
+    }
+
+    /// Unlink `name` from directory `dir`, returning the inode it named
+    fn detach(&mut self, dir: u64, name: &str) -> VfsResult<u64> {
+        let ino = self
+            .entries_mut(dir)?
+            .remove(name)
+            .ok_or(VfsError::NotFound)?;
+        let inode = self.get_mut(ino)?;
+        inode.nlink = inode.nlink.saturating_sub(1);
+        inode.ctime = now();
+        if inode.is_directory() {
+            let parent = self.get_mut(dir)?;
+            parent.nlink = parent.nlink.saturating_sub(1);
+        }
+        self.get_mut(dir)?.touch();
+        Ok(ino)
+    }
+
+    /// Remove `name` from directory `dir` for good, freeing its inode once
+    /// it is no longer open
+    fn remove(&mut self, dir: u64, name: &str) -> VfsResult<()> {
+        let ino = self.detach(dir, name)?;
+        let inode = self.get_mut(ino)?;
+        if inode.is_directory() {
+            // Drop the link of its `.` entry too
+            inode.nlink = 0;
+        }
+        self.release(ino);
+        Ok(())
+    }
+
+    /// Free an inode that has neither links nor open handles
+    fn release(&mut self, ino: u64) {
+        if self
+            .inodes
+            .get(&ino)
+            .is_some_and(|inode| inode.nlink == 0 && inode.open == 0)
+        {
+            self.inodes.remove(&ino);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Vfs for MemoryVfs {
+    fn translate_path(&self, path: &Path) -> VfsResult<PathBuf> {
+        // For virtual VFS, we just validate the path is under our mount point,
+        // comparing whole components so that /scratchfoo is not under /scratch
+        if path.starts_with(&self.mount_point) {
+            Ok(path.to_path_buf())
+        } else {
+            Err(VfsError::NotFound)
+        }
+    }
+
+    fn is_virtual(&self) -> bool {
+        true
+    }
+
+    async fn open(&self, path: &Path, flags: i32, mode: u32) -> VfsResult<BoxedFileOps> {
+        // As on Linux, a final symlink is not followed with O_NOFOLLOW, nor
+        // created through with O_CREAT | O_EXCL
+        let exclusive = flags & libc::O_CREAT != 0 && flags & libc::O_EXCL != 0;
+        let follow = !exclusive && flags & libc::O_NOFOLLOW == 0;
+        let (mut tree, lookup) = self.lookup(path, follow)?;
+
+        match lookup.ino {
+            Some(ino) => {
+                // O_CREAT | O_EXCL must fail if the path already exists
+                if exclusive {
+                    return Err(VfsError::AlreadyExists);
+                }
+                let inode = tree.get_mut(ino)?;
+                match inode.kind {
+                    Kind::Symlink(_) => return Err(VfsError::SymlinkLoop),
+                    Kind::Directory { .. } => {
+                        // Directories can only be opened read-only
+                        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                            return Err(VfsError::IsADirectory);
+                        }
+                    }
+                    Kind::File(ref mut data) => {
+                        if flags & libc::O_DIRECTORY != 0 {
+                            return Err(VfsError::NotADirectory);
+                        }
+                        if flags & libc::O_TRUNC != 0 {
+                            data.clear();
+                            inode.touch();
+                        }
+                    }
+                }
+                self.handle(&mut tree, ino, flags)
+            }
+            None => {
+                // File doesn't exist - check if O_CREAT is set
+                if flags & libc::O_CREAT == 0 {
+                    return Err(VfsError::NotFound);
+                }
+                if flags & libc::O_DIRECTORY != 0 {
+                    // O_CREAT | O_DIRECTORY cannot create a directory
+                    return Err(VfsError::InvalidInput(
+                        "O_CREAT with O_DIRECTORY".to_string(),
+                    ));
+                }
+
+                let name = lookup.name.ok_or(VfsError::AlreadyExists)?;
+                let inode = Inode::new(
+                    Kind::File(Vec::new()),
+                    libc::S_IFREG | (mode & 0o7777),
+                    self.uid,
+                    self.gid,
+                );
+                let ino = tree.allocate(inode);
+                tree.attach(lookup.parent, name, ino)?;
+                self.handle(&mut tree, ino, flags)
+            }
+        }
+    }
+
+    async fn read(&self, file: &BoxedFileOps, offset: u64, len: usize) -> VfsResult<Vec<u8>> {
+        file.pread(offset, len).await
+    }
+
+    async fn write(&self, file: &BoxedFileOps, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        file.pwrite(offset, buf).await
+    }
+
+    async fn ftruncate(&self, file: &BoxedFileOps, size: u64) -> VfsResult<()> {
+        file.truncate(size).await
+    }
+
+    async fn fallocate(
+        &self,
+        file: &BoxedFileOps,
+        mode: i32,
+        offset: u64,
+        len: u64,
+    ) -> VfsResult<()> {
+        file.fallocate(mode, offset, len).await
+    }
+
+    async fn futimes(
+        &self,
+        file: &BoxedFileOps,
+        atime: Option<i64>,
+        mtime: Option<i64>,
+    ) -> VfsResult<()> {
+        file.set_times(atime, mtime).await
+    }
+
+    async fn readdir(&self, file: &BoxedFileOps) -> VfsResult<Vec<DirEntry>> {
+        file.readdir().await
+    }
+
+    async fn stat(&self, path: &Path) -> VfsResult<libc::stat> {
+        let (tree, lookup) = self.lookup(path, true)?;
+        let ino = lookup.ino.ok_or(VfsError::NotFound)?;
+        Ok(tree.get(ino)?.stat(ino))
+    }
+
+    async fn lstat(&self, path: &Path) -> VfsResult<libc::stat> {
+        let (tree, lookup) = self.lookup(path, false)?;
+        let ino = lookup.ino.ok_or(VfsError::NotFound)?;
+        Ok(tree.get(ino)?.stat(ino))
+    }
+
+    async fn statfs(&self) -> VfsResult<StatFs> {
+        let tree = self.tree();
+        let used_blocks: u64 = tree
+            .inodes
+            .values()
+            .map(|inode| inode.size().div_ceil(STATFS_BLOCK_SIZE))
+            .sum();
+        let free_blocks = STATFS_TOTAL_BLOCKS.saturating_sub(used_blocks);
+        Ok(StatFs {
+            fs_type: TMPFS_MAGIC,
+            block_size: STATFS_BLOCK_SIZE,
+            blocks: STATFS_TOTAL_BLOCKS,
+            blocks_free: free_blocks,
+            blocks_available: free_blocks,
+            files: STATFS_TOTAL_INODES,
+            files_free: STATFS_TOTAL_INODES.saturating_sub(tree.inodes.len() as u64),
+            name_max: libc::NAME_MAX as u64,
+        })
+    }
+
+    async fn rename(&self, oldpath: &Path, newpath: &Path, flags: u32) -> VfsResult<()> {
+        let noreplace = flags & libc::RENAME_NOREPLACE != 0;
+        let exchange = flags & libc::RENAME_EXCHANGE != 0;
+        if flags & !(libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE) != 0 || (noreplace && exchange)
+        {
+            return Err(VfsError::InvalidInput(format!(
+                "Unsupported rename flags: {:#x}",
+                flags
+            )));
+        }
+
+        let (old_relative, new_relative) = (self.relative(oldpath)?, self.relative(newpath)?);
+        let mut tree = self.tree();
+        let old = tree.lookup(old_relative, false)?;
+        let new = tree.lookup(new_relative, false)?;
+        let old_ino = old.ino.ok_or(VfsError::NotFound)?;
+        let (Some(old_name), Some(new_name)) = (old.name, new.name) else {
+            return Err(VfsError::InvalidInput(
+                "Cannot rename a directory through . or ..".to_string(),
+            ));
+        };
+
+        if exchange {
+            // Both paths must exist for an exchange
+            let new_ino = new.ino.ok_or(VfsError::NotFound)?;
+            if old_ino == new_ino {
+                return Ok(());
+            }
+            if tree.is_within(new.parent, old_ino) || tree.is_within(old.parent, new_ino) {
+                return Err(VfsError::InvalidInput(
+                    "Cannot move a directory into itself".to_string(),
+                ));
+            }
+
+            tree.detach(old.parent, &old_name)?;
+            tree.detach(new.parent, &new_name)?;
+            tree.attach(old.parent, old_name, new_ino)?;
+            return tree.attach(new.parent, new_name, old_ino);
+        }
+
+        let is_directory = tree.get(old_ino)?.is_directory();
+        if let Some(new_ino) = new.ino {
+            if noreplace {
+                return Err(VfsError::AlreadyExists);
+            }
+            if new_ino == old_ino {
+                return Ok(());
+            }
+            match (is_directory, tree.get(new_ino)?.is_directory()) {
+                (true, false) => return Err(VfsError::NotADirectory),
+                (false, true) => return Err(VfsError::IsADirectory),
+                (true, true) if !tree.entries(new_ino)?.is_empty() => {
+                    return Err(VfsError::NotEmpty)
+                }
+                _ => {}
+            }
+        }
+        if is_directory && tree.is_within(new.parent, old_ino) {
+            return Err(VfsError::InvalidInput(
+                "Cannot move a directory into itself".to_string(),
+            ));
+        }
+
+        if new.ino.is_some() {
+            tree.remove(new.parent, &new_name)?;
+        }
+        tree.detach(old.parent, &old_name)?;
+        tree.attach(new.parent, new_name, old_ino)
+    }
+
+    async fn mkdir(&self, path: &Path, mode: u32) -> VfsResult<()> {
+        let kind = Kind::Directory {
+            entries: BTreeMap::new(),
+            parent: ROOT_INO,
+        };
+        self.create(path, kind, libc::S_IFDIR | (mode & 0o7777))?;
+        Ok(())
+    }
+
+    async fn truncate(&self, path: &Path, size: u64) -> VfsResult<()> {
+        let (mut tree, lookup) = self.lookup(path, true)?;
+        let inode = tree.get_mut(lookup.ino.ok_or(VfsError::NotFound)?)?;
+        inode.data_mut()?.resize(size as usize, 0);
+        inode.touch();
+        Ok(())
+    }
+
+    async fn chmod(&self, path: &Path, mode: u32) -> VfsResult<()> {
+        self.update(path, true, |inode| {
+            inode.mode = (inode.mode & libc::S_IFMT) | (mode & 0o7777);
+            Ok(())
+        })
+    }
+
+    async fn getxattr(&self, path: &Path, name: &str, follow: bool) -> VfsResult<Vec<u8>> {
+        let (tree, lookup) = self.lookup(path, follow)?;
+        let inode = tree.get(lookup.ino.ok_or(VfsError::NotFound)?)?;
+        inode.xattrs.get(name).cloned().ok_or(VfsError::NoData)
+    }
+
+    async fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+        follow: bool,
+    ) -> VfsResult<()> {
+        self.update(path, follow, |inode| {
+            let exists = inode.xattrs.contains_key(name);
+            if flags & libc::XATTR_CREATE != 0 && exists {
+                return Err(VfsError::AlreadyExists);
+            }
+            if flags & libc::XATTR_REPLACE != 0 && !exists {
+                return Err(VfsError::NoData);
+            }
+
+            // Enforce the per-inode cap over all names and values, counting the
+            // new value in place of the one it replaces
+            let others: usize = inode
+                .xattrs
+                .iter()
+                .filter(|(other, _)| *other != name)
+                .map(|(other, value)| other.len() + value.len())
+                .sum();
+            if others + name.len() + value.len() > MAX_XATTR_SIZE {
+                return Err(VfsError::TooBig);
+            }
+
+            inode.xattrs.insert(name.to_string(), value.to_vec());
+            Ok(())
+        })
+    }
+
+    async fn listxattr(&self, path: &Path, follow: bool) -> VfsResult<Vec<String>> {
+        let (tree, lookup) = self.lookup(path, follow)?;
+        let inode = tree.get(lookup.ino.ok_or(VfsError::NotFound)?)?;
+        Ok(inode.xattrs.keys().cloned().collect())
+    }
+
+    async fn removexattr(&self, path: &Path, name: &str, follow: bool) -> VfsResult<()> {
+        self.update(path, follow, |inode| {
+            inode.xattrs.remove(name).map(drop).ok_or(VfsError::NoData)
+        })
+    }
+
+    async fn chown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> VfsResult<()> {
+        self.update(path, true, |inode| {
+            inode.uid = uid.unwrap_or(inode.uid);
+            inode.gid = gid.unwrap_or(inode.gid);
+            Ok(())
+        })
+    }
+
+    async fn lchown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> VfsResult<()> {
+        self.update(path, false, |inode| {
+            inode.uid = uid.unwrap_or(inode.uid);
+            inode.gid = gid.unwrap_or(inode.gid);
+            Ok(())
+        })
+    }
+
+    async fn utimes(&self, path: &Path, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.update(path, true, |inode| {
+            inode.set_times(atime, mtime);
+            Ok(())
+        })
+    }
+
+    async fn lutimes(&self, path: &Path, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.update(path, false, |inode| {
+            inode.set_times(atime, mtime);
+            Ok(())
+        })
+    }
+
+    async fn unlink(&self, path: &Path) -> VfsResult<()> {
+        let (mut tree, lookup) = self.lookup(path, false)?;
+        let ino = lookup.ino.ok_or(VfsError::NotFound)?;
+        let name = match lookup.name {
+            Some(name) if !tree.get(ino)?.is_directory() => name,
+            _ => return Err(VfsError::IsADirectory),
+        };
+        tree.remove(lookup.parent, &name)
+    }
+
+    async fn rmdir(&self, path: &Path) -> VfsResult<()> {
+        let (mut tree, lookup) = self.lookup(path, false)?;
+        let ino = lookup.ino.ok_or(VfsError::NotFound)?;
+        if !tree.get(ino)?.is_directory() {
+            return Err(VfsError::NotADirectory);
+        }
+        let name = lookup.name.ok_or_else(|| {
+            VfsError::InvalidInput("Cannot remove a directory through . or ..".to_string())
+        })?;
+        if !tree.entries(ino)?.is_empty() {
+            return Err(VfsError::NotEmpty);
+        }
+        tree.remove(lookup.parent, &name)
+    }
+
+    async fn symlink(&self, target: &Path, linkpath: &Path) -> VfsResult<()> {
+        let kind = Kind::Symlink(target.to_path_buf());
+        self.create(linkpath, kind, libc::S_IFLNK | 0o777)?;
+        Ok(())
+    }
+
+    async fn readlink(&self, path: &Path) -> VfsResult<PathBuf> {
+        let (tree, lookup) = self.lookup(path, false)?;
+        match &tree.get(lookup.ino.ok_or(VfsError::NotFound)?)?.kind {
+            Kind::Symlink(target) => Ok(target.clone()),
+            _ => Err(VfsError::InvalidInput("Not a symlink".to_string())),
+        }
+    }
+
+    async fn link(&self, oldpath: &Path, newpath: &Path) -> VfsResult<()> {
+        let (old_relative, new_relative) = (self.relative(oldpath)?, self.relative(newpath)?);
+        let mut tree = self.tree();
+        let old = tree.lookup(old_relative, false)?;
+        let ino = old.ino.ok_or(VfsError::NotFound)?;
+        if tree.get(ino)?.is_directory() {
+            // Hard links to directories are not allowed
+            return Err(VfsError::PermissionDenied);
+        }
+
+        let new = tree.lookup(new_relative, false)?;
+        let name = match (new.name, new.ino) {
+            (Some(name), None) => name,
+            _ => return Err(VfsError::AlreadyExists),
+        };
+        tree.attach(new.parent, name, ino)
+    }
+    async fn open_tmpfile(&self, dir: &Path, flags: i32, mode: u32) -> VfsResult<BoxedFileOps> {
+        let (mut tree, lookup) = self.lookup(dir, true)?;
+        let dir = lookup.ino.ok_or(VfsError::NotFound)?;
+        if !tree.get(dir)?.is_directory() {
+            return Err(VfsError::NotADirectory);
+        }
+
+        // The inode has no links, so closing its last handle frees it
+        let inode = Inode::new(
+            Kind::File(Vec::new()),
+            libc::S_IFREG | (mode & 0o7777),
+            self.uid,
+            self.gid,
+        );
+        let ino = tree.allocate(inode);
+        self.handle(&mut tree, ino, flags)
+    }
+
+    async fn link_file(&self, file: &BoxedFileOps, newpath: &Path) -> VfsResult<()> {
+        let ino = file.fstat().await?.st_ino;
+        let (mut tree, new) = self.lookup(newpath, false)?;
+        if tree.get(ino)?.is_directory() {
+            // Hard links to directories are not allowed
+            return Err(VfsError::PermissionDenied);
+        }
+        let name = match (new.name, new.ino) {
+            (Some(name), None) => name,
+            _ => return Err(VfsError::AlreadyExists),
+        };
+        tree.attach(new.parent, name, ino)
+    }
+
+    async fn add_whiteout(&self, path: &Path) -> VfsResult<()> {
+        let relative = self.relative(path)?.to_path_buf();
+        self.tree().whiteouts.insert(relative);
+        Ok(())
+    }
+
+    async fn has_whiteout(&self, path: &Path) -> VfsResult<bool> {
+        let relative = self.relative(path)?;
+        let tree = self.tree();
+        Ok(relative
+            .ancestors()
+            .any(|ancestor| tree.whiteouts.contains(ancestor)))
+    }
+
+    async fn child_whiteouts(&self, dir: &Path) -> VfsResult<HashSet<String>> {
+        let relative = self.relative(dir)?;
+        let tree = self.tree();
+        Ok(tree
+            .whiteouts
+            .iter()
+            .filter(|path| path.parent() == Some(relative))
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect())
+    }
+}
+
+/// State shared by the open files and directories of a `MemoryVfs`
+struct Handle {
+    tree: Arc<Mutex<Tree>>,
+    ino: u64,
+    flags: Mutex<i32>,
+    /// Whether the handle was closed, releasing its hold on the inode
+    closed: AtomicBool,
+}
+
+impl Handle {
+    fn tree(&self) -> MutexGuard<'_, Tree> {
+        lock(&self.tree)
+    }
+
+    fn stat(&self) -> VfsResult<libc::stat> {
+        Ok(self.tree().get(self.ino)?.stat(self.ino))
+    }
+
+    fn set_times(&self, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.tree().get_mut(self.ino)?.set_times(atime, mtime);
+        Ok(())
+    }
+
+    fn fcntl(&self, cmd: i32, arg: i64) -> VfsResult<i64> {
+        match cmd {
+            libc::F_GETFL => Ok(self.get_flags() as i64),
+            libc::F_SETFL => {
+                self.set_flags(arg as i32);
+                Ok(0)
+            }
+            _ => Err(VfsError::Other(format!(
+                "Unsupported fcntl command: {}",
+                cmd
+            ))),
+        }
+    }
+
+    fn close(&self) {
+        if self.closed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let mut tree = self.tree();
+        if let Ok(inode) = tree.get_mut(self.ino) {
+            inode.open -= 1;
+        }
+        tree.release(self.ino);
+    }
+
+    fn get_flags(&self) -> i32 {
+        *self.flags.lock().unwrap()
+    }
+
+    fn set_flags(&self, flags: i32) {
+        *self.flags.lock().unwrap() = flags;
+    }
+}
+
+/// File operations for in-memory files
+///
+/// Writes go straight to the inode, so every handle sees them right away.
+struct MemoryFileOps {
+    handle: Handle,
+    offset: Mutex<i64>,
+}
+
+#[async_trait::async_trait]
+impl FileOps for MemoryFileOps {
+    async fn read(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let tree = self.handle.tree();
+        let data = tree.get(self.handle.ino)?.data()?;
+        let mut offset = self.offset.lock().unwrap();
+
+        let start = *offset as usize;
+        if start >= data.len() {
+            return Ok(0);
+        }
+
+        let end = std::cmp::min(start + buf.len(), data.len());
+        let bytes_read = end - start;
+        buf[..bytes_read].copy_from_slice(&data[start..end]);
+        *offset += bytes_read as i64;
+
+        Ok(bytes_read)
+    }
+
+    async fn pread(&self, offset: u64, len: usize) -> VfsResult<Vec<u8>> {
+        let tree = self.handle.tree();
+        let data = tree.get(self.handle.ino)?.data()?;
+
+        let start = offset as usize;
+        if start >= data.len() {
+            return Ok(Vec::new());
+        }
+
+        let end = std::cmp::min(start.saturating_add(len), data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    async fn pwrite(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut tree = self.handle.tree();
+        tree.get_mut(self.handle.ino)?
+            .write_at(offset as usize, buf)
+    }
+
+    async fn write(&self, buf: &[u8]) -> VfsResult<usize> {
+        let mut tree = self.handle.tree();
+        let inode = tree.get_mut(self.handle.ino)?;
+        let mut offset = self.offset.lock().unwrap();
+
+        // Handle O_APPEND: always write at the end of the file
+        let start = if self.handle.get_flags() & libc::O_APPEND != 0 {
+            inode.data()?.len()
+        } else {
+            *offset as usize
+        };
+
+        let written = inode.write_at(start, buf)?;
+        *offset = (start + written) as i64;
+        Ok(written)
+    }
+
+    async fn truncate(&self, size: u64) -> VfsResult<()> {
+        let mut tree = self.handle.tree();
+        let inode = tree.get_mut(self.handle.ino)?;
+        inode.data_mut()?.resize(size as usize, 0);
+        inode.touch();
+        Ok(())
+    }
+
+    async fn set_times(&self, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.handle.set_times(atime, mtime)
+    }
+
+    async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64> {
+        let tree = self.handle.tree();
+        let len = tree.get(self.handle.ino)?.data()?.len();
+        let mut current_offset = self.offset.lock().unwrap();
+
+        let new_offset = match whence {
+            libc::SEEK_SET => offset,
+            libc::SEEK_CUR => *current_offset + offset,
+            libc::SEEK_END => len as i64 + offset,
+            _ => return Err(VfsError::Other("Invalid whence".to_string())),
+        };
+
+        if new_offset < 0 {
+            return Err(VfsError::Other("Invalid offset".to_string()));
+        }
+
+        *current_offset = new_offset;
+        Ok(new_offset)
+    }
+
+    async fn fstat(&self) -> VfsResult<libc::stat> {
+        self.handle.stat()
+    }
+
+    async fn fsync(&self) -> VfsResult<()> {
+        // Nothing to sync: the data only ever lives in memory
+        Ok(())
+    }
+
+    async fn fdatasync(&self) -> VfsResult<()> {
+        // Nothing to sync: the data only ever lives in memory
+        Ok(())
+    }
+
+    fn fcntl(&self, cmd: i32, arg: i64) -> VfsResult<i64> {
+        self.handle.fcntl(cmd, arg)
+    }
+
+    fn ioctl(&self, _request: u64, _arg: u64) -> VfsResult<i64> {
+        // Virtual file doesn't support ioctl
+        Err(VfsError::Other("ioctl not supported".to_string()))
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        // No real kernel FD for virtual files
+        None
+    }
+
+    async fn close(&self) -> VfsResult<()> {
+        self.handle.close();
+        Ok(())
+    }
+
+    fn get_flags(&self) -> i32 {
+        self.handle.get_flags()
+    }
+
+    fn set_flags(&self, flags: i32) -> VfsResult<()> {
+        self.handle.set_flags(flags);
+        Ok(())
+    }
+}
+
+/// Directory operations for in-memory directories
+struct MemoryDirectoryOps {
+    handle: Handle,
+    /// Cached directory entries
+    entries: Mutex<Option<Vec<DirEntry>>>,
+    /// Current position in the directory listing
+    position: Mutex<usize>,
+}
+
+#[async_trait::async_trait]
+impl FileOps for MemoryDirectoryOps {
+    async fn read(&self, _buf: &mut [u8]) -> VfsResult<usize> {
+        // Cannot read from a directory
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn write(&self, _buf: &[u8]) -> VfsResult<usize> {
+        // Cannot write to a directory
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn pread(&self, _offset: u64, _len: usize) -> VfsResult<Vec<u8>> {
+        // Cannot read from a directory
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn pwrite(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        // Cannot write to a directory
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64> {
+        // The directory offset is an index into the cached listing
+        let mut position = self.position.lock().unwrap();
+        let new_position = match whence {
+            libc::SEEK_SET => offset,
+            libc::SEEK_CUR => *position as i64 + offset,
+            _ => {
+                return Err(VfsError::InvalidInput(
+                    "Invalid whence for directory".to_string(),
+                ))
+            }
+        };
+
+        if new_position < 0 {
+            return Err(VfsError::InvalidInput(
+                "Negative directory offset".to_string(),
+            ));
+        }
+
+        *position = new_position as usize;
+        Ok(new_position)
+    }
+
+    async fn set_times(&self, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.handle.set_times(atime, mtime)
+    }
+
+    async fn fstat(&self) -> VfsResult<libc::stat> {
+        self.handle.stat()
+    }
+
+    async fn fsync(&self) -> VfsResult<()> {
+        // Nothing to sync for directories
+        Ok(())
+    }
+
+    async fn fdatasync(&self) -> VfsResult<()> {
+        // Nothing to sync for directories
+        Ok(())
+    }
+
+    fn fcntl(&self, cmd: i32, arg: i64) -> VfsResult<i64> {
+        self.handle.fcntl(cmd, arg)
+    }
+
+    fn ioctl(&self, _request: u64, _arg: u64) -> VfsResult<i64> {
+        // Virtual directory doesn't support ioctl
+        Err(VfsError::Other("ioctl not supported".to_string()))
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        // No real kernel FD for virtual directories
+        None
+    }
+
+    async fn close(&self) -> VfsResult<()> {
+        self.handle.close();
+        Ok(())
+    }
+
+    fn get_flags(&self) -> i32 {
+        self.handle.get_flags()
+    }
+
+    fn set_flags(&self, flags: i32) -> VfsResult<()> {
+        self.handle.set_flags(flags);
+        Ok(())
+    }
+
+    async fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        let mut cached = self.entries.lock().unwrap();
+        if let Some(entries) = cached.as_ref() {
+            return Ok(entries.clone());
+        }
+
+        let tree = self.handle.tree();
+        let ino = self.handle.ino;
+        let entries = tree.entries(ino)?;
+
+        let mut result = Vec::with_capacity(entries.len() + 2);
+        result.push(DirEntry {
+            ino,
+            d_type: libc::DT_DIR,
+            name: ".".to_string(),
+        });
+        result.push(DirEntry {
+            ino: tree.parent(ino),
+            d_type: libc::DT_DIR,
+            name: "..".to_string(),
+        });
+        for (name, &entry_ino) in entries {
+            result.push(DirEntry {
+                ino: entry_ino,
+                d_type: tree.get(entry_ino)?.d_type(),
+                name: name.clone(),
+            });
+        }
+
+        // Cache the listing so that pagination sees a stable snapshot
+        *cached = Some(result.clone());
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_vfs() -> MemoryVfs {
+        MemoryVfs::new(PathBuf::from("/scratch"))
+    }
+
+    async fn write_file(vfs: &MemoryVfs, path: &str, data: &[u8]) {
+        let file = vfs
+            .open(Path::new(path), libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        vfs.write(&file, 0, data).await.unwrap();
+        file.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_translate_path_matches_whole_components() {
+        let vfs = create_test_vfs();
+
+        assert!(vfs.translate_path(Path::new("/scratch")).is_ok());
+        assert!(vfs.translate_path(Path::new("/scratch/file")).is_ok());
+        assert!(vfs.translate_path(Path::new("/scratchfoo")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_flags() {
+        let vfs = create_test_vfs();
+        let path = Path::new("/scratch/file.txt");
+        let excl = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL;
+
+        let result = vfs.open(path, libc::O_RDONLY, 0).await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+
+        let file = vfs.open(path, excl, 0o600).await.unwrap();
+        file.write(b"hello").await.unwrap();
+        file.close().await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_mode, stat.st_size), (libc::S_IFREG | 0o600, 5));
+
+        let result = vfs.open(path, excl, 0o600).await;
+        assert!(matches!(result, Err(VfsError::AlreadyExists)));
+        let result = vfs.open(path, libc::O_RDONLY | libc::O_DIRECTORY, 0).await;
+        assert!(matches!(result, Err(VfsError::NotADirectory)));
+        let result = vfs.open(Path::new("/scratch"), libc::O_WRONLY, 0).await;
+        assert!(matches!(result, Err(VfsError::IsADirectory)));
+        let result = vfs
+            .open(Path::new("/scratch/nodir/file"), libc::O_CREAT, 0o644)
+            .await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_TRUNC, 0)
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        assert_eq!(vfs.stat(path).await.unwrap().st_size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_write_and_seek() {
+        let vfs = create_test_vfs();
+        let path = Path::new("/scratch/data.txt");
+        write_file(&vfs, "/scratch/data.txt", b"hello world").await;
+
+        let file = vfs
+            .open(path, libc::O_RDWR | libc::O_APPEND, 0)
+            .await
+            .unwrap();
+        assert_eq!(vfs.read(&file, 6, 100).await.unwrap(), b"world");
+        assert!(vfs.read(&file, 100, 10).await.unwrap().is_empty());
+
+        // Appends go to the end wherever the offset is, and pwrite fills gaps
+        file.seek(0, libc::SEEK_SET).await.unwrap();
+        file.write(b"!").await.unwrap();
+        vfs.write(&file, 14, b"?").await.unwrap();
+        assert_eq!(file.seek(0, libc::SEEK_END).await.unwrap(), 15);
+
+        // Other handles see the writes right away
+        let other = vfs.open(path, libc::O_RDONLY, 0).await.unwrap();
+        let mut buf = [0u8; 32];
+        let n = other.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"hello world!\0\0?");
+        file.close().await.unwrap();
+        other.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unlinked_file_stays_open() {
+        let vfs = create_test_vfs();
+        let path = Path::new("/scratch/tmp");
+        write_file(&vfs, "/scratch/tmp", b"data").await;
+
+        let file = vfs.open(path, libc::O_RDONLY, 0).await.unwrap();
+        vfs.unlink(path).await.unwrap();
+        assert!(matches!(vfs.stat(path).await, Err(VfsError::NotFound)));
+
+        assert_eq!(file.pread(0, 10).await.unwrap(), b"data");
+        assert_eq!(file.fstat().await.unwrap().st_nlink, 0);
+        file.close().await.unwrap();
+        assert_eq!(vfs.tree().inodes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tmpfile() {
+        let vfs = create_test_vfs();
+        let dir = Path::new("/scratch");
+
+        // Closing an unnamed file frees it
+        let file = vfs.open_tmpfile(dir, libc::O_RDWR, 0o600).await.unwrap();
+        assert_eq!(file.fstat().await.unwrap().st_nlink, 0);
+        file.close().await.unwrap();
+        assert_eq!(vfs.tree().inodes.len(), 1);
+
+        // Linking one keeps it
+        let file = vfs.open_tmpfile(dir, libc::O_RDWR, 0o600).await.unwrap();
+        file.write(b"data").await.unwrap();
+        vfs.link_file(&file, Path::new("/scratch/named"))
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        let stat = vfs.stat(Path::new("/scratch/named")).await.unwrap();
+        assert_eq!((stat.st_nlink, stat.st_size), (1, 4));
+        assert_eq!(stat.st_mode, libc::S_IFREG | 0o600);
+
+        let file = vfs.open_tmpfile(dir, libc::O_RDWR, 0o600).await.unwrap();
+        let result = vfs.link_file(&file, Path::new("/scratch/named")).await;
+        assert!(matches!(result, Err(VfsError::AlreadyExists)));
+        let result = vfs
+            .open_tmpfile(Path::new("/scratch/named"), libc::O_RDWR, 0o600)
+            .await;
+        assert!(matches!(result, Err(VfsError::NotADirectory)));
+    }
+
+    #[tokio::test]
+    async fn test_rename_flags() {
+        let vfs = create_test_vfs();
+        write_file(&vfs, "/scratch/a", b"a").await;
+        write_file(&vfs, "/scratch/b", b"bb").await;
+        let (a, b) = (Path::new("/scratch/a"), Path::new("/scratch/b"));
+
+        let result = vfs.rename(a, b, libc::RENAME_NOREPLACE).await;
+        assert!(matches!(result, Err(VfsError::AlreadyExists)));
+
+        vfs.rename(a, b, libc::RENAME_EXCHANGE).await.unwrap();
+        assert_eq!(vfs.stat(a).await.unwrap().st_size, 2);
+        assert_eq!(vfs.stat(b).await.unwrap().st_size, 1);
+
+        vfs.rename(a, b, 0).await.unwrap();
+        assert!(matches!(vfs.stat(a).await, Err(VfsError::NotFound)));
+        assert_eq!(vfs.stat(b).await.unwrap().st_size, 2);
+
+        let result = vfs.rename(b, a, libc::RENAME_EXCHANGE).await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_rename_directories() {
+        let vfs = create_test_vfs();
+        vfs.mkdir(Path::new("/scratch/a"), 0o755).await.unwrap();
+        vfs.mkdir(Path::new("/scratch/a/sub"), 0o755).await.unwrap();
+        vfs.mkdir(Path::new("/scratch/b"), 0o755).await.unwrap();
+        write_file(&vfs, "/scratch/b/file", b"").await;
+
+        let result = vfs
+            .rename(Path::new("/scratch/a"), Path::new("/scratch/a/sub/a"), 0)
+            .await;
+        assert!(matches!(result, Err(VfsError::InvalidInput(_))));
+        let result = vfs
+            .rename(Path::new("/scratch/a"), Path::new("/scratch/b"), 0)
+            .await;
+        assert!(matches!(result, Err(VfsError::NotEmpty)));
+
+        // Moving a directory moves the link of its `..` entry too
+        vfs.rename(Path::new("/scratch/a/sub"), Path::new("/scratch/b/sub"), 0)
+            .await
+            .unwrap();
+        assert_eq!(vfs.stat(Path::new("/scratch/a")).await.unwrap().st_nlink, 2);
+        assert_eq!(vfs.stat(Path::new("/scratch/b")).await.unwrap().st_nlink, 3);
+        let stat = vfs.stat(Path::new("/scratch/b/sub/..")).await.unwrap();
+        assert_eq!(
+            stat.st_ino,
+            vfs.stat(Path::new("/scratch/b")).await.unwrap().st_ino
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unlink_and_rmdir() {
+        let vfs = create_test_vfs();
+        vfs.mkdir(Path::new("/scratch/dir"), 0o755).await.unwrap();
+        write_file(&vfs, "/scratch/dir/file", b"").await;
+
+        let result = vfs.unlink(Path::new("/scratch/dir")).await;
+        assert!(matches!(result, Err(VfsError::IsADirectory)));
+        let result = vfs.rmdir(Path::new("/scratch/dir/file")).await;
+        assert!(matches!(result, Err(VfsError::NotADirectory)));
+        let result = vfs.rmdir(Path::new("/scratch/dir")).await;
+        assert!(matches!(result, Err(VfsError::NotEmpty)));
+
+        vfs.unlink(Path::new("/scratch/dir/file")).await.unwrap();
+        vfs.rmdir(Path::new("/scratch/dir")).await.unwrap();
+        let result = vfs.rmdir(Path::new("/scratch/dir")).await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+        assert_eq!(vfs.stat(Path::new("/scratch")).await.unwrap().st_nlink, 2);
+    }
+
+    #[tokio::test]
+    async fn test_readdir_entries() {
+        let vfs = create_test_vfs();
+        vfs.mkdir(Path::new("/scratch/dir"), 0o755).await.unwrap();
+        write_file(&vfs, "/scratch/file", b"").await;
+        vfs.symlink(Path::new("file"), Path::new("/scratch/link"))
+            .await
+            .unwrap();
+
+        let dir = vfs
+            .open(Path::new("/scratch"), libc::O_RDONLY | libc::O_DIRECTORY, 0)
+            .await
+            .unwrap();
+        let entries: Vec<(String, u8)> = vfs
+            .readdir(&dir)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.d_type))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (".".to_string(), libc::DT_DIR),
+                ("..".to_string(), libc::DT_DIR),
+                ("dir".to_string(), libc::DT_DIR),
+                ("file".to_string(), libc::DT_REG),
+                ("link".to_string(), libc::DT_LNK),
+            ]
+        );
+        dir.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_symlinks_and_hard_links() {
+        let vfs = create_test_vfs();
+        vfs.mkdir(Path::new("/scratch/dir"), 0o755).await.unwrap();
+        write_file(&vfs, "/scratch/dir/file", b"data").await;
+        vfs.symlink(Path::new("/dir"), Path::new("/scratch/abs"))
+            .await
+            .unwrap();
+        vfs.symlink(Path::new("loop"), Path::new("/scratch/loop"))
+            .await
+            .unwrap();
+
+        // Absolute targets are resolved from the root of the VFS
+        let stat = vfs.stat(Path::new("/scratch/abs/file")).await.unwrap();
+        assert_eq!(stat.st_size, 4);
+        let lstat = vfs.lstat(Path::new("/scratch/abs")).await.unwrap();
+        assert_eq!(lstat.st_mode & libc::S_IFMT, libc::S_IFLNK);
+        let result = vfs.stat(Path::new("/scratch/loop")).await;
+        assert!(matches!(result, Err(VfsError::SymlinkLoop)));
+
+        vfs.link(Path::new("/scratch/dir/file"), Path::new("/scratch/hard"))
+            .await
+            .unwrap();
+        let stat = vfs.stat(Path::new("/scratch/hard")).await.unwrap();
+        assert_eq!((stat.st_nlink, stat.st_size), (2, 4));
+        let result = vfs
+            .link(Path::new("/scratch/dir"), Path::new("/scratch/dir2"))
+            .await;
+        assert!(matches!(result, Err(VfsError::PermissionDenied)));
+    }
+
+    #[tokio::test]
+    async fn test_xattr_flags_and_limit() {
+        let vfs = create_test_vfs();
+        let path = Path::new("/scratch/file");
+        write_file(&vfs, "/scratch/file", b"").await;
+
+        let result = vfs
+            .setxattr(path, "user.a", b"1", libc::XATTR_REPLACE, true)
+            .await;
+        assert!(matches!(result, Err(VfsError::NoData)));
+        vfs.setxattr(path, "user.a", b"1", libc::XATTR_CREATE, true)
+            .await
+            .unwrap();
+        let result = vfs
+            .setxattr(path, "user.a", b"2", libc::XATTR_CREATE, true)
+            .await;
+        assert!(matches!(result, Err(VfsError::AlreadyExists)));
+
+        let big = vec![0u8; MAX_XATTR_SIZE];
+        let result = vfs.setxattr(path, "user.b", &big, 0, true).await;
+        assert!(matches!(result, Err(VfsError::TooBig)));
+
+        assert_eq!(vfs.getxattr(path, "user.a", true).await.unwrap(), b"1");
+        assert_eq!(vfs.listxattr(path, true).await.unwrap(), vec!["user.a"]);
+        vfs.removexattr(path, "user.a", true).await.unwrap();
+        let result = vfs.getxattr(path, "user.a", true).await;
+        assert!(matches!(result, Err(VfsError::NoData)));
+    }
+}
@@ -1,11 +1,17 @@
 pub mod bind;
 pub mod fdtable;
 pub mod file;
+pub mod idmap;
+pub mod lock;
+pub mod memory;
 pub mod mount;
+pub mod overlay;
+pub mod registry;
 #[cfg(target_os = "linux")]
 pub mod sqlite;
 
 use async_trait::async_trait;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::result::Result as StdResult;
 
@@ -15,6 +21,18 @@ pub enum VfsError {
     NotFound,
     PermissionDenied,
     AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    NotEmpty,
+    CrossDevice,
+    NoSpace,
+    ReadOnly,
+    NoData,
+    TooBig,
+    FileTooBig,
+    SymlinkLoop,
+    NameTooLong,
+    NotSupported,
     InvalidInput(String),
     IoError(std::io::Error),
     Other(String),
@@ -32,6 +50,18 @@ impl std::fmt::Display for VfsError {
             VfsError::NotFound => write!(f, "Not found"),
             VfsError::PermissionDenied => write!(f, "Permission denied"),
             VfsError::AlreadyExists => write!(f, "Already exists"),
+            VfsError::NotADirectory => write!(f, "Not a directory"),
+            VfsError::IsADirectory => write!(f, "Is a directory"),
+            VfsError::NotEmpty => write!(f, "Directory not empty"),
+            VfsError::CrossDevice => write!(f, "Cross-device link"),
+            VfsError::NoSpace => write!(f, "No space left on device"),
+            VfsError::ReadOnly => write!(f, "Read-only file system"),
+            VfsError::NoData => write!(f, "No data available"),
+            VfsError::TooBig => write!(f, "Argument list too long"),
+            VfsError::FileTooBig => write!(f, "File too large"),
+            VfsError::SymlinkLoop => write!(f, "Too many levels of symbolic links"),
+            VfsError::NameTooLong => write!(f, "File name too long"),
+            VfsError::NotSupported => write!(f, "Operation not supported"),
             VfsError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
             VfsError::IoError(err) => write!(f, "IO error: {}", err),
             VfsError::Other(msg) => write!(f, "{}", msg),
@@ -41,14 +71,97 @@ impl std::fmt::Display for VfsError {
 
 impl std::error::Error for VfsError {}
 
+impl VfsError {
+    /// Convert to a negated errno value suitable for returning from a syscall
+    pub fn to_errno(&self) -> i64 {
+        let errno = match self {
+            VfsError::NotFound => libc::ENOENT,
+            VfsError::PermissionDenied => libc::EACCES,
+            VfsError::AlreadyExists => libc::EEXIST,
+            VfsError::NotADirectory => libc::ENOTDIR,
+            VfsError::IsADirectory => libc::EISDIR,
+            VfsError::NotEmpty => libc::ENOTEMPTY,
+            VfsError::CrossDevice => libc::EXDEV,
+            VfsError::NoSpace => libc::ENOSPC,
+            VfsError::ReadOnly => libc::EROFS,
+            VfsError::NoData => libc::ENODATA,
+            VfsError::TooBig => libc::E2BIG,
+            VfsError::FileTooBig => libc::EFBIG,
+            VfsError::SymlinkLoop => libc::ELOOP,
+            VfsError::NameTooLong => libc::ENAMETOOLONG,
+            VfsError::NotSupported => libc::EOPNOTSUPP,
+            VfsError::InvalidInput(_) => libc::EINVAL,
+            VfsError::IoError(err) => err.raw_os_error().unwrap_or(libc::EIO),
+            VfsError::Other(_) => libc::EIO,
+        };
+        -errno as i64
+    }
+}
+
 pub type VfsResult<T> = StdResult<T, VfsError>;
 
+/// Check a path against the kernel's limits on path and file name length
+///
+/// Fails with `NameTooLong` if the path, with its terminating NUL, exceeds
+/// `PATH_MAX`, or if any component exceeds `NAME_MAX`.
+pub fn check_path_length(path: &Path) -> VfsResult<()> {
+    if path.as_os_str().len() >= libc::PATH_MAX as usize {
+        return Err(VfsError::NameTooLong);
+    }
+    if path
+        .components()
+        .any(|component| component.as_os_str().len() > libc::NAME_MAX as usize)
+    {
+        return Err(VfsError::NameTooLong);
+    }
+    Ok(())
+}
+
+/// A single directory entry, as returned by `Vfs::readdir()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// Inode number
+    pub ino: u64,
+    /// Entry type (`DT_REG`, `DT_DIR` or `DT_LNK`)
+    pub d_type: u8,
+    /// Entry name (without path)
+    pub name: String,
+}
+
+/// Filesystem statistics, as returned by `Vfs::statfs()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatFs {
+    /// Filesystem type magic number (`f_type`)
+    pub fs_type: i64,
+    /// Block size in bytes
+    pub block_size: u64,
+    /// Total number of blocks
+    pub blocks: u64,
+    /// Number of free blocks
+    pub blocks_free: u64,
+    /// Number of free blocks available to unprivileged users
+    pub blocks_available: u64,
+    /// Total number of inodes
+    pub files: u64,
+    /// Number of free inodes
+    pub files_free: u64,
+    /// Maximum length of a file name
+    pub name_max: u64,
+}
+
 use file::BoxedFileOps;
 
 /// Virtual file system trait.
 ///
 /// This trait provides a Linux VFS-like interface for implementing
 /// different filesystem backends.
+///
+/// The trait is object safe, and the mount table holds every backend as an
+/// `Arc<dyn Vfs>`. A virtual backend implements `open()` and the path-based
+/// operations it supports, and leaves the rest to their defaults, which fail
+/// with `VfsError::Other`; `memory::MemoryVfs` is a complete reference
+/// implementation. Backends can be registered by URL scheme in a
+/// `registry::VfsRegistry` to be mountable by URL.
 #[async_trait]
 pub trait Vfs: Send + Sync {
     /// Translate a sandbox path to the actual backend path
@@ -65,15 +178,106 @@ pub trait Vfs: Send + Sync {
         false
     }
 
+    /// Get the mapping between host IDs and the IDs reported inside this VFS
+    ///
+    /// Ownership reported by `stat()` is in terms of the inside IDs, so callers
+    /// map their own credentials through this before comparing against it.
+    fn id_map(&self) -> &idmap::IdMap {
+        &idmap::IDENTITY
+    }
+
     /// Open a file directly in the VFS (for virtual filesystems)
     ///
     /// This is only called for virtual VFS implementations. For passthrough
+    /// VFS, the path is translated and the kernel performs the open.
+    ///
+    /// Implementations must honor `O_CREAT`, `O_EXCL`, `O_TRUNC` and
+    /// `O_DIRECTORY`, returning `VfsError::AlreadyExists`,
+    /// `VfsError::NotFound`, `VfsError::NotADirectory` or
+    /// `VfsError::IsADirectory` as appropriate.
     async fn open(&self, _path: &Path, _flags: i32, _mode: u32) -> VfsResult<BoxedFileOps> {
         Err(VfsError::Other(
             "open() not supported by this VFS".to_string(),
         ))
     }
 
+    /// Read up to `len` bytes at `offset` from a file opened with `open()`
+    ///
+    /// Partial reads at end-of-file return the bytes that are available, and
+    /// reads past end-of-file return an empty buffer.
+    /// This is only called for virtual VFS implementations.
+    async fn read(&self, _file: &BoxedFileOps, _offset: u64, _len: usize) -> VfsResult<Vec<u8>> {
+        Err(VfsError::Other(
+            "read() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Write `buf` at `offset` to a file opened with `open()`
+    ///
+    /// Returns the number of bytes actually written. Writes past end-of-file
+    /// grow the file, filling any gap with zeros.
+    /// This is only called for virtual VFS implementations.
+    async fn write(&self, _file: &BoxedFileOps, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Other(
+            "write() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Truncate a file opened with `open()` to `size` bytes
+    ///
+    /// Growing the file fills the new tail with zeros, and shrinking it drops
+    /// the data past `size`. The file offset is left unchanged.
+    /// This is only called for virtual VFS implementations.
+    async fn ftruncate(&self, _file: &BoxedFileOps, _size: u64) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "ftruncate() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Allocate or deallocate `len` bytes at `offset` of a file opened with `open()`
+    ///
+    /// `mode` takes the `fallocate(2)` flags described at `FileOps::fallocate`.
+    /// Modes the VFS does not implement report `VfsError::NotSupported`.
+    /// This is only called for virtual VFS implementations.
+    async fn fallocate(
+        &self,
+        _file: &BoxedFileOps,
+        _mode: i32,
+        _offset: u64,
+        _len: u64,
+    ) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Set the access and modification times of a file opened with `open()`
+    ///
+    /// Times are in seconds since the Unix epoch, and a `None` time leaves that
+    /// timestamp unchanged.
+    /// This is only called for virtual VFS implementations.
+    async fn futimes(
+        &self,
+        _file: &BoxedFileOps,
+        _atime: Option<i64>,
+        _mtime: Option<i64>,
+    ) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "futimes() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// List the entries of a directory opened with `open()`
+    ///
+    /// Returns every entry, including `.` and `..`, in a stable order for the
+    /// lifetime of the handle. Callers paginate through the listing using the
+    /// handle's position, which `FileOps::seek()` reads and updates.
+    /// Returns `VfsError::NotADirectory` if the handle is not a directory.
+    /// This is only called for virtual VFS implementations.
+    async fn readdir(&self, _file: &BoxedFileOps) -> VfsResult<Vec<DirEntry>> {
+        Err(VfsError::Other(
+            "readdir() not supported by this VFS".to_string(),
+        ))
+    }
+
     /// Get file status directly from the VFS (for virtual filesystems)
     /// This follows symlinks.
     ///
@@ -94,6 +298,190 @@ pub trait Vfs: Send + Sync {
         ))
     }
 
+    /// Get statistics of the filesystem (for virtual filesystems)
+    ///
+    /// This is only called for virtual VFS implementations. For passthrough
+    /// VFS, the kernel reports the statistics of the backing filesystem.
+    async fn statfs(&self) -> VfsResult<StatFs> {
+        Err(VfsError::Other(
+            "statfs() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Rename a file or directory (for virtual filesystems)
+    ///
+    /// `flags` takes the `renameat2` flags: `RENAME_NOREPLACE` fails with
+    /// `VfsError::AlreadyExists` if `newpath` exists, and `RENAME_EXCHANGE`
+    /// atomically swaps both paths, which must exist. `RENAME_WHITEOUT` leaves a
+    /// whiteout at `oldpath` and only makes sense for overlays; other VFSes fail
+    /// with `VfsError::InvalidInput`.
+    /// This is only called for virtual VFS implementations.
+    async fn rename(&self, _oldpath: &Path, _newpath: &Path, _flags: u32) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "rename() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Create a directory with the given permission bits (for virtual filesystems)
+    ///
+    /// Returns `VfsError::AlreadyExists` if `path` exists and `VfsError::NotFound`
+    /// if a parent component is missing.
+    /// This is only called for virtual VFS implementations.
+    async fn mkdir(&self, _path: &Path, _mode: u32) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "mkdir() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Truncate a file to `size` bytes (for virtual filesystems)
+    ///
+    /// Symlinks are followed. Growing the file fills the new tail with zeros,
+    /// and shrinking it drops the data past `size`. Returns
+    /// `VfsError::IsADirectory` if `path` is a directory.
+    /// This is only called for virtual VFS implementations.
+    async fn truncate(&self, _path: &Path, _size: u64) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "truncate() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Change the permission bits of a file (for virtual filesystems)
+    ///
+    /// Symlinks are followed. Only the permission bits of `mode` are applied;
+    /// the file type is preserved, and the new bits are visible to subsequent
+    /// `stat()` and `lstat()` calls.
+    /// This is only called for virtual VFS implementations.
+    async fn chmod(&self, _path: &Path, _mode: u32) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "chmod() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Get the value of an extended attribute (for virtual filesystems)
+    ///
+    /// `follow` selects whether a symlink at `path` is followed, as for
+    /// `getxattr()` versus `lgetxattr()`. Returns `VfsError::NoData` if the
+    /// attribute is not set.
+    /// This is only called for virtual VFS implementations.
+    async fn getxattr(&self, _path: &Path, _name: &str, _follow: bool) -> VfsResult<Vec<u8>> {
+        Err(VfsError::Other(
+            "getxattr() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Set an extended attribute (for virtual filesystems)
+    ///
+    /// `flags` takes `XATTR_CREATE`, which fails with `VfsError::AlreadyExists`
+    /// if the attribute is set, and `XATTR_REPLACE`, which fails with
+    /// `VfsError::NoData` if it is not. Returns `VfsError::TooBig` if the
+    /// attributes of the file would exceed the size the VFS allows.
+    /// This is only called for virtual VFS implementations.
+    async fn setxattr(
+        &self,
+        _path: &Path,
+        _name: &str,
+        _value: &[u8],
+        _flags: i32,
+        _follow: bool,
+    ) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "setxattr() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// List the names of the extended attributes of a file (for virtual filesystems)
+    ///
+    /// This is only called for virtual VFS implementations.
+    async fn listxattr(&self, _path: &Path, _follow: bool) -> VfsResult<Vec<String>> {
+        Err(VfsError::Other(
+            "listxattr() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Remove an extended attribute (for virtual filesystems)
+    ///
+    /// Returns `VfsError::NoData` if the attribute is not set.
+    /// This is only called for virtual VFS implementations.
+    async fn removexattr(&self, _path: &Path, _name: &str, _follow: bool) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "removexattr() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Change the ownership of a file, following symlinks (for virtual filesystems)
+    ///
+    /// A `None` uid or gid leaves that field unchanged. The new ownership is
+    /// visible to subsequent `stat()` and `lstat()` calls.
+    /// This is only called for virtual VFS implementations.
+    async fn chown(&self, _path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "chown() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Change the ownership of a file without following symlinks (for virtual filesystems)
+    ///
+    /// Like `chown()`, but a symlink at `path` is changed itself.
+    /// This is only called for virtual VFS implementations.
+    async fn lchown(&self, _path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "lchown() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Set the access and modification times of a file, following symlinks (for virtual filesystems)
+    ///
+    /// Times are in whole seconds since the Unix epoch, which is the resolution
+    /// the VFS stores, and a `None` time leaves that timestamp unchanged. The new
+    /// times are visible to subsequent `stat()` calls, with zero nanoseconds.
+    /// This is only called for virtual VFS implementations.
+    async fn utimes(
+        &self,
+        _path: &Path,
+        _atime: Option<i64>,
+        _mtime: Option<i64>,
+    ) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "utimes() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Set the access and modification times of a file without following symlinks (for virtual filesystems)
+    ///
+    /// Like `utimes()`, but a symlink at `path` is changed itself.
+    /// This is only called for virtual VFS implementations.
+    async fn lutimes(
+        &self,
+        _path: &Path,
+        _atime: Option<i64>,
+        _mtime: Option<i64>,
+    ) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "lutimes() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Remove a non-directory entry (for virtual filesystems)
+    ///
+    /// Returns `VfsError::IsADirectory` if `path` is a directory.
+    /// This is only called for virtual VFS implementations.
+    async fn unlink(&self, _path: &Path) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "unlink() not supported by this VFS".to_string(),
+        ))
+    }
+
+    /// Remove an empty directory (for virtual filesystems)
+    ///
+    /// Returns `VfsError::NotADirectory` if `path` is not a directory and
+    /// `VfsError::NotEmpty` if it still has entries.
+    /// This is only called for virtual VFS implementations.
+    async fn rmdir(&self, _path: &Path) -> VfsResult<()> {
+        Err(VfsError::Other(
+            "rmdir() not supported by this VFS".to_string(),
+        ))
+    }
+
     /// Create a symbolic link (for virtual filesystems)
     ///
     /// This is only called for virtual VFS implementations.
@@ -121,6 +509,45 @@ pub trait Vfs: Send + Sync {
             "link() not supported by this VFS".to_string(),
         ))
     }
+
+    /// Create an unnamed regular file in directory `dir` (for virtual filesystems)
+    ///
+    /// This implements `O_TMPFILE`. The file has no links, and is freed when its
+    /// last handle is closed unless `link_file()` names it first. Fails with
+    /// `VfsError::NotSupported` by default, which is what the kernel reports for
+    /// filesystems without `O_TMPFILE`.
+    async fn open_tmpfile(&self, _dir: &Path, _flags: i32, _mode: u32) -> VfsResult<BoxedFileOps> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Link a file opened from this VFS at `newpath` (for virtual filesystems)
+    ///
+    /// Unlike `link()`, this names the file behind an open handle, which may
+    /// have no links left, like one from `open_tmpfile()`. Fails with
+    /// `VfsError::NotSupported` by default.
+    async fn link_file(&self, _file: &BoxedFileOps, _newpath: &Path) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Record a whiteout at `path` (for the upper layer of an overlay)
+    ///
+    /// A whiteout hides the lower layer's entry at `path` and everything below
+    /// it. Whiteouts are kept apart from the files, so they take no names in
+    /// the directory tree. Fails with `VfsError::NotSupported` by default, and
+    /// such a VFS can't be the upper layer of an overlay.
+    async fn add_whiteout(&self, _path: &Path) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Check whether `path` or one of its parents has a whiteout
+    async fn has_whiteout(&self, _path: &Path) -> VfsResult<bool> {
+        Ok(false)
+    }
+
+    /// Get the names of the entries of directory `dir` that have a whiteout
+    async fn child_whiteouts(&self, _dir: &Path) -> VfsResult<HashSet<String>> {
+        Ok(HashSet::new())
+    }
 }
 
 /// A boxed VFS trait object for dynamic dispatch
@@ -1,11 +1,26 @@
 use super::Vfs;
+use agentfs_sdk::{glob::Glob, AgentFSOptions};
+use lru::LruCache;
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::Reverse,
-    path::{Path, PathBuf},
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    num::NonZeroUsize,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
+/// Default number of resolved paths a mount table keeps
+pub const DEFAULT_RESOLVE_CACHE_SIZE: usize = 4096;
+
+/// A path resolved to a VFS, translated path and the mount's read-only flag
+type Resolution = (Arc<dyn Vfs>, PathBuf, bool);
+
+/// Recently resolved paths, including those no mount owns, each with the
+/// generation of the mount table it was resolved in
+type ResolveCache = Mutex<LruCache<PathBuf, (u64, Option<Resolution>)>>;
+
 /// A mount point entry in the mount table
 #[derive(Clone)]
 pub struct MountPoint {
@@ -13,6 +28,45 @@ pub struct MountPoint {
     pub sandbox_path: PathBuf,
     /// The VFS implementation for this mount point
     pub vfs: Arc<dyn Vfs>,
+    /// Whether modifications through this mount are rejected with `EROFS`
+    pub read_only: bool,
+    /// Modes and owner of the files created through this mount
+    pub defaults: MountDefaults,
+}
+
+/// Modes and owner for the files created in a virtual mount
+///
+/// The modes replace those programs create files and directories with, and the
+/// process umask still applies on top of them, so that everything written to
+/// the mount starts from the same permissions. The owner is reported for files
+/// that don't record one of their own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MountDefaults {
+    /// Mode of created files, such as `0o644`
+    pub file_mode: Option<u32>,
+    /// Mode of created directories, such as `0o755`
+    pub dir_mode: Option<u32>,
+    /// User ID that owns the files
+    pub uid: Option<u32>,
+    /// Group ID that owns the files
+    pub gid: Option<u32>,
+}
+
+impl MountDefaults {
+    /// Fill the fields left unset with those of `other`
+    pub fn or(self, other: MountDefaults) -> Self {
+        Self {
+            file_mode: self.file_mode.or(other.file_mode),
+            dir_mode: self.dir_mode.or(other.dir_mode),
+            uid: self.uid.or(other.uid),
+            gid: self.gid.or(other.gid),
+        }
+    }
+
+    /// Whether no field is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 /// Mount table manages multiple VFS mount points
@@ -20,15 +74,48 @@ pub struct MountPoint {
 /// This is similar to Linux's VFS mount table - it tracks multiple
 /// mounted filesystems and resolves paths to the appropriate VFS
 /// implementation using longest-prefix matching.
-#[derive(Clone)]
+///
+/// The table also holds a list of exclusion patterns. Exclusions win over
+/// mounts: a path matching one passes through to the host even if it lies
+/// under a mount point.
+///
+/// Every intercepted syscall resolves its paths, so resolutions are cached.
+/// Each change to the table bumps its generation, which makes the resolutions
+/// cached before it stale. Most paths a program uses, like those under `/usr`
+/// and `/lib`, are under no mount at all, so those are told apart by their
+/// first component before the cache is consulted.
 pub struct MountTable {
     mounts: Vec<MountPoint>,
+    /// First components of the mount points, or `None` if a mount point has
+    /// none, like a mount at `/`
+    roots: Option<HashSet<OsString>>,
+    exclusions: Vec<String>,
+    /// The exclusion patterns, compiled to match a path or its ancestors
+    excluded: Vec<Glob>,
+    /// Number of changes made to the table
+    generation: u64,
+    /// `None` when caching is disabled
+    cache: Option<ResolveCache>,
 }
 
 impl MountTable {
     /// Create a new empty mount table
     pub fn new() -> Self {
-        Self { mounts: Vec::new() }
+        Self {
+            mounts: Vec::new(),
+            roots: Some(HashSet::new()),
+            exclusions: Vec::new(),
+            excluded: Vec::new(),
+            generation: 0,
+            cache: new_cache(DEFAULT_RESOLVE_CACHE_SIZE),
+        }
+    }
+
+    /// Set the number of resolved paths to keep, dropping those kept so far
+    ///
+    /// A size of 0 disables caching.
+    pub fn set_resolve_cache_size(&mut self, size: usize) {
+        self.cache = new_cache(size);
     }
 
     /// Add a new mount point
@@ -36,32 +123,165 @@ impl MountTable {
     /// Mount points are automatically sorted by path depth (longest first)
     /// to ensure longest-prefix matching works correctly.
     pub fn add_mount(&mut self, sandbox_path: PathBuf, vfs: Arc<dyn Vfs>) {
-        self.mounts.push(MountPoint { sandbox_path, vfs });
+        self.push(MountPoint {
+            sandbox_path,
+            vfs,
+            read_only: false,
+            defaults: MountDefaults::default(),
+        });
+    }
+
+    /// Add a new read-only mount point
+    ///
+    /// Syscall handlers reject any modification of paths under this mount
+    /// with `EROFS` before the VFS is consulted.
+    pub fn add_read_only_mount(&mut self, sandbox_path: PathBuf, vfs: Arc<dyn Vfs>) {
+        self.push(MountPoint {
+            sandbox_path,
+            vfs,
+            read_only: true,
+            defaults: MountDefaults::default(),
+        });
+    }
+
+    /// Set the modes and owner of the files created through the mount at
+    /// `sandbox_path`
+    ///
+    /// Only the modes are applied by the table, in `create_mode()`: the owner
+    /// is reported by the VFS itself, so it has to be set when the VFS is
+    /// created.
+    pub fn set_defaults(&mut self, sandbox_path: &Path, defaults: MountDefaults) {
+        if let Some(mount) = self
+            .mounts
+            .iter_mut()
+            .find(|mount| mount.sandbox_path == sandbox_path)
+        {
+            mount.defaults = defaults;
+        }
+    }
+
+    fn push(&mut self, mount: MountPoint) {
+        self.generation += 1;
+        self.mounts.push(mount);
         // Sort by path depth (deepest first) to implement longest-prefix matching
         self.mounts
             .sort_by_key(|m| Reverse(m.sandbox_path.components().count()));
+        self.roots = self
+            .mounts
+            .iter()
+            .map(|m| first_component(&m.sandbox_path).map(OsStr::to_os_string))
+            .collect();
     }
 
-    /// Resolve a path to a VFS and translated path
+    /// Add a glob pattern for paths that pass through to the host
+    ///
+    /// Patterns are absolute paths matched component by component: `*` and
+    /// `?` match within a single component, `[...]` matches a character class
+    /// and `**` matches any number of components. A path is excluded when the
+    /// pattern matches the path itself or one of its ancestors, so
+    /// `/home/user/.ssh` excludes everything below that directory as well.
+    pub fn add_exclusion(&mut self, pattern: impl Into<String>) {
+        let pattern = pattern.into();
+        self.generation += 1;
+        // A trailing `**` makes the pattern match everything below a match too
+        self.excluded.push(Glob::new(&format!("{}/**", pattern)));
+        self.exclusions.push(pattern);
+    }
+
+    /// Check whether a path matches one of the exclusion patterns
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let Some(path) = path.to_str() else {
+            return false;
+        };
+        self.excluded.iter().any(|glob| glob.matches_path(path))
+    }
+
+    /// Find the mount point that owns a path
+    ///
+    /// Paths are matched component by component, so `/data` owns `/data` and
+    /// `/data/file` but not `/database`. Mounts are kept deepest first, so the
+    /// first match is the one with the longest prefix. Excluded paths are not
+    /// owned by any mount.
+    pub fn find(&self, path: &Path) -> Option<&MountPoint> {
+        if self.is_excluded(path) {
+            return None;
+        }
+        self.mounts
+            .iter()
+            .find(|mount| path.starts_with(&mount.sandbox_path))
+    }
+
+    /// Resolve a path to a VFS, translated path and the mount's read-only flag
     ///
     /// This implements longest-prefix matching - if multiple mount points
-    /// could match, the one with the longest matching prefix is chosen.
+    /// could match, the one with the longest matching prefix is chosen, and
+    /// its VFS translates the remainder of the path.
     ///
-    /// Returns None if no mount point matches the path.
-    pub fn resolve(&self, path: &Path) -> Option<(Arc<dyn Vfs>, PathBuf)> {
-        for mount in &self.mounts {
-            // Try to translate the path using this mount's VFS
-            if let Ok(translated) = mount.vfs.translate_path(path) {
-                return Some((mount.vfs.clone(), translated));
+    /// Returns None if no mount point matches the path, or if the path matches
+    /// an exclusion pattern, in which case it passes through to the host.
+    pub fn resolve(&self, path: &Path) -> Option<(Arc<dyn Vfs>, PathBuf, bool)> {
+        if !self.may_own(path) {
+            return None;
+        }
+        let Some(cache) = &self.cache else {
+            return self.resolve_uncached(path);
+        };
+        if let Some((generation, resolution)) = cache.lock().unwrap().get(path) {
+            if *generation == self.generation {
+                return resolution.clone();
             }
         }
-        None
+
+        let resolution = self.resolve_uncached(path);
+        cache
+            .lock()
+            .unwrap()
+            .put(path.to_path_buf(), (self.generation, resolution.clone()));
+        resolution
+    }
+
+    /// The mode to create `path` with, before the umask applies: the default
+    /// file or directory mode of the mount that owns it, or `mode` if it has
+    /// none
+    pub fn create_mode(&self, path: &Path, mode: u32, dir: bool) -> u32 {
+        let Some(mount) = self.find(path) else {
+            return mode;
+        };
+        let default = if dir {
+            mount.defaults.dir_mode
+        } else {
+            mount.defaults.file_mode
+        };
+        default.unwrap_or(mode)
+    }
+
+    /// Check whether a mount could own a path, comparing only its first
+    /// component with those of the mount points
+    ///
+    /// Paths this rejects are not cached, so they don't evict the resolutions
+    /// of paths under mounts.
+    pub fn may_own(&self, path: &Path) -> bool {
+        match &self.roots {
+            Some(roots) => first_component(path).is_some_and(|root| roots.contains(root)),
+            None => true,
+        }
+    }
+
+    fn resolve_uncached(&self, path: &Path) -> Option<Resolution> {
+        let mount = self.find(path)?;
+        let translated = mount.vfs.translate_path(path).ok()?;
+        Some((mount.vfs.clone(), translated, mount.read_only))
     }
 
     /// Get all mount points
     pub fn mounts(&self) -> &[MountPoint] {
         &self.mounts
     }
+
+    /// Get all exclusion patterns
+    pub fn exclusions(&self) -> &[String] {
+        &self.exclusions
+    }
 }
 
 impl Default for MountTable {
@@ -70,10 +290,42 @@ impl Default for MountTable {
     }
 }
 
+impl Clone for MountTable {
+    /// Clone the mounts and exclusions, with an empty cache of the same size
+    fn clone(&self) -> Self {
+        let size = self
+            .cache
+            .as_ref()
+            .map_or(0, |cache| cache.lock().unwrap().cap().get());
+        Self {
+            mounts: self.mounts.clone(),
+            roots: self.roots.clone(),
+            exclusions: self.exclusions.clone(),
+            excluded: self.excluded.clone(),
+            generation: self.generation,
+            cache: new_cache(size),
+        }
+    }
+}
+
+fn new_cache(size: usize) -> Option<ResolveCache> {
+    NonZeroUsize::new(size).map(|size| Mutex::new(LruCache::new(size)))
+}
+
+/// The component after the root of an absolute path, like `usr` for `/usr/lib`
+fn first_component(path: &Path) -> Option<&OsStr> {
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::RootDir), Some(Component::Normal(name))) => Some(name),
+        _ => None,
+    }
+}
+
 impl std::fmt::Debug for MountTable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MountTable")
             .field("mount_count", &self.mounts.len())
+            .field("exclusions", &self.exclusions)
             .finish()
     }
 }
@@ -101,6 +353,25 @@ pub enum MountType {
         /// Path to the SQLite database file.
         src: PathBuf,
     },
+    /// Union of two SQLite-backed virtual filesystems.
+    ///
+    /// Lookups see the upper filesystem first and then the lower one, which
+    /// is never modified: changes are copied up to the upper filesystem and
+    /// deletions are recorded there as whiteouts.
+    Overlay {
+        /// Path to the SQLite database of the read-only lower layer.
+        lower: PathBuf,
+        /// Path to the SQLite database of the writable upper layer.
+        upper: PathBuf,
+    },
+    /// Virtual filesystem opened by URL through a `VfsRegistry`.
+    ///
+    /// The scheme of the URL picks the backend, such as `mem://` for an
+    /// in-memory filesystem that is discarded when the sandbox exits.
+    Vfs {
+        /// URL of the filesystem, `SCHEME://LOCATION`.
+        url: String,
+    },
 }
 
 /// Configuration for a mount point (used for CLI parsing).
@@ -109,24 +380,67 @@ pub enum MountType {
 /// `type=bind,src=/host/path,dst=/sandbox/path`
 ///
 /// Aliases are supported: `source` for `src`, `target` for `dst`.
+///
+/// A mount is made read-only with a bare `readonly` (or `ro`) option, or with
+/// `readonly=true`: `type=sqlite,src=agent.db,dst=/agent,readonly`
+///
+/// An in-memory filesystem can be mounted with the `mem:/sandbox/path`
+/// shorthand for `type=vfs,src=mem://,dst=/sandbox/path`, and an AgentFS
+/// filesystem with `ID_OR_PATH:/sandbox/path`, where `ID_OR_PATH` is an agent
+/// ID or the path of its database.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MountConfig {
     /// Type of mount.
     pub mount_type: MountType,
     /// Destination path in the sandbox (must be absolute).
     pub dst: PathBuf,
+    /// Reject modifications through the mount with `EROFS`.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Modes and owner of the files created in a virtual mount.
+    #[serde(default)]
+    pub defaults: MountDefaults,
 }
 
 impl std::str::FromStr for MountConfig {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use std::collections::HashMap;
+        // Shorthand for an in-memory filesystem
+        if let Some(dst) = s.strip_prefix("mem:") {
+            return Ok(MountConfig {
+                mount_type: MountType::Vfs {
+                    url: "mem://".to_string(),
+                },
+                dst: absolute_dst(dst)?,
+                read_only: false,
+                defaults: MountDefaults::default(),
+            });
+        }
+
+        // Shorthand for an AgentFS filesystem, by agent ID or database path
+        if !s.contains('=') {
+            if let Some((id_or_path, dst)) = s.split_once(':') {
+                return Ok(MountConfig {
+                    mount_type: MountType::Sqlite {
+                        src: agent_db_path(id_or_path)?,
+                    },
+                    dst: absolute_dst(dst)?,
+                    read_only: false,
+                    defaults: MountDefaults::default(),
+                });
+            }
+        }
 
         // Parse key=value pairs separated by commas
         let mut options: HashMap<String, String> = HashMap::new();
 
         for part in s.split(',') {
+            // Flag options without a value
+            let part = match part {
+                "readonly" | "ro" => "readonly=true",
+                _ => part,
+            };
             let kv: Vec<&str> = part.splitn(2, '=').collect();
             if kv.len() != 2 {
                 return Err(format!(
@@ -143,6 +457,25 @@ impl std::str::FromStr for MountConfig {
             }
         }
 
+        // Accept 'ro' as an alias for 'readonly'
+        if let Some(value) = options.remove("ro") {
+            if options.insert("readonly".to_string(), value).is_some() {
+                return Err("Duplicate key 'readonly' in mount specification.".to_string());
+            }
+        }
+        let read_only = match options.get("readonly").map(String::as_str) {
+            None | Some("false") => false,
+            Some("true") => true,
+            Some(value) => {
+                return Err(format!(
+                    "Invalid value '{}' for 'readonly'. Expected true or false.",
+                    value
+                ))
+            }
+        };
+
+        let defaults = parse_defaults(&options)?;
+
         // Check for required 'type' field
         let mount_type = options.get("type").ok_or_else(|| {
             "Missing required field 'type'. Example: type=bind,src=/host/path,dst=/sandbox/path."
@@ -151,6 +484,14 @@ impl std::str::FromStr for MountConfig {
 
         match mount_type.as_str() {
             "bind" => {
+                if !defaults.is_empty() {
+                    return Err(
+                        "Bind mounts take modes and ownership from the host: 'mode', 'dir_mode', \
+                         'uid' and 'gid' are not supported."
+                            .to_string(),
+                    );
+                }
+
                 // Get src (or source as alias)
                 let src_str = options.get("src")
                     .or_else(|| options.get("source"))
@@ -179,6 +520,8 @@ impl std::str::FromStr for MountConfig {
                 Ok(MountConfig {
                     mount_type: MountType::Bind { src },
                     dst,
+                    read_only,
+                    defaults,
                 })
             }
             "sqlite" => {
@@ -208,16 +551,162 @@ impl std::str::FromStr for MountConfig {
                 Ok(MountConfig {
                     mount_type: MountType::Sqlite { src },
                     dst,
+                    read_only,
+                    defaults,
+                })
+            }
+            "overlay" => {
+                let lower = options.get("lower").ok_or_else(|| {
+                    "Overlay mount requires 'lower' field. Example: type=overlay,lower=base.db,upper=delta.db,dst=/agent.".to_string()
+                })?;
+                let upper = options.get("upper").ok_or_else(|| {
+                    "Overlay mount requires 'upper' field. Example: type=overlay,lower=base.db,upper=delta.db,dst=/agent.".to_string()
+                })?;
+
+                // Get dst (or target as alias)
+                let dst_str = options.get("dst")
+                    .or_else(|| options.get("target"))
+                    .ok_or_else(|| {
+                        "Overlay mount requires 'dst' field. Example: type=overlay,lower=base.db,upper=delta.db,dst=/agent.".to_string()
+                    })?;
+
+                // Validate destination is absolute
+                let dst = PathBuf::from(dst_str);
+                if !dst.is_absolute() {
+                    return Err(format!("Destination path '{}' must be absolute.", dst_str));
+                }
+
+                Ok(MountConfig {
+                    mount_type: MountType::Overlay {
+                        lower: PathBuf::from(lower),
+                        upper: PathBuf::from(upper),
+                    },
+                    dst,
+                    read_only,
+                    defaults,
+                })
+            }
+            "vfs" => {
+                // Get src (or source as alias)
+                let url = options
+                    .get("src")
+                    .or_else(|| options.get("source"))
+                    .ok_or_else(|| {
+                        "VFS mount requires 'src' field. Example: type=vfs,src=mem://,dst=/scratch."
+                            .to_string()
+                    })?;
+                crate::vfs::registry::parse_url(url).map_err(|e| e.to_string())?;
+                if defaults.uid.is_some() || defaults.gid.is_some() {
+                    return Err(
+                        "'uid' and 'gid' are only supported by sqlite and overlay mounts."
+                            .to_string(),
+                    );
+                }
+
+                // Get dst (or target as alias)
+                let dst_str = options
+                    .get("dst")
+                    .or_else(|| options.get("target"))
+                    .ok_or_else(|| {
+                        "VFS mount requires 'dst' field. Example: type=vfs,src=mem://,dst=/scratch."
+                            .to_string()
+                    })?;
+
+                // Validate destination is absolute
+                let dst = PathBuf::from(dst_str);
+                if !dst.is_absolute() {
+                    return Err(format!("Destination path '{}' must be absolute.", dst_str));
+                }
+
+                Ok(MountConfig {
+                    mount_type: MountType::Vfs { url: url.clone() },
+                    dst,
+                    read_only,
+                    defaults,
                 })
             }
             _ => Err(format!(
-                "Unsupported mount type '{}'. Supported types: bind, sqlite.",
+                "Unsupported mount type '{}'. Supported types: bind, sqlite, overlay, vfs.",
                 mount_type
             )),
         }
     }
 }
 
+/// Parse the destination of a mount, which must be absolute.
+fn absolute_dst(dst: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(dst);
+    if !path.is_absolute() {
+        return Err(format!("Destination path '{}' must be absolute.", dst));
+    }
+    Ok(path)
+}
+
+/// Find the database of `id_or_path`: `.agentfs/ID.db` for an existing agent,
+/// or else the path itself, which is created if it doesn't exist.
+fn agent_db_path(id_or_path: &str) -> Result<PathBuf, String> {
+    if id_or_path.is_empty() {
+        return Err("Missing agent ID or database path before ':'.".to_string());
+    }
+    match AgentFSOptions::resolve(id_or_path).and_then(|options| options.db_path()) {
+        Ok(path) => Ok(PathBuf::from(path)),
+        // A bare name can only be an agent, which must exist
+        Err(e) if AgentFSOptions::validate_agent_id(id_or_path) => Err(e.to_string()),
+        Err(_) if Path::new(id_or_path).is_dir() => Err(format!(
+            "'{}' is a directory. Bind mount it with type=bind,src={},dst=....",
+            id_or_path, id_or_path
+        )),
+        Err(_) => Ok(PathBuf::from(id_or_path)),
+    }
+}
+
+/// Parse a file mode in octal, such as `0644`
+pub fn parse_mode(s: &str) -> Result<u32, String> {
+    match u32::from_str_radix(s, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("Invalid mode '{}'. Expected octal, e.g. 0644.", s)),
+    }
+}
+
+/// Parse the `mode`, `dir_mode`, `uid` and `gid` options of a mount specification
+fn parse_defaults(options: &HashMap<String, String>) -> Result<MountDefaults, String> {
+    let id = |key: &str| {
+        options
+            .get(key)
+            .map(|value| {
+                value.parse::<u32>().map_err(|_| {
+                    format!("Invalid value '{}' for '{}'. Expected an ID.", value, key)
+                })
+            })
+            .transpose()
+    };
+    Ok(MountDefaults {
+        file_mode: options.get("mode").map(|s| parse_mode(s)).transpose()?,
+        dir_mode: options.get("dir_mode").map(|s| parse_mode(s)).transpose()?,
+        uid: id("uid")?,
+        gid: id("gid")?,
+    })
+}
+
+/// Check that no two mounts share a mount point
+///
+/// Mounts may be nested, in which case the deepest one owns the paths under
+/// it, but two filesystems at the same path have to be layered explicitly
+/// with an overlay mount.
+pub fn check_mount_points(configs: &[MountConfig]) -> Result<(), String> {
+    for (i, config) in configs.iter().enumerate() {
+        if configs[..i].iter().any(|other| other.dst == config.dst) {
+            return Err(format!(
+                "More than one filesystem is mounted at {}. To layer two filesystems, \
+                 use type=overlay,lower=LOWER.db,upper=UPPER.db,dst={}.",
+                config.dst.display(),
+                config.dst.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -248,14 +737,14 @@ mod tests {
         let result = table.resolve(Path::new("/agent/special/file"));
         assert!(result.is_some());
 
-        let (_, translated) = result.unwrap();
+        let (_, translated, _) = result.unwrap();
         assert_eq!(translated, PathBuf::from("/tmp/special/file"));
 
         // Path /agent/normal should match the less specific mount
         let result = table.resolve(Path::new("/agent/normal"));
         assert!(result.is_some());
 
-        let (_, translated) = result.unwrap();
+        let (_, translated, _) = result.unwrap();
         assert_eq!(translated, PathBuf::from("/tmp/agent/normal"));
     }
 
@@ -275,6 +764,216 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_mount_table_skips_unmounted_roots() {
+        let mut table = MountTable::new();
+        for path in ["/agent/workspace", "/data"] {
+            table.add_mount(
+                PathBuf::from(path),
+                Arc::new(BindVfs::new(PathBuf::from("/tmp"), PathBuf::from(path))),
+            );
+        }
+
+        assert!(table.may_own(Path::new("/agent/other")));
+        assert!(table.may_own(Path::new("/data/file")));
+        assert!(!table.may_own(Path::new("/usr/lib/libc.so.6")));
+        assert!(!table.may_own(Path::new("/")));
+        assert!(!table.may_own(Path::new("relative/data")));
+
+        // Paths under no mount root are neither resolved nor cached
+        assert!(table.resolve(Path::new("/usr/lib/libc.so.6")).is_none());
+        assert!(table.resolve(Path::new("/agent/other")).is_none());
+        assert!(table.resolve(Path::new("/agent/workspace/file")).is_some());
+        let cache = table.cache.as_ref().unwrap().lock().unwrap();
+        assert_eq!(cache.len(), 2);
+        drop(cache);
+
+        // A mount at the root may own any path
+        table.add_mount(
+            PathBuf::from("/"),
+            Arc::new(BindVfs::new(PathBuf::from("/tmp"), PathBuf::from("/"))),
+        );
+        assert!(table.may_own(Path::new("/usr/lib/libc.so.6")));
+        assert!(table.may_own(Path::new("/")));
+    }
+
+    #[test]
+    fn test_mount_table_exact_mount_point() {
+        let mut table = MountTable::new();
+
+        table.add_mount(
+            PathBuf::from("/data"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/data"),
+                PathBuf::from("/data"),
+            )),
+        );
+        table.add_mount(
+            PathBuf::from("/data/cache"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/cache"),
+                PathBuf::from("/data/cache"),
+            )),
+        );
+
+        let (_, translated, _) = table.resolve(Path::new("/data/cache")).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/cache"));
+
+        let (_, translated, _) = table.resolve(Path::new("/data/cache/")).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/cache"));
+
+        let (_, translated, _) = table.resolve(Path::new("/data")).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/data"));
+    }
+
+    #[test]
+    fn test_mount_table_matches_whole_components() {
+        let mut table = MountTable::new();
+
+        table.add_mount(
+            PathBuf::from("/data"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/data"),
+                PathBuf::from("/data"),
+            )),
+        );
+        table.add_mount(
+            PathBuf::from("/data/cache"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/cache"),
+                PathBuf::from("/data/cache"),
+            )),
+        );
+
+        // /data/cachefile is under /data, not /data/cache
+        let (_, translated, _) = table.resolve(Path::new("/data/cachefile")).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/data/cachefile"));
+
+        assert!(table.resolve(Path::new("/database")).is_none());
+    }
+
+    #[test]
+    fn test_mount_table_read_only() {
+        let mut table = MountTable::new();
+
+        table.add_mount(
+            PathBuf::from("/agent"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/agent"),
+                PathBuf::from("/agent"),
+            )),
+        );
+        table.add_read_only_mount(
+            PathBuf::from("/agent/ro"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/ro"),
+                PathBuf::from("/agent/ro"),
+            )),
+        );
+
+        let (_, _, read_only) = table.resolve(Path::new("/agent/ro/file")).unwrap();
+        assert!(read_only);
+
+        let (_, _, read_only) = table.resolve(Path::new("/agent/file")).unwrap();
+        assert!(!read_only);
+    }
+
+    #[test]
+    fn test_mount_table_exclusions() {
+        let mut table = MountTable::new();
+
+        table.add_mount(
+            PathBuf::from("/home/user"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/home"),
+                PathBuf::from("/home/user"),
+            )),
+        );
+        table.add_exclusion("/home/user/.ssh");
+        table.add_exclusion("/home/*/.cache/**/*.lock");
+
+        assert!(table.resolve(Path::new("/home/user/.ssh")).is_none());
+        assert!(table.resolve(Path::new("/home/user/.ssh/id_rsa")).is_none());
+        assert!(table
+            .resolve(Path::new("/home/user/.cache/pip/x/wheel.lock"))
+            .is_none());
+        assert!(table
+            .resolve(Path::new("/home/user/.cache/a.lock"))
+            .is_none());
+
+        let (_, translated, _) = table.resolve(Path::new("/home/user/.sshrc")).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/home/.sshrc"));
+        assert!(table
+            .resolve(Path::new("/home/user/.cache/a.txt"))
+            .is_some());
+    }
+
+    #[test]
+    fn test_mount_table_cache_invalidated_by_changes() {
+        let mut table = MountTable::new();
+        table.add_mount(
+            PathBuf::from("/data"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/data"),
+                PathBuf::from("/data"),
+            )),
+        );
+
+        let path = Path::new("/data/cache/file");
+        let (_, translated, _) = table.resolve(path).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/data/cache/file"));
+        assert!(table.resolve(Path::new("/other")).is_none());
+
+        // Cached resolutions, including misses, don't outlive a change
+        table.add_mount(
+            PathBuf::from("/data/cache"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/cache"),
+                PathBuf::from("/data/cache"),
+            )),
+        );
+        table.add_mount(
+            PathBuf::from("/other"),
+            Arc::new(BindVfs::new(
+                PathBuf::from("/tmp/other"),
+                PathBuf::from("/other"),
+            )),
+        );
+        let (_, translated, _) = table.resolve(path).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/cache/file"));
+        assert!(table.resolve(Path::new("/other")).is_some());
+
+        let mut clone = table.clone();
+        clone.add_exclusion("/data/cache/file");
+        assert!(clone.resolve(path).is_none());
+        assert!(table.resolve(path).is_some());
+
+        clone.set_resolve_cache_size(0);
+        assert!(clone.resolve(path).is_none());
+        let (_, translated, _) = clone.resolve(Path::new("/data/x")).unwrap();
+        assert_eq!(translated, PathBuf::from("/tmp/data/x"));
+    }
+
+    #[test]
+    fn test_exclusion_patterns() {
+        let excluded = |pattern: &str, path: &str| {
+            let mut table = MountTable::new();
+            table.add_exclusion(pattern);
+            table.is_excluded(Path::new(path))
+        };
+        assert!(excluded("/a/*.txt", "/a/file.txt"));
+        assert!(!excluded("/a/*.txt", "/a/b/file.md"));
+        assert!(excluded("/a/?", "/a/b/c"));
+        assert!(!excluded("/a/?", "/a/bc"));
+        assert!(excluded("/a/[bc]d", "/a/cd"));
+        assert!(!excluded("/a/[!bc]d", "/a/cd"));
+        assert!(excluded("/a/[0-9]", "/a/7"));
+        assert!(excluded("/**/.git", "/x/y/.git/config"));
+        assert!(excluded("/a/**", "/a"));
+        assert!(!excluded("/a/b", "/a"));
+        assert!(excluded("/", "/anything"));
+    }
+
     #[test]
     fn test_parse_bind_mount() {
         // Use /tmp which should exist on all systems
@@ -287,7 +986,7 @@ mod tests {
                 assert_eq!(src, std::fs::canonicalize("/tmp").unwrap());
                 assert_eq!(config.dst, PathBuf::from("/data"));
             }
-            MountType::Sqlite { .. } => panic!("Expected Bind mount, got Sqlite"),
+            other => panic!("Expected Bind mount, got {:?}", other),
         }
     }
 
@@ -303,8 +1002,167 @@ mod tests {
                 assert_eq!(src, std::fs::canonicalize("/tmp").unwrap());
                 assert_eq!(config.dst, PathBuf::from("/data"));
             }
-            MountType::Sqlite { .. } => panic!("Expected Bind mount, got Sqlite"),
+            other => panic!("Expected Bind mount, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_overlay_mount() {
+        let config: MountConfig = "type=overlay,lower=base.db,upper=delta.db,dst=/agent"
+            .parse()
+            .unwrap();
+        match config.mount_type {
+            MountType::Overlay { lower, upper } => {
+                assert_eq!(lower, PathBuf::from("base.db"));
+                assert_eq!(upper, PathBuf::from("delta.db"));
+                assert_eq!(config.dst, PathBuf::from("/agent"));
+            }
+            other => panic!("Expected Overlay mount, got {:?}", other),
+        }
+
+        let config: Result<MountConfig, _> = "type=overlay,lower=base.db,dst=/agent".parse();
+        assert!(config.unwrap_err().contains("requires 'upper' field"));
+    }
+
+    #[test]
+    fn test_parse_vfs_mount() {
+        let config: MountConfig = "type=vfs,src=mem://,dst=/scratch".parse().unwrap();
+        match config.mount_type {
+            MountType::Vfs { url } => {
+                assert_eq!(url, "mem://");
+                assert_eq!(config.dst, PathBuf::from("/scratch"));
+            }
+            other => panic!("Expected Vfs mount, got {:?}", other),
+        }
+
+        let config: MountConfig = "mem:/workspace".parse().unwrap();
+        match config.mount_type {
+            MountType::Vfs { url } => {
+                assert_eq!(url, "mem://");
+                assert_eq!(config.dst, PathBuf::from("/workspace"));
+            }
+            other => panic!("Expected Vfs mount, got {:?}", other),
+        }
+        let config: Result<MountConfig, _> = "mem:workspace".parse();
+        assert!(config.unwrap_err().contains("must be absolute"));
+
+        let config: Result<MountConfig, _> = "type=vfs,src=agent.db,dst=/agent".parse();
+        assert!(config
+            .unwrap_err()
+            .contains("Expected format: SCHEME://LOCATION"));
+    }
+
+    #[test]
+    fn test_parse_agent_mount() {
+        let config: MountConfig = "/nonexistent/cache.db:/cache".parse().unwrap();
+        match config.mount_type {
+            MountType::Sqlite { src } => {
+                assert_eq!(src, PathBuf::from("/nonexistent/cache.db"));
+                assert_eq!(config.dst, PathBuf::from("/cache"));
+            }
+            other => panic!("Expected Sqlite mount, got {:?}", other),
         }
+        assert!(!config.read_only);
+
+        // A bare name must be an existing agent
+        let config: Result<MountConfig, _> = "no-such-agent-id:/workspace".parse();
+        assert!(config.is_err());
+        let config: Result<MountConfig, _> = "/tmp:/workspace".parse();
+        assert!(config.unwrap_err().contains("type=bind"));
+        let config: Result<MountConfig, _> = ":/workspace".parse();
+        assert!(config.is_err());
+        let config: Result<MountConfig, _> = "agent.db:workspace".parse();
+        assert!(config.unwrap_err().contains("must be absolute"));
+    }
+
+    #[test]
+    fn test_check_mount_points() {
+        let configs: Vec<MountConfig> = ["agent.db:/workspace", "mem:/cache", "mem:/workspace/tmp"]
+            .iter()
+            .map(|spec| spec.parse().unwrap())
+            .collect();
+        assert!(check_mount_points(&configs).is_ok());
+
+        let configs: Vec<MountConfig> = ["agent.db:/workspace", "mem:/workspace"]
+            .iter()
+            .map(|spec| spec.parse().unwrap())
+            .collect();
+        let err = check_mount_points(&configs).unwrap_err();
+        assert!(err.contains("/workspace"));
+        assert!(err.contains("type=overlay"));
+    }
+
+    #[test]
+    fn test_parse_read_only() {
+        let config: MountConfig = "type=sqlite,src=agent.db,dst=/agent".parse().unwrap();
+        assert!(!config.read_only);
+
+        for spec in [
+            "type=sqlite,src=agent.db,dst=/agent,readonly",
+            "type=sqlite,src=agent.db,dst=/agent,ro",
+            "type=sqlite,src=agent.db,dst=/agent,readonly=true",
+            "ro=true,type=bind,src=/tmp,dst=/data",
+        ] {
+            let config: MountConfig = spec.parse().unwrap();
+            assert!(config.read_only, "{spec}");
+        }
+
+        let config: MountConfig = "type=sqlite,src=agent.db,dst=/agent,readonly=false"
+            .parse()
+            .unwrap();
+        assert!(!config.read_only);
+    }
+
+    #[test]
+    fn test_parse_read_only_invalid() {
+        let config: Result<MountConfig, _> = "type=sqlite,src=a.db,dst=/a,readonly=yes".parse();
+        assert!(config.unwrap_err().contains("Invalid value 'yes'"));
+
+        let config: Result<MountConfig, _> = "type=sqlite,src=a.db,dst=/a,ro,readonly".parse();
+        assert!(config.unwrap_err().contains("Duplicate key 'readonly'"));
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let config: MountConfig = "type=sqlite,src=a.db,dst=/a,mode=0640,dir_mode=750,uid=1000"
+            .parse()
+            .unwrap();
+        let defaults = MountDefaults {
+            file_mode: Some(0o640),
+            dir_mode: Some(0o750),
+            uid: Some(1000),
+            gid: None,
+        };
+        assert_eq!(config.defaults, defaults);
+
+        let config: Result<MountConfig, _> = "type=sqlite,src=a.db,dst=/a,mode=0999".parse();
+        assert!(config.unwrap_err().contains("Invalid mode '0999'"));
+        let config: Result<MountConfig, _> = "type=bind,src=/tmp,dst=/data,mode=0644".parse();
+        assert!(config.unwrap_err().contains("not supported"));
+        let config: Result<MountConfig, _> = "type=vfs,src=mem://,dst=/scratch,gid=1".parse();
+        assert!(config.unwrap_err().contains("only supported"));
+    }
+
+    #[test]
+    fn test_create_mode() {
+        let mut table = MountTable::new();
+        for path in ["/agent", "/scratch"] {
+            table.add_mount(
+                PathBuf::from(path),
+                Arc::new(BindVfs::new(PathBuf::from("/tmp"), PathBuf::from(path))),
+            );
+        }
+        let defaults = MountDefaults {
+            file_mode: Some(0o640),
+            ..Default::default()
+        };
+        table.set_defaults(Path::new("/agent"), defaults);
+
+        let mode = |path: &str, mode, dir| table.create_mode(Path::new(path), mode, dir);
+        assert_eq!(mode("/agent/f", 0o666, false), 0o640);
+        assert_eq!(mode("/agent/d", 0o777, true), 0o777);
+        assert_eq!(mode("/scratch/f", 0o666, false), 0o666);
+        assert_eq!(mode("/tmp/f", 0o600, false), 0o600);
     }
 
     #[test]
@@ -0,0 +1,1083 @@
+use super::file::{BoxedFileOps, FileOps};
+use super::idmap::IdMap;
+use super::{DirEntry, StatFs, Vfs, VfsError, VfsResult};
+use std::os::unix::io::RawFd;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Size of the chunks in which file data is copied up
+const COPY_UP_CHUNK_SIZE: usize = 128 * 1024;
+
+/// Maximum number of symlinks followed when resolving a path
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// The layer a path was found in
+enum Layer {
+    Upper(libc::stat),
+    Lower(libc::stat),
+}
+
+impl Layer {
+    fn stat(&self) -> &libc::stat {
+        match self {
+            Layer::Upper(stat) | Layer::Lower(stat) => stat,
+        }
+    }
+}
+
+/// Whether a stat describes an entry of the given `S_IFMT` type
+fn is_type(stat: &libc::stat, kind: u32) -> bool {
+    stat.st_mode & libc::S_IFMT == kind
+}
+
+/// Turn a missing path into `None`
+fn found<T>(result: VfsResult<T>) -> VfsResult<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(VfsError::NotFound | VfsError::NotADirectory) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// A union of a read-only lower VFS and a writable upper VFS
+///
+/// Lookups consult the upper layer first and fall back to the lower layer,
+/// and directory listings merge both. The lower layer is never modified:
+/// the first change to a lower-layer entry copies it, along with any missing
+/// parent directories, to the upper layer ("copy-up"), and removing a
+/// lower-layer entry leaves a whiteout in the upper layer that hides it.
+/// Whiteouts are recorded with `Vfs::add_whiteout()` rather than as entries,
+/// so every name stays free for the guest.
+///
+/// Both layers must be virtual VFSes mounted at the same sandbox path, and the
+/// upper layer must support whiteouts, as `SqliteVfs` and `MemoryVfs` do.
+#[derive(Clone)]
+pub struct OverlayVfs {
+    /// The read-only base layer
+    lower: Arc<dyn Vfs>,
+    /// The writable layer capturing changes
+    upper: Arc<dyn Vfs>,
+    /// The virtual path as seen by the sandboxed process
+    mount_point: PathBuf,
+}
+
+impl OverlayVfs {
+    /// Create a new overlay VFS
+    ///
+    /// # Arguments
+    /// * `lower` - The read-only base layer
+    /// * `upper` - The writable layer that receives all changes
+    /// * `mount_point` - The virtual path seen by the guest, shared by both layers
+    pub fn new(lower: Arc<dyn Vfs>, upper: Arc<dyn Vfs>, mount_point: PathBuf) -> Self {
+        Self {
+            lower,
+            upper,
+            mount_point,
+        }
+    }
+
+    /// Get the mount point path
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    /// The paths from the first component below the mount point down to `path`
+    fn prefixes(&self, path: &Path) -> VfsResult<Vec<PathBuf>> {
+        let relative = path
+            .strip_prefix(&self.mount_point)
+            .map_err(|_| VfsError::NotFound)?;
+
+        let mut current = self.mount_point.clone();
+        let mut prefixes = Vec::new();
+        for component in relative.components() {
+            match component {
+                Component::Normal(name) => current.push(name),
+                Component::CurDir => continue,
+                _ => return Err(VfsError::InvalidInput("Invalid path".to_string())),
+            }
+            prefixes.push(current.clone());
+        }
+        Ok(prefixes)
+    }
+
+    /// Whether the lower layer is visible at `path`
+    ///
+    /// The lower layer is hidden below a whiteout and below an upper-layer
+    /// entry that is not a directory.
+    async fn lower_visible(&self, path: &Path) -> VfsResult<bool> {
+        if self.upper.has_whiteout(path).await? {
+            return Ok(false);
+        }
+        let prefixes = self.prefixes(path)?;
+        for prefix in prefixes.iter().take(prefixes.len().saturating_sub(1)) {
+            if let Some(stat) = found(self.upper.lstat(prefix).await)? {
+                if !is_type(&stat, libc::S_IFDIR) {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Look up `path` without following a symlink at its final component
+    async fn lookup(&self, path: &Path) -> VfsResult<Layer> {
+        if let Some(stat) = found(self.upper.lstat(path).await)? {
+            return Ok(Layer::Upper(stat));
+        }
+        if self.lower_visible(path).await? {
+            if let Some(stat) = found(self.lower.lstat(path).await)? {
+                return Ok(Layer::Lower(stat));
+            }
+        }
+        Err(VfsError::NotFound)
+    }
+
+    /// Whether the lower layer has a visible entry at `path`
+    async fn has_lower(&self, path: &Path) -> VfsResult<bool> {
+        Ok(self.lower_visible(path).await? && found(self.lower.lstat(path).await)?.is_some())
+    }
+
+    /// Follow symlinks at the final component of `path`
+    ///
+    /// Absolute link targets are resolved from the mount point, as the layers
+    /// do. A dangling link resolves to its target so that `O_CREAT` can
+    /// create it.
+    async fn follow(&self, path: &Path) -> VfsResult<PathBuf> {
+        let mut current = path.to_path_buf();
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            let layer = match self.lookup(&current).await {
+                Ok(layer) => layer,
+                Err(VfsError::NotFound) => return Ok(current),
+                Err(e) => return Err(e),
+            };
+            if !is_type(layer.stat(), libc::S_IFLNK) {
+                return Ok(current);
+            }
+
+            let target = self.readlink(&current).await?;
+            current = match target.strip_prefix("/") {
+                Ok(relative) => self.mount_point.join(relative),
+                Err(_) => current.parent().unwrap_or(&self.mount_point).join(target),
+            };
+        }
+
+        Err(VfsError::SymlinkLoop)
+    }
+
+    /// Copy `path` and any missing parent directories to the upper layer
+    ///
+    /// Returns the status of the upper-layer entry. Entries already in the
+    /// upper layer are left untouched.
+    async fn copy_up(&self, path: &Path) -> VfsResult<libc::stat> {
+        let prefixes = self.prefixes(path)?;
+        for (i, prefix) in prefixes.iter().enumerate() {
+            let stat = match self.lookup(prefix).await? {
+                Layer::Upper(stat) => stat,
+                Layer::Lower(stat) => {
+                    self.copy_up_entry(prefix, &stat).await?;
+                    stat
+                }
+            };
+            if i + 1 < prefixes.len() && !is_type(&stat, libc::S_IFDIR) {
+                return Err(VfsError::NotADirectory);
+            }
+        }
+        self.upper.lstat(path).await
+    }
+
+    /// Copy a single lower-layer entry, whose parent is in the upper layer
+    async fn copy_up_entry(&self, path: &Path, stat: &libc::stat) -> VfsResult<()> {
+        let mode = stat.st_mode & 0o7777;
+        if is_type(stat, libc::S_IFDIR) {
+            self.upper.mkdir(path, mode).await?;
+        } else if is_type(stat, libc::S_IFLNK) {
+            let target = self.lower.readlink(path).await?;
+            self.upper.symlink(&target, path).await?;
+        } else {
+            let src = self.lower.open(path, libc::O_RDONLY, 0).await?;
+            let dst = self
+                .upper
+                .open(path, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, mode)
+                .await?;
+            let result = copy_data(&src, &dst).await;
+            src.close().await?;
+            dst.close().await?;
+            result?;
+        }
+
+        for name in self.lower.listxattr(path, false).await? {
+            let value = self.lower.getxattr(path, &name, false).await?;
+            self.upper.setxattr(path, &name, &value, 0, false).await?;
+        }
+
+        // Set the times last, as writing the data updates them
+        self.upper
+            .lutimes(path, Some(stat.st_atime), Some(stat.st_mtime))
+            .await
+    }
+
+    /// Whether the merged listing of a directory is empty
+    async fn is_empty_dir(&self, path: &Path) -> VfsResult<bool> {
+        let dir = self
+            .open(path, libc::O_RDONLY | libc::O_DIRECTORY, 0)
+            .await?;
+        let entries = dir.readdir().await;
+        dir.close().await?;
+        Ok(entries?.iter().all(|e| e.name == "." || e.name == ".."))
+    }
+
+    /// Prepare the upper layer for creating a new entry at `path`
+    ///
+    /// Fails if the entry exists in the merged view, and copies up the parent
+    /// directory.
+    async fn prepare_create(&self, path: &Path) -> VfsResult<()> {
+        match self.lookup(path).await {
+            Ok(_) => return Err(VfsError::AlreadyExists),
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let parent = path.parent().ok_or(VfsError::AlreadyExists)?;
+        let stat = self.copy_up(parent).await?;
+        if !is_type(&stat, libc::S_IFDIR) {
+            return Err(VfsError::NotADirectory);
+        }
+        Ok(())
+    }
+
+    /// Open the merged view of a directory
+    async fn open_dir(&self, path: &Path, layer: &Layer, flags: i32) -> VfsResult<BoxedFileOps> {
+        let upper = match layer {
+            Layer::Upper(_) => Some(self.upper.open(path, libc::O_RDONLY, 0).await?),
+            Layer::Lower(_) => None,
+        };
+        let lower = match found(self.lower.lstat(path).await)? {
+            Some(stat) if is_type(&stat, libc::S_IFDIR) && self.lower_visible(path).await? => {
+                Some(self.lower.open(path, libc::O_RDONLY, 0).await?)
+            }
+            _ => None,
+        };
+
+        Ok(Arc::new(OverlayDirectoryOps {
+            overlay: self.clone(),
+            path: path.to_path_buf(),
+            upper,
+            lower,
+            flags: Mutex::new(flags),
+            entries: Mutex::new(None),
+            position: Mutex::new(0),
+        }))
+    }
+}
+
+/// Copy the contents of one open file to another
+async fn copy_data(src: &BoxedFileOps, dst: &BoxedFileOps) -> VfsResult<()> {
+    let mut offset = 0;
+    loop {
+        let chunk = src.pread(offset, COPY_UP_CHUNK_SIZE).await?;
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        dst.pwrite(offset, &chunk).await?;
+        offset += chunk.len() as u64;
+    }
+}
+
+#[async_trait::async_trait]
+impl Vfs for OverlayVfs {
+    fn translate_path(&self, path: &Path) -> VfsResult<PathBuf> {
+        self.upper.translate_path(path)
+    }
+
+    fn is_virtual(&self) -> bool {
+        true
+    }
+
+    fn id_map(&self) -> &IdMap {
+        // Ownership changes are stored in the upper layer
+        self.upper.id_map()
+    }
+
+    async fn open(&self, path: &Path, flags: i32, mode: u32) -> VfsResult<BoxedFileOps> {
+        let path = self.follow(path).await?;
+        let writes = flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0;
+
+        let layer = match self.lookup(&path).await {
+            Ok(layer) => layer,
+            Err(VfsError::NotFound) if flags & libc::O_CREAT != 0 => {
+                if flags & libc::O_DIRECTORY != 0 {
+                    return Err(VfsError::InvalidInput(
+                        "O_CREAT with O_DIRECTORY".to_string(),
+                    ));
+                }
+                self.prepare_create(&path).await?;
+                return self.upper.open(&path, flags, mode).await;
+            }
+            Err(e) => return Err(e),
+        };
+
+        if flags & libc::O_CREAT != 0 && flags & libc::O_EXCL != 0 {
+            return Err(VfsError::AlreadyExists);
+        }
+        if is_type(layer.stat(), libc::S_IFDIR) {
+            if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                return Err(VfsError::IsADirectory);
+            }
+            return self.open_dir(&path, &layer, flags).await;
+        }
+        if flags & libc::O_DIRECTORY != 0 {
+            return Err(VfsError::NotADirectory);
+        }
+
+        match layer {
+            Layer::Upper(_) => self.upper.open(&path, flags, mode).await,
+            Layer::Lower(_) if writes => {
+                self.copy_up(&path).await?;
+                self.upper.open(&path, flags, mode).await
+            }
+            Layer::Lower(_) => Ok(Arc::new(LowerFileOps {
+                inner: self.lower.open(&path, flags, mode).await?,
+                overlay: self.clone(),
+                path,
+            })),
+        }
+    }
+
+    async fn read(&self, file: &BoxedFileOps, offset: u64, len: usize) -> VfsResult<Vec<u8>> {
+        file.pread(offset, len).await
+    }
+
+    async fn write(&self, file: &BoxedFileOps, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        file.pwrite(offset, buf).await
+    }
+
+    async fn ftruncate(&self, file: &BoxedFileOps, size: u64) -> VfsResult<()> {
+        file.truncate(size).await
+    }
+
+    async fn fallocate(
+        &self,
+        file: &BoxedFileOps,
+        mode: i32,
+        offset: u64,
+        len: u64,
+    ) -> VfsResult<()> {
+        file.fallocate(mode, offset, len).await
+    }
+
+    async fn futimes(
+        &self,
+        file: &BoxedFileOps,
+        atime: Option<i64>,
+        mtime: Option<i64>,
+    ) -> VfsResult<()> {
+        file.set_times(atime, mtime).await
+    }
+
+    async fn readdir(&self, file: &BoxedFileOps) -> VfsResult<Vec<DirEntry>> {
+        file.readdir().await
+    }
+
+    async fn stat(&self, path: &Path) -> VfsResult<libc::stat> {
+        let path = self.follow(path).await?;
+        self.lstat(&path).await
+    }
+
+    async fn lstat(&self, path: &Path) -> VfsResult<libc::stat> {
+        Ok(*self.lookup(path).await?.stat())
+    }
+
+    async fn statfs(&self) -> VfsResult<StatFs> {
+        // New data goes to the upper layer, so its space is what's left
+        self.upper.statfs().await
+    }
+
+    async fn rename(&self, oldpath: &Path, newpath: &Path, flags: u32) -> VfsResult<()> {
+        // The upper layer knows nothing of whiteouts, so the flag stops here
+        let whiteout = flags & libc::RENAME_WHITEOUT != 0;
+        if whiteout && flags & libc::RENAME_EXCHANGE != 0 {
+            return Err(VfsError::InvalidInput(format!(
+                "Unsupported rename flags: {:#x}",
+                flags
+            )));
+        }
+        let flags = flags & !libc::RENAME_WHITEOUT;
+
+        let old_stat = *self.lookup(oldpath).await?.stat();
+        let new_stat = match self.lookup(newpath).await {
+            Ok(layer) => Some(*layer.stat()),
+            Err(VfsError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+
+        // Moving a directory would leave its lower-layer entries behind
+        let old_is_dir = is_type(&old_stat, libc::S_IFDIR);
+        if old_is_dir && self.has_lower(oldpath).await? {
+            return Err(VfsError::CrossDevice);
+        }
+
+        if flags & libc::RENAME_EXCHANGE != 0 {
+            let new_stat = new_stat.ok_or(VfsError::NotFound)?;
+            if is_type(&new_stat, libc::S_IFDIR) && self.has_lower(newpath).await? {
+                return Err(VfsError::CrossDevice);
+            }
+            self.copy_up(oldpath).await?;
+            self.copy_up(newpath).await?;
+            return self.upper.rename(oldpath, newpath, flags).await;
+        }
+
+        if let Some(new_stat) = new_stat {
+            if flags & libc::RENAME_NOREPLACE != 0 {
+                return Err(VfsError::AlreadyExists);
+            }
+            if oldpath == newpath {
+                return Ok(());
+            }
+            match (old_is_dir, is_type(&new_stat, libc::S_IFDIR)) {
+                (false, true) => return Err(VfsError::IsADirectory),
+                (true, false) => return Err(VfsError::NotADirectory),
+                (true, true) if !self.is_empty_dir(newpath).await? => {
+                    return Err(VfsError::NotEmpty)
+                }
+                _ => {}
+            }
+        }
+
+        let old_has_lower = self.has_lower(oldpath).await?;
+        let new_has_lower = self.has_lower(newpath).await?;
+
+        self.copy_up(oldpath).await?;
+        let parent = newpath.parent().ok_or(VfsError::NotFound)?;
+        if !is_type(&self.copy_up(parent).await?, libc::S_IFDIR) {
+            return Err(VfsError::NotADirectory);
+        }
+        // A directory moved over a lower-layer directory must not show its entries
+        if old_is_dir && new_has_lower {
+            self.upper.add_whiteout(newpath).await?;
+        }
+
+        self.upper.rename(oldpath, newpath, flags).await?;
+
+        if old_has_lower || whiteout {
+            self.upper.add_whiteout(oldpath).await?;
+        }
+        Ok(())
+    }
+
+    async fn mkdir(&self, path: &Path, mode: u32) -> VfsResult<()> {
+        self.prepare_create(path).await?;
+        self.upper.mkdir(path, mode).await
+    }
+
+    async fn truncate(&self, path: &Path, size: u64) -> VfsResult<()> {
+        let path = self.follow(path).await?;
+        if is_type(self.lookup(&path).await?.stat(), libc::S_IFDIR) {
+            return Err(VfsError::IsADirectory);
+        }
+        self.copy_up(&path).await?;
+        self.upper.truncate(&path, size).await
+    }
+
+    async fn chmod(&self, path: &Path, mode: u32) -> VfsResult<()> {
+        let path = self.follow(path).await?;
+        self.copy_up(&path).await?;
+        self.upper.chmod(&path, mode).await
+    }
+
+    async fn getxattr(&self, path: &Path, name: &str, follow: bool) -> VfsResult<Vec<u8>> {
+        let path = if follow {
+            self.follow(path).await?
+        } else {
+            path.to_path_buf()
+        };
+        match self.lookup(&path).await? {
+            Layer::Upper(_) => self.upper.getxattr(&path, name, false).await,
+            Layer::Lower(_) => self.lower.getxattr(&path, name, false).await,
+        }
+    }
+
+    async fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+        follow: bool,
+    ) -> VfsResult<()> {
+        let path = if follow {
+            self.follow(path).await?
+        } else {
+            path.to_path_buf()
+        };
+        self.copy_up(&path).await?;
+        self.upper.setxattr(&path, name, value, flags, false).await
+    }
+
+    async fn listxattr(&self, path: &Path, follow: bool) -> VfsResult<Vec<String>> {
+        let path = if follow {
+            self.follow(path).await?
+        } else {
+            path.to_path_buf()
+        };
+        match self.lookup(&path).await? {
+            Layer::Upper(_) => self.upper.listxattr(&path, false).await,
+            Layer::Lower(_) => self.lower.listxattr(&path, false).await,
+        }
+    }
+
+    async fn removexattr(&self, path: &Path, name: &str, follow: bool) -> VfsResult<()> {
+        let path = if follow {
+            self.follow(path).await?
+        } else {
+            path.to_path_buf()
+        };
+        self.copy_up(&path).await?;
+        self.upper.removexattr(&path, name, false).await
+    }
+
+    async fn chown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> VfsResult<()> {
+        let path = self.follow(path).await?;
+        self.copy_up(&path).await?;
+        self.upper.lchown(&path, uid, gid).await
+    }
+
+    async fn lchown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> VfsResult<()> {
+        self.copy_up(path).await?;
+        self.upper.lchown(path, uid, gid).await
+    }
+
+    async fn utimes(&self, path: &Path, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        let path = self.follow(path).await?;
+        self.copy_up(&path).await?;
+        self.upper.lutimes(&path, atime, mtime).await
+    }
+
+    async fn lutimes(&self, path: &Path, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.copy_up(path).await?;
+        self.upper.lutimes(path, atime, mtime).await
+    }
+
+    async fn unlink(&self, path: &Path) -> VfsResult<()> {
+        let layer = self.lookup(path).await?;
+        if is_type(layer.stat(), libc::S_IFDIR) {
+            return Err(VfsError::IsADirectory);
+        }
+
+        let has_lower = self.has_lower(path).await?;
+        if let Layer::Upper(_) = layer {
+            self.upper.unlink(path).await?;
+        }
+        if has_lower {
+            self.upper.add_whiteout(path).await?;
+        }
+        Ok(())
+    }
+
+    async fn rmdir(&self, path: &Path) -> VfsResult<()> {
+        let layer = self.lookup(path).await?;
+        if !is_type(layer.stat(), libc::S_IFDIR) {
+            return Err(VfsError::NotADirectory);
+        }
+        if !self.is_empty_dir(path).await? {
+            return Err(VfsError::NotEmpty);
+        }
+
+        let has_lower = self.has_lower(path).await?;
+        if let Layer::Upper(_) = layer {
+            self.upper.rmdir(path).await?;
+        }
+        if has_lower {
+            self.upper.add_whiteout(path).await?;
+        }
+        Ok(())
+    }
+
+    async fn symlink(&self, target: &Path, linkpath: &Path) -> VfsResult<()> {
+        self.prepare_create(linkpath).await?;
+        self.upper.symlink(target, linkpath).await
+    }
+
+    async fn readlink(&self, path: &Path) -> VfsResult<PathBuf> {
+        match self.lookup(path).await? {
+            Layer::Upper(_) => self.upper.readlink(path).await,
+            Layer::Lower(_) => self.lower.readlink(path).await,
+        }
+    }
+
+    async fn link(&self, oldpath: &Path, newpath: &Path) -> VfsResult<()> {
+        if is_type(self.lookup(oldpath).await?.stat(), libc::S_IFDIR) {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.copy_up(oldpath).await?;
+        self.prepare_create(newpath).await?;
+        self.upper.link(oldpath, newpath).await
+    }
+}
+
+/// A read-only handle on a file that is only in the lower layer
+///
+/// Reads go to the lower layer. Changing the file's times copies it up first,
+/// so that the lower layer is never modified.
+struct LowerFileOps {
+    inner: BoxedFileOps,
+    overlay: OverlayVfs,
+    path: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl FileOps for LowerFileOps {
+    async fn read(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        self.inner.read(buf).await
+    }
+
+    async fn write(&self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::ReadOnly)
+    }
+
+    async fn pread(&self, offset: u64, len: usize) -> VfsResult<Vec<u8>> {
+        self.inner.pread(offset, len).await
+    }
+
+    async fn pwrite(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::ReadOnly)
+    }
+
+    async fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    async fn set_times(&self, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.overlay.copy_up(&self.path).await?;
+        self.overlay.upper.lutimes(&self.path, atime, mtime).await
+    }
+
+    async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64> {
+        self.inner.seek(offset, whence).await
+    }
+
+    async fn fstat(&self) -> VfsResult<libc::stat> {
+        self.inner.fstat().await
+    }
+
+    async fn fsync(&self) -> VfsResult<()> {
+        // Nothing was written through this handle
+        Ok(())
+    }
+
+    async fn fdatasync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn fcntl(&self, cmd: i32, arg: i64) -> VfsResult<i64> {
+        self.inner.fcntl(cmd, arg)
+    }
+
+    fn ioctl(&self, request: u64, arg: u64) -> VfsResult<i64> {
+        self.inner.ioctl(request, arg)
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        self.inner.as_raw_fd()
+    }
+
+    async fn close(&self) -> VfsResult<()> {
+        self.inner.close().await
+    }
+
+    fn get_flags(&self) -> i32 {
+        self.inner.get_flags()
+    }
+
+    fn set_flags(&self, flags: i32) -> VfsResult<()> {
+        self.inner.set_flags(flags)
+    }
+}
+
+/// Directory operations on the merged view of an overlay directory
+struct OverlayDirectoryOps {
+    overlay: OverlayVfs,
+    path: PathBuf,
+    /// The directory in the upper layer, if it exists there
+    upper: Option<BoxedFileOps>,
+    /// The directory in the lower layer, if it exists there and is visible
+    lower: Option<BoxedFileOps>,
+    flags: Mutex<i32>,
+    /// Cached merged directory entries
+    entries: Mutex<Option<Vec<DirEntry>>>,
+    /// Current position in the directory listing
+    position: Mutex<usize>,
+}
+
+impl OverlayDirectoryOps {
+    /// The handle whose metadata the directory reports
+    fn primary(&self) -> &BoxedFileOps {
+        self.upper
+            .as_ref()
+            .or(self.lower.as_ref())
+            .expect("overlay directory without a layer")
+    }
+}
+
+#[async_trait::async_trait]
+impl FileOps for OverlayDirectoryOps {
+    async fn read(&self, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn write(&self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn pread(&self, _offset: u64, _len: usize) -> VfsResult<Vec<u8>> {
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn pwrite(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn set_times(&self, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        self.overlay.copy_up(&self.path).await?;
+        self.overlay.upper.lutimes(&self.path, atime, mtime).await
+    }
+
+    async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64> {
+        // The directory offset is an index into the cached listing
+        let mut position = self.position.lock().unwrap();
+        let new_position = match whence {
+            libc::SEEK_SET => offset,
+            libc::SEEK_CUR => *position as i64 + offset,
+            _ => {
+                return Err(VfsError::InvalidInput(
+                    "Invalid whence for directory".to_string(),
+                ))
+            }
+        };
+
+        if new_position < 0 {
+            return Err(VfsError::InvalidInput(
+                "Negative directory offset".to_string(),
+            ));
+        }
+
+        *position = new_position as usize;
+        Ok(new_position)
+    }
+
+    async fn fstat(&self) -> VfsResult<libc::stat> {
+        self.primary().fstat().await
+    }
+
+    async fn fsync(&self) -> VfsResult<()> {
+        // Nothing to sync for directories
+        Ok(())
+    }
+
+    async fn fdatasync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn fcntl(&self, cmd: i32, arg: i64) -> VfsResult<i64> {
+        match cmd {
+            libc::F_GETFL => Ok(self.get_flags() as i64),
+            libc::F_SETFL => {
+                self.set_flags(arg as i32)?;
+                Ok(0)
+            }
+            _ => Err(VfsError::Other(format!(
+                "Unsupported fcntl command: {}",
+                cmd
+            ))),
+        }
+    }
+
+    fn ioctl(&self, _request: u64, _arg: u64) -> VfsResult<i64> {
+        Err(VfsError::Other("ioctl not supported".to_string()))
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    async fn close(&self) -> VfsResult<()> {
+        if let Some(upper) = &self.upper {
+            upper.close().await?;
+        }
+        if let Some(lower) = &self.lower {
+            lower.close().await?;
+        }
+        Ok(())
+    }
+
+    fn get_flags(&self) -> i32 {
+        *self.flags.lock().unwrap()
+    }
+
+    fn set_flags(&self, flags: i32) -> VfsResult<()> {
+        *self.flags.lock().unwrap() = flags;
+        Ok(())
+    }
+
+    async fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        if let Some(entries) = self.entries.lock().unwrap().as_ref() {
+            return Ok(entries.clone());
+        }
+
+        let mut result = Vec::new();
+
+        // Upper-layer entries shadow lower-layer entries with the same name,
+        // and whiteouts hide them
+        if let Some(upper) = &self.upper {
+            result = upper.readdir().await?;
+        }
+        if let Some(lower) = &self.lower {
+            let mut hidden = self.overlay.upper.child_whiteouts(&self.path).await?;
+            hidden.extend(result.iter().map(|entry| entry.name.clone()));
+            for entry in lower.readdir().await? {
+                if hidden.contains(&entry.name) {
+                    continue;
+                }
+                result.push(entry);
+            }
+        }
+
+        // Cache the listing so that pagination sees a stable snapshot
+        *self.entries.lock().unwrap() = Some(result.clone());
+        Ok(result)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::vfs::sqlite::SqliteVfs;
+    use tempfile::TempDir;
+
+    /// Create an overlay at `/agent` whose lower layer has `/agent/base.txt`
+    /// containing "base" and `/agent/dir/lower.txt` containing "lower"
+    async fn create_test_overlay() -> (OverlayVfs, Arc<dyn Vfs>, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let lower = SqliteVfs::new(dir.path().join("lower.db"), mount_point.clone())
+            .await
+            .unwrap();
+        let upper = SqliteVfs::new(dir.path().join("upper.db"), mount_point.clone())
+            .await
+            .unwrap();
+
+        lower.mkdir(Path::new("/agent/dir"), 0o755).await.unwrap();
+        for (path, data) in [
+            ("/agent/base.txt", b"base".as_slice()),
+            ("/agent/dir/lower.txt", b"lower".as_slice()),
+        ] {
+            let file = lower
+                .open(Path::new(path), libc::O_WRONLY | libc::O_CREAT, 0o644)
+                .await
+                .unwrap();
+            file.pwrite(0, data).await.unwrap();
+            file.close().await.unwrap();
+        }
+
+        let lower: Arc<dyn Vfs> = Arc::new(lower);
+        let overlay = OverlayVfs::new(lower.clone(), Arc::new(upper), mount_point);
+        (overlay, lower, dir)
+    }
+
+    async fn read_all(vfs: &dyn Vfs, path: &str) -> Vec<u8> {
+        let file = vfs.open(Path::new(path), libc::O_RDONLY, 0).await.unwrap();
+        let data = file.pread(0, 1024).await.unwrap();
+        file.close().await.unwrap();
+        data
+    }
+
+    async fn list(vfs: &dyn Vfs, path: &str) -> Vec<String> {
+        let dir = vfs.open(Path::new(path), libc::O_RDONLY, 0).await.unwrap();
+        let mut names: Vec<_> = vfs
+            .readdir(&dir)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .filter(|name| name != "." && name != "..")
+            .collect();
+        dir.close().await.unwrap();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_copy_up_on_write() {
+        let (overlay, lower, _dir) = create_test_overlay().await;
+
+        assert_eq!(read_all(&overlay, "/agent/dir/lower.txt").await, b"lower");
+
+        let file = overlay
+            .open(Path::new("/agent/dir/lower.txt"), libc::O_WRONLY, 0)
+            .await
+            .unwrap();
+        file.pwrite(0, b"UPPER").await.unwrap();
+        file.close().await.unwrap();
+
+        assert_eq!(read_all(&overlay, "/agent/dir/lower.txt").await, b"UPPER");
+        assert_eq!(read_all(&*lower, "/agent/dir/lower.txt").await, b"lower");
+
+        // Metadata changes copy up as well
+        overlay
+            .chmod(Path::new("/agent/base.txt"), 0o600)
+            .await
+            .unwrap();
+        let stat = overlay.stat(Path::new("/agent/base.txt")).await.unwrap();
+        assert_eq!(stat.st_mode & 0o777, 0o600);
+        assert_eq!(read_all(&overlay, "/agent/base.txt").await, b"base");
+        let stat = lower.stat(Path::new("/agent/base.txt")).await.unwrap();
+        assert_eq!(stat.st_mode & 0o777, 0o644);
+    }
+
+    #[tokio::test]
+    async fn test_delete_then_read() {
+        let (overlay, lower, _dir) = create_test_overlay().await;
+
+        overlay.unlink(Path::new("/agent/base.txt")).await.unwrap();
+        assert!(matches!(
+            overlay
+                .open(Path::new("/agent/base.txt"), libc::O_RDONLY, 0)
+                .await,
+            Err(VfsError::NotFound)
+        ));
+        assert!(matches!(
+            overlay.stat(Path::new("/agent/base.txt")).await,
+            Err(VfsError::NotFound)
+        ));
+        assert!(lower.stat(Path::new("/agent/base.txt")).await.is_ok());
+
+        // Recreating the file does not bring back the lower-layer contents
+        let file = overlay
+            .open(
+                Path::new("/agent/base.txt"),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL,
+                0o644,
+            )
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        assert_eq!(read_all(&overlay, "/agent/base.txt").await, b"");
+
+        // A removed directory hides its lower-layer entries for good
+        overlay
+            .unlink(Path::new("/agent/dir/lower.txt"))
+            .await
+            .unwrap();
+        overlay.rmdir(Path::new("/agent/dir")).await.unwrap();
+        overlay.mkdir(Path::new("/agent/dir"), 0o755).await.unwrap();
+        assert!(matches!(
+            overlay.stat(Path::new("/agent/dir/lower.txt")).await,
+            Err(VfsError::NotFound)
+        ));
+        assert!(list(&overlay, "/agent/dir").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rename_whiteout() {
+        let (overlay, lower, _dir) = create_test_overlay().await;
+
+        overlay
+            .rename(
+                Path::new("/agent/base.txt"),
+                Path::new("/agent/moved.txt"),
+                libc::RENAME_WHITEOUT,
+            )
+            .await
+            .unwrap();
+        assert_eq!(read_all(&overlay, "/agent/moved.txt").await, b"base");
+        assert!(matches!(
+            overlay.stat(Path::new("/agent/base.txt")).await,
+            Err(VfsError::NotFound)
+        ));
+        assert_eq!(list(&overlay, "/agent").await, ["dir", "moved.txt"]);
+        assert_eq!(read_all(&*lower, "/agent/base.txt").await, b"base");
+
+        // Whiteouts can't be exchanged, and other VFSes don't have them
+        assert!(matches!(
+            overlay
+                .rename(
+                    Path::new("/agent/moved.txt"),
+                    Path::new("/agent/dir/lower.txt"),
+                    libc::RENAME_EXCHANGE | libc::RENAME_WHITEOUT,
+                )
+                .await,
+            Err(VfsError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            lower
+                .rename(
+                    Path::new("/agent/base.txt"),
+                    Path::new("/agent/moved.txt"),
+                    libc::RENAME_WHITEOUT,
+                )
+                .await,
+            Err(VfsError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_readdir_merges_layers() {
+        let (overlay, _lower, _dir) = create_test_overlay().await;
+
+        let file = overlay
+            .open(
+                Path::new("/agent/dir/upper.txt"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        overlay.unlink(Path::new("/agent/base.txt")).await.unwrap();
+
+        assert_eq!(list(&overlay, "/agent").await, ["dir"]);
+        assert_eq!(
+            list(&overlay, "/agent/dir").await,
+            ["lower.txt", "upper.txt"]
+        );
+
+        // A non-empty merged directory cannot be removed
+        assert!(matches!(
+            overlay.rmdir(Path::new("/agent/dir")).await,
+            Err(VfsError::NotEmpty)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_whiteouts_take_no_names() {
+        let (overlay, _lower, _dir) = create_test_overlay().await;
+
+        overlay.unlink(Path::new("/agent/base.txt")).await.unwrap();
+        for path in ["/agent/.wh.base.txt", "/agent/.wh.dir"] {
+            let file = overlay
+                .open(
+                    Path::new(path),
+                    libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL,
+                    0o644,
+                )
+                .await
+                .unwrap();
+            file.close().await.unwrap();
+        }
+
+        assert_eq!(
+            list(&overlay, "/agent").await,
+            [".wh.base.txt", ".wh.dir", "dir"]
+        );
+        assert_eq!(list(&overlay, "/agent/dir").await, ["lower.txt"]);
+
+        // Removing the files doesn't bring back what they were named after
+        overlay
+            .unlink(Path::new("/agent/.wh.base.txt"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            overlay.stat(Path::new("/agent/base.txt")).await,
+            Err(VfsError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+//! VFS backends by URL scheme.
+//!
+//! A mount can name its backend with a URL such as `mem://` or
+//! `sqlite://agent.db`, whose scheme picks the factory that opens it.
+//! `VfsRegistry::with_defaults()` knows the built-in backends, and embedders
+//! register their own (remote stores, object storage, ...) next to them.
+
+use super::memory::MemoryVfs;
+use super::{Vfs, VfsError, VfsResult};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by a `VfsFactory`
+pub type VfsFuture = Pin<Box<dyn Future<Output = VfsResult<Arc<dyn Vfs>>> + Send>>;
+
+/// Opens a VFS from the location part of its URL, for a mount point
+pub type VfsFactory = Arc<dyn Fn(String, PathBuf) -> VfsFuture + Send + Sync>;
+
+/// Factories that open VFS backends by URL scheme
+#[derive(Clone, Default)]
+pub struct VfsRegistry {
+    factories: HashMap<String, VfsFactory>,
+}
+
+impl VfsRegistry {
+    /// Create a registry without any backends
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the built-in backends
+    ///
+    /// * `mem://` - an empty `MemoryVfs`, which takes no location
+    /// * `sqlite://PATH` - the AgentFS database at `PATH` (Linux only)
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry.register("mem", |location, mount_point| async move {
+            if !location.is_empty() {
+                return Err(VfsError::InvalidInput(format!(
+                    "mem:// takes no location, got '{}'",
+                    location
+                )));
+            }
+            Ok(Arc::new(MemoryVfs::new(mount_point)) as Arc<dyn Vfs>)
+        });
+        #[cfg(target_os = "linux")]
+        registry.register("sqlite", |location, mount_point| async move {
+            let vfs = super::sqlite::SqliteVfs::new(&location, mount_point).await;
+            vfs.map(|vfs| Arc::new(vfs) as Arc<dyn Vfs>)
+        });
+        registry
+    }
+
+    /// Register `factory` to open the URLs of `scheme`, replacing any factory
+    /// already registered for it
+    ///
+    /// The factory is called with the part of the URL after `scheme://` and
+    /// the mount point the VFS is opened for.
+    pub fn register<F, Fut>(&mut self, scheme: &str, factory: F)
+    where
+        F: Fn(String, PathBuf) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = VfsResult<Arc<dyn Vfs>>> + Send + 'static,
+    {
+        let factory: VfsFactory =
+            Arc::new(move |location, mount_point| Box::pin(factory(location, mount_point)));
+        self.factories.insert(scheme.to_string(), factory);
+    }
+
+    /// The registered schemes, sorted
+    pub fn schemes(&self) -> Vec<&str> {
+        let mut schemes: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        schemes.sort_unstable();
+        schemes
+    }
+
+    /// Open the VFS named by `url` for `mount_point`
+    ///
+    /// Fails with `VfsError::InvalidInput` if the URL is malformed or its
+    /// scheme is not registered.
+    pub async fn open(&self, url: &str, mount_point: &Path) -> VfsResult<Arc<dyn Vfs>> {
+        let (scheme, location) = parse_url(url)?;
+        let factory = self.factories.get(scheme).ok_or_else(|| {
+            VfsError::InvalidInput(format!(
+                "Unknown VFS scheme '{}'. Registered schemes: {}.",
+                scheme,
+                self.schemes().join(", ")
+            ))
+        })?;
+        factory(location.to_string(), mount_point.to_path_buf()).await
+    }
+}
+
+/// Split a VFS URL into its scheme and location
+pub fn parse_url(url: &str) -> VfsResult<(&str, &str)> {
+    match url.split_once("://") {
+        Some((scheme, location)) if !scheme.is_empty() => Ok((scheme, location)),
+        _ => Err(VfsError::InvalidInput(format!(
+            "Invalid VFS URL '{}'. Expected format: SCHEME://LOCATION.",
+            url
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(parse_url("mem://").unwrap(), ("mem", ""));
+        assert_eq!(
+            parse_url("s3://bucket/prefix").unwrap(),
+            ("s3", "bucket/prefix")
+        );
+        assert!(parse_url("agent.db").is_err());
+        assert!(parse_url("://agent.db").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_by_scheme() {
+        let mut registry = VfsRegistry::with_defaults();
+        let mount_point = Path::new("/scratch");
+
+        let vfs = registry.open("mem://", mount_point).await.unwrap();
+        assert!(vfs.is_virtual());
+        vfs.mkdir(Path::new("/scratch/dir"), 0o755).await.unwrap();
+
+        let result = registry.open("mem://name", mount_point).await;
+        assert!(matches!(result, Err(VfsError::InvalidInput(_))));
+        let result = registry.open("s3://bucket", mount_point).await;
+        assert!(matches!(result, Err(VfsError::InvalidInput(_))));
+
+        // Registered backends are opened with their location and mount point
+        registry.register("s3", |location, mount_point| async move {
+            assert_eq!(location, "bucket");
+            Ok(Arc::new(MemoryVfs::new(mount_point)) as Arc<dyn Vfs>)
+        });
+        let vfs = registry.open("s3://bucket", mount_point).await.unwrap();
+        assert!(vfs.translate_path(Path::new("/scratch/file")).is_ok());
+        assert!(registry.schemes().contains(&"s3"));
+    }
+}
@@ -1,9 +1,17 @@
 use super::file::{BoxedFileOps, FileOps};
-use super::{Vfs, VfsError, VfsResult};
-use agentfs_sdk::{filesystem::AgentFS, FileSystem};
+use super::idmap::IdMap;
+use super::{check_path_length, DirEntry, StatFs, Vfs, VfsError, VfsResult};
+use agentfs_sdk::{
+    error::Error as SdkError, filesystem::AgentFS, AgentFSOptions, BoxedFile, FileSystem, FsError,
+    Quota, Stats,
+};
+use lru::LruCache;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
 use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// A SQLite-backed virtual filesystem using the AgentFS SDK
 ///
@@ -15,28 +23,100 @@ pub struct SqliteVfs {
     fs: Arc<dyn FileSystem>,
     /// The virtual path as seen by the sandboxed process
     mount_point: PathBuf,
+    /// User ID reported as the owner of all files
+    uid: u32,
+    /// Group ID reported as the owner of all files
+    gid: u32,
+    /// Mapping of the host IDs to the IDs seen by the sandboxed process
+    id_map: Arc<IdMap>,
+    /// Recent `lstat()` results, shared with the open files
+    stat_cache: Arc<StatCache>,
+    /// Limits on the size of the filesystem, read when it is opened
+    quota: Quota,
 }
 
 impl SqliteVfs {
     /// Create a new SQLite VFS
     ///
+    /// Files are owned by the current user and group; use `with_owner()` to
+    /// report a different owner.
+    ///
     /// # Arguments
     /// * `db_path` - Path to the SQLite database file
     /// * `mount_point` - The virtual path seen by the guest (e.g., "/agent")
     pub async fn new(db_path: impl AsRef<Path>, mount_point: PathBuf) -> VfsResult<Self> {
+        Self::new_with_passphrase(db_path, mount_point, None).await
+    }
+
+    /// Create a new SQLite VFS, unlocking an encrypted filesystem with `passphrase`
+    pub async fn new_with_passphrase(
+        db_path: impl AsRef<Path>,
+        mount_point: PathBuf,
+        passphrase: Option<&str>,
+    ) -> VfsResult<Self> {
         let db_path_str = db_path
             .as_ref()
             .to_str()
             .ok_or_else(|| VfsError::InvalidInput("Invalid database path".to_string()))?;
 
-        let fs = AgentFS::new(db_path_str)
+        let fs = AgentFS::new_with_passphrase(db_path_str, passphrase)
+            .await
+            .map_err(|e| VfsError::Other(format!("Failed to create filesystem: {}", e)))?;
+
+        Ok(Self::from_fs(fs, mount_point))
+    }
+
+    /// Create a new SQLite VFS with the database and settings in `options`,
+    /// such as the passphrase and the size of the block cache
+    pub async fn open(options: AgentFSOptions, mount_point: PathBuf) -> VfsResult<Self> {
+        let agent = agentfs_sdk::AgentFS::open(options)
             .await
             .map_err(|e| VfsError::Other(format!("Failed to create filesystem: {}", e)))?;
 
-        Ok(Self {
+        Ok(Self::from_fs(agent.fs, mount_point))
+    }
+
+    fn from_fs(fs: AgentFS, mount_point: PathBuf) -> Self {
+        let quota = fs.quota();
+        Self {
             fs: Arc::new(fs) as Arc<dyn FileSystem>,
             mount_point,
-        })
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            id_map: Arc::default(),
+            stat_cache: Arc::new(StatCache::new(DEFAULT_STAT_CACHE_SIZE)),
+            quota,
+        }
+    }
+
+    /// Set the user and group IDs that own the files in this VFS
+    ///
+    /// This matches the `uid`/`gid` options of `agentfs mount`: ownership is
+    /// reported for every file, and only the owner (or root) may change
+    /// permission bits.
+    pub fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.uid = uid;
+        self.gid = gid;
+        self
+    }
+
+    /// Remap user and group IDs between the host and the sandboxed process
+    ///
+    /// Ownership is reported with the host IDs mapped to the inside IDs, and
+    /// IDs passed to `chown()` are mapped back before they are stored.
+    pub fn with_id_map(mut self, id_map: IdMap) -> Self {
+        self.id_map = Arc::new(id_map);
+        self
+    }
+
+    /// Set the number of `lstat()` results cached, 0 disabling the cache
+    ///
+    /// Results are cached for at most a second and dropped when the VFS
+    /// changes them, so the cache only needs to be disabled when another
+    /// process changes the database while the VFS is in use.
+    pub fn with_stat_cache_size(mut self, size: usize) -> Self {
+        self.stat_cache = Arc::new(StatCache::new(size));
+        self
     }
 
     /// Get the mount point path
@@ -46,6 +126,7 @@ impl SqliteVfs {
 
     /// Translate a sandbox path to a relative path for the SDK
     fn translate_to_relative(&self, path: &Path) -> VfsResult<String> {
+        check_path_length(path)?;
         let path_str = path
             .to_str()
             .ok_or_else(|| VfsError::InvalidInput("Invalid path".to_string()))?;
@@ -66,22 +147,338 @@ impl SqliteVfs {
 
         Ok(relative.to_string())
     }
+
+    /// Translate a sandbox path to a relative path for the SDK, resolving the
+    /// symlinks in the directories leading to it
+    ///
+    /// The final component is left as is, for the operation to follow or not.
+    async fn resolve_path(&self, path: &Path) -> VfsResult<String> {
+        let relative_path = self.translate_to_relative(path)?;
+        self.resolve_parents(&relative_path).await
+    }
+
+    /// Resolve the symlinks in the directories leading to a relative path
+    ///
+    /// Link targets are resolved the same way the SDK does when stat-ing
+    /// through a link: absolute targets from the root of the filesystem, and
+    /// relative ones from the link's directory. Missing directories are kept
+    /// as is, for the operation to fail on.
+    async fn resolve_parents(&self, relative_path: &str) -> VfsResult<String> {
+        // Components left to resolve, the next one last
+        let mut pending: Vec<String> = components(relative_path).rev().collect();
+        let mut resolved = PathBuf::from("/");
+        let mut hops = 0;
+
+        while let Some(component) = pending.pop() {
+            match component.as_str() {
+                "." => continue,
+                ".." => {
+                    resolved.pop();
+                    continue;
+                }
+                _ => {}
+            }
+            let candidate = resolved.join(&component);
+            if pending.is_empty() {
+                resolved = candidate;
+                break;
+            }
+
+            let candidate_str = candidate.to_string_lossy();
+            let stats = self.cached_lstat(&candidate_str).await?;
+            if !stats.is_some_and(|stats| stats.is_symlink()) {
+                resolved = candidate;
+                continue;
+            }
+
+            hops += 1;
+            if hops > MAX_SYMLINK_DEPTH {
+                return Err(VfsError::SymlinkLoop);
+            }
+            let target = self
+                .fs
+                .readlink(&candidate_str)
+                .await
+                .map_err(|e| map_fs_error(e, "Failed to read link"))?
+                .ok_or(VfsError::NotFound)?;
+            if target.starts_with('/') {
+                resolved = PathBuf::from("/");
+            }
+            pending.extend(components(&target).rev().map(String::from));
+        }
+
+        Ok(resolved.to_string_lossy().into_owned())
+    }
+
+    /// Look up a relative path returned by `resolve_path()`
+    ///
+    /// When `follow` is set, symlinks at the final component are followed, and
+    /// the path of the first non-symlink is returned along with its status. A
+    /// dangling link resolves to its missing target, so that `O_CREAT` can
+    /// create it.
+    async fn lookup(
+        &self,
+        relative_path: &str,
+        follow: bool,
+    ) -> VfsResult<(String, Option<Stats>)> {
+        let mut current = relative_path.to_string();
+
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            let stats = self.cached_lstat(&current).await?;
+            match stats {
+                Some(stats) if follow && stats.is_symlink() => {
+                    let target = self
+                        .fs
+                        .readlink(&current)
+                        .await
+                        .map_err(|e| map_fs_error(e, "Failed to read link"))?
+                        .ok_or(VfsError::NotFound)?;
+                    let target = if target.starts_with('/') {
+                        target
+                    } else {
+                        // Relative target - resolve against the link's directory
+                        let parent = Path::new(&current).parent().unwrap_or(Path::new("/"));
+                        parent.join(&target).to_string_lossy().into_owned()
+                    };
+                    current = self.resolve_parents(&target).await?;
+                }
+                stats => return Ok((current, stats)),
+            }
+        }
+
+        Err(VfsError::SymlinkLoop)
+    }
+
+    /// Store the ownership of a relative path, given as inside IDs
+    ///
+    /// The SDK marks both IDs as set, so a `None` field of an inode that was
+    /// never chowned is stored as the mount owner it was reported as.
+    async fn store_owner(
+        &self,
+        relative_path: &str,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> VfsResult<()> {
+        let mut uid = uid.map(|uid| self.id_map.uid_outside(uid));
+        let mut gid = gid.map(|gid| self.id_map.gid_outside(gid));
+        if uid.is_none() || gid.is_none() {
+            let stats = self
+                .cached_lstat(relative_path)
+                .await?
+                .ok_or(VfsError::NotFound)?;
+            if !stats.owned {
+                uid = uid.or(Some(self.uid));
+                gid = gid.or(Some(self.gid));
+            }
+        }
+
+        let result = self.fs.chown(relative_path, uid, gid).await;
+        self.stat_cache.invalidate(relative_path);
+        result.map_err(|e| map_fs_error(e, "Failed to chown"))
+    }
+
+    /// Get the status of a relative path without following a final symlink,
+    /// from the stat cache if it was looked up recently
+    async fn cached_lstat(&self, relative_path: &str) -> VfsResult<Option<Stats>> {
+        if let Some(stats) = self.stat_cache.get(relative_path) {
+            return Ok(stats);
+        }
+        let stats = self
+            .fs
+            .lstat(relative_path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to stat"))?;
+        self.stat_cache.insert(relative_path, stats.clone());
+        Ok(stats)
+    }
+
+    /// Follow symlinks at the final component of a relative path returned by
+    /// `resolve_path()`
+    ///
+    /// Returns the relative path of the first non-symlink.
+    async fn follow_symlinks(&self, relative_path: &str) -> VfsResult<String> {
+        match self.lookup(relative_path, true).await? {
+            (target, Some(_)) => Ok(target),
+            (_, None) => Err(VfsError::NotFound),
+        }
+    }
+
+    /// Translate a path for an extended attribute operation
+    ///
+    /// Symlinks are followed when `follow` is set; otherwise the link itself
+    /// is used.
+    async fn xattr_path(&self, path: &Path, follow: bool) -> VfsResult<String> {
+        let relative_path = self.resolve_path(path).await?;
+        if follow {
+            self.follow_symlinks(&relative_path).await
+        } else {
+            Ok(relative_path)
+        }
+    }
+}
+
+/// Split a relative path into its components
+fn components(path: &str) -> impl DoubleEndedIterator<Item = &str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
+
+/// Ownership reported for an inode, given the mount owner and ID mapping
+///
+/// The SDK records root (0:0) for the inodes it creates, so an inode whose
+/// ownership was never set with `chown()` is reported as owned by the mount
+/// owner, and otherwise as stored. Either way, the host IDs are then mapped to
+/// the inside IDs.
+fn effective_owner(stats: &Stats, uid: u32, gid: u32, id_map: &IdMap) -> (u32, u32) {
+    let (uid, gid) = if stats.owned {
+        (stats.uid, stats.gid)
+    } else {
+        (uid, gid)
+    };
+    (id_map.uid_inside(uid), id_map.gid_inside(gid))
+}
+
+/// Default number of `lstat()` results kept in the stat cache
+pub const DEFAULT_STAT_CACHE_SIZE: usize = 4096;
+
+/// How long a cached `lstat()` result is used
+///
+/// Changes made through the VFS invalidate the cache right away, so this only
+/// bounds how long changes made to the database by another process, such as
+/// an `agentfs mount` of it, can go unnoticed.
+const STAT_CACHE_TTL: Duration = Duration::from_secs(1);
+
+/// A short-lived cache of `lstat()` results by relative path
+///
+/// Path resolution stats every directory leading to a path, and tools such
+/// as configure scripts stat the same paths over and over, so most lookups
+/// can be answered without querying the database. Missing paths are cached
+/// too, as `None`.
+struct StatCache {
+    /// The cached results, or `None` if caching is disabled
+    entries: Option<Mutex<LruCache<String, (Instant, Option<Stats>)>>>,
+}
+
+impl StatCache {
+    /// Create a cache of `size` entries, or a disabled one if `size` is 0
+    fn new(size: usize) -> Self {
+        Self {
+            entries: NonZeroUsize::new(size).map(|size| Mutex::new(LruCache::new(size))),
+        }
+    }
+
+    /// Get the cached status of a path, unless it has expired
+    fn get(&self, path: &str) -> Option<Option<Stats>> {
+        let mut entries = self.entries.as_ref()?.lock().unwrap();
+        match entries.get(path) {
+            Some((cached_at, stats)) if cached_at.elapsed() < STAT_CACHE_TTL => Some(stats.clone()),
+            Some(_) => {
+                entries.pop(path);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, path: &str, stats: Option<Stats>) {
+        if let Some(entries) = &self.entries {
+            let mut entries = entries.lock().unwrap();
+            entries.put(path.to_string(), (Instant::now(), stats));
+        }
+    }
+
+    /// Forget the status of the inode at `path`, under all of its names
+    ///
+    /// Hard links share the status of their inode, so the entries of other
+    /// paths to the same inode are removed as well. When `path` is not cached,
+    /// its inode is unknown and the whole cache is cleared.
+    fn invalidate(&self, path: &str) {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        let mut entries = entries.lock().unwrap();
+        match entries.pop(path) {
+            Some((_, Some(stats))) => {
+                let links: Vec<String> = entries
+                    .iter()
+                    .filter(|(_, (_, other))| other.as_ref().is_some_and(|o| o.ino == stats.ino))
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                for link in links {
+                    entries.pop(&link);
+                }
+            }
+            Some((_, None)) => {}
+            None => entries.clear(),
+        }
+    }
+
+    /// Forget all cached results
+    ///
+    /// Used after changes to the directory tree, which can affect any number
+    /// of paths: every path under a renamed directory, and the link counts and
+    /// times of the directories involved.
+    fn clear(&self) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().clear();
+        }
+    }
+}
+
+/// Maximum combined size of the extended attribute names and values of an inode
+const MAX_XATTR_SIZE: usize = 64 * 1024;
+
+/// Maximum number of symlinks followed when resolving a path
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Filesystem type reported by `statfs()` ("AGFS")
+const AGENTFS_MAGIC: i64 = 0x4147_4653;
+
+/// Block size reported by `statfs()`
+const STATFS_BLOCK_SIZE: u64 = 4096;
+
+/// Capacity reported by `statfs()`, in blocks (4 TiB), so that tools don't
+/// think the filesystem is out of space
+const STATFS_TOTAL_BLOCKS: u64 = 1024 * 1024 * 1024;
+
+/// Number of inodes reported by `statfs()`
+const STATFS_TOTAL_INODES: u64 = 1_000_000;
+
+/// Largest size a file opened for writing may grow to, since its contents are
+/// buffered in memory until flushed
+const MAX_BUFFERED_FILE_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Map an SDK error to a VFS error, preserving errno semantics where possible
+fn map_fs_error(err: SdkError, context: &str) -> VfsError {
+    match err {
+        SdkError::Fs(FsError::NotFound) => VfsError::NotFound,
+        SdkError::Fs(FsError::AlreadyExists) => VfsError::AlreadyExists,
+        SdkError::Fs(FsError::NotADirectory) => VfsError::NotADirectory,
+        SdkError::Fs(FsError::IsADirectory) => VfsError::IsADirectory,
+        SdkError::Fs(FsError::NotEmpty) => VfsError::NotEmpty,
+        SdkError::Fs(FsError::InvalidRename) => {
+            VfsError::InvalidInput("Cannot move a directory into itself".to_string())
+        }
+        SdkError::Fs(FsError::RootOperation) => VfsError::PermissionDenied,
+        SdkError::Fs(FsError::InvalidPath) => VfsError::InvalidInput("Invalid path".to_string()),
+        SdkError::Fs(FsError::SymlinkLoop) => VfsError::SymlinkLoop,
+        SdkError::Fs(FsError::NoSpace) => VfsError::NoSpace,
+        SdkError::Fs(FsError::NotSupported) => VfsError::NotSupported,
+        SdkError::Io(io_err) => VfsError::IoError(io_err),
+        err if err.is_database_full() => VfsError::NoSpace,
+        other => VfsError::Other(format!("{}: {}", context, other)),
+    }
 }
 
 #[async_trait::async_trait]
 impl Vfs for SqliteVfs {
     fn translate_path(&self, path: &Path) -> VfsResult<PathBuf> {
-        // For virtual VFS, we just validate the path is under our mount point
-        let path_str = path
-            .to_str()
-            .ok_or_else(|| VfsError::InvalidInput("Invalid path".to_string()))?;
-
-        let mount_str = self
-            .mount_point
-            .to_str()
-            .ok_or_else(|| VfsError::InvalidInput("Invalid mount point".to_string()))?;
+        // For virtual VFS, we just validate the path is under our mount point,
+        // comparing whole components so that /agentfoo is not under /agent
+        if path.to_str().is_none() {
+            return Err(VfsError::InvalidInput("Invalid path".to_string()));
+        }
 
-        if path_str.starts_with(mount_str) {
+        if path.starts_with(&self.mount_point) {
             Ok(path.to_path_buf())
         } else {
             Err(VfsError::NotFound)
@@ -92,26 +489,67 @@ impl Vfs for SqliteVfs {
         true
     }
 
-    async fn open(&self, path: &Path, flags: i32, _mode: u32) -> VfsResult<BoxedFileOps> {
-        let relative_path = self.translate_to_relative(path)?;
+    fn id_map(&self) -> &IdMap {
+        &self.id_map
+    }
 
-        let stats = self
-            .fs
-            .stat(&relative_path)
-            .await
-            .map_err(|e| VfsError::Other(format!("Failed to stat: {}", e)))?;
+    async fn open(&self, path: &Path, flags: i32, mode: u32) -> VfsResult<BoxedFileOps> {
+        let relative_path = self.resolve_path(path).await?;
+
+        // As on Linux, a final symlink is not followed with O_NOFOLLOW, nor
+        // created through with O_CREAT | O_EXCL
+        let exclusive = flags & libc::O_CREAT != 0 && flags & libc::O_EXCL != 0;
+        let follow = !exclusive && flags & libc::O_NOFOLLOW == 0;
+        let (relative_path, stats) = self.lookup(&relative_path, follow).await?;
 
         match stats {
             Some(stats) => {
+                // O_CREAT | O_EXCL must fail if the path already exists
+                if exclusive {
+                    return Err(VfsError::AlreadyExists);
+                }
+                if stats.is_symlink() {
+                    return Err(VfsError::SymlinkLoop);
+                }
+
                 if stats.is_directory() {
+                    // Directories can only be opened read-only
+                    if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                        return Err(VfsError::IsADirectory);
+                    }
                     Ok(Arc::new(SqliteDirectoryOps {
                         fs: self.fs.clone(),
+                        stat_cache: self.stat_cache.clone(),
                         path: relative_path,
+                        uid: self.uid,
+                        gid: self.gid,
+                        id_map: self.id_map.clone(),
                         flags: Mutex::new(flags),
                         entries: Arc::new(Mutex::new(None)),
                         position: Arc::new(Mutex::new(0)),
                     }))
                 } else {
+                    if flags & libc::O_DIRECTORY != 0 {
+                        return Err(VfsError::NotADirectory);
+                    }
+                    // Files opened only for reading are read from the database
+                    // a block at a time rather than buffered whole
+                    if flags & libc::O_ACCMODE == libc::O_RDONLY && flags & libc::O_TRUNC == 0 {
+                        let file = self
+                            .fs
+                            .open(&relative_path)
+                            .await
+                            .map_err(|e| map_fs_error(e, "Failed to open file"))?;
+                        return Ok(Arc::new(SqliteInodeFileOps {
+                            file,
+                            stat_cache: self.stat_cache.clone(),
+                            uid: self.uid,
+                            gid: self.gid,
+                            id_map: self.id_map.clone(),
+                            offset: Mutex::new(0),
+                            flags: Mutex::new(flags),
+                        }));
+                    }
                     // If O_TRUNC is set, skip reading the file and use empty data
                     let data = if flags & libc::O_TRUNC != 0 {
                         Vec::new()
@@ -119,49 +557,101 @@ impl Vfs for SqliteVfs {
                         self.fs
                             .read_file(&relative_path)
                             .await
-                            .map_err(|e| VfsError::Other(format!("Failed to read file: {}", e)))?
+                            .map_err(|e| map_fs_error(e, "Failed to read file"))?
                             .ok_or(VfsError::NotFound)?
                     };
                     Ok(Arc::new(SqliteFileOps {
                         fs: self.fs.clone(),
+                        stat_cache: self.stat_cache.clone(),
                         path: relative_path,
+                        uid: self.uid,
+                        gid: self.gid,
+                        id_map: self.id_map.clone(),
                         data: Arc::new(Mutex::new(data)),
                         offset: Arc::new(Mutex::new(0)),
                         flags: Mutex::new(flags),
                         dirty: Arc::new(Mutex::new(flags & libc::O_TRUNC != 0)),
+                        unsynced: Arc::new(Mutex::new(false)),
+                        quota: self.quota,
                     }))
                 }
             }
             None => {
                 // File doesn't exist - check if O_CREAT is set
-                if flags & libc::O_CREAT != 0 {
-                    let data = Vec::new();
-
-                    Ok(Arc::new(SqliteFileOps {
-                        fs: self.fs.clone(),
-                        path: relative_path,
-                        data: Arc::new(Mutex::new(data)),
-                        offset: Arc::new(Mutex::new(0)),
-                        flags: Mutex::new(flags),
-                        dirty: Arc::new(Mutex::new(true)), // Mark as dirty so it gets written on close
-                    }))
-                } else {
-                    // File doesn't exist and O_CREAT not set
-                    Err(VfsError::NotFound)
+                if flags & libc::O_CREAT == 0 {
+                    return Err(VfsError::NotFound);
+                }
+                if flags & libc::O_DIRECTORY != 0 {
+                    // O_CREAT | O_DIRECTORY cannot create a directory
+                    return Err(VfsError::InvalidInput(
+                        "O_CREAT with O_DIRECTORY".to_string(),
+                    ));
                 }
+
+                // Create the inode eagerly so that a concurrent O_EXCL open
+                // observes the file and a missing parent surfaces as ENOENT.
+                let created = self.fs.create_file(&relative_path, mode).await;
+                self.stat_cache.clear();
+                created.map_err(|e| map_fs_error(e, "Failed to create file"))?;
+
+                Ok(Arc::new(SqliteFileOps {
+                    fs: self.fs.clone(),
+                    stat_cache: self.stat_cache.clone(),
+                    path: relative_path,
+                    uid: self.uid,
+                    gid: self.gid,
+                    id_map: self.id_map.clone(),
+                    data: Arc::new(Mutex::new(Vec::new())),
+                    offset: Arc::new(Mutex::new(0)),
+                    flags: Mutex::new(flags),
+                    dirty: Arc::new(Mutex::new(false)),
+                    unsynced: Arc::new(Mutex::new(false)),
+                    quota: self.quota,
+                }))
             }
         }
     }
 
+    async fn read(&self, file: &BoxedFileOps, offset: u64, len: usize) -> VfsResult<Vec<u8>> {
+        file.pread(offset, len).await
+    }
+
+    async fn write(&self, file: &BoxedFileOps, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        file.pwrite(offset, buf).await
+    }
+
+    async fn ftruncate(&self, file: &BoxedFileOps, size: u64) -> VfsResult<()> {
+        file.truncate(size).await
+    }
+
+    async fn fallocate(
+        &self,
+        file: &BoxedFileOps,
+        mode: i32,
+        offset: u64,
+        len: u64,
+    ) -> VfsResult<()> {
+        file.fallocate(mode, offset, len).await
+    }
+
+    async fn futimes(
+        &self,
+        file: &BoxedFileOps,
+        atime: Option<i64>,
+        mtime: Option<i64>,
+    ) -> VfsResult<()> {
+        file.set_times(atime, mtime).await
+    }
+
+    async fn readdir(&self, file: &BoxedFileOps) -> VfsResult<Vec<DirEntry>> {
+        file.readdir().await
+    }
+
     async fn stat(&self, path: &Path) -> VfsResult<libc::stat> {
-        let relative_path = self.translate_to_relative(path)?;
+        let relative_path = self.resolve_path(path).await?;
 
-        let stats = self
-            .fs
-            .stat(&relative_path)
-            .await
-            .map_err(|e| VfsError::Other(format!("Failed to stat: {}", e)))?
-            .ok_or(VfsError::NotFound)?;
+        let (_, stats) = self.lookup(&relative_path, true).await?;
+        let stats = stats.ok_or(VfsError::NotFound)?;
 
         // Use MaybeUninit to construct libc::stat safely
         let mut stat: std::mem::MaybeUninit<libc::stat> = std::mem::MaybeUninit::zeroed();
@@ -171,8 +661,9 @@ impl Vfs for SqliteVfs {
             (*stat_ptr).st_ino = stats.ino as u64;
             (*stat_ptr).st_nlink = stats.nlink.into();
             (*stat_ptr).st_mode = stats.mode;
-            (*stat_ptr).st_uid = stats.uid;
-            (*stat_ptr).st_gid = stats.gid;
+            let (uid, gid) = effective_owner(&stats, self.uid, self.gid, &self.id_map);
+            (*stat_ptr).st_uid = uid;
+            (*stat_ptr).st_gid = gid;
             (*stat_ptr).st_rdev = 0;
             (*stat_ptr).st_size = stats.size;
             (*stat_ptr).st_blksize = 4096;
@@ -188,13 +679,11 @@ impl Vfs for SqliteVfs {
     }
 
     async fn lstat(&self, path: &Path) -> VfsResult<libc::stat> {
-        let relative_path = self.translate_to_relative(path)?;
+        let relative_path = self.resolve_path(path).await?;
 
         let stats = self
-            .fs
-            .lstat(&relative_path)
-            .await
-            .map_err(|e| VfsError::Other(format!("Failed to lstat: {}", e)))?
+            .cached_lstat(&relative_path)
+            .await?
             .ok_or(VfsError::NotFound)?;
 
         // Use MaybeUninit to construct libc::stat safely
@@ -205,8 +694,9 @@ impl Vfs for SqliteVfs {
             (*stat_ptr).st_ino = stats.ino as u64;
             (*stat_ptr).st_nlink = stats.nlink.into();
             (*stat_ptr).st_mode = stats.mode;
-            (*stat_ptr).st_uid = stats.uid;
-            (*stat_ptr).st_gid = stats.gid;
+            let (uid, gid) = effective_owner(&stats, self.uid, self.gid, &self.id_map);
+            (*stat_ptr).st_uid = uid;
+            (*stat_ptr).st_gid = gid;
             (*stat_ptr).st_rdev = 0;
             (*stat_ptr).st_size = stats.size;
             (*stat_ptr).st_blksize = 4096;
@@ -221,68 +711,441 @@ impl Vfs for SqliteVfs {
         }
     }
 
+    async fn statfs(&self) -> VfsResult<StatFs> {
+        let stats = self
+            .fs
+            .statfs()
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to get filesystem statistics"))?;
+
+        // A quota, when set, is the size of the filesystem
+        let total_blocks = stats
+            .quota
+            .bytes
+            .map_or(STATFS_TOTAL_BLOCKS, |bytes| bytes / STATFS_BLOCK_SIZE);
+        let total_inodes = stats.quota.inodes.unwrap_or(STATFS_TOTAL_INODES);
+
+        let used_blocks = stats.bytes_used.div_ceil(STATFS_BLOCK_SIZE);
+        let free_blocks = total_blocks.saturating_sub(used_blocks);
+        Ok(StatFs {
+            fs_type: AGENTFS_MAGIC,
+            block_size: STATFS_BLOCK_SIZE,
+            blocks: total_blocks,
+            blocks_free: free_blocks,
+            blocks_available: free_blocks,
+            files: total_inodes,
+            files_free: total_inodes.saturating_sub(stats.inodes),
+            name_max: libc::NAME_MAX as u64,
+        })
+    }
+
+    async fn rename(&self, oldpath: &Path, newpath: &Path, flags: u32) -> VfsResult<()> {
+        let oldpath_rel = self.resolve_path(oldpath).await?;
+        let newpath_rel = self.resolve_path(newpath).await?;
+
+        let noreplace = flags & libc::RENAME_NOREPLACE != 0;
+        let exchange = flags & libc::RENAME_EXCHANGE != 0;
+        if flags & !(libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE) != 0 || (noreplace && exchange)
+        {
+            return Err(VfsError::InvalidInput(format!(
+                "Unsupported rename flags: {:#x}",
+                flags
+            )));
+        }
+
+        if exchange {
+            // The two entries swap inodes in one transaction
+            let result = self.fs.exchange(&oldpath_rel, &newpath_rel).await;
+            self.stat_cache.clear();
+            return result.map_err(|e| map_fs_error(e, "Failed to exchange"));
+        }
+
+        if noreplace && self.cached_lstat(&newpath_rel).await?.is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let result = self.fs.rename(&oldpath_rel, &newpath_rel).await;
+        self.stat_cache.clear();
+        result.map_err(|e| map_fs_error(e, "Failed to rename"))
+    }
+
+    async fn mkdir(&self, path: &Path, mode: u32) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+
+        let created = self.fs.mkdir(&relative_path).await;
+        self.stat_cache.clear();
+        created.map_err(|e| map_fs_error(e, "Failed to create directory"))?;
+
+        // The SDK creates directories with a default mode, so apply the requested one
+        let result = self.fs.chmod(&relative_path, mode).await;
+        self.stat_cache.invalidate(&relative_path);
+        result.map_err(|e| map_fs_error(e, "Failed to set directory mode"))
+    }
+
+    async fn truncate(&self, path: &Path, size: u64) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+        let (target, stats) = self.lookup(&relative_path, true).await?;
+        if stats.ok_or(VfsError::NotFound)?.is_directory() {
+            return Err(VfsError::IsADirectory);
+        }
+
+        let file = self
+            .fs
+            .open(&target)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to open file"))?;
+        let result = file.truncate(size).await;
+        self.stat_cache.invalidate(&target);
+        result.map_err(|e| map_fs_error(e, "Failed to truncate"))
+    }
+
+    async fn chmod(&self, path: &Path, mode: u32) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+        let target = self.follow_symlinks(&relative_path).await?;
+
+        let result = self.fs.chmod(&target, mode).await;
+        self.stat_cache.invalidate(&target);
+        result.map_err(|e| map_fs_error(e, "Failed to chmod"))
+    }
+
+    async fn getxattr(&self, path: &Path, name: &str, follow: bool) -> VfsResult<Vec<u8>> {
+        let target = self.xattr_path(path, follow).await?;
+
+        self.fs
+            .getxattr(&target, name)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to get extended attribute"))?
+            .ok_or(VfsError::NoData)
+    }
+
+    async fn setxattr(
+        &self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+        flags: i32,
+        follow: bool,
+    ) -> VfsResult<()> {
+        let target = self.xattr_path(path, follow).await?;
+
+        let names = self
+            .fs
+            .listxattr(&target)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to list extended attributes"))?;
+        let exists = names.iter().any(|n| n == name);
+        if flags & libc::XATTR_CREATE != 0 && exists {
+            return Err(VfsError::AlreadyExists);
+        }
+        if flags & libc::XATTR_REPLACE != 0 && !exists {
+            return Err(VfsError::NoData);
+        }
+
+        // Enforce the per-inode cap over all names and values, counting the
+        // new value in place of the one it replaces
+        let mut total = name.len() + value.len();
+        for other in names.iter().filter(|n| *n != name) {
+            let other_value = self
+                .fs
+                .getxattr(&target, other)
+                .await
+                .map_err(|e| map_fs_error(e, "Failed to get extended attribute"))?
+                .unwrap_or_default();
+            total += other.len() + other_value.len();
+        }
+        if total > MAX_XATTR_SIZE {
+            return Err(VfsError::TooBig);
+        }
+
+        let result = self.fs.setxattr(&target, name, value).await;
+        self.stat_cache.invalidate(&target);
+        result.map_err(|e| map_fs_error(e, "Failed to set extended attribute"))
+    }
+
+    async fn listxattr(&self, path: &Path, follow: bool) -> VfsResult<Vec<String>> {
+        let target = self.xattr_path(path, follow).await?;
+
+        self.fs
+            .listxattr(&target)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to list extended attributes"))
+    }
+
+    async fn removexattr(&self, path: &Path, name: &str, follow: bool) -> VfsResult<()> {
+        let target = self.xattr_path(path, follow).await?;
+
+        let removed = self.fs.removexattr(&target, name).await;
+        self.stat_cache.invalidate(&target);
+        let removed =
+            removed.map_err(|e| map_fs_error(e, "Failed to remove extended attribute"))?;
+        if removed {
+            Ok(())
+        } else {
+            Err(VfsError::NoData)
+        }
+    }
+
+    async fn chown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+        let target = self.follow_symlinks(&relative_path).await?;
+        self.store_owner(&target, uid, gid).await
+    }
+
+    async fn lchown(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+        self.store_owner(&relative_path, uid, gid).await
+    }
+
+    async fn utimes(&self, path: &Path, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+        let target = self.follow_symlinks(&relative_path).await?;
+
+        let result = self.fs.utimes(&target, atime, mtime).await;
+        self.stat_cache.invalidate(&target);
+        result.map_err(|e| map_fs_error(e, "Failed to set times"))
+    }
+
+    async fn lutimes(&self, path: &Path, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+
+        let result = self.fs.utimes(&relative_path, atime, mtime).await;
+        self.stat_cache.invalidate(&relative_path);
+        result.map_err(|e| map_fs_error(e, "Failed to set times"))
+    }
+
+    async fn unlink(&self, path: &Path) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+
+        let stats = self
+            .cached_lstat(&relative_path)
+            .await?
+            .ok_or(VfsError::NotFound)?;
+        if stats.is_directory() {
+            return Err(VfsError::IsADirectory);
+        }
+
+        let result = self.fs.remove(&relative_path).await;
+        self.stat_cache.clear();
+        result.map_err(|e| map_fs_error(e, "Failed to unlink"))
+    }
+
+    async fn rmdir(&self, path: &Path) -> VfsResult<()> {
+        let relative_path = self.resolve_path(path).await?;
+
+        let stats = self
+            .cached_lstat(&relative_path)
+            .await?
+            .ok_or(VfsError::NotFound)?;
+        if !stats.is_directory() {
+            return Err(VfsError::NotADirectory);
+        }
+
+        let result = self.fs.remove(&relative_path).await;
+        self.stat_cache.clear();
+        result.map_err(|e| map_fs_error(e, "Failed to remove directory"))
+    }
+
     async fn symlink(&self, target: &Path, linkpath: &Path) -> VfsResult<()> {
-        let linkpath_rel = self.translate_to_relative(linkpath)?;
+        let linkpath_rel = self.resolve_path(linkpath).await?;
         let target_str = target
             .to_str()
             .ok_or_else(|| VfsError::InvalidInput("Invalid target path".to_string()))?;
 
-        self.fs
-            .symlink(target_str, &linkpath_rel)
-            .await
-            .map_err(|e| {
-                let err_msg = e.to_string();
-                if err_msg.contains("already exists") {
-                    VfsError::AlreadyExists
-                } else {
-                    VfsError::Other(format!("Failed to create symlink: {}", e))
-                }
-            })
+        let result = self.fs.symlink(target_str, &linkpath_rel).await;
+        self.stat_cache.clear();
+        result.map_err(|e| {
+            let err_msg = e.to_string();
+            if err_msg.contains("already exists") {
+                VfsError::AlreadyExists
+            } else {
+                VfsError::Other(format!("Failed to create symlink: {}", e))
+            }
+        })
     }
 
     async fn readlink(&self, path: &Path) -> VfsResult<PathBuf> {
-        let relative_path = self.translate_to_relative(path)?;
+        let relative_path = self.resolve_path(path).await?;
 
         let target = self
             .fs
             .readlink(&relative_path)
             .await
-            .map_err(|e| VfsError::Other(format!("Failed to read symlink: {}", e)))?
+            .map_err(|e| map_fs_error(e, "Failed to read symlink"))?
             .ok_or(VfsError::NotFound)?;
 
         Ok(PathBuf::from(target))
     }
 
     async fn link(&self, oldpath: &Path, newpath: &Path) -> VfsResult<()> {
-        let oldpath_rel = self.translate_to_relative(oldpath)?;
-        let newpath_rel = self.translate_to_relative(newpath)?;
+        let oldpath_rel = self.resolve_path(oldpath).await?;
+        let newpath_rel = self.resolve_path(newpath).await?;
+
+        let result = self.fs.link(&oldpath_rel, &newpath_rel).await;
+        self.stat_cache.clear();
+        result.map_err(|e| {
+            let err_msg = e.to_string();
+            if err_msg.contains("does not exist") {
+                VfsError::NotFound
+            } else if err_msg.contains("already exists") {
+                VfsError::AlreadyExists
+            } else if err_msg.contains("directory") {
+                VfsError::PermissionDenied
+            } else {
+                VfsError::Other(format!("Failed to create hard link: {}", e))
+            }
+        })
+    }
+
+    async fn open_tmpfile(&self, dir: &Path, flags: i32, mode: u32) -> VfsResult<BoxedFileOps> {
+        let relative_path = self.resolve_path(dir).await?;
+        let created = self.fs.create_tmpfile(&relative_path, mode).await;
+        let (_, file) = created.map_err(|e| map_fs_error(e, "Failed to create file"))?;
+
+        Ok(Arc::new(SqliteInodeFileOps {
+            file,
+            stat_cache: self.stat_cache.clone(),
+            uid: self.uid,
+            gid: self.gid,
+            id_map: self.id_map.clone(),
+            offset: Mutex::new(0),
+            flags: Mutex::new(flags),
+        }))
+    }
+
+    async fn link_file(&self, file: &BoxedFileOps, newpath: &Path) -> VfsResult<()> {
+        let ino = file.fstat().await?.st_ino;
+        let newpath_rel = self.resolve_path(newpath).await?;
+
+        let result = self.fs.link_inode(ino as i64, &newpath_rel).await;
+        self.stat_cache.clear();
+        result.map_err(|e| map_fs_error(e, "Failed to create hard link"))
+    }
 
+    async fn add_whiteout(&self, path: &Path) -> VfsResult<()> {
+        // Whiteouts name paths of the overlay, whatever the entries there are
+        let relative_path = self.translate_to_relative(path)?;
         self.fs
-            .link(&oldpath_rel, &newpath_rel)
-            .await
-            .map_err(|e| {
-                let err_msg = e.to_string();
-                if err_msg.contains("does not exist") {
-                    VfsError::NotFound
-                } else if err_msg.contains("already exists") {
-                    VfsError::AlreadyExists
-                } else if err_msg.contains("directory") {
-                    VfsError::PermissionDenied
-                } else {
-                    VfsError::Other(format!("Failed to create hard link: {}", e))
-                }
-            })
+            .add_whiteout(&relative_path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to add whiteout"))
+    }
+
+    async fn has_whiteout(&self, path: &Path) -> VfsResult<bool> {
+        let relative_path = self.translate_to_relative(path)?;
+        self.fs
+            .has_whiteout(&relative_path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to look up whiteout"))
+    }
+
+    async fn child_whiteouts(&self, dir: &Path) -> VfsResult<HashSet<String>> {
+        let relative_path = self.translate_to_relative(dir)?;
+        self.fs
+            .child_whiteouts(&relative_path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to list whiteouts"))
     }
 }
 
 /// File operations for SQLite VFS files
 struct SqliteFileOps {
     fs: Arc<dyn FileSystem>,
+    stat_cache: Arc<StatCache>,
     path: String,
+    uid: u32,
+    gid: u32,
+    id_map: Arc<IdMap>,
     data: Arc<Mutex<Vec<u8>>>,
     offset: Arc<Mutex<i64>>,
     flags: Mutex<i32>,
     dirty: Arc<Mutex<bool>>,
+    /// Whether data was written to the database since the last durable sync
+    unsynced: Arc<Mutex<bool>>,
+    /// Limits on the size of the filesystem, from the VFS
+    quota: Quota,
+}
+
+impl SqliteFileOps {
+    /// Write the buffered file contents to the database
+    ///
+    /// This makes the data visible to other handles, but not necessarily
+    /// durable; see `sync()`.
+    async fn flush(&self) -> VfsResult<()> {
+        let dirty = *self.dirty.lock().unwrap();
+        if !dirty {
+            return Ok(());
+        }
+
+        let data = self.data.lock().unwrap().clone();
+
+        // Write the data to the database
+        let result = self.fs.write_file(&self.path, &data).await;
+        self.stat_cache.invalidate(&self.path);
+        result.map_err(|e| map_fs_error(e, "Failed to write file"))?;
+
+        // Clear dirty flag after successful write
+        *self.dirty.lock().unwrap() = false;
+        *self.unsynced.lock().unwrap() = true;
+
+        Ok(())
+    }
+
+    /// Flush the database to disk so that everything written so far is durable
+    async fn sync(&self) -> VfsResult<()> {
+        let file = self
+            .fs
+            .open(&self.path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to open file"))?;
+        file.fsync()
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to sync"))?;
+
+        *self.unsynced.lock().unwrap() = false;
+        Ok(())
+    }
+
+    /// Fail with `FileTooBig` if `len` bytes don't fit in the in-memory buffer
+    fn check_size(len: u64) -> VfsResult<()> {
+        if len > MAX_BUFFERED_FILE_SIZE {
+            return Err(VfsError::FileTooBig);
+        }
+        Ok(())
+    }
+
+    /// Fail with `NoSpace` if growing the file to `len` bytes would exceed the quota
+    ///
+    /// The buffer is only stored on flush, so this checks up front to report the
+    /// error from the write that caused it rather than from a later close.
+    ///
+    /// Without a byte limit this returns right away, since finding the space
+    /// used takes a scan of every inode.
+    async fn reserve(&self, len: usize) -> VfsResult<()> {
+        if self.quota.bytes.is_none() {
+            return Ok(());
+        }
+
+        let stored = self
+            .fs
+            .stat(&self.path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to stat file"))?
+            .map_or(0, |stats| stats.size.max(0) as u64);
+        let growth = (len as u64).saturating_sub(stored);
+        if growth == 0 {
+            return Ok(());
+        }
+        let stats = self
+            .fs
+            .statfs()
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to get filesystem statistics"))?;
+        if stats.fits(growth, 0) {
+            Ok(())
+        } else {
+            Err(VfsError::NoSpace)
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -304,10 +1167,59 @@ impl FileOps for SqliteFileOps {
         Ok(bytes_read)
     }
 
+    async fn pread(&self, offset: u64, len: usize) -> VfsResult<Vec<u8>> {
+        let data = self.data.lock().unwrap();
+
+        let start = offset as usize;
+        if start >= data.len() {
+            return Ok(Vec::new());
+        }
+
+        let end = std::cmp::min(start.saturating_add(len), data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    async fn pwrite(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or(VfsError::FileTooBig)?;
+        Self::check_size(end)?;
+        let (start, end) = (offset as usize, end as usize);
+
+        let len = self.data.lock().unwrap().len();
+        if end > len {
+            self.reserve(end).await?;
+        }
+
+        let mut data = self.data.lock().unwrap();
+        // Extend the buffer if necessary, zero-filling any gap
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+
+        data[start..end].copy_from_slice(buf);
+
+        // Mark as dirty since we modified the data
+        *self.dirty.lock().unwrap() = true;
+
+        Ok(buf.len())
+    }
+
     async fn write(&self, buf: &[u8]) -> VfsResult<usize> {
+        let flags = *self.flags.lock().unwrap();
+        let len = self.data.lock().unwrap().len();
+        let end = if flags & libc::O_APPEND != 0 {
+            len
+        } else {
+            *self.offset.lock().unwrap() as usize
+        } + buf.len();
+        Self::check_size(end as u64)?;
+        if end > len {
+            self.reserve(end).await?;
+        }
+
         let mut data = self.data.lock().unwrap();
         let mut offset = self.offset.lock().unwrap();
-        let flags = *self.flags.lock().unwrap();
 
         // Handle O_APPEND: always write at the end of the file
         let start = if flags & libc::O_APPEND != 0 {
@@ -330,83 +1242,272 @@ impl FileOps for SqliteFileOps {
         Ok(buf.len())
     }
 
+    async fn truncate(&self, size: u64) -> VfsResult<()> {
+        Self::check_size(size)?;
+        let len = self.data.lock().unwrap().len();
+        if size as usize > len {
+            self.reserve(size as usize).await?;
+        }
+
+        {
+            let mut data = self.data.lock().unwrap();
+            data.resize(size as usize, 0);
+            *self.dirty.lock().unwrap() = true;
+        }
+
+        // Persist right away so the dropped tail is removed from the database
+        self.flush().await
+    }
+
+    async fn set_times(&self, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        // Store buffered data first, since storing it updates the modification time
+        self.flush().await?;
+        let result = self.fs.utimes(&self.path, atime, mtime).await;
+        self.stat_cache.invalidate(&self.path);
+        result.map_err(|e| map_fs_error(e, "Failed to set times"))
+    }
+
     async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64> {
         let data = self.data.lock().unwrap();
         let mut current_offset = self.offset.lock().unwrap();
 
-        let new_offset = match whence {
-            libc::SEEK_SET => offset,
-            libc::SEEK_CUR => *current_offset + offset,
-            libc::SEEK_END => data.len() as i64 + offset,
+        let new_offset = match whence {
+            libc::SEEK_SET => offset,
+            libc::SEEK_CUR => *current_offset + offset,
+            libc::SEEK_END => data.len() as i64 + offset,
+            _ => return Err(VfsError::Other("Invalid whence".to_string())),
+        };
+
+        if new_offset < 0 {
+            return Err(VfsError::Other("Invalid offset".to_string()));
+        }
+
+        *current_offset = new_offset;
+        Ok(new_offset)
+    }
+
+    async fn fstat(&self) -> VfsResult<libc::stat> {
+        // Get the actual file stats from the filesystem
+        let stats = self
+            .fs
+            .stat(&self.path)
+            .await
+            .map_err(|e| VfsError::Other(format!("Failed to stat: {}", e)))?
+            .ok_or(VfsError::NotFound)?;
+
+        let data = self.data.lock().unwrap();
+
+        // Use MaybeUninit to construct libc::stat safely
+        let mut stat: std::mem::MaybeUninit<libc::stat> = std::mem::MaybeUninit::zeroed();
+        unsafe {
+            let stat_ptr = stat.as_mut_ptr();
+            (*stat_ptr).st_dev = 0;
+            (*stat_ptr).st_ino = stats.ino as u64;
+            (*stat_ptr).st_nlink = stats.nlink.into();
+            (*stat_ptr).st_mode = stats.mode;
+            let (uid, gid) = effective_owner(&stats, self.uid, self.gid, &self.id_map);
+            (*stat_ptr).st_uid = uid;
+            (*stat_ptr).st_gid = gid;
+            (*stat_ptr).st_rdev = 0;
+            (*stat_ptr).st_size = data.len() as i64;
+            (*stat_ptr).st_blksize = 4096;
+            (*stat_ptr).st_blocks = (data.len() as i64 + 4095) / 4096;
+            (*stat_ptr).st_atime = stats.atime;
+            (*stat_ptr).st_atime_nsec = 0;
+            (*stat_ptr).st_mtime = stats.mtime;
+            (*stat_ptr).st_mtime_nsec = 0;
+            (*stat_ptr).st_ctime = stats.ctime;
+            (*stat_ptr).st_ctime_nsec = 0;
+            Ok(stat.assume_init())
+        }
+    }
+
+    async fn fsync(&self) -> VfsResult<()> {
+        self.flush().await?;
+        self.sync().await
+    }
+
+    async fn fdatasync(&self) -> VfsResult<()> {
+        self.flush().await?;
+
+        // Nothing but metadata can have changed since the last sync, which
+        // fdatasync does not need to flush
+        if !*self.unsynced.lock().unwrap() {
+            return Ok(());
+        }
+        self.sync().await
+    }
+
+    fn fcntl(&self, cmd: i32, arg: i64) -> VfsResult<i64> {
+        match cmd {
+            libc::F_GETFL => Ok(self.get_flags() as i64),
+            libc::F_SETFL => {
+                self.set_flags(arg as i32)?;
+                Ok(0)
+            }
+            _ => Err(VfsError::Other(format!(
+                "Unsupported fcntl command: {}",
+                cmd
+            ))),
+        }
+    }
+
+    fn ioctl(&self, _request: u64, _arg: u64) -> VfsResult<i64> {
+        // Virtual file doesn't support ioctl
+        Err(VfsError::Other("ioctl not supported".to_string()))
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        // No real kernel FD for virtual files
+        None
+    }
+
+    async fn close(&self) -> VfsResult<()> {
+        // Ensure all data is written to the database before closing
+        self.flush().await
+    }
+
+    fn get_flags(&self) -> i32 {
+        *self.flags.lock().unwrap()
+    }
+
+    fn set_flags(&self, flags: i32) -> VfsResult<()> {
+        *self.flags.lock().unwrap() = flags;
+        Ok(())
+    }
+}
+
+/// File operations for SQLite VFS files opened read-only, or without a name
+/// from `O_TMPFILE`
+///
+/// Unlike `SqliteFileOps`, which buffers a file by path, these go straight to
+/// the inode through an SDK file handle, so that reads fetch only the blocks
+/// they need and files without a path can be used. The inode of a file
+/// without links is freed along with the handle.
+struct SqliteInodeFileOps {
+    file: BoxedFile,
+    /// Cleared on every change, as the paths linking to the inode are unknown
+    stat_cache: Arc<StatCache>,
+    uid: u32,
+    gid: u32,
+    id_map: Arc<IdMap>,
+    offset: Mutex<i64>,
+    flags: Mutex<i32>,
+}
+
+impl SqliteInodeFileOps {
+    async fn stats(&self) -> VfsResult<Stats> {
+        self.file
+            .fstat()
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to stat"))
+    }
+
+    /// Fail with `EBADF` if the file was opened read-only
+    fn check_writable(&self) -> VfsResult<()> {
+        if self.get_flags() & libc::O_ACCMODE == libc::O_RDONLY {
+            return Err(std::io::Error::from_raw_os_error(libc::EBADF).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl FileOps for SqliteInodeFileOps {
+    async fn read(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let offset = *self.offset.lock().unwrap();
+        let data = self.pread(offset as u64, buf.len()).await?;
+        buf[..data.len()].copy_from_slice(&data);
+        *self.offset.lock().unwrap() = offset + data.len() as i64;
+        Ok(data.len())
+    }
+
+    async fn pread(&self, offset: u64, len: usize) -> VfsResult<Vec<u8>> {
+        // The SDK zero-fills reads past the end of the file
+        let size = self.stats().await?.size.max(0) as u64;
+        let len = (len as u64).min(size.saturating_sub(offset));
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        self.file
+            .pread(offset, len)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to read file"))
+    }
+
+    async fn pwrite(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.check_writable()?;
+        let result = self.file.pwrite(offset, buf).await;
+        self.stat_cache.clear();
+        result.map_err(|e| map_fs_error(e, "Failed to write file"))?;
+        Ok(buf.len())
+    }
+
+    async fn write(&self, buf: &[u8]) -> VfsResult<usize> {
+        let start = if self.get_flags() & libc::O_APPEND != 0 {
+            self.stats().await?.size
+        } else {
+            *self.offset.lock().unwrap()
+        };
+        self.pwrite(start as u64, buf).await?;
+        *self.offset.lock().unwrap() = start + buf.len() as i64;
+        Ok(buf.len())
+    }
+
+    async fn truncate(&self, size: u64) -> VfsResult<()> {
+        self.check_writable()?;
+        let result = self.file.truncate(size).await;
+        self.stat_cache.clear();
+        result.map_err(|e| map_fs_error(e, "Failed to truncate file"))
+    }
+
+    async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64> {
+        let base = match whence {
+            libc::SEEK_SET => 0,
+            libc::SEEK_CUR => *self.offset.lock().unwrap(),
+            libc::SEEK_END => self.stats().await?.size,
             _ => return Err(VfsError::Other("Invalid whence".to_string())),
         };
 
+        let new_offset = base + offset;
         if new_offset < 0 {
             return Err(VfsError::Other("Invalid offset".to_string()));
         }
-
-        *current_offset = new_offset;
+        *self.offset.lock().unwrap() = new_offset;
         Ok(new_offset)
     }
 
     async fn fstat(&self) -> VfsResult<libc::stat> {
-        // Get the actual file stats from the filesystem
-        let stats = self
-            .fs
-            .stat(&self.path)
-            .await
-            .map_err(|e| VfsError::Other(format!("Failed to stat: {}", e)))?
-            .ok_or(VfsError::NotFound)?;
-
-        let data = self.data.lock().unwrap();
+        let stats = self.stats().await?;
 
         // Use MaybeUninit to construct libc::stat safely
         let mut stat: std::mem::MaybeUninit<libc::stat> = std::mem::MaybeUninit::zeroed();
         unsafe {
             let stat_ptr = stat.as_mut_ptr();
-            (*stat_ptr).st_dev = 0;
             (*stat_ptr).st_ino = stats.ino as u64;
             (*stat_ptr).st_nlink = stats.nlink.into();
             (*stat_ptr).st_mode = stats.mode;
-            (*stat_ptr).st_uid = stats.uid;
-            (*stat_ptr).st_gid = stats.gid;
-            (*stat_ptr).st_rdev = 0;
-            (*stat_ptr).st_size = data.len() as i64;
+            let (uid, gid) = effective_owner(&stats, self.uid, self.gid, &self.id_map);
+            (*stat_ptr).st_uid = uid;
+            (*stat_ptr).st_gid = gid;
+            (*stat_ptr).st_size = stats.size;
             (*stat_ptr).st_blksize = 4096;
-            (*stat_ptr).st_blocks = (data.len() as i64 + 4095) / 4096;
+            (*stat_ptr).st_blocks = (stats.size + 4095) / 4096;
             (*stat_ptr).st_atime = stats.atime;
-            (*stat_ptr).st_atime_nsec = 0;
             (*stat_ptr).st_mtime = stats.mtime;
-            (*stat_ptr).st_mtime_nsec = 0;
             (*stat_ptr).st_ctime = stats.ctime;
-            (*stat_ptr).st_ctime_nsec = 0;
             Ok(stat.assume_init())
         }
     }
 
     async fn fsync(&self) -> VfsResult<()> {
-        // For virtual file, sync means write to database
-        let dirty = *self.dirty.lock().unwrap();
-        if !dirty {
-            return Ok(());
-        }
-
-        let data = self.data.lock().unwrap().clone();
-
-        // Write the data to the database
-        self.fs
-            .write_file(&self.path, &data)
+        self.file
+            .fsync()
             .await
-            .map_err(|e| VfsError::Other(format!("Failed to write file: {}", e)))?;
-
-        // Clear dirty flag after successful write
-        *self.dirty.lock().unwrap() = false;
-
-        Ok(())
+            .map_err(|e| map_fs_error(e, "Failed to sync"))
     }
 
     async fn fdatasync(&self) -> VfsResult<()> {
-        // For virtual file, same as fsync
         self.fsync().await
     }
 
@@ -435,8 +1536,8 @@ impl FileOps for SqliteFileOps {
     }
 
     async fn close(&self) -> VfsResult<()> {
-        // Ensure all data is written to the database before closing
-        self.fsync().await
+        // Writes already went to the database
+        Ok(())
     }
 
     fn get_flags(&self) -> i32 {
@@ -449,16 +1550,17 @@ impl FileOps for SqliteFileOps {
     }
 }
 
-/// Type alias for directory entry list: (inode, name, type)
-type DirEntryList = Vec<(u64, String, u8)>;
-
 /// Directory operations for SQLite VFS directories
 struct SqliteDirectoryOps {
     fs: Arc<dyn FileSystem>,
+    stat_cache: Arc<StatCache>,
     path: String,
+    uid: u32,
+    gid: u32,
+    id_map: Arc<IdMap>,
     flags: Mutex<i32>,
     /// Cached directory entries
-    entries: Arc<Mutex<Option<DirEntryList>>>,
+    entries: Arc<Mutex<Option<Vec<DirEntry>>>>,
     /// Current position in the directory listing
     position: Arc<Mutex<usize>>,
 }
@@ -475,9 +1577,43 @@ impl FileOps for SqliteDirectoryOps {
         Err(VfsError::Other("Is a directory".to_string()))
     }
 
-    async fn seek(&self, _offset: i64, _whence: i32) -> VfsResult<i64> {
-        // Cannot seek in a directory
-        Err(VfsError::Other("Is a directory".to_string()))
+    async fn pread(&self, _offset: u64, _len: usize) -> VfsResult<Vec<u8>> {
+        // Cannot read from a directory
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn pwrite(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        // Cannot write to a directory
+        Err(VfsError::IsADirectory)
+    }
+
+    async fn seek(&self, offset: i64, whence: i32) -> VfsResult<i64> {
+        // The directory offset is an index into the cached listing
+        let mut position = self.position.lock().unwrap();
+        let new_position = match whence {
+            libc::SEEK_SET => offset,
+            libc::SEEK_CUR => *position as i64 + offset,
+            _ => {
+                return Err(VfsError::InvalidInput(
+                    "Invalid whence for directory".to_string(),
+                ))
+            }
+        };
+
+        if new_position < 0 {
+            return Err(VfsError::InvalidInput(
+                "Negative directory offset".to_string(),
+            ));
+        }
+
+        *position = new_position as usize;
+        Ok(new_position)
+    }
+
+    async fn set_times(&self, atime: Option<i64>, mtime: Option<i64>) -> VfsResult<()> {
+        let result = self.fs.utimes(&self.path, atime, mtime).await;
+        self.stat_cache.invalidate(&self.path);
+        result.map_err(|e| map_fs_error(e, "Failed to set times"))
     }
 
     async fn fstat(&self) -> VfsResult<libc::stat> {
@@ -486,7 +1622,7 @@ impl FileOps for SqliteDirectoryOps {
             .fs
             .stat(&self.path)
             .await
-            .map_err(|e| VfsError::Other(format!("Failed to stat: {}", e)))?
+            .map_err(|e| map_fs_error(e, "Failed to stat"))?
             .ok_or(VfsError::NotFound)?;
 
         // Use MaybeUninit to construct libc::stat safely
@@ -497,8 +1633,9 @@ impl FileOps for SqliteDirectoryOps {
             (*stat_ptr).st_ino = stats.ino as u64;
             (*stat_ptr).st_nlink = stats.nlink.into();
             (*stat_ptr).st_mode = stats.mode;
-            (*stat_ptr).st_uid = stats.uid;
-            (*stat_ptr).st_gid = stats.gid;
+            let (uid, gid) = effective_owner(&stats, self.uid, self.gid, &self.id_map);
+            (*stat_ptr).st_uid = uid;
+            (*stat_ptr).st_gid = gid;
             (*stat_ptr).st_rdev = 0;
             (*stat_ptr).st_size = stats.size;
             (*stat_ptr).st_blksize = 4096;
@@ -561,94 +1698,971 @@ impl FileOps for SqliteDirectoryOps {
         Ok(())
     }
 
-    async fn getdents(&self) -> VfsResult<DirEntryList> {
-        // Check if we need to populate the entries cache
-        let needs_populate = {
-            let entries_lock = self.entries.lock().unwrap();
-            entries_lock.is_none()
-        };
+    async fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        if let Some(entries) = self.entries.lock().unwrap().as_ref() {
+            return Ok(entries.clone());
+        }
 
-        if needs_populate {
-            // Read directory entries from the filesystem (without holding lock)
-            let dir_entries = self
-                .fs
-                .readdir(&self.path)
-                .await
-                .map_err(|e| VfsError::Other(format!("Failed to read directory: {}", e)))?
-                .ok_or(VfsError::NotFound)?;
+        // Read directory entries from the filesystem (without holding lock)
+        let dir_entries = self
+            .fs
+            .readdir_plus(&self.path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to read directory"))?
+            .ok_or(VfsError::NotFound)?;
 
-            // Convert to the format expected by getdents64
-            let mut result = Vec::new();
+        // Add . and .. entries with correct inode numbers
+        let current_stats = self
+            .fs
+            .stat(&self.path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to stat current dir"))?
+            .ok_or(VfsError::NotFound)?;
 
-            // Add . and .. entries with correct inode numbers
-            // Get current directory inode
-            let current_stats = self
-                .fs
-                .stat(&self.path)
-                .await
-                .map_err(|e| VfsError::Other(format!("Failed to stat current dir: {}", e)))?
-                .ok_or(VfsError::NotFound)?;
-            let current_ino = current_stats.ino as u64;
+        let parent_path = Path::new(&self.path)
+            .parent()
+            .and_then(|p| p.to_str())
+            .unwrap_or("/");
+        let parent_stats = self
+            .fs
+            .stat(parent_path)
+            .await
+            .map_err(|e| map_fs_error(e, "Failed to stat parent dir"))?
+            .ok_or(VfsError::NotFound)?;
 
-            // Get parent directory inode
-            let parent_path = if self.path == "/" {
-                "/".to_string()
+        let mut result = Vec::with_capacity(dir_entries.len() + 2);
+        result.push(DirEntry {
+            ino: current_stats.ino as u64,
+            d_type: libc::DT_DIR,
+            name: ".".to_string(),
+        });
+        result.push(DirEntry {
+            ino: parent_stats.ino as u64,
+            d_type: libc::DT_DIR,
+            name: "..".to_string(),
+        });
+
+        // Entry stats are not resolved through symlinks, so links report DT_LNK
+        for entry in dir_entries {
+            let d_type = if entry.stats.is_directory() {
+                libc::DT_DIR
+            } else if entry.stats.is_symlink() {
+                libc::DT_LNK
             } else {
-                Path::new(&self.path)
-                    .parent()
-                    .map(|p| p.to_str().unwrap_or("/").to_string())
-                    .unwrap_or("/".to_string())
+                libc::DT_REG
             };
-            let parent_stats = self
-                .fs
-                .stat(&parent_path)
-                .await
-                .map_err(|e| VfsError::Other(format!("Failed to stat parent dir: {}", e)))?
-                .ok_or(VfsError::NotFound)?;
-            let parent_ino = parent_stats.ino as u64;
+            result.push(DirEntry {
+                ino: entry.stats.ino as u64,
+                d_type,
+                name: entry.name,
+            });
+        }
 
-            result.push((current_ino, ".".to_string(), libc::DT_DIR));
-            result.push((parent_ino, "..".to_string(), libc::DT_DIR));
+        // Cache the listing so that pagination sees a stable snapshot
+        *self.entries.lock().unwrap() = Some(result.clone());
+        Ok(result)
+    }
+}
 
-            for name in dir_entries {
-                // Construct the full path for this entry
-                let entry_path = if self.path == "/" {
-                    format!("/{}", name)
-                } else {
-                    format!("{}/{}", self.path, name)
-                };
-
-                // Get stats to determine type and inode
-                if let Ok(Some(stats)) = self.fs.stat(&entry_path).await {
-                    let d_type = if stats.is_directory() {
-                        libc::DT_DIR
-                    } else if stats.is_symlink() {
-                        libc::DT_LNK
-                    } else {
-                        libc::DT_REG
-                    };
-                    result.push((stats.ino as u64, name, d_type));
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn create_test_vfs() -> (SqliteVfs, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let vfs = SqliteVfs::new(&db_path, PathBuf::from("/agent"))
+            .await
+            .unwrap();
+        (vfs, dir)
+    }
+
+    #[tokio::test]
+    async fn test_translate_path_matches_whole_components() {
+        let (vfs, _dir) = create_test_vfs().await;
+
+        assert!(vfs.translate_path(Path::new("/agent")).is_ok());
+        assert!(vfs.translate_path(Path::new("/agent/file")).is_ok());
+        assert!(vfs.translate_path(Path::new("/agentfoo")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_utimes_survives_close() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        vfs.write(&file, 0, b"data").await.unwrap();
+        vfs.futimes(&file, Some(100), Some(200)).await.unwrap();
+        file.close().await.unwrap();
+
+        // The buffered data was stored before the times, so closing keeps them
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_atime, stat.st_mtime, stat.st_size), (100, 200, 4));
+
+        vfs.utimes(path, None, Some(300)).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_atime, stat.st_mtime), (100, 300));
+    }
+
+    #[tokio::test]
+    async fn test_open_missing_without_creat() {
+        let (vfs, _dir) = create_test_vfs().await;
+
+        let result = vfs
+            .open(Path::new("/agent/missing.txt"), libc::O_RDONLY, 0)
+            .await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_open_creat_excl() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL;
+
+        let file = vfs.open(path, flags, 0o600).await.unwrap();
+        file.close().await.unwrap();
+
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_mode & 0o777, 0o600);
+
+        let result = vfs.open(path, flags, 0o600).await;
+        assert!(matches!(result, Err(VfsError::AlreadyExists)));
+        assert_eq!(result.err().unwrap().to_errno(), -libc::EEXIST as i64);
+    }
+
+    #[tokio::test]
+    async fn test_open_creat_missing_parent() {
+        let (vfs, _dir) = create_test_vfs().await;
+
+        let result = vfs
+            .open(
+                Path::new("/agent/nodir/file.txt"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_open_trunc() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/trunc.txt");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        file.write(b"hello").await.unwrap();
+        file.close().await.unwrap();
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_TRUNC, 0)
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_open_directory_flags() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+
+        let result = vfs.open(path, libc::O_RDONLY | libc::O_DIRECTORY, 0).await;
+        assert!(matches!(result, Err(VfsError::NotADirectory)));
+
+        let result = vfs.open(Path::new("/agent"), libc::O_WRONLY, 0).await;
+        assert!(matches!(result, Err(VfsError::IsADirectory)));
+    }
+
+    #[tokio::test]
+    async fn test_read_partial_and_past_eof() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/data.txt");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        file.write(b"hello world").await.unwrap();
+        file.close().await.unwrap();
+
+        let file = vfs.open(path, libc::O_RDONLY, 0).await.unwrap();
+        assert_eq!(vfs.read(&file, 0, 5).await.unwrap(), b"hello");
+        assert_eq!(vfs.read(&file, 6, 100).await.unwrap(), b"world");
+        assert!(vfs.read(&file, 11, 10).await.unwrap().is_empty());
+        assert!(vfs.read(&file, 100, 10).await.unwrap().is_empty());
+
+        // pread must not move the file offset
+        assert_eq!(file.seek(0, libc::SEEK_CUR).await.unwrap(), 0);
+    }
 
-            // Store the results
-            let mut entries_lock = self.entries.lock().unwrap();
-            *entries_lock = Some(result);
+    #[tokio::test]
+    async fn test_read_only_open_reads_blocks() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/data.txt");
+
+        let writer = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        writer.write(b"hello").await.unwrap();
+        writer.close().await.unwrap();
+
+        // Nothing is buffered at open, so reads see data stored afterwards
+        let reader = vfs.open(path, libc::O_RDONLY, 0).await.unwrap();
+        let writer = vfs.open(path, libc::O_WRONLY, 0).await.unwrap();
+        vfs.write(&writer, 5, b" world").await.unwrap();
+        writer.close().await.unwrap();
+        assert_eq!(vfs.read(&reader, 0, 100).await.unwrap(), b"hello world");
+        assert_eq!(reader.fstat().await.unwrap().st_size, 11);
+
+        let result = vfs.write(&reader, 0, b"x").await;
+        assert_eq!(result.unwrap_err().to_errno(), -libc::EBADF as i64);
+        reader.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_extends_file() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/sparse.bin");
+
+        let file = vfs
+            .open(path, libc::O_RDWR | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        assert_eq!(vfs.write(&file, 0, b"abc").await.unwrap(), 3);
+        assert_eq!(vfs.write(&file, 6, b"xyz").await.unwrap(), 3);
+        file.close().await.unwrap();
+
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_size, 9);
+
+        let file = vfs.open(path, libc::O_RDONLY, 0).await.unwrap();
+        assert_eq!(vfs.read(&file, 0, 9).await.unwrap(), b"abc\0\0\0xyz");
+    }
+
+    #[tokio::test]
+    async fn test_write_past_size_limit() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/huge.bin");
+
+        let file = vfs
+            .open(path, libc::O_RDWR | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        let err = vfs.write(&file, 1 << 40, b"x").await.unwrap_err();
+        assert_eq!(err.to_errno(), -libc::EFBIG as i64);
+        let err = vfs.write(&file, u64::MAX, b"x").await.unwrap_err();
+        assert_eq!(err.to_errno(), -libc::EFBIG as i64);
+        file.close().await.unwrap();
+
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_ftruncate_past_size_limit() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/huge.bin");
+
+        let file = vfs
+            .open(path, libc::O_RDWR | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        vfs.write(&file, 0, b"abc").await.unwrap();
+        let err = vfs.ftruncate(&file, 1 << 40).await.unwrap_err();
+        assert_eq!(err.to_errno(), -libc::EFBIG as i64);
+        let err = vfs.ftruncate(&file, u64::MAX).await.unwrap_err();
+        assert_eq!(err.to_errno(), -libc::EFBIG as i64);
+        file.close().await.unwrap();
+
+        let file = vfs.open(path, libc::O_RDONLY, 0).await.unwrap();
+        assert_eq!(vfs.read(&file, 0, 16).await.unwrap(), b"abc");
+    }
+
+    #[tokio::test]
+    async fn test_rename_flags() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let a = Path::new("/agent/a.txt");
+        let b = Path::new("/agent/b.txt");
+
+        for (path, contents) in [(a, b"aaa"), (b, b"bbb")] {
+            let file = vfs
+                .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+                .await
+                .unwrap();
+            file.write(contents).await.unwrap();
+            file.close().await.unwrap();
         }
 
-        // Get the current position and return entries
-        let mut position = self.position.lock().unwrap();
-        let entries_lock = self.entries.lock().unwrap();
-        let all_entries = entries_lock.as_ref().unwrap();
+        let result = vfs.rename(a, b, libc::RENAME_NOREPLACE).await;
+        assert!(matches!(result, Err(VfsError::AlreadyExists)));
 
-        if *position >= all_entries.len() {
-            // No more entries - return empty to signal EOF
-            Ok(Vec::new())
-        } else {
-            // Return remaining entries and update position
-            let remaining = all_entries[*position..].to_vec();
-            *position = all_entries.len();
-            Ok(remaining)
+        vfs.rename(a, b, libc::RENAME_EXCHANGE).await.unwrap();
+        let file = vfs.open(a, libc::O_RDONLY, 0).await.unwrap();
+        assert_eq!(vfs.read(&file, 0, 3).await.unwrap(), b"bbb");
+        let file = vfs.open(b, libc::O_RDONLY, 0).await.unwrap();
+        assert_eq!(vfs.read(&file, 0, 3).await.unwrap(), b"aaa");
+
+        vfs.rename(a, b, 0).await.unwrap();
+        assert!(matches!(vfs.stat(a).await, Err(VfsError::NotFound)));
+
+        let result = vfs.rename(a, b, libc::RENAME_EXCHANGE).await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_unlink_and_rmdir() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let file_path = Path::new("/agent/dir/file.txt");
+
+        vfs.fs.mkdir("/dir").await.unwrap();
+        let file = vfs
+            .open(file_path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+
+        let dir_path = Path::new("/agent/dir");
+        assert!(matches!(
+            vfs.unlink(dir_path).await,
+            Err(VfsError::IsADirectory)
+        ));
+        assert!(matches!(vfs.rmdir(dir_path).await, Err(VfsError::NotEmpty)));
+        assert!(matches!(
+            vfs.rmdir(file_path).await,
+            Err(VfsError::NotADirectory)
+        ));
+
+        vfs.unlink(file_path).await.unwrap();
+        assert!(matches!(
+            vfs.unlink(file_path).await,
+            Err(VfsError::NotFound)
+        ));
+        vfs.rmdir(dir_path).await.unwrap();
+        assert!(matches!(vfs.stat(dir_path).await, Err(VfsError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_mkdir() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/newdir");
+
+        vfs.mkdir(path, 0o700).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_mode & libc::S_IFMT, libc::S_IFDIR);
+        assert_eq!(stat.st_mode & 0o777, 0o700);
+
+        let result = vfs.mkdir(path, 0o755).await;
+        assert!(matches!(result, Err(VfsError::AlreadyExists)));
+
+        let result = vfs.mkdir(Path::new("/agent/missing/child"), 0o755).await;
+        assert!(matches!(result, Err(VfsError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_readdir_entries_and_position() {
+        let (vfs, _dir) = create_test_vfs().await;
+
+        vfs.mkdir(Path::new("/agent/sub"), 0o755).await.unwrap();
+        let file = vfs
+            .open(
+                Path::new("/agent/file.txt"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        vfs.symlink(Path::new("file.txt"), Path::new("/agent/link"))
+            .await
+            .unwrap();
+
+        let dir = vfs
+            .open(Path::new("/agent"), libc::O_RDONLY | libc::O_DIRECTORY, 0)
+            .await
+            .unwrap();
+        let entries = vfs.readdir(&dir).await.unwrap();
+        let mut names: Vec<_> = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.d_type))
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                (".", libc::DT_DIR),
+                ("..", libc::DT_DIR),
+                ("file.txt", libc::DT_REG),
+                ("link", libc::DT_LNK),
+                ("sub", libc::DT_DIR),
+            ]
+        );
+
+        // The position is an index into the listing and survives repeated listings
+        assert_eq!(dir.seek(0, libc::SEEK_CUR).await.unwrap(), 0);
+        assert_eq!(dir.seek(2, libc::SEEK_SET).await.unwrap(), 2);
+        assert_eq!(dir.seek(1, libc::SEEK_CUR).await.unwrap(), 3);
+        assert_eq!(vfs.readdir(&dir).await.unwrap(), entries);
+        assert!(matches!(
+            dir.seek(0, libc::SEEK_END).await,
+            Err(VfsError::InvalidInput(_))
+        ));
+
+        let file = vfs
+            .open(Path::new("/agent/file.txt"), libc::O_RDONLY, 0)
+            .await
+            .unwrap();
+        assert!(matches!(
+            vfs.readdir(&file).await,
+            Err(VfsError::NotADirectory)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_readdir_inodes_match_stat() {
+        let (vfs, _dir) = create_test_vfs().await;
+
+        vfs.mkdir(Path::new("/agent/sub"), 0o755).await.unwrap();
+        let file = vfs
+            .open(
+                Path::new("/agent/file.txt"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        vfs.link(Path::new("/agent/file.txt"), Path::new("/agent/hard"))
+            .await
+            .unwrap();
+        vfs.symlink(Path::new("file.txt"), Path::new("/agent/soft"))
+            .await
+            .unwrap();
+
+        let dir = vfs
+            .open(Path::new("/agent"), libc::O_RDONLY | libc::O_DIRECTORY, 0)
+            .await
+            .unwrap();
+        let entries = vfs.readdir(&dir).await.unwrap();
+        let entry = |name: &str| entries.iter().find(|e| e.name == name).unwrap().clone();
+
+        // Every entry reports the inode its path stats to
+        for name in ["file.txt", "hard", "sub"] {
+            let path = Path::new("/agent").join(name);
+            let stat = vfs.stat(&path).await.unwrap();
+            assert_eq!(entry(name).ino, stat.st_ino, "{name}");
         }
+        let stat = vfs.lstat(Path::new("/agent/soft")).await.unwrap();
+        assert_eq!(entry("soft").ino, stat.st_ino);
+        assert_eq!(entry("soft").d_type, libc::DT_LNK);
+        assert_eq!(entry("sub").d_type, libc::DT_DIR);
+
+        // Both names of a hard link are the same inode, which a symlink isn't
+        assert_eq!(entry("hard").ino, entry("file.txt").ino);
+        assert_eq!(entry("hard").d_type, libc::DT_REG);
+        assert_ne!(entry("soft").ino, entry("file.txt").ino);
+        let stat = vfs.stat(Path::new("/agent/hard")).await.unwrap();
+        assert_eq!(stat.st_nlink, 2);
+    }
+
+    #[tokio::test]
+    async fn test_chmod_follows_symlinks() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+        let link = Path::new("/agent/link");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        vfs.symlink(Path::new("file.txt"), link).await.unwrap();
+
+        vfs.chmod(link, 0o600).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_mode & libc::S_IFMT, libc::S_IFREG);
+        assert_eq!(stat.st_mode & 0o7777, 0o600);
+        let lstat = vfs.lstat(link).await.unwrap();
+        assert_eq!(lstat.st_mode & libc::S_IFMT, libc::S_IFLNK);
+
+        let missing = vfs.chmod(Path::new("/agent/missing"), 0o600).await;
+        assert!(matches!(missing, Err(VfsError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_stat_cache_invalidation() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+        let link = Path::new("/agent/link.txt");
+        let renamed = Path::new("/agent/renamed.txt");
+
+        // A cached miss must not hide a file created afterwards
+        assert!(matches!(vfs.stat(path).await, Err(VfsError::NotFound)));
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        assert_eq!(vfs.stat(path).await.unwrap().st_size, 0);
+
+        vfs.write(&file, 0, b"data").await.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(vfs.stat(path).await.unwrap().st_size, 4);
+
+        // Changes through one name are seen through the others
+        vfs.link(path, link).await.unwrap();
+        assert_eq!(vfs.stat(link).await.unwrap().st_nlink, 2);
+        vfs.chmod(path, 0o600).await.unwrap();
+        assert_eq!(vfs.stat(link).await.unwrap().st_mode & 0o7777, 0o600);
+        vfs.chown(path, Some(1234), None).await.unwrap();
+        assert_eq!(vfs.stat(link).await.unwrap().st_uid, 1234);
+        vfs.truncate(path, 1).await.unwrap();
+        assert_eq!(vfs.stat(link).await.unwrap().st_size, 1);
+
+        vfs.rename(link, renamed, 0).await.unwrap();
+        assert!(matches!(vfs.stat(link).await, Err(VfsError::NotFound)));
+        vfs.unlink(renamed).await.unwrap();
+        assert_eq!(vfs.stat(path).await.unwrap().st_nlink, 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_owner() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let vfs = vfs.with_owner(1234, 5678);
+
+        let stat = vfs.stat(Path::new("/agent")).await.unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid), (1234, 5678));
+    }
+
+    #[tokio::test]
+    async fn test_chown_and_lchown() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let vfs = vfs.with_owner(1000, 1000);
+        let path = Path::new("/agent/file.txt");
+        let link = Path::new("/agent/link");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        vfs.symlink(Path::new("file.txt"), link).await.unwrap();
+
+        // New files are reported as owned by the mount owner
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid), (1000, 1000));
+
+        // chown follows the link and leaves a `None` field unchanged
+        vfs.chown(link, Some(2000), None).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid), (2000, 1000));
+        let lstat = vfs.lstat(link).await.unwrap();
+        assert_eq!((lstat.st_uid, lstat.st_gid), (1000, 1000));
+
+        // lchown changes the link itself
+        vfs.lchown(link, Some(3000), Some(3000)).await.unwrap();
+        let lstat = vfs.lstat(link).await.unwrap();
+        assert_eq!((lstat.st_uid, lstat.st_gid), (3000, 3000));
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid), (2000, 1000));
+
+        // Ownership by root is stored as such, not as unset
+        vfs.chown(path, Some(0), Some(0)).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_id_map() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let id_map = IdMap::new(vec![(0, 1000), (500, 2000)], vec![(0, 100)]);
+        let vfs = vfs.with_owner(1000, 100).with_id_map(id_map);
+        let path = Path::new("/agent/file.txt");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        let fstat = file.fstat().await.unwrap();
+        assert_eq!((fstat.st_uid, fstat.st_gid), (0, 0));
+        file.close().await.unwrap();
+
+        // The mount owner shows up as the mapped IDs
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid), (0, 0));
+
+        // chown stores the host ID and reports the inside one
+        vfs.chown(path, Some(500), Some(42)).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!((stat.st_uid, stat.st_gid), (500, 42));
+        let stored = vfs.fs.stat("/file.txt").await.unwrap().unwrap();
+        assert_eq!((stored.uid, stored.gid), (2000, 42));
+    }
+
+    #[tokio::test]
+    async fn test_truncate_shrinks_and_grows() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+        let data: Vec<u8> = (0..100).collect();
+
+        let file = vfs
+            .open(path, libc::O_RDWR | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        vfs.write(&file, 0, &data).await.unwrap();
+        file.close().await.unwrap();
+
+        // Shrink by path
+        vfs.truncate(path, 40).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_size, 40);
+
+        // Grow through an open handle, which zero-fills the tail
+        let file = vfs.open(path, libc::O_RDWR, 0).await.unwrap();
+        vfs.ftruncate(&file, 64).await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_size, 64);
+        let contents = vfs.read(&file, 0, 100).await.unwrap();
+        assert_eq!(&contents[..40], &data[..40]);
+        assert!(contents[40..].iter().all(|&b| b == 0));
+        file.close().await.unwrap();
+
+        let dir = vfs.truncate(Path::new("/agent"), 0).await;
+        assert!(matches!(dir, Err(VfsError::IsADirectory)));
+    }
+
+    #[tokio::test]
+    async fn test_fallocate_preallocate_and_punch_hole() {
+        use crate::vfs::file::{FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE};
+
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+        let data = vec![7u8; 100];
+
+        let file = vfs
+            .open(path, libc::O_RDWR | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        vfs.write(&file, 0, &data).await.unwrap();
+
+        // Preallocating past the end grows the file with zeros
+        vfs.fallocate(&file, 0, 50, 150).await.unwrap();
+        assert_eq!(file.fstat().await.unwrap().st_size, 200);
+        let contents = vfs.read(&file, 0, 200).await.unwrap();
+        assert_eq!(&contents[..100], &data[..]);
+        assert!(contents[100..].iter().all(|&b| b == 0));
+
+        // Unless the size is kept
+        vfs.fallocate(&file, FALLOC_FL_KEEP_SIZE, 0, 4096)
+            .await
+            .unwrap();
+        assert_eq!(file.fstat().await.unwrap().st_size, 200);
+
+        // Punching a hole zeroes the range without changing the size
+        let punch = FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE;
+        vfs.fallocate(&file, punch, 10, 1000).await.unwrap();
+        file.close().await.unwrap();
+        let stat = vfs.stat(path).await.unwrap();
+        assert_eq!(stat.st_size, 200);
+        let contents = vfs.fs.read_file("/file.txt").await.unwrap().unwrap();
+        assert_eq!(&contents[..10], &data[..10]);
+        assert!(contents[10..].iter().all(|&b| b == 0));
+
+        // A hole can't be punched without keeping the size
+        let file = vfs.open(path, libc::O_RDWR, 0).await.unwrap();
+        let result = vfs.fallocate(&file, FALLOC_FL_PUNCH_HOLE, 0, 10).await;
+        assert!(matches!(result, Err(VfsError::NotSupported)));
+        file.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_xattr_flags_and_limit() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+        let link = Path::new("/agent/link");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        vfs.symlink(Path::new("file.txt"), link).await.unwrap();
+
+        // Attributes set through the link land on the target
+        vfs.setxattr(link, "user.a", b"1", 0, true).await.unwrap();
+        assert_eq!(vfs.getxattr(path, "user.a", true).await.unwrap(), b"1");
+        assert!(vfs.listxattr(link, false).await.unwrap().is_empty());
+
+        let create = vfs
+            .setxattr(path, "user.a", b"2", libc::XATTR_CREATE, true)
+            .await;
+        assert!(matches!(create, Err(VfsError::AlreadyExists)));
+        let replace = vfs
+            .setxattr(path, "user.b", b"2", libc::XATTR_REPLACE, true)
+            .await;
+        assert!(matches!(replace, Err(VfsError::NoData)));
+
+        let missing = vfs.getxattr(path, "user.b", true).await;
+        assert!(matches!(missing, Err(VfsError::NoData)));
+
+        // Replacing a value only counts the new one against the cap
+        let big = vec![0u8; MAX_XATTR_SIZE - "user.a".len()];
+        vfs.setxattr(path, "user.a", &big, 0, true).await.unwrap();
+        let over = vfs.setxattr(path, "user.b", b"x", 0, true).await;
+        assert!(matches!(over, Err(VfsError::TooBig)));
+
+        vfs.removexattr(path, "user.a", true).await.unwrap();
+        let removed = vfs.removexattr(path, "user.a", true).await;
+        assert!(matches!(removed, Err(VfsError::NoData)));
+    }
+
+    #[tokio::test]
+    async fn test_fsync_survives_reopen() {
+        let (vfs, dir) = create_test_vfs().await;
+        let path = Path::new("/agent/file.txt");
+
+        let file = vfs
+            .open(path, libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await
+            .unwrap();
+        vfs.write(&file, 0, b"durable").await.unwrap();
+        file.fsync().await.unwrap();
+        file.fdatasync().await.unwrap();
+
+        // Reopen the database without closing the file first
+        drop(file);
+        drop(vfs);
+        let vfs = SqliteVfs::new(dir.path().join("test.db"), PathBuf::from("/agent"))
+            .await
+            .unwrap();
+
+        let file = vfs.open(path, libc::O_RDONLY, 0).await.unwrap();
+        assert_eq!(vfs.read(&file, 0, 64).await.unwrap(), b"durable");
+    }
+
+    #[tokio::test]
+    async fn test_symlink_loop() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let link = Path::new("/agent/loop");
+        vfs.symlink(Path::new("loop"), link).await.unwrap();
+
+        assert!(matches!(vfs.stat(link).await, Err(VfsError::SymlinkLoop)));
+        let opened = vfs.open(link, libc::O_RDONLY, 0).await;
+        assert!(matches!(opened, Err(VfsError::SymlinkLoop)));
+        assert_eq!(VfsError::SymlinkLoop.to_errno(), -libc::ELOOP as i64);
+
+        // The link itself can still be inspected
+        let lstat = vfs.lstat(link).await.unwrap();
+        assert_eq!(lstat.st_mode & libc::S_IFMT, libc::S_IFLNK);
+    }
+
+    #[tokio::test]
+    async fn test_name_too_long() {
+        let (vfs, _dir) = create_test_vfs().await;
+
+        let long_name = format!("/agent/{}", "a".repeat(256));
+        let result = vfs.stat(Path::new(&long_name)).await;
+        assert!(matches!(result, Err(VfsError::NameTooLong)));
+
+        let long_path = format!("/agent{}", "/dir".repeat(1024));
+        let result = vfs
+            .open(Path::new(&long_path), libc::O_WRONLY | libc::O_CREAT, 0o644)
+            .await;
+        assert!(matches!(result, Err(VfsError::NameTooLong)));
+        assert_eq!(VfsError::NameTooLong.to_errno(), -libc::ENAMETOOLONG as i64);
+    }
+
+    #[tokio::test]
+    async fn test_symlinked_parent_directories() {
+        let (vfs, _dir) = create_test_vfs().await;
+        vfs.mkdir(Path::new("/agent/real"), 0o755).await.unwrap();
+        vfs.mkdir(Path::new("/agent/real/sub"), 0o755)
+            .await
+            .unwrap();
+        vfs.symlink(Path::new("real"), Path::new("/agent/rel"))
+            .await
+            .unwrap();
+        vfs.symlink(Path::new("/rel/sub"), Path::new("/agent/abs"))
+            .await
+            .unwrap();
+
+        // Create and read back through a relative link to the parent directory
+        let file = vfs
+            .open(
+                Path::new("/agent/rel/file.txt"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        vfs.write(&file, 0, b"data").await.unwrap();
+        file.close().await.unwrap();
+        let stat = vfs.stat(Path::new("/agent/real/file.txt")).await.unwrap();
+        assert_eq!(stat.st_size, 4);
+
+        // Chains of links, absolute targets and `..` after a link
+        vfs.mkdir(Path::new("/agent/abs/nested"), 0o755)
+            .await
+            .unwrap();
+        let nested = vfs.stat(Path::new("/agent/real/sub/nested")).await.unwrap();
+        assert_eq!(nested.st_mode & libc::S_IFMT, libc::S_IFDIR);
+        let up = vfs.stat(Path::new("/agent/abs/../file.txt")).await.unwrap();
+        assert_eq!(up.st_ino, stat.st_ino);
+
+        let dir = vfs
+            .open(Path::new("/agent/rel/sub"), libc::O_RDONLY, 0)
+            .await
+            .unwrap();
+        let names: Vec<String> = vfs
+            .readdir(&dir)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .filter(|name| name != "." && name != "..")
+            .collect();
+        assert_eq!(names, vec!["nested".to_string()]);
+
+        // The final component is only followed when asked to
+        vfs.symlink(Path::new("file.txt"), Path::new("/agent/real/last"))
+            .await
+            .unwrap();
+        let lstat = vfs.lstat(Path::new("/agent/rel/last")).await.unwrap();
+        assert_eq!(lstat.st_mode & libc::S_IFMT, libc::S_IFLNK);
+        let stat = vfs.stat(Path::new("/agent/rel/last")).await.unwrap();
+        assert_eq!(stat.st_mode & libc::S_IFMT, libc::S_IFREG);
+        let nofollow = vfs
+            .open(
+                Path::new("/agent/rel/last"),
+                libc::O_RDONLY | libc::O_NOFOLLOW,
+                0,
+            )
+            .await;
+        assert!(matches!(nofollow, Err(VfsError::SymlinkLoop)));
+    }
+
+    #[tokio::test]
+    async fn test_symlinked_parent_loop() {
+        let (vfs, _dir) = create_test_vfs().await;
+        vfs.symlink(Path::new("b"), Path::new("/agent/a"))
+            .await
+            .unwrap();
+        vfs.symlink(Path::new("a"), Path::new("/agent/b"))
+            .await
+            .unwrap();
+
+        let result = vfs.lstat(Path::new("/agent/a/file")).await;
+        assert!(matches!(result, Err(VfsError::SymlinkLoop)));
+    }
+
+    #[tokio::test]
+    async fn test_statfs_reports_usage() {
+        let (vfs, _dir) = create_test_vfs().await;
+        let empty = vfs.statfs().await.unwrap();
+        assert_eq!(empty.fs_type, AGENTFS_MAGIC);
+        assert_eq!(empty.block_size, STATFS_BLOCK_SIZE);
+
+        let file = vfs
+            .open(
+                Path::new("/agent/file.bin"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        vfs.write(&file, 0, &vec![1u8; 3 * STATFS_BLOCK_SIZE as usize])
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+
+        let stats = vfs.statfs().await.unwrap();
+        assert_eq!(stats.blocks, empty.blocks);
+        assert_eq!(stats.blocks_free, empty.blocks_free - 3);
+        assert_eq!(stats.blocks_available, stats.blocks_free);
+        assert_eq!(stats.files_free, empty.files_free - 1);
+    }
+
+    #[tokio::test]
+    async fn test_quota_write_no_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let quota = agentfs_sdk::Quota {
+            bytes: Some(2 * STATFS_BLOCK_SIZE),
+            inodes: None,
+        };
+        let options = agentfs_sdk::AgentFSOptions::with_path(db_path.to_str().unwrap());
+        agentfs_sdk::AgentFS::open(options.with_quota(quota))
+            .await
+            .unwrap();
+        let vfs = SqliteVfs::new(&db_path, PathBuf::from("/agent"))
+            .await
+            .unwrap();
+
+        let stats = vfs.statfs().await.unwrap();
+        assert_eq!(stats.blocks, 2);
+
+        let file = vfs
+            .open(
+                Path::new("/agent/file.bin"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        let full = vec![1u8; 2 * STATFS_BLOCK_SIZE as usize];
+        assert_eq!(vfs.write(&file, 0, &full).await.unwrap(), full.len());
+        let err = vfs.write(&file, full.len() as u64, b"x").await.unwrap_err();
+        assert_eq!(err.to_errno(), -libc::ENOSPC as i64);
+        file.close().await.unwrap();
+
+        let stats = vfs.statfs().await.unwrap();
+        assert_eq!(stats.blocks_free, 0);
+    }
+
+    #[tokio::test]
+    async fn test_open_with_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        let quota = agentfs_sdk::Quota {
+            bytes: Some(4 * STATFS_BLOCK_SIZE),
+            inodes: None,
+        };
+        let options = AgentFSOptions::with_path(db_path.to_str().unwrap())
+            .with_quota(quota)
+            .with_block_cache_size(0);
+        let vfs = SqliteVfs::open(options, PathBuf::from("/agent"))
+            .await
+            .unwrap();
+
+        let stats = vfs.statfs().await.unwrap();
+        assert_eq!(stats.blocks, 4);
+
+        let file = vfs
+            .open(
+                Path::new("/agent/file.txt"),
+                libc::O_RDWR | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        vfs.write(&file, 0, b"uncached").await.unwrap();
+        assert_eq!(vfs.read(&file, 0, 64).await.unwrap(), b"uncached");
+        file.close().await.unwrap();
     }
 }
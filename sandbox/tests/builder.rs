@@ -0,0 +1,57 @@
+//! Spawning a guest through `SandboxBuilder`.
+//!
+//! The guest writes a file under `/workspace`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{MemoryVfs, SandboxBuilder, Vfs};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Write the value of `GREETING` to a file in the working directory
+fn greet() -> std::io::Result<bool> {
+    let cwd = std::env::current_dir()?;
+    let greeting = std::env::var("GREETING").unwrap_or_default();
+    fs::write("greeting.txt", &greeting)?;
+    let written = fs::read_to_string("/workspace/greeting.txt")? == greeting;
+    Ok(written && cwd == Path::new("/workspace"))
+}
+
+#[test]
+fn test_builder_spawn() {
+    if common::stage().is_some() {
+        let passed = greet().unwrap_or(false);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mount_point = PathBuf::from("/workspace");
+        let vfs = MemoryVfs::new(mount_point.clone());
+
+        let handle = SandboxBuilder::new()
+            .mount(mount_point, Arc::new(vfs.clone()))
+            .env(common::STAGE_VAR, "greet")
+            .env("GREETING", "hello")
+            .workdir("/workspace")
+            .spawn(
+                std::env::current_exe().unwrap(),
+                ["test_builder_spawn", "--exact", "--test-threads=1"],
+            )
+            .await
+            .unwrap();
+        let exit = handle.wait().await.unwrap();
+        assert!(exit.success(), "guest exited with {:?}", exit.status);
+        assert!(!exit.timed_out);
+        assert!(exit.open_fds.is_empty());
+
+        let stat = vfs
+            .stat(Path::new("/workspace/greeting.txt"))
+            .await
+            .unwrap();
+        assert_eq!(stat.st_size, 5);
+    });
+}
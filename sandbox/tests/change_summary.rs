@@ -0,0 +1,72 @@
+//! Summarize the files a traced guest changes in a virtual mount.
+#![cfg(target_os = "linux")]
+
+use agentfs_sandbox::{
+    changed_files, close_virtual_files, init_change_tracking, init_fd_tables, init_mount_table,
+    init_strace, ChangeKind, MountTable, Sandbox, SqliteVfs, Vfs,
+};
+use reverie_process::{Command, ExitStatus};
+use reverie_ptrace::TracerBuilder;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[tokio::test]
+async fn test_change_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let mount_point = PathBuf::from("/agent");
+    let vfs = SqliteVfs::new(&dir.path().join("agent.db"), mount_point.clone())
+        .await
+        .unwrap();
+    let file = vfs
+        .open(
+            Path::new("/agent/old.txt"),
+            libc::O_WRONLY | libc::O_CREAT,
+            0o644,
+        )
+        .await
+        .unwrap();
+    file.close().await.unwrap();
+
+    let mut mount_table = MountTable::new();
+    mount_table.add_mount(mount_point, Arc::new(vfs));
+    init_mount_table(mount_table);
+    init_fd_tables();
+    init_strace(false);
+    init_change_tracking(true);
+
+    // The summary is collected even though the command fails
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c")
+        .arg("echo hello > /agent/new.txt && rm /agent/old.txt && cat /agent/old.txt");
+
+    let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+    let (status, _) = tracer.wait().await.unwrap();
+    assert_eq!(status, ExitStatus::Exited(1));
+    close_virtual_files().await;
+
+    let hello = blake3::hash(b"hello\n").to_hex().to_string();
+    let changes: Vec<_> = changed_files()
+        .await
+        .into_iter()
+        .map(|change| (change.path, change.change, change.size, change.hash))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (
+                PathBuf::from("/agent/new.txt"),
+                ChangeKind::Created,
+                Some(6),
+                Some(hello)
+            ),
+            (
+                PathBuf::from("/agent/old.txt"),
+                ChangeKind::Deleted,
+                None,
+                None
+            ),
+        ]
+    );
+}
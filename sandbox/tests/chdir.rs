@@ -0,0 +1,77 @@
+//! Changing into a virtual directory from a traced guest.
+//!
+//! The guest changes into directories of a virtual mount.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{MountTable, Vfs};
+use reverie_process::ExitStatus;
+use std::{
+    ffi::CString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Change into `path`, returning 0 or the errno
+fn chdir(path: &str) -> i32 {
+    let path = CString::new(path).unwrap();
+    if unsafe { libc::chdir(path.as_ptr()) } == 0 {
+        0
+    } else {
+        std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+    }
+}
+
+/// Guest stage: exit with 0 if `chdir` into a subdirectory of `/data` succeeds and
+/// `file` then opens relative to it, while missing directories, files and
+/// directories without search permission fail.
+fn change_into() -> ! {
+    // Directories that don't exist or aren't directories are refused
+    let refused =
+        chdir("/data/missing") == libc::ENOENT && chdir("/data/sub/file") == libc::ENOTDIR;
+
+    // Root may search any directory
+    let locked = if unsafe { libc::geteuid() } == 0 {
+        0
+    } else {
+        libc::EACCES
+    };
+    let denied = chdir("/data/locked") == locked;
+
+    // A relative path from a virtual directory leads to another one
+    let changed = chdir("/data") == 0 && chdir("sub") == 0;
+    let cwd = std::env::current_dir().ok();
+
+    let file = CString::new("file").unwrap();
+    let fd = unsafe { libc::open(file.as_ptr(), libc::O_RDONLY) };
+    let mut buf = [0u8; 16];
+    let read = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+    let opened = fd >= 0 && read == 5 && &buf[..5] == b"hello";
+    unsafe { libc::close(fd) };
+
+    let passed = refused && denied && changed && cwd == Some(PathBuf::from("/data/sub")) && opened;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_chdir_virtual_dir() {
+    if common::stage().is_some() {
+        change_into();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/data");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+
+        vfs.mkdir(Path::new("/data/sub"), 0o755).await.unwrap();
+        vfs.mkdir(Path::new("/data/locked"), 0o644).await.unwrap();
+        common::write_file(&vfs, "/data/sub/file", b"hello").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_chdir_virtual_dir", "chdir", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
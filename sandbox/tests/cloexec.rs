@@ -0,0 +1,72 @@
+//! Drop close-on-exec virtual FDs when a traced guest execs.
+//!
+//! The first guest stage opens a virtual file with and without `O_CLOEXEC` and
+//! re-execs, and the second stage checks which FDs survived.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, os::unix::process::CommandExt, path::PathBuf, sync::Arc};
+
+/// The FDs opened by the first stage, as `<cloexec>,<kept>`
+const FDS_VAR: &str = "AGENTFS_CLOEXEC_FDS";
+
+/// First guest stage: open the virtual file twice and exec the second stage.
+fn open_and_exec() -> ! {
+    let path = CString::new("/agent/hello.txt").unwrap();
+    let cloexec_fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    let kept_fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+    assert!(cloexec_fd >= 0 && kept_fd >= 0);
+
+    let err = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(std::env::args_os().skip(1))
+        .env(common::STAGE_VAR, "check")
+        .env(FDS_VAR, format!("{cloexec_fd},{kept_fd}"))
+        .exec();
+    panic!("exec failed: {err}");
+}
+
+/// Second guest stage: exit with 0 if only the FD without `O_CLOEXEC` is still open.
+fn check_fds() -> ! {
+    let fds = std::env::var(FDS_VAR).unwrap();
+    let (cloexec_fd, kept_fd) = fds.split_once(',').unwrap();
+    let cloexec_fd: i32 = cloexec_fd.parse().unwrap();
+    let kept_fd: i32 = kept_fd.parse().unwrap();
+
+    let mut buf = [0u8; 16];
+    let closed = unsafe { libc::read(cloexec_fd, buf.as_mut_ptr().cast(), buf.len()) } < 0
+        && std::io::Error::last_os_error().raw_os_error() == Some(libc::EBADF);
+    let n = unsafe { libc::read(kept_fd, buf.as_mut_ptr().cast(), buf.len()) };
+    let kept = n == 5 && &buf[..5] == b"hello";
+
+    std::process::exit(if closed && kept { 0 } else { 1 });
+}
+
+#[test]
+fn test_cloexec_virtual_fd_closed_on_exec() {
+    // Check the stage before anything else opens FDs in the guest
+    match common::stage().as_deref() {
+        Some("open") => open_and_exec(),
+        Some("check") => check_fds(),
+        _ => {}
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+        common::write_file(&vfs, "/agent/hello.txt", b"hello").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest(
+            "test_cloexec_virtual_fd_closed_on_exec",
+            "open",
+            mount_table,
+        )
+        .await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
@@ -0,0 +1,77 @@
+//! Harness for tests that run a traced guest.
+//!
+//! The guest is the test binary itself, re-run under the tracer with only the
+//! test at hand selected and `STAGE_VAR` set to the stage it should run. Each
+//! test checks `stage()` first, and in the guest runs that stage and exits
+//! instead of starting the tracer again.
+#![allow(dead_code)]
+
+use agentfs_sandbox::{
+    init_fd_tables, init_mount_table, init_strace, MountTable, Sandbox, SqliteVfs, Vfs,
+};
+use reverie_process::{Command, ExitStatus};
+use reverie_ptrace::TracerBuilder;
+use std::path::Path;
+
+/// The guest stage to run, set in the guest and unset in the tracer
+pub const STAGE_VAR: &str = "AGENTFS_TEST_STAGE";
+
+/// The stage this process should run, or `None` in the tracer
+pub fn stage() -> Option<String> {
+    std::env::var(STAGE_VAR).ok()
+}
+
+/// Create a SQLite VFS mounted at `mount_point`, in a database under `dir`
+pub async fn sqlite_vfs(dir: &Path, mount_point: &Path) -> SqliteVfs {
+    SqliteVfs::new(&dir.join("agent.db"), mount_point.to_path_buf())
+        .await
+        .unwrap()
+}
+
+/// Create the file at `path` in `vfs` with `data`
+pub async fn write_file(vfs: &dyn Vfs, path: &str, data: &[u8]) {
+    let file = vfs
+        .open(Path::new(path), libc::O_WRONLY | libc::O_CREAT, 0o644)
+        .await
+        .unwrap();
+    vfs.write(&file, 0, data).await.unwrap();
+    file.close().await.unwrap();
+}
+
+/// Run `stage` of the test `test_name` as a traced guest with `mount_table`,
+/// returning how the guest exited
+pub async fn run_guest(test_name: &str, stage: &str, mount_table: MountTable) -> ExitStatus {
+    run_guest_with(test_name, stage, mount_table, |_| {}).await
+}
+
+/// Like `run_guest()`, with `configure` applied to the guest command
+pub async fn run_guest_with(
+    test_name: &str,
+    stage: &str,
+    mount_table: MountTable,
+    configure: impl FnOnce(&mut Command),
+) -> ExitStatus {
+    init_mount_table(mount_table);
+    init_fd_tables();
+    init_strace(false);
+    spawn_guest(test_name, stage, configure).await
+}
+
+/// Run `stage` of the test `test_name` as a traced guest, with `configure`
+/// applied to its command, once the sandbox has been initialized
+pub async fn spawn_guest(
+    test_name: &str,
+    stage: &str,
+    configure: impl FnOnce(&mut Command),
+) -> ExitStatus {
+    let mut cmd = Command::new(std::env::current_exe().unwrap());
+    cmd.arg(test_name)
+        .arg("--exact")
+        .arg("--test-threads=1")
+        .env(STAGE_VAR, stage);
+    configure(&mut cmd);
+
+    let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+    let (status, _) = tracer.wait().await.unwrap();
+    status
+}
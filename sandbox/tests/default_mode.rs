@@ -0,0 +1,71 @@
+//! Creating files and directories in a mount with default modes from a traced guest.
+//!
+//! The guest creates a file and a directory under `/agent`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{MountDefaults, MountTable};
+use reverie_process::ExitStatus;
+use std::{
+    fs::{self, DirBuilder, OpenOptions},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::PathBuf,
+    sync::Arc,
+};
+
+/// The permission bits of `path`
+fn mode(path: &str) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+/// Guest stage: exit with 0 if files and directories get the mount's default modes
+/// whatever mode they are created with, with the umask still applied on top.
+fn create_files() -> ! {
+    unsafe { libc::umask(0o022) };
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(0o666)
+        .open("/agent/file.txt")
+        .unwrap();
+    DirBuilder::new().mode(0o777).create("/agent/dir").unwrap();
+
+    unsafe { libc::umask(0o077) };
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(0o666)
+        .open("/agent/private.txt")
+        .unwrap();
+
+    let passed = mode("/agent/file.txt") == 0o640
+        && mode("/agent/dir") == 0o750
+        && mode("/agent/private.txt") == 0o600;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_default_mode() {
+    if common::stage().is_some() {
+        create_files();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point.clone(), Arc::new(vfs));
+        mount_table.set_defaults(
+            &mount_point,
+            MountDefaults {
+                file_mode: Some(0o640),
+                dir_mode: Some(0o750),
+                ..Default::default()
+            },
+        );
+        let status = common::run_guest("test_default_mode", "create", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
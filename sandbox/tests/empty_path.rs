@@ -0,0 +1,82 @@
+//! Stat-ing and linking open virtual files with `AT_EMPTY_PATH` from a traced guest.
+//!
+//! The guest passes FDs of files under `/data` to `statx`, `fstatat` and `linkat` with
+//! an empty path.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, fs, os::fd::AsRawFd, path::PathBuf, sync::Arc};
+
+/// The size `statx` and `fstatat` report for `fd` with an empty path
+fn empty_path_sizes(fd: i32) -> (i64, i64) {
+    let empty = CString::new("").unwrap();
+    let mut statx: libc::statx = unsafe { std::mem::zeroed() };
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    let statx_result = unsafe {
+        libc::statx(
+            fd,
+            empty.as_ptr(),
+            libc::AT_EMPTY_PATH,
+            libc::STATX_BASIC_STATS,
+            &mut statx,
+        )
+    };
+    let stat_result = unsafe { libc::fstatat(fd, empty.as_ptr(), &mut stat, libc::AT_EMPTY_PATH) };
+    if statx_result != 0 || stat_result != 0 {
+        return (-1, -1);
+    }
+    (statx.stx_size as i64, stat.st_size)
+}
+
+/// Guest stage: exit with 0 if both stats of an open file report its size, and an
+/// `O_TMPFILE` file links into place by its FD.
+fn stat_and_link() -> ! {
+    let file = fs::File::open("/data/file.txt").unwrap();
+    let sizes = empty_path_sizes(file.as_raw_fd());
+
+    let dir = CString::new("/data").unwrap();
+    let fd = unsafe { libc::open(dir.as_ptr(), libc::O_TMPFILE | libc::O_RDWR, 0o600) };
+    let written = unsafe { libc::write(fd, b"hi".as_ptr().cast(), 2) };
+    let empty = CString::new("").unwrap();
+    let linked = CString::new("/data/linked.txt").unwrap();
+    let result = unsafe {
+        libc::linkat(
+            fd,
+            empty.as_ptr(),
+            libc::AT_FDCWD,
+            linked.as_ptr(),
+            libc::AT_EMPTY_PATH,
+        )
+    };
+    let tmpfile_sizes = empty_path_sizes(fd);
+    unsafe { libc::close(fd) };
+
+    let contents = fs::read_to_string("/data/linked.txt").ok();
+    let passed = sizes == (5, 5)
+        && written == 2
+        && result == 0
+        && tmpfile_sizes == (2, 2)
+        && contents.as_deref() == Some("hi");
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_empty_path() {
+    if common::stage().is_some() {
+        stat_and_link();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/data");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+        common::write_file(&vfs, "/data/file.txt", b"hello").await;
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_empty_path", "stat", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
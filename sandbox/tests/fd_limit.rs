@@ -0,0 +1,55 @@
+//! Limit the number of virtual FDs a traced guest can have open.
+//!
+//! The guest opens a virtual file up to the limit, checks that the next open and
+//! dup fail with `EMFILE`, and that closing one of the FDs makes room for another.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{init_max_open_files, MountTable};
+use reverie_process::ExitStatus;
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+const MAX_OPEN_FILES: usize = 4;
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// Guest: exit with 0 if the FD limit is enforced and closing an FD frees a slot.
+fn open_to_limit() -> ! {
+    let path = CString::new("/agent/hello.txt").unwrap();
+    let open = || unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+
+    let fds: Vec<i32> = (0..MAX_OPEN_FILES).map(|_| open()).collect();
+    let opened = fds.iter().all(|&fd| fd >= 0);
+
+    let open_fails = open() < 0 && errno() == libc::EMFILE;
+    let dup_fails = unsafe { libc::dup(fds[0]) } < 0 && errno() == libc::EMFILE;
+
+    let closed = unsafe { libc::close(fds[0]) } == 0;
+    let reopened = open() >= 0;
+
+    let ok = opened && open_fails && dup_fails && closed && reopened;
+    std::process::exit(if ok { 0 } else { 1 });
+}
+
+#[test]
+fn test_fd_limit_returns_emfile() {
+    if common::stage().is_some() {
+        open_to_limit();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+        common::write_file(&vfs, "/agent/hello.txt", b"hello").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        init_max_open_files(MAX_OPEN_FILES);
+        let status = common::run_guest("test_fd_limit_returns_emfile", "open", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
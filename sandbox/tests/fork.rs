@@ -0,0 +1,62 @@
+//! Inherit virtual FDs across `fork` in a traced guest.
+//!
+//! The guest forks with a virtual file open.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+/// Read up to `len` bytes from `fd` at its file offset.
+fn read_fd(fd: i32, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), len) };
+    buf.truncate(n.max(0) as usize);
+    buf
+}
+
+/// Guest stage: exit with 0 if a forked child reads on from the parent's offset,
+/// and the parent then reads on from the child's.
+fn fork_and_read() -> ! {
+    let path = CString::new("/agent/hello.txt").unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+    assert!(fd >= 0);
+    let head = read_fd(fd, 2);
+
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        let middle = read_fd(fd, 2);
+        unsafe { libc::_exit(if middle == b"ll" { 0 } else { 1 }) };
+    }
+    assert!(pid > 0);
+
+    let mut status = 0;
+    unsafe { libc::waitpid(pid, &mut status, 0) };
+    let child_ok = libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0;
+    let tail = read_fd(fd, 16);
+
+    let passed = head == b"he" && child_ok && tail == b"o";
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_fork_inherits_virtual_fd() {
+    if common::stage().is_some() {
+        fork_and_read();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+
+        common::write_file(&vfs, "/agent/hello.txt", b"hello").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_fork_inherits_virtual_fd", "fork", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
@@ -0,0 +1,49 @@
+//! Stat-ing open virtual files and their filesystem by FD from a traced guest.
+//!
+//! The guest opens a file under `/agent` and calls `fstat` and `fstatfs` on it.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{fs, os::fd::AsRawFd, path::PathBuf, sync::Arc};
+
+/// Filesystem type `statfs` reports for AgentFS mounts ("AGFS")
+const AGENTFS_MAGIC: i64 = 0x4147_4653;
+
+/// Guest stage: exit with 0 if `fstat` reports the size and type of the open file,
+/// and `fstatfs` the type of the AgentFS mount.
+fn stat_open_file() -> ! {
+    let file = fs::File::open("/agent/hello.txt").unwrap();
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    let mut statfs: libc::statfs = unsafe { std::mem::zeroed() };
+    let stat_result = unsafe { libc::fstat(file.as_raw_fd(), &mut stat) };
+    let statfs_result = unsafe { libc::fstatfs(file.as_raw_fd(), &mut statfs) };
+
+    let passed = stat_result == 0
+        && stat.st_size == 5
+        && stat.st_mode & libc::S_IFMT == libc::S_IFREG
+        && statfs_result == 0
+        && statfs.f_type as i64 == AGENTFS_MAGIC;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_fstat() {
+    if common::stage().is_some() {
+        stat_open_file();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+        common::write_file(&vfs, "/agent/hello.txt", b"hello").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_fstat", "stat", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
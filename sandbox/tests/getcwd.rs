@@ -0,0 +1,72 @@
+//! The working directory of a traced guest under a bind mount.
+//!
+//! The guest changes directories under the mount.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{init_workdir, BindVfs, GuestCwd, MountTable};
+use reverie_process::ExitStatus;
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+fn getcwd() -> Option<PathBuf> {
+    std::env::current_dir().ok()
+}
+
+/// Guest stage: exit with 0 if `getcwd` reports the paths under `/work` rather than
+/// the host directory behind it.
+fn change_dirs() -> ! {
+    // The command starts in the mount's host directory
+    let started = getcwd() == Some(PathBuf::from("/work"));
+
+    // A buffer too small for the path and its NUL fails with ERANGE
+    let mut buf = [0u8; 5];
+    let short = unsafe { libc::getcwd(buf.as_mut_ptr().cast(), buf.len()) }.is_null()
+        && std::io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE);
+
+    // Relative paths move around under the mount, and out of it with `..`
+    let sub = CString::new("sub").unwrap();
+    let into_sub =
+        unsafe { libc::chdir(sub.as_ptr()) } == 0 && getcwd() == Some(PathBuf::from("/work/sub"));
+    let dotdot = CString::new("../..").unwrap();
+    let out = unsafe { libc::chdir(dotdot.as_ptr()) } == 0 && getcwd() == Some(PathBuf::from("/"));
+
+    // fchdir into a directory opened under the mount reports its path too
+    let work = CString::new("/work/sub").unwrap();
+    let dirfd = unsafe { libc::open(work.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY) };
+    let fchdir = dirfd >= 0
+        && unsafe { libc::fchdir(dirfd) } == 0
+        && getcwd() == Some(PathBuf::from("/work/sub"));
+    unsafe { libc::close(dirfd) };
+
+    let passed = started && short && into_sub && out && fchdir;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_getcwd_under_bind_mount() {
+    if common::stage().is_some() {
+        change_dirs();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let mount_point = PathBuf::from("/work");
+        let vfs = BindVfs::new(dir.path().to_path_buf(), mount_point.clone());
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point.clone(), Arc::new(vfs));
+        init_workdir(GuestCwd::Translated(mount_point));
+        let status = common::run_guest_with(
+            "test_getcwd_under_bind_mount",
+            "getcwd",
+            mount_table,
+            |cmd| {
+                cmd.current_dir(dir.path());
+            },
+        )
+        .await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
@@ -0,0 +1,38 @@
+//! Report remapped ownership for a virtual mount to a traced guest.
+#![cfg(target_os = "linux")]
+
+use agentfs_sandbox::{
+    init_fd_tables, init_mount_table, init_strace, IdMap, MountTable, Sandbox, SqliteVfs,
+};
+use reverie_process::{Command, ExitStatus};
+use reverie_ptrace::TracerBuilder;
+use std::{path::PathBuf, sync::Arc};
+
+#[tokio::test]
+async fn test_id_map_reports_mapped_owner() {
+    let dir = tempfile::tempdir().unwrap();
+    let mount_point = PathBuf::from("/agent");
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let vfs = SqliteVfs::new(&dir.path().join("agent.db"), mount_point.clone())
+        .await
+        .unwrap()
+        .with_id_map(IdMap::new(vec![(4321, uid)], vec![(8765, gid)]));
+
+    let mut mount_table = MountTable::new();
+    mount_table.add_mount(mount_point, Arc::new(vfs));
+    init_mount_table(mount_table);
+    init_fd_tables();
+    init_strace(false);
+
+    // The guest still owns the file under its mapped IDs, so it may chmod it
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(
+        "echo hello > /agent/file.txt \
+         && test \"$(stat -c '%u:%g' /agent/file.txt)\" = '4321:8765' \
+         && chmod 600 /agent/file.txt",
+    );
+
+    let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+    let (status, _) = tracer.wait().await.unwrap();
+    assert_eq!(status, ExitStatus::Exited(0));
+}
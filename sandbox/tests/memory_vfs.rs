@@ -0,0 +1,84 @@
+//! The lifecycle of files in an in-memory mount, from a traced guest.
+//!
+//! The guest creates, reads, links and removes files under `/workspace`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{MemoryVfs, MountTable, Vfs};
+use reverie_process::ExitStatus;
+use std::{
+    fs,
+    io::Result,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const SOURCE: &[u8] = b"fn main() {}\n";
+
+/// Names in `dir`, sorted
+fn list(dir: &Path) -> Result<Vec<String>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+/// Create, inspect and remove files under `/workspace`, returning whether each
+/// step saw what it should
+fn lifecycle() -> Result<bool> {
+    let root = Path::new("/workspace");
+
+    fs::create_dir_all(root.join("src/module"))?;
+    fs::write(root.join("src/main.rs"), SOURCE)?;
+    let written = fs::read(root.join("src/main.rs"))? == SOURCE;
+    let metadata = fs::metadata(root.join("src/main.rs"))?;
+    let stat = metadata.is_file() && metadata.len() == SOURCE.len() as u64;
+
+    std::os::unix::fs::symlink("src/main.rs", root.join("link"))?;
+    let symlinked = fs::read_link(root.join("link"))? == Path::new("src/main.rs")
+        && fs::read(root.join("link"))? == SOURCE
+        && fs::symlink_metadata(root.join("link"))?
+            .file_type()
+            .is_symlink();
+
+    fs::hard_link(root.join("src/main.rs"), root.join("hard"))?;
+    let linked = fs::metadata(root.join("hard"))?.nlink() == 2;
+    let listed =
+        list(root)? == ["hard", "link", "src"] && list(&root.join("src"))? == ["main.rs", "module"];
+
+    fs::rename(root.join("hard"), root.join("src/copy.rs"))?;
+    fs::remove_file(root.join("link"))?;
+    fs::remove_file(root.join("src/main.rs"))?;
+    fs::remove_dir(root.join("src/module"))?;
+    let removed = list(root)? == ["src"]
+        && list(&root.join("src"))? == ["copy.rs"]
+        && fs::metadata(root.join("src/copy.rs"))?.nlink() == 1;
+
+    Ok(written && stat && symlinked && linked && listed && removed)
+}
+
+#[test]
+fn test_memory_vfs_lifecycle() {
+    if common::stage().is_some() {
+        let passed = lifecycle().unwrap_or(false);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mount_point = PathBuf::from("/workspace");
+        let vfs = MemoryVfs::new(mount_point.clone());
+
+        // Clones share their files, so this one sees what the guest left behind
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs.clone()));
+        let status = common::run_guest("test_memory_vfs_lifecycle", "lifecycle", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+
+        let stat = vfs.stat(Path::new("/workspace/src/copy.rs")).await.unwrap();
+        assert_eq!(stat.st_size, SOURCE.len() as i64);
+        assert!(vfs.stat(Path::new("/workspace/src/module")).await.is_err());
+    });
+}
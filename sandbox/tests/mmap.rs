@@ -0,0 +1,71 @@
+//! Map a virtual file into memory from a traced guest.
+//!
+//! The guest maps the virtual file.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+/// Guest stage: exit with 0 if the file reads back through a private mapping
+/// and a shared mapping is refused.
+fn map_file() -> ! {
+    let path = CString::new("/agent/hello.txt").unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+    assert!(fd >= 0);
+
+    let len = 4096;
+    let addr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            fd,
+            0,
+        )
+    };
+    assert_ne!(addr, libc::MAP_FAILED);
+    let mapped = unsafe { std::slice::from_raw_parts(addr as *const u8, len) };
+    // The rest of the page past end-of-file reads as zeros
+    let readable = &mapped[..5] == b"hello" && mapped[5..].iter().all(|&b| b == 0);
+    let unmapped = unsafe { libc::munmap(addr, len) } == 0;
+
+    let shared = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            fd,
+            0,
+        )
+    };
+    let refused = shared == libc::MAP_FAILED
+        && std::io::Error::last_os_error().raw_os_error() == Some(libc::ENODEV);
+
+    let passed = readable && unmapped && refused;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_mmap_virtual_file() {
+    if common::stage().is_some() {
+        map_file();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+
+        common::write_file(&vfs, "/agent/hello.txt", b"hello").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_mmap_virtual_file", "map", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
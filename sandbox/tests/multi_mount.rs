@@ -0,0 +1,49 @@
+//! Reading from two mounted filesystems in one traced guest.
+//!
+//! The guest reads files from an AgentFS mount at `/workspace` and an in-memory mount
+//! at `/cache`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{MemoryVfs, MountTable};
+use reverie_process::ExitStatus;
+use std::{fs, path::PathBuf, sync::Arc};
+
+/// Guest stage: exit with 0 if both mounts serve their own file, and neither
+/// serves the other's.
+fn read_both() -> ! {
+    let workspace = fs::read_to_string("/workspace/notes.txt").ok();
+    let cache = fs::read_to_string("/cache/entry.txt").ok();
+    let separate =
+        fs::metadata("/workspace/entry.txt").is_err() && fs::metadata("/cache/notes.txt").is_err();
+
+    let passed = workspace.as_deref() == Some("from sqlite")
+        && cache.as_deref() == Some("from memory")
+        && separate;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_multi_mount() {
+    if common::stage().is_some() {
+        read_both();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = PathBuf::from("/workspace");
+        let sqlite = common::sqlite_vfs(dir.path(), &workspace).await;
+        common::write_file(&sqlite, "/workspace/notes.txt", b"from sqlite").await;
+
+        let cache = PathBuf::from("/cache");
+        let memory = MemoryVfs::new(cache.clone());
+        common::write_file(&memory, "/cache/entry.txt", b"from memory").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(workspace, Arc::new(sqlite));
+        mount_table.add_mount(cache, Arc::new(memory));
+        let status = common::run_guest("test_multi_mount", "read", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
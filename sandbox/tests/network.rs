@@ -0,0 +1,47 @@
+//! Keep the traced guest off the network when networking is disabled.
+//!
+//! The guest connects to a TCP listener the tracer opened on the loopback
+//! interface, whose address is its stage, and exits with the resulting errno, or
+//! 0 if it got through.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{init_fd_tables, init_mount_table, init_no_network, init_strace, MountTable};
+use reverie_process::ExitStatus;
+use std::net::{TcpListener, TcpStream};
+
+const TEST_NAME: &str = "test_no_network_blocks_connect";
+
+/// Guest: exit with the errno of connecting to `addr`, or 0 on success.
+fn connect(addr: &str) -> ! {
+    let code = match TcpStream::connect(addr) {
+        Ok(_) => 0,
+        Err(e) => e.raw_os_error().unwrap_or(-1),
+    };
+    std::process::exit(code);
+}
+
+#[test]
+fn test_no_network_blocks_connect() {
+    if let Some(addr) = common::stage() {
+        connect(&addr);
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        init_mount_table(MountTable::new());
+        init_fd_tables();
+        init_strace(false);
+
+        init_no_network(false);
+        let status = common::spawn_guest(TEST_NAME, &addr, |_| {}).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+
+        init_no_network(true);
+        let status = common::spawn_guest(TEST_NAME, &addr, |_| {}).await;
+        assert_eq!(status, ExitStatus::Exited(libc::EACCES));
+    });
+}
@@ -0,0 +1,59 @@
+//! Virtual FDs open when a traced guest exits.
+//!
+//! The guest opens two files under `/data`, closes one and exits with the other
+//! still open, which is reported at teardown.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{format_open_fds, ExitStatus, MemoryVfs, SandboxBuilder};
+use std::{fs, os::fd::IntoRawFd, path::PathBuf, sync::Arc};
+
+/// Open `kept.txt` and `closed.txt`, close only the latter and exit with the
+/// number of the FD left open
+fn keep_one_open() -> ! {
+    let kept = fs::File::create("/data/kept.txt").unwrap().into_raw_fd();
+    drop(fs::File::create("/data/closed.txt").unwrap());
+    std::process::exit(kept);
+}
+
+#[test]
+fn test_open_fds_at_exit() {
+    if common::stage().is_some() {
+        keep_one_open();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mount_point = PathBuf::from("/data");
+        let handle = SandboxBuilder::new()
+            .mount(mount_point.clone(), Arc::new(MemoryVfs::new(mount_point)))
+            .env(common::STAGE_VAR, "open")
+            .spawn(
+                std::env::current_exe().unwrap(),
+                ["test_open_fds_at_exit", "--exact", "--test-threads=1"],
+            )
+            .await
+            .unwrap();
+        let exit = handle.wait().await.unwrap();
+        let ExitStatus::Exited(kept) = exit.status else {
+            panic!("guest exited with {:?}", exit.status);
+        };
+        assert!(kept > 2);
+
+        // Only the file left open is reported, and not the host's standard streams
+        let open_fds: Vec<_> = exit
+            .open_fds
+            .iter()
+            .map(|open_fd| (open_fd.fd, open_fd.path.clone()))
+            .collect();
+        assert_eq!(open_fds, [(kept, PathBuf::from("/data/kept.txt"))]);
+
+        // The report `agentfs run` logs, or fails with under --strict-fds, is
+        // about that FD alone
+        let pid = exit.open_fds[0].pid;
+        assert_eq!(
+            format_open_fds(&exit.open_fds),
+            format!("1 virtual fd open at exit\n  [{pid}] fd {kept} -> /data/kept.txt")
+        );
+    });
+}
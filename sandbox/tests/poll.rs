@@ -0,0 +1,83 @@
+//! Polling a virtual file from a traced guest.
+//!
+//! The guest waits on a virtual file with `poll` and `epoll`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+/// Guest stage: exit with 0 if waiting without a timeout on a virtual file and an
+/// empty pipe returns right away with the file ready.
+fn poll_virtual_file() -> ! {
+    let path = CString::new("/agent/poll.txt").unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR | libc::O_CREAT, 0o644) };
+    assert!(fd >= 0);
+    let mut pipe = [0i32; 2];
+    assert_eq!(unsafe { libc::pipe2(pipe.as_mut_ptr(), 0) }, 0);
+
+    // poll reports the file readable and writable, and the pipe not ready
+    let mut fds = [
+        libc::pollfd {
+            fd,
+            events: libc::POLLIN | libc::POLLOUT,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: pipe[0],
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let ready = unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) };
+    let polled =
+        ready == 1 && fds[0].revents == libc::POLLIN | libc::POLLOUT && fds[1].revents == 0;
+
+    // epoll reports the file with the data it was registered with
+    let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    assert!(epfd >= 0);
+    let mut event = libc::epoll_event {
+        events: libc::EPOLLIN as u32,
+        u64: 42,
+    };
+    let added = unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, fd, &mut event) } == 0;
+    let mut event = libc::epoll_event {
+        events: libc::EPOLLIN as u32,
+        u64: 7,
+    };
+    let added =
+        added && unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, pipe[0], &mut event) } == 0;
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 4];
+    let ready = unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), 4, -1) };
+    let (ready_events, ready_data) = (events[0].events, events[0].u64);
+    let waited = added && ready == 1 && ready_events == libc::EPOLLIN as u32 && ready_data == 42;
+
+    unsafe {
+        libc::close(epfd);
+        libc::close(pipe[0]);
+        libc::close(pipe[1]);
+        libc::close(fd);
+    }
+    let passed = polled && waited;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_poll_virtual_file() {
+    if common::stage().is_some() {
+        poll_virtual_file();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_poll_virtual_file", "poll", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
@@ -0,0 +1,53 @@
+//! Reading the `/proc/self/fd` links of a traced guest.
+//!
+//! The guest reads the links of its FDs.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{path::PathBuf, sync::Arc};
+
+/// Guest stage: exit with 0 if the links of a virtual file and a pipe name them.
+fn read_fd_links() -> ! {
+    use std::os::fd::AsRawFd;
+
+    let file = std::fs::File::create("/agent/linked.txt").unwrap();
+    let fd = file.as_raw_fd();
+    let virtual_link = std::fs::read_link(format!("/proc/self/fd/{}", fd)).ok();
+    let pid_link = std::fs::read_link(format!("/proc/{}/fd/{}", std::process::id(), fd)).ok();
+    let linked =
+        virtual_link == Some(PathBuf::from("/agent/linked.txt")) && pid_link == virtual_link;
+
+    // Passthrough FDs read the link of their kernel FD
+    let mut pipe = [0i32; 2];
+    assert_eq!(unsafe { libc::pipe2(pipe.as_mut_ptr(), 0) }, 0);
+    let pipe_link = std::fs::read_link(format!("/proc/self/fd/{}", pipe[0])).unwrap();
+    let piped = pipe_link.to_string_lossy().starts_with("pipe:");
+
+    // Closed FDs have no link
+    drop(file);
+    let closed = std::fs::read_link(format!("/proc/self/fd/{}", fd)).is_err();
+
+    let passed = linked && piped && closed;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_proc_fd_links() {
+    if common::stage().is_some() {
+        read_fd_links();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_proc_fd_links", "proc_fd", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
@@ -0,0 +1,103 @@
+//! Changing files in read-only mounts through open FDs from a traced guest.
+//!
+//! The guest opens files under `/ro-host` and `/ro-agent` and changes them by FD.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{BindVfs, MountTable, Vfs};
+use reverie_process::ExitStatus;
+use std::{
+    fs,
+    os::{fd::AsRawFd, unix::fs::PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Whether a libc call returned -1 with `EROFS`
+fn read_only(result: i32) -> bool {
+    result == -1 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EROFS)
+}
+
+/// The FD-based changes to `fd` that fail with `EROFS`, as a bit per change:
+/// `fchmod`, `fchown`, `fsetxattr`, `fremovexattr` and `futimens`.
+fn rejected_changes(fd: i32) -> u8 {
+    let name = c"user.test";
+    let times = [libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    }; 2];
+    let results = unsafe {
+        [
+            libc::fchmod(fd, 0o600),
+            libc::fchown(fd, libc::getuid(), libc::getgid()),
+            libc::fsetxattr(fd, name.as_ptr(), b"x".as_ptr().cast(), 1, 0),
+            libc::fremovexattr(fd, name.as_ptr()),
+            libc::futimens(fd, times.as_ptr()),
+        ]
+    };
+    results
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, result)| read_only(result))
+        .fold(0, |bits, (i, _)| bits | 1 << i)
+}
+
+/// Guest stage: exit with 0 if every FD-based change fails with `EROFS` on both
+/// mounts, or else with the bits of the changes that didn't, shifted by 5 for
+/// the virtual mount.
+fn change_by_fd() -> ! {
+    let host = fs::File::open("/ro-host/file.txt").unwrap();
+    let agent = fs::File::open("/ro-agent/file.txt").unwrap();
+    let host_bits = !rejected_changes(host.as_raw_fd()) & 0x1f;
+    let agent_bits = !rejected_changes(agent.as_raw_fd()) & 0x1f;
+    std::process::exit((host_bits as i32) | (agent_bits as i32) << 5);
+}
+
+#[test]
+fn test_ro_fd_changes() {
+    if common::stage().is_some() {
+        change_by_fd();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let host_dir = dir.path().join("host");
+        fs::create_dir(&host_dir).unwrap();
+        let host_file = host_dir.join("file.txt");
+        fs::write(&host_file, "host").unwrap();
+        fs::set_permissions(&host_file, fs::Permissions::from_mode(0o644)).unwrap();
+        let host_mtime = fs::metadata(&host_file).unwrap().modified().unwrap();
+
+        let agent_point = PathBuf::from("/ro-agent");
+        let agent = common::sqlite_vfs(dir.path(), &agent_point).await;
+        let file = agent
+            .open(
+                Path::new("/ro-agent/file.txt"),
+                libc::O_WRONLY | libc::O_CREAT,
+                0o644,
+            )
+            .await
+            .unwrap();
+        file.close().await.unwrap();
+        let agent = Arc::new(agent);
+
+        let host_point = PathBuf::from("/ro-host");
+        let mut mount_table = MountTable::new();
+        mount_table.add_read_only_mount(
+            host_point.clone(),
+            Arc::new(BindVfs::new(host_dir.clone(), host_point)),
+        );
+        mount_table.add_read_only_mount(agent_point, agent.clone());
+        let status = common::run_guest("test_ro_fd_changes", "change", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+
+        // Neither file changed
+        let metadata = fs::metadata(&host_file).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o644);
+        assert_eq!(metadata.modified().unwrap(), host_mtime);
+        let stat = agent.stat(Path::new("/ro-agent/file.txt")).await.unwrap();
+        assert_eq!(stat.st_mode & 0o7777, 0o644);
+        assert_ne!(stat.st_mtime, 0);
+    });
+}
@@ -0,0 +1,51 @@
+//! Reading a host directory through a read-only bind mount from a traced guest.
+//!
+//! The guest reads and tries to change files under `/toolchain`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{BindVfs, MountTable};
+use reverie_process::ExitStatus;
+use std::{fs, path::PathBuf, sync::Arc};
+
+/// Whether `result` failed with `EROFS`
+fn read_only<T>(result: std::io::Result<T>) -> bool {
+    matches!(result, Err(e) if e.raw_os_error() == Some(libc::EROFS))
+}
+
+/// Guest stage: exit with 0 if the host file reads through the mount, and
+/// writing, creating and removing files under it fail with `EROFS`.
+fn read_not_write() -> ! {
+    let read = fs::read_to_string("/toolchain/version.txt").ok();
+    let rejected = read_only(fs::write("/toolchain/version.txt", "changed"))
+        && read_only(fs::write("/toolchain/new.txt", "new"))
+        && read_only(fs::remove_file("/toolchain/version.txt"));
+
+    let passed = read.as_deref() == Some("1.0") && rejected;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_ro_host_mount() {
+    if common::stage().is_some() {
+        read_not_write();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("version.txt"), "1.0").unwrap();
+
+        let mount_point = PathBuf::from("/toolchain");
+        let vfs = BindVfs::new(dir.path().to_path_buf(), mount_point.clone());
+        let mut mount_table = MountTable::new();
+        mount_table.add_read_only_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_ro_host_mount", "read", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+
+        // The host directory is untouched
+        let version = fs::read_to_string(dir.path().join("version.txt")).unwrap();
+        assert_eq!(version, "1.0");
+        assert!(!dir.path().join("new.txt").exists());
+    });
+}
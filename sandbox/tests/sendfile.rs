@@ -0,0 +1,82 @@
+//! Sending a virtual file to a pipe from a traced guest.
+//!
+//! The guest uses `sendfile` from the virtual file.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+const CONTENTS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Read exactly `len` bytes from the pipe
+fn read_pipe(fd: i32, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    let read = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), len) };
+    buf.truncate(read.max(0) as usize);
+    buf
+}
+
+/// Guest stage: exit with 0 if `sendfile` copies the file to a pipe as on a
+/// kernel file.
+fn send_to_pipe() -> ! {
+    let path = CString::new("/agent/sendfile.txt").unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+    assert!(fd >= 0);
+    let mut pipe = [0i32; 2];
+    assert_eq!(unsafe { libc::pipe2(pipe.as_mut_ptr(), 0) }, 0);
+
+    // With an offset pointer, the pointed-to offset is used and updated while the
+    // file offset stays put
+    let mut offset: libc::off_t = 10;
+    let sent = unsafe { libc::sendfile(pipe[1], fd, &mut offset, 6) };
+    let file_offset = unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) };
+    let positioned =
+        sent == 6 && offset == 16 && file_offset == 0 && read_pipe(pipe[0], 6) == b"abcdef";
+
+    // Without one, the file offset is used and advanced, and count is clamped to
+    // end-of-file
+    unsafe { libc::lseek(fd, 30, libc::SEEK_SET) };
+    let sent = unsafe { libc::sendfile(pipe[1], fd, std::ptr::null_mut(), 100) };
+    let file_offset = unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) };
+    let advanced = sent == 6 && file_offset == 36 && read_pipe(pipe[0], 6) == b"uvwxyz";
+
+    // At end-of-file nothing is sent
+    let sent = unsafe { libc::sendfile(pipe[1], fd, std::ptr::null_mut(), 100) };
+    let at_eof = sent == 0;
+
+    unsafe {
+        libc::close(pipe[0]);
+        libc::close(pipe[1]);
+        libc::close(fd);
+    }
+    let passed = positioned && advanced && at_eof;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_sendfile_virtual_file_to_pipe() {
+    if common::stage().is_some() {
+        send_to_pipe();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+
+        common::write_file(&vfs, "/agent/sendfile.txt", CONTENTS).await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest(
+            "test_sendfile_virtual_file_to_pipe",
+            "sendfile",
+            mount_table,
+        )
+        .await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
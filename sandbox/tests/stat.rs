@@ -0,0 +1,53 @@
+//! Stat files in a virtual mount from a traced guest.
+//!
+//! `stat` reaches the kernel as `newfstatat` on x86_64 and as `fstatat` on aarch64,
+//! which has no legacy path-based system calls, so this test covers both handlers.
+#![cfg(target_os = "linux")]
+
+use agentfs_sandbox::{
+    init_fd_tables, init_mount_table, init_strace, MountTable, Sandbox, SqliteVfs, Vfs,
+};
+use reverie_process::{Command, ExitStatus};
+use reverie_ptrace::TracerBuilder;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[tokio::test]
+async fn test_stat_virtual_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let mount_point = PathBuf::from("/agent");
+    let vfs = SqliteVfs::new(&dir.path().join("agent.db"), mount_point.clone())
+        .await
+        .unwrap();
+
+    let file = vfs
+        .open(
+            Path::new("/agent/hello.txt"),
+            libc::O_WRONLY | libc::O_CREAT,
+            0o644,
+        )
+        .await
+        .unwrap();
+    vfs.write(&file, 0, b"hello").await.unwrap();
+    file.close().await.unwrap();
+    vfs.mkdir(Path::new("/agent/dir"), 0o755).await.unwrap();
+
+    let mut mount_table = MountTable::new();
+    mount_table.add_mount(mount_point, Arc::new(vfs));
+    init_mount_table(mount_table);
+    init_fd_tables();
+    init_strace(false);
+
+    // `test` stats its operand, so each check goes through the stat handlers
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(
+        "test -f /agent/hello.txt && test -s /agent/hello.txt \
+         && test -d /agent/dir && test ! -e /agent/missing",
+    );
+
+    let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+    let (status, _) = tracer.wait().await.unwrap();
+    assert_eq!(status, ExitStatus::Exited(0));
+}
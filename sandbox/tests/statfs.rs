@@ -0,0 +1,35 @@
+//! Get the filesystem statistics of a virtual mount from a traced guest.
+#![cfg(target_os = "linux")]
+
+use agentfs_sandbox::{
+    init_fd_tables, init_mount_table, init_strace, MountTable, Sandbox, SqliteVfs,
+};
+use reverie_process::{Command, ExitStatus};
+use reverie_ptrace::TracerBuilder;
+use std::{path::PathBuf, sync::Arc};
+
+#[tokio::test]
+async fn test_statfs_virtual_mount() {
+    let dir = tempfile::tempdir().unwrap();
+    let mount_point = PathBuf::from("/agent");
+    let vfs = SqliteVfs::new(&dir.path().join("agent.db"), mount_point.clone())
+        .await
+        .unwrap();
+
+    let mut mount_table = MountTable::new();
+    mount_table.add_mount(mount_point, Arc::new(vfs));
+    init_mount_table(mount_table);
+    init_fd_tables();
+    init_strace(false);
+
+    // `stat -f` prints the filesystem type and block size from `statfs`
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(
+        "test \"$(stat -f -c '%t %S' /agent)\" = '41474653 4096' \
+         && ! stat -f /agent/missing",
+    );
+
+    let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+    let (status, _) = tracer.wait().await.unwrap();
+    assert_eq!(status, ExitStatus::Exited(0));
+}
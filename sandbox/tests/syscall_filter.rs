@@ -0,0 +1,43 @@
+//! Fail denied syscalls with the filter's errno instead of running them.
+//!
+//! The guest is this test binary itself. With `openat` denied, the dynamic
+//! loader can't open the shared libraries the binary needs, so it exits before
+//! `main` and reports the `EPERM` it got on stderr.
+#![cfg(target_os = "linux")]
+
+use agentfs_sandbox::{
+    init_fd_tables, init_mount_table, init_strace, init_syscall_filter, MountTable, Sandbox,
+    SyscallFilter,
+};
+use reverie_process::{Command, ExitStatus, Stdio};
+use reverie_ptrace::TracerBuilder;
+
+/// Exit status of the dynamic loader when it can't load a library
+const LOADER_FAILED: i32 = 127;
+
+#[test]
+fn test_denied_syscall_returns_eperm() {
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut filter = SyscallFilter::new();
+        filter.deny("openat").unwrap();
+
+        init_mount_table(MountTable::new());
+        init_fd_tables();
+        init_strace(false);
+        init_syscall_filter(filter);
+
+        let mut cmd = Command::new(std::env::current_exe().unwrap());
+        cmd.arg("--list").stderr(Stdio::piped());
+
+        let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+        let (output, _) = tracer.wait_with_output().await.unwrap();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status, ExitStatus::Exited(LOADER_FAILED));
+        assert!(
+            stderr.contains("Operation not permitted"),
+            "unexpected stderr: {}",
+            stderr
+        );
+    });
+}
@@ -0,0 +1,34 @@
+//! Terminate a traced guest that runs past its wall-clock timeout.
+#![cfg(target_os = "linux")]
+
+use agentfs_sandbox::{
+    close_virtual_files, init_fd_tables, init_mount_table, init_strace, wait_with_timeout,
+    MountTable, Sandbox,
+};
+use reverie_process::{Command, ExitStatus};
+use reverie_ptrace::TracerBuilder;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_timeout_terminates_guest() {
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        init_mount_table(MountTable::new());
+        init_fd_tables();
+        init_strace(false);
+
+        let mut cmd = Command::new("sleep");
+        cmd.arg("100");
+
+        let start = Instant::now();
+        let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+        let (result, timed_out) =
+            wait_with_timeout(tracer.wait(), Some(Duration::from_secs(1))).await;
+        let (status, _) = result.unwrap();
+        close_virtual_files().await;
+
+        assert!(timed_out);
+        assert_ne!(status, ExitStatus::Exited(0));
+        // sleep dies on SIGTERM, well before the grace period is over
+        assert!(start.elapsed() < Duration::from_secs(5));
+    });
+}
@@ -0,0 +1,56 @@
+//! Creating an unnamed file with `O_TMPFILE` on a mounted filesystem and linking it into
+//! place from a traced guest.
+//!
+//! The guest writes a temporary file under `/data` and links it as `/data/linked.txt`.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, fs, path::PathBuf, sync::Arc};
+
+/// Guest stage: exit with 0 if an `O_TMPFILE` file written under `/data` can be
+/// linked by its `/proc/self/fd` path and read back by name after it is closed.
+fn write_and_link() -> ! {
+    let dir = CString::new("/data").unwrap();
+    let fd = unsafe { libc::open(dir.as_ptr(), libc::O_TMPFILE | libc::O_RDWR, 0o600) };
+    if fd < 0 {
+        std::process::exit(1);
+    }
+    let written = unsafe { libc::write(fd, b"hello".as_ptr().cast(), 5) };
+
+    let proc_path = CString::new(format!("/proc/self/fd/{}", fd)).unwrap();
+    let linked = CString::new("/data/linked.txt").unwrap();
+    let result = unsafe {
+        libc::linkat(
+            libc::AT_FDCWD,
+            proc_path.as_ptr(),
+            libc::AT_FDCWD,
+            linked.as_ptr(),
+            libc::AT_SYMLINK_FOLLOW,
+        )
+    };
+    unsafe { libc::close(fd) };
+
+    let contents = fs::read_to_string("/data/linked.txt").ok();
+    let passed = written == 5 && result == 0 && contents.as_deref() == Some("hello");
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_tmpfile() {
+    if common::stage().is_some() {
+        write_and_link();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/data");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest("test_tmpfile", "link", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
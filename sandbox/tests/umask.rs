@@ -0,0 +1,44 @@
+//! Apply the guest's umask to files and directories created in a virtual mount.
+#![cfg(target_os = "linux")]
+
+use agentfs_sandbox::{
+    init_fd_tables, init_mount_table, init_strace, MountTable, Sandbox, SqliteVfs, Vfs,
+};
+use reverie_process::{Command, ExitStatus};
+use reverie_ptrace::TracerBuilder;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[tokio::test]
+async fn test_umask_applied_to_created_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let mount_point = PathBuf::from("/agent");
+    let vfs = Arc::new(
+        SqliteVfs::new(&dir.path().join("agent.db"), mount_point.clone())
+            .await
+            .unwrap(),
+    );
+
+    let mut mount_table = MountTable::new();
+    mount_table.add_mount(mount_point, vfs.clone());
+    init_mount_table(mount_table);
+    init_fd_tables();
+    init_strace(false);
+
+    // The shell creates the file itself, while `mkdir` runs in a child that
+    // inherits the umask
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c")
+        .arg("umask 077 && echo hello > /agent/file.txt && mkdir /agent/dir");
+
+    let tracer = TracerBuilder::<Sandbox>::new(cmd).spawn().await.unwrap();
+    let (status, _) = tracer.wait().await.unwrap();
+    assert_eq!(status, ExitStatus::Exited(0));
+
+    let file = vfs.stat(Path::new("/agent/file.txt")).await.unwrap();
+    assert_eq!(file.st_mode & 0o777, 0o600);
+    let dir = vfs.stat(Path::new("/agent/dir")).await.unwrap();
+    assert_eq!(dir.st_mode & 0o777, 0o700);
+}
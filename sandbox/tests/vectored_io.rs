@@ -0,0 +1,104 @@
+//! Scatter-gather I/O on a virtual file from a traced guest.
+//!
+//! The guest uses `writev`, `readv`, `pwritev` and `preadv` on the virtual file.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::{MountTable, SqliteVfs, Vfs};
+use reverie_process::ExitStatus;
+use std::{
+    ffi::CString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+fn iovec(buf: &[u8]) -> libc::iovec {
+    libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    }
+}
+
+fn iovec_mut(buf: &mut [u8]) -> libc::iovec {
+    libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    }
+}
+
+/// Guest stage: exit with 0 if vectored reads and writes of the file behave as
+/// on a kernel file.
+fn vectored_io() -> ! {
+    let path = CString::new("/agent/vectored.txt").unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR | libc::O_CREAT, 0o644) };
+    assert!(fd >= 0);
+
+    // writev writes the buffers in order and advances the offset past them
+    let parts: [&[u8]; 3] = [b"hello", b", ", b"world"];
+    let iov = parts.map(iovec);
+    let written = unsafe { libc::writev(fd, iov.as_ptr(), 3) };
+    let offset = unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) };
+    let wrote = written == 12 && offset == 12;
+
+    // readv fills the buffers in order, stopping at end-of-file
+    unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
+    let (mut first, mut second, mut third) = ([0u8; 4], [0u8; 6], [0u8; 8]);
+    let iov = [
+        iovec_mut(&mut first),
+        iovec_mut(&mut second),
+        iovec_mut(&mut third),
+    ];
+    let read = unsafe { libc::readv(fd, iov.as_ptr(), 3) };
+    let offset = unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) };
+    let reread = read == 12
+        && offset == 12
+        && &first == b"hell"
+        && &second == b"o, wor"
+        && third[..2] == *b"ld"
+        && third[2..].iter().all(|&b| b == 0);
+
+    // pwritev and preadv leave the offset alone
+    let iov = [iovec(b"W"), iovec(b"!")];
+    let pwritten = unsafe { libc::pwritev(fd, iov.as_ptr(), 2, 7) };
+    let (mut head, mut tail) = ([0u8; 7], [0u8; 5]);
+    let iov = [iovec_mut(&mut head), iovec_mut(&mut tail)];
+    let pread = unsafe { libc::preadv(fd, iov.as_ptr(), 2, 0) };
+    let offset = unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) };
+    let positioned =
+        pwritten == 2 && pread == 12 && offset == 12 && &head == b"hello, " && &tail == b"W!rld";
+
+    unsafe { libc::close(fd) };
+    let passed = wrote && reread && positioned;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_vectored_io_virtual_file() {
+    if common::stage().is_some() {
+        vectored_io();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/agent");
+        let vfs = Arc::new(
+            SqliteVfs::new(&dir.path().join("agent.db"), mount_point.clone())
+                .await
+                .unwrap(),
+        );
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, vfs.clone());
+        let status =
+            common::run_guest("test_vectored_io_virtual_file", "vectored", mount_table).await;
+        assert_eq!(status, ExitStatus::Exited(0));
+
+        // The writes reached the database
+        let file = vfs
+            .open(Path::new("/agent/vectored.txt"), libc::O_RDONLY, 0)
+            .await
+            .unwrap();
+        assert_eq!(vfs.read(&file, 0, 64).await.unwrap(), b"hello, W!rld");
+    });
+}
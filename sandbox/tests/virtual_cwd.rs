@@ -0,0 +1,70 @@
+//! Resolving relative paths from a virtual directory in a traced guest.
+//!
+//! The guest opens files relative to a virtual directory.
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use agentfs_sandbox::MountTable;
+use reverie_process::ExitStatus;
+use std::{ffi::CString, path::PathBuf, sync::Arc};
+
+/// Read all of `fd` and close it
+fn read_fd(fd: i32) -> Vec<u8> {
+    let mut buf = [0u8; 64];
+    let read = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+    unsafe { libc::close(fd) };
+    buf[..read.max(0) as usize].to_vec()
+}
+
+/// Guest stage: exit with 0 if `file` opens relative to `/data`, both as a dirfd
+/// and as the working directory.
+fn open_relative() -> ! {
+    let data = CString::new("/data").unwrap();
+    let file = CString::new("file").unwrap();
+
+    let dirfd = unsafe { libc::open(data.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY) };
+    assert!(dirfd >= 0);
+    let fd = unsafe { libc::openat(dirfd, file.as_ptr(), libc::O_RDONLY) };
+    let from_dirfd = fd >= 0 && read_fd(fd) == b"hello";
+
+    // After fchdir, relative paths resolve against the virtual directory
+    let changed = unsafe { libc::fchdir(dirfd) } == 0;
+    let cwd = std::env::current_dir().ok();
+    let fd = unsafe { libc::open(file.as_ptr(), libc::O_RDONLY) };
+    let from_cwd =
+        changed && cwd == Some(PathBuf::from("/data")) && fd >= 0 && read_fd(fd) == b"hello";
+
+    // Changing back to a host directory leaves the virtual one
+    let left = std::env::set_current_dir("/").is_ok()
+        && std::env::current_dir().ok() == Some(PathBuf::from("/"));
+
+    unsafe { libc::close(dirfd) };
+    let passed = from_dirfd && from_cwd && left;
+    std::process::exit(if passed { 0 } else { 1 });
+}
+
+#[test]
+fn test_virtual_dir_relative_paths() {
+    if common::stage().is_some() {
+        open_relative();
+    }
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let dir = tempfile::tempdir().unwrap();
+        let mount_point = PathBuf::from("/data");
+        let vfs = common::sqlite_vfs(dir.path(), &mount_point).await;
+
+        common::write_file(&vfs, "/data/file", b"hello").await;
+
+        let mut mount_table = MountTable::new();
+        mount_table.add_mount(mount_point, Arc::new(vfs));
+        let status = common::run_guest(
+            "test_virtual_dir_relative_paths",
+            "virtual_cwd",
+            mount_table,
+        )
+        .await;
+        assert_eq!(status, ExitStatus::Exited(0));
+    });
+}
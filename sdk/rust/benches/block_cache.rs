@@ -0,0 +1,59 @@
+//! Streaming a 32 MiB file through a file handle.
+//!
+//! `uncached` reads it without the block cache, which takes one query per
+//! read: 8192 queries with 4 KiB reads and 256 with 128 KiB reads. `cached`
+//! reads it with the default cache, whose readahead fetches the next 32 chunks
+//! along with each read that misses, which brings these down to 249 and 128.
+//! The file is larger than the cache, so no chunk is served twice.
+//!
+//! Run with: cargo bench --bench block_cache
+
+use agentfs_sdk::filesystem::AgentFS;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tempfile::tempdir;
+
+const FILE_SIZE: u64 = 32 * 1024 * 1024;
+
+fn bench_stream(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let dir = tempdir().expect("Failed to create temp dir");
+    let db_path = dir.path().join("bench.db");
+    let (uncached, cached) = rt.block_on(async {
+        let fs = AgentFS::new(db_path.to_str().unwrap())
+            .await
+            .expect("Failed to create AgentFS");
+        let data: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+        fs.write_file("/large.bin", &data)
+            .await
+            .expect("Failed to write file");
+        (fs.clone().with_block_cache_size(0), fs)
+    });
+
+    let mut group = c.benchmark_group("stream_32mib_file");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(FILE_SIZE));
+
+    for read_size in [4 * 1024, 128 * 1024] {
+        for (name, fs) in [("uncached", &uncached), ("cached", &cached)] {
+            group.bench_with_input(BenchmarkId::new(name, read_size), &read_size, |b, &size| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let file = fs.open("/large.bin").await.unwrap();
+                        let mut offset = 0;
+                        while offset < FILE_SIZE {
+                            let data = file.pread(offset, size).await.unwrap();
+                            assert_eq!(data.len() as u64, size);
+                            offset += size;
+                        }
+                    })
+                });
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_stream);
+criterion_main!(benches);
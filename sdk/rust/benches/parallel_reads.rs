@@ -0,0 +1,89 @@
+//! Reading files from 8 threads at once.
+//!
+//! `shared_connection` runs every read on the filesystem's main connection;
+//! `read_connections` spreads them over a connection per thread.
+//!
+//! Run with: cargo bench --bench parallel_reads
+
+use agentfs_sdk::filesystem::{AgentFS, FileSystem};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::sync::Arc;
+use tempfile::tempdir;
+use turso::Builder;
+
+const THREADS: usize = 8;
+const FILES: usize = 1_000;
+const FILE_SIZE: usize = 16 * 1024;
+
+fn bench_parallel_reads(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(THREADS)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let dir = tempdir().expect("Failed to create temp dir");
+    let db_path = dir.path().join("bench.db");
+    let (shared, pooled) = rt.block_on(async {
+        let db = Builder::new_local(db_path.to_str().unwrap())
+            .build()
+            .await
+            .expect("Failed to open database");
+        let conn = Arc::new(db.connect().expect("Failed to connect"));
+        let shared = AgentFS::from_connection(conn)
+            .await
+            .expect("Failed to create AgentFS");
+        let data = vec![0xa5u8; FILE_SIZE];
+        for i in 0..FILES {
+            shared
+                .write_file(&format!("/file{}", i), &data)
+                .await
+                .expect("Failed to write file");
+        }
+        let pooled = shared
+            .clone()
+            .with_read_connections(&db, THREADS)
+            .await
+            .expect("Failed to open read connections");
+        (
+            Arc::new(shared) as Arc<dyn FileSystem>,
+            Arc::new(pooled) as Arc<dyn FileSystem>,
+        )
+    });
+
+    let mut group = c.benchmark_group("read_1k_files_8_threads");
+    group.sample_size(10);
+
+    for (name, fs) in [
+        ("shared_connection", &shared),
+        ("read_connections", &pooled),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let tasks: Vec<_> = (0..THREADS)
+                        .map(|thread| {
+                            let fs = fs.clone();
+                            tokio::spawn(async move {
+                                for i in (thread..FILES).step_by(THREADS) {
+                                    let path = format!("/file{}", i);
+                                    fs.stat(&path).await.unwrap().unwrap();
+                                    let data = fs.read_file(&path).await.unwrap().unwrap();
+                                    assert_eq!(data.len(), FILE_SIZE);
+                                }
+                            })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                })
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parallel_reads);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! Listing a directory of 10k entries with their attributes.
+//!
+//! `readdir_then_lstat` is what a FUSE mount without readdirplus does for
+//! `ls -l`, one lookup per entry; `readdir_plus` is what it does with it.
+//!
+//! Run with: cargo bench --bench readdir
+
+use agentfs_sdk::filesystem::{AgentFS, FileSystem};
+use criterion::{criterion_group, criterion_main, Criterion};
+use tempfile::tempdir;
+
+const ENTRIES: usize = 10_000;
+
+fn bench_readdir(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let dir = tempdir().expect("Failed to create temp dir");
+    let db_path = dir.path().join("bench.db");
+    let fs = rt.block_on(async {
+        let fs = AgentFS::new(db_path.to_str().unwrap())
+            .await
+            .expect("Failed to create AgentFS");
+        fs.mkdir("/dir").await.expect("Failed to create directory");
+        for i in 0..ENTRIES {
+            fs.create_file(&format!("/dir/file{}", i), 0o644)
+                .await
+                .expect("Failed to create file");
+        }
+        fs
+    });
+
+    let mut group = c.benchmark_group("list_10k_entries");
+    group.sample_size(10);
+
+    group.bench_function("readdir_then_lstat", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let names = fs.readdir("/dir").await.unwrap().unwrap();
+                for name in names {
+                    fs.lstat(&format!("/dir/{}", name)).await.unwrap().unwrap();
+                }
+            })
+        });
+    });
+
+    group.bench_function("readdir_plus", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let entries = fs.readdir_plus("/dir").await.unwrap().unwrap();
+                assert_eq!(entries.len(), ENTRIES);
+            })
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_readdir);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Throughput of a 100MB sequential write through a file handle.
+//!
+//! The write sizes match what the FUSE mount gets from the kernel: 4 KiB
+//! without writeback caching, 128 KiB with writeback caching but without
+//! `FUSE_MAX_PAGES`, and 1 MiB with both.
+//!
+//! Run with: cargo bench --bench sequential_write
+
+use agentfs_sdk::filesystem::{AgentFS, FileSystem};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tempfile::tempdir;
+
+const FILE_SIZE: usize = 100 * 1024 * 1024;
+
+fn bench_sequential_write(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("sequential_write_100mb");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+
+    for write_size in [4 * 1024, 128 * 1024, 1024 * 1024] {
+        let data = vec![0xabu8; write_size];
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}K", write_size / 1024)),
+            &data,
+            |b, data| {
+                b.iter_batched(
+                    || {
+                        rt.block_on(async {
+                            let dir = tempdir().expect("Failed to create temp dir");
+                            let db_path = dir.path().join("bench.db");
+                            let fs = AgentFS::new(db_path.to_str().unwrap())
+                                .await
+                                .expect("Failed to create AgentFS");
+                            let (_, file) = fs
+                                .create_file("/file.bin", 0o644)
+                                .await
+                                .expect("Failed to create file");
+                            (fs, file, dir)
+                        })
+                    },
+                    |(_fs, file, _dir)| {
+                        rt.block_on(async {
+                            for offset in (0..FILE_SIZE).step_by(data.len()) {
+                                file.pwrite(offset as u64, data).await.unwrap();
+                            }
+                            file.fsync().await.unwrap();
+                        });
+                    },
+                    criterion::BatchSize::PerIteration,
+                );
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_sequential_write);
+criterion_main!(benches);
@@ -49,10 +49,33 @@ pub enum Error {
     #[error("tool call not found")]
     ToolCallNotFound,
 
+    /// Snapshot not found
+    #[error("snapshot '{0}' not found")]
+    SnapshotNotFound(String),
+
+    /// A snapshot with the same name already exists
+    #[error("snapshot '{0}' already exists")]
+    SnapshotExists(String),
+
+    /// The filesystem is encrypted, but no passphrase was given
+    #[error("the filesystem is encrypted: a passphrase is required")]
+    PassphraseRequired,
+
+    /// The passphrase doesn't match the one the filesystem was encrypted with
+    #[error("invalid passphrase")]
+    InvalidPassphrase,
+
     /// Internal error (for unexpected conditions)
     #[error("{0}")]
     Internal(String),
 }
 
+impl Error {
+    /// Whether the database ran out of space (`SQLITE_FULL`)
+    pub fn is_database_full(&self) -> bool {
+        matches!(self, Error::Database(turso::Error::DatabaseFull(_)))
+    }
+}
+
 /// Result type alias using the SDK Error type.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,21 +1,37 @@
 use crate::error::{Error, Result};
 use async_trait::async_trait;
 use lru::LruCache;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroUsize;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
-use turso::{Builder, Connection, Value};
+use turso::{Builder, Connection, Database, Value};
 
+use super::encryption::{from_hex, to_hex, ChunkCipher, ChunkPlace};
+use super::manifest::{self, ManifestEntry};
+use super::repair::{self, RepairReport};
+use super::snapshot::{self, DataLayout, Snapshot};
 use super::{
-    BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, Stats, DEFAULT_DIR_MODE,
-    DEFAULT_FILE_MODE, S_IFLNK, S_IFMT, S_IFREG,
+    BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, Quota, Stats,
+    DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, S_IFLNK, S_IFMT, S_IFREG,
 };
 
 const ROOT_INO: i64 = 1;
 const DEFAULT_CHUNK_SIZE: usize = 4096;
 const DENTRY_CACHE_MAX_SIZE: usize = 10000;
 
+/// Number of read connections opened along with a filesystem by `new()`
+pub const DEFAULT_READ_CONNECTIONS: usize = 4;
+
+/// Default size in bytes of the cache of file data read through file handles
+pub const DEFAULT_BLOCK_CACHE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Number of chunks past a sequential read that are prefetched along with it
+const READAHEAD_CHUNKS: u64 = 32;
+
 /// LRU cache for directory entry lookups.
 ///
 /// Maps (parent_ino, name) -> child_ino to avoid repeated database queries
@@ -24,6 +40,8 @@ const DENTRY_CACHE_MAX_SIZE: usize = 10000;
 struct DentryCache {
     // Mutex required because LruCache::get() mutates internal order
     entries: Mutex<LruCache<(i64, String), i64>>,
+    /// Number of removals so far, changed with the entries locked
+    removals: AtomicU64,
 }
 
 impl DentryCache {
@@ -32,9 +50,15 @@ impl DentryCache {
             entries: Mutex::new(LruCache::new(
                 NonZeroUsize::new(max_size).expect("cache size must be > 0"),
             )),
+            removals: AtomicU64::new(0),
         }
     }
 
+    /// Get the number of removals so far, to pass to `insert_looked_up()`
+    fn removals(&self) -> u64 {
+        self.removals.load(Ordering::Acquire)
+    }
+
     /// Look up a cached entry (updates LRU order)
     fn get(&self, parent_ino: i64, name: &str) -> Option<i64> {
         self.entries
@@ -52,12 +76,615 @@ impl DentryCache {
             .put((parent_ino, name.to_string()), child_ino);
     }
 
+    /// Insert an entry looked up after `removals()` returned `removals`,
+    /// unless entries were removed since
+    ///
+    /// A lookup on a read connection can race with a change on the main
+    /// connection, and find the entry the change removes from the cache once
+    /// it is committed. Caching it after that would keep it indefinitely.
+    fn insert_looked_up(&self, parent_ino: i64, name: &str, child_ino: i64, removals: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if self.removals.load(Ordering::Acquire) == removals {
+            entries.put((parent_ino, name.to_string()), child_ino);
+        }
+    }
+
     /// Remove an entry from the cache
     fn remove(&self, parent_ino: i64, name: &str) {
-        self.entries
-            .lock()
-            .unwrap()
-            .pop(&(parent_ino, name.to_string()));
+        let mut entries = self.entries.lock().unwrap();
+        entries.pop(&(parent_ino, name.to_string()));
+        self.removals.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Remove all entries from the cache
+    fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.removals.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// Inodes with open file handles.
+///
+/// As with POSIX `unlink`, a file that loses its last link while open keeps its
+/// content until its last handle is dropped, and is freed then.
+struct OpenInodes {
+    state: Mutex<OpenInodesState>,
+    /// Runtime on which orphaned inodes are freed when their last handle is dropped
+    runtime: Option<tokio::runtime::Handle>,
+}
+
+#[derive(Default)]
+struct OpenInodesState {
+    /// Number of open handles of each inode
+    handles: HashMap<i64, usize>,
+    /// Open inodes without links left, to free along with their last handle
+    orphans: HashSet<i64>,
+}
+
+impl OpenInodes {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(OpenInodesState::default()),
+            runtime: tokio::runtime::Handle::try_current().ok(),
+        }
+    }
+
+    /// Count a new handle of `ino`
+    fn open(&self, ino: i64) {
+        let mut state = self.state.lock().unwrap();
+        *state.handles.entry(ino).or_insert(0) += 1;
+    }
+
+    /// Drop a handle of `ino`
+    ///
+    /// Returns true if it was the last handle of an orphaned inode, which the
+    /// caller should free.
+    fn close(&self, ino: i64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.handles.get_mut(&ino) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            _ => {
+                state.handles.remove(&ino);
+                state.orphans.remove(&ino)
+            }
+        }
+    }
+
+    /// Keep `ino`, which has no links left, until its last handle is dropped
+    ///
+    /// Returns false if it has no open handles, in which case the caller
+    /// should free it right away.
+    fn defer_free(&self, ino: i64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.handles.contains_key(&ino) {
+            return false;
+        }
+        state.orphans.insert(ino);
+        true
+    }
+
+    /// Keep `ino`, which was linked again, once its last handle is dropped
+    fn adopt(&self, ino: i64) {
+        self.state.lock().unwrap().orphans.remove(&ino);
+    }
+
+    /// Forget the orphaned inodes, whose numbers may now belong to other files
+    fn clear_orphans(&self) {
+        self.state.lock().unwrap().orphans.clear();
+    }
+}
+
+/// Extra connections to the database for read-only operations.
+///
+/// All changes go through the main connection, which serializes them. Reads
+/// are spread over these connections round-robin instead, so that they run
+/// alongside each other and alongside writes, as the database is in WAL mode.
+/// Without any, reads share the main connection.
+#[derive(Default)]
+struct ReadPool {
+    connections: Vec<Arc<Connection>>,
+    next: AtomicUsize,
+}
+
+impl ReadPool {
+    /// Get the connection for the next read, if there are any
+    fn get(&self) -> Option<Arc<Connection>> {
+        if self.connections.is_empty() {
+            return None;
+        }
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        Some(self.connections[next % self.connections.len()].clone())
+    }
+}
+
+/// LRU cache of file data chunks read through file handles.
+///
+/// Maps (ino, chunk_index) -> chunk data, decoded, so that rereads and the
+/// chunks prefetched by readahead are served without querying the database.
+/// Writes invalidate the chunks of their inode once committed, and so does
+/// opening the inode, for changes made by other processes. Inode numbers are
+/// never reused, so the chunks of freed inodes are simply evicted.
+struct BlockCache {
+    state: Option<Mutex<BlockCacheState>>,
+}
+
+struct BlockCacheState {
+    /// Cached chunks, with the value of `seq` when they were read
+    blocks: LruCache<(i64, i64), (u64, Arc<Vec<u8>>)>,
+    /// Value of `seq` at the last invalidation of each inode
+    invalidated: HashMap<i64, u64>,
+    /// Value of `seq` at the last invalidation of all inodes
+    cleared: u64,
+    seq: u64,
+}
+
+impl BlockCacheState {
+    /// Get the value of `seq` before which chunks of `ino` are stale
+    fn stale_before(&self, ino: i64) -> u64 {
+        let invalidated = self.invalidated.get(&ino).copied().unwrap_or(0);
+        invalidated.max(self.cleared)
+    }
+
+    /// Invalidate every inode
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.invalidated.clear();
+        self.cleared = self.seq;
+    }
+}
+
+impl BlockCache {
+    /// Create a cache of `size` bytes of `chunk_size` chunks, disabled if it
+    /// holds less than one chunk
+    fn new(size: u64, chunk_size: usize) -> Self {
+        let capacity = (size / chunk_size as u64) as usize;
+        Self {
+            state: NonZeroUsize::new(capacity).map(|capacity| {
+                Mutex::new(BlockCacheState {
+                    blocks: LruCache::new(capacity),
+                    invalidated: HashMap::new(),
+                    cleared: 0,
+                    seq: 0,
+                })
+            }),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// Get the current sequence number, to pass to `insert()` for chunks read
+    /// after this call
+    fn seq(&self) -> u64 {
+        self.state
+            .as_ref()
+            .map_or(0, |state| state.lock().unwrap().seq)
+    }
+
+    /// Look up a cached chunk (updates LRU order)
+    fn get(&self, ino: i64, chunk_index: i64) -> Option<Arc<Vec<u8>>> {
+        let mut state = self.state.as_ref()?.lock().unwrap();
+        let stale_before = state.stale_before(ino);
+        match state.blocks.get(&(ino, chunk_index)) {
+            Some((seq, data)) if *seq >= stale_before => Some(data.clone()),
+            _ => None,
+        }
+    }
+
+    /// Insert a chunk read after `seq()` returned `seq`, unless its inode was
+    /// invalidated since
+    ///
+    /// A read on a read connection can race with a write on the main
+    /// connection, and find the data the write replaces. The write invalidates
+    /// the inode after it commits, which makes such chunks stale.
+    fn insert(&self, ino: i64, chunk_index: i64, data: Arc<Vec<u8>>, seq: u64) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let mut state = state.lock().unwrap();
+        if seq >= state.stale_before(ino) {
+            state.blocks.put((ino, chunk_index), (seq, data));
+        }
+    }
+
+    /// Drop the cached chunks of `ino`
+    fn invalidate(&self, ino: i64) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let mut state = state.lock().unwrap();
+        state.seq += 1;
+        let seq = state.seq;
+        // Stale chunks are dropped lazily, so bound the bookkeeping instead
+        if state.invalidated.len() >= state.blocks.cap().get() {
+            state.clear();
+        } else {
+            state.invalidated.insert(ino, seq);
+        }
+    }
+
+    /// Drop all cached chunks
+    fn clear(&self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let mut state = state.lock().unwrap();
+        state.seq += 1;
+        state.clear();
+    }
+}
+
+/// Delete inode `ino` along with its data, symlink target and extended attributes
+async fn free_inode(conn: &Connection, chunks: &ChunkStore, ino: i64) -> Result<()> {
+    // Manually handle cascading deletes since we don't use foreign keys
+    chunks.delete_all(ino).await?;
+    for sql in [
+        "DELETE FROM fs_symlink WHERE ino = ?",
+        "DELETE FROM fs_xattr WHERE ino = ?",
+        "DELETE FROM fs_inode WHERE ino = ?",
+    ] {
+        conn.prepare_cached(sql).await?.execute((ino,)).await?;
+    }
+    Ok(())
+}
+
+/// Count the inodes of a filesystem and the bytes of file content they hold
+async fn filesystem_stats(conn: &Connection, quota: Quota) -> Result<FilesystemStats> {
+    let mut stmt = conn
+        .prepare_cached("SELECT COUNT(*), COALESCE(SUM(size), 0) FROM fs_inode")
+        .await?;
+    let mut rows = stmt.query(()).await?;
+    let (inodes, bytes_used) = match rows.next().await? {
+        Some(row) => {
+            let count = |i| {
+                row.get_value(i)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .unwrap_or(0) as u64
+            };
+            (count(0), count(1))
+        }
+        None => (0, 0),
+    };
+    Ok(FilesystemStats {
+        inodes,
+        bytes_used,
+        quota,
+    })
+}
+
+/// Fail with `FsError::NoSpace` unless `bytes` more bytes of file content and
+/// `inodes` more inodes fit in `quota`
+async fn reserve(conn: &Connection, quota: Quota, bytes: u64, inodes: u64) -> Result<()> {
+    if quota.is_unlimited() || (bytes == 0 && inodes == 0) {
+        return Ok(());
+    }
+    if !filesystem_stats(conn, quota).await?.fits(bytes, inodes) {
+        return Err(FsError::NoSpace.into());
+    }
+    Ok(())
+}
+
+/// Compression of file data chunks, chosen when a filesystem is created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Chunks are stored as is
+    #[default]
+    None,
+    /// Chunks are compressed with zstd at the given level
+    Zstd(i32),
+}
+
+/// Number of data chunks of a file, and the bytes stored in the database for them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataUsage {
+    pub chunks: u64,
+    pub stored_bytes: u64,
+}
+
+/// Cipher of encrypted filesystems, as recorded in config
+const ENCRYPTION_CIPHER: &str = "xchacha20poly1305";
+
+/// Values of the `compression` flag of a stored chunk
+const CHUNK_RAW: i64 = 0;
+const CHUNK_ZSTD: i64 = 1;
+
+/// Storage for the data chunks of files.
+///
+/// Chunks are stored inline in `fs_data`, or, on filesystems created with
+/// deduplication, once per distinct content in `fs_content`. In that case each
+/// file's `fs_content_ref` rows point to its chunks by BLAKE3 hash, and a
+/// chunk is freed once no file references it anymore.
+///
+/// With compression, each stored chunk is flagged with whether it is
+/// compressed, since chunks that don't shrink are stored raw. With encryption,
+/// chunks are encrypted after compression, bound to their content hash with
+/// deduplication and to their inode and index otherwise.
+#[derive(Clone)]
+struct ChunkStore {
+    conn: Arc<Connection>,
+    dedup: bool,
+    compression: Compression,
+    cipher: Option<Arc<ChunkCipher>>,
+}
+
+impl ChunkStore {
+    /// Get the same store on another connection to the database
+    fn on(&self, conn: Arc<Connection>) -> Self {
+        Self {
+            conn,
+            ..self.clone()
+        }
+    }
+
+    /// Read chunk `chunk_index` of inode `ino`
+    async fn read(&self, ino: i64, chunk_index: i64) -> Result<Option<Vec<u8>>> {
+        let chunks = self.read_range(ino, chunk_index, chunk_index).await?;
+        Ok(chunks.into_iter().next().map(|(_, data)| data))
+    }
+
+    /// Read the chunks of inode `ino` from `first` to `last` (inclusive), in
+    /// order, as (chunk index, data) pairs
+    async fn read_range(&self, ino: i64, first: i64, last: i64) -> Result<Vec<(i64, Vec<u8>)>> {
+        let sql = if self.dedup {
+            "SELECT r.chunk_index, c.data, c.compression, r.hash FROM fs_content_ref r
+            JOIN fs_content c ON c.hash = r.hash
+            WHERE r.ino = ? AND r.chunk_index >= ? AND r.chunk_index <= ?
+            ORDER BY r.chunk_index"
+        } else if self.compression != Compression::None {
+            "SELECT chunk_index, data, compression FROM fs_data
+            WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ?
+            ORDER BY chunk_index"
+        } else {
+            "SELECT chunk_index, data, 0 FROM fs_data
+            WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ?
+            ORDER BY chunk_index"
+        };
+        let mut stmt = self.conn.prepare_cached(sql).await?;
+        let mut rows = stmt.query((ino, first, last)).await?;
+
+        let mut chunks = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let get_integer = |idx| {
+                row.get_value(idx)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .unwrap_or(0)
+            };
+            let chunk_index = get_integer(0);
+            let flag = get_integer(2);
+            let hash = match row.get_value(3) {
+                Ok(Value::Blob(hash)) if self.dedup => Some(hash),
+                _ => None,
+            };
+            let place = match &hash {
+                Some(hash) => ChunkPlace::Content(hash),
+                None => ChunkPlace::Inode { ino, chunk_index },
+            };
+            if let Ok(Value::Blob(data)) = row.get_value(1) {
+                chunks.push((chunk_index, self.decode(data, flag, place)?));
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Read all chunks of inode `ino`, in order
+    async fn read_all(&self, ino: i64) -> Result<Vec<(i64, Vec<u8>)>> {
+        self.read_range(ino, 0, i64::MAX).await
+    }
+
+    /// Store `data` as chunk `chunk_index` of inode `ino`, replacing the
+    /// chunk's previous content
+    async fn write(&self, ino: i64, chunk_index: i64, data: &[u8]) -> Result<()> {
+        if !self.dedup {
+            let (stored, flag) = self.encode(data, ChunkPlace::Inode { ino, chunk_index })?;
+            if self.compression == Compression::None {
+                let mut stmt = self
+                    .conn
+                    .prepare_cached(
+                        "INSERT OR REPLACE INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
+                    )
+                    .await?;
+                stmt.execute((ino, chunk_index, stored.as_ref())).await?;
+            } else {
+                let mut stmt = self
+                    .conn
+                    .prepare_cached(
+                        "INSERT OR REPLACE INTO fs_data (ino, chunk_index, data, compression)
+                        VALUES (?, ?, ?, ?)",
+                    )
+                    .await?;
+                stmt.execute((ino, chunk_index, stored.as_ref(), flag))
+                    .await?;
+            }
+            return Ok(());
+        }
+
+        let hash = self.content_hash(data);
+        let hash = &hash.as_bytes()[..];
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT hash FROM fs_content_ref WHERE ino = ? AND chunk_index = ?")
+            .await?;
+        let mut rows = stmt.query((ino, chunk_index)).await?;
+        let old_hash = match rows.next().await? {
+            Some(row) => match row.get_value(0) {
+                Ok(Value::Blob(old_hash)) => Some(old_hash),
+                _ => None,
+            },
+            None => None,
+        };
+        if old_hash.as_deref() == Some(hash) {
+            return Ok(());
+        }
+
+        self.acquire_content(hash, data).await?;
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "INSERT OR REPLACE INTO fs_content_ref (ino, chunk_index, hash) VALUES (?, ?, ?)",
+            )
+            .await?;
+        stmt.execute((ino, chunk_index, hash)).await?;
+        if let Some(old_hash) = old_hash {
+            self.release_content(&old_hash).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete the chunks of inode `ino` from `first` on
+    async fn delete_from(&self, ino: i64, first: i64) -> Result<()> {
+        if !self.dedup {
+            let mut stmt = self
+                .conn
+                .prepare_cached("DELETE FROM fs_data WHERE ino = ? AND chunk_index >= ?")
+                .await?;
+            stmt.execute((ino, first)).await?;
+            return Ok(());
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT hash FROM fs_content_ref WHERE ino = ? AND chunk_index >= ?")
+            .await?;
+        let mut rows = stmt.query((ino, first)).await?;
+        let mut hashes = Vec::new();
+        while let Some(row) = rows.next().await? {
+            if let Ok(Value::Blob(hash)) = row.get_value(0) {
+                hashes.push(hash);
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("DELETE FROM fs_content_ref WHERE ino = ? AND chunk_index >= ?")
+            .await?;
+        stmt.execute((ino, first)).await?;
+        for hash in hashes {
+            self.release_content(&hash).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete all chunks of inode `ino`
+    async fn delete_all(&self, ino: i64) -> Result<()> {
+        self.delete_from(ino, 0).await
+    }
+
+    /// Get the data usage of each inode that has data chunks
+    ///
+    /// With deduplication, shared content counts towards every file using it.
+    async fn usage(&self) -> Result<HashMap<i64, DataUsage>> {
+        let sql = if self.dedup {
+            "SELECT r.ino, COUNT(*), SUM(length(c.data)) FROM fs_content_ref r
+            JOIN fs_content c ON c.hash = r.hash
+            GROUP BY r.ino"
+        } else {
+            "SELECT ino, COUNT(*), SUM(length(data)) FROM fs_data GROUP BY ino"
+        };
+        let mut rows = self.conn.query(sql, ()).await?;
+
+        let mut usage = HashMap::new();
+        while let Some(row) = rows.next().await? {
+            let get_integer = |idx| {
+                row.get_value(idx)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .unwrap_or(0)
+            };
+            let data_usage = DataUsage {
+                chunks: get_integer(1) as u64,
+                stored_bytes: get_integer(2) as u64,
+            };
+            usage.insert(get_integer(0), data_usage);
+        }
+        Ok(usage)
+    }
+
+    /// Take a reference to the content with `hash`, storing `data` for it if
+    /// it isn't stored yet
+    async fn acquire_content(&self, hash: &[u8], data: &[u8]) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("UPDATE fs_content SET refcount = refcount + 1 WHERE hash = ?")
+            .await?;
+        if stmt.execute((hash,)).await? == 0 {
+            let (stored, flag) = self.encode(data, ChunkPlace::Content(hash))?;
+            let mut stmt = self
+                .conn
+                .prepare_cached(
+                    "INSERT INTO fs_content (hash, data, compression, refcount)
+                    VALUES (?, ?, ?, 1)",
+                )
+                .await?;
+            stmt.execute((hash, stored.as_ref(), flag)).await?;
+        }
+        Ok(())
+    }
+
+    /// Drop a reference to the content with `hash`, freeing it at zero
+    async fn release_content(&self, hash: &[u8]) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("UPDATE fs_content SET refcount = refcount - 1 WHERE hash = ?")
+            .await?;
+        stmt.execute((hash,)).await?;
+        let mut stmt = self
+            .conn
+            .prepare_cached("DELETE FROM fs_content WHERE hash = ? AND refcount <= 0")
+            .await?;
+        stmt.execute((hash,)).await?;
+        Ok(())
+    }
+
+    /// Hash chunk `data` to identify its content
+    fn content_hash(&self, data: &[u8]) -> blake3::Hash {
+        match &self.cipher {
+            Some(cipher) => cipher.content_hash(data),
+            None => blake3::hash(data),
+        }
+    }
+
+    /// Prepare chunk `data` for storage at `place`, returning the bytes to
+    /// store and their compression flag
+    fn encode<'a>(&self, data: &'a [u8], place: ChunkPlace) -> Result<(Cow<'a, [u8]>, i64)> {
+        let (data, flag) = match self.compression {
+            Compression::Zstd(level) => {
+                let compressed = zstd::bulk::compress(data, level)?;
+                if compressed.len() < data.len() {
+                    (Cow::Owned(compressed), CHUNK_ZSTD)
+                } else {
+                    (Cow::Borrowed(data), CHUNK_RAW)
+                }
+            }
+            Compression::None => (Cow::Borrowed(data), CHUNK_RAW),
+        };
+        match &self.cipher {
+            Some(cipher) => Ok((Cow::Owned(cipher.encrypt(&data, place)?), flag)),
+            None => Ok((data, flag)),
+        }
+    }
+
+    /// Restore the data of a chunk stored at `place` from its bytes and
+    /// compression flag
+    fn decode(&self, stored: Vec<u8>, flag: i64, place: ChunkPlace) -> Result<Vec<u8>> {
+        let stored = match &self.cipher {
+            Some(cipher) => cipher.decrypt(&stored, place)?,
+            None => stored,
+        };
+        match flag {
+            CHUNK_RAW => Ok(stored),
+            CHUNK_ZSTD => Ok(zstd::decode_all(stored.as_slice())?),
+            _ => Err(Error::Internal(format!(
+                "unknown chunk compression flag {}",
+                flag
+            ))),
+        }
     }
 }
 
@@ -66,8 +693,20 @@ impl DentryCache {
 pub struct AgentFS {
     conn: Arc<Connection>,
     chunk_size: usize,
+    /// Storage for file data chunks
+    chunks: ChunkStore,
     /// Cache for directory entry lookups (shared across clones)
     dentry_cache: Arc<DentryCache>,
+    /// Number of snapshot restores, which invalidate open file handles
+    generation: Arc<AtomicU64>,
+    /// Inodes with open file handles (shared across clones)
+    open_inodes: Arc<OpenInodes>,
+    /// Connections for read-only operations (shared across clones)
+    readers: Arc<ReadPool>,
+    /// Cache for file data read through file handles (shared across clones)
+    blocks: Arc<BlockCache>,
+    /// Limits on the size of the filesystem, read when it is opened
+    quota: Quota,
 }
 
 /// An open file handle for AgentFS.
@@ -76,35 +715,57 @@ pub struct AgentFS {
 /// efficient read/write/fsync operations without path lookups.
 pub struct AgentFSFile {
     conn: Arc<Connection>,
+    chunks: ChunkStore,
     ino: i64,
     chunk_size: usize,
+    /// The filesystem's generation, and its value when the file was opened
+    generation: Arc<AtomicU64>,
+    opened_generation: u64,
+    open_inodes: Arc<OpenInodes>,
+    readers: Arc<ReadPool>,
+    blocks: Arc<BlockCache>,
+    /// Offset just past the last read, to detect sequential reads
+    next_offset: AtomicU64,
+    quota: Quota,
+}
+
+impl Drop for AgentFSFile {
+    fn drop(&mut self) {
+        if !self.open_inodes.close(self.ino) || self.check_generation().is_err() {
+            return;
+        }
+        // Without a runtime, the orphaned inode is left in the database
+        let Some(runtime) = &self.open_inodes.runtime else {
+            return;
+        };
+        let (conn, chunks, ino) = (self.conn.clone(), self.chunks.clone(), self.ino);
+        runtime.spawn(async move {
+            let _ = free_inode(&conn, &chunks, ino).await;
+        });
+    }
 }
 
 #[async_trait]
 impl File for AgentFSFile {
     async fn pread(&self, offset: u64, size: u64) -> Result<Vec<u8>> {
+        self.check_generation()?;
         let chunk_size = self.chunk_size as u64;
         let start_chunk = offset / chunk_size;
         let end_chunk = (offset + size).saturating_sub(1) / chunk_size;
 
-        let mut stmt = self
-            .conn
-            .prepare_cached("SELECT chunk_index, data FROM fs_data WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ? ORDER BY chunk_index")
-            .await?;
-        let mut rows = stmt
-            .query((self.ino, start_chunk as i64, end_chunk as i64))
+        // Prefetch past reads that continue the previous one
+        let sequential = self.next_offset.swap(offset + size, Ordering::Relaxed) == offset;
+        let readahead = if sequential { READAHEAD_CHUNKS } else { 0 };
+        let chunks = self
+            .read_chunks(start_chunk as i64, end_chunk as i64, readahead as i64)
             .await?;
 
         let mut result = Vec::with_capacity(size as usize);
         let start_offset_in_chunk = (offset % chunk_size) as usize;
         let mut next_expected_chunk = start_chunk;
 
-        while let Some(row) = rows.next().await? {
-            let chunk_index = row
-                .get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64;
+        for (chunk_index, chunk_data) in chunks {
+            let chunk_index = chunk_index as u64;
 
             // Fill gaps with zeros for sparse files
             while next_expected_chunk < chunk_index && result.len() < size as usize {
@@ -119,31 +780,29 @@ impl File for AgentFSFile {
                 next_expected_chunk += 1;
             }
 
-            if let Ok(Value::Blob(chunk_data)) = row.get_value(1) {
-                let skip = if chunk_index == start_chunk {
-                    start_offset_in_chunk
-                } else {
-                    0
-                };
-                if skip >= chunk_data.len() {
-                    // Chunk is smaller than skip offset, fill with zeros
-                    let zeros_needed =
-                        std::cmp::min(chunk_size as usize - skip, size as usize - result.len());
+            let skip = if chunk_index == start_chunk {
+                start_offset_in_chunk
+            } else {
+                0
+            };
+            if skip >= chunk_data.len() {
+                // Chunk is smaller than skip offset, fill with zeros
+                let zeros_needed =
+                    std::cmp::min(chunk_size as usize - skip, size as usize - result.len());
+                result.extend(std::iter::repeat_n(0u8, zeros_needed));
+            } else {
+                let remaining = size as usize - result.len();
+                let take = std::cmp::min(chunk_data.len() - skip, remaining);
+                result.extend_from_slice(&chunk_data[skip..skip + take]);
+
+                // If chunk is smaller than chunk_size, pad with zeros
+                let chunk_end = skip + take;
+                if chunk_end < chunk_size as usize && result.len() < size as usize {
+                    let zeros_needed = std::cmp::min(
+                        chunk_size as usize - chunk_end,
+                        size as usize - result.len(),
+                    );
                     result.extend(std::iter::repeat_n(0u8, zeros_needed));
-                } else {
-                    let remaining = size as usize - result.len();
-                    let take = std::cmp::min(chunk_data.len() - skip, remaining);
-                    result.extend_from_slice(&chunk_data[skip..skip + take]);
-
-                    // If chunk is smaller than chunk_size, pad with zeros
-                    let chunk_end = skip + take;
-                    if chunk_end < chunk_size as usize && result.len() < size as usize {
-                        let zeros_needed = std::cmp::min(
-                            chunk_size as usize - chunk_end,
-                            size as usize - result.len(),
-                        );
-                        result.extend(std::iter::repeat_n(0u8, zeros_needed));
-                    }
                 }
             }
             next_expected_chunk = chunk_index + 1;
@@ -158,62 +817,68 @@ impl File for AgentFSFile {
     }
 
     async fn pwrite(&self, offset: u64, data: &[u8]) -> Result<()> {
+        self.check_generation()?;
         if data.is_empty() {
             return Ok(());
         }
 
-        // Get current file size
-        let mut stmt = self
-            .conn
-            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
+        // Write all chunks in one transaction, rather than committing each one
+        self.conn
+            .prepare_cached("BEGIN IMMEDIATE")
+            .await?
+            .execute(())
             .await?;
-        let mut rows = stmt.query((self.ino,)).await?;
-        let current_size = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
 
-        // If writing beyond current size, extend with zeros first
-        if offset > current_size {
-            let zeros = vec![0u8; (offset - current_size) as usize];
-            self.write_data_at_offset(current_size, &zeros).await?;
+        let result: Result<()> = async {
+            // Check the quota in the transaction, so concurrent writers can't
+            // both take the same space
+            let current_size = self.size().await?;
+            let new_size = std::cmp::max(current_size, offset + data.len() as u64);
+            reserve(&self.conn, self.quota, new_size - current_size, 0).await?;
+
+            // If writing beyond current size, extend with zeros first
+            if offset > current_size {
+                let zeros = vec![0u8; (offset - current_size) as usize];
+                self.write_data_at_offset(current_size, &zeros).await?;
+            }
+
+            // Write the actual data
+            self.write_data_at_offset(offset, data).await?;
+
+            // Update file size and mtime
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            let mut stmt = self
+                .conn
+                .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ? WHERE ino = ?")
+                .await?;
+            stmt.execute((new_size as i64, now, self.ino)).await?;
+
+            Ok(())
         }
+        .await;
 
-        // Write the actual data
-        self.write_data_at_offset(offset, data).await?;
+        if result.is_err() {
+            let _ = self
+                .conn
+                .prepare_cached("ROLLBACK")
+                .await?
+                .execute(())
+                .await;
+            self.blocks.invalidate(self.ino);
+            return result;
+        }
 
-        // Update file size and mtime
-        let new_size = std::cmp::max(current_size, offset + data.len() as u64);
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-        let mut stmt = self
-            .conn
-            .prepare_cached("UPDATE fs_inode SET size = ?, mtime = ? WHERE ino = ?")
+        self.conn
+            .prepare_cached("COMMIT")
+            .await?
+            .execute(())
             .await?;
-        stmt.execute((new_size as i64, now, self.ino)).await?;
-
+        self.blocks.invalidate(self.ino);
         Ok(())
     }
 
     async fn truncate(&self, new_size: u64) -> Result<()> {
-        // Get current size
-        let mut stmt = self
-            .conn
-            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((self.ino,)).await?;
-        let current_size = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
-
+        self.check_generation()?;
         let chunk_size = self.chunk_size as u64;
 
         self.conn
@@ -223,44 +888,33 @@ impl File for AgentFSFile {
             .await?;
 
         let result: Result<()> = async {
+            // Check the quota in the transaction, as in pwrite()
+            let current_size = self.size().await?;
+            let growth = new_size.saturating_sub(current_size);
+            reserve(&self.conn, self.quota, growth, 0).await?;
+
             if new_size == 0 {
                 // Special case: truncate to zero - just delete all chunks
-                let mut stmt = self
-                    .conn
-                    .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                    .await?;
-                stmt.execute((self.ino,)).await?;
+                self.chunks.delete_all(self.ino).await?;
             } else if new_size < current_size {
                 // Shrinking: delete excess chunks and truncate last chunk if needed
                 let last_chunk_idx = (new_size - 1) / chunk_size;
 
                 // Delete all chunks beyond the last one we need
-                self.conn
-                    .execute(
-                        "DELETE FROM fs_data WHERE ino = ? AND chunk_index > ?",
-                        (self.ino, last_chunk_idx as i64),
-                    )
+                self.chunks
+                    .delete_from(self.ino, last_chunk_idx as i64 + 1)
                     .await?;
 
                 // Truncate the last chunk if needed
                 let offset_in_chunk = (new_size % chunk_size) as usize;
                 if offset_in_chunk > 0 {
-                    let mut stmt = self
-                        .conn
-                        .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-                        .await?;
-                    let mut rows = stmt.query((self.ino, last_chunk_idx as i64)).await?;
-
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(mut chunk_data)) = row.get_value(0) {
-                            if chunk_data.len() > offset_in_chunk {
-                                chunk_data.truncate(offset_in_chunk);
-                                let mut stmt = self
-                                    .conn
-                                    .prepare_cached("UPDATE fs_data SET data = ? WHERE ino = ? AND chunk_index = ?")
-                                    .await?;
-                                stmt.execute((Value::Blob(chunk_data), self.ino, last_chunk_idx as i64)).await?;
-                            }
+                    let chunk = self.chunks.read(self.ino, last_chunk_idx as i64).await?;
+                    if let Some(mut chunk_data) = chunk {
+                        if chunk_data.len() > offset_in_chunk {
+                            chunk_data.truncate(offset_in_chunk);
+                            self.chunks
+                                .write(self.ino, last_chunk_idx as i64, &chunk_data)
+                                .await?;
                         }
                     }
                 }
@@ -287,6 +941,7 @@ impl File for AgentFSFile {
                 .await?
                 .execute(())
                 .await;
+            self.blocks.invalidate(self.ino);
             return result;
         }
 
@@ -295,6 +950,19 @@ impl File for AgentFSFile {
             .await?
             .execute(())
             .await?;
+        self.blocks.invalidate(self.ino);
+        Ok(())
+    }
+
+    async fn readahead(&self, offset: u64, len: u64) -> Result<()> {
+        self.check_generation()?;
+        if !self.blocks.is_enabled() || len == 0 {
+            return Ok(());
+        }
+        let chunk_size = self.chunk_size as u64;
+        let first = (offset / chunk_size) as i64;
+        let last = ((offset + len - 1) / chunk_size) as i64;
+        self.read_chunks(first, last, 0).await?;
         Ok(())
     }
 
@@ -319,9 +987,10 @@ impl File for AgentFSFile {
     }
 
     async fn fstat(&self) -> Result<Stats> {
+        self.check_generation()?;
         let mut stmt = self
             .conn
-            .prepare_cached("SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime FROM fs_inode WHERE ino = ?")
+            .prepare_cached("SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime, owned FROM fs_inode WHERE ino = ?")
             .await?;
         let mut rows = stmt.query((self.ino,)).await?;
 
@@ -334,6 +1003,96 @@ impl File for AgentFSFile {
 }
 
 impl AgentFSFile {
+    /// Fail with `FsError::StaleHandle` if a snapshot was restored since the
+    /// file was opened, as its inode may now be another file
+    fn check_generation(&self) -> Result<()> {
+        if self.generation.load(Ordering::Acquire) != self.opened_generation {
+            return Err(FsError::StaleHandle.into());
+        }
+        Ok(())
+    }
+
+    /// Get the size of the file
+    async fn size(&self) -> Result<u64> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
+            .await?;
+        let mut rows = stmt.query((self.ino,)).await?;
+        let size = match rows.next().await? {
+            Some(row) => row
+                .get_value(0)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0) as u64,
+            None => 0,
+        };
+        Ok(size)
+    }
+
+    /// Get the chunk store to read from, on a read connection if there are any
+    fn reader(&self) -> ChunkStore {
+        match self.readers.get() {
+            Some(conn) => self.chunks.on(conn),
+            None => self.chunks.clone(),
+        }
+    }
+
+    /// Read chunks `first` to `last`, and prefetch the `readahead` chunks after
+    /// them into the block cache along with any it misses
+    ///
+    /// Chunks that do not exist, in sparse files or past the end of the file,
+    /// are left out.
+    async fn read_chunks(
+        &self,
+        first: i64,
+        last: i64,
+        readahead: i64,
+    ) -> Result<Vec<(i64, Arc<Vec<u8>>)>> {
+        if !self.blocks.is_enabled() {
+            let chunks = self.reader().read_range(self.ino, first, last).await?;
+            return Ok(chunks
+                .into_iter()
+                .map(|(index, data)| (index, Arc::new(data)))
+                .collect());
+        }
+
+        // Serve chunks from the cache up to the first one it misses
+        let mut chunks = Vec::new();
+        let mut missing = first;
+        while missing <= last {
+            let Some(data) = self.blocks.get(self.ino, missing) else {
+                break;
+            };
+            chunks.push((missing, data));
+            missing += 1;
+        }
+        if missing > last {
+            return Ok(chunks);
+        }
+
+        // Read the rest, along with the readahead, in one query
+        let seq = self.blocks.seq();
+        let fetched = self
+            .reader()
+            .read_range(self.ino, missing, last + readahead)
+            .await?;
+        let mut next = missing;
+        for (index, data) in fetched {
+            let data = Arc::new(data);
+            // Cache holes of sparse files as empty chunks, which read as zeros
+            for hole in next..index {
+                self.blocks.insert(self.ino, hole, Arc::default(), seq);
+            }
+            self.blocks.insert(self.ino, index, data.clone(), seq);
+            if index <= last {
+                chunks.push((index, data));
+            }
+            next = index + 1;
+        }
+        Ok(chunks)
+    }
+
     /// Write data at a specific offset, handling chunk boundaries.
     async fn write_data_at_offset(&self, offset: u64, data: &[u8]) -> Result<()> {
         let chunk_size = self.chunk_size as u64;
@@ -349,26 +1108,14 @@ impl AgentFSFile {
             let remaining_data = data.len() - written;
             let to_write = std::cmp::min(remaining_in_chunk, remaining_data);
 
-            // Get existing chunk data (if any)
-            let mut stmt = self
-                .conn
-                .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-                .await?;
-            let mut rows = stmt.query((self.ino, chunk_index)).await?;
-
-            let mut chunk_data = if let Some(row) = rows.next().await? {
-                row.get_value(0)
-                    .ok()
-                    .and_then(|v| {
-                        if let Value::Blob(b) = v {
-                            Some(b.clone())
-                        } else {
-                            None
-                        }
-                    })
-                    .unwrap_or_default()
-            } else {
+            // Get existing chunk data (if any), unless it is overwritten entirely
+            let mut chunk_data = if to_write == self.chunk_size {
                 Vec::new()
+            } else {
+                self.chunks
+                    .read(self.ino, chunk_index)
+                    .await?
+                    .unwrap_or_default()
             };
 
             // Extend chunk if needed
@@ -376,61 +1123,421 @@ impl AgentFSFile {
                 chunk_data.resize(offset_in_chunk + to_write, 0);
             }
 
-            // Write data into chunk
-            chunk_data[offset_in_chunk..offset_in_chunk + to_write]
-                .copy_from_slice(&data[written..written + to_write]);
+            // Write data into chunk
+            chunk_data[offset_in_chunk..offset_in_chunk + to_write]
+                .copy_from_slice(&data[written..written + to_write]);
+
+            // Save chunk
+            self.chunks
+                .write(self.ino, chunk_index, &chunk_data)
+                .await?;
+
+            written += to_write;
+        }
+
+        Ok(())
+    }
+}
+
+impl AgentFS {
+    /// Create a new filesystem
+    pub async fn new(db_path: &str) -> Result<Self> {
+        Self::new_with_passphrase(db_path, None).await
+    }
+
+    /// Create a new filesystem, unlocking it with `passphrase` if it is encrypted
+    ///
+    /// Unless the database is in memory, `DEFAULT_READ_CONNECTIONS` read
+    /// connections are opened along with it; see `with_read_connections()`.
+    pub async fn new_with_passphrase(db_path: &str, passphrase: Option<&str>) -> Result<Self> {
+        let db = Builder::new_local(db_path).build().await?;
+        let conn = Arc::new(db.connect()?);
+        let fs = Self::from_connection_with_passphrase(conn, passphrase).await?;
+        if db_path == ":memory:" {
+            return Ok(fs);
+        }
+        fs.with_read_connections(&db, DEFAULT_READ_CONNECTIONS)
+            .await
+    }
+
+    /// Create a filesystem from an existing connection
+    pub async fn from_connection(conn: Arc<Connection>) -> Result<Self> {
+        Self::from_connection_with_passphrase(conn, None).await
+    }
+
+    /// Create a filesystem from an existing connection, unlocking it with
+    /// `passphrase` if it is encrypted
+    ///
+    /// Fails with `Error::PassphraseRequired` if the filesystem is encrypted
+    /// and no passphrase is given, and with `Error::InvalidPassphrase` if the
+    /// passphrase is wrong. The passphrase is ignored for unencrypted
+    /// filesystems.
+    pub async fn from_connection_with_passphrase(
+        conn: Arc<Connection>,
+        passphrase: Option<&str>,
+    ) -> Result<Self> {
+        // Initialize schema first
+        Self::initialize_schema(&conn).await?;
+
+        // Disable synchronous mode for filesystem fsync() semantics.
+        conn.execute("PRAGMA synchronous = OFF", ()).await?;
+
+        // Set busy timeout to handle concurrent access gracefully.
+        // Without this, concurrent transactions fail immediately with SQLITE_BUSY.
+        conn.execute("PRAGMA busy_timeout = 5000", ()).await?;
+
+        // WAL mode lets reads on other connections run alongside writes
+        conn.execute("PRAGMA journal_mode = WAL", ()).await?;
+
+        // Get chunk_size from config (or use default)
+        let chunk_size = Self::read_chunk_size(&conn).await?;
+
+        let chunks = ChunkStore {
+            conn: conn.clone(),
+            dedup: Self::read_dedup(&conn).await?,
+            compression: Self::read_compression(&conn).await?,
+            cipher: Self::read_cipher(&conn, passphrase).await?.map(Arc::new),
+        };
+        let fs = Self {
+            conn,
+            chunk_size,
+            chunks,
+            dentry_cache: Arc::new(DentryCache::new(DENTRY_CACHE_MAX_SIZE)),
+            generation: Arc::new(AtomicU64::new(0)),
+            open_inodes: Arc::new(OpenInodes::new()),
+            readers: Arc::default(),
+            blocks: Arc::new(BlockCache::new(DEFAULT_BLOCK_CACHE_SIZE, chunk_size)),
+            quota: Self::read_quota(&conn).await?,
+        };
+        Ok(fs)
+    }
+
+    /// Open `count` connections to `db` for read-only operations
+    ///
+    /// `db` must be the database the filesystem was opened from. Stats, reads,
+    /// directory listings and other `FileSystem` operations that change
+    /// nothing then run on these connections, without waiting for each other
+    /// or for writes, which stay serialized on the main connection. With a
+    /// `count` of 0, reads share the main connection.
+    pub async fn with_read_connections(mut self, db: &Database, count: usize) -> Result<Self> {
+        let mut connections = Vec::with_capacity(count);
+        for _ in 0..count {
+            let conn = db.connect()?;
+            conn.execute("PRAGMA busy_timeout = 5000", ()).await?;
+            connections.push(Arc::new(conn));
+        }
+        self.readers = Arc::new(ReadPool {
+            connections,
+            next: AtomicUsize::new(0),
+        });
+        Ok(self)
+    }
+
+    /// Cache up to `size` bytes of the file data read through file handles
+    ///
+    /// Sequential reads through a handle also prefetch the chunks after them
+    /// into the cache, so that streaming a file takes one query per readahead
+    /// window instead of one per read. Writes through this filesystem
+    /// invalidate the chunks of the file they change. As with NFS, changes
+    /// made by other processes are seen by handles opened after them, as
+    /// opening a file drops its cached chunks. A `size` of less than one chunk
+    /// disables the cache. Handles opened before the call keep the previous
+    /// cache.
+    pub fn with_block_cache_size(mut self, size: u64) -> Self {
+        self.blocks = Arc::new(BlockCache::new(size, self.chunk_size));
+        self
+    }
+
+    /// Get a view of the filesystem that runs its queries on a read connection
+    ///
+    /// Only for operations that change nothing: a read connection does not see
+    /// changes until they are committed on the main connection.
+    fn for_reads(&self) -> Cow<'_, AgentFS> {
+        match self.readers.get() {
+            Some(conn) => Cow::Owned(AgentFS {
+                chunks: self.chunks.on(conn.clone()),
+                conn,
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// Enable content deduplication for the filesystem in `conn`
+    ///
+    /// File data is then stored once per distinct chunk, keyed by its BLAKE3
+    /// hash, no matter how many files contain it. This must be done before any
+    /// file data is written, and before the filesystem is opened.
+    pub async fn enable_dedup(conn: &Connection) -> Result<()> {
+        Self::initialize_schema(conn).await?;
+        if Self::read_dedup(conn).await? {
+            return Ok(());
+        }
+        Self::ensure_no_data(conn, "deduplication").await?;
+
+        // Create deduplicated content table
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_content (
+                hash BLOB PRIMARY KEY,
+                data BLOB NOT NULL,
+                compression INTEGER NOT NULL DEFAULT 0,
+                refcount INTEGER NOT NULL
+            )",
+            (),
+        )
+        .await?;
+
+        // Create table of the content chunks of each file
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_content_ref (
+                ino INTEGER NOT NULL,
+                chunk_index INTEGER NOT NULL,
+                hash BLOB NOT NULL,
+                PRIMARY KEY (ino, chunk_index)
+            )",
+            (),
+        )
+        .await?;
+
+        conn.execute(
+            "INSERT INTO fs_config (key, value) VALUES ('dedup', 'true')",
+            (),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Enable compression of file data for the filesystem in `conn`
+    ///
+    /// File data is then stored compressed, chunk by chunk, except for chunks
+    /// that don't shrink. Reads decompress transparently, and file sizes stay
+    /// the uncompressed ones. This must be done before any file data is
+    /// written, and before the filesystem is opened.
+    pub async fn enable_compression(conn: &Connection, compression: Compression) -> Result<()> {
+        let Compression::Zstd(level) = compression else {
+            return Ok(());
+        };
+        Self::initialize_schema(conn).await?;
+        if Self::read_compression(conn).await? == compression {
+            return Ok(());
+        }
+        Self::ensure_no_data(conn, "compression").await?;
+
+        if Self::read_compression(conn).await? == Compression::None {
+            conn.execute(
+                "ALTER TABLE fs_data ADD COLUMN compression INTEGER NOT NULL DEFAULT 0",
+                (),
+            )
+            .await?;
+        }
+        conn.execute(
+            "INSERT OR REPLACE INTO fs_config (key, value) VALUES ('compression', 'zstd')",
+            (),
+        )
+        .await?;
+        conn.execute(
+            "INSERT OR REPLACE INTO fs_config (key, value) VALUES ('compression_level', ?)",
+            (level.to_string(),),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Enable encryption of file data for the filesystem in `conn`
+    ///
+    /// File data is then encrypted with a key derived from `passphrase`, which
+    /// is needed to open the filesystem from then on. File names and metadata
+    /// are not encrypted. This must be done before any file data is written,
+    /// and before the filesystem is opened.
+    pub async fn enable_encryption(conn: &Connection, passphrase: &str) -> Result<()> {
+        Self::initialize_schema(conn).await?;
+        if Self::read_config(conn, "encryption").await?.is_some() {
+            return Ok(());
+        }
+        Self::ensure_no_data(conn, "encryption").await?;
+
+        let salt = ChunkCipher::generate_salt();
+        let cipher = ChunkCipher::derive(passphrase, &salt)?;
+        let config = [
+            ("encryption", ENCRYPTION_CIPHER.to_string()),
+            ("encryption_salt", to_hex(&salt)),
+            ("encryption_check", to_hex(&cipher.check_token()?)),
+        ];
+        for (key, value) in config {
+            conn.execute(
+                "INSERT INTO fs_config (key, value) VALUES (?, ?)",
+                (key, value),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Set the quota of the filesystem in `conn`
+    ///
+    /// Writes that would grow the filesystem past the quota then fail with
+    /// `FsError::NoSpace`. Data already stored is kept even if it exceeds the
+    /// quota. The quota applies from the next time the filesystem is opened.
+    pub async fn set_quota(conn: &Connection, quota: Quota) -> Result<()> {
+        Self::initialize_schema(conn).await?;
+        for (key, limit) in [("quota_bytes", quota.bytes), ("quota_inodes", quota.inodes)] {
+            match limit {
+                Some(limit) => {
+                    conn.execute(
+                        "INSERT OR REPLACE INTO fs_config (key, value) VALUES (?, ?)",
+                        (key, limit.to_string()),
+                    )
+                    .await?;
+                }
+                None => {
+                    conn.execute("DELETE FROM fs_config WHERE key = ?", (key,))
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail unless no file data has been written yet, as `feature` can't be
+    /// enabled for existing data
+    async fn ensure_no_data(conn: &Connection, feature: &str) -> Result<()> {
+        let sql = if Self::read_dedup(conn).await? {
+            "SELECT 1 FROM fs_content_ref LIMIT 1"
+        } else {
+            "SELECT 1 FROM fs_data LIMIT 1"
+        };
+        let mut rows = conn.query(sql, ()).await?;
+        if rows.next().await?.is_some() {
+            return Err(Error::Internal(format!(
+                "{} can only be enabled on an empty filesystem",
+                feature
+            )));
+        }
+        Ok(())
+    }
+
+    /// Get the configured chunk size
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
 
-            // Save chunk
-            let mut stmt = self
-                .conn
-                .prepare_cached(
-                    "INSERT OR REPLACE INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
-                )
-                .await?;
-            stmt.execute((self.ino, chunk_index, Value::Blob(chunk_data)))
-                .await?;
+    /// Check if file content is deduplicated
+    pub fn is_dedup(&self) -> bool {
+        self.chunks.dedup
+    }
 
-            written += to_write;
-        }
+    /// Get the compression of file content
+    pub fn compression(&self) -> Compression {
+        self.chunks.compression
+    }
 
-        Ok(())
+    /// Check if file content is encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.chunks.cipher.is_some()
     }
-}
 
-impl AgentFS {
-    /// Create a new filesystem
-    pub async fn new(db_path: &str) -> Result<Self> {
-        let db = Builder::new_local(db_path).build().await?;
-        let conn = Arc::new(db.connect()?);
-        Self::from_connection(conn).await
+    /// Get the quota of the filesystem
+    pub fn quota(&self) -> Quota {
+        self.quota
     }
 
-    /// Create a filesystem from an existing connection
-    pub async fn from_connection(conn: Arc<Connection>) -> Result<Self> {
-        // Initialize schema first
-        Self::initialize_schema(&conn).await?;
+    /// Get the data usage of each inode that has file content, by inode number
+    ///
+    /// The stored bytes are the compressed size on filesystems with
+    /// compression. With deduplication, shared content counts towards every
+    /// file using it.
+    pub async fn data_usage(&self) -> Result<HashMap<i64, DataUsage>> {
+        self.chunks.usage().await
+    }
 
-        // Disable synchronous mode for filesystem fsync() semantics.
-        conn.execute("PRAGMA synchronous = OFF", ()).await?;
+    /// Record the current state of the filesystem as snapshot `name`
+    ///
+    /// Fails with `Error::SnapshotExists` if the name is taken.
+    pub async fn create_snapshot(&self, name: &str) -> Result<Snapshot> {
+        snapshot::create(&self.conn, self.data_layout(), name).await
+    }
 
-        // Set busy timeout to handle concurrent access gracefully.
-        // Without this, concurrent transactions fail immediately with SQLITE_BUSY.
-        conn.execute("PRAGMA busy_timeout = 5000", ()).await?;
+    /// List the snapshots of the filesystem, oldest first
+    pub async fn list_snapshots(&self) -> Result<Vec<Snapshot>> {
+        snapshot::list(&self.conn).await
+    }
 
-        // Get chunk_size from config (or use default)
-        let chunk_size = Self::read_chunk_size(&conn).await?;
+    /// Atomically roll the filesystem back to snapshot `name`
+    ///
+    /// File handles opened before the restore are invalidated: their
+    /// operations fail with `FsError::StaleHandle`. Other `AgentFS` instances
+    /// on the same database, such as a mounted filesystem, are not notified
+    /// and must be reopened.
+    pub async fn restore_snapshot(&self, name: &str) -> Result<()> {
+        snapshot::restore(&self.conn, self.data_layout(), name).await?;
+        self.dentry_cache.clear();
+        self.blocks.clear();
+        self.open_inodes.clear_orphans();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
 
-        let fs = Self {
-            conn,
-            chunk_size,
-            dentry_cache: Arc::new(DentryCache::new(DENTRY_CACHE_MAX_SIZE)),
+    /// Delete snapshot `name`, freeing the data only it uses
+    pub async fn delete_snapshot(&self, name: &str) -> Result<()> {
+        snapshot::delete(&self.conn, self.data_layout(), name).await
+    }
+
+    /// List every path of the filesystem, or of snapshot `snapshot`, with a
+    /// hash of its content
+    ///
+    /// Paths are listed parents first. Content is hashed a chunk at a time, so
+    /// hashes of files from different filesystems can be compared.
+    pub async fn manifest(&self, snapshot: Option<&str>) -> Result<Vec<ManifestEntry>> {
+        let snapshot_id = match snapshot {
+            Some(name) => Some(
+                snapshot::find(&self.conn, name)
+                    .await?
+                    .ok_or_else(|| Error::SnapshotNotFound(name.to_string()))?,
+            ),
+            None => None,
         };
-        Ok(fs)
+        let source = manifest::Source {
+            snapshot_id,
+            layout: self.data_layout(),
+            chunk_size: self.chunk_size,
+        };
+        manifest::walk(&self.conn, &source, |stored, flag, place| {
+            self.chunks.decode(stored, flag, place)
+        })
+        .await
     }
 
-    /// Get the configured chunk size
-    pub fn chunk_size(&self) -> usize {
-        self.chunk_size
+    /// Check the database for inconsistencies left by a crash or by editing it
+    /// directly, and fix them if `apply` is set
+    ///
+    /// Runs SQLite's integrity check, and looks for directory entries of
+    /// missing inodes, inodes without directory entries, wrong link counts,
+    /// rows of missing inodes and, with deduplication, wrong content refcounts
+    /// and references to missing content. Applying also rebuilds the indexes. Unlinked files that are still open
+    /// count as orphaned inodes, so no other process may be using the
+    /// filesystem while repairs are applied.
+    pub async fn repair(&self, apply: bool) -> Result<RepairReport> {
+        let report = repair::repair(&self.conn, self.data_layout(), apply).await?;
+        if apply {
+            self.dentry_cache.clear();
+            self.blocks.clear();
+        }
+        Ok(report)
+    }
+
+    /// Check the database for inconsistencies without changing it
+    ///
+    /// Runs the same checks as [`AgentFS::repair`] with nothing applied, so the
+    /// report lists what a repair would fix.
+    pub async fn verify(&self) -> Result<RepairReport> {
+        repair::repair(&self.conn, self.data_layout(), false).await
+    }
+
+    fn data_layout(&self) -> DataLayout {
+        DataLayout {
+            dedup: self.chunks.dedup,
+            compressed: self.chunks.compression != Compression::None,
+        }
     }
 
     /// Get the underlying database connection
@@ -461,12 +1568,26 @@ impl AgentFS {
                 size INTEGER NOT NULL DEFAULT 0,
                 atime INTEGER NOT NULL,
                 mtime INTEGER NOT NULL,
-                ctime INTEGER NOT NULL
+                ctime INTEGER NOT NULL,
+                owned INTEGER NOT NULL DEFAULT 0
             )",
             (),
         )
         .await?;
 
+        // Databases created before ownership was tracked lack the owned column
+        if conn
+            .query("SELECT owned FROM fs_inode LIMIT 0", ())
+            .await
+            .is_err()
+        {
+            conn.execute(
+                "ALTER TABLE fs_inode ADD COLUMN owned INTEGER NOT NULL DEFAULT 0",
+                (),
+            )
+            .await?;
+        }
+
         // Create directory entry table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS fs_dentry (
@@ -510,6 +1631,18 @@ impl AgentFS {
         )
         .await?;
 
+        // Create extended attribute table
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_xattr (
+                ino INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (ino, name)
+            )",
+            (),
+        )
+        .await?;
+
         // Ensure chunk_size config exists
         let mut rows = conn
             .query("SELECT value FROM fs_config WHERE key = 'chunk_size'", ())
@@ -563,6 +1696,92 @@ impl AgentFS {
         }
     }
 
+    /// Read whether deduplication is enabled from config
+    async fn read_dedup(conn: &Connection) -> Result<bool> {
+        Ok(Self::read_config(conn, "dedup").await?.as_deref() == Some("true"))
+    }
+
+    /// Read the compression of file data from config
+    async fn read_compression(conn: &Connection) -> Result<Compression> {
+        match Self::read_config(conn, "compression").await?.as_deref() {
+            None | Some("none") => Ok(Compression::None),
+            Some("zstd") => {
+                let level = Self::read_config(conn, "compression_level")
+                    .await?
+                    .and_then(|level| level.parse().ok())
+                    .unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+                Ok(Compression::Zstd(level))
+            }
+            Some(other) => Err(Error::Internal(format!("unknown compression '{}'", other))),
+        }
+    }
+
+    /// Read the quota from config
+    async fn read_quota(conn: &Connection) -> Result<Quota> {
+        let mut quota = Quota::default();
+        for (key, limit) in [
+            ("quota_bytes", &mut quota.bytes),
+            ("quota_inodes", &mut quota.inodes),
+        ] {
+            *limit = match Self::read_config(conn, key).await? {
+                Some(value) => Some(
+                    value
+                        .parse()
+                        .map_err(|_| Error::Internal(format!("invalid {} in config", key)))?,
+                ),
+                None => None,
+            };
+        }
+        Ok(quota)
+    }
+
+    /// Derive the cipher of an encrypted filesystem from `passphrase`,
+    /// checking that it is the right one
+    async fn read_cipher(
+        conn: &Connection,
+        passphrase: Option<&str>,
+    ) -> Result<Option<ChunkCipher>> {
+        match Self::read_config(conn, "encryption").await?.as_deref() {
+            None => return Ok(None),
+            Some(ENCRYPTION_CIPHER) => {}
+            Some(other) => return Err(Error::Internal(format!("unknown encryption '{}'", other))),
+        }
+        let passphrase = passphrase.ok_or(Error::PassphraseRequired)?;
+
+        let salt = Self::read_hex_config(conn, "encryption_salt").await?;
+        let check = Self::read_hex_config(conn, "encryption_check").await?;
+
+        let cipher = ChunkCipher::derive(passphrase, &salt)?;
+        if !cipher.verify(&check) {
+            return Err(Error::InvalidPassphrase);
+        }
+        Ok(Some(cipher))
+    }
+
+    /// Read a hex-encoded binary value from config
+    async fn read_hex_config(conn: &Connection, key: &str) -> Result<Vec<u8>> {
+        Self::read_config(conn, key)
+            .await?
+            .as_deref()
+            .and_then(from_hex)
+            .ok_or_else(|| Error::Internal(format!("invalid {} in config", key)))
+    }
+
+    /// Read a text value from config
+    async fn read_config(conn: &Connection, key: &str) -> Result<Option<String>> {
+        let mut rows = conn
+            .query("SELECT value FROM fs_config WHERE key = ?", (key,))
+            .await?;
+
+        Ok(match rows.next().await? {
+            Some(row) => match row.get_value(0) {
+                Ok(Value::Text(value)) => Some(value),
+                _ => None,
+            },
+            None => None,
+        })
+    }
+
     /// Normalize a path
     fn normalize_path(&self, path: &str) -> String {
         let normalized = path.trim_end_matches('/');
@@ -662,10 +1881,30 @@ impl AgentFS {
         }
     }
 
+    /// Get the size of an inode's content
+    async fn get_size(&self, ino: i64) -> Result<u64> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
+
+        if let Some(row) = rows.next().await? {
+            let size = row
+                .get_value(0)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0);
+            Ok(size as u64)
+        } else {
+            Ok(0)
+        }
+    }
+
     /// Build a Stats object from a database row
     ///
     /// The row should contain columns in this order:
-    /// ino, mode, nlink, uid, gid, size, atime, mtime, ctime
+    /// ino, mode, nlink, uid, gid, size, atime, mtime, ctime, owned
     fn build_stats_from_row(row: &turso::Row) -> Result<Stats> {
         Ok(Stats {
             ino: row
@@ -713,6 +1952,11 @@ impl AgentFS {
                 .ok()
                 .and_then(|v| v.as_integer().copied())
                 .unwrap_or(0),
+            owned: row
+                .get_value(9)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .is_some_and(|owned| owned != 0),
         })
     }
 
@@ -732,6 +1976,7 @@ impl AgentFS {
             }
 
             // Cache miss - query database
+            let removals = self.dentry_cache.removals();
             let mut statement = self
                 .conn
                 .prepare_cached("SELECT ino FROM fs_dentry WHERE parent_ino = ? AND name = ?")
@@ -758,7 +2003,8 @@ impl AgentFS {
                     .unwrap_or(0);
 
                 // Populate cache
-                self.dentry_cache.insert(current_ino, &component, child_ino);
+                self.dentry_cache
+                    .insert_looked_up(current_ino, &component, child_ino, removals);
                 current_ino = child_ino;
             } else {
                 return Ok(None);
@@ -778,7 +2024,7 @@ impl AgentFS {
 
         let mut stmt = self
             .conn
-            .prepare_cached("SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime FROM fs_inode WHERE ino = ?")
+            .prepare_cached("SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime, owned FROM fs_inode WHERE ino = ?")
             .await?;
 
         let mut rows = stmt.query((ino,)).await?;
@@ -808,7 +2054,7 @@ impl AgentFS {
             let mut rows = self
                 .conn
                 .query(
-                    "SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime FROM fs_inode WHERE ino = ?",
+                    "SELECT ino, mode, nlink, uid, gid, size, atime, mtime, ctime, owned FROM fs_inode WHERE ino = ?",
                     (ino,),
                 )
                 .await?;
@@ -880,6 +2126,7 @@ impl AgentFS {
         if self.lookup_child(parent_ino, name).await?.is_some() {
             return Err(FsError::AlreadyExists.into());
         }
+        reserve(&self.conn, self.quota, 0, 1).await?;
 
         // Create inode
         let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
@@ -948,15 +2195,19 @@ impl AgentFS {
             .execute(())
             .await?;
 
-        let result: Result<()> = async {
+        let result: Result<i64> = async {
             // Check if file exists (single query using parent_ino we already have)
-            let ino = if let Some(ino) = self.lookup_child(parent_ino, name).await? {
+            let existing = self.lookup_child(parent_ino, name).await?;
+            let old_size = match existing {
+                Some(ino) => self.get_size(ino).await?,
+                None => 0,
+            };
+            let growth = (data.len() as u64).saturating_sub(old_size);
+            reserve(&self.conn, self.quota, growth, existing.is_none() as u64).await?;
+
+            let ino = if let Some(ino) = existing {
                 // Delete existing data
-                let mut stmt = self
-                    .conn
-                    .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                    .await?;
-                stmt.execute((ino,)).await?;
+                self.chunks.delete_all(ino).await?;
                 ino
             } else {
                 // Create new inode
@@ -1002,12 +2253,7 @@ impl AgentFS {
 
             // Write data in chunks
             for (chunk_index, chunk) in data.chunks(self.chunk_size).enumerate() {
-                self.conn
-                    .execute(
-                        "INSERT INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
-                        (ino, chunk_index as i64, chunk),
-                    )
-                    .await?;
+                self.chunks.write(ino, chunk_index as i64, chunk).await?;
             }
 
             // Update mode (to regular file), size and mtime
@@ -1019,17 +2265,18 @@ impl AgentFS {
             stmt.execute((DEFAULT_FILE_MODE as i64, data.len() as i64, now, ino))
                 .await?;
 
-            Ok(())
+            Ok(ino)
         }
         .await;
 
         match result {
-            Ok(()) => {
+            Ok(ino) => {
                 self.conn
                     .prepare_cached("COMMIT")
                     .await?
                     .execute(())
                     .await?;
+                self.blocks.invalidate(ino);
                 Ok(())
             }
             Err(e) => {
@@ -1092,6 +2339,18 @@ impl AgentFS {
             .execute(())
             .await?;
 
+        // Check the quota in the transaction, so concurrent creates can't
+        // both take the last inode
+        if let Err(e) = reserve(&self.conn, self.quota, 0, 1).await {
+            let _ = self
+                .conn
+                .prepare_cached("ROLLBACK")
+                .await?
+                .execute(())
+                .await;
+            return Err(e);
+        }
+
         let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
         let file_mode = S_IFREG | (mode & 0o7777);
 
@@ -1127,14 +2386,57 @@ impl AgentFS {
             atime: now,
             mtime: now,
             ctime: now,
+            owned: false,
         };
 
-        let file: BoxedFile = Arc::new(AgentFSFile {
-            conn: self.conn.clone(),
-            ino,
-            chunk_size: self.chunk_size,
-        });
+        let file: BoxedFile = Arc::new(self.file_handle(ino));
+
+        Ok((stats, file))
+    }
+
+    /// Create an unnamed regular file in the directory `dir`, like `O_TMPFILE`.
+    ///
+    /// The inode starts without links, so it is freed along with the last
+    /// handle of the returned file, unless `link_inode` links it first.
+    pub async fn create_tmpfile(&self, dir: &str, mode: u32) -> Result<(Stats, BoxedFile)> {
+        let dir_stats = self.stat(dir).await?.ok_or(FsError::NotFound)?;
+        if !dir_stats.is_directory() {
+            return Err(FsError::NotADirectory.into());
+        }
+        reserve(&self.conn, self.quota, 0, 1).await?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let file_mode = S_IFREG | (mode & 0o7777);
+        let row = self
+            .conn
+            .prepare_cached(
+                "INSERT INTO fs_inode (mode, nlink, uid, gid, size, atime, mtime, ctime)
+                 VALUES (?, 0, 0, 0, 0, ?, ?, ?) RETURNING ino",
+            )
+            .await?
+            .query_row((file_mode as i64, now, now, now))
+            .await?;
+        let ino = row
+            .get_value(0)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .ok_or_else(|| Error::Internal("failed to get inode".to_string()))?;
+
+        let file: BoxedFile = Arc::new(self.file_handle(ino));
+        self.open_inodes.defer_free(ino);
 
+        let stats = Stats {
+            ino,
+            mode: file_mode,
+            nlink: 0,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            owned: false,
+        };
         Ok((stats, file))
     }
 
@@ -1145,20 +2447,15 @@ impl AgentFS {
             None => return Ok(None),
         };
 
-        let mut rows = self
-            .conn
-            .query(
-                "SELECT data FROM fs_data WHERE ino = ? ORDER BY chunk_index",
-                (ino,),
-            )
-            .await?;
-
-        let mut data = Vec::new();
-        while let Some(row) = rows.next().await? {
-            if let Ok(Value::Blob(chunk)) = row.get_value(0) {
-                data.extend_from_slice(&chunk);
-            }
+        let size = self.get_size(ino).await? as usize;
+        let mut data = Vec::with_capacity(size);
+        for (chunk_index, chunk) in self.chunks.read_all(ino).await? {
+            // Holes of sparse files read as zeros
+            data.resize(chunk_index as usize * self.chunk_size, 0);
+            data.extend_from_slice(&chunk);
         }
+        // So does the tail of a file extended past its last chunk
+        data.resize(size, 0);
 
         Ok(Some(data))
     }
@@ -1175,35 +2472,32 @@ impl AgentFS {
             None => return Ok(None),
         };
 
+        // Stop at the end of the file
+        let size = size.min(self.get_size(ino).await?.saturating_sub(offset));
+        if size == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
         // Calculate which chunks we need
         let chunk_size = self.chunk_size as u64;
         let start_chunk = offset / chunk_size;
-        let end_chunk = (offset + size).saturating_sub(1) / chunk_size;
+        let end_chunk = (offset + size - 1) / chunk_size;
 
-        let mut rows = self
-            .conn
-            .query(
-                "SELECT chunk_index, data FROM fs_data WHERE ino = ? AND chunk_index >= ? AND chunk_index <= ? ORDER BY chunk_index",
-                (ino, start_chunk as i64, end_chunk as i64),
-            )
+        let chunks = self
+            .chunks
+            .read_range(ino, start_chunk as i64, end_chunk as i64)
             .await?;
 
-        let mut result = Vec::with_capacity(size as usize);
-        let start_offset_in_chunk = (offset % chunk_size) as usize;
-
-        while let Some(row) = rows.next().await? {
-            if let Ok(Value::Blob(chunk_data)) = row.get_value(1) {
-                let skip = if result.is_empty() {
-                    start_offset_in_chunk
-                } else {
-                    0
-                };
-                if skip >= chunk_data.len() {
-                    continue;
-                }
-                let remaining = size as usize - result.len();
-                let take = std::cmp::min(chunk_data.len() - skip, remaining);
-                result.extend_from_slice(&chunk_data[skip..skip + take]);
+        // Holes of sparse files and the tail past the last chunk read as zeros
+        let mut result = vec![0u8; size as usize];
+        for (chunk_index, chunk_data) in chunks {
+            let chunk_start = chunk_index as u64 * chunk_size;
+            let from = offset.max(chunk_start);
+            let to = (offset + size).min(chunk_start + chunk_data.len() as u64);
+            if from < to {
+                result[(from - offset) as usize..(to - offset) as usize].copy_from_slice(
+                    &chunk_data[(from - chunk_start) as usize..(to - chunk_start) as usize],
+                );
             }
         }
 
@@ -1244,24 +2538,21 @@ impl AgentFS {
             .execute(())
             .await?;
 
-        let result: Result<()> = async {
+        let result: Result<i64> = async {
+            let existing = self.resolve_path(&path).await?;
+            let current_size = match existing {
+                Some(ino) => self.get_size(ino).await?,
+                None => 0,
+            };
+            let growth = match data.len() {
+                0 => 0,
+                len => (offset + len as u64).saturating_sub(current_size),
+            };
+            reserve(&self.conn, self.quota, growth, existing.is_none() as u64).await?;
+
             // Get or create the inode
-            let (ino, current_size) = if let Some(ino) = self.resolve_path(&path).await? {
-                // Get current file size
-                let mut stmt = self
-                    .conn
-                    .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
-                    .await?;
-                let mut rows = stmt.query((ino,)).await?;
-                let size = if let Some(row) = rows.next().await? {
-                    row.get_value(0)
-                        .ok()
-                        .and_then(|v| v.as_integer().copied())
-                        .unwrap_or(0) as u64
-                } else {
-                    0
-                };
-                (ino, size)
+            let (ino, current_size) = if let Some(ino) = existing {
+                (ino, current_size)
             } else {
                 // Create new inode
                 let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
@@ -1309,7 +2600,7 @@ impl AgentFS {
                     .await?
                     .execute((now, ino))
                     .await?;
-                return Ok(());
+                return Ok(ino);
             }
 
             let chunk_size = self.chunk_size as u64;
@@ -1343,24 +2634,13 @@ impl AgentFS {
                 // Read existing chunk if we need to preserve some data
                 let needs_read = data_start > 0 || data_end < chunk_size as usize;
                 let mut chunk_data = if needs_read {
-                    let mut rows = self
-                        .conn
-                        .query(
-                            "SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?",
-                            (ino, chunk_idx as i64),
-                        )
-                        .await?;
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(data)) = row.get_value(0) {
-                            let mut v = data.clone();
-                            v.resize(chunk_size as usize, 0);
-                            v
-                        } else {
-                            vec![0u8; chunk_size as usize]
-                        }
-                    } else {
-                        vec![0u8; chunk_size as usize]
-                    }
+                    let mut v = self
+                        .chunks
+                        .read(ino, chunk_idx as i64)
+                        .await?
+                        .unwrap_or_default();
+                    v.resize(chunk_size as usize, 0);
+                    v
                 } else {
                     vec![0u8; chunk_size as usize]
                 };
@@ -1381,18 +2661,9 @@ impl AgentFS {
                     chunk_size as usize
                 };
 
-                // Write the chunk - delete existing then insert
-                self.conn
-                    .execute(
-                        "DELETE FROM fs_data WHERE ino = ? AND chunk_index = ?",
-                        (ino, chunk_idx as i64),
-                    )
-                    .await?;
-                self.conn
-                    .execute(
-                        "INSERT INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
-                        (ino, chunk_idx as i64, &chunk_data[..actual_len]),
-                    )
+                // Write the chunk, replacing the existing one
+                self.chunks
+                    .write(ino, chunk_idx as i64, &chunk_data[..actual_len])
                     .await?;
             }
 
@@ -1405,17 +2676,18 @@ impl AgentFS {
                 .await?;
             stmt.execute((new_size as i64, now, ino)).await?;
 
-            Ok(())
+            Ok(ino)
         }
         .await;
 
         match result {
-            Ok(()) => {
+            Ok(ino) => {
                 self.conn
                     .prepare_cached("COMMIT")
                     .await?
                     .execute(())
                     .await?;
+                self.blocks.invalidate(ino);
                 Ok(())
             }
             Err(e) => {
@@ -1430,30 +2702,14 @@ impl AgentFS {
         }
     }
 
-    /// Truncate a file to a specific size.
-    ///
-    /// This operates directly on chunks without loading the entire file into memory:
-    /// - Shrinking: deletes chunks beyond new size, truncates the last chunk if needed
-    /// - Extending: pads with zeros up to the new size
-    pub async fn truncate(&self, path: &str, new_size: u64) -> Result<()> {
-        let path = self.normalize_path(path);
-        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
-
-        // Get current size
-        let mut stmt = self
-            .conn
-            .prepare_cached("SELECT size FROM fs_inode WHERE ino = ?")
-            .await?;
-        let mut rows = stmt.query((ino,)).await?;
-        let current_size = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
-
+    /// Truncate a file to a specific size.
+    ///
+    /// This operates directly on chunks without loading the entire file into memory:
+    /// - Shrinking: deletes chunks beyond new size, truncates the last chunk if needed
+    /// - Extending: pads with zeros up to the new size
+    pub async fn truncate(&self, path: &str, new_size: u64) -> Result<()> {
+        let path = self.normalize_path(path);
+        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
         let chunk_size = self.chunk_size as u64;
 
         self.conn
@@ -1463,23 +2719,22 @@ impl AgentFS {
             .await?;
 
         let result: Result<()> = async {
+            // Get current size, and check the quota in the transaction so
+            // concurrent writers can't both take the same space
+            let current_size = self.get_size(ino).await?;
+            let growth = new_size.saturating_sub(current_size);
+            reserve(&self.conn, self.quota, growth, 0).await?;
+
             if new_size == 0 {
                 // Special case: truncate to zero - just delete all chunks
-                let mut stmt = self
-                    .conn
-                    .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                    .await?;
-                stmt.execute((ino,)).await?;
+                self.chunks.delete_all(ino).await?;
             } else if new_size < current_size {
                 // Shrinking: delete excess chunks and truncate last chunk if needed
                 let last_chunk_idx = (new_size - 1) / chunk_size;
 
                 // Delete all chunks beyond the last one we need
-                self.conn
-                    .execute(
-                        "DELETE FROM fs_data WHERE ino = ? AND chunk_index > ?",
-                        (ino, last_chunk_idx as i64),
-                    )
+                self.chunks
+                    .delete_from(ino, last_chunk_idx as i64 + 1)
                     .await?;
 
                 // Calculate where in the last chunk the file should end
@@ -1488,22 +2743,13 @@ impl AgentFS {
                 // If the last chunk needs to be truncated (not a full chunk),
                 // read it, truncate, and rewrite
                 if end_in_last_chunk < chunk_size {
-                    let mut stmt = self
-                        .conn
-                        .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-                        .await?;
-                    let mut rows = stmt.query((ino, last_chunk_idx as i64)).await?;
-
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(chunk_data)) = row.get_value(0) {
-                            if chunk_data.len() > end_in_last_chunk as usize {
-                                let truncated = &chunk_data[..end_in_last_chunk as usize];
-                                let mut stmt = self
-                                    .conn
-                                    .prepare_cached("UPDATE fs_data SET data = ? WHERE ino = ? AND chunk_index = ?")
-                                    .await?;
-                                stmt.execute((truncated, ino, last_chunk_idx as i64)).await?;
-                            }
+                    let chunk = self.chunks.read(ino, last_chunk_idx as i64).await?;
+                    if let Some(chunk_data) = chunk {
+                        if chunk_data.len() > end_in_last_chunk as usize {
+                            let truncated = &chunk_data[..end_in_last_chunk as usize];
+                            self.chunks
+                                .write(ino, last_chunk_idx as i64, truncated)
+                                .await?;
                         }
                     }
                 }
@@ -1518,32 +2764,18 @@ impl AgentFS {
 
                 // Pad the last existing chunk with zeros if it's not full
                 if let Some(last_idx) = last_existing_chunk {
-                    let mut stmt = self
-                        .conn
-                        .prepare_cached("SELECT data FROM fs_data WHERE ino = ? AND chunk_index = ?")
-                        .await?;
-                    let mut rows = stmt.query((ino, last_idx as i64)).await?;
+                    if let Some(mut chunk_data) = self.chunks.read(ino, last_idx as i64).await? {
+                        let needed_len = if last_idx == last_new_chunk {
+                            // Last existing chunk is also the last new chunk
+                            ((new_size - 1) % chunk_size + 1) as usize
+                        } else {
+                            // Need to fill this chunk completely
+                            chunk_size as usize
+                        };
 
-                    if let Some(row) = rows.next().await? {
-                        if let Ok(Value::Blob(chunk_data)) = row.get_value(0) {
-                            let current_chunk_len = chunk_data.len();
-                            let needed_len = if last_idx == last_new_chunk {
-                                // Last existing chunk is also the last new chunk
-                                ((new_size - 1) % chunk_size + 1) as usize
-                            } else {
-                                // Need to fill this chunk completely
-                                chunk_size as usize
-                            };
-
-                            if needed_len > current_chunk_len {
-                                let mut padded = chunk_data.clone();
-                                padded.resize(needed_len, 0);
-                                let mut stmt = self
-                                    .conn
-                                    .prepare_cached("UPDATE fs_data SET data = ? WHERE ino = ? AND chunk_index = ?")
-                                    .await?;
-                                stmt.execute((&padded[..], ino, last_idx as i64)).await?;
-                            }
+                        if needed_len > chunk_data.len() {
+                            chunk_data.resize(needed_len, 0);
+                            self.chunks.write(ino, last_idx as i64, &chunk_data).await?;
                         }
                     }
                 }
@@ -1557,12 +2789,7 @@ impl AgentFS {
                         chunk_size as usize
                     };
                     let zeros = vec![0u8; chunk_len];
-                    self.conn
-                        .execute(
-                            "INSERT INTO fs_data (ino, chunk_index, data) VALUES (?, ?, ?)",
-                            (ino, chunk_idx as i64, &zeros[..]),
-                        )
-                        .await?;
+                    self.chunks.write(ino, chunk_idx as i64, &zeros).await?;
                 }
             }
             // else: new_size == current_size, nothing to do for data
@@ -1586,6 +2813,7 @@ impl AgentFS {
                     .await?
                     .execute(())
                     .await?;
+                self.blocks.invalidate(ino);
                 Ok(())
             }
             Err(e) => {
@@ -1649,7 +2877,7 @@ impl AgentFS {
         let mut rows = self
             .conn
             .query(
-                "SELECT d.name, i.ino, i.mode, i.nlink, i.uid, i.gid, i.size, i.atime, i.mtime, i.ctime
+                "SELECT d.name, i.ino, i.mode, i.nlink, i.uid, i.gid, i.size, i.atime, i.mtime, i.ctime, i.owned
                  FROM fs_dentry d
                  JOIN fs_inode i ON d.ino = i.ino
                  WHERE d.parent_ino = ?
@@ -1726,6 +2954,11 @@ impl AgentFS {
                     .ok()
                     .and_then(|v| v.as_integer().copied())
                     .unwrap_or(0),
+                owned: row
+                    .get_value(10)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .is_some_and(|owned| owned != 0),
             };
 
             entries.push(DirEntry { name, stats });
@@ -1761,6 +2994,7 @@ impl AgentFS {
         if self.lookup_child(parent_ino, name).await?.is_some() {
             return Err(FsError::AlreadyExists.into());
         }
+        reserve(&self.conn, self.quota, target.len() as u64, 1).await?;
 
         // Create inode for symlink
         let now = SystemTime::now()
@@ -1824,12 +3058,6 @@ impl AgentFS {
     /// The link count (nlink) of the inode is incremented.
     pub async fn link(&self, oldpath: &str, newpath: &str) -> Result<()> {
         let oldpath = self.normalize_path(oldpath);
-        let newpath = self.normalize_path(newpath);
-        let components = self.split_path(&newpath);
-
-        if components.is_empty() {
-            return Err(FsError::RootOperation.into());
-        }
 
         // Resolve old path to get its inode
         let ino = self
@@ -1837,6 +3065,22 @@ impl AgentFS {
             .await?
             .ok_or(FsError::NotFound)?;
 
+        self.link_inode(ino, newpath).await
+    }
+
+    /// Create a hard link to inode `ino` at `newpath`
+    ///
+    /// The inode may have no links left, like one from `create_tmpfile`, as
+    /// long as a handle keeps it open; it is then no longer freed along with
+    /// its last handle.
+    pub async fn link_inode(&self, ino: i64, newpath: &str) -> Result<()> {
+        let newpath = self.normalize_path(newpath);
+        let components = self.split_path(&newpath);
+
+        if components.is_empty() {
+            return Err(FsError::RootOperation.into());
+        }
+
         // Check if source is a directory (hard links to directories are not allowed)
         let mut rows = self
             .conn
@@ -1891,6 +3135,7 @@ impl AgentFS {
                 (ino,),
             )
             .await?;
+        self.open_inodes.adopt(ino);
 
         // Populate dentry cache
         self.dentry_cache.insert(parent_ino, name, ino);
@@ -2013,30 +3258,10 @@ impl AgentFS {
             .await?;
         stmt.execute((ino,)).await?;
 
-        // Check if this was the last link to the inode
+        // Free the inode with its last link, unless it is still open
         let link_count = self.get_link_count(ino).await?;
-        if link_count == 0 {
-            // Manually handle cascading deletes since we don't use foreign keys
-            // Delete data blocks
-            let mut stmt = self
-                .conn
-                .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
-
-            // Delete symlink if exists
-            let mut stmt = self
-                .conn
-                .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
-
-            // Delete inode
-            let mut stmt = self
-                .conn
-                .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
-                .await?;
-            stmt.execute((ino,)).await?;
+        if link_count == 0 && !self.open_inodes.defer_free(ino) {
+            free_inode(&self.conn, &self.chunks, ino).await?;
         }
 
         Ok(())
@@ -2078,6 +3303,256 @@ impl AgentFS {
         Ok(())
     }
 
+    /// Change file ownership.
+    ///
+    /// A `None` uid or gid leaves that field unchanged. Either way the
+    /// ownership is marked as set, see `Stats::owned`. Symlinks are not
+    /// followed, so chown on a symlink changes the link itself.
+    pub async fn chown(&self, path: &str, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        let path = self.normalize_path(path);
+
+        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
+
+        // Get current ownership to preserve unchanged fields
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT uid, gid FROM fs_inode WHERE ino = ?")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
+
+        let (current_uid, current_gid) = if let Some(row) = rows.next().await? {
+            let get = |idx| {
+                row.get_value(idx)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .unwrap_or(0) as u32
+            };
+            (get(0), get(1))
+        } else {
+            return Err(FsError::NotFound.into());
+        };
+
+        let new_uid = uid.unwrap_or(current_uid);
+        let new_gid = gid.unwrap_or(current_gid);
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("UPDATE fs_inode SET uid = ?, gid = ?, owned = 1 WHERE ino = ?")
+            .await?;
+        stmt.execute((new_uid as i64, new_gid as i64, ino)).await?;
+
+        Ok(())
+    }
+
+    /// Change file access and modification times.
+    ///
+    /// Times are in seconds since the Unix epoch. A `None` time leaves that
+    /// timestamp unchanged. Symlinks are not followed, so utimes on a symlink
+    /// changes the link itself. The change time is set to the current time.
+    pub async fn utimes(&self, path: &str, atime: Option<i64>, mtime: Option<i64>) -> Result<()> {
+        let path = self.normalize_path(path);
+
+        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
+
+        // Get current times to preserve unchanged fields
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT atime, mtime FROM fs_inode WHERE ino = ?")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
+
+        let (current_atime, current_mtime) = if let Some(row) = rows.next().await? {
+            let get = |idx| {
+                row.get_value(idx)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .unwrap_or(0)
+            };
+            (get(0), get(1))
+        } else {
+            return Err(FsError::NotFound.into());
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let new_atime = atime.unwrap_or(current_atime);
+        let new_mtime = mtime.unwrap_or(current_mtime);
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("UPDATE fs_inode SET atime = ?, mtime = ?, ctime = ? WHERE ino = ?")
+            .await?;
+        stmt.execute((new_atime, new_mtime, now, ino)).await?;
+
+        Ok(())
+    }
+
+    /// Get the value of an extended attribute.
+    ///
+    /// Returns `Ok(None)` if the attribute is not set. Symlinks are not
+    /// followed, so the attributes of the link itself are read.
+    pub async fn getxattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.normalize_path(path);
+        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT value FROM fs_xattr WHERE ino = ? AND name = ?")
+            .await?;
+        let mut rows = stmt.query((ino, name)).await?;
+
+        if let Some(row) = rows.next().await? {
+            match row.get_value(0) {
+                Ok(Value::Blob(value)) => Ok(Some(value)),
+                _ => Ok(Some(Vec::new())),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Set an extended attribute, replacing any existing value.
+    ///
+    /// Symlinks are not followed, so the attribute is set on the link itself.
+    pub async fn setxattr(&self, path: &str, name: &str, value: &[u8]) -> Result<()> {
+        let path = self.normalize_path(path);
+        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("INSERT OR REPLACE INTO fs_xattr (ino, name, value) VALUES (?, ?, ?)")
+            .await?;
+        stmt.execute((ino, name, Value::Blob(value.to_vec())))
+            .await?;
+
+        Ok(())
+    }
+
+    /// List the names of the extended attributes set on a path.
+    ///
+    /// Names are returned in sorted order. Symlinks are not followed.
+    pub async fn listxattr(&self, path: &str) -> Result<Vec<String>> {
+        let path = self.normalize_path(path);
+        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT name FROM fs_xattr WHERE ino = ? ORDER BY name")
+            .await?;
+        let mut rows = stmt.query((ino,)).await?;
+
+        let mut names = Vec::new();
+        while let Some(row) = rows.next().await? {
+            if let Ok(Value::Text(name)) = row.get_value(0) {
+                names.push(name);
+            }
+        }
+
+        Ok(names)
+    }
+
+    /// Remove an extended attribute.
+    ///
+    /// Returns `Ok(false)` if the attribute was not set. Symlinks are not
+    /// followed.
+    pub async fn removexattr(&self, path: &str, name: &str) -> Result<bool> {
+        let path = self.normalize_path(path);
+        let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT 1 FROM fs_xattr WHERE ino = ? AND name = ?")
+            .await?;
+        let mut rows = stmt.query((ino, name)).await?;
+        if rows.next().await?.is_none() {
+            return Ok(false);
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare_cached("DELETE FROM fs_xattr WHERE ino = ? AND name = ?")
+            .await?;
+        stmt.execute((ino, name)).await?;
+
+        Ok(true)
+    }
+
+    /// Record a whiteout at `path`, for an overlay whose writable layer this
+    /// filesystem is.
+    ///
+    /// A whiteout hides the lower layer's entry at `path` and below it. It is
+    /// kept in `fs_whiteout`, outside the directory tree, as `OverlayFS` does.
+    pub async fn add_whiteout(&self, path: &str) -> Result<()> {
+        let path = self.normalize_path(path);
+        let parent = match path.rfind('/') {
+            Some(0) | None => "/",
+            Some(idx) => &path[..idx],
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        super::OverlayFS::init_whiteout_schema(&self.conn).await?;
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "INSERT INTO fs_whiteout (path, parent_path, created_at) VALUES (?, ?, ?)
+                ON CONFLICT(path) DO UPDATE SET created_at = excluded.created_at",
+            )
+            .await?;
+        stmt.execute((path.as_str(), parent, now)).await?;
+
+        Ok(())
+    }
+
+    /// Check whether `path` or one of its parents has a whiteout.
+    pub async fn has_whiteout(&self, path: &str) -> Result<bool> {
+        let path = self.normalize_path(path);
+
+        let mut prefix = String::new();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            prefix.push('/');
+            prefix.push_str(component);
+            // Databases without whiteouts have no fs_whiteout table
+            let Ok(mut stmt) = self
+                .conn
+                .prepare_cached("SELECT 1 FROM fs_whiteout WHERE path = ?")
+                .await
+            else {
+                return Ok(false);
+            };
+            let mut rows = stmt.query((prefix.as_str(),)).await?;
+            if rows.next().await?.is_some() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Get the names of the entries of directory `path` that have a whiteout.
+    pub async fn child_whiteouts(&self, path: &str) -> Result<HashSet<String>> {
+        let path = self.normalize_path(path);
+
+        // Databases without whiteouts have no fs_whiteout table
+        let Ok(mut stmt) = self
+            .conn
+            .prepare_cached("SELECT path FROM fs_whiteout WHERE parent_path = ?")
+            .await
+        else {
+            return Ok(HashSet::new());
+        };
+        let mut rows = stmt.query((path.as_str(),)).await?;
+
+        let mut names = HashSet::new();
+        while let Some(row) = rows.next().await? {
+            if let Ok(Value::Text(child)) = row.get_value(0) {
+                if let Some(name) = child.rsplit('/').next() {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
     /// Rename/move a file or directory.
     ///
     /// This operation is atomic - either all changes succeed or none do.
@@ -2198,24 +3673,10 @@ impl AgentFS {
                     .await?;
                 stmt.execute((dst_ino,)).await?;
 
-                // Clean up destination inode if no more links
+                // Clean up destination inode if no more links, unless it is still open
                 let link_count = self.get_link_count(dst_ino).await?;
-                if link_count == 0 {
-                    let mut stmt = self
-                        .conn
-                        .prepare_cached("DELETE FROM fs_data WHERE ino = ?")
-                        .await?;
-                    stmt.execute((dst_ino,)).await?;
-                    let mut stmt = self
-                        .conn
-                        .prepare_cached("DELETE FROM fs_symlink WHERE ino = ?")
-                        .await?;
-                    stmt.execute((dst_ino,)).await?;
-                    let mut stmt = self
-                        .conn
-                        .prepare_cached("DELETE FROM fs_inode WHERE ino = ?")
-                        .await?;
-                    stmt.execute((dst_ino,)).await?;
+                if link_count == 0 && !self.open_inodes.defer_free(dst_ino) {
+                    free_inode(&self.conn, &self.chunks, dst_ino).await?;
                 }
             }
 
@@ -2234,37 +3695,124 @@ impl AgentFS {
             ))
             .await?;
 
-            // Update ctime of the inode
+            // Update ctime of the inode
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64;
+
+            let mut stmt = self
+                .conn
+                .prepare_cached("UPDATE fs_inode SET ctime = ? WHERE ino = ?")
+                .await?;
+            stmt.execute((now, src_ino)).await?;
+
+            Ok(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                self.conn
+                    .prepare_cached("COMMIT")
+                    .await?
+                    .execute(())
+                    .await?;
+
+                // Invalidate cache for source and destination
+                self.dentry_cache.remove(src_parent_ino, &src_name);
+                self.dentry_cache.remove(dst_parent_ino, &dst_name);
+
+                // Add new entry to cache (source inode is now at destination)
+                self.dentry_cache.insert(dst_parent_ino, &dst_name, src_ino);
+
+                Ok(())
+            }
+            Err(e) => {
+                let _ = self
+                    .conn
+                    .prepare_cached("ROLLBACK")
+                    .await?
+                    .execute(())
+                    .await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Atomically exchange the entries at two paths
+    ///
+    /// Like `renameat2` with `RENAME_EXCHANGE`, both paths must exist and may
+    /// be of different types, but neither may be an ancestor of the other. The
+    /// two directory entries swap inodes in a single transaction.
+    pub async fn exchange(&self, a: &str, b: &str) -> Result<()> {
+        let a_path = self.normalize_path(a);
+        let b_path = self.normalize_path(b);
+        if a_path == "/" || b_path == "/" {
+            return Err(FsError::RootOperation.into());
+        }
+        if b_path.starts_with(&format!("{}/", a_path))
+            || a_path.starts_with(&format!("{}/", b_path))
+        {
+            return Err(FsError::InvalidRename.into());
+        }
+
+        let (a_parent_ino, a_name) = self.parent_and_name(&a_path).await?;
+        let (b_parent_ino, b_name) = self.parent_and_name(&b_path).await?;
+
+        self.conn
+            .prepare_cached("BEGIN IMMEDIATE")
+            .await?
+            .execute(())
+            .await?;
+
+        let result: Result<(i64, i64)> = async {
+            // Look up both entries inside the transaction for atomicity
+            let a_ino = self.resolve_path(&a_path).await?.ok_or(FsError::NotFound)?;
+            let b_ino = self.resolve_path(&b_path).await?.ok_or(FsError::NotFound)?;
+            if a_ino == b_ino {
+                return Ok((a_ino, b_ino));
+            }
+
+            for (ino, parent_ino, name) in [
+                (b_ino, a_parent_ino, &a_name),
+                (a_ino, b_parent_ino, &b_name),
+            ] {
+                let mut stmt = self
+                    .conn
+                    .prepare_cached(
+                        "UPDATE fs_dentry SET ino = ? WHERE parent_ino = ? AND name = ?",
+                    )
+                    .await?;
+                stmt.execute((ino, parent_ino, name.as_str())).await?;
+            }
+
             let now = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs() as i64;
-
             let mut stmt = self
                 .conn
-                .prepare_cached("UPDATE fs_inode SET ctime = ? WHERE ino = ?")
+                .prepare_cached("UPDATE fs_inode SET ctime = ? WHERE ino IN (?, ?)")
                 .await?;
-            stmt.execute((now, src_ino)).await?;
+            stmt.execute((now, a_ino, b_ino)).await?;
 
-            Ok(())
+            Ok((a_ino, b_ino))
         }
         .await;
 
         match result {
-            Ok(()) => {
+            Ok((a_ino, b_ino)) => {
                 self.conn
                     .prepare_cached("COMMIT")
                     .await?
                     .execute(())
                     .await?;
 
-                // Invalidate cache for source and destination
-                self.dentry_cache.remove(src_parent_ino, &src_name);
-                self.dentry_cache.remove(dst_parent_ino, &dst_name);
-
-                // Add new entry to cache (source inode is now at destination)
-                self.dentry_cache.insert(dst_parent_ino, &dst_name, src_ino);
-
+                self.dentry_cache.remove(a_parent_ino, &a_name);
+                self.dentry_cache.remove(b_parent_ino, &b_name);
+                self.dentry_cache.insert(a_parent_ino, &a_name, b_ino);
+                self.dentry_cache.insert(b_parent_ino, &b_name, a_ino);
                 Ok(())
             }
             Err(e) => {
@@ -2279,43 +3827,24 @@ impl AgentFS {
         }
     }
 
+    /// The inode of the parent directory of a normalized path, and the path's name in it
+    async fn parent_and_name(&self, path: &str) -> Result<(i64, String)> {
+        let components = self.split_path(path);
+        let name = components.last().ok_or(FsError::InvalidPath)?.clone();
+        let parent_path = format!("/{}", components[..components.len() - 1].join("/"));
+        let parent_ino = self
+            .resolve_path(&parent_path)
+            .await?
+            .ok_or(FsError::NotFound)?;
+        Ok((parent_ino, name))
+    }
+
     /// Get filesystem statistics
     ///
-    /// Returns the total number of inodes and bytes used by file contents.
+    /// Returns the total number of inodes and bytes used by file contents,
+    /// along with the quota.
     pub async fn statfs(&self) -> Result<FilesystemStats> {
-        // Count total inodes
-        let mut stmt = self
-            .conn
-            .prepare_cached("SELECT COUNT(*) FROM fs_inode")
-            .await?;
-        let mut rows = stmt.query(()).await?;
-
-        let inodes = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
-
-        // Sum total bytes used (from file sizes in inodes)
-        let mut stmt = self
-            .conn
-            .prepare_cached("SELECT COALESCE(SUM(size), 0) FROM fs_inode")
-            .await?;
-        let mut rows = stmt.query(()).await?;
-
-        let bytes_used = if let Some(row) = rows.next().await? {
-            row.get_value(0)
-                .ok()
-                .and_then(|v| v.as_integer().copied())
-                .unwrap_or(0) as u64
-        } else {
-            0
-        };
-
-        Ok(FilesystemStats { inodes, bytes_used })
+        filesystem_stats(&self.conn, self.quota).await
     }
 
     /// Synchronize file data to persistent storage
@@ -2345,6 +3874,26 @@ impl AgentFS {
         Ok(())
     }
 
+    /// Create a handle of the file with inode `ino`
+    fn file_handle(&self, ino: i64) -> AgentFSFile {
+        self.open_inodes.open(ino);
+        // Other processes may have changed the file since it was cached
+        self.blocks.invalidate(ino);
+        AgentFSFile {
+            conn: self.conn.clone(),
+            chunks: self.chunks.clone(),
+            ino,
+            chunk_size: self.chunk_size,
+            generation: self.generation.clone(),
+            opened_generation: self.generation.load(Ordering::Acquire),
+            open_inodes: self.open_inodes.clone(),
+            readers: self.readers.clone(),
+            blocks: self.blocks.clone(),
+            next_offset: AtomicU64::new(0),
+            quota: self.quota,
+        }
+    }
+
     /// Open a file and return a file handle.
     ///
     /// The returned handle can be used for efficient read/write/fsync operations
@@ -2353,11 +3902,7 @@ impl AgentFS {
         let path = self.normalize_path(path);
         let ino = self.resolve_path(&path).await?.ok_or(FsError::NotFound)?;
 
-        Ok(Arc::new(AgentFSFile {
-            conn: self.conn.clone(),
-            ino,
-            chunk_size: self.chunk_size,
-        }))
+        Ok(Arc::new(self.file_handle(ino)))
     }
 
     /// Get the number of chunks for a given inode (for testing)
@@ -2383,15 +3928,15 @@ impl AgentFS {
 #[async_trait]
 impl FileSystem for AgentFS {
     async fn stat(&self, path: &str) -> Result<Option<Stats>> {
-        AgentFS::stat(self, path).await
+        AgentFS::stat(&self.for_reads(), path).await
     }
 
     async fn lstat(&self, path: &str) -> Result<Option<Stats>> {
-        AgentFS::lstat(self, path).await
+        AgentFS::lstat(&self.for_reads(), path).await
     }
 
     async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
-        AgentFS::read_file(self, path).await
+        AgentFS::read_file(&self.for_reads(), path).await
     }
 
     async fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
@@ -2399,11 +3944,11 @@ impl FileSystem for AgentFS {
     }
 
     async fn readdir(&self, path: &str) -> Result<Option<Vec<String>>> {
-        AgentFS::readdir(self, path).await
+        AgentFS::readdir(&self.for_reads(), path).await
     }
 
     async fn readdir_plus(&self, path: &str) -> Result<Option<Vec<DirEntry>>> {
-        AgentFS::readdir_plus(self, path).await
+        AgentFS::readdir_plus(&self.for_reads(), path).await
     }
 
     async fn mkdir(&self, path: &str) -> Result<()> {
@@ -2418,10 +3963,38 @@ impl FileSystem for AgentFS {
         AgentFS::chmod(self, path, mode).await
     }
 
+    async fn chown(&self, path: &str, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        AgentFS::chown(self, path, uid, gid).await
+    }
+
+    async fn utimes(&self, path: &str, atime: Option<i64>, mtime: Option<i64>) -> Result<()> {
+        AgentFS::utimes(self, path, atime, mtime).await
+    }
+
+    async fn getxattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>> {
+        AgentFS::getxattr(&self.for_reads(), path, name).await
+    }
+
+    async fn setxattr(&self, path: &str, name: &str, value: &[u8]) -> Result<()> {
+        AgentFS::setxattr(self, path, name, value).await
+    }
+
+    async fn listxattr(&self, path: &str) -> Result<Vec<String>> {
+        AgentFS::listxattr(&self.for_reads(), path).await
+    }
+
+    async fn removexattr(&self, path: &str, name: &str) -> Result<bool> {
+        AgentFS::removexattr(self, path, name).await
+    }
+
     async fn rename(&self, from: &str, to: &str) -> Result<()> {
         AgentFS::rename(self, from, to).await
     }
 
+    async fn exchange(&self, a: &str, b: &str) -> Result<()> {
+        AgentFS::exchange(self, a, b).await
+    }
+
     async fn symlink(&self, target: &str, linkpath: &str) -> Result<()> {
         AgentFS::symlink(self, target, linkpath).await
     }
@@ -2431,11 +4004,11 @@ impl FileSystem for AgentFS {
     }
 
     async fn readlink(&self, path: &str) -> Result<Option<String>> {
-        AgentFS::readlink(self, path).await
+        AgentFS::readlink(&self.for_reads(), path).await
     }
 
     async fn statfs(&self) -> Result<FilesystemStats> {
-        AgentFS::statfs(self).await
+        AgentFS::statfs(&self.for_reads()).await
     }
 
     async fn open(&self, path: &str) -> Result<BoxedFile> {
@@ -2445,6 +4018,26 @@ impl FileSystem for AgentFS {
     async fn create_file(&self, path: &str, mode: u32) -> Result<(Stats, BoxedFile)> {
         AgentFS::create_file(self, path, mode).await
     }
+
+    async fn create_tmpfile(&self, dir: &str, mode: u32) -> Result<(Stats, BoxedFile)> {
+        AgentFS::create_tmpfile(self, dir, mode).await
+    }
+
+    async fn link_inode(&self, ino: i64, newpath: &str) -> Result<()> {
+        AgentFS::link_inode(self, ino, newpath).await
+    }
+
+    async fn add_whiteout(&self, path: &str) -> Result<()> {
+        AgentFS::add_whiteout(self, path).await
+    }
+
+    async fn has_whiteout(&self, path: &str) -> Result<bool> {
+        AgentFS::has_whiteout(self, path).await
+    }
+
+    async fn child_whiteouts(&self, path: &str) -> Result<HashSet<String>> {
+        AgentFS::child_whiteouts(self, path).await
+    }
 }
 
 #[cfg(test)]
@@ -2856,64 +4449,492 @@ mod tests {
         // Delete the file
         fs.remove("/deleteme.txt").await?;
 
-        // Verify all chunks are gone
-        let mut rows = fs
-            .conn
-            .query("SELECT COUNT(*) FROM fs_data WHERE ino = ?", (ino,))
-            .await?;
+        // Verify all chunks are gone
+        let mut rows = fs
+            .conn
+            .query("SELECT COUNT(*) FROM fs_data WHERE ino = ?", (ino,))
+            .await?;
+
+        let count = rows
+            .next()
+            .await?
+            .and_then(|r| r.get_value(0).ok().and_then(|v| v.as_integer().copied()))
+            .unwrap_or(-1);
+
+        assert_eq!(count, 0, "All chunks should be deleted");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_link_count() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.write_file("/original.txt", b"data").await?;
+
+        fs.link("/original.txt", "/link1.txt").await?;
+        fs.link("/original.txt", "/link2.txt").await?;
+        assert_eq!(fs.stat("/original.txt").await?.unwrap().nlink, 3);
+        assert_eq!(fs.lstat("/link2.txt").await?.unwrap().nlink, 3);
+
+        fs.remove("/original.txt").await?;
+        fs.remove("/link1.txt").await?;
+        let stats = fs.stat("/link2.txt").await?.unwrap();
+        assert_eq!(stats.nlink, 1);
+        assert_eq!(fs.read_file("/link2.txt").await?.unwrap(), b"data");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unlink_open_file_defers_free() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.write_file("/open.txt", b"still here").await?;
+        let ino = fs.resolve_path("/open.txt").await?.unwrap();
+
+        let file = fs.open("/open.txt").await?;
+        fs.remove("/open.txt").await?;
+        assert!(fs.stat("/open.txt").await?.is_none());
+        assert_eq!(file.pread(0, 10).await?, b"still here");
+
+        // The content goes with the last handle
+        drop(file);
+        for _ in 0..100 {
+            if fs.get_chunk_count(ino).await? == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(fs.get_chunk_count(ino).await?, 0);
+        let mut rows = fs
+            .conn
+            .query("SELECT COUNT(*) FROM fs_inode WHERE ino = ?", (ino,))
+            .await?;
+        let count = rows
+            .next()
+            .await?
+            .and_then(|r| r.get_value(0).ok().and_then(|v| v.as_integer().copied()))
+            .unwrap_or(-1);
+        assert_eq!(count, 0, "Inode should be freed");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tmpfile() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.mkdir("/dir").await?;
+
+        // An unnamed file is freed with its last handle
+        let (stats, file) = fs.create_tmpfile("/dir", 0o600).await?;
+        assert_eq!(stats.nlink, 0);
+        assert_eq!(stats.mode, S_IFREG | 0o600);
+        file.pwrite(0, b"scratch").await?;
+        assert_eq!(fs.readdir("/dir").await?.unwrap(), Vec::<String>::new());
+        drop(file);
+        for _ in 0..100 {
+            if fs.get_chunk_count(stats.ino).await? == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(fs.get_chunk_count(stats.ino).await?, 0);
+
+        // Linking one keeps it, with the data written before and after
+        let (stats, file) = fs.create_tmpfile("/dir", 0o644).await?;
+        file.pwrite(0, b"hello").await?;
+        fs.link_inode(stats.ino, "/dir/named.txt").await?;
+        file.pwrite(5, b", world").await?;
+        drop(file);
+        let named = fs.stat("/dir/named.txt").await?.unwrap();
+        assert_eq!(named.ino, stats.ino);
+        assert_eq!(named.nlink, 1);
+        assert_eq!(
+            fs.read_file("/dir/named.txt").await?.unwrap(),
+            b"hello, world"
+        );
+
+        let err = fs.create_tmpfile("/dir/named.txt", 0o644).await.err();
+        assert!(matches!(err, Some(Error::Fs(FsError::NotADirectory))));
+        let err = fs.create_tmpfile("/missing", 0o644).await.err();
+        assert!(matches!(err, Some(Error::Fs(FsError::NotFound))));
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_read_connections() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        assert_eq!(fs.readers.connections.len(), DEFAULT_READ_CONNECTIONS);
+        let fs: Arc<dyn FileSystem> = Arc::new(fs);
+
+        // Reads on the read connections see each change once it is made
+        fs.write_file("/a.txt", b"one").await?;
+        assert_eq!(fs.read_file("/a.txt").await?.unwrap(), b"one");
+        fs.write_file("/a.txt", b"two!").await?;
+        assert_eq!(fs.stat("/a.txt").await?.unwrap().size, 4);
+        fs.rename("/a.txt", "/b.txt").await?;
+        assert!(fs.lstat("/a.txt").await?.is_none());
+        assert_eq!(fs.readdir("/").await?.unwrap(), vec!["b.txt"]);
+
+        // Reads from many tasks run alongside each other and alongside writes
+        for i in 0..8u8 {
+            fs.write_file(&format!("/file{}.txt", i), &[i; 16]).await?;
+        }
+        let writer = {
+            let fs = fs.clone();
+            tokio::spawn(async move {
+                for round in 0..20u8 {
+                    fs.write_file("/b.txt", &[round; 64]).await?;
+                }
+                Ok::<_, Error>(())
+            })
+        };
+        let readers: Vec<_> = (0..8u8)
+            .map(|i| {
+                let fs = fs.clone();
+                tokio::spawn(async move {
+                    let path = format!("/file{}.txt", i);
+                    for _ in 0..20 {
+                        assert_eq!(fs.read_file(&path).await?.unwrap(), [i; 16]);
+                        assert!(fs.stat("/b.txt").await?.is_some());
+                    }
+                    Ok::<_, Error>(())
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.await.unwrap()?;
+        }
+        writer.await.unwrap()?;
+        assert_eq!(fs.read_file("/b.txt").await?.unwrap(), [19; 64]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_block_cache() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let chunk_size = fs.chunk_size();
+        let data: Vec<u8> = (0..chunk_size * 8).map(|i| (i % 251) as u8).collect();
+        fs.write_file("/big.bin", &data).await?;
+        let ino = fs.resolve_path("/big.bin").await?.unwrap();
+
+        // A sequential read prefetches the rest of the file
+        let reader = fs.open("/big.bin").await?;
+        assert_eq!(reader.pread(0, 100).await?, &data[..100]);
+        for index in 0..8 {
+            assert!(fs.blocks.get(ino, index).is_some());
+        }
+        assert_eq!(
+            reader.pread(100, chunk_size as u64 * 2).await?,
+            &data[100..100 + chunk_size * 2]
+        );
+
+        // Writes through other handles invalidate the cached chunks
+        let writer = fs.open("/big.bin").await?;
+        reader.pread(0, data.len() as u64).await?;
+        writer.pwrite(chunk_size as u64, b"changed").await?;
+        let read = reader.pread(chunk_size as u64, 7).await?;
+        assert_eq!(read, b"changed");
+        writer.truncate(10).await?;
+        let mut truncated = data[..10].to_vec();
+        truncated.resize(16, 0);
+        assert_eq!(reader.pread(0, 16).await?, truncated);
+        fs.write_file("/big.bin", b"rewritten").await?;
+        assert_eq!(reader.pread(0, 9).await?, b"rewritten");
+
+        // Without the cache, reads still see every change
+        let fs = fs.with_block_cache_size(0);
+        let file = fs.open("/big.bin").await?;
+        fs.write_file("/big.bin", b"uncached").await?;
+        assert_eq!(file.pread(0, 8).await?, b"uncached");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_quota_enforced() -> Result<()> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("test.db");
+        let db = Builder::new_local(db_path.to_str().unwrap())
+            .build()
+            .await?;
+        let conn = db.connect()?;
+        let quota = Quota {
+            bytes: Some(1000),
+            inodes: Some(3),
+        };
+        AgentFS::set_quota(&conn, quota).await?;
+        let fs = AgentFS::from_connection(Arc::new(conn)).await?;
+        assert_eq!(fs.quota(), quota);
+
+        // Writing up to the quota succeeds, going past it does not
+        fs.write_file("/full.bin", &[1u8; 1000]).await?;
+        let err = fs.pwrite("/full.bin", 1000, b"x").await.unwrap_err();
+        assert!(matches!(err, Error::Fs(FsError::NoSpace)));
+        fs.truncate("/full.bin", 600).await?;
+        fs.write_file("/more.bin", &[2u8; 400]).await?;
+        let err = fs.write_file("/more.bin", &[2u8; 401]).await.unwrap_err();
+        assert!(matches!(err, Error::Fs(FsError::NoSpace)));
+
+        // The root and the two files use up the inodes
+        let err = fs.mkdir("/dir").await.unwrap_err();
+        assert!(matches!(err, Error::Fs(FsError::NoSpace)));
+
+        let stats = fs.statfs().await?;
+        assert_eq!(stats.quota, quota);
+        assert_eq!(stats.bytes_used, 1000);
+        assert_eq!(stats.inodes, 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multiple_files_different_sizes() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        let chunk_size = fs.chunk_size();
+
+        // Create files of various sizes
+        let files = vec![
+            ("/tiny.txt", 10),
+            ("/small.txt", chunk_size / 2),
+            ("/exact.txt", chunk_size),
+            ("/medium.txt", chunk_size * 2 + 100),
+            ("/large.txt", chunk_size * 5),
+        ];
+
+        for (path, size) in &files {
+            let data: Vec<u8> = (0..*size).map(|i| (i % 256) as u8).collect();
+            fs.write_file(path, &data).await?;
+        }
+
+        // Verify each file has correct data and chunk count
+        for (path, size) in &files {
+            let read_data = fs.read_file(path).await?.unwrap();
+            assert_eq!(read_data.len(), *size, "Size mismatch for {}", path);
+
+            let expected_data: Vec<u8> = (0..*size).map(|i| (i % 256) as u8).collect();
+            assert_eq!(read_data, expected_data, "Data mismatch for {}", path);
+
+            let expected_chunks = size.div_ceil(chunk_size);
+            let ino = fs.resolve_path(path).await?.unwrap();
+            let actual_chunks = fs.get_chunk_count(ino).await? as usize;
+            assert_eq!(
+                actual_chunks, expected_chunks,
+                "Chunk count mismatch for {}",
+                path
+            );
+        }
+
+        Ok(())
+    }
+
+    // ==================== Deduplication Tests ====================
+
+    const DEDUP_PAYLOAD_SIZE: usize = 1024 * 1024;
+
+    async fn create_dedup_test_fs() -> Result<(AgentFS, tempfile::TempDir)> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("test.db");
+        let db = Builder::new_local(db_path.to_str().unwrap())
+            .build()
+            .await?;
+        let conn = db.connect()?;
+        AgentFS::enable_dedup(&conn).await?;
+        let fs = AgentFS::from_connection(Arc::new(conn)).await?;
+        Ok((fs, dir))
+    }
+
+    /// A pseudo-random payload, which neither dedups against itself nor
+    /// compresses
+    fn dedup_payload() -> Vec<u8> {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        (0..DEDUP_PAYLOAD_SIZE)
+            .map(|_| {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    async fn database_size(fs: &AgentFS) -> Result<i64> {
+        let mut size = 1;
+        for pragma in ["PRAGMA page_count", "PRAGMA page_size"] {
+            let mut rows = fs.conn.query(pragma, ()).await?;
+            size *= rows
+                .next()
+                .await?
+                .and_then(|r| r.get_value(0).ok().and_then(|v| v.as_integer().copied()))
+                .unwrap_or(0);
+        }
+        Ok(size)
+    }
+
+    async fn content_count(fs: &AgentFS) -> Result<i64> {
+        let mut rows = fs.conn.query("SELECT COUNT(*) FROM fs_content", ()).await?;
+        Ok(rows
+            .next()
+            .await?
+            .and_then(|r| r.get_value(0).ok().and_then(|v| v.as_integer().copied()))
+            .unwrap_or(-1))
+    }
+
+    #[tokio::test]
+    async fn test_dedup_stores_identical_files_once() -> Result<()> {
+        let (fs, _dir) = create_dedup_test_fs().await?;
+        assert!(fs.is_dedup());
+
+        let payload = dedup_payload();
+        let initial_size = database_size(&fs).await?;
+        fs.write_file("/a.bin", &payload).await?;
+        fs.write_file("/b.bin", &payload).await?;
+        let growth = database_size(&fs).await? - initial_size;
+
+        let payload_size = DEDUP_PAYLOAD_SIZE as i64;
+        assert!(
+            growth >= payload_size && growth < payload_size * 3 / 2,
+            "database grew by {} bytes for two copies of {} bytes",
+            growth,
+            payload_size
+        );
+        assert_eq!(fs.read_file("/a.bin").await?.unwrap(), payload);
+        assert_eq!(fs.read_file("/b.bin").await?.unwrap(), payload);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dedup_unlink_frees_content_at_zero_refs() -> Result<()> {
+        let (fs, _dir) = create_dedup_test_fs().await?;
+
+        let payload = dedup_payload();
+        fs.write_file("/a.bin", &payload).await?;
+        fs.write_file("/b.bin", &payload).await?;
+        let chunks = DEDUP_PAYLOAD_SIZE.div_ceil(fs.chunk_size()) as i64;
+        assert_eq!(content_count(&fs).await?, chunks);
+
+        fs.remove("/a.bin").await?;
+        assert_eq!(content_count(&fs).await?, chunks);
+        assert_eq!(fs.read_file("/b.bin").await?.unwrap(), payload);
+
+        fs.remove("/b.bin").await?;
+        assert_eq!(content_count(&fs).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dedup_overwrite_releases_old_content() -> Result<()> {
+        let (fs, _dir) = create_dedup_test_fs().await?;
+        let chunk_size = fs.chunk_size();
 
-        let count = rows
-            .next()
-            .await?
-            .and_then(|r| r.get_value(0).ok().and_then(|v| v.as_integer().copied()))
-            .unwrap_or(-1);
+        fs.write_file("/a.bin", &vec![1u8; chunk_size]).await?;
+        fs.write_file("/b.bin", &vec![1u8; chunk_size]).await?;
+        assert_eq!(content_count(&fs).await?, 1);
 
-        assert_eq!(count, 0, "All chunks should be deleted");
+        // Diverging copies get their own content
+        fs.pwrite("/b.bin", 0, b"x").await?;
+        assert_eq!(content_count(&fs).await?, 2);
+        assert_eq!(
+            fs.read_file("/a.bin").await?.unwrap(),
+            vec![1u8; chunk_size]
+        );
+
+        fs.truncate("/a.bin", 0).await?;
+        assert_eq!(content_count(&fs).await?, 1);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_multiple_files_different_sizes() -> Result<()> {
+    async fn test_enable_dedup_requires_empty_filesystem() -> Result<()> {
         let (fs, _dir) = create_test_fs().await?;
+        fs.write_file("/a.txt", b"hello").await?;
 
-        let chunk_size = fs.chunk_size();
+        assert!(AgentFS::enable_dedup(&fs.conn).await.is_err());
 
-        // Create files of various sizes
-        let files = vec![
-            ("/tiny.txt", 10),
-            ("/small.txt", chunk_size / 2),
-            ("/exact.txt", chunk_size),
-            ("/medium.txt", chunk_size * 2 + 100),
-            ("/large.txt", chunk_size * 5),
-        ];
+        Ok(())
+    }
 
-        for (path, size) in &files {
-            let data: Vec<u8> = (0..*size).map(|i| (i % 256) as u8).collect();
-            fs.write_file(path, &data).await?;
-        }
+    // ==================== Compression Tests ====================
 
-        // Verify each file has correct data and chunk count
-        for (path, size) in &files {
-            let read_data = fs.read_file(path).await?.unwrap();
-            assert_eq!(read_data.len(), *size, "Size mismatch for {}", path);
+    async fn create_compressed_test_fs(dedup: bool) -> Result<(AgentFS, tempfile::TempDir)> {
+        let dir = tempdir()?;
+        let db_path = dir.path().join("test.db");
+        let db = Builder::new_local(db_path.to_str().unwrap())
+            .build()
+            .await?;
+        let conn = db.connect()?;
+        if dedup {
+            AgentFS::enable_dedup(&conn).await?;
+        }
+        AgentFS::enable_compression(&conn, Compression::Zstd(3)).await?;
+        let fs = AgentFS::from_connection(Arc::new(conn)).await?;
+        Ok((fs, dir))
+    }
 
-            let expected_data: Vec<u8> = (0..*size).map(|i| (i % 256) as u8).collect();
-            assert_eq!(read_data, expected_data, "Data mismatch for {}", path);
+    #[tokio::test]
+    async fn test_compression_round_trip() -> Result<()> {
+        for dedup in [false, true] {
+            let (fs, _dir) = create_compressed_test_fs(dedup).await?;
+            assert_eq!(fs.compression(), Compression::Zstd(3));
+            let chunk_size = fs.chunk_size();
+
+            let text = "the quick brown fox jumps over the lazy dog\n".repeat(1000);
+            fs.write_file("/text.txt", text.as_bytes()).await?;
+            fs.pwrite("/text.txt", 10, b"QUICK").await?;
+            fs.truncate("/text.txt", (chunk_size * 3 + 7) as u64)
+                .await?;
 
-            let expected_chunks = size.div_ceil(chunk_size);
-            let ino = fs.resolve_path(path).await?.unwrap();
-            let actual_chunks = fs.get_chunk_count(ino).await? as usize;
+            let mut expected = text.into_bytes();
+            expected[10..15].copy_from_slice(b"QUICK");
+            expected.truncate(chunk_size * 3 + 7);
+            assert_eq!(fs.read_file("/text.txt").await?.unwrap(), expected);
             assert_eq!(
-                actual_chunks, expected_chunks,
-                "Chunk count mismatch for {}",
-                path
+                fs.pread("/text.txt", chunk_size as u64 - 2, 4)
+                    .await?
+                    .unwrap(),
+                &expected[chunk_size - 2..chunk_size + 2]
             );
+
+            // stat reports the uncompressed size, while far less is stored
+            let stats = fs.stat("/text.txt").await?.unwrap();
+            assert_eq!(stats.size as usize, expected.len());
+            let usage = fs.data_usage().await?[&stats.ino];
+            assert_eq!(usage.chunks, 4);
+            assert!(usage.stored_bytes < chunk_size as u64);
         }
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_compression_stores_incompressible_chunks_raw() -> Result<()> {
+        let (fs, _dir) = create_compressed_test_fs(false).await?;
+        let chunk_size = fs.chunk_size();
+
+        let payload = dedup_payload()[..chunk_size].to_vec();
+        fs.write_file("/random.bin", &payload).await?;
+        assert_eq!(fs.read_file("/random.bin").await?.unwrap(), payload);
+
+        let mut rows = fs
+            .conn
+            .query("SELECT compression, length(data) FROM fs_data", ())
+            .await?;
+        let row = rows.next().await?.unwrap();
+        let get = |idx| {
+            row.get_value(idx)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+        };
+        assert_eq!(get(0), Some(CHUNK_RAW));
+        assert_eq!(get(1), Some(chunk_size as i64));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_pread_basic() -> Result<()> {
         let (fs, _dir) = create_test_fs().await?;
@@ -3192,6 +5213,40 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_file_truncate_extend_zero_fills() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        let data: Vec<u8> = (1..=50).collect();
+        fs.write_file("/test.txt", &data).await?;
+        let ino = fs.resolve_path("/test.txt").await?.unwrap();
+
+        // Extend through an open handle, past several chunks
+        let new_size = fs.chunk_size() as u64 * 4 + 10;
+        let file = fs.open("/test.txt").await?;
+        file.truncate(new_size).await?;
+
+        // Only the size changes, no chunks are written for the tail
+        assert_eq!(file.fstat().await?.size as u64, new_size);
+        assert_eq!(fs.get_chunk_count(ino).await?, 1);
+
+        // Reads zero-fill up to the new size
+        let result = fs.read_file("/test.txt").await?.unwrap();
+        assert_eq!(result.len() as u64, new_size);
+        assert_eq!(&result[..50], &data[..]);
+        assert!(result[50..].iter().all(|&b| b == 0));
+
+        let result = fs.pread("/test.txt", 40, new_size).await?.unwrap();
+        assert_eq!(result.len() as u64, new_size - 40);
+        assert_eq!(&result[..10], &data[40..]);
+        assert!(result[10..].iter().all(|&b| b == 0));
+
+        let result = file.pread(new_size - 20, 20).await?;
+        assert_eq!(result, vec![0u8; 20]);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_truncate_nonexistent_file() -> Result<()> {
         let (fs, _dir) = create_test_fs().await?;
@@ -3401,6 +5456,53 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_exchange_file_and_directory() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        // Create a file, a directory with a file inside, and an entry named
+        // like a temporary swap name
+        fs.write_file("/file.txt", b"data").await?;
+        fs.mkdir("/dir").await?;
+        fs.write_file("/dir/inner.txt", b"inner").await?;
+        fs.write_file("/dir.agentfs-exchange", b"bystander").await?;
+
+        // Swap the two entries
+        fs.exchange("/file.txt", "/dir").await?;
+
+        // Each name now refers to the other entry
+        assert!(fs.stat("/file.txt").await?.unwrap().is_directory());
+        let result = fs.read_file("/file.txt/inner.txt").await?.unwrap();
+        assert_eq!(result, b"inner");
+        let result = fs.read_file("/dir").await?.unwrap();
+        assert_eq!(result, b"data");
+
+        // Unrelated entries are untouched
+        let result = fs.read_file("/dir.agentfs-exchange").await?.unwrap();
+        assert_eq!(result, b"bystander");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exchange_missing_or_nested_fails() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        fs.mkdir("/parent").await?;
+        fs.mkdir("/parent/child").await?;
+
+        // Both paths must exist
+        let result = fs.exchange("/parent", "/missing").await;
+        assert!(result.is_err());
+
+        // Neither path may contain the other
+        let result = fs.exchange("/parent", "/parent/child").await;
+        assert!(result.is_err());
+        assert!(fs.stat("/parent/child").await?.unwrap().is_directory());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_rename_directory_to_file_fails() -> Result<()> {
         let (fs, _dir) = create_test_fs().await?;
@@ -3556,4 +5658,369 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_chown_partial_update() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        fs.write_file("/file.txt", b"content").await?;
+        assert!(!fs.stat("/file.txt").await?.unwrap().owned);
+        fs.chown("/file.txt", Some(1000), Some(100)).await?;
+        let stats = fs.stat("/file.txt").await?.unwrap();
+        assert_eq!((stats.uid, stats.gid), (1000, 100));
+        assert!(stats.owned);
+
+        // None leaves the field unchanged
+        fs.chown("/file.txt", None, Some(200)).await?;
+        let stats = fs.stat("/file.txt").await?.unwrap();
+        assert_eq!((stats.uid, stats.gid), (1000, 200));
+
+        let result = fs.chown("/nonexistent.txt", Some(1), None).await;
+        assert!(result.is_err(), "chown on nonexistent file should fail");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chown_symlink_changes_link() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        fs.write_file("/target.txt", b"content").await?;
+        fs.symlink("/target.txt", "/link.txt").await?;
+        fs.chown("/link.txt", Some(1000), Some(1000)).await?;
+
+        let link_stats = fs.lstat("/link.txt").await?.unwrap();
+        assert_eq!((link_stats.uid, link_stats.gid), (1000, 1000));
+        let target_stats = fs.stat("/target.txt").await?.unwrap();
+        assert_eq!((target_stats.uid, target_stats.gid), (0, 0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_utimes() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        fs.write_file("/file.txt", b"content").await?;
+        fs.utimes("/file.txt", Some(1000), Some(2000)).await?;
+        let stats = fs.stat("/file.txt").await?.unwrap();
+        assert_eq!((stats.atime, stats.mtime), (1000, 2000));
+
+        // None leaves the time unchanged
+        fs.utimes("/file.txt", None, Some(3000)).await?;
+        let stats = fs.stat("/file.txt").await?.unwrap();
+        assert_eq!((stats.atime, stats.mtime), (1000, 3000));
+
+        // Symlinks are not followed
+        fs.symlink("/file.txt", "/link.txt").await?;
+        fs.utimes("/link.txt", Some(5), Some(5)).await?;
+        assert_eq!(fs.lstat("/link.txt").await?.unwrap().mtime, 5);
+        assert_eq!(fs.stat("/file.txt").await?.unwrap().mtime, 3000);
+
+        let result = fs.utimes("/nonexistent.txt", None, None).await;
+        assert!(result.is_err(), "utimes on nonexistent file should fail");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xattr_roundtrip() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+
+        fs.write_file("/test.txt", b"content").await?;
+        fs.setxattr("/test.txt", "user.b", b"2").await?;
+        fs.setxattr("/test.txt", "user.a", b"1").await?;
+        fs.setxattr("/test.txt", "user.a", b"one").await?;
+
+        assert_eq!(
+            fs.getxattr("/test.txt", "user.a").await?,
+            Some(b"one".to_vec())
+        );
+        assert_eq!(fs.getxattr("/test.txt", "user.missing").await?, None);
+        assert_eq!(fs.listxattr("/test.txt").await?, vec!["user.a", "user.b"]);
+
+        assert!(fs.removexattr("/test.txt", "user.a").await?);
+        assert!(!fs.removexattr("/test.txt", "user.a").await?);
+        assert_eq!(fs.listxattr("/test.txt").await?, vec!["user.b"]);
+
+        // Attributes go away with the inode
+        fs.remove("/test.txt").await?;
+        fs.write_file("/test.txt", b"content").await?;
+        assert!(fs.listxattr("/test.txt").await?.is_empty());
+
+        Ok(())
+    }
+
+    // ==================== Snapshot Tests ====================
+
+    #[tokio::test]
+    async fn test_snapshot_restore_rolls_back() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.write_file("/a.txt", b"original").await?;
+        fs.mkdir("/dir").await?;
+
+        fs.create_snapshot("before").await?;
+        fs.write_file("/a.txt", b"modified").await?;
+        fs.write_file("/b.txt", b"new").await?;
+        fs.remove("/dir").await?;
+
+        fs.restore_snapshot("before").await?;
+        assert_eq!(fs.read_file("/a.txt").await?.unwrap(), b"original");
+        assert!(fs.stat("/b.txt").await?.is_none());
+        assert!(fs.stat("/dir").await?.unwrap().is_directory());
+
+        // The snapshot survives its restore
+        let names: Vec<_> = fs
+            .list_snapshots()
+            .await?
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["before"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_names() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        assert!(fs.list_snapshots().await?.is_empty());
+        assert!(matches!(
+            fs.restore_snapshot("missing").await,
+            Err(Error::SnapshotNotFound(_))
+        ));
+
+        fs.create_snapshot("one").await?;
+        assert!(matches!(
+            fs.create_snapshot("one").await,
+            Err(Error::SnapshotExists(_))
+        ));
+
+        fs.delete_snapshot("one").await?;
+        assert!(fs.list_snapshots().await?.is_empty());
+        fs.create_snapshot("one").await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_shares_dedup_content() -> Result<()> {
+        let (fs, _dir) = create_dedup_test_fs().await?;
+        let payload = dedup_payload();
+        let chunks = DEDUP_PAYLOAD_SIZE.div_ceil(fs.chunk_size()) as i64;
+
+        fs.write_file("/a.bin", &payload).await?;
+        fs.create_snapshot("before").await?;
+        assert_eq!(content_count(&fs).await?, chunks);
+
+        // The snapshot keeps the content of removed files alive
+        fs.remove("/a.bin").await?;
+        assert_eq!(content_count(&fs).await?, chunks);
+
+        fs.restore_snapshot("before").await?;
+        assert_eq!(fs.read_file("/a.bin").await?.unwrap(), payload);
+
+        fs.delete_snapshot("before").await?;
+        assert_eq!(content_count(&fs).await?, chunks);
+        fs.remove("/a.bin").await?;
+        assert_eq!(content_count(&fs).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_invalidates_open_files() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.write_file("/a.txt", b"original").await?;
+        fs.create_snapshot("before").await?;
+
+        let file = fs.open("/a.txt").await?;
+        fs.restore_snapshot("before").await?;
+        assert!(matches!(
+            file.pread(0, 8).await,
+            Err(Error::Fs(FsError::StaleHandle))
+        ));
+
+        let file = fs.open("/a.txt").await?;
+        assert_eq!(file.pread(0, 8).await?, b"original");
+
+        Ok(())
+    }
+
+    // ==================== Manifest Tests ====================
+
+    #[tokio::test]
+    async fn test_manifest_hashes_content() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let (other, _other_dir) = create_dedup_test_fs().await?;
+        let payload = dedup_payload();
+        for fs in [&fs, &other] {
+            fs.mkdir("/dir").await?;
+            fs.write_file("/dir/a.bin", &payload).await?;
+            fs.symlink("a.bin", "/dir/link").await?;
+        }
+
+        let manifest = fs.manifest(None).await?;
+        let paths: Vec<_> = manifest.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["/dir", "/dir/a.bin", "/dir/link"]);
+        assert_eq!(manifest[0].hash, None);
+        assert_eq!(manifest[1].hash, Some(*blake3::hash(&payload).as_bytes()));
+        assert_eq!(manifest[1].size, DEDUP_PAYLOAD_SIZE as u64);
+
+        // Hashes don't depend on how content is stored
+        assert_eq!(other.manifest(None).await?, manifest);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_manifest_of_snapshot() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.write_file("/a.txt", b"original").await?;
+        fs.create_snapshot("before").await?;
+        fs.write_file("/a.txt", b"modified").await?;
+        fs.write_file("/b.txt", b"new").await?;
+
+        let before = fs.manifest(Some("before")).await?;
+        assert_eq!(before.len(), 1);
+        assert_eq!(before[0].hash, Some(*blake3::hash(b"original").as_bytes()));
+        assert_eq!(fs.manifest(None).await?.len(), 2);
+        assert!(matches!(
+            fs.manifest(Some("missing")).await,
+            Err(Error::SnapshotNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_manifest_hashes_sparse_files() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        let chunk_size = fs.chunk_size();
+        let mut expected = vec![0u8; chunk_size * 2 + 10];
+        expected[chunk_size * 2..].copy_from_slice(b"0123456789");
+
+        fs.write_file("/sparse.bin", b"").await?;
+        fs.pwrite("/sparse.bin", (chunk_size * 2) as u64, b"0123456789")
+            .await?;
+        fs.truncate("/sparse.bin", (chunk_size * 3) as u64).await?;
+        expected.resize(chunk_size * 3, 0);
+
+        let manifest = fs.manifest(None).await?;
+        assert_eq!(manifest[0].hash, Some(*blake3::hash(&expected).as_bytes()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repair_fixes_links() -> Result<()> {
+        let (fs, _dir) = create_test_fs().await?;
+        fs.write_file("/a.txt", b"a").await?;
+        fs.mkdir("/dir").await?;
+        fs.write_file("/dir/c.txt", b"c").await?;
+        assert!(fs.repair(false).await?.is_clean());
+
+        // Unlinking the directory orphans it and everything in it
+        let a_ino = fs.stat("/a.txt").await?.unwrap().ino;
+        for sql in [
+            "DELETE FROM fs_dentry WHERE name = 'dir'",
+            "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES ('ghost', 1, 9999)",
+        ] {
+            fs.conn.execute(sql, ()).await?;
+        }
+        fs.conn
+            .execute("UPDATE fs_inode SET nlink = 5 WHERE ino = ?", (a_ino,))
+            .await?;
+
+        let expected = RepairReport {
+            dangling_dentries: 2,
+            orphaned_inodes: 2,
+            link_counts: 1,
+            orphaned_rows: 1,
+            ..Default::default()
+        };
+        // A dry run leaves the database as it was
+        assert_eq!(fs.repair(false).await?, expected);
+        assert_eq!(fs.repair(false).await?, expected);
+
+        let report = fs.repair(true).await?;
+        assert!(report.reindexed);
+        assert_eq!(report.link_counts, 1);
+        assert!(fs.repair(false).await?.is_clean());
+        assert_eq!(fs.stat("/a.txt").await?.unwrap().nlink, 1);
+        assert_eq!(fs.read_file("/a.txt").await?.unwrap(), b"a");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repair_fixes_refcounts() -> Result<()> {
+        let (fs, _dir) = create_dedup_test_fs().await?;
+        let payload = dedup_payload();
+        let chunks = DEDUP_PAYLOAD_SIZE.div_ceil(fs.chunk_size()) as u64;
+        fs.write_file("/a.bin", &payload).await?;
+        fs.write_file("/b.bin", &payload).await?;
+        fs.create_snapshot("before").await?;
+
+        fs.conn
+            .execute("UPDATE fs_content SET refcount = 1", ())
+            .await?;
+        fs.conn
+            .execute(
+                "INSERT INTO fs_content (hash, data, refcount) VALUES (x'00', x'00', 1)",
+                (),
+            )
+            .await?;
+
+        let report = fs.repair(true).await?;
+        assert_eq!(report.refcounts, chunks);
+        assert_eq!(report.unreferenced_content, 1);
+        assert!(fs.repair(false).await?.is_clean());
+
+        // The content outlives both files, held by the snapshot
+        fs.remove("/a.bin").await?;
+        fs.remove("/b.bin").await?;
+        assert_eq!(content_count(&fs).await?, chunks as i64);
+        fs.restore_snapshot("before").await?;
+        assert_eq!(fs.read_file("/b.bin").await?.unwrap(), payload);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_reports_without_changes() -> Result<()> {
+        let (fs, _dir) = create_dedup_test_fs().await?;
+        let payload = dedup_payload();
+        fs.write_file("/a.bin", &payload).await?;
+        assert!(fs.verify().await?.is_clean());
+
+        fs.conn
+            .execute(
+                "INSERT INTO fs_dentry (name, parent_ino, ino) VALUES ('ghost', 1, 9999)",
+                (),
+            )
+            .await?;
+        fs.conn
+            .execute(
+                "DELETE FROM fs_content WHERE hash = (SELECT hash FROM fs_content LIMIT 1)",
+                (),
+            )
+            .await?;
+
+        let expected = RepairReport {
+            dangling_dentries: 1,
+            missing_content: 1,
+            ..Default::default()
+        };
+        assert_eq!(fs.verify().await?, expected);
+        assert_eq!(fs.verify().await?, expected);
+
+        // Repairs remove the dangling entry, but can't bring back the content
+        fs.repair(true).await?;
+        let report = fs.verify().await?;
+        assert_eq!(report.dangling_dentries, 0);
+        assert_eq!(report.missing_content, 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,232 @@
+//! Encryption of file content at rest.
+//!
+//! The key is derived from a passphrase with Argon2id and a random salt kept
+//! in `fs_config`. Each chunk is encrypted with XChaCha20-Poly1305 under a
+//! fresh random nonce, stored in front of the ciphertext, so that rewriting a
+//! chunk never reuses a nonce. The ciphertext is authenticated together with
+//! where the chunk is stored (see `ChunkPlace`), so that chunks can't be
+//! swapped around in the database without failing to decrypt. A known
+//! plaintext encrypted when the filesystem is created tells a wrong passphrase
+//! apart from corrupt data.
+
+use crate::error::{Error, Result};
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use zeroize::Zeroizing;
+
+/// Length of the key derivation salt in bytes
+const SALT_LEN: usize = 16;
+
+/// Length of the nonce in front of each encrypted chunk in bytes
+const NONCE_LEN: usize = 24;
+
+/// Plaintext of the token that verifies the passphrase
+const CHECK_PLAINTEXT: &[u8] = b"agentfs";
+
+/// Where an encrypted chunk is stored
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ChunkPlace<'a> {
+    /// Deduplicated content, by its hash
+    Content(&'a [u8]),
+    /// Chunk `chunk_index` of inode `ino`
+    Inode { ino: i64, chunk_index: i64 },
+}
+
+impl ChunkPlace<'_> {
+    /// The additional authenticated data of a chunk stored here
+    fn aad(&self) -> Vec<u8> {
+        match *self {
+            ChunkPlace::Content(hash) => {
+                let mut aad = b"content:".to_vec();
+                aad.extend_from_slice(hash);
+                aad
+            }
+            ChunkPlace::Inode { ino, chunk_index } => {
+                let mut aad = b"inode:".to_vec();
+                aad.extend_from_slice(&ino.to_le_bytes());
+                aad.extend_from_slice(&chunk_index.to_le_bytes());
+                aad
+            }
+        }
+    }
+}
+
+/// Cipher for the chunks of an encrypted filesystem
+///
+/// The keys are zeroed when the cipher is dropped.
+pub(crate) struct ChunkCipher {
+    cipher: XChaCha20Poly1305,
+    /// Key for hashing content, so deduplication hashes don't reveal it
+    hash_key: Zeroizing<[u8; 32]>,
+}
+
+impl ChunkCipher {
+    /// Derive the cipher from `passphrase` and `salt`
+    pub fn derive(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = Zeroizing::new([0u8; 64]);
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+            .map_err(|e| Error::Internal(format!("failed to derive key: {}", e)))?;
+
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&key[..32]));
+        let mut hash_key = Zeroizing::new([0u8; 32]);
+        hash_key.copy_from_slice(&key[32..]);
+        Ok(Self { cipher, hash_key })
+    }
+
+    /// Generate a random key derivation salt
+    pub fn generate_salt() -> Vec<u8> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    }
+
+    /// Encrypt chunk `plaintext` to store at `place`, returning the nonce
+    /// followed by the ciphertext
+    pub fn encrypt(&self, plaintext: &[u8], place: ChunkPlace) -> Result<Vec<u8>> {
+        self.seal(plaintext, &place.aad())
+    }
+
+    /// Decrypt the output of `encrypt()`, which must have been for `place`
+    pub fn decrypt(&self, stored: &[u8], place: ChunkPlace) -> Result<Vec<u8>> {
+        self.open(stored, &place.aad())
+    }
+
+    /// Encrypt `plaintext` with additional data `aad`
+    fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| Error::Internal("failed to encrypt chunk".to_string()))?;
+
+        let mut stored = nonce.to_vec();
+        stored.extend_from_slice(&ciphertext);
+        Ok(stored)
+    }
+
+    /// Decrypt the output of `seal()` with the same additional data `aad`
+    fn open(&self, stored: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let failed = || Error::Internal("encrypted chunk failed authentication".to_string());
+        if stored.len() < NONCE_LEN {
+            return Err(failed());
+        }
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| failed())
+    }
+
+    /// Hash chunk `data` to identify its content
+    pub fn content_hash(&self, data: &[u8]) -> blake3::Hash {
+        blake3::keyed_hash(&self.hash_key, data)
+    }
+
+    /// Create a token that `verify()` accepts for the same passphrase only
+    pub fn check_token(&self) -> Result<Vec<u8>> {
+        self.seal(CHECK_PLAINTEXT, &[])
+    }
+
+    /// Check that `token` was created with the same passphrase
+    pub fn verify(&self, token: &[u8]) -> bool {
+        self.open(token, &[])
+            .is_ok_and(|plaintext| plaintext == CHECK_PLAINTEXT)
+    }
+}
+
+/// Encode `bytes` as lowercase hex
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode hex produced by `to_hex()`
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLACE: ChunkPlace = ChunkPlace::Inode {
+        ino: 2,
+        chunk_index: 0,
+    };
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let salt = ChunkCipher::generate_salt();
+        let cipher = ChunkCipher::derive("secret", &salt).unwrap();
+
+        let stored = cipher.encrypt(b"hello", PLACE).unwrap();
+        assert_ne!(&stored[NONCE_LEN..], b"hello");
+        assert_eq!(cipher.decrypt(&stored, PLACE).unwrap(), b"hello");
+
+        // Every encryption uses a fresh nonce
+        assert_ne!(cipher.encrypt(b"hello", PLACE).unwrap(), stored);
+    }
+
+    #[test]
+    fn test_chunk_bound_to_place() {
+        let salt = ChunkCipher::generate_salt();
+        let cipher = ChunkCipher::derive("secret", &salt).unwrap();
+
+        let stored = cipher.encrypt(b"hello", PLACE).unwrap();
+        let other_chunk = ChunkPlace::Inode {
+            ino: 2,
+            chunk_index: 1,
+        };
+        let other_inode = ChunkPlace::Inode {
+            ino: 3,
+            chunk_index: 0,
+        };
+        assert!(cipher.decrypt(&stored, other_chunk).is_err());
+        assert!(cipher.decrypt(&stored, other_inode).is_err());
+
+        let hash = cipher.content_hash(b"hello");
+        let content = ChunkPlace::Content(hash.as_bytes());
+        let stored = cipher.encrypt(b"hello", content).unwrap();
+        assert_eq!(cipher.decrypt(&stored, content).unwrap(), b"hello");
+        let other_hash = cipher.content_hash(b"world");
+        let other_content = ChunkPlace::Content(other_hash.as_bytes());
+        assert!(cipher.decrypt(&stored, other_content).is_err());
+        assert!(cipher.decrypt(&stored, PLACE).is_err());
+    }
+
+    #[test]
+    fn test_wrong_passphrase_fails_verification() {
+        let salt = ChunkCipher::generate_salt();
+        let cipher = ChunkCipher::derive("secret", &salt).unwrap();
+        let token = cipher.check_token().unwrap();
+
+        assert!(ChunkCipher::derive("secret", &salt).unwrap().verify(&token));
+        let wrong = ChunkCipher::derive("wrong", &salt).unwrap();
+        assert!(!wrong.verify(&token));
+        let stored = cipher.encrypt(b"hello", PLACE).unwrap();
+        assert!(wrong.decrypt(&stored, PLACE).is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(to_hex(&[0x00, 0xab, 0x10]), "00ab10");
+        assert_eq!(from_hex("00ab10"), Some(vec![0x00, 0xab, 0x10]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
@@ -8,7 +8,7 @@ use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 #[cfg(unix)]
 use libc;
 
-use super::{BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, Stats};
+use super::{BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, Quota, Stats};
 use std::sync::Arc;
 
 /// A filesystem backed by a host directory (passthrough)
@@ -148,6 +148,7 @@ impl HostFS {
             atime: metadata.atime(),
             mtime: metadata.mtime(),
             ctime: metadata.ctime(),
+            owned: true,
         }
     }
 }
@@ -289,6 +290,39 @@ impl FileSystem for HostFS {
         Ok(())
     }
 
+    async fn chown(&self, path: &str, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        let full_path = self.resolve_path(path);
+        std::os::unix::fs::lchown(&full_path, uid, gid)?;
+        Ok(())
+    }
+
+    async fn utimes(&self, path: &str, atime: Option<i64>, mtime: Option<i64>) -> Result<()> {
+        let full_path = self.resolve_path(path);
+        set_times(&full_path, atime, mtime)?;
+        Ok(())
+    }
+
+    async fn getxattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>> {
+        let full_path = self.resolve_path(path);
+        Ok(xattr::get(&full_path, name)?)
+    }
+
+    async fn setxattr(&self, path: &str, name: &str, value: &[u8]) -> Result<()> {
+        let full_path = self.resolve_path(path);
+        xattr::set(&full_path, name, value)?;
+        Ok(())
+    }
+
+    async fn listxattr(&self, path: &str) -> Result<Vec<String>> {
+        let full_path = self.resolve_path(path);
+        Ok(xattr::list(&full_path)?)
+    }
+
+    async fn removexattr(&self, path: &str, name: &str) -> Result<bool> {
+        let full_path = self.resolve_path(path);
+        Ok(xattr::remove(&full_path, name)?)
+    }
+
     async fn rename(&self, from: &str, to: &str) -> Result<()> {
         let from_path = self.resolve_path(from);
         let to_path = self.resolve_path(to);
@@ -345,7 +379,11 @@ impl FileSystem for HostFS {
 
         let _ = count_recursive(&self.root, &mut inodes, &mut bytes_used);
 
-        Ok(FilesystemStats { inodes, bytes_used })
+        Ok(FilesystemStats {
+            inodes,
+            bytes_used,
+            quota: Quota::default(),
+        })
     }
 
     async fn open(&self, path: &str) -> Result<BoxedFile> {
@@ -370,6 +408,192 @@ impl FileSystem for HostFS {
     }
 }
 
+/// Set the access and modification times of a path without following symlinks
+///
+/// A `None` time is left unchanged.
+fn set_times(
+    path: &std::path::Path,
+    atime: Option<i64>,
+    mtime: Option<i64>,
+) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let timespec = |time: Option<i64>| libc::timespec {
+        tv_sec: time.unwrap_or(0) as libc::time_t,
+        tv_nsec: if time.is_some() { 0 } else { libc::UTIME_OMIT },
+    };
+    let times = [timespec(atime), timespec(mtime)];
+    let ret = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Extended attribute system calls that do not follow symlinks
+mod xattr {
+    use libc::{c_char, c_void};
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[cfg(target_os = "linux")]
+    mod sys {
+        use libc::{c_char, c_int, c_void};
+
+        pub const ENOATTR: c_int = libc::ENODATA;
+
+        pub unsafe fn get(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize {
+            libc::lgetxattr(path, name, value, size)
+        }
+
+        pub unsafe fn set(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+        ) -> c_int {
+            libc::lsetxattr(path, name, value, size, 0)
+        }
+
+        pub unsafe fn list(path: *const c_char, list: *mut c_char, size: usize) -> isize {
+            libc::llistxattr(path, list, size)
+        }
+
+        pub unsafe fn remove(path: *const c_char, name: *const c_char) -> c_int {
+            libc::lremovexattr(path, name)
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod sys {
+        use libc::{c_char, c_int, c_void, XATTR_NOFOLLOW};
+
+        pub const ENOATTR: c_int = libc::ENOATTR;
+
+        pub unsafe fn get(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize {
+            libc::getxattr(path, name, value, size, 0, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn set(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+        ) -> c_int {
+            libc::setxattr(path, name, value, size, 0, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn list(path: *const c_char, list: *mut c_char, size: usize) -> isize {
+            libc::listxattr(path, list, size, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn remove(path: *const c_char, name: *const c_char) -> c_int {
+            libc::removexattr(path, name, XATTR_NOFOLLOW)
+        }
+    }
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        Ok(CString::new(path.as_os_str().as_bytes())?)
+    }
+
+    /// Call `op` with a buffer until the attribute data fits
+    ///
+    /// `op` follows the size-query convention: a zero-length buffer returns the
+    /// needed length, and `ERANGE` means the data grew between the two calls.
+    fn read_sized(mut op: impl FnMut(*mut c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let len = op(std::ptr::null_mut(), 0);
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buf = vec![0u8; len as usize];
+            let n = op(buf.as_mut_ptr().cast(), buf.len());
+            if n >= 0 {
+                buf.truncate(n as usize);
+                return Ok(buf);
+            }
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ERANGE) {
+                return Err(err);
+            }
+        }
+    }
+
+    pub fn get(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let path = c_path(path)?;
+        let name = CString::new(name)?;
+        match read_sized(|value, size| unsafe {
+            sys::get(path.as_ptr(), name.as_ptr(), value, size)
+        }) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.raw_os_error() == Some(sys::ENOATTR) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = c_path(path)?;
+        let name = CString::new(name)?;
+        let ret = unsafe {
+            sys::set(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn list(path: &Path) -> io::Result<Vec<String>> {
+        let path = c_path(path)?;
+        let names = read_sized(|list, size| unsafe {
+            sys::list(path.as_ptr(), list.cast::<c_char>(), size)
+        })?;
+        Ok(names
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect())
+    }
+
+    pub fn remove(path: &Path, name: &str) -> io::Result<bool> {
+        let path = c_path(path)?;
+        let name = CString::new(name)?;
+        if unsafe { sys::remove(path.as_ptr(), name.as_ptr()) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(sys::ENOATTR) {
+                return Ok(false);
+            }
+            return Err(err);
+        }
+        Ok(true)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
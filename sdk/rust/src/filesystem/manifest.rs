@@ -0,0 +1,210 @@
+//! Listing of every path of a filesystem, or of one of its snapshots, with a
+//! hash of its content, for comparing filesystems without reading whole files
+//! into memory.
+
+use super::encryption::ChunkPlace;
+use super::snapshot::DataLayout;
+use super::{S_IFLNK, S_IFMT, S_IFREG};
+use crate::error::Result;
+use std::collections::VecDeque;
+use turso::{Connection, Value};
+
+const ROOT_INO: i64 = 1;
+
+/// A path of a filesystem, with its type, size and content hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Absolute path, e.g. `/dir/file.txt`
+    pub path: String,
+    pub mode: u32,
+    pub size: u64,
+    /// BLAKE3 hash of the file content or symlink target, `None` for directories
+    pub hash: Option<[u8; 32]>,
+}
+
+/// Tables of the live filesystem, or of snapshot `snapshot_id`
+pub(crate) struct Source {
+    pub snapshot_id: Option<i64>,
+    pub layout: DataLayout,
+    pub chunk_size: usize,
+}
+
+impl Source {
+    fn table(&self, table: &str) -> String {
+        match self.snapshot_id {
+            Some(_) => format!("fs_snapshot_{}", &table["fs_".len()..]),
+            None => table.to_string(),
+        }
+    }
+
+    /// Condition restricting `alias` to the rows of the source
+    fn filter(&self, alias: &str) -> String {
+        match self.snapshot_id {
+            Some(id) => format!(" AND {}.snapshot_id = {}", alias, id),
+            None => String::new(),
+        }
+    }
+
+    /// Query returning the chunk index, stored bytes and compression flag of
+    /// each chunk of an inode, in order, and with deduplication its hash
+    fn chunks_query(&self) -> String {
+        if self.layout.dedup {
+            format!(
+                "SELECT r.chunk_index, c.data, c.compression, r.hash FROM {} r
+                JOIN fs_content c ON c.hash = r.hash
+                WHERE r.ino = ?{} ORDER BY r.chunk_index",
+                self.table("fs_content_ref"),
+                self.filter("r")
+            )
+        } else {
+            // Only snapshots of uncompressed filesystems lack the column
+            let compression = if self.layout.compressed || self.snapshot_id.is_some() {
+                "d.compression"
+            } else {
+                "0"
+            };
+            format!(
+                "SELECT d.chunk_index, d.data, {} FROM {} d
+                WHERE d.ino = ?{} ORDER BY d.chunk_index",
+                compression,
+                self.table("fs_data"),
+                self.filter("d")
+            )
+        }
+    }
+}
+
+/// List every path of `source` in `conn`, parents before their children
+///
+/// `decode` restores chunk data from its stored bytes and compression flag,
+/// and where it is stored.
+pub(crate) async fn walk(
+    conn: &Connection,
+    source: &Source,
+    decode: impl Fn(Vec<u8>, i64, ChunkPlace) -> Result<Vec<u8>>,
+) -> Result<Vec<ManifestEntry>> {
+    let children_sql = format!(
+        "SELECT d.name, d.ino, i.mode, i.size FROM {} d
+        JOIN {} i ON i.ino = d.ino{}
+        WHERE d.parent_ino = ?{} ORDER BY d.name",
+        source.table("fs_dentry"),
+        source.table("fs_inode"),
+        source.filter("i"),
+        source.filter("d")
+    );
+    let chunks_sql = source.chunks_query();
+    let symlink_sql = format!(
+        "SELECT s.target FROM {} s WHERE s.ino = ?{}",
+        source.table("fs_symlink"),
+        source.filter("s")
+    );
+
+    let mut entries = Vec::new();
+    let mut queue = VecDeque::from([(ROOT_INO, String::new())]);
+    while let Some((parent_ino, prefix)) = queue.pop_front() {
+        let mut children = Vec::new();
+        let mut rows = conn.query(&children_sql, (parent_ino,)).await?;
+        while let Some(row) = rows.next().await? {
+            let name = match row.get_value(0) {
+                Ok(Value::Text(name)) => name,
+                _ => continue,
+            };
+            let int = |i| {
+                row.get_value(i)
+                    .ok()
+                    .and_then(|v| v.as_integer().copied())
+                    .unwrap_or(0)
+            };
+            children.push((name, int(1), int(2) as u32, int(3) as u64));
+        }
+        drop(rows);
+
+        for (name, ino, mode, size) in children {
+            let path = format!("{}/{}", prefix, name);
+            let hash = match mode & S_IFMT {
+                S_IFREG => Some(hash_file(conn, &chunks_sql, source, ino, size, &decode).await?),
+                S_IFLNK => {
+                    let mut rows = conn.query(&symlink_sql, (ino,)).await?;
+                    let target = match rows.next().await? {
+                        Some(row) => match row.get_value(0) {
+                            Ok(Value::Text(target)) => target,
+                            _ => String::new(),
+                        },
+                        None => String::new(),
+                    };
+                    Some(*blake3::hash(target.as_bytes()).as_bytes())
+                }
+                _ => {
+                    queue.push_back((ino, path.clone()));
+                    None
+                }
+            };
+            entries.push(ManifestEntry {
+                path,
+                mode,
+                size,
+                hash,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Hash the `size` bytes of content of inode `ino`, a chunk at a time
+///
+/// Missing chunks and the end of a file extended by truncation read as zeros.
+async fn hash_file(
+    conn: &Connection,
+    chunks_sql: &str,
+    source: &Source,
+    ino: i64,
+    size: u64,
+    decode: &impl Fn(Vec<u8>, i64, ChunkPlace) -> Result<Vec<u8>>,
+) -> Result<[u8; 32]> {
+    let zeros = vec![0u8; source.chunk_size];
+    let mut hasher = blake3::Hasher::new();
+    let mut hashed = 0u64;
+
+    let mut rows = conn.query(chunks_sql, (ino,)).await?;
+    while let Some(row) = rows.next().await? {
+        let chunk_index = row
+            .get_value(0)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .unwrap_or(0);
+        let Ok(Value::Blob(stored)) = row.get_value(1) else {
+            continue;
+        };
+        let flag = row
+            .get_value(2)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .unwrap_or(0);
+
+        let hash = match row.get_value(3) {
+            Ok(Value::Blob(hash)) if source.layout.dedup => Some(hash),
+            _ => None,
+        };
+        let place = match &hash {
+            Some(hash) => ChunkPlace::Content(hash),
+            None => ChunkPlace::Inode { ino, chunk_index },
+        };
+
+        let offset = chunk_index as u64 * source.chunk_size as u64;
+        while hashed < offset.min(size) {
+            let len = (offset.min(size) - hashed).min(zeros.len() as u64) as usize;
+            hasher.update(&zeros[..len]);
+            hashed += len as u64;
+        }
+        let data = decode(stored, flag, place)?;
+        let len = (data.len() as u64).min(size.saturating_sub(hashed)) as usize;
+        hasher.update(&data[..len]);
+        hashed += len as u64;
+    }
+    while hashed < size {
+        let len = (size - hashed).min(zeros.len() as u64) as usize;
+        hasher.update(&zeros[..len]);
+        hashed += len as u64;
+    }
+    Ok(*hasher.finalize().as_bytes())
+}
@@ -1,18 +1,28 @@
 pub mod agentfs;
+mod encryption;
 #[cfg(unix)]
 pub mod hostfs;
+mod manifest;
 pub mod overlayfs;
+mod repair;
+mod snapshot;
 
 use crate::error::Result;
 use async_trait::async_trait;
+use std::collections::HashSet;
 use std::sync::Arc;
 use thiserror::Error;
 
 // Re-export implementations
-pub use agentfs::AgentFS;
+pub use agentfs::{
+    AgentFS, Compression, DataUsage, DEFAULT_BLOCK_CACHE_SIZE, DEFAULT_READ_CONNECTIONS,
+};
 #[cfg(unix)]
 pub use hostfs::HostFS;
+pub use manifest::ManifestEntry;
 pub use overlayfs::OverlayFS;
+pub use repair::RepairReport;
+pub use snapshot::Snapshot;
 
 /// Filesystem-specific errors with errno semantics
 #[derive(Debug, Error)]
@@ -46,6 +56,15 @@ pub enum FsError {
 
     #[error("Cannot rename directory into its own subdirectory")]
     InvalidRename,
+
+    #[error("Stale file handle")]
+    StaleHandle,
+
+    #[error("No space left on device")]
+    NoSpace,
+
+    #[error("Operation not supported")]
+    NotSupported,
 }
 
 impl FsError {
@@ -62,6 +81,9 @@ impl FsError {
             FsError::RootOperation => libc::EPERM,
             FsError::SymlinkLoop => libc::ELOOP,
             FsError::InvalidRename => libc::EINVAL,
+            FsError::StaleHandle => libc::ESTALE,
+            FsError::NoSpace => libc::ENOSPC,
+            FsError::NotSupported => libc::EOPNOTSUPP,
         }
     }
 }
@@ -88,6 +110,27 @@ pub struct Stats {
     pub atime: i64,
     pub mtime: i64,
     pub ctime: i64,
+    /// Whether `uid` and `gid` were set with `chown()`, rather than being the
+    /// root ownership new inodes are created with
+    pub owned: bool,
+}
+
+/// Limits on how large a filesystem may grow
+///
+/// A `None` limit leaves that resource unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Maximum total bytes of file contents
+    pub bytes: Option<u64>,
+    /// Maximum number of inodes (files, directories, symlinks)
+    pub inodes: Option<u64>,
+}
+
+impl Quota {
+    /// Check whether neither resource is limited
+    pub fn is_unlimited(&self) -> bool {
+        self.bytes.is_none() && self.inodes.is_none()
+    }
 }
 
 /// Filesystem statistics for statfs
@@ -97,6 +140,19 @@ pub struct FilesystemStats {
     pub inodes: u64,
     /// Total bytes used by file contents
     pub bytes_used: u64,
+    /// Limits on the inodes and bytes used
+    pub quota: Quota,
+}
+
+impl FilesystemStats {
+    /// Check whether `bytes` more bytes and `inodes` more inodes fit in the quota
+    pub fn fits(&self, bytes: u64, inodes: u64) -> bool {
+        let fits = |used: u64, more: u64, limit: Option<u64>| {
+            more == 0 || limit.is_none_or(|limit| used.saturating_add(more) <= limit)
+        };
+        fits(self.bytes_used, bytes, self.quota.bytes)
+            && fits(self.inodes, inodes, self.quota.inodes)
+    }
 }
 
 /// Directory entry with full statistics
@@ -138,6 +194,12 @@ pub trait File: Send + Sync {
     /// Truncate the file to the specified size.
     async fn truncate(&self, size: u64) -> Result<()>;
 
+    /// Hint that `len` bytes at `offset` will be read soon, so that they can
+    /// be prefetched. Does nothing by default.
+    async fn readahead(&self, _offset: u64, _len: u64) -> Result<()> {
+        Ok(())
+    }
+
     /// Synchronize file data to persistent storage.
     async fn fsync(&self) -> Result<()>;
 
@@ -190,9 +252,58 @@ pub trait FileSystem: Send + Sync {
     /// but only the permission bits (lower 12 bits) will be modified.
     async fn chmod(&self, path: &str, mode: u32) -> Result<()>;
 
+    /// Change file ownership
+    ///
+    /// A `None` uid or gid leaves that field unchanged. Symlinks at the final
+    /// path component are not followed, so the link itself is changed.
+    /// Fails with NotSupported by default.
+    async fn chown(&self, _path: &str, _uid: Option<u32>, _gid: Option<u32>) -> Result<()> {
+        Err(FsError::NotSupported.into())
+    }
+
+    /// Change file access and modification times
+    ///
+    /// Times are in seconds since the Unix epoch, and a `None` time leaves that
+    /// timestamp unchanged. Like `chown`, a symlink at the final path component
+    /// is not followed.
+    async fn utimes(&self, path: &str, atime: Option<i64>, mtime: Option<i64>) -> Result<()>;
+
+    /// Get the value of an extended attribute
+    ///
+    /// Returns `Ok(None)` if the attribute is not set. Like `chown`, the
+    /// extended attribute operations do not follow a symlink at the final
+    /// path component, and fail with NotSupported by default.
+    async fn getxattr(&self, _path: &str, _name: &str) -> Result<Option<Vec<u8>>> {
+        Err(FsError::NotSupported.into())
+    }
+
+    /// Set an extended attribute, replacing any existing value
+    async fn setxattr(&self, _path: &str, _name: &str, _value: &[u8]) -> Result<()> {
+        Err(FsError::NotSupported.into())
+    }
+
+    /// List the names of the extended attributes set on a path
+    async fn listxattr(&self, _path: &str) -> Result<Vec<String>> {
+        Err(FsError::NotSupported.into())
+    }
+
+    /// Remove an extended attribute
+    ///
+    /// Returns `Ok(false)` if the attribute was not set.
+    async fn removexattr(&self, _path: &str, _name: &str) -> Result<bool> {
+        Err(FsError::NotSupported.into())
+    }
+
     /// Rename/move a file or directory
     async fn rename(&self, from: &str, to: &str) -> Result<()>;
 
+    /// Atomically exchange the entries at two paths, which must both exist
+    ///
+    /// Fails with NotSupported by default.
+    async fn exchange(&self, _a: &str, _b: &str) -> Result<()> {
+        Err(FsError::NotSupported.into())
+    }
+
     /// Create a symbolic link
     async fn symlink(&self, target: &str, linkpath: &str) -> Result<()>;
 
@@ -223,4 +334,41 @@ pub trait FileSystem: Send + Sync {
     /// This is optimized for FUSE create() which needs both atomically.
     /// Fails with AlreadyExists if the file exists.
     async fn create_file(&self, path: &str, mode: u32) -> Result<(Stats, BoxedFile)>;
+
+    /// Create an unnamed regular file in the directory `dir`, like `O_TMPFILE`.
+    ///
+    /// The file starts without links and is freed along with its last handle,
+    /// unless `link_inode` gives it a name first. Fails with NotSupported by
+    /// default.
+    async fn create_tmpfile(&self, _dir: &str, _mode: u32) -> Result<(Stats, BoxedFile)> {
+        Err(FsError::NotSupported.into())
+    }
+
+    /// Create a hard link to inode `ino` at `newpath`
+    ///
+    /// Unlike `link`, this can name a file that has no links left, such as one
+    /// from `create_tmpfile`, as long as a handle keeps it open. Fails with
+    /// NotSupported by default.
+    async fn link_inode(&self, _ino: i64, _newpath: &str) -> Result<()> {
+        Err(FsError::NotSupported.into())
+    }
+
+    /// Record a whiteout at `path`, for an overlay whose writable layer this
+    /// filesystem is
+    ///
+    /// The whiteout hides the lower layer's entry at `path` and below it.
+    /// Fails with NotSupported by default.
+    async fn add_whiteout(&self, _path: &str) -> Result<()> {
+        Err(FsError::NotSupported.into())
+    }
+
+    /// Check whether `path` or one of its parents has a whiteout
+    async fn has_whiteout(&self, _path: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Get the names of the entries of directory `path` that have a whiteout
+    async fn child_whiteouts(&self, _path: &str) -> Result<HashSet<String>> {
+        Ok(HashSet::new())
+    }
 }
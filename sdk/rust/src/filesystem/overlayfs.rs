@@ -375,6 +375,13 @@ impl File for OverlayFile {
         delta_file.truncate(size).await
     }
 
+    async fn readahead(&self, offset: u64, len: u64) -> Result<()> {
+        match self.delta_file.as_ref().or(self.base_file.as_ref()) {
+            Some(file) => file.readahead(offset, len).await,
+            None => Ok(()),
+        }
+    }
+
     async fn fsync(&self) -> Result<()> {
         // If we have a delta file handle, use it
         if let Some(ref delta_file) = self.delta_file {
@@ -453,21 +460,7 @@ impl OverlayFS {
     /// base layer represents. This is stored in the delta database so that
     /// tools like `agentfs diff` can determine what files were modified.
     pub async fn init_schema(conn: &Connection, base_path: &str) -> Result<()> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS fs_whiteout (
-                path TEXT PRIMARY KEY,
-                parent_path TEXT NOT NULL,
-                created_at INTEGER NOT NULL
-            )",
-            (),
-        )
-        .await?;
-        // Index on parent_path for efficient child lookups (avoids LIKE regex compilation)
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_fs_whiteout_parent ON fs_whiteout(parent_path)",
-            (),
-        )
-        .await?;
+        Self::init_whiteout_schema(conn).await?;
         // Store overlay configuration so tools can identify this as an overlay database
         conn.execute(
             "CREATE TABLE IF NOT EXISTS fs_overlay_config (
@@ -496,6 +489,26 @@ impl OverlayFS {
         Ok(())
     }
 
+    /// Create the whiteout table in a database, if it doesn't exist
+    pub async fn init_whiteout_schema(conn: &Connection) -> Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fs_whiteout (
+                path TEXT PRIMARY KEY,
+                parent_path TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            (),
+        )
+        .await?;
+        // Index on parent_path for efficient child lookups (avoids LIKE regex compilation)
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_fs_whiteout_parent ON fs_whiteout(parent_path)",
+            (),
+        )
+        .await?;
+        Ok(())
+    }
+
     /// Initialize the overlay filesystem schema (creates whiteout table)
     ///
     /// This must be called before using the overlay filesystem to ensure
@@ -1075,25 +1088,8 @@ impl FileSystem for OverlayFS {
         let base_stats = self.base.lstat(&normalized).await?;
         if let Some(stats) = base_stats {
             // Need to copy to delta first, then chmod
-            if stats.is_directory() {
-                // For directories, just create in delta and chmod
-                self.ensure_parent_dirs(&normalized).await?;
-                self.delta.mkdir(&normalized).await?;
+            if self.copy_up_entry(&normalized, &stats).await? {
                 self.delta.chmod(&normalized, mode).await?;
-            } else if stats.is_symlink() {
-                // For symlinks, copy the symlink to delta
-                if let Some(target) = self.base.readlink(&normalized).await? {
-                    self.ensure_parent_dirs(&normalized).await?;
-                    self.delta.symlink(&target, &normalized).await?;
-                    self.delta.chmod(&normalized, mode).await?;
-                }
-            } else {
-                // For regular files, copy content to delta
-                if let Some(data) = self.base.read_file(&normalized).await? {
-                    self.ensure_parent_dirs(&normalized).await?;
-                    self.delta.write_file(&normalized, &data).await?;
-                    self.delta.chmod(&normalized, mode).await?;
-                }
             }
             Ok(())
         } else {
@@ -1101,6 +1097,106 @@ impl FileSystem for OverlayFS {
         }
     }
 
+    async fn chown(&self, path: &str, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        let normalized = self.normalize_path(path);
+
+        // Check if whited-out
+        if self.is_whiteout(&normalized) {
+            return Err(FsError::NotFound.into());
+        }
+
+        // If file exists in delta, chown there directly
+        if self.exists_in_delta(&normalized).await? {
+            return self.delta.chown(&normalized, uid, gid).await;
+        }
+
+        // Check if exists in base
+        let base_stats = self.base.lstat(&normalized).await?;
+        if let Some(stats) = base_stats {
+            // Need to copy to delta first, then chown. Unchanged fields keep the
+            // base ownership rather than the delta's defaults.
+            if self.copy_up_entry(&normalized, &stats).await? {
+                let uid = uid.unwrap_or(stats.uid);
+                let gid = gid.unwrap_or(stats.gid);
+                self.delta.chown(&normalized, Some(uid), Some(gid)).await?;
+            }
+            Ok(())
+        } else {
+            Err(FsError::NotFound.into())
+        }
+    }
+
+    async fn utimes(&self, path: &str, atime: Option<i64>, mtime: Option<i64>) -> Result<()> {
+        let normalized = self.normalize_path(path);
+
+        // Check if whited-out
+        if self.is_whiteout(&normalized) {
+            return Err(FsError::NotFound.into());
+        }
+
+        // If file exists in delta, change the times there directly
+        if self.exists_in_delta(&normalized).await? {
+            return self.delta.utimes(&normalized, atime, mtime).await;
+        }
+
+        // Check if exists in base
+        let base_stats = self.base.lstat(&normalized).await?;
+        if let Some(stats) = base_stats {
+            // Need to copy to delta first. Unchanged times keep the base values
+            // rather than the time of the copy.
+            if self.copy_up_entry(&normalized, &stats).await? {
+                let atime = atime.unwrap_or(stats.atime);
+                let mtime = mtime.unwrap_or(stats.mtime);
+                self.delta
+                    .utimes(&normalized, Some(atime), Some(mtime))
+                    .await?;
+            }
+            Ok(())
+        } else {
+            Err(FsError::NotFound.into())
+        }
+    }
+
+    async fn getxattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>> {
+        let normalized = self.normalize_path(path);
+
+        if self.is_whiteout(&normalized) {
+            return Err(FsError::NotFound.into());
+        }
+
+        if self.exists_in_delta(&normalized).await? {
+            return self.delta.getxattr(&normalized, name).await;
+        }
+
+        self.base.getxattr(&normalized, name).await
+    }
+
+    async fn setxattr(&self, path: &str, name: &str, value: &[u8]) -> Result<()> {
+        let normalized = self.normalize_path(path);
+        self.ensure_in_delta(&normalized).await?;
+        self.delta.setxattr(&normalized, name, value).await
+    }
+
+    async fn listxattr(&self, path: &str) -> Result<Vec<String>> {
+        let normalized = self.normalize_path(path);
+
+        if self.is_whiteout(&normalized) {
+            return Err(FsError::NotFound.into());
+        }
+
+        if self.exists_in_delta(&normalized).await? {
+            return self.delta.listxattr(&normalized).await;
+        }
+
+        self.base.listxattr(&normalized).await
+    }
+
+    async fn removexattr(&self, path: &str, name: &str) -> Result<bool> {
+        let normalized = self.normalize_path(path);
+        self.ensure_in_delta(&normalized).await?;
+        self.delta.removexattr(&normalized, name).await
+    }
+
     async fn rename(&self, from: &str, to: &str) -> Result<()> {
         let from_normalized = self.normalize_path(from);
         let to_normalized = self.normalize_path(to);
@@ -1289,6 +1385,63 @@ impl FileSystem for OverlayFS {
 }
 
 impl OverlayFS {
+    /// Copy a single base-layer entry into the delta layer for a metadata change.
+    ///
+    /// Directories are created empty, symlinks are recreated with the same target,
+    /// and regular files are copied with their content. The permission bits of the
+    /// base entry are preserved. Returns `false` if the entry vanished from the base
+    /// layer and nothing was copied.
+    async fn copy_up_entry(&self, normalized: &str, stats: &Stats) -> Result<bool> {
+        if stats.is_directory() {
+            // For directories, just create in delta
+            self.ensure_parent_dirs(normalized).await?;
+            self.delta.mkdir(normalized).await?;
+        } else if stats.is_symlink() {
+            // For symlinks, copy the symlink to delta
+            let Some(target) = self.base.readlink(normalized).await? else {
+                return Ok(false);
+            };
+            self.ensure_parent_dirs(normalized).await?;
+            self.delta.symlink(&target, normalized).await?;
+        } else {
+            // For regular files, copy content to delta
+            let Some(data) = self.base.read_file(normalized).await? else {
+                return Ok(false);
+            };
+            self.ensure_parent_dirs(normalized).await?;
+            self.delta.write_file(normalized, &data).await?;
+        }
+        self.delta.chmod(normalized, stats.mode).await?;
+
+        // Carry extended attributes over. A base layer that does not support
+        // them simply has none to copy.
+        if let Ok(names) = self.base.listxattr(normalized).await {
+            for name in names {
+                if let Some(value) = self.base.getxattr(normalized, &name).await? {
+                    self.delta.setxattr(normalized, &name, &value).await?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Make sure an entry exists in delta before modifying its metadata
+    ///
+    /// Entries only present in base are copied up. Fails with `NotFound` if
+    /// the path is whited out or exists in neither layer.
+    async fn ensure_in_delta(&self, normalized: &str) -> Result<()> {
+        if self.is_whiteout(normalized) {
+            return Err(FsError::NotFound.into());
+        }
+        if self.exists_in_delta(normalized).await? {
+            return Ok(());
+        }
+        match self.base.lstat(normalized).await? {
+            Some(stats) if self.copy_up_entry(normalized, &stats).await? => Ok(()),
+            _ => Err(FsError::NotFound.into()),
+        }
+    }
+
     /// Recursively copy a directory from base to delta
     async fn copy_dir_to_delta(&self, path: &str) -> Result<()> {
         self.delta.mkdir(path).await?;
@@ -1546,6 +1699,30 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_overlay_chown_base_file_copies_to_delta() -> Result<()> {
+        let (overlay, _base_dir, _delta_dir) = create_test_overlay().await?;
+
+        let original_stats = overlay.stat("/base.txt").await?.unwrap();
+
+        // chown only the uid - the gid and mode should be carried over from base
+        overlay.chown("/base.txt", Some(1234), None).await?;
+
+        let stats = overlay.stat("/base.txt").await?.unwrap();
+        assert_eq!(stats.uid, 1234, "uid should be updated");
+        assert_eq!(stats.gid, original_stats.gid, "gid should be unchanged");
+        assert_eq!(
+            stats.mode & 0o777,
+            original_stats.mode & 0o777,
+            "Mode should be preserved on copy-up"
+        );
+
+        let data = overlay.read_file("/base.txt").await?.unwrap();
+        assert_eq!(data, b"base content", "Content should be preserved");
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_overlay_link_base_file_preserves_inode() -> Result<()> {
         let (overlay, _base_dir, _delta_dir) = create_test_overlay().await?;
@@ -0,0 +1,275 @@
+//! Consistency checks and repairs of a filesystem database.
+//!
+//! A database left behind by a crashed process, or edited by hand, can hold
+//! rows the filesystem never leaves behind itself: directory entries of missing
+//! inodes, inodes no directory links to, and link counts or content refcounts
+//! that disagree with the references to them. The repairs are made in a single
+//! transaction that is only committed when applying them, so a dry run reports
+//! exactly what applying would fix. References to content that is gone are
+//! reported but left alone, as there is nothing to repair them with.
+
+use super::snapshot::{self, DataLayout};
+use super::{S_IFDIR, S_IFMT};
+use crate::error::Result;
+use std::collections::{HashMap, HashSet};
+use turso::{Connection, Row, Value};
+
+const ROOT_INO: i64 = 1;
+
+/// What a repair found, and fixed unless it was a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Problems reported by SQLite's integrity check
+    pub integrity_errors: Vec<String>,
+    /// Whether the indexes were rebuilt
+    pub reindexed: bool,
+    /// Directory entries whose inode or parent directory is missing
+    pub dangling_dentries: u64,
+    /// Inodes without directory entries, other than the root
+    pub orphaned_inodes: u64,
+    /// Inodes whose link count differs from their number of directory entries
+    pub link_counts: u64,
+    /// Data, symlink, xattr and origin rows of missing inodes
+    pub orphaned_rows: u64,
+    /// Content whose refcount differs from its number of references
+    pub refcounts: u64,
+    /// Content nothing references
+    pub unreferenced_content: u64,
+    /// References to content that doesn't exist, which can't be repaired
+    pub missing_content: u64,
+}
+
+impl RepairReport {
+    /// Check whether no problems were found
+    pub fn is_clean(&self) -> bool {
+        self.integrity_errors.is_empty()
+            && self.dangling_dentries == 0
+            && self.orphaned_inodes == 0
+            && self.link_counts == 0
+            && self.orphaned_rows == 0
+            && self.refcounts == 0
+            && self.unreferenced_content == 0
+            && self.missing_content == 0
+    }
+}
+
+/// Check the filesystem for inconsistencies, fixing them if `apply` is set
+pub(crate) async fn repair(
+    conn: &Connection,
+    layout: DataLayout,
+    apply: bool,
+) -> Result<RepairReport> {
+    let mut report = RepairReport {
+        integrity_errors: integrity_check(conn).await?,
+        ..Default::default()
+    };
+    if apply {
+        conn.execute("REINDEX", ()).await?;
+        report.reindexed = true;
+    }
+
+    conn.execute("BEGIN IMMEDIATE", ()).await?;
+    let result = fix_references(conn, layout, &mut report).await;
+    if apply && result.is_ok() {
+        conn.execute("COMMIT", ()).await?;
+    } else {
+        let _ = conn.execute("ROLLBACK", ()).await;
+    }
+    result?;
+
+    Ok(report)
+}
+
+async fn integrity_check(conn: &Connection) -> Result<Vec<String>> {
+    let mut rows = conn.query("PRAGMA integrity_check", ()).await?;
+    let mut errors = Vec::new();
+    while let Some(row) = rows.next().await? {
+        if let Ok(Value::Text(message)) = row.get_value(0) {
+            if message != "ok" {
+                errors.push(message);
+            }
+        }
+    }
+    Ok(errors)
+}
+
+async fn fix_references(
+    conn: &Connection,
+    layout: DataLayout,
+    report: &mut RepairReport,
+) -> Result<()> {
+    // Whether each inode is a directory, and its link count
+    let mut inodes = HashMap::new();
+    let mut rows = conn
+        .query("SELECT ino, mode, nlink FROM fs_inode", ())
+        .await?;
+    while let Some(row) = rows.next().await? {
+        let is_dir = (integer(&row, 1) as u32 & S_IFMT) == S_IFDIR;
+        inodes.insert(integer(&row, 0), (is_dir, integer(&row, 2)));
+    }
+    drop(rows);
+
+    // (id, parent_ino, ino) of each directory entry
+    let mut dentries = Vec::new();
+    let mut rows = conn
+        .query("SELECT id, parent_ino, ino FROM fs_dentry", ())
+        .await?;
+    while let Some(row) = rows.next().await? {
+        dentries.push((integer(&row, 0), integer(&row, 1), integer(&row, 2)));
+    }
+    drop(rows);
+
+    // Removing a dangling entry can orphan its inode, and removing an orphaned
+    // directory leaves the entries in it dangling, so repeat until neither
+    // finds anything
+    loop {
+        let (kept, dangling): (Vec<_>, Vec<_>) =
+            dentries.into_iter().partition(|(_, parent_ino, ino)| {
+                inodes.contains_key(ino) && matches!(inodes.get(parent_ino), Some((true, _)))
+            });
+        dentries = kept;
+        let linked: HashSet<i64> = dentries.iter().map(|(_, _, ino)| *ino).collect();
+        let orphans: Vec<i64> = inodes
+            .keys()
+            .copied()
+            .filter(|ino| *ino != ROOT_INO && !linked.contains(ino))
+            .collect();
+        if dangling.is_empty() && orphans.is_empty() {
+            break;
+        }
+
+        for (id, _, _) in dangling {
+            conn.execute("DELETE FROM fs_dentry WHERE id = ?", (id,))
+                .await?;
+            report.dangling_dentries += 1;
+        }
+        // The rows of the inode are removed with those of other missing inodes
+        for ino in orphans {
+            inodes.remove(&ino);
+            conn.execute("DELETE FROM fs_inode WHERE ino = ?", (ino,))
+                .await?;
+            report.orphaned_inodes += 1;
+        }
+    }
+
+    let mut links: HashMap<i64, i64> = HashMap::new();
+    for (_, _, ino) in &dentries {
+        *links.entry(*ino).or_default() += 1;
+    }
+    for (ino, (_, nlink)) in &inodes {
+        let expected = links.get(ino).copied().unwrap_or(0);
+        if *ino != ROOT_INO && *nlink != expected {
+            conn.execute(
+                "UPDATE fs_inode SET nlink = ? WHERE ino = ?",
+                (expected, *ino),
+            )
+            .await?;
+            report.link_counts += 1;
+        }
+    }
+
+    let data_table = if layout.dedup {
+        "fs_content_ref"
+    } else {
+        "fs_data"
+    };
+    for (table, column) in [
+        (data_table, "ino"),
+        ("fs_symlink", "ino"),
+        ("fs_xattr", "ino"),
+        ("fs_origin", "delta_ino"),
+    ] {
+        if !snapshot::table_exists(conn, table).await? {
+            continue;
+        }
+        let mut missing = Vec::new();
+        let mut rows = conn
+            .query(&format!("SELECT DISTINCT {} FROM {}", column, table), ())
+            .await?;
+        while let Some(row) = rows.next().await? {
+            let ino = integer(&row, 0);
+            if !inodes.contains_key(&ino) {
+                missing.push(ino);
+            }
+        }
+        drop(rows);
+
+        for ino in missing {
+            report.orphaned_rows += conn
+                .execute(
+                    &format!("DELETE FROM {} WHERE {} = ?", table, column),
+                    (ino,),
+                )
+                .await?;
+        }
+    }
+
+    if layout.dedup {
+        fix_refcounts(conn, report).await?;
+    }
+    Ok(())
+}
+
+/// Set the refcount of content to its number of references from the
+/// filesystem and its snapshots, removing content without any and counting
+/// references to content that doesn't exist
+async fn fix_refcounts(conn: &Connection, report: &mut RepairReport) -> Result<()> {
+    let mut references: HashMap<Vec<u8>, i64> = HashMap::new();
+    let mut sources = vec!["SELECT hash, COUNT(*) FROM fs_content_ref GROUP BY hash"];
+    if snapshot::table_exists(conn, "fs_snapshot_content_ref").await? {
+        sources.push("SELECT hash, COUNT(*) FROM fs_snapshot_content_ref GROUP BY hash");
+    }
+    for sql in sources {
+        let mut rows = conn.query(sql, ()).await?;
+        while let Some(row) = rows.next().await? {
+            if let Ok(Value::Blob(hash)) = row.get_value(0) {
+                *references.entry(hash).or_default() += integer(&row, 1);
+            }
+        }
+    }
+
+    let mut content = Vec::new();
+    let mut rows = conn
+        .query("SELECT hash, refcount FROM fs_content", ())
+        .await?;
+    while let Some(row) = rows.next().await? {
+        if let Ok(Value::Blob(hash)) = row.get_value(0) {
+            content.push((hash, integer(&row, 1)));
+        }
+    }
+    drop(rows);
+
+    let stored: HashSet<&[u8]> = content.iter().map(|(hash, _)| hash.as_slice()).collect();
+    report.missing_content = references
+        .iter()
+        .filter(|(hash, _)| !stored.contains(hash.as_slice()))
+        .map(|(_, count)| *count as u64)
+        .sum();
+
+    for (hash, refcount) in content {
+        match references.get(&hash).copied().unwrap_or(0) {
+            0 => {
+                conn.execute("DELETE FROM fs_content WHERE hash = ?", (hash,))
+                    .await?;
+                report.unreferenced_content += 1;
+            }
+            expected if expected != refcount => {
+                conn.execute(
+                    "UPDATE fs_content SET refcount = ? WHERE hash = ?",
+                    (expected, hash),
+                )
+                .await?;
+                report.refcounts += 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn integer(row: &Row, index: usize) -> i64 {
+    row.get_value(index)
+        .ok()
+        .and_then(|v| v.as_integer().copied())
+        .unwrap_or(0)
+}
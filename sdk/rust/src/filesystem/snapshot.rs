@@ -0,0 +1,403 @@
+//! Named point-in-time snapshots of a filesystem.
+//!
+//! Creating a snapshot copies the filesystem tables into `fs_snapshot_*`
+//! tables, tagged with the snapshot's id. With deduplication, only the chunk
+//! references are copied: the snapshot holds a reference to each content it
+//! uses, so content is shared with the live filesystem until either changes.
+//! Restoring replaces the filesystem tables with the snapshot's copy in a
+//! single transaction.
+
+use crate::error::{Error, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+use turso::{Connection, Value};
+
+/// A named snapshot of a filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub name: String,
+    /// Creation time in seconds since the Unix epoch
+    pub created_at: i64,
+}
+
+/// A table captured by snapshots
+struct SnapshotTable {
+    table: &'static str,
+    /// Columns copied to and from the snapshot table
+    columns: &'static str,
+    /// Schema of the snapshot table
+    schema: &'static str,
+}
+
+impl SnapshotTable {
+    fn snapshot_table(&self) -> String {
+        format!("fs_snapshot_{}", &self.table["fs_".len()..])
+    }
+}
+
+/// Metadata tables, copied as is. The overlay tables only exist in the delta
+/// layer of an overlay filesystem.
+const TABLES: &[SnapshotTable] = &[
+    SnapshotTable {
+        table: "fs_inode",
+        columns: "ino, mode, nlink, uid, gid, size, atime, mtime, ctime",
+        schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_inode (
+            snapshot_id INTEGER NOT NULL,
+            ino INTEGER NOT NULL,
+            mode INTEGER NOT NULL,
+            nlink INTEGER NOT NULL,
+            uid INTEGER NOT NULL,
+            gid INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            atime INTEGER NOT NULL,
+            mtime INTEGER NOT NULL,
+            ctime INTEGER NOT NULL,
+            PRIMARY KEY (snapshot_id, ino)
+        )",
+    },
+    SnapshotTable {
+        table: "fs_dentry",
+        columns: "id, name, parent_ino, ino",
+        schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_dentry (
+            snapshot_id INTEGER NOT NULL,
+            id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            parent_ino INTEGER NOT NULL,
+            ino INTEGER NOT NULL,
+            PRIMARY KEY (snapshot_id, id)
+        )",
+    },
+    SnapshotTable {
+        table: "fs_symlink",
+        columns: "ino, target",
+        schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_symlink (
+            snapshot_id INTEGER NOT NULL,
+            ino INTEGER NOT NULL,
+            target TEXT NOT NULL,
+            PRIMARY KEY (snapshot_id, ino)
+        )",
+    },
+    SnapshotTable {
+        table: "fs_xattr",
+        columns: "ino, name, value",
+        schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_xattr (
+            snapshot_id INTEGER NOT NULL,
+            ino INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            value BLOB NOT NULL,
+            PRIMARY KEY (snapshot_id, ino, name)
+        )",
+    },
+    SnapshotTable {
+        table: "fs_whiteout",
+        columns: "path, parent_path, created_at",
+        schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_whiteout (
+            snapshot_id INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            parent_path TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (snapshot_id, path)
+        )",
+    },
+    SnapshotTable {
+        table: "fs_origin",
+        columns: "delta_ino, base_ino",
+        schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_origin (
+            snapshot_id INTEGER NOT NULL,
+            delta_ino INTEGER NOT NULL,
+            base_ino INTEGER NOT NULL,
+            PRIMARY KEY (snapshot_id, delta_ino)
+        )",
+    },
+];
+
+/// File data of a filesystem without deduplication
+const DATA_TABLE: SnapshotTable = SnapshotTable {
+    table: "fs_data",
+    columns: "ino, chunk_index, data",
+    schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_data (
+        snapshot_id INTEGER NOT NULL,
+        ino INTEGER NOT NULL,
+        chunk_index INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        compression INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (snapshot_id, ino, chunk_index)
+    )",
+};
+
+/// `DATA_TABLE` of a compressed filesystem
+const COMPRESSED_DATA_TABLE: SnapshotTable = SnapshotTable {
+    columns: "ino, chunk_index, data, compression",
+    ..DATA_TABLE
+};
+
+/// Chunk references of a filesystem with deduplication
+const CONTENT_REF_TABLE: SnapshotTable = SnapshotTable {
+    table: "fs_content_ref",
+    columns: "ino, chunk_index, hash",
+    schema: "CREATE TABLE IF NOT EXISTS fs_snapshot_content_ref (
+        snapshot_id INTEGER NOT NULL,
+        ino INTEGER NOT NULL,
+        chunk_index INTEGER NOT NULL,
+        hash BLOB NOT NULL,
+        PRIMARY KEY (snapshot_id, ino, chunk_index)
+    )",
+};
+
+/// How the file data of a filesystem is stored
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DataLayout {
+    pub dedup: bool,
+    pub compressed: bool,
+}
+
+impl DataLayout {
+    fn data_table(&self) -> &'static SnapshotTable {
+        match (self.dedup, self.compressed) {
+            (true, _) => &CONTENT_REF_TABLE,
+            (false, true) => &COMPRESSED_DATA_TABLE,
+            (false, false) => &DATA_TABLE,
+        }
+    }
+
+    /// Tables of the filesystem in `conn` that snapshots capture
+    async fn tables(&self, conn: &Connection) -> Result<Vec<&'static SnapshotTable>> {
+        let mut tables = Vec::new();
+        for table in TABLES.iter().chain([self.data_table()]) {
+            if table_exists(conn, table.table).await? {
+                tables.push(table);
+            }
+        }
+        Ok(tables)
+    }
+}
+
+/// Record the current state of the filesystem in `conn` as snapshot `name`
+pub(crate) async fn create(conn: &Connection, layout: DataLayout, name: &str) -> Result<Snapshot> {
+    initialize_schema(conn).await?;
+    let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    in_transaction(conn, async {
+        if find(conn, name).await?.is_some() {
+            return Err(Error::SnapshotExists(name.to_string()));
+        }
+        let mut rows = conn
+            .query(
+                "INSERT INTO fs_snapshot (name, created_at) VALUES (?, ?) RETURNING id",
+                (name, created_at),
+            )
+            .await?;
+        let id = match rows.next().await? {
+            Some(row) => row.get_value(0).ok().and_then(|v| v.as_integer().copied()),
+            None => None,
+        }
+        .ok_or_else(|| Error::Internal("failed to create snapshot".to_string()))?;
+        drop(rows);
+
+        for table in layout.tables(conn).await? {
+            let sql = format!(
+                "INSERT INTO {} (snapshot_id, {columns}) SELECT ?, {columns} FROM {}",
+                table.snapshot_table(),
+                table.table,
+                columns = table.columns,
+            );
+            conn.execute(&sql, (id,)).await?;
+        }
+        if layout.dedup {
+            adjust_refcounts(conn, Some(id), 1).await?;
+        }
+        Ok(Snapshot {
+            name: name.to_string(),
+            created_at,
+        })
+    })
+    .await
+}
+
+/// List the snapshots of the filesystem in `conn`, oldest first
+pub(crate) async fn list(conn: &Connection) -> Result<Vec<Snapshot>> {
+    if !table_exists(conn, "fs_snapshot").await? {
+        return Ok(Vec::new());
+    }
+    let mut rows = conn
+        .query("SELECT name, created_at FROM fs_snapshot ORDER BY id", ())
+        .await?;
+
+    let mut snapshots = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let name = match row.get_value(0) {
+            Ok(Value::Text(name)) => name,
+            _ => continue,
+        };
+        let created_at = row
+            .get_value(1)
+            .ok()
+            .and_then(|v| v.as_integer().copied())
+            .unwrap_or(0);
+        snapshots.push(Snapshot { name, created_at });
+    }
+    Ok(snapshots)
+}
+
+/// Roll the filesystem in `conn` back to snapshot `name`
+///
+/// The snapshot is kept, so it can be restored again.
+pub(crate) async fn restore(conn: &Connection, layout: DataLayout, name: &str) -> Result<()> {
+    in_transaction(conn, async {
+        let id = find(conn, name)
+            .await?
+            .ok_or_else(|| Error::SnapshotNotFound(name.to_string()))?;
+
+        // Take the snapshot's references before dropping the live ones, so
+        // that content they share is never freed
+        if layout.dedup {
+            adjust_refcounts(conn, Some(id), 1).await?;
+            adjust_refcounts(conn, None, -1).await?;
+            free_unreferenced_content(conn).await?;
+        }
+        for table in layout.tables(conn).await? {
+            conn.execute(&format!("DELETE FROM {}", table.table), ())
+                .await?;
+            let sql = format!(
+                "INSERT INTO {} ({columns}) SELECT {columns} FROM {} WHERE snapshot_id = ?",
+                table.table,
+                table.snapshot_table(),
+                columns = table.columns,
+            );
+            conn.execute(&sql, (id,)).await?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Delete snapshot `name` of the filesystem in `conn`
+pub(crate) async fn delete(conn: &Connection, layout: DataLayout, name: &str) -> Result<()> {
+    in_transaction(conn, async {
+        let id = find(conn, name)
+            .await?
+            .ok_or_else(|| Error::SnapshotNotFound(name.to_string()))?;
+
+        if layout.dedup {
+            adjust_refcounts(conn, Some(id), -1).await?;
+            free_unreferenced_content(conn).await?;
+        }
+        for table in TABLES.iter().chain([&DATA_TABLE, &CONTENT_REF_TABLE]) {
+            let sql = format!(
+                "DELETE FROM {} WHERE snapshot_id = ?",
+                table.snapshot_table()
+            );
+            conn.execute(&sql, (id,)).await?;
+        }
+        conn.execute("DELETE FROM fs_snapshot WHERE id = ?", (id,))
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+async fn initialize_schema(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS fs_snapshot (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            created_at INTEGER NOT NULL
+        )",
+        (),
+    )
+    .await?;
+    for table in TABLES.iter().chain([&DATA_TABLE, &CONTENT_REF_TABLE]) {
+        conn.execute(table.schema, ()).await?;
+    }
+    Ok(())
+}
+
+/// Look up the id of snapshot `name`
+pub(crate) async fn find(conn: &Connection, name: &str) -> Result<Option<i64>> {
+    if !table_exists(conn, "fs_snapshot").await? {
+        return Ok(None);
+    }
+    let mut rows = conn
+        .query("SELECT id FROM fs_snapshot WHERE name = ?", (name,))
+        .await?;
+    Ok(match rows.next().await? {
+        Some(row) => row.get_value(0).ok().and_then(|v| v.as_integer().copied()),
+        None => None,
+    })
+}
+
+pub(crate) async fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    let mut rows = conn
+        .query(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+            (table,),
+        )
+        .await?;
+    Ok(rows.next().await?.is_some())
+}
+
+/// Add `delta` to the refcount of content for each reference to it, in
+/// snapshot `snapshot_id` or in the live filesystem without one
+async fn adjust_refcounts(conn: &Connection, snapshot_id: Option<i64>, delta: i64) -> Result<()> {
+    let mut rows = match snapshot_id {
+        Some(id) => {
+            conn.query(
+                "SELECT hash, COUNT(*) FROM fs_snapshot_content_ref
+                WHERE snapshot_id = ? GROUP BY hash",
+                (id,),
+            )
+            .await?
+        }
+        None => {
+            conn.query(
+                "SELECT hash, COUNT(*) FROM fs_content_ref GROUP BY hash",
+                (),
+            )
+            .await?
+        }
+    };
+    let mut counts = Vec::new();
+    while let Some(row) = rows.next().await? {
+        if let Ok(Value::Blob(hash)) = row.get_value(0) {
+            let count = row
+                .get_value(1)
+                .ok()
+                .and_then(|v| v.as_integer().copied())
+                .unwrap_or(0);
+            counts.push((hash, count));
+        }
+    }
+    drop(rows);
+
+    for (hash, count) in counts {
+        conn.execute(
+            "UPDATE fs_content SET refcount = refcount + ? WHERE hash = ?",
+            (count * delta, hash),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn free_unreferenced_content(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM fs_content WHERE refcount <= 0", ())
+        .await?;
+    Ok(())
+}
+
+/// Run `body` in an immediate transaction, rolling it back on error
+async fn in_transaction<T>(
+    conn: &Connection,
+    body: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    conn.execute("BEGIN IMMEDIATE", ()).await?;
+    match body.await {
+        Ok(value) => {
+            conn.execute("COMMIT", ()).await?;
+            Ok(value)
+        }
+        Err(e) => {
+            let _ = conn.execute("ROLLBACK", ()).await;
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Glob patterns over `/`-separated paths.
+//!
+//! `*` and `?` match within a single component, `[...]` matches a character
+//! class (negated with a leading `!` or `^`, and taken literally without a
+//! closing `]`), and a `**` component matches any number of components.
+//!
+//! Patterns are compiled once and matched without exponential backtracking:
+//! matching takes at most time proportional to the pattern length times the
+//! path length.
+
+/// A compiled glob pattern
+#[derive(Debug, Clone)]
+pub struct Glob {
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Clone)]
+enum Component {
+    /// `**`, any number of components
+    AnyDepth,
+    /// A component of literal characters and wildcards
+    Name(Vec<Token>),
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    /// `?`, any single character
+    AnyChar,
+    /// `*`, any run of characters
+    AnyRun,
+    /// `[...]`, as inclusive character ranges
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+impl Glob {
+    /// Compile a pattern
+    ///
+    /// Empty components, as from a leading, trailing or doubled `/`, are
+    /// ignored, so `/a/b` and `a/b` are the same pattern.
+    pub fn new(pattern: &str) -> Self {
+        let components = pattern
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(|component| match component {
+                "**" => Component::AnyDepth,
+                _ => Component::Name(compile_name(component)),
+            })
+            .collect();
+        Self { components }
+    }
+
+    /// Check whether the pattern matches a path given as its components
+    pub fn matches(&self, components: &[&str]) -> bool {
+        wildcard(
+            &self.components,
+            components,
+            |component| matches!(component, Component::AnyDepth),
+            |component, name| match component {
+                Component::AnyDepth => true,
+                Component::Name(tokens) => match_name(tokens, name),
+            },
+        )
+    }
+
+    /// Check whether the pattern matches a `/`-separated path
+    ///
+    /// Empty components are ignored, as in the pattern.
+    pub fn matches_path(&self, path: &str) -> bool {
+        let components: Vec<&str> = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect();
+        self.matches(&components)
+    }
+}
+
+/// Compile a single component of a pattern
+fn compile_name(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // A run of stars matches the same names as one
+            '*' if matches!(tokens.last(), Some(Token::AnyRun)) => {}
+            '*' => tokens.push(Token::AnyRun),
+            '?' => tokens.push(Token::AnyChar),
+            '[' => {
+                if let Some((class, len)) = compile_class(&chars[i + 1..]) {
+                    tokens.push(class);
+                    i += len;
+                } else {
+                    tokens.push(Token::Char('['));
+                }
+            }
+            c => tokens.push(Token::Char(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Compile the character class following a `[`, returning it and the number
+/// of characters up to and including the closing `]`, or `None` if the class
+/// is not closed.
+///
+/// A `]` right after the `[` (or the negation) is a member of the class.
+fn compile_class(pattern: &[char]) -> Option<(Token, usize)> {
+    let (negated, start) = match pattern.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut ranges = Vec::new();
+    let mut i = start;
+    loop {
+        match pattern[i..] {
+            [']', ..] if i > start => {
+                return Some((Token::Class { ranges, negated }, i + 1));
+            }
+            [lo, '-', hi, ..] if hi != ']' => {
+                ranges.push((lo, hi));
+                i += 3;
+            }
+            [c, ..] => {
+                ranges.push((c, c));
+                i += 1;
+            }
+            [] => return None,
+        }
+    }
+}
+
+impl Token {
+    fn matches(&self, c: &char) -> bool {
+        match self {
+            Token::Char(expected) => expected == c,
+            Token::AnyChar | Token::AnyRun => true,
+            Token::Class { ranges, negated } => {
+                ranges.iter().any(|(lo, hi)| (lo..=hi).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/// Match a single component against the tokens of a pattern component
+fn match_name(tokens: &[Token], name: &str) -> bool {
+    let chars: Vec<char> = name.chars().collect();
+    wildcard(
+        tokens,
+        &chars,
+        |token| matches!(token, Token::AnyRun),
+        Token::matches,
+    )
+}
+
+/// Match `items` against `pattern`, where an element for which `is_star`
+/// holds matches any run of items and every other element exactly one item,
+/// if `matches` holds for it.
+///
+/// On a mismatch only the most recent star is retried with one more item.
+/// Since every other element matches a single item, an earlier star never
+/// needs to be retried, which keeps this from backtracking exponentially.
+fn wildcard<P, I>(
+    pattern: &[P],
+    items: &[I],
+    is_star: impl Fn(&P) -> bool,
+    matches: impl Fn(&P, &I) -> bool,
+) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // Position of the most recent star, and the first item it hasn't matched
+    let mut retry = None;
+    while i < items.len() {
+        if p < pattern.len() && is_star(&pattern[p]) {
+            retry = Some((p, i));
+            p += 1;
+        } else if p < pattern.len() && matches(&pattern[p], &items[i]) {
+            p += 1;
+            i += 1;
+        } else if let Some((star, start)) = retry {
+            retry = Some((star, start + 1));
+            p = star + 1;
+            i = start + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(is_star)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches_name(pattern: &str, name: &str) -> bool {
+        Glob::new(pattern).matches(&[name])
+    }
+
+    #[test]
+    fn test_glob_names() {
+        assert!(matches_name("*.log", "app.log"));
+        assert!(!matches_name("*.log", "app.log.1"));
+        assert!(matches_name("app.?", "app.1"));
+        assert!(!matches_name("app.?", "app."));
+        assert!(matches_name("*", ""));
+        assert!(matches_name("a*b*c", "aXbYbZc"));
+        assert!(!matches_name("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn test_glob_classes() {
+        assert!(matches_name("[ab]*", "bin"));
+        assert!(!matches_name("[!ab]*", "bin"));
+        assert!(!matches_name("[^ab]*", "bin"));
+        assert!(matches_name("file[0-9]", "file7"));
+        assert!(!matches_name("file[0-9]", "filex"));
+        assert!(matches_name("[]]", "]"));
+        assert!(matches_name("[a-]", "-"));
+
+        // Without a closing bracket the `[` is literal
+        assert!(matches_name("[]", "[]"));
+        assert!(matches_name("[ab", "[ab"));
+        assert!(!matches_name("[ab", "a"));
+    }
+
+    #[test]
+    fn test_glob_paths() {
+        let glob = Glob::new("/a/*.txt");
+        assert!(glob.matches_path("/a/file.txt"));
+        assert!(!glob.matches_path("/a/b/file.txt"));
+
+        let glob = Glob::new("/**/.git");
+        assert!(glob.matches_path("/.git"));
+        assert!(glob.matches_path("/x/y/.git"));
+        assert!(!glob.matches_path("/x/y/.git/config"));
+
+        let glob = Glob::new("docs/**/*.tmp");
+        assert!(glob.matches(&["docs", "x.tmp"]));
+        assert!(glob.matches(&["docs", "a", "b", "x.tmp"]));
+        assert!(!glob.matches(&["x.tmp"]));
+
+        assert!(Glob::new("/a/**").matches_path("/a"));
+        assert!(!Glob::new("/a/b").matches_path("/a"));
+    }
+
+    #[test]
+    fn test_glob_no_exponential_backtracking() {
+        let name = "a".repeat(64);
+        let pattern = format!("{}b", "a*".repeat(32));
+        assert!(!matches_name(&pattern, &name));
+
+        let path = vec!["a"; 64];
+        let pattern = format!("{}b", "a/**/".repeat(32));
+        assert!(!Glob::new(&pattern).matches(&path));
+    }
+}
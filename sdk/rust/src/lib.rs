@@ -1,5 +1,6 @@
 pub mod error;
 pub mod filesystem;
+pub mod glob;
 pub mod kvstore;
 pub mod toolcalls;
 
@@ -15,8 +16,10 @@ use turso::{Builder, Connection, Value};
 #[cfg(unix)]
 pub use filesystem::HostFS;
 pub use filesystem::{
-    BoxedFile, DirEntry, File, FileSystem, FilesystemStats, FsError, OverlayFS, Stats,
-    DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG,
+    BoxedFile, Compression, DataUsage, DirEntry, File, FileSystem, FilesystemStats, FsError,
+    ManifestEntry, OverlayFS, Quota, RepairReport, Snapshot, Stats, DEFAULT_BLOCK_CACHE_SIZE,
+    DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, DEFAULT_READ_CONNECTIONS, S_IFDIR, S_IFLNK, S_IFMT,
+    S_IFREG,
 };
 pub use kvstore::KvStore;
 pub use toolcalls::{ToolCall, ToolCallStats, ToolCallStatus, ToolCalls};
@@ -74,7 +77,7 @@ pub fn get_mounts() -> Vec<Mount> {
 }
 
 /// Configuration options for opening an AgentFS instance
-#[derive(Debug, Clone, Default)]
+#[derive(Clone, Default)]
 pub struct AgentFSOptions {
     /// Optional unique identifier for the agent.
     /// - If Some(id): Creates persistent storage at `.agentfs/{id}.db`
@@ -86,6 +89,47 @@ pub struct AgentFSOptions {
     /// Optional base directory for overlay filesystem (copy-on-write).
     /// When set, the filesystem operates as an overlay on top of this directory.
     pub base: Option<PathBuf>,
+    /// Whether to deduplicate file content.
+    /// Only takes effect when the filesystem is created.
+    pub dedup: bool,
+    /// Compression of file content.
+    /// Only takes effect when the filesystem is created.
+    pub compression: Compression,
+    /// Passphrase that unlocks an encrypted filesystem.
+    /// Ignored for unencrypted filesystems.
+    pub passphrase: Option<String>,
+    /// Whether to encrypt file content with `passphrase`.
+    /// Only takes effect when the filesystem is created.
+    pub encrypt: bool,
+    /// Limits on the size of the filesystem.
+    /// Stored in the filesystem when set; an unlimited quota leaves the stored one.
+    pub quota: Quota,
+    /// Number of extra connections for filesystem reads.
+    /// `None` uses `DEFAULT_READ_CONNECTIONS`; in-memory databases use none.
+    pub read_connections: Option<usize>,
+    /// Size in bytes of the cache of file data read through file handles.
+    /// `None` uses `DEFAULT_BLOCK_CACHE_SIZE`.
+    pub block_cache_size: Option<u64>,
+}
+
+impl std::fmt::Debug for AgentFSOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentFSOptions")
+            .field("id", &self.id)
+            .field("path", &self.path)
+            .field("base", &self.base)
+            .field("dedup", &self.dedup)
+            .field("compression", &self.compression)
+            .field(
+                "passphrase",
+                &self.passphrase.as_ref().map(|_| "<redacted>"),
+            )
+            .field("encrypt", &self.encrypt)
+            .field("quota", &self.quota)
+            .field("read_connections", &self.read_connections)
+            .field("block_cache_size", &self.block_cache_size)
+            .finish()
+    }
 }
 
 impl AgentFSOptions {
@@ -125,6 +169,13 @@ impl AgentFSOptions {
             id: Some(id.into()),
             path: None,
             base: None,
+            dedup: false,
+            compression: Compression::None,
+            passphrase: None,
+            encrypt: false,
+            quota: Quota::default(),
+            read_connections: None,
+            block_cache_size: None,
         }
     }
 
@@ -134,6 +185,13 @@ impl AgentFSOptions {
             id: None,
             path: None,
             base: None,
+            dedup: false,
+            compression: Compression::None,
+            passphrase: None,
+            encrypt: false,
+            quota: Quota::default(),
+            read_connections: None,
+            block_cache_size: None,
         }
     }
 
@@ -143,6 +201,13 @@ impl AgentFSOptions {
             id: None,
             path: Some(path.into()),
             base: None,
+            dedup: false,
+            compression: Compression::None,
+            passphrase: None,
+            encrypt: false,
+            quota: Quota::default(),
+            read_connections: None,
+            block_cache_size: None,
         }
     }
 
@@ -152,6 +217,50 @@ impl AgentFSOptions {
         self
     }
 
+    /// Deduplicate file content of a new filesystem
+    pub fn with_dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+
+    /// Compress file content of a new filesystem
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Limit the size of the filesystem
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Set the passphrase that unlocks an encrypted filesystem
+    pub fn with_passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    /// Encrypt file content of a new filesystem with `passphrase`
+    pub fn with_encryption(mut self, passphrase: impl Into<String>) -> Self {
+        self.encrypt = true;
+        self.with_passphrase(passphrase)
+    }
+
+    /// Set the number of extra connections for filesystem reads, 0 making
+    /// reads share the main connection
+    pub fn with_read_connections(mut self, count: usize) -> Self {
+        self.read_connections = Some(count);
+        self
+    }
+
+    /// Set the size in bytes of the cache of file data read through file
+    /// handles, 0 disabling it
+    pub fn with_block_cache_size(mut self, size: u64) -> Self {
+        self.block_cache_size = Some(size);
+        self
+    }
+
     /// Resolve an id-or-path string to AgentFSOptions
     ///
     /// Resolution order (first match wins):
@@ -247,14 +356,44 @@ impl AgentFS {
             OverlayFS::init_schema(&conn, &base_path_str).await?;
         }
 
-        Self::open_with(conn).await
+        if options.dedup {
+            filesystem::AgentFS::enable_dedup(&conn).await?;
+        }
+        filesystem::AgentFS::enable_compression(&conn, options.compression).await?;
+        if options.encrypt {
+            let passphrase = options
+                .passphrase
+                .as_deref()
+                .ok_or(Error::PassphraseRequired)?;
+            filesystem::AgentFS::enable_encryption(&conn, passphrase).await?;
+        }
+        if !options.quota.is_unlimited() {
+            filesystem::AgentFS::set_quota(&conn, options.quota).await?;
+        }
+
+        let mut agent = Self::open_with_passphrase(conn, options.passphrase.as_deref()).await?;
+        if db_path != ":memory:" {
+            let count = options.read_connections.unwrap_or(DEFAULT_READ_CONNECTIONS);
+            agent.fs = agent.fs.with_read_connections(&db, count).await?;
+        }
+        if let Some(size) = options.block_cache_size {
+            agent.fs = agent.fs.with_block_cache_size(size);
+        }
+        Ok(agent)
     }
 
     pub async fn open_with(conn: Connection) -> Result<Self> {
+        Self::open_with_passphrase(conn, None).await
+    }
+
+    /// Open an AgentFS instance from a connection, unlocking its filesystem
+    /// with `passphrase` if it is encrypted
+    pub async fn open_with_passphrase(conn: Connection, passphrase: Option<&str>) -> Result<Self> {
         let conn = Arc::new(conn);
 
         let kv = KvStore::from_connection(conn.clone()).await?;
-        let fs = filesystem::AgentFS::from_connection(conn.clone()).await?;
+        let fs =
+            filesystem::AgentFS::from_connection_with_passphrase(conn.clone(), passphrase).await?;
         let tools = ToolCalls::from_connection(conn.clone()).await?;
 
         Ok(Self {
@@ -513,6 +652,75 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_encrypted_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("encrypted.db");
+        let path = path.to_str().unwrap();
+        let secret = b"top secret agent output, top secret agent output";
+
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(path).with_encryption("hunter2"))
+            .await
+            .unwrap();
+        assert!(agentfs.fs.is_encrypted());
+        agentfs.fs.write_file("/secret.txt", secret).await.unwrap();
+        let conn = agentfs.get_connection();
+        let mut rows = conn.query("SELECT data FROM fs_data", ()).await.unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        let Ok(Value::Blob(stored)) = row.get_value(0) else {
+            panic!("chunk data should be a blob");
+        };
+        assert!(!stored.windows(secret.len()).any(|w| w == secret));
+        drop(rows);
+        drop(agentfs);
+
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(path).with_passphrase("hunter2"))
+            .await
+            .unwrap();
+        let data = agentfs.fs.read_file("/secret.txt").await.unwrap();
+        assert_eq!(data.as_deref(), Some(&secret[..]));
+        drop(agentfs);
+
+        let result = AgentFS::open(AgentFSOptions::with_path(path).with_passphrase("wrong")).await;
+        assert!(matches!(result, Err(Error::InvalidPassphrase)));
+        let result = AgentFS::open(AgentFSOptions::with_path(path)).await;
+        assert!(matches!(result, Err(Error::PassphraseRequired)));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_chunks_bound_to_their_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("encrypted.db");
+        let path = path.to_str().unwrap();
+
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(path).with_encryption("hunter2"))
+            .await
+            .unwrap();
+        agentfs.fs.write_file("/a.txt", b"first").await.unwrap();
+        agentfs.fs.write_file("/b.txt", b"second").await.unwrap();
+
+        // Copy the encrypted chunk of one file over the other's
+        let conn = agentfs.get_connection();
+        conn.execute(
+            "UPDATE fs_data SET data = (
+                SELECT data FROM fs_data
+                WHERE ino = (SELECT ino FROM fs_dentry WHERE name = 'a.txt')
+            )
+            WHERE ino = (SELECT ino FROM fs_dentry WHERE name = 'b.txt')",
+            (),
+        )
+        .await
+        .unwrap();
+        drop(agentfs);
+
+        let agentfs = AgentFS::open(AgentFSOptions::with_path(path).with_passphrase("hunter2"))
+            .await
+            .unwrap();
+        let data = agentfs.fs.read_file("/a.txt").await.unwrap();
+        assert_eq!(data.as_deref(), Some(&b"first"[..]));
+        assert!(agentfs.fs.read_file("/b.txt").await.is_err());
+    }
+
     #[tokio::test]
     async fn test_kv_operations() {
         let agentfs = AgentFS::open(AgentFSOptions::ephemeral()).await.unwrap();